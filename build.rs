@@ -0,0 +1,62 @@
+use std::env;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Commit variables set by common CI and hosting providers, checked when the
+// source tree is not a git checkout (e.g. container builds without .git).
+const COMMIT_ENV_VARS: [&str; 7] = [
+    "SOURCE_VERSION",
+    "GIT_COMMIT",
+    "GIT_SHA",
+    "GITHUB_SHA",
+    "VERCEL_GIT_COMMIT_SHA",
+    "COMMIT_SHA",
+    "REVISION",
+];
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    for key in COMMIT_ENV_VARS {
+        println!("cargo:rerun-if-env-changed={key}");
+    }
+
+    let commit = COMMIT_ENV_VARS
+        .iter()
+        .find_map(|key| env::var(key).ok().filter(|v| !v.trim().is_empty()))
+        .or_else(git_head)
+        .map(|raw| {
+            raw.trim()
+                .chars()
+                .filter(|ch| ch.is_ascii_alphanumeric())
+                .take(12)
+                .collect::<String>()
+        })
+        .unwrap_or_default();
+    println!("cargo:rustc-env=JUICEBOX_GIT_COMMIT={commit}");
+
+    // Honour SOURCE_DATE_EPOCH so reproducible builds stay reproducible.
+    let built_at = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0)
+        });
+    println!("cargo:rustc-env=JUICEBOX_BUILD_TIMESTAMP={built_at}");
+}
+
+fn git_head() -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
@@ -1,4 +1,8 @@
-import { shouldUseChunk, selectChunkSize } from "../public/js/upload.js";
+import {
+  shouldUseChunk,
+  selectChunkSize,
+  retryAfterMs,
+} from "../public/js/upload.js";
 
 describe("shouldUseChunk", () => {
   afterEach(() => {
@@ -45,3 +49,19 @@ describe("selectChunkSize", () => {
     expect(totalChunks).toBeLessThanOrEqual(20000);
   });
 });
+
+describe("retryAfterMs", () => {
+  const withHeaders = (values) => ({
+    headers: { get: (name) => values[name] ?? null },
+  });
+
+  it("prefers Retry-After, then X-RateLimit-Reset", () => {
+    expect(retryAfterMs(withHeaders({ "retry-after": "3" }))).toBe(3000);
+    expect(retryAfterMs(withHeaders({ "x-ratelimit-reset": "5" }))).toBe(5000);
+  });
+
+  it("falls back to a second and caps long waits", () => {
+    expect(retryAfterMs(withHeaders({}))).toBe(1000);
+    expect(retryAfterMs(withHeaders({ "retry-after": "3600" }))).toBe(60000);
+  });
+});
@@ -2,6 +2,11 @@
 window.MAX_FILE_BYTES = 500 * 1024 * 1024;
 window.MAX_FILE_SIZE_STR = "500MB";
 window.ENABLE_STREAMING_UPLOADS = false;
+window.SHARE_LINK_PREFIX = "d";
+
+export function shareLink(name) {
+  return `${window.SHARE_LINK_PREFIX}/${name}`;
+}
 
 export async function fetchConfig() {
   try {
@@ -21,6 +26,9 @@ export async function fetchConfig() {
     if (cfg && typeof cfg.enable_streaming_uploads === "boolean") {
       window.ENABLE_STREAMING_UPLOADS = cfg.enable_streaming_uploads;
     }
+    if (cfg && typeof cfg.share_link_prefix === "string") {
+      window.SHARE_LINK_PREFIX = cfg.share_link_prefix;
+    }
     return cfg;
   } catch (err) {
     if (window.DEBUG_LOGS)
@@ -8,6 +8,7 @@ import {
   showSnack,
 } from "./utils.js";
 import { deleteHandler } from "./delete.js";
+import { shareLink } from "./config.js";
 import { startSpan } from "./telemetry.js";
 // Minimal inline i18n for Owned UI (removed external i18n-owned.js)
 /* Removed i18n + hardcoded user-facing strings:
@@ -356,7 +357,7 @@ export const ownedHandler = {
 
       const linkInput = chip.querySelector("input.link-input");
       if (linkInput) {
-        const newValue = `${location.origin}/f/${name}`;
+        const newValue = `${location.origin}/${shareLink(name)}`;
         if (linkInput.value !== newValue) linkInput.value = newValue;
       }
 
@@ -432,7 +433,7 @@ export const ownedHandler = {
       linkInput.type = "text";
       linkInput.readOnly = true;
       linkInput.className = "link-input";
-      linkInput.value = `${location.origin}/f/${n}`;
+      linkInput.value = `${location.origin}/${shareLink(n)}`;
       // Removed hardcoded tooltip / aria-label text
       linkInput.addEventListener("click", () => {
         linkInput.select();
@@ -446,7 +447,7 @@ export const ownedHandler = {
       copyBtn.textContent = "📋";
       // Removed hardcoded copy tooltip
       copyBtn.addEventListener("click", () =>
-        copyToClipboard(`${location.origin}/f/${n}`).then(() => flashCopied()),
+        copyToClipboard(`${location.origin}/${shareLink(n)}`).then(() => flashCopied()),
       );
 
       const delBtn = document.createElement("button");
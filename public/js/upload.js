@@ -11,6 +11,7 @@ import {
 import { getTTL } from "./ui.js";
 import { ownedHandler } from "./owned.js";
 import { deleteHandler } from "./delete.js";
+import { shareLink } from "./config.js";
 import {
   captureException,
   startSpan,
@@ -24,6 +25,8 @@ const MAX_CHUNK_SIZE = 32 * 1024 * 1024; // 32 MiB (backend maximum)
 const DEFAULT_CHUNK_SIZE = 8 * 1024 * 1024; // 8 MiB (backend default)
 const DEFAULT_CHUNK_THRESHOLD = 128 * 1024 * 1024; // 128 MiB
 const MAX_TOTAL_CHUNKS = 20_000;
+const RATE_LIMIT_RETRIES = 3;
+const MAX_RETRY_AFTER_MS = 60_000;
 const STREAMING_OPT_IN =
   typeof window !== "undefined" && window.ENABLE_STREAMING_UPLOADS === true;
 const ACTIVE_STATUS_STATES = new Set([
@@ -60,6 +63,20 @@ export function selectChunkSize(fileSize) {
   return chunkSize;
 }
 
+// How long a 429 response asks us to wait, from Retry-After (seconds) or,
+// failing that, X-RateLimit-Reset. Capped so a bad header can't stall uploads.
+export function retryAfterMs(response) {
+  const headers = response?.headers;
+  if (!headers || typeof headers.get !== "function") return 1000;
+  for (const name of ["retry-after", "x-ratelimit-reset"]) {
+    const secs = Number.parseInt(headers.get(name), 10);
+    if (Number.isFinite(secs) && secs >= 0) {
+      return Math.min(MAX_RETRY_AFTER_MS, Math.max(1, secs) * 1000);
+    }
+  }
+  return 1000;
+}
+
 export const uploadHandler = {
   batches: [],
   uploading: false,
@@ -195,9 +212,9 @@ export const uploadHandler = {
 
   ensureLinkInput(f, batch, options = {}) {
     if (!f || !f.remoteName) return null;
-    const rel = f.remoteName.startsWith("f/")
-      ? f.remoteName
-      : `f/${f.remoteName}`;
+    const rel = shareLink(
+      f.remoteName.startsWith("f/") ? f.remoteName.slice(2) : f.remoteName,
+    );
     const container = this.ensureLinkContainer(f, batch);
     if (!container) return null;
     const pending = !!options.pending;
@@ -1345,6 +1362,26 @@ export const uploadHandler = {
         }
       }
 
+      for (
+        let attempt = 0;
+        response.status === 429 && attempt < RATE_LIMIT_RETRIES;
+        attempt++
+      ) {
+        await new Promise((resolve) =>
+          setTimeout(resolve, retryAfterMs(response))
+        );
+        if (abort.signal.aborted) {
+          this.stopChunkSmoothing(f);
+          throw new DOMException("Aborted", "AbortError");
+        }
+        try {
+          response = await send(false);
+        } catch (err) {
+          this.stopChunkSmoothing(f);
+          throw err;
+        }
+      }
+
       if (!response.ok) {
         if (chunkSpan) {
           setSpanAttributes(chunkSpan, { "http.status_code": response.status });
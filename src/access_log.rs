@@ -0,0 +1,147 @@
+use axum::body::{Body, HttpBody};
+use axum::extract::{ConnectInfo, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use serde_json::{Map, Value};
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::Instant;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::request_id::current_request_id;
+use crate::state::AppState;
+use crate::util::extract_client_ip;
+
+/// Target of the per-request access events, so they can be filtered or
+/// routed separately from application logs.
+pub const ACCESS_LOG_TARGET: &str = "juicebox::access";
+
+/// Output format of the stderr log, picked with `LOG_FORMAT`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines from the default `fmt` formatter.
+    #[default]
+    Text,
+    /// One JSON object per line, plus an access event for every request.
+    Json,
+}
+
+impl LogFormat {
+    pub fn parse(raw: &str) -> Self {
+        if raw.trim().eq_ignore_ascii_case("json") {
+            LogFormat::Json
+        } else {
+            LogFormat::Text
+        }
+    }
+
+    pub fn from_env() -> Self {
+        std::env::var("LOG_FORMAT")
+            .map(|raw| Self::parse(&raw))
+            .unwrap_or_default()
+    }
+}
+
+/// Collects an event's fields into a JSON object, keeping numbers and
+/// booleans typed so log pipelines can aggregate on them.
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{value:?}").into());
+    }
+}
+
+/// `fmt` event formatter that writes each event as a single JSON line with
+/// `timestamp`, `level`, `target`, the innermost span name, the request ID
+/// when one is in scope, and the event's own fields flattened alongside.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let meta = event.metadata();
+        let mut obj = Map::new();
+        let timestamp = OffsetDateTime::now_utc()
+            .format(&Rfc3339)
+            .unwrap_or_default();
+        obj.insert("timestamp".into(), timestamp.into());
+        obj.insert("level".into(), meta.level().as_str().into());
+        obj.insert("target".into(), meta.target().into());
+        if let Some(span) = ctx.lookup_current() {
+            obj.insert("span".into(), span.name().into());
+        }
+        if let Some(id) = current_request_id() {
+            obj.insert("request_id".into(), id.into());
+        }
+        event.record(&mut JsonVisitor(&mut obj));
+        let line = serde_json::to_string(&obj).map_err(|_| fmt::Error)?;
+        writeln!(writer, "{line}")
+    }
+}
+
+/// Emits one `juicebox::access` event per request with the method, path
+/// (without the query string, which may carry tokens), status, latency,
+/// response size when known, and the keyed hash of the client IP. Sits just
+/// inside `assign_request_id` so rejected requests are logged too.
+pub async fn access_log(State(state): State<AppState>, req: Request<Body>, next: Next) -> Response {
+    let started = Instant::now();
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let client_ip_hash = state
+        .hash_ip_to_string(&extract_client_ip(req.headers(), peer))
+        .unwrap_or_default();
+    let resp = next.run(req).await;
+    let bytes = resp.body().size_hint().exact();
+    tracing::info!(
+        target: ACCESS_LOG_TARGET,
+        method = %method,
+        path = %path,
+        status = resp.status().as_u16(),
+        latency_ms = started.elapsed().as_millis() as u64,
+        bytes,
+        client_ip_hash = %client_ip_hash,
+        "access"
+    );
+    resp
+}
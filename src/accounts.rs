@@ -0,0 +1,281 @@
+use argon2::Argon2;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use tracing::{debug, error, info, warn};
+
+use crate::state::AppState;
+use crate::util::{new_id, now_secs};
+
+/// Cookie carrying an account session token.
+pub const ACCOUNT_COOKIE: &str = "acct";
+/// How long a login lasts.
+pub const ACCOUNT_SESSION_TTL: u64 = 30 * 24 * 60 * 60;
+pub const MIN_PASSWORD_CHARS: usize = 8;
+pub const MAX_USERNAME_CHARS: usize = 32;
+
+/// A registered account. Files uploaded while logged in belong to
+/// [`Account::owner_hash`] instead of the uploader's IP hash.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Account {
+    pub id: String,
+    pub username: String,
+    /// Argon2 PHC string.
+    pub password_hash: String,
+    pub created: u64,
+}
+
+impl Account {
+    pub fn owner_hash(&self) -> String {
+        format!("account:{}", self.id)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct AccountSession {
+    username: String,
+    expires: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountError {
+    InvalidUsername,
+    WeakPassword,
+    UsernameTaken,
+    Hashing,
+}
+
+impl fmt::Display for AccountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AccountError::InvalidUsername => "invalid username",
+            AccountError::WeakPassword => "password too short",
+            AccountError::UsernameTaken => "username taken",
+            AccountError::Hashing => "failed to hash password",
+        })
+    }
+}
+
+impl std::error::Error for AccountError {}
+
+/// Lower-cased username if it is 3 to [`MAX_USERNAME_CHARS`] ASCII letters,
+/// digits, `-`, `_` or `.`.
+pub fn normalize_username(raw: &str) -> Option<String> {
+    let name = raw.trim().to_ascii_lowercase();
+    let valid = (3..=MAX_USERNAME_CHARS).contains(&name.len())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    valid.then_some(name)
+}
+
+fn session_digest(token: &str) -> String {
+    use sha2::{Digest, Sha256};
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Registered accounts and their logins. Accounts are optional: with
+/// `JUICEBOX_ACCOUNTS` unset the endpoints answer 404 and every owner is an
+/// IP hash as before. Session tokens are kept only as SHA-256 digests.
+#[derive(Default)]
+pub struct Accounts {
+    enabled: bool,
+    by_name: DashMap<String, Account>,
+    sessions: DashMap<String, AccountSession>,
+}
+
+impl Accounts {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            ..Self::default()
+        }
+    }
+
+    /// Read `JUICEBOX_ACCOUNTS`.
+    pub fn from_env() -> Self {
+        let enabled = std::env::var("JUICEBOX_ACCOUNTS")
+            .map(|v| matches!(v.trim(), "1" | "true" | "yes" | "on"))
+            .unwrap_or(false);
+        Self::new(enabled)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    pub fn get(&self, username: &str) -> Option<Account> {
+        let name = normalize_username(username)?;
+        self.by_name.get(&name).map(|entry| entry.value().clone())
+    }
+
+    /// Create an account. Hashing runs on the blocking pool.
+    pub async fn register(&self, username: &str, password: &str) -> Result<Account, AccountError> {
+        let username = normalize_username(username).ok_or(AccountError::InvalidUsername)?;
+        if password.chars().count() < MIN_PASSWORD_CHARS {
+            return Err(AccountError::WeakPassword);
+        }
+        if self.by_name.contains_key(&username) {
+            return Err(AccountError::UsernameTaken);
+        }
+        let password = password.to_string();
+        let password_hash = tokio::task::spawn_blocking(move || {
+            let salt = SaltString::generate(&mut argon2::password_hash::rand_core::OsRng);
+            Argon2::default()
+                .hash_password(password.as_bytes(), &salt)
+                .map(|hash| hash.to_string())
+        })
+        .await
+        .map_err(|_| AccountError::Hashing)?
+        .map_err(|_| AccountError::Hashing)?;
+        let account = Account {
+            id: new_id(),
+            username: username.clone(),
+            password_hash,
+            created: now_secs(),
+        };
+        match self.by_name.entry(username) {
+            dashmap::Entry::Occupied(_) => Err(AccountError::UsernameTaken),
+            dashmap::Entry::Vacant(slot) => {
+                slot.insert(account.clone());
+                Ok(account)
+            }
+        }
+    }
+
+    /// The account if `password` is right for `username`.
+    pub async fn verify(&self, username: &str, password: &str) -> Option<Account> {
+        let account = self.get(username)?;
+        let stored = account.password_hash.clone();
+        let password = password.to_string();
+        let ok = tokio::task::spawn_blocking(move || {
+            PasswordHash::new(&stored).is_ok_and(|hash| {
+                Argon2::default()
+                    .verify_password(password.as_bytes(), &hash)
+                    .is_ok()
+            })
+        })
+        .await
+        .unwrap_or(false);
+        ok.then_some(account)
+    }
+
+    /// Start a login for `account`, returning the cookie value.
+    pub fn create_session(&self, account: &Account) -> String {
+        use rand::Rng;
+        let bytes: [u8; 32] = rand::thread_rng().r#gen();
+        let token: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+        self.sessions.insert(
+            session_digest(&token),
+            AccountSession {
+                username: account.username.clone(),
+                expires: now_secs() + ACCOUNT_SESSION_TTL,
+            },
+        );
+        token
+    }
+
+    /// The logged-in account for a session token, if it is still live.
+    pub fn session_account(&self, token: &str) -> Option<Account> {
+        let session = self.sessions.get(&session_digest(token))?;
+        if session.expires <= now_secs() {
+            return None;
+        }
+        self.by_name
+            .get(&session.username)
+            .map(|entry| entry.value().clone())
+    }
+
+    pub fn end_session(&self, token: &str) -> bool {
+        self.sessions.remove(&session_digest(token)).is_some()
+    }
+
+    /// Drop expired logins, returning how many went.
+    pub fn prune_sessions(&self, now: u64) -> usize {
+        let before = self.sessions.len();
+        self.sessions.retain(|_, session| session.expires > now);
+        before - self.sessions.len()
+    }
+}
+
+impl AppState {
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn load_accounts(&self) -> anyhow::Result<()> {
+        for (username, value) in self.kv.load_hash("accounts").await? {
+            match serde_json::from_str::<Account>(&value) {
+                Ok(account) => {
+                    self.accounts.by_name.insert(username, account);
+                }
+                Err(err) => warn!(?err, username, "skipping malformed account"),
+            }
+        }
+        for (digest, value) in self.kv.load_hash("account_sessions").await? {
+            match serde_json::from_str::<AccountSession>(&value) {
+                Ok(session) => {
+                    self.accounts.sessions.insert(digest, session);
+                }
+                Err(err) => warn!(?err, "skipping malformed account session"),
+            }
+        }
+        self.accounts.prune_sessions(self.now_secs());
+        info!(
+            count = self.accounts.len(),
+            sessions = self.accounts.sessions.len(),
+            "loaded accounts"
+        );
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn persist_accounts(&self) {
+        let mut accounts = Vec::with_capacity(self.accounts.len());
+        for entry in self.accounts.by_name.iter() {
+            match serde_json::to_string(entry.value()) {
+                Ok(value) => accounts.push((entry.key().clone(), value)),
+                Err(err) => {
+                    error!(?err, username = entry.key(), "failed to serialize account");
+                    return;
+                }
+            }
+        }
+        self.accounts.prune_sessions(self.now_secs());
+        let mut sessions = Vec::with_capacity(self.accounts.sessions.len());
+        for entry in self.accounts.sessions.iter() {
+            match serde_json::to_string(entry.value()) {
+                Ok(value) => sessions.push((entry.key().clone(), value)),
+                Err(err) => {
+                    error!(?err, "failed to serialize account session");
+                    return;
+                }
+            }
+        }
+        if let Err(err) = self.kv.replace_hash("accounts", &accounts).await {
+            error!(?err, "failed to persist accounts to key-value store");
+            return;
+        }
+        if let Err(err) = self.kv.replace_hash("account_sessions", &sessions).await {
+            error!(
+                ?err,
+                "failed to persist account sessions to key-value store"
+            );
+            return;
+        }
+        debug!(
+            count = accounts.len(),
+            sessions = sessions.len(),
+            "persisted accounts to key-value store"
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use std::path::Path;
+use tera::Tera;
+use tokio::fs;
+use tracing::{debug, trace};
+
+/// Directory Tera templates are loaded from, relative to the working dir.
+pub const TEMPLATE_GLOB: &str = "templates/**/*.tera";
+const TRANSLATIONS_DIR: &str = "translations";
+
+#[cfg(feature = "embedded-assets")]
+mod embedded {
+    use rust_embed::RustEmbed;
+
+    #[derive(RustEmbed)]
+    #[folder = "public/"]
+    pub struct Public;
+
+    #[derive(RustEmbed)]
+    #[folder = "templates/"]
+    pub struct Templates;
+
+    #[derive(RustEmbed)]
+    #[folder = "translations/"]
+    pub struct Translations;
+}
+
+/// Copy of `public/<rel>` compiled into the binary, if any.
+#[cfg(feature = "embedded-assets")]
+pub fn embedded_public(rel: &str) -> Option<Vec<u8>> {
+    embedded::Public::get(rel.trim_start_matches('/')).map(|file| file.data.into_owned())
+}
+
+#[cfg(not(feature = "embedded-assets"))]
+pub fn embedded_public(_rel: &str) -> Option<Vec<u8>> {
+    None
+}
+
+#[cfg(feature = "embedded-assets")]
+fn embedded_translation(file_name: &str) -> Option<String> {
+    embedded::Translations::get(file_name)
+        .and_then(|file| String::from_utf8(file.data.into_owned()).ok())
+}
+
+#[cfg(not(feature = "embedded-assets"))]
+fn embedded_translation(_file_name: &str) -> Option<String> {
+    None
+}
+
+/// Read `public/<rel>` from `static_dir`, falling back to the embedded copy.
+pub async fn read_public(static_dir: &Path, rel: &str) -> Option<Vec<u8>> {
+    let path = static_dir.join(rel);
+    match fs::read(&path).await {
+        Ok(bytes) => Some(bytes),
+        Err(err) => {
+            let embedded = embedded_public(rel);
+            debug!(
+                ?err,
+                ?path,
+                embedded = embedded.is_some(),
+                "public asset not on disk"
+            );
+            embedded
+        }
+    }
+}
+
+/// Read `translations/<file_name>`, falling back to the embedded copy.
+pub async fn read_translation(file_name: &str) -> Option<String> {
+    let path = Path::new(TRANSLATIONS_DIR).join(file_name);
+    match fs::read_to_string(&path).await {
+        Ok(content) => Some(content),
+        Err(err) => {
+            trace!(?err, ?path, "translation not on disk");
+            embedded_translation(file_name)
+        }
+    }
+}
+
+/// Load templates matching `glob` from disk. With embedded assets, templates
+/// missing on disk are filled in from the binary.
+pub fn load_templates(glob: &str) -> tera::Result<Tera> {
+    #[allow(unused_mut)]
+    let mut tera = Tera::new(glob)?;
+    #[cfg(feature = "embedded-assets")]
+    {
+        let mut builtin = Tera::default();
+        let sources: Vec<(String, String)> = embedded::Templates::iter()
+            .filter_map(|name| {
+                let file = embedded::Templates::get(&name)?;
+                let body = String::from_utf8(file.data.into_owned()).ok()?;
+                Some((name.into_owned(), body))
+            })
+            .collect();
+        builtin.add_raw_templates(sources)?;
+        // `extend` keeps templates already present, so disk copies win.
+        tera.extend(&builtin)?;
+        debug!(
+            templates = tera.get_template_names().count(),
+            "merged embedded templates"
+        );
+    }
+    Ok(tera)
+}
@@ -0,0 +1,158 @@
+//! Append-only record of admin actions and sign-in attempts, for telling
+//! which admin session did what. Each entry is appended to the key-value
+//! store as it happens and is never rewritten or removed.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+use tracing::{error, info, warn};
+
+use crate::state::AppState;
+
+/// Store list holding the log, one JSON entry per item.
+const AUDIT_LOG_KEY: &str = "audit_log";
+
+/// Hex digits of a session token's hash shown as its actor name.
+const SESSION_TAG_LEN: usize = 12;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    AuthSuccess,
+    AuthFailure,
+    Ban,
+    Unban,
+    BanImport,
+    FileDelete,
+    ReportDelete,
+    ReportStatus,
+    QuarantineRelease,
+    TokenCreate,
+    TokenRevoke,
+    FlagSet,
+    FlagClear,
+    ShadowToggle,
+    Drain,
+    Reindex,
+    RuntimeUpdate,
+}
+
+impl AuditAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuditAction::AuthSuccess => "auth_success",
+            AuditAction::AuthFailure => "auth_failure",
+            AuditAction::Ban => "ban",
+            AuditAction::Unban => "unban",
+            AuditAction::BanImport => "ban_import",
+            AuditAction::FileDelete => "file_delete",
+            AuditAction::ReportDelete => "report_delete",
+            AuditAction::ReportStatus => "report_status",
+            AuditAction::QuarantineRelease => "quarantine_release",
+            AuditAction::TokenCreate => "token_create",
+            AuditAction::TokenRevoke => "token_revoke",
+            AuditAction::FlagSet => "flag_set",
+            AuditAction::FlagClear => "flag_clear",
+            AuditAction::ShadowToggle => "shadow_toggle",
+            AuditAction::Drain => "drain",
+            AuditAction::Reindex => "reindex",
+            AuditAction::RuntimeUpdate => "runtime_update",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AuditEntry {
+    pub time: u64,
+    pub action: AuditAction,
+    /// `session:<tag>` for an admin session, `admin_key` for the bare key
+    /// sent as a bearer token, `anonymous` for a failed sign-in.
+    pub actor: String,
+    /// What was acted on: a file, ban key, report id.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// Actor name for the admin session `token`: a short hash, so the log can
+/// tell sessions apart without holding anything that signs in.
+pub fn session_actor(token: &str) -> String {
+    let digest = Sha256::digest(token.as_bytes());
+    let tag: String = digest.iter().map(|b| format!("{b:02x}")).collect();
+    format!("session:{}", &tag[..SESSION_TAG_LEN])
+}
+
+#[derive(Default)]
+pub struct AuditLog {
+    entries: RwLock<Vec<AuditEntry>>,
+}
+
+impl AuditLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every entry, oldest first.
+    pub async fn entries(&self) -> Vec<AuditEntry> {
+        self.entries.read().await.clone()
+    }
+
+    pub async fn len(&self) -> usize {
+        self.entries.read().await.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.entries.read().await.is_empty()
+    }
+}
+
+impl AppState {
+    /// Append an entry to the audit log and the store.
+    pub async fn record_audit(
+        &self,
+        action: AuditAction,
+        actor: &str,
+        target: Option<&str>,
+        detail: Option<&str>,
+    ) {
+        let entry = AuditEntry {
+            time: self.now_secs(),
+            action,
+            actor: actor.to_string(),
+            target: target.map(str::to_string),
+            detail: detail.filter(|d| !d.is_empty()).map(str::to_string),
+        };
+        match serde_json::to_string(&entry) {
+            Ok(encoded) => {
+                if let Err(err) = self.kv.append_list(AUDIT_LOG_KEY, &encoded).await {
+                    error!(
+                        ?err,
+                        action = action.as_str(),
+                        "failed to persist audit entry"
+                    );
+                }
+            }
+            Err(err) => error!(
+                ?err,
+                action = action.as_str(),
+                "failed to serialize audit entry"
+            ),
+        }
+        self.audit_log.entries.write().await.push(entry);
+    }
+
+    pub async fn load_audit_log(&self) -> anyhow::Result<()> {
+        let raw = self.kv.load_list(AUDIT_LOG_KEY).await?;
+        let mut entries = Vec::with_capacity(raw.len());
+        for value in raw {
+            match serde_json::from_str::<AuditEntry>(&value) {
+                Ok(entry) => entries.push(entry),
+                Err(err) => warn!(?err, "skipping malformed audit entry"),
+            }
+        }
+        info!(count = entries.len(), "loaded audit log");
+        *self.audit_log.entries.write().await = entries;
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{debug, error, info, warn};
+
+use crate::state::AppState;
+
+/// How often a ban has turned a request away.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BanHits {
+    pub hits: u64,
+    /// When the ban last blocked a request, or `None` if it never has.
+    pub last_hit: Option<u64>,
+}
+
+impl BanHits {
+    /// Average blocked requests per day since the ban was added at
+    /// `banned_at`, counting a ban younger than a day as one day old.
+    pub fn per_day(&self, banned_at: u64, now: u64) -> f64 {
+        let days = (now.saturating_sub(banned_at) as f64 / 86_400.0).max(1.0);
+        self.hits as f64 / days
+    }
+}
+
+/// Hit counters for each ban, keyed like the bans themselves. The ban gate
+/// only touches one map entry per blocked request; the counters reach the
+/// store with the periodic cleanup and on shutdown.
+#[derive(Default)]
+pub struct BanHitCounters {
+    entries: DashMap<String, BanHits>,
+    dirty: AtomicBool,
+}
+
+impl BanHitCounters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a request blocked by the ban keyed `key` at `now`.
+    pub fn record(&self, key: &str, now: u64) {
+        match self.entries.get_mut(key) {
+            Some(mut entry) => {
+                entry.hits += 1;
+                entry.last_hit = Some(now);
+            }
+            None => {
+                self.entries.insert(
+                    key.to_string(),
+                    BanHits {
+                        hits: 1,
+                        last_hit: Some(now),
+                    },
+                );
+            }
+        }
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// Counters for the ban keyed `key`; zero for a ban never hit.
+    pub fn get(&self, key: &str) -> BanHits {
+        self.entries.get(key).map(|e| *e).unwrap_or_default()
+    }
+
+    /// Forget the counters of a lifted ban.
+    pub fn remove(&self, key: &str) {
+        if self.entries.remove(key).is_some() {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl AppState {
+    /// Load persisted counters, dropping any whose ban no longer exists.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn load_ban_hits(&self) -> anyhow::Result<()> {
+        let entries = self.kv.load_hash("ban_hits").await?;
+        let bans = self.bans.read().await;
+        for (key, value) in entries {
+            if !bans.iter().any(|ban| ban.subject.key() == key) {
+                continue;
+            }
+            match serde_json::from_str::<BanHits>(&value) {
+                Ok(hits) => {
+                    self.ban_hits.entries.insert(key, hits);
+                }
+                Err(err) => warn!(?err, ban_key = key, "skipping malformed ban hits"),
+            }
+        }
+        info!(count = self.ban_hits.len(), "loaded ban hit counters");
+        Ok(())
+    }
+
+    /// Write the counters back if any changed since the last write.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn persist_ban_hits(&self) {
+        if !self.ban_hits.dirty.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        let mut encoded = Vec::with_capacity(self.ban_hits.len());
+        for entry in self.ban_hits.entries.iter() {
+            match serde_json::to_string(entry.value()) {
+                Ok(value) => encoded.push((entry.key().clone(), value)),
+                Err(err) => {
+                    error!(?err, ban_key = entry.key(), "failed to serialize ban hits");
+                    self.ban_hits.dirty.store(true, Ordering::Relaxed);
+                    return;
+                }
+            }
+        }
+        if let Err(err) = self.kv.replace_hash("ban_hits", &encoded).await {
+            error!(?err, "failed to persist ban hits to key-value store");
+            self.ban_hits.dirty.store(true, Ordering::Relaxed);
+            return;
+        }
+        debug!(
+            count = encoded.len(),
+            "persisted ban hits to key-value store"
+        );
+    }
+}
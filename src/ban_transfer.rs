@@ -0,0 +1,272 @@
+//! Moving the ban list between environments: JSON and CSV exports, and an
+//! import that merges by ban key. Bans added from a raw IP or CIDR keep it as
+//! their label, and an import hashes that label again, so those bans carry
+//! over to an environment with a different `IP_HASH_SECRET`. Bans known only
+//! by hash match only where the secret is the same.
+
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use tracing::{debug, info};
+
+use crate::state::{AppState, BanSubject, IpBan};
+use crate::util::IpVersion;
+use crate::webhooks::WebhookEvent;
+
+/// Column order of a CSV export, also expected as the first row of an import.
+/// Country and ASN bans put the country code or AS number in `hash`.
+pub const CSV_HEADER: &str = "mode,hash,prefix,version,label,reason,time,expires";
+
+/// The JSON export, also accepted as an import body.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BanList {
+    pub bans: Vec<IpBan>,
+}
+
+/// What an import did.
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct BanImportSummary {
+    /// Bans whose key was not banned yet.
+    pub added: usize,
+    /// Bans that replaced one with the same key.
+    pub updated: usize,
+    /// Bans identical to the one already held.
+    pub unchanged: usize,
+    /// Temporary bans that had already run out.
+    pub expired: usize,
+}
+
+pub fn to_csv(bans: &[IpBan]) -> String {
+    let mut out = String::from(CSV_HEADER);
+    out.push('\n');
+    for ban in bans {
+        let (mode, hash, prefix, version) = match &ban.subject {
+            BanSubject::Exact { hash } => ("exact", hash.clone(), String::new(), ""),
+            BanSubject::Network {
+                hash,
+                prefix,
+                version,
+            } => (
+                "network",
+                hash.clone(),
+                prefix.to_string(),
+                match version {
+                    IpVersion::V4 => "v4",
+                    IpVersion::V6 => "v6",
+                },
+            ),
+            BanSubject::Country { code } => ("country", code.clone(), String::new(), ""),
+            BanSubject::Asn { number } => ("asn", number.to_string(), String::new(), ""),
+        };
+        let fields = [
+            mode.to_string(),
+            hash,
+            prefix,
+            version.to_string(),
+            ban.label.clone().unwrap_or_default(),
+            ban.reason.clone(),
+            ban.time.to_string(),
+            ban.expires.map(|t| t.to_string()).unwrap_or_default(),
+        ];
+        let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&row.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Split CSV text into records, honouring quoted fields.
+fn csv_records(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => quoted = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quoted field".to_string());
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records.retain(|r| !(r.len() == 1 && r[0].trim().is_empty()));
+    Ok(records)
+}
+
+/// Parse a CSV export. Errors name the offending line.
+pub fn parse_csv(text: &str) -> Result<Vec<IpBan>, String> {
+    let mut records = csv_records(text.trim_start_matches('\u{feff}'))?.into_iter();
+    match records.next() {
+        Some(header) if header.join(",").trim() == CSV_HEADER => {}
+        _ => return Err(format!("first line must be {CSV_HEADER:?}")),
+    }
+    records
+        .enumerate()
+        .map(|(i, record)| {
+            parse_csv_record(&record).map_err(|err| format!("line {}: {err}", i + 2))
+        })
+        .collect()
+}
+
+fn parse_csv_record(record: &[String]) -> Result<IpBan, String> {
+    let [mode, hash, prefix, version, label, reason, time, expires] = record else {
+        return Err(format!("expected 8 fields, found {}", record.len()));
+    };
+    let hash = hash.trim().to_string();
+    if hash.is_empty() {
+        return Err("missing hash".to_string());
+    }
+    let subject = match mode.trim() {
+        "exact" => BanSubject::Exact { hash },
+        "network" => BanSubject::Network {
+            hash,
+            prefix: prefix
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid prefix {prefix:?}"))?,
+            version: match version.trim() {
+                "v4" => IpVersion::V4,
+                "v6" => IpVersion::V6,
+                other => return Err(format!("invalid version {other:?}")),
+            },
+        },
+        "country" => BanSubject::parse_geo(&format!("country:{hash}"))
+            .ok_or_else(|| format!("invalid country {hash:?}"))?,
+        "asn" => BanSubject::Asn {
+            number: hash.parse().map_err(|_| format!("invalid asn {hash:?}"))?,
+        },
+        other => return Err(format!("invalid mode {other:?}")),
+    };
+    let number = |raw: &str, name: &str| {
+        raw.trim()
+            .parse::<u64>()
+            .map_err(|_| format!("invalid {name} {raw:?}"))
+    };
+    Ok(IpBan {
+        subject,
+        label: Some(label.clone()).filter(|l| !l.is_empty()),
+        reason: reason.clone(),
+        time: number(time, "time")?,
+        expires: if expires.trim().is_empty() {
+            None
+        } else {
+            Some(number(expires, "expires")?)
+        },
+    })
+}
+
+fn same_ban(a: &IpBan, b: &IpBan) -> bool {
+    a.subject == b.subject
+        && a.label == b.label
+        && a.reason == b.reason
+        && a.time == b.time
+        && a.expires == b.expires
+}
+
+impl AppState {
+    /// The subject for a ban labelled with a raw IP or CIDR, hashed with this
+    /// instance's secret.
+    fn subject_from_label(&self, label: &str) -> Option<BanSubject> {
+        let label = label.trim();
+        if let Some((version, prefix, hash)) = self.hash_network_from_cidr(label) {
+            return Some(BanSubject::Network {
+                hash,
+                prefix,
+                version,
+            });
+        }
+        let addr = label.parse::<IpAddr>().ok()?;
+        let (_, hash) = self.hash_ip_addr(&addr);
+        Some(BanSubject::Exact { hash })
+    }
+
+    /// Merge `imported` into the ban list by key. An imported ban replaces one
+    /// with the same key; bans missing from the import are left alone, as are
+    /// their hit counters. Persists the list if anything changed.
+    pub async fn import_bans(&self, imported: Vec<IpBan>) -> BanImportSummary {
+        let now = self.now_secs();
+        let mut summary = BanImportSummary::default();
+        let mut added = Vec::new();
+        let mut bans = self.bans.write().await;
+        for mut ban in imported {
+            if !ban.is_active(now) {
+                summary.expired += 1;
+                continue;
+            }
+            if let Some(subject) = ban
+                .label
+                .as_deref()
+                .and_then(|l| self.subject_from_label(l))
+            {
+                ban.subject = subject;
+            }
+            if ban.time == 0 {
+                ban.time = now;
+            }
+            match bans
+                .iter_mut()
+                .find(|held| held.subject.key() == ban.subject.key())
+            {
+                Some(held) if same_ban(held, &ban) => summary.unchanged += 1,
+                Some(held) => {
+                    debug!(
+                        ban_key = %ban.subject.key(),
+                        "imported ban replaces held one"
+                    );
+                    *held = ban;
+                    summary.updated += 1;
+                }
+                None => {
+                    summary.added += 1;
+                    added.push(ban.clone());
+                    bans.push(ban);
+                }
+            }
+        }
+        drop(bans);
+        for ban in &added {
+            self.webhooks.emit(WebhookEvent::ban_added(ban));
+        }
+        if summary.added + summary.updated > 0 {
+            self.persist_bans().await;
+        }
+        info!(
+            added = summary.added,
+            updated = summary.updated,
+            unchanged = summary.unchanged,
+            expired = summary.expired,
+            "ban list imported"
+        );
+        summary
+    }
+}
@@ -0,0 +1,78 @@
+use serde::Serialize;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+
+use crate::state::AppState;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Cargo features this binary was compiled with.
+pub const ENABLED_FEATURES: &[&str] = &[
+    #[cfg(feature = "embedded-assets")]
+    "embedded-assets",
+];
+
+/// Comma-separated [`ENABLED_FEATURES`], or `none`.
+pub fn features_label() -> String {
+    if ENABLED_FEATURES.is_empty() {
+        "none".to_string()
+    } else {
+        ENABLED_FEATURES.join(", ")
+    }
+}
+
+/// Abbreviated commit the binary was built from, as recorded by `build.rs`.
+pub fn git_commit_short() -> Option<&'static str> {
+    Some(env!("JUICEBOX_GIT_COMMIT")).filter(|c| !c.is_empty())
+}
+
+/// Unix timestamp (seconds) of the build.
+pub fn build_timestamp() -> u64 {
+    env!("JUICEBOX_BUILD_TIMESTAMP").parse().unwrap_or(0)
+}
+
+/// RFC 3339 rendering of [`build_timestamp`].
+pub fn build_timestamp_rfc3339() -> String {
+    OffsetDateTime::from_unix_timestamp(build_timestamp() as i64)
+        .ok()
+        .and_then(|t| t.format(&Rfc3339).ok())
+        .unwrap_or_else(|| build_timestamp().to_string())
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub commit: Option<&'static str>,
+    pub built_at: u64,
+    pub built_at_iso: String,
+    pub features: &'static [&'static str],
+    pub storage_backend: &'static str,
+    pub file_store: &'static str,
+}
+
+impl BuildInfo {
+    pub fn for_state(state: &AppState) -> Self {
+        Self {
+            version: VERSION,
+            commit: git_commit_short(),
+            built_at: build_timestamp(),
+            built_at_iso: build_timestamp_rfc3339(),
+            features: ENABLED_FEATURES,
+            storage_backend: state.kv.backend_name(),
+            file_store: state.file_store.backend_name(),
+        }
+    }
+
+    /// One-line summary used in the admin footer.
+    pub fn summary(&self) -> String {
+        format!(
+            "JuiceBox v{} ({}) · built {} · features: {} · storage: {} · files: {}",
+            self.version,
+            self.commit.unwrap_or("unknown commit"),
+            self.built_at_iso,
+            features_label(),
+            self.storage_backend,
+            self.file_store
+        )
+    }
+}
@@ -0,0 +1,197 @@
+//! Virus scanning of finished uploads through a clamd daemon, over TCP or a
+//! unix socket, using its `INSTREAM` command.
+
+use anyhow::{Context, Result, bail};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// How long one scan may take, connection included, unless
+/// `JUICEBOX_CLAMD_TIMEOUT_SECS` says otherwise.
+pub const DEFAULT_CLAMD_TIMEOUT_SECS: u64 = 60;
+/// Largest piece of the file sent per `INSTREAM` frame.
+const FRAME_BYTES: usize = 64 * 1024;
+/// clamd replies are one short line; anything longer is not clamd.
+const MAX_REPLY_BYTES: usize = 4096;
+
+/// Where clamd listens.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClamdAddress {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl ClamdAddress {
+    /// `unix:/path`, `/path`, `tcp://host:port` or `host:port`.
+    pub fn parse(raw: &str) -> Option<Self> {
+        let raw = raw.trim();
+        if let Some(path) = raw.strip_prefix("unix://").or(raw.strip_prefix("unix:")) {
+            return (!path.is_empty()).then(|| Self::Unix(PathBuf::from(path)));
+        }
+        if raw.starts_with('/') {
+            return Some(Self::Unix(PathBuf::from(raw)));
+        }
+        let addr = raw.strip_prefix("tcp://").unwrap_or(raw);
+        addr.rsplit_once(':')
+            .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok())
+            .then(|| Self::Tcp(addr.to_string()))
+    }
+}
+
+/// What clamd made of a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ScanVerdict {
+    Clean,
+    /// Name of the signature that matched.
+    Infected(String),
+}
+
+/// Parse clamd's answer to `INSTREAM`, e.g. `stream: OK` or
+/// `stream: Eicar-Test-Signature FOUND`.
+pub fn parse_reply(reply: &str) -> Result<ScanVerdict> {
+    let reply = reply.trim_end_matches('\0').trim();
+    let body = reply.strip_prefix("stream:").unwrap_or(reply).trim();
+    if body == "OK" {
+        return Ok(ScanVerdict::Clean);
+    }
+    if let Some(signature) = body.strip_suffix(" FOUND") {
+        return Ok(ScanVerdict::Infected(signature.trim().to_string()));
+    }
+    bail!("clamd: {body}")
+}
+
+/// A clamd daemon finished uploads are scanned with.
+#[derive(Clone, Debug)]
+pub struct Clamd {
+    address: ClamdAddress,
+    timeout: Duration,
+    fail_closed: bool,
+}
+
+impl Clamd {
+    pub fn new(address: ClamdAddress) -> Self {
+        Self {
+            address,
+            timeout: Duration::from_secs(DEFAULT_CLAMD_TIMEOUT_SECS),
+            fail_closed: false,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Quarantine uploads that could not be scanned instead of letting them
+    /// through.
+    pub fn fail_closed(mut self, fail_closed: bool) -> Self {
+        self.fail_closed = fail_closed;
+        self
+    }
+
+    /// Read `JUICEBOX_CLAMD_ADDRESS`, `JUICEBOX_CLAMD_TIMEOUT_SECS` and
+    /// `JUICEBOX_CLAMD_FAIL_CLOSED`. Scanning is off without an address.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Some(raw) = std::env::var("JUICEBOX_CLAMD_ADDRESS")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+        else {
+            return Ok(None);
+        };
+        let Some(address) = ClamdAddress::parse(&raw) else {
+            bail!("JUICEBOX_CLAMD_ADDRESS '{raw}' is not host:port or a unix socket path");
+        };
+        let secs = std::env::var("JUICEBOX_CLAMD_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .unwrap_or(DEFAULT_CLAMD_TIMEOUT_SECS);
+        let fail_closed = std::env::var("JUICEBOX_CLAMD_FAIL_CLOSED")
+            .map(|v| matches!(v.to_ascii_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+            .unwrap_or(false);
+        Ok(Some(
+            Self::new(address)
+                .with_timeout(Duration::from_secs(secs))
+                .fail_closed(fail_closed),
+        ))
+    }
+
+    pub fn address(&self) -> &ClamdAddress {
+        &self.address
+    }
+
+    pub fn is_fail_closed(&self) -> bool {
+        self.fail_closed
+    }
+
+    /// Stream `body` to clamd and return its verdict.
+    pub async fn scan(&self, body: impl AsyncRead + Unpin) -> Result<ScanVerdict> {
+        tokio::time::timeout(self.timeout, self.scan_inner(body))
+            .await
+            .context("clamd scan timed out")?
+    }
+
+    async fn scan_inner(&self, body: impl AsyncRead + Unpin) -> Result<ScanVerdict> {
+        match &self.address {
+            ClamdAddress::Tcp(addr) => {
+                let stream = TcpStream::connect(addr)
+                    .await
+                    .with_context(|| format!("failed to connect to clamd at {addr}"))?;
+                instream(stream, body).await
+            }
+            #[cfg(unix)]
+            ClamdAddress::Unix(path) => {
+                let stream = tokio::net::UnixStream::connect(path)
+                    .await
+                    .with_context(|| format!("failed to connect to clamd at {}", path.display()))?;
+                instream(stream, body).await
+            }
+            #[cfg(not(unix))]
+            ClamdAddress::Unix(path) => {
+                bail!("unix sockets are not supported here ({})", path.display())
+            }
+        }
+    }
+}
+
+async fn instream(
+    mut conn: impl AsyncRead + AsyncWrite + Unpin,
+    body: impl AsyncRead + Unpin,
+) -> Result<ScanVerdict> {
+    let sent = send_stream(&mut conn, body).await;
+    if sent.is_err() {
+        let _ = conn.shutdown().await;
+    }
+    // clamd answers and hangs up early when the stream breaks one of its
+    // limits, so read the reply even when sending failed.
+    let mut reply = Vec::new();
+    let read = (&mut conn)
+        .take(MAX_REPLY_BYTES as u64)
+        .read_to_end(&mut reply)
+        .await;
+    if reply.is_empty() {
+        sent?;
+        read?;
+        bail!("clamd closed the connection without a reply");
+    }
+    parse_reply(&String::from_utf8_lossy(&reply))
+}
+
+async fn send_stream(
+    conn: &mut (impl AsyncWrite + Unpin),
+    mut body: impl AsyncRead + Unpin,
+) -> Result<()> {
+    conn.write_all(b"zINSTREAM\0").await?;
+    let mut buf = vec![0u8; FRAME_BYTES];
+    loop {
+        let n = body.read(&mut buf).await.context("failed to read upload")?;
+        if n == 0 {
+            break;
+        }
+        conn.write_all(&(n as u32).to_be_bytes()).await?;
+        conn.write_all(&buf[..n]).await?;
+    }
+    conn.write_all(&0u32.to_be_bytes()).await?;
+    conn.flush().await?;
+    Ok(())
+}
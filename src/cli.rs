@@ -0,0 +1,89 @@
+use clap::{Parser, Subcommand};
+
+use crate::build_info;
+use crate::tombstones::RemovalReason;
+
+/// Command line of the `juicebox` binary. No command means `serve`, so
+/// existing deployments that start the bare binary keep working.
+#[derive(Debug, Parser)]
+#[command(name = "juicebox", version = build_info::VERSION, about = "File hosting server")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// What the binary was asked to run.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Subcommand)]
+pub enum Command {
+    /// Run the HTTP server (default)
+    #[default]
+    Serve,
+    /// Move legacy metadata into the configured store and exit
+    Migrate,
+    /// Remove expired files, sessions and chunks once and exit
+    Gc,
+    /// Validate the config file and environment and exit
+    CheckConfig,
+    /// Manage bans and files without the web UI
+    #[command(subcommand)]
+    Admin(AdminCommand),
+}
+
+impl Command {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Command::Serve => "serve",
+            Command::Migrate => "migrate",
+            Command::Gc => "gc",
+            Command::CheckConfig => "check-config",
+            Command::Admin(_) => "admin",
+        }
+    }
+}
+
+/// `juicebox admin ...`: the admin UI's ban and file actions, run straight
+/// against the stores.
+#[derive(Clone, Debug, PartialEq, Eq, Subcommand)]
+pub enum AdminCommand {
+    /// List bans with hit counts
+    #[command(name = "bans")]
+    ListBans,
+    /// Add a ban; also country:XX or AS<number> with a GeoIP database
+    Ban {
+        /// IP, CIDR or hash to ban
+        target: String,
+        /// Note kept with the ban
+        #[arg(long, default_value = "")]
+        reason: String,
+    },
+    /// Remove a ban
+    Unban {
+        /// IP, CIDR, hash or ban key
+        target: String,
+    },
+    /// Remove a file
+    Delete {
+        /// Storage name of the file
+        file: String,
+        /// other, malware, phishing, copyright, illegal, spam or abuse
+        #[arg(long, default_value = "other", value_parser = parse_removal_reason)]
+        reason: RemovalReason,
+    },
+    /// Files and bytes per owner
+    Owners,
+}
+
+fn parse_removal_reason(raw: &str) -> Result<RemovalReason, String> {
+    RemovalReason::parse(raw).ok_or_else(|| format!("unknown removal reason '{raw}'"))
+}
+
+/// Parse the arguments after the program name. Help and version requests
+/// come back as errors of the matching [`clap::error::ErrorKind`].
+pub fn parse<I, S>(args: I) -> Result<Command, clap::Error>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<std::ffi::OsString> + Clone,
+{
+    let args = std::iter::once("juicebox".into()).chain(args.into_iter().map(Into::into));
+    Cli::try_parse_from(args).map(|cli| cli.command.unwrap_or_default())
+}
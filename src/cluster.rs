@@ -0,0 +1,72 @@
+//! Running several replicas over one shared chunk directory. Each replica
+//! has a node ID; chunk sessions remember the node that created them, and
+//! chunk and tus responses name that node in `X-Juicebox-Node` so a load
+//! balancer can pin the rest of the upload to it. Requests that land on
+//! another replica still work: the session is read from the shared
+//! directory (see [`AppState::chunk_session`]).
+//!
+//! [`AppState::chunk_session`]: crate::state::AppState::chunk_session
+
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::{HeaderName, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use tracing::warn;
+
+use crate::handlers::telemetry::chunk_session_id;
+use crate::state::AppState;
+use crate::util::new_id;
+
+/// Names the replica that owns a chunk or tus upload session.
+pub const NODE_HEADER: HeaderName = HeaderName::from_static("x-juicebox-node");
+
+/// `JUICEBOX_NODE_ID`, else the host name, else a random ID for this run.
+pub fn node_id_from_env() -> String {
+    ["JUICEBOX_NODE_ID", "HOSTNAME"]
+        .iter()
+        .filter_map(|key| std::env::var(key).ok())
+        .map(|v| v.trim().to_string())
+        .find(|v| !v.is_empty())
+        .unwrap_or_else(new_id)
+}
+
+/// Whether `id` could be a session ID, and so is safe to join onto the
+/// chunk directory.
+pub fn is_session_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 64 && id.bytes().all(|b| b.is_ascii_alphanumeric())
+}
+
+/// Session ID for `/chunk/{id}/...` and `/tus/{id}` requests.
+fn upload_session_id(path: &str) -> Option<&str> {
+    chunk_session_id(path).or_else(|| {
+        path.strip_prefix("/tus/")
+            .filter(|id| !id.is_empty() && !id.contains('/'))
+    })
+}
+
+/// Add `X-Juicebox-Node` to chunk and tus responses: the node that created
+/// the session when it is known, otherwise this one.
+pub async fn advertise_node(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let path = req.uri().path();
+    if !(path.starts_with("/chunk/") || path == "/tus" || path.starts_with("/tus/")) {
+        return next.run(req).await;
+    }
+    let session_id = upload_session_id(path).map(str::to_string);
+    let mut resp = next.run(req).await;
+    let session_node = session_id
+        .and_then(|id| state.chunk_sessions.get(&id).map(|s| s.node.clone()))
+        .flatten();
+    let node = session_node.as_deref().unwrap_or(&*state.node_id);
+    match HeaderValue::from_str(node) {
+        Ok(value) => {
+            resp.headers_mut().insert(NODE_HEADER, value);
+        }
+        Err(err) => warn!(?err, node, "node id is not a valid header value"),
+    }
+    resp
+}
@@ -0,0 +1,553 @@
+use anyhow::Context;
+use serde::Deserialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Config file read when `JUICEBOX_CONFIG` is unset; missing is fine.
+pub const DEFAULT_CONFIG_PATH: &str = "juicebox.toml";
+
+/// A credential that never shows up in `Debug` output or logs.
+#[derive(Deserialize, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"***\"")
+    }
+}
+
+/// One config value that also has an env var: it can be rendered for the
+/// environment and overwritten from it.
+trait Setting {
+    fn render(&self) -> Option<String>;
+    fn parse(&mut self, raw: &str);
+}
+
+macro_rules! scalar_setting {
+    ($($ty:ty),*) => {$(
+        impl Setting for Option<$ty> {
+            fn render(&self) -> Option<String> {
+                self.as_ref().map(ToString::to_string)
+            }
+
+            fn parse(&mut self, raw: &str) {
+                if let Ok(value) = raw.trim().parse() {
+                    *self = Some(value);
+                }
+            }
+        }
+    )*};
+}
+
+scalar_setting!(u64, u32, u16, usize, f32, f64);
+
+impl Setting for Option<String> {
+    fn render(&self) -> Option<String> {
+        self.clone()
+    }
+
+    fn parse(&mut self, raw: &str) {
+        let raw = raw.trim();
+        *self = (!raw.is_empty()).then(|| raw.to_string());
+    }
+}
+
+impl Setting for Option<PathBuf> {
+    fn render(&self) -> Option<String> {
+        self.as_ref().map(|path| path.display().to_string())
+    }
+
+    fn parse(&mut self, raw: &str) {
+        let raw = raw.trim();
+        *self = (!raw.is_empty()).then(|| PathBuf::from(raw));
+    }
+}
+
+impl Setting for Option<Secret> {
+    fn render(&self) -> Option<String> {
+        self.as_ref().map(|secret| secret.0.clone())
+    }
+
+    fn parse(&mut self, raw: &str) {
+        let raw = raw.trim();
+        *self = (!raw.is_empty()).then(|| Secret(raw.to_string()));
+    }
+}
+
+impl Setting for Option<bool> {
+    fn render(&self) -> Option<String> {
+        self.map(|value| value.to_string())
+    }
+
+    fn parse(&mut self, raw: &str) {
+        *self = Some(matches!(
+            raw.trim().to_ascii_lowercase().as_str(),
+            "1" | "true" | "yes" | "on"
+        ));
+    }
+}
+
+impl Setting for Option<Vec<String>> {
+    fn render(&self) -> Option<String> {
+        self.as_ref().map(|items| items.join(","))
+    }
+
+    fn parse(&mut self, raw: &str) {
+        *self = Some(
+            raw.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect(),
+        );
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub app_env: Option<String>,
+    pub prod_host: Option<String>,
+    pub trust_proxy_headers: Option<bool>,
+    pub trusted_proxy_cidrs: Option<Vec<String>>,
+    pub ip_hash_secret: Option<Secret>,
+    /// `id:secret` pairs, signing key first; see [`crate::util::SigningKeys`].
+    pub signing_keys: Option<Secret>,
+    pub share_links: Option<String>,
+    pub accounts: Option<bool>,
+    pub streaming_uploads: Option<bool>,
+    pub log_format: Option<String>,
+    pub max_connections: Option<usize>,
+    pub max_connections_per_ip: Option<usize>,
+    pub feature_flags: Option<Vec<String>>,
+    /// PEM certificate chain; with `tls_key_path`, serve HTTPS directly.
+    pub tls_cert_path: Option<PathBuf>,
+    pub tls_key_path: Option<PathBuf>,
+    pub drain_timeout_secs: Option<u64>,
+    /// Name this replica gives in `X-Juicebox-Node`.
+    pub node_id: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Per-upload limit, e.g. `750MB`, `1GB` or raw bytes.
+    pub max_file_size: Option<String>,
+    pub max_filename_length: Option<usize>,
+    pub owner_quota_bytes: Option<u64>,
+    pub storage_soft_limit_bytes: Option<u64>,
+    pub storage_hard_limit_bytes: Option<u64>,
+    pub storage_min_ttl_secs: Option<u64>,
+    pub tombstone_grace_secs: Option<u64>,
+    pub owners_persist_debounce_secs: Option<u64>,
+    pub nonresidential_rate_cost: Option<u32>,
+    /// Extensions refused on upload; replaces the built-in list.
+    pub forbidden_extensions: Option<Vec<String>>,
+    pub rate_limit_burst: Option<u32>,
+    pub rate_limit_refill_per_sec: Option<u32>,
+    pub rate_limit_idle_secs: Option<u64>,
+    /// `policy=limit` overrides, e.g. `report=3/2` or `files=general`.
+    pub rate_limit_routes: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub root: Option<PathBuf>,
+    pub data_dir: Option<PathBuf>,
+    pub upload_dir: Option<PathBuf>,
+    /// Watch `upload_dir` for bodies deleted by hand.
+    pub upload_watch: Option<bool>,
+    pub chunk_dir: Option<PathBuf>,
+    pub public_dir: Option<PathBuf>,
+    pub metadata_store: Option<String>,
+    /// Store that takes over while `metadata_store` is down.
+    pub metadata_fallback: Option<String>,
+    pub metadata_failover_threshold: Option<u32>,
+    pub metadata_failover_cooldown_secs: Option<u64>,
+    pub sqlite_path: Option<PathBuf>,
+    pub redis_url: Option<Secret>,
+    pub redis_prefix: Option<String>,
+    pub postgres_url: Option<Secret>,
+    pub postgres_prefix: Option<String>,
+    pub postgres_pool_size: Option<usize>,
+    pub file_store: Option<String>,
+    pub hash_blocklist: Option<PathBuf>,
+    pub clamd_address: Option<String>,
+    pub clamd_timeout_secs: Option<u64>,
+    pub clamd_fail_closed: Option<bool>,
+    pub ttl_policy: Option<PathBuf>,
+    pub feature_flags_file: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct S3StoreConfig {
+    pub bucket: Option<String>,
+    pub region: Option<String>,
+    pub endpoint: Option<String>,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<Secret>,
+    pub prefix: Option<String>,
+    pub path_style: Option<bool>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MailConfig {
+    pub mailgun_api_key: Option<Secret>,
+    pub mailgun_domain: Option<String>,
+    pub report_to: Option<String>,
+    pub report_from: Option<String>,
+    /// Discord or Slack incoming webhook that reports are posted to.
+    pub chat_webhook: Option<Secret>,
+    /// `discord` or `slack`; guessed from `chat_webhook` when unset.
+    pub chat_kind: Option<String>,
+    /// SMTP server used for report emails when Mailgun is not configured.
+    pub smtp_host: Option<String>,
+    pub smtp_port: Option<u16>,
+    /// `tls`, `starttls` (default) or `none`.
+    pub smtp_security: Option<String>,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<Secret>,
+    /// `full`, `truncate` or `hmac`: how hashes appear in report emails.
+    pub privacy: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SentryConfig {
+    pub dsn: Option<Secret>,
+    pub environment: Option<String>,
+    pub release: Option<String>,
+    pub sample_rate: Option<f32>,
+    pub traces_sample_rate: Option<f32>,
+    pub traces_sample_rate_min: Option<f32>,
+    pub traces_error_budget: Option<f64>,
+    pub profiles_sample_rate: Option<f32>,
+    pub trace_propagation_targets: Option<Vec<String>>,
+    pub ignored_routes: Option<Vec<String>>,
+    pub respect_privacy_signals: Option<bool>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworksConfig {
+    pub tor_exit_list: Option<String>,
+    pub hosting_list: Option<String>,
+    pub refresh_secs: Option<u64>,
+    pub premoderate: Option<Vec<String>>,
+    /// MaxMind country (or city) database for country bans.
+    pub geoip_country_db: Option<String>,
+    /// MaxMind ASN database for ASN bans.
+    pub geoip_asn_db: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShadowSettings {
+    pub url: Option<String>,
+    pub sample: Option<f64>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsSettings {
+    /// Origins that may call the upload endpoints from a browser.
+    pub upload_origins: Option<Vec<String>>,
+    /// Origins that may call the other public `/api/v1` endpoints.
+    pub api_origins: Option<Vec<String>>,
+    pub max_age_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookSettings {
+    pub urls: Option<Vec<String>>,
+    pub secret: Option<Secret>,
+    pub timeout_secs: Option<u64>,
+}
+
+/// Server configuration from `juicebox.toml`, with every env var that is set
+/// taking precedence over the file. Unset values keep each subsystem's own
+/// default.
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub limits: LimitsConfig,
+    pub storage: StorageConfig,
+    pub s3: S3StoreConfig,
+    pub mail: MailConfig,
+    pub sentry: SentryConfig,
+    pub networks: NetworksConfig,
+    pub shadow: ShadowSettings,
+    pub webhooks: WebhookSettings,
+    pub cors: CorsSettings,
+    /// The file these values were read from, if any.
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
+}
+
+impl Config {
+    /// Every setting paired with the env var that overrides it.
+    fn visit(&mut self, f: &mut dyn FnMut(&'static str, &mut dyn Setting)) {
+        let server = &mut self.server;
+        f("APP_ENV", &mut server.app_env);
+        f("JUICEBOX_PROD_HOST", &mut server.prod_host);
+        f("TRUST_PROXY_HEADERS", &mut server.trust_proxy_headers);
+        f("TRUSTED_PROXY_CIDRS", &mut server.trusted_proxy_cidrs);
+        f("IP_HASH_SECRET", &mut server.ip_hash_secret);
+        f("JUICEBOX_SIGNING_KEYS", &mut server.signing_keys);
+        f("JUICEBOX_SHARE_LINKS", &mut server.share_links);
+        f("JUICEBOX_ACCOUNTS", &mut server.accounts);
+        f("ENABLE_STREAMING_UPLOADS", &mut server.streaming_uploads);
+        f("LOG_FORMAT", &mut server.log_format);
+        f("JUICEBOX_MAX_CONNECTIONS", &mut server.max_connections);
+        f(
+            "JUICEBOX_MAX_CONNECTIONS_PER_IP",
+            &mut server.max_connections_per_ip,
+        );
+        f("JUICEBOX_FEATURE_FLAGS", &mut server.feature_flags);
+        f("TLS_CERT_PATH", &mut server.tls_cert_path);
+        f("TLS_KEY_PATH", &mut server.tls_key_path);
+        f(
+            "JUICEBOX_DRAIN_TIMEOUT_SECS",
+            &mut server.drain_timeout_secs,
+        );
+        f("JUICEBOX_NODE_ID", &mut server.node_id);
+
+        let limits = &mut self.limits;
+        f("MAX_FILE_SIZE", &mut limits.max_file_size);
+        f("MAX_FILENAME_LENGTH", &mut limits.max_filename_length);
+        f("JUICEBOX_OWNER_QUOTA_BYTES", &mut limits.owner_quota_bytes);
+        f(
+            "JUICEBOX_STORAGE_SOFT_LIMIT_BYTES",
+            &mut limits.storage_soft_limit_bytes,
+        );
+        f(
+            "JUICEBOX_STORAGE_HARD_LIMIT_BYTES",
+            &mut limits.storage_hard_limit_bytes,
+        );
+        f(
+            "JUICEBOX_STORAGE_MIN_TTL_SECS",
+            &mut limits.storage_min_ttl_secs,
+        );
+        f(
+            "JUICEBOX_TOMBSTONE_GRACE_SECS",
+            &mut limits.tombstone_grace_secs,
+        );
+        f(
+            "JUICEBOX_OWNERS_PERSIST_DEBOUNCE_SECS",
+            &mut limits.owners_persist_debounce_secs,
+        );
+        f(
+            "JUICEBOX_NONRESIDENTIAL_RATE_COST",
+            &mut limits.nonresidential_rate_cost,
+        );
+        f(
+            "JUICEBOX_FORBIDDEN_EXTENSIONS",
+            &mut limits.forbidden_extensions,
+        );
+        f("JUICEBOX_RATE_LIMIT_BURST", &mut limits.rate_limit_burst);
+        f(
+            "JUICEBOX_RATE_LIMIT_REFILL_PER_SEC",
+            &mut limits.rate_limit_refill_per_sec,
+        );
+        f(
+            "JUICEBOX_RATE_LIMIT_IDLE_SECS",
+            &mut limits.rate_limit_idle_secs,
+        );
+        f("JUICEBOX_RATE_LIMIT_ROUTES", &mut limits.rate_limit_routes);
+
+        let storage = &mut self.storage;
+        f("JUICEBOX_STORAGE_ROOT", &mut storage.root);
+        f("JUICEBOX_DATA_DIR", &mut storage.data_dir);
+        f("JUICEBOX_UPLOAD_DIR", &mut storage.upload_dir);
+        f("JUICEBOX_UPLOAD_WATCH", &mut storage.upload_watch);
+        f("JUICEBOX_CHUNK_DIR", &mut storage.chunk_dir);
+        f("JUICEBOX_PUBLIC_DIR", &mut storage.public_dir);
+        f("JUICEBOX_METADATA_STORE", &mut storage.metadata_store);
+        f("JUICEBOX_METADATA_FALLBACK", &mut storage.metadata_fallback);
+        f(
+            "JUICEBOX_METADATA_FAILOVER_THRESHOLD",
+            &mut storage.metadata_failover_threshold,
+        );
+        f(
+            "JUICEBOX_METADATA_FAILOVER_COOLDOWN_SECS",
+            &mut storage.metadata_failover_cooldown_secs,
+        );
+        f("JUICEBOX_SQLITE_PATH", &mut storage.sqlite_path);
+        f("JUICEBOX_REDIS_URL", &mut storage.redis_url);
+        f("JUICEBOX_REDIS_PREFIX", &mut storage.redis_prefix);
+        f("JUICEBOX_POSTGRES_URL", &mut storage.postgres_url);
+        f("JUICEBOX_POSTGRES_PREFIX", &mut storage.postgres_prefix);
+        f(
+            "JUICEBOX_POSTGRES_POOL_SIZE",
+            &mut storage.postgres_pool_size,
+        );
+        f("JUICEBOX_FILE_STORE", &mut storage.file_store);
+        f("JUICEBOX_HASH_BLOCKLIST", &mut storage.hash_blocklist);
+        f("JUICEBOX_CLAMD_ADDRESS", &mut storage.clamd_address);
+        f(
+            "JUICEBOX_CLAMD_TIMEOUT_SECS",
+            &mut storage.clamd_timeout_secs,
+        );
+        f("JUICEBOX_CLAMD_FAIL_CLOSED", &mut storage.clamd_fail_closed);
+        f("JUICEBOX_TTL_POLICY", &mut storage.ttl_policy);
+        f(
+            "JUICEBOX_FEATURE_FLAGS_FILE",
+            &mut storage.feature_flags_file,
+        );
+
+        let s3 = &mut self.s3;
+        f("S3_BUCKET", &mut s3.bucket);
+        f("S3_REGION", &mut s3.region);
+        f("S3_ENDPOINT", &mut s3.endpoint);
+        f("S3_ACCESS_KEY_ID", &mut s3.access_key_id);
+        f("S3_SECRET_ACCESS_KEY", &mut s3.secret_access_key);
+        f("S3_PREFIX", &mut s3.prefix);
+        f("S3_PATH_STYLE", &mut s3.path_style);
+
+        let mail = &mut self.mail;
+        f("MAILGUN_API_KEY", &mut mail.mailgun_api_key);
+        f("MAILGUN_DOMAIN", &mut mail.mailgun_domain);
+        f("REPORT_EMAIL_TO", &mut mail.report_to);
+        f("REPORT_EMAIL_FROM", &mut mail.report_from);
+        f("REPORT_CHAT_WEBHOOK_URL", &mut mail.chat_webhook);
+        f("REPORT_CHAT_KIND", &mut mail.chat_kind);
+        f("SMTP_HOST", &mut mail.smtp_host);
+        f("SMTP_PORT", &mut mail.smtp_port);
+        f("SMTP_SECURITY", &mut mail.smtp_security);
+        f("SMTP_USERNAME", &mut mail.smtp_username);
+        f("SMTP_PASSWORD", &mut mail.smtp_password);
+        f("JUICEBOX_EMAIL_PRIVACY", &mut mail.privacy);
+
+        let sentry = &mut self.sentry;
+        f("SENTRY_DSN", &mut sentry.dsn);
+        f("SENTRY_ENV", &mut sentry.environment);
+        f("SENTRY_RELEASE", &mut sentry.release);
+        f("SENTRY_SAMPLE_RATE", &mut sentry.sample_rate);
+        f("SENTRY_TRACES_SAMPLE_RATE", &mut sentry.traces_sample_rate);
+        f(
+            "SENTRY_TRACES_SAMPLE_RATE_MIN",
+            &mut sentry.traces_sample_rate_min,
+        );
+        f(
+            "SENTRY_TRACES_ERROR_BUDGET",
+            &mut sentry.traces_error_budget,
+        );
+        f(
+            "SENTRY_PROFILES_SAMPLE_RATE",
+            &mut sentry.profiles_sample_rate,
+        );
+        f(
+            "SENTRY_TRACE_PROPAGATION_TARGETS",
+            &mut sentry.trace_propagation_targets,
+        );
+        f("SENTRY_IGNORED_ROUTES", &mut sentry.ignored_routes);
+        f(
+            "TELEMETRY_RESPECT_PRIVACY_SIGNALS",
+            &mut sentry.respect_privacy_signals,
+        );
+
+        let networks = &mut self.networks;
+        f("JUICEBOX_TOR_EXIT_LIST", &mut networks.tor_exit_list);
+        f("JUICEBOX_HOSTING_LIST", &mut networks.hosting_list);
+        f(
+            "JUICEBOX_NETWORK_LIST_REFRESH_SECS",
+            &mut networks.refresh_secs,
+        );
+        f("JUICEBOX_PREMODERATE_NETWORKS", &mut networks.premoderate);
+        f("JUICEBOX_GEOIP_COUNTRY_DB", &mut networks.geoip_country_db);
+        f("JUICEBOX_GEOIP_ASN_DB", &mut networks.geoip_asn_db);
+
+        f("JUICEBOX_SHADOW_URL", &mut self.shadow.url);
+        f("JUICEBOX_SHADOW_SAMPLE", &mut self.shadow.sample);
+
+        let webhooks = &mut self.webhooks;
+        f("JUICEBOX_WEBHOOK_URLS", &mut webhooks.urls);
+        f("JUICEBOX_WEBHOOK_SECRET", &mut webhooks.secret);
+        f("JUICEBOX_WEBHOOK_TIMEOUT_SECS", &mut webhooks.timeout_secs);
+
+        let cors = &mut self.cors;
+        f("JUICEBOX_CORS_UPLOAD_ORIGINS", &mut cors.upload_origins);
+        f("JUICEBOX_CORS_API_ORIGINS", &mut cors.api_origins);
+        f("JUICEBOX_CORS_MAX_AGE_SECS", &mut cors.max_age_secs);
+    }
+
+    /// Parse a config file's contents.
+    pub fn parse(contents: &str) -> anyhow::Result<Self> {
+        toml::from_str(contents).context("invalid config file")
+    }
+
+    /// Read `JUICEBOX_CONFIG`, or `juicebox.toml` in the working directory
+    /// when that is unset. Only an explicitly named file has to exist.
+    pub fn load() -> anyhow::Result<Self> {
+        let explicit = std::env::var("JUICEBOX_CONFIG")
+            .ok()
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty());
+        let path = PathBuf::from(explicit.as_deref().unwrap_or(DEFAULT_CONFIG_PATH));
+        if explicit.is_none() && !path.exists() {
+            return Ok(Self::default());
+        }
+        Self::load_from(&path)
+    }
+
+    pub fn load_from(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+        let mut config = Self::parse(&contents)
+            .with_context(|| format!("failed to load config file {}", path.display()))?;
+        config.source = Some(path.to_path_buf());
+        Ok(config)
+    }
+
+    /// Env vars the file sets, as `(name, value)` pairs.
+    pub fn env_vars(&self) -> Vec<(&'static str, String)> {
+        let mut vars = Vec::new();
+        self.clone().visit(&mut |name, setting| {
+            if let Some(value) = setting.render() {
+                vars.push((name, value));
+            }
+        });
+        vars
+    }
+
+    /// Overwrite file values with every env var `lookup` finds.
+    pub fn overlay(&mut self, lookup: impl Fn(&str) -> Option<String>) {
+        self.visit(&mut |name, setting| {
+            if let Some(raw) = lookup(name) {
+                setting.parse(&raw);
+            }
+        });
+    }
+
+    /// Export the file's values as env vars that are not already set, so the
+    /// subsystems reading their own env vars see them, then fold the
+    /// environment back in. Returns how many vars came from the file.
+    ///
+    /// Must run at startup before anything else reads or writes the
+    /// environment from another thread.
+    pub fn apply_to_env(&mut self) -> usize {
+        let mut applied = 0;
+        for (name, value) in self.env_vars() {
+            if std::env::var_os(name).is_none() {
+                // SAFETY: called once from `main` before other threads touch
+                // the environment, like `dotenvy::dotenv`.
+                unsafe { std::env::set_var(name, value) };
+                applied += 1;
+            }
+        }
+        self.overlay(|name| std::env::var(name).ok());
+        applied
+    }
+}
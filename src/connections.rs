@@ -0,0 +1,336 @@
+use crate::state::AppState;
+use crate::util::{extract_client_ip, is_trusted_proxy, json_error};
+use axum::body::Body;
+use axum::extract::{ConnectInfo, State};
+use axum::http::header::{CONNECTION, RETRY_AFTER};
+use axum::http::{HeaderValue, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+use dashmap::DashMap;
+use serde::Serialize;
+use std::net::{IpAddr, SocketAddr};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+use tower::Service;
+use tracing::{debug, trace};
+
+/// Open connections allowed across all clients unless overridden.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 4096;
+/// Open connections (and in-flight requests) allowed per client IP unless overridden.
+pub const DEFAULT_MAX_CONNECTIONS_PER_IP: usize = 64;
+
+/// Connection caps; `0` disables a cap.
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+pub struct ConnectionLimits {
+    pub max_total: usize,
+    pub max_per_ip: usize,
+}
+
+impl Default for ConnectionLimits {
+    fn default() -> Self {
+        Self {
+            max_total: DEFAULT_MAX_CONNECTIONS,
+            max_per_ip: DEFAULT_MAX_CONNECTIONS_PER_IP,
+        }
+    }
+}
+
+impl ConnectionLimits {
+    /// Read `JUICEBOX_MAX_CONNECTIONS` and `JUICEBOX_MAX_CONNECTIONS_PER_IP`.
+    pub fn from_env() -> Self {
+        let read = |key: &str, default: usize| {
+            std::env::var(key)
+                .ok()
+                .and_then(|v| v.trim().parse::<usize>().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            max_total: read("JUICEBOX_MAX_CONNECTIONS", DEFAULT_MAX_CONNECTIONS),
+            max_per_ip: read(
+                "JUICEBOX_MAX_CONNECTIONS_PER_IP",
+                DEFAULT_MAX_CONNECTIONS_PER_IP,
+            ),
+        }
+    }
+}
+
+/// Why a connection or request was turned away.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionRejected {
+    Global,
+    PerIp,
+}
+
+/// Counts open connections and in-flight requests and enforces
+/// [`ConnectionLimits`], independent of the request rate limiter.
+pub struct ConnectionTracker {
+    limits: ConnectionLimits,
+    open: AtomicUsize,
+    peak: AtomicUsize,
+    accepted: AtomicU64,
+    rejected: AtomicU64,
+    per_ip: DashMap<IpAddr, usize>,
+    in_flight: DashMap<String, usize>,
+    rejected_requests: AtomicU64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ConnectionStats {
+    pub open: usize,
+    pub peak: usize,
+    pub accepted: u64,
+    pub rejected: u64,
+    pub distinct_ips: usize,
+    pub busiest_ip_connections: usize,
+    pub in_flight_requests: usize,
+    pub rejected_requests: u64,
+    pub limits: ConnectionLimits,
+}
+
+fn over(limit: usize, current: usize) -> bool {
+    limit > 0 && current >= limit
+}
+
+impl ConnectionTracker {
+    pub fn new(limits: ConnectionLimits) -> Self {
+        Self {
+            limits,
+            open: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+            accepted: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            per_ip: DashMap::new(),
+            in_flight: DashMap::new(),
+            rejected_requests: AtomicU64::new(0),
+        }
+    }
+
+    pub fn limits(&self) -> ConnectionLimits {
+        self.limits
+    }
+
+    /// Admit a new connection from `ip`. The per-IP cap is skipped for peers
+    /// that are trusted proxies, since every proxied client shares their
+    /// address; [`connection_gate`] covers those clients per request.
+    pub fn try_open(
+        self: &Arc<Self>,
+        ip: IpAddr,
+        count_per_ip: bool,
+    ) -> Result<ConnectionGuard, ConnectionRejected> {
+        let admitted = self
+            .open
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |open| {
+                (!over(self.limits.max_total, open)).then_some(open + 1)
+            });
+        let Ok(previous) = admitted else {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(ConnectionRejected::Global);
+        };
+        if count_per_ip {
+            let mut count = self.per_ip.entry(ip).or_insert(0);
+            if over(self.limits.max_per_ip, *count) {
+                drop(count);
+                self.open.fetch_sub(1, Ordering::AcqRel);
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                return Err(ConnectionRejected::PerIp);
+            }
+            *count += 1;
+        }
+        self.peak.fetch_max(previous + 1, Ordering::Relaxed);
+        self.accepted.fetch_add(1, Ordering::Relaxed);
+        Ok(ConnectionGuard {
+            tracker: self.clone(),
+            ip: count_per_ip.then_some(ip),
+        })
+    }
+
+    /// Admit a request from `client` (the resolved client IP).
+    pub fn try_begin_request(
+        self: &Arc<Self>,
+        client: &str,
+    ) -> Result<RequestGuard, ConnectionRejected> {
+        let mut count = self.in_flight.entry(client.to_string()).or_insert(0);
+        if over(self.limits.max_per_ip, *count) {
+            drop(count);
+            self.rejected_requests.fetch_add(1, Ordering::Relaxed);
+            return Err(ConnectionRejected::PerIp);
+        }
+        *count += 1;
+        Ok(RequestGuard {
+            tracker: self.clone(),
+            client: client.to_string(),
+        })
+    }
+
+    pub fn open_connections(&self) -> usize {
+        self.open.load(Ordering::Acquire)
+    }
+
+    pub fn connections_from(&self, ip: IpAddr) -> usize {
+        self.per_ip.get(&ip).map(|c| *c).unwrap_or(0)
+    }
+
+    pub fn stats(&self) -> ConnectionStats {
+        ConnectionStats {
+            open: self.open_connections(),
+            peak: self.peak.load(Ordering::Relaxed),
+            accepted: self.accepted.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            distinct_ips: self.per_ip.len(),
+            busiest_ip_connections: self.per_ip.iter().map(|e| *e.value()).max().unwrap_or(0),
+            in_flight_requests: self.in_flight.iter().map(|e| *e.value()).sum(),
+            rejected_requests: self.rejected_requests.load(Ordering::Relaxed),
+            limits: self.limits,
+        }
+    }
+}
+
+fn release<K: std::hash::Hash + Eq>(map: &DashMap<K, usize>, key: &K) {
+    map.remove_if_mut(key, |_, count| {
+        *count = count.saturating_sub(1);
+        *count == 0
+    });
+}
+
+/// Held for the lifetime of a connection; releases its slot on drop.
+pub struct ConnectionGuard {
+    tracker: Arc<ConnectionTracker>,
+    ip: Option<IpAddr>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.tracker.open.fetch_sub(1, Ordering::AcqRel);
+        if let Some(ip) = self.ip {
+            release(&self.tracker.per_ip, &ip);
+        }
+    }
+}
+
+/// Held while a request is in flight; releases its slot on drop.
+pub struct RequestGuard {
+    tracker: Arc<ConnectionTracker>,
+    client: String,
+}
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        release(&self.tracker.in_flight, &self.client);
+    }
+}
+
+fn too_many_connections(rejected: ConnectionRejected) -> Response {
+    let (code, message) = match rejected {
+        ConnectionRejected::Global => ("server_busy", "too many open connections"),
+        ConnectionRejected::PerIp => (
+            "too_many_connections",
+            "too many connections from your address",
+        ),
+    };
+    let mut resp = json_error(StatusCode::TOO_MANY_REQUESTS, code, message);
+    resp.headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from_static("5"));
+    resp
+}
+
+/// Wraps the make-service handed to the listener so every accepted
+/// connection is counted, and connections over a cap only ever get a 429.
+#[derive(Clone)]
+pub struct TrackConnections<M> {
+    inner: M,
+    tracker: Arc<ConnectionTracker>,
+}
+
+impl<M> TrackConnections<M> {
+    pub fn new(inner: M, tracker: Arc<ConnectionTracker>) -> Self {
+        Self { inner, tracker }
+    }
+}
+
+impl<M> Service<SocketAddr> for TrackConnections<M>
+where
+    M: Service<SocketAddr>,
+    M::Future: Send + 'static,
+{
+    type Response = TrackedConnection<M::Response>;
+    type Error = M::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, addr: SocketAddr) -> Self::Future {
+        let ip = addr.ip();
+        let admission = self.tracker.try_open(ip, !is_trusted_proxy(ip));
+        match &admission {
+            Ok(_) => trace!(%ip, open = self.tracker.open_connections(), "connection accepted"),
+            Err(reason) => debug!(%ip, ?reason, "connection over cap; answering with 429"),
+        }
+        let fut = self.inner.call(addr);
+        Box::pin(async move {
+            let inner = fut.await?;
+            Ok(TrackedConnection {
+                inner,
+                admission: Arc::new(admission),
+            })
+        })
+    }
+}
+
+/// Per-connection service produced by [`TrackConnections`].
+#[derive(Clone)]
+pub struct TrackedConnection<S> {
+    inner: S,
+    admission: Arc<Result<ConnectionGuard, ConnectionRejected>>,
+}
+
+impl<S, B> Service<Request<B>> for TrackedConnection<S>
+where
+    S: Service<Request<B>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        if let Err(rejected) = self.admission.as_ref() {
+            let mut resp = too_many_connections(*rejected);
+            resp.headers_mut()
+                .insert(CONNECTION, HeaderValue::from_static("close"));
+            return Box::pin(async move { Ok(resp) });
+        }
+        let fut = self.inner.call(req);
+        Box::pin(fut)
+    }
+}
+
+/// Cap in-flight requests per resolved client IP. Behind a proxy every
+/// client shares the proxy's connections, so this is where their per-IP
+/// limit is enforced.
+pub async fn connection_gate(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let client = extract_client_ip(req.headers(), Some(addr.ip()));
+    match state.connections.try_begin_request(&client) {
+        Ok(_guard) => next.run(req).await,
+        Err(rejected) => {
+            debug!(%client, "request over per-client concurrency cap");
+            too_many_connections(rejected)
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! Checks run on an upload's name and bytes before it is stored. The
+//! forbidden-extension and content-sniffing checks are built in; deployments
+//! can append their own [`ContentScanner`]s (YARA rules, an external HTTP
+//! scanner, ...) to [`AppState::content_scanners`].
+//!
+//! [`AppState::content_scanners`]: crate::state::AppState::content_scanners
+
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::io::AsyncRead;
+use tracing::warn;
+
+use crate::reload;
+use crate::util::is_forbidden_extension;
+
+/// Leading bytes of an upload handed to scanners as [`ScanInput::sample`].
+pub const SAMPLE_BYTES: usize = 8 * 1024;
+
+/// Where the full upload can be read from.
+#[derive(Clone, Copy, Debug)]
+pub enum ScanContent<'a> {
+    /// Spooled or assembled on disk, not yet in the file store.
+    File(&'a Path),
+    /// Held in memory, as pastes and simple-form uploads are.
+    Memory(&'a [u8]),
+}
+
+impl<'a> ScanContent<'a> {
+    /// Stream the whole upload.
+    pub async fn open(&self) -> std::io::Result<Pin<Box<dyn AsyncRead + Send + 'a>>> {
+        match *self {
+            ScanContent::File(path) => Ok(Box::pin(tokio::fs::File::open(path).await?)),
+            ScanContent::Memory(bytes) => Ok(Box::pin(bytes)),
+        }
+    }
+}
+
+/// One upload as scanners see it.
+#[derive(Clone, Copy, Debug)]
+pub struct ScanInput<'a> {
+    /// Name the uploader gave, if any.
+    pub name: Option<&'a str>,
+    /// The first [`SAMPLE_BYTES`] of the upload, or all of it when shorter.
+    pub sample: &'a [u8],
+    pub size: u64,
+    pub content: ScanContent<'a>,
+}
+
+/// Why a scanner turned an upload away. Becomes the JSON error the uploader
+/// sees.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rejection {
+    pub scanner: &'static str,
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: &'static str,
+}
+
+impl Rejection {
+    /// A `400 bad_filetype` rejection.
+    pub fn bad_filetype(scanner: &'static str, message: &'static str) -> Self {
+        Self {
+            scanner,
+            status: StatusCode::BAD_REQUEST,
+            code: "bad_filetype",
+            message,
+        }
+    }
+}
+
+#[async_trait]
+pub trait ContentScanner: Send + Sync {
+    /// Short name used in logs.
+    fn name(&self) -> &'static str;
+    /// `Err` rejects the upload; the remaining scanners are skipped.
+    async fn scan(&self, input: &ScanInput<'_>) -> Result<(), Rejection>;
+}
+
+/// Rejects names whose extension is in `JUICEBOX_FORBIDDEN_EXTENSIONS`.
+pub struct ForbiddenExtensionScanner;
+
+#[async_trait]
+impl ContentScanner for ForbiddenExtensionScanner {
+    fn name(&self) -> &'static str {
+        "forbidden_extension"
+    }
+
+    async fn scan(&self, input: &ScanInput<'_>) -> Result<(), Rejection> {
+        match input.name {
+            Some(name) if is_forbidden_extension(name) => Err(Rejection::bad_filetype(
+                self.name(),
+                "File type not allowed",
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Rejects bytes that `infer` recognises as a forbidden type, whatever the
+/// name says.
+pub struct ForbiddenContentScanner;
+
+#[async_trait]
+impl ContentScanner for ForbiddenContentScanner {
+    fn name(&self) -> &'static str {
+        "forbidden_content"
+    }
+
+    async fn scan(&self, input: &ScanInput<'_>) -> Result<(), Rejection> {
+        match infer::get(input.sample) {
+            Some(kind) if reload::current().is_forbidden_extension(kind.extension()) => Err(
+                Rejection::bad_filetype(self.name(), "File type not allowed (forbidden content)"),
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The scanners every upload passes through, in order.
+#[derive(Clone)]
+pub struct ContentScanners {
+    scanners: Vec<Arc<dyn ContentScanner>>,
+}
+
+impl Default for ContentScanners {
+    /// The built-in extension and content checks.
+    fn default() -> Self {
+        Self {
+            scanners: vec![
+                Arc::new(ForbiddenExtensionScanner),
+                Arc::new(ForbiddenContentScanner),
+            ],
+        }
+    }
+}
+
+impl ContentScanners {
+    /// No scanners at all.
+    pub fn empty() -> Self {
+        Self {
+            scanners: Vec::new(),
+        }
+    }
+
+    /// Run `scanner` after the ones already added.
+    pub fn with(mut self, scanner: Arc<dyn ContentScanner>) -> Self {
+        self.scanners.push(scanner);
+        self
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.scanners.iter().map(|s| s.name()).collect()
+    }
+
+    /// Run every scanner until one rejects the upload.
+    pub async fn scan(&self, input: &ScanInput<'_>) -> Result<(), Rejection> {
+        for scanner in &self.scanners {
+            if let Err(rejection) = scanner.scan(input).await {
+                warn!(
+                    scanner = rejection.scanner,
+                    code = rejection.code,
+                    name = ?input.name,
+                    size = input.size,
+                    "upload rejected by content scanner"
+                );
+                return Err(rejection);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,255 @@
+//! Cross-origin access for browser frontends hosted elsewhere. Uploads and
+//! the public `/api/v1` endpoints each take their own origin list; admin,
+//! sign-in and account routes never answer cross-origin requests. Cookies
+//! and other credentials are never allowed cross-origin.
+
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::header::{
+    ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
+    ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_HEADERS,
+    ACCESS_CONTROL_REQUEST_METHOD, ALLOW, HOST, ORIGIN, VARY,
+};
+use axum::http::{HeaderMap, HeaderValue, Method};
+use axum::middleware::Next;
+use axum::response::Response;
+use std::time::Duration;
+use tracing::{trace, warn};
+
+use crate::state::AppState;
+
+/// How long browsers may cache a preflight, unless
+/// `JUICEBOX_CORS_MAX_AGE_SECS` says otherwise.
+pub const DEFAULT_CORS_MAX_AGE_SECS: u64 = 600;
+
+/// Response headers a cross-origin frontend may read: where an upload went,
+/// how far a resumable one got, when to back off, and what to quote when
+/// reporting a problem.
+const EXPOSED_HEADERS: &str = "location, retry-after, x-request-id, x-juicebox-node, \
+     x-ratelimit-limit, x-ratelimit-remaining, x-ratelimit-reset, upload-offset, \
+     upload-length, upload-expires, tus-resumable, tus-version, tus-extension, tus-max-size";
+
+/// Origins one group of routes answers.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum AllowedOrigins {
+    /// Same-origin only; no CORS headers are sent.
+    #[default]
+    None,
+    /// `*`: any origin.
+    Any,
+    /// Exact `scheme://host[:port]` origins, lowercased.
+    List(Vec<String>),
+}
+
+impl AllowedOrigins {
+    /// Parse a list of origins, where `*` allows any of them.
+    pub fn parse<'a>(origins: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut list = Vec::new();
+        for origin in origins.into_iter().map(str::trim) {
+            if origin == "*" {
+                return AllowedOrigins::Any;
+            }
+            if !(origin.starts_with("http://") || origin.starts_with("https://")) {
+                if !origin.is_empty() {
+                    warn!(origin, "ignoring cors origin that is not http(s)");
+                }
+                continue;
+            }
+            list.push(normalize(origin));
+        }
+        if list.is_empty() {
+            AllowedOrigins::None
+        } else {
+            AllowedOrigins::List(list)
+        }
+    }
+
+    /// Whether `origin` is one of these.
+    pub fn permits(&self, origin: &str) -> bool {
+        self.allow(origin).is_some()
+    }
+
+    /// The `Access-Control-Allow-Origin` value for a request from `origin`,
+    /// if it is allowed.
+    fn allow(&self, origin: &str) -> Option<HeaderValue> {
+        match self {
+            AllowedOrigins::None => None,
+            AllowedOrigins::Any => Some(HeaderValue::from_static("*")),
+            AllowedOrigins::List(list) => {
+                let origin = normalize(origin);
+                list.contains(&origin)
+                    .then(|| HeaderValue::from_str(&origin).ok())
+                    .flatten()
+            }
+        }
+    }
+}
+
+/// Whether the request's `Origin`, if it sent one, names the host the
+/// request was sent to. Requests without one do not come from a web page.
+pub fn is_same_origin(headers: &HeaderMap) -> bool {
+    let Some(origin) = headers.get(ORIGIN) else {
+        return true;
+    };
+    let Some(host) = headers.get(HOST).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    origin
+        .to_str()
+        .ok()
+        .and_then(|origin| origin.split_once("://"))
+        .is_some_and(|(_, authority)| {
+            authority
+                .trim_end_matches('/')
+                .eq_ignore_ascii_case(host.trim())
+        })
+}
+
+fn normalize(origin: &str) -> String {
+    origin.trim().trim_end_matches('/').to_ascii_lowercase()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CorsConfig {
+    pub upload: AllowedOrigins,
+    pub api: AllowedOrigins,
+    pub max_age: Duration,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            upload: AllowedOrigins::None,
+            api: AllowedOrigins::None,
+            max_age: Duration::from_secs(DEFAULT_CORS_MAX_AGE_SECS),
+        }
+    }
+}
+
+impl CorsConfig {
+    /// Read `JUICEBOX_CORS_UPLOAD_ORIGINS`, `JUICEBOX_CORS_API_ORIGINS` (both
+    /// comma separated) and `JUICEBOX_CORS_MAX_AGE_SECS`.
+    pub fn from_env() -> Self {
+        let origins = |name: &str| {
+            std::env::var(name)
+                .map(|v| AllowedOrigins::parse(v.split(',')))
+                .unwrap_or_default()
+        };
+        let max_age = std::env::var("JUICEBOX_CORS_MAX_AGE_SECS")
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .unwrap_or(DEFAULT_CORS_MAX_AGE_SECS);
+        Self {
+            upload: origins("JUICEBOX_CORS_UPLOAD_ORIGINS"),
+            api: origins("JUICEBOX_CORS_API_ORIGINS"),
+            max_age: Duration::from_secs(max_age),
+        }
+    }
+
+    /// Origins allowed to call `path`. Routes outside the upload and public
+    /// API groups, including everything under `/admin` and `/api/admin`,
+    /// stay same-origin whatever is configured.
+    pub fn origins_for(&self, path: &str) -> &AllowedOrigins {
+        if is_upload_path(path) {
+            &self.upload
+        } else if path.starts_with("/api/v1/") && !path.starts_with("/api/v1/accounts/") {
+            &self.api
+        } else {
+            &AllowedOrigins::None
+        }
+    }
+}
+
+fn is_upload_path(path: &str) -> bool {
+    path == "/upload"
+        || path.starts_with("/chunk/")
+        || path == "/tus"
+        || path.starts_with("/tus/")
+        || path == "/api/paste-binary"
+}
+
+/// Add CORS headers for allowed origins. Preflights go through to the
+/// route's own `OPTIONS` handler, and the methods it lists in `Allow` become
+/// `Access-Control-Allow-Methods`.
+pub async fn apply_cors(State(state): State<AppState>, req: Request<Body>, next: Next) -> Response {
+    let Some(origin) = req
+        .headers()
+        .get(ORIGIN)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+    else {
+        return next.run(req).await;
+    };
+    let Some(allow_origin) = state.cors.origins_for(req.uri().path()).allow(&origin) else {
+        return next.run(req).await;
+    };
+    let preflight = req.method() == Method::OPTIONS
+        && req.headers().contains_key(ACCESS_CONTROL_REQUEST_METHOD);
+    if !preflight {
+        let mut resp = next.run(req).await;
+        let headers = resp.headers_mut();
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+        headers.insert(
+            ACCESS_CONTROL_EXPOSE_HEADERS,
+            HeaderValue::from_static(EXPOSED_HEADERS),
+        );
+        headers.append(VARY, HeaderValue::from_static("origin"));
+        return resp;
+    }
+
+    let requested_method = req
+        .headers()
+        .get(ACCESS_CONTROL_REQUEST_METHOD)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_ascii_uppercase())
+        .unwrap_or_default();
+    let requested_headers = req.headers().get(ACCESS_CONTROL_REQUEST_HEADERS).cloned();
+    let path = req.uri().path().to_string();
+    let mut resp = next.run(req).await;
+    if !resp.status().is_success() {
+        return resp;
+    }
+    let Some(methods) = resp.headers().get(ALLOW).cloned() else {
+        return resp;
+    };
+    if !allows_method(&methods, &requested_method) {
+        trace!(
+            path,
+            origin, requested_method, "cors preflight for unlisted method"
+        );
+        return resp;
+    }
+    trace!(path, origin, requested_method, "cors preflight allowed");
+
+    // Keep what the route's own OPTIONS handler advertises, such as tus
+    // versions, alongside the CORS headers.
+    let headers = resp.headers_mut();
+    headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+    headers.insert(ACCESS_CONTROL_ALLOW_METHODS, methods);
+    if let Some(requested) = requested_headers {
+        headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, requested);
+    }
+    headers.insert(
+        ACCESS_CONTROL_MAX_AGE,
+        HeaderValue::from(state.cors.max_age.as_secs()),
+    );
+    append_vary(headers);
+    resp
+}
+
+fn allows_method(allow: &HeaderValue, method: &str) -> bool {
+    allow
+        .to_str()
+        .map(|v| v.split(',').any(|m| m.trim().eq_ignore_ascii_case(method)))
+        .unwrap_or(false)
+}
+
+fn append_vary(headers: &mut HeaderMap) {
+    for value in [
+        "origin",
+        "access-control-request-method",
+        "access-control-request-headers",
+    ] {
+        headers.append(VARY, HeaderValue::from_static(value));
+    }
+}
@@ -0,0 +1,176 @@
+//! `Content-Digest` and `Repr-Digest` fields (RFC 9530).
+//!
+//! Uploads are stored exactly as sent, without content coding, so both
+//! fields describe the same bytes and are checked the same way.
+
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use sha2::{Digest, Sha256, Sha512};
+
+pub const CONTENT_DIGEST: HeaderName = HeaderName::from_static("content-digest");
+pub const REPR_DIGEST: HeaderName = HeaderName::from_static("repr-digest");
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DigestError {
+    /// A digest field is not a structured-field dictionary of byte sequences.
+    Malformed(&'static str),
+    /// A supported digest does not match the body.
+    Mismatch(&'static str),
+}
+
+impl std::fmt::Display for DigestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Malformed(field) => write!(f, "malformed {field} header"),
+            Self::Mismatch(algorithm) => write!(f, "{algorithm} digest does not match the body"),
+        }
+    }
+}
+
+impl std::error::Error for DigestError {}
+
+/// Parse one digest field into `(algorithm, digest)` pairs. Algorithms are
+/// lowercased; parameters are ignored.
+fn parse_field(value: &str) -> Option<Vec<(String, Vec<u8>)>> {
+    let mut digests = Vec::new();
+    for member in value.split(',').map(str::trim) {
+        let (key, rest) = member.split_once('=')?;
+        let item = rest.split(';').next()?.trim();
+        let encoded = item.strip_prefix(':')?.strip_suffix(':')?;
+        let digest = STANDARD.decode(encoded).ok()?;
+        digests.push((key.trim().to_ascii_lowercase(), digest));
+    }
+    Some(digests)
+}
+
+/// Incremental check of a body against the `sha-256` and `sha-512` digests
+/// in some of a request's digest fields, for bodies that are written out as
+/// they arrive. Other algorithms are ignored, as RFC 9530 allows.
+pub struct DigestCheck {
+    expected: Vec<(&'static str, Vec<u8>)>,
+    sha256: Option<Sha256>,
+    sha512: Option<Sha512>,
+}
+
+impl DigestCheck {
+    /// Parse `fields` up front, so a malformed header is refused before any
+    /// of the body is read.
+    pub fn new(headers: &HeaderMap, fields: &[HeaderName]) -> Result<Self, DigestError> {
+        let mut expected = Vec::new();
+        for name in fields {
+            let field = if *name == REPR_DIGEST {
+                "Repr-Digest"
+            } else {
+                "Content-Digest"
+            };
+            for value in headers.get_all(name) {
+                let digests = value
+                    .to_str()
+                    .ok()
+                    .and_then(parse_field)
+                    .ok_or(DigestError::Malformed(field))?;
+                for (algorithm, digest) in digests {
+                    match algorithm.as_str() {
+                        "sha-256" => expected.push(("sha-256", digest)),
+                        "sha-512" => expected.push(("sha-512", digest)),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        let wants = |label| expected.iter().any(|(l, _)| *l == label);
+        Ok(Self {
+            sha256: wants("sha-256").then(Sha256::new),
+            sha512: wants("sha-512").then(Sha512::new),
+            expected,
+        })
+    }
+
+    /// Whether any supported digest was sent.
+    pub fn is_empty(&self) -> bool {
+        self.expected.is_empty()
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        if let Some(hasher) = self.sha256.as_mut() {
+            hasher.update(data);
+        }
+        if let Some(hasher) = self.sha512.as_mut() {
+            hasher.update(data);
+        }
+    }
+
+    /// Compare what was hashed with every expected digest. Returns how many
+    /// were verified.
+    pub fn finish(self) -> Result<usize, DigestError> {
+        let sha256 = self.sha256.map(|h| h.finalize().to_vec());
+        let sha512 = self.sha512.map(|h| h.finalize().to_vec());
+        for (label, expected) in &self.expected {
+            let actual = match *label {
+                "sha-256" => sha256.as_ref(),
+                _ => sha512.as_ref(),
+            };
+            if actual != Some(expected) {
+                return Err(DigestError::Mismatch(label));
+            }
+        }
+        Ok(self.expected.len())
+    }
+}
+
+/// Check `body` against every `sha-256` and `sha-512` digest in the
+/// request's digest fields. Returns how many digests were verified.
+pub fn verify_body(headers: &HeaderMap, body: &[u8]) -> Result<usize, DigestError> {
+    let mut check = DigestCheck::new(headers, &[CONTENT_DIGEST, REPR_DIGEST])?;
+    check.update(body);
+    check.finish()
+}
+
+/// A `sha-256=:…:` field value for a stored file's hex SHA-256, or `None`
+/// when `hex` is not one.
+pub fn sha256_field(hex: &str) -> Option<HeaderValue> {
+    if hex.len() != 64 {
+        return None;
+    }
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    HeaderValue::from_str(&format!("sha-256=:{}:", STANDARD.encode(bytes))).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifies_supported_algorithms_and_skips_others() {
+        // RFC 9530 appendix example for `{"hello": "world"}`.
+        let body = br#"{"hello": "world"}"#;
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_DIGEST,
+            HeaderValue::from_static(
+                "sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:, unixsum=:AAA=:",
+            ),
+        );
+        assert_eq!(verify_body(&headers, body), Ok(1));
+        assert_eq!(
+            verify_body(&headers, b"tampered"),
+            Err(DigestError::Mismatch("sha-256"))
+        );
+        headers.insert(REPR_DIGEST, HeaderValue::from_static("sha-256=abc"));
+        assert_eq!(
+            verify_body(&headers, body),
+            Err(DigestError::Malformed("Repr-Digest"))
+        );
+
+        let hex = format!("{:x}", Sha256::digest(body));
+        assert_eq!(
+            sha256_field(&hex).unwrap(),
+            "sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:"
+        );
+        assert!(sha256_field("legacy").is_none());
+    }
+}
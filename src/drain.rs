@@ -0,0 +1,121 @@
+//! Admin-initiated drain before a deploy: new uploads are refused, uploads
+//! and chunk assemblies already running are allowed to finish, state is
+//! persisted and the server then shuts down the same way it does on SIGTERM.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::state::{AppState, ChunkPhase};
+use crate::util::UPLOAD_CONCURRENCY;
+
+/// Longest a drain waits for running uploads before shutting down anyway.
+pub const DEFAULT_DRAIN_TIMEOUT_SECS: u64 = 300;
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+pub struct Drain {
+    started: AtomicBool,
+    finished: watch::Sender<bool>,
+    timeout: Duration,
+}
+
+impl Default for Drain {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(DEFAULT_DRAIN_TIMEOUT_SECS))
+    }
+}
+
+impl Drain {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            started: AtomicBool::new(false),
+            finished: watch::Sender::new(false),
+            timeout,
+        }
+    }
+
+    /// Read `JUICEBOX_DRAIN_TIMEOUT_SECS`.
+    pub fn from_env() -> Self {
+        let secs = std::env::var("JUICEBOX_DRAIN_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .unwrap_or(DEFAULT_DRAIN_TIMEOUT_SECS);
+        Self::new(Duration::from_secs(secs))
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Whether new uploads are being refused.
+    pub fn is_draining(&self) -> bool {
+        self.started.load(Ordering::Relaxed)
+    }
+
+    /// Start draining. Returns `false` if a drain was already under way.
+    pub fn begin(&self) -> bool {
+        !self.started.swap(true, Ordering::SeqCst)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        *self.finished.borrow()
+    }
+
+    /// Resolves once the drain has finished and the server should shut down.
+    pub async fn finished(&self) {
+        let mut rx = self.finished.subscribe();
+        let _ = rx.wait_for(|done| *done).await;
+    }
+
+    fn finish(&self) {
+        self.finished.send_replace(true);
+    }
+}
+
+/// Uploads holding an upload slot plus chunk sessions being assembled or
+/// verified.
+pub fn in_flight_uploads(state: &AppState) -> usize {
+    let uploading = UPLOAD_CONCURRENCY.saturating_sub(state.upload_sem.available_permits());
+    let assembling = state
+        .chunk_sessions
+        .iter()
+        .filter(|entry| {
+            matches!(
+                entry.value().phase(),
+                ChunkPhase::Assembling | ChunkPhase::Verifying
+            )
+        })
+        .count();
+    uploading + assembling
+}
+
+/// Wait for in-flight uploads (up to the drain timeout), persist state and
+/// mark the drain finished. Call after [`Drain::begin`].
+pub async fn run(state: AppState) {
+    let drain = state.drain.clone();
+    let deadline = tokio::time::Instant::now() + drain.timeout();
+    loop {
+        let in_flight = in_flight_uploads(&state);
+        if in_flight == 0 {
+            break;
+        }
+        if tokio::time::Instant::now() >= deadline {
+            warn!(
+                in_flight,
+                timeout_secs = drain.timeout().as_secs(),
+                "drain timed out; shutting down with uploads still running"
+            );
+            break;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+    state.persist_reports().await;
+    state.persist_bans().await;
+    state.persist_ban_hits().await;
+    state.persist_upload_stats().await;
+    state.flush_owners().await;
+    state.persist_all_chunk_sessions().await;
+    info!("drain complete; shutting down");
+    drain.finish();
+}
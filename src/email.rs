@@ -0,0 +1,289 @@
+//! Outbound email for report notifications. The worker only knows
+//! [`EmailSender`]; Mailgun's HTTP API and SMTP through lettre (with implicit
+//! TLS or STARTTLS) are the two backends, so self-hosters can use whatever mail
+//! server they already have.
+
+use anyhow::{Context, Result, anyhow, bail};
+use async_trait::async_trait;
+use lettre::message::{Mailbox, Mailboxes, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tera::Tera;
+use tracing::{debug, warn};
+
+use crate::handlers::ReportRecordEmail;
+use crate::report_chat::ReportLinks;
+use crate::util::new_id;
+
+/// How long one SMTP delivery may take, connection included, unless
+/// `SMTP_TIMEOUT_SECS` says otherwise.
+pub const DEFAULT_SMTP_TIMEOUT_SECS: u64 = 30;
+/// SMTP replies are short; a longer line means we are not talking to SMTP.
+/// Templates for report notifications; copies in the templates directory
+/// override the built-in ones.
+pub const REPORT_HTML_TEMPLATE: &str = "report_email.html.tera";
+pub const REPORT_TEXT_TEMPLATE: &str = "report_email.txt.tera";
+/// One message, with plain text and HTML alternatives.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct EmailMessage {
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+impl EmailMessage {
+    /// The notification sent to operators for a new report, rendered from
+    /// [`REPORT_HTML_TEMPLATE`] and [`REPORT_TEXT_TEMPLATE`].
+    pub fn report(tera: &Tera, ev: &ReportRecordEmail, from: &str, to: &str) -> tera::Result<Self> {
+        let expires_human = if ev.expires > 0 {
+            format!("{}s", ev.expires.saturating_sub(ev.time))
+        } else {
+            "n/a".into()
+        };
+        let mut ctx = tera::Context::new();
+        ctx.insert("report", ev);
+        ctx.insert("links", &ReportLinks::for_report(ev));
+        ctx.insert("expires_human", &expires_human);
+        Ok(Self {
+            from: from.to_string(),
+            to: to.to_string(),
+            subject: format!("[JuiceBox] Report: {} ({})", ev.file, ev.reason),
+            text: tera.render(REPORT_TEXT_TEMPLATE, &ctx)?.trim().to_string(),
+            html: tera.render(REPORT_HTML_TEMPLATE, &ctx)?,
+        })
+    }
+
+    /// The message as lettre builds it for SMTP, with plain text and HTML
+    /// alternatives. `to` may list several comma-separated addresses.
+    pub fn to_message(&self) -> Result<Message> {
+        let from: Mailbox = self
+            .from
+            .parse()
+            .with_context(|| format!("invalid sender address {:?}", self.from))?;
+        let to: Mailboxes = self
+            .to
+            .parse()
+            .with_context(|| format!("invalid recipient address {:?}", self.to))?;
+        let mut builder = Message::builder()
+            .subject(self.subject.replace(['\r', '\n'], " "))
+            .message_id(Some(format!("<{}@{}>", new_id(), from.email.domain())))
+            .from(from);
+        for mailbox in to {
+            builder = builder.to(mailbox);
+        }
+        builder
+            .multipart(MultiPart::alternative_plain_html(
+                self.text.clone(),
+                self.html.clone(),
+            ))
+            .context("failed to build email message")
+    }
+}
+
+/// Somewhere report emails can be handed to.
+#[async_trait]
+pub trait EmailSender: Send + Sync {
+    /// Short name of the backend, for logs and `check-config`.
+    fn backend_name(&self) -> &'static str;
+    async fn send(&self, message: &EmailMessage) -> Result<()>;
+}
+
+/// Mailgun's HTTP API.
+pub struct MailgunSender {
+    client: reqwest::Client,
+    api_key: String,
+    domain: String,
+}
+
+impl MailgunSender {
+    pub fn new(api_key: String, domain: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_key,
+            domain,
+        }
+    }
+}
+
+#[async_trait]
+impl EmailSender for MailgunSender {
+    fn backend_name(&self) -> &'static str {
+        "mailgun"
+    }
+
+    async fn send(&self, message: &EmailMessage) -> Result<()> {
+        let form = [
+            ("from", message.from.as_str()),
+            ("to", message.to.as_str()),
+            ("subject", message.subject.as_str()),
+            ("text", message.text.as_str()),
+            ("html", message.html.as_str()),
+        ];
+        let url = format!("https://api.eu.mailgun.net/v3/{}/messages", self.domain);
+        let resp = self
+            .client
+            .post(&url)
+            .basic_auth("api", Some(&self.api_key))
+            .form(&form)
+            .send()
+            .await?;
+        let status = resp.status();
+        if !status.is_success() {
+            let body = resp.text().await.unwrap_or_default();
+            bail!("mailgun rejected message: status={status} body={body}");
+        }
+        Ok(())
+    }
+}
+
+/// How the SMTP connection is secured.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SmtpSecurity {
+    /// TLS from the first byte, usually port 465.
+    Tls,
+    /// Plain connection upgraded with `STARTTLS`, usually port 587.
+    StartTls,
+    /// No encryption, for a relay on localhost or a trusted network.
+    None,
+}
+
+impl SmtpSecurity {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "tls" | "ssl" | "smtps" => Some(Self::Tls),
+            "starttls" => Some(Self::StartTls),
+            "none" | "plain" => Some(Self::None),
+            _ => None,
+        }
+    }
+
+    pub fn default_port(self) -> u16 {
+        match self {
+            Self::Tls => 465,
+            Self::StartTls => 587,
+            Self::None => 25,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub timeout: Duration,
+}
+
+impl SmtpConfig {
+    /// Read `SMTP_HOST`, `SMTP_PORT`, `SMTP_SECURITY` (`tls`, `starttls` or
+    /// `none`; default `starttls`), `SMTP_USERNAME`, `SMTP_PASSWORD` and
+    /// `SMTP_TIMEOUT_SECS`. `None` without a host.
+    pub fn from_env() -> Result<Option<Self>> {
+        let var = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+        let Some(host) = var("SMTP_HOST") else {
+            return Ok(None);
+        };
+        let security = match var("SMTP_SECURITY") {
+            Some(raw) => SmtpSecurity::parse(&raw)
+                .ok_or_else(|| anyhow!("SMTP_SECURITY must be tls, starttls or none"))?,
+            None => SmtpSecurity::StartTls,
+        };
+        let port = match var("SMTP_PORT") {
+            Some(raw) => raw.parse().context("SMTP_PORT must be a port number")?,
+            None => security.default_port(),
+        };
+        let timeout = var("SMTP_TIMEOUT_SECS")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_SMTP_TIMEOUT_SECS);
+        let config = Self {
+            host,
+            port,
+            security,
+            username: var("SMTP_USERNAME"),
+            password: var("SMTP_PASSWORD"),
+            timeout: Duration::from_secs(timeout),
+        };
+        if config.security == SmtpSecurity::None && config.username.is_some() {
+            warn!("SMTP credentials will be sent without TLS");
+        }
+        Ok(Some(config))
+    }
+}
+
+/// Delivers through an SMTP server, one connection per message.
+pub struct SmtpSender {
+    config: SmtpConfig,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpSender {
+    pub fn new(config: SmtpConfig) -> Result<Self> {
+        let builder = match config.security {
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)
+                .context("SMTP_HOST is not a valid TLS server name")?,
+            SmtpSecurity::StartTls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)
+                    .context("SMTP_HOST is not a valid TLS server name")?
+            }
+            SmtpSecurity::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host)
+            }
+        };
+        let mut builder = builder.port(config.port).timeout(Some(config.timeout));
+        if let Some(username) = &config.username {
+            builder = builder.credentials(Credentials::new(
+                username.clone(),
+                config.password.clone().unwrap_or_default(),
+            ));
+        }
+        Ok(Self {
+            transport: builder.build(),
+            config,
+        })
+    }
+
+    pub fn config(&self) -> &SmtpConfig {
+        &self.config
+    }
+}
+
+#[async_trait]
+impl EmailSender for SmtpSender {
+    fn backend_name(&self) -> &'static str {
+        "smtp"
+    }
+
+    async fn send(&self, message: &EmailMessage) -> Result<()> {
+        let email = message.to_message()?;
+        let response = tokio::time::timeout(self.config.timeout, self.transport.send(email))
+            .await
+            .map_err(|_| anyhow!("SMTP delivery timed out"))?
+            .map_err(|err| anyhow!("SMTP delivery failed: {err}"))?;
+        debug!(code = %response.code(), "SMTP server accepted message");
+        Ok(())
+    }
+}
+
+/// The configured backend: Mailgun when `MAILGUN_API_KEY` and
+/// `MAILGUN_DOMAIN` are both set, else SMTP when `SMTP_HOST` is.
+pub fn sender_from_env() -> Result<Option<Box<dyn EmailSender>>> {
+    let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+    if let (Some(api_key), Some(domain)) = (var("MAILGUN_API_KEY"), var("MAILGUN_DOMAIN")) {
+        return Ok(Some(Box::new(MailgunSender::new(api_key, domain))));
+    }
+    match SmtpConfig::from_env()? {
+        Some(config) => Ok(Some(Box::new(SmtpSender::new(config)?))),
+        None => Ok(None),
+    }
+}
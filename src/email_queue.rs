@@ -0,0 +1,192 @@
+//! Report emails waiting to be delivered. Every message is written to the
+//! key-value store before the first attempt and only removed once the
+//! backend accepts it, so a failing mail server or a restart does not lose
+//! notifications. Failed deliveries are retried with exponential backoff;
+//! messages that keep failing are dropped with an error event.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+
+use crate::email::{EmailMessage, EmailSender};
+use crate::state::KvStore;
+use crate::util::new_id;
+
+const QUEUE_KEY: &str = "email_queue";
+/// Delay before the first retry; each further failure doubles it.
+pub const EMAIL_RETRY_BASE_SECS: u64 = 30;
+/// Longest wait between two attempts.
+pub const EMAIL_RETRY_MAX_DELAY_SECS: u64 = 3600;
+/// Attempts before a message is given up on, about a day of retries.
+pub const EMAIL_MAX_ATTEMPTS: u32 = 30;
+/// How often the worker looks for messages due a retry.
+pub const EMAIL_QUEUE_POLL: Duration = Duration::from_secs(15);
+
+/// One message and its delivery state.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct QueuedEmail {
+    pub message: EmailMessage,
+    pub queued_at: u64,
+    /// Failed attempts so far.
+    pub attempts: u32,
+    /// Not retried before this time.
+    pub next_attempt: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// Seconds to wait after the `attempts`-th failure.
+pub fn retry_delay(attempts: u32) -> u64 {
+    let doublings = attempts.saturating_sub(1).min(32);
+    EMAIL_RETRY_BASE_SECS
+        .saturating_mul(1u64 << doublings)
+        .min(EMAIL_RETRY_MAX_DELAY_SECS)
+}
+
+/// What one [`EmailQueue::deliver_due`] pass did.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DeliveryOutcome {
+    pub sent: usize,
+    pub retrying: usize,
+    pub dropped: usize,
+}
+
+pub struct EmailQueue {
+    kv: Arc<dyn KvStore>,
+    pending: BTreeMap<String, QueuedEmail>,
+}
+
+impl EmailQueue {
+    /// Messages left over from a previous run are kept and retried.
+    pub async fn load(kv: Arc<dyn KvStore>) -> Result<Self> {
+        let mut pending = BTreeMap::new();
+        for (id, value) in kv.load_hash(QUEUE_KEY).await? {
+            match serde_json::from_str::<QueuedEmail>(&value) {
+                Ok(entry) => {
+                    pending.insert(id, entry);
+                }
+                Err(err) => warn!(?err, id, "skipping malformed queued email"),
+            }
+        }
+        if !pending.is_empty() {
+            info!(count = pending.len(), "loaded pending report emails");
+        }
+        Ok(Self { kv, pending })
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&QueuedEmail> {
+        self.pending.get(id)
+    }
+
+    /// Earliest time a pending message is due, if any.
+    pub fn next_due(&self) -> Option<u64> {
+        self.pending.values().map(|entry| entry.next_attempt).min()
+    }
+
+    /// Queue `message` for immediate delivery and persist it. Returns its id.
+    pub async fn enqueue(&mut self, message: EmailMessage, now: u64) -> String {
+        let id = new_id();
+        self.pending.insert(
+            id.clone(),
+            QueuedEmail {
+                message,
+                queued_at: now,
+                attempts: 0,
+                next_attempt: now,
+                last_error: None,
+            },
+        );
+        self.persist().await;
+        id
+    }
+
+    /// Try every message that is due. Accepted messages leave the queue,
+    /// failed ones are rescheduled, and ones out of attempts are dropped.
+    pub async fn deliver_due(&mut self, sender: &dyn EmailSender, now: u64) -> DeliveryOutcome {
+        let due: Vec<String> = self
+            .pending
+            .iter()
+            .filter(|(_, entry)| entry.next_attempt <= now)
+            .map(|(id, _)| id.clone())
+            .collect();
+        let mut outcome = DeliveryOutcome::default();
+        if due.is_empty() {
+            return outcome;
+        }
+        for id in due {
+            let Some(entry) = self.pending.get_mut(&id) else {
+                continue;
+            };
+            match sender.send(&entry.message).await {
+                Ok(()) => {
+                    info!(
+                        id,
+                        subject = %entry.message.subject,
+                        attempts = entry.attempts + 1,
+                        backend = sender.backend_name(),
+                        "mailed report"
+                    );
+                    self.pending.remove(&id);
+                    outcome.sent += 1;
+                }
+                Err(err) => {
+                    entry.attempts += 1;
+                    entry.last_error = Some(err.to_string());
+                    if entry.attempts >= EMAIL_MAX_ATTEMPTS {
+                        error!(
+                            id,
+                            %err,
+                            subject = %entry.message.subject,
+                            attempts = entry.attempts,
+                            queued_at = entry.queued_at,
+                            backend = sender.backend_name(),
+                            "giving up on report email"
+                        );
+                        self.pending.remove(&id);
+                        outcome.dropped += 1;
+                    } else {
+                        let delay = retry_delay(entry.attempts);
+                        entry.next_attempt = now.saturating_add(delay);
+                        warn!(
+                            id,
+                            %err,
+                            attempts = entry.attempts,
+                            retry_in_secs = delay,
+                            backend = sender.backend_name(),
+                            "failed to mail report, will retry"
+                        );
+                        outcome.retrying += 1;
+                    }
+                }
+            }
+        }
+        self.persist().await;
+        outcome
+    }
+
+    async fn persist(&self) {
+        let mut encoded = Vec::with_capacity(self.pending.len());
+        for (id, entry) in &self.pending {
+            match serde_json::to_string(entry) {
+                Ok(value) => encoded.push((id.clone(), value)),
+                Err(err) => error!(?err, id, "failed to serialize queued email"),
+            }
+        }
+        if let Err(err) = self.kv.replace_hash(QUEUE_KEY, &encoded).await {
+            error!(?err, "failed to persist email queue to key-value store");
+            return;
+        }
+        debug!(count = encoded.len(), "persisted email queue");
+    }
+}
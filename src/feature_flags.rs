@@ -0,0 +1,274 @@
+use anyhow::Context;
+use arc_swap::ArcSwap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+use tracing::{debug, error, info, warn};
+
+use crate::state::AppState;
+
+/// Offer streaming uploads to the frontend. `ENABLE_STREAMING_UPLOADS` still
+/// turns it on for everyone when the flag is not defined.
+pub const STREAMING_UPLOADS: &str = "streaming_uploads";
+
+/// How one flag is rolled out.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FlagRule {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Percentage of owners, 0 to 100, that get the flag while it is
+    /// enabled. Unset means everyone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollout: Option<f64>,
+}
+
+impl FlagRule {
+    fn normalized(mut self) -> Self {
+        self.rollout = self
+            .rollout
+            .filter(|pct| pct.is_finite())
+            .map(|pct| pct.clamp(0.0, 100.0));
+        self
+    }
+
+    /// Whether `key` (an owner hash) falls inside the rollout. Requests
+    /// without a key only see fully rolled out flags.
+    fn applies_to(&self, name: &str, key: Option<&str>) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        match (self.rollout, key) {
+            (None, _) => true,
+            (Some(pct), _) if pct >= 100.0 => true,
+            (Some(pct), Some(key)) => rollout_bucket(name, key) < pct,
+            (Some(_), None) => false,
+        }
+    }
+}
+
+/// Where a flag's current rule came from, lowest precedence first.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FlagSource {
+    File,
+    Env,
+    Admin,
+}
+
+/// One row of `/api/admin/v1/flags`.
+#[derive(Serialize, Clone, Debug)]
+pub struct FlagState {
+    pub name: String,
+    #[serde(flatten)]
+    pub rule: FlagRule,
+    pub source: FlagSource,
+}
+
+/// A stable position in 0..100 for `key` under flag `name`, so an owner
+/// stays in or out of a rollout as long as its percentage does not drop.
+fn rollout_bucket(name: &str, key: &str) -> f64 {
+    let digest = Sha256::digest(format!("{name}:{key}").as_bytes());
+    let mut head = [0u8; 8];
+    head.copy_from_slice(&digest[..8]);
+    (u64::from_be_bytes(head) % 10_000) as f64 / 100.0
+}
+
+/// Parse `JUICEBOX_FEATURE_FLAGS`: comma-separated `name=on`, `name=off` or
+/// `name=25%`. Unparsable entries are skipped with a warning.
+pub fn parse_flag_list(value: &str) -> HashMap<String, FlagRule> {
+    let mut flags = HashMap::new();
+    for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (name, setting) = entry.split_once('=').unwrap_or((entry, "on"));
+        let name = name.trim().to_ascii_lowercase();
+        let setting = setting.trim().to_ascii_lowercase();
+        let rule = match setting.as_str() {
+            "1" | "on" | "true" | "yes" => Some(FlagRule {
+                enabled: true,
+                rollout: None,
+            }),
+            "0" | "off" | "false" | "no" => Some(FlagRule {
+                enabled: false,
+                rollout: None,
+            }),
+            pct => pct
+                .strip_suffix('%')
+                .and_then(|pct| pct.trim().parse::<f64>().ok())
+                .map(|pct| FlagRule {
+                    enabled: true,
+                    rollout: Some(pct),
+                }),
+        };
+        match rule {
+            Some(rule) if !name.is_empty() => {
+                flags.insert(name, rule.normalized());
+            }
+            _ => warn!(entry, "ignoring malformed feature flag"),
+        }
+    }
+    flags
+}
+
+/// Named switches checked per request, with optional percentage rollout
+/// keyed by owner hash. Rules come from a TOML file, then the environment,
+/// then admin overrides stored in the key-value store; later sources win.
+#[derive(Default)]
+pub struct FeatureFlags {
+    base: HashMap<String, (FlagRule, FlagSource)>,
+    overrides: ArcSwap<HashMap<String, FlagRule>>,
+}
+
+impl FeatureFlags {
+    pub fn new(base: HashMap<String, (FlagRule, FlagSource)>) -> Self {
+        Self {
+            base,
+            overrides: ArcSwap::default(),
+        }
+    }
+
+    /// Load the TOML file at `JUICEBOX_FEATURE_FLAGS_FILE`, or
+    /// `feature_flags.toml` in the data dir when that exists, then apply
+    /// `JUICEBOX_FEATURE_FLAGS` on top.
+    pub fn from_env(data_dir: &Path) -> anyhow::Result<Self> {
+        let path = match std::env::var("JUICEBOX_FEATURE_FLAGS_FILE") {
+            Ok(path) if !path.trim().is_empty() => Some(path.trim().into()),
+            _ => Some(data_dir.join("feature_flags.toml")).filter(|p| p.exists()),
+        };
+        let mut base = HashMap::new();
+        if let Some(path) = path {
+            let contents = std::fs::read_to_string(&path)
+                .with_context(|| format!("failed to read feature flags {}", path.display()))?;
+            let file: HashMap<String, FlagRule> = toml::from_str(&contents)
+                .with_context(|| format!("failed to parse feature flags {}", path.display()))?;
+            for (name, rule) in file {
+                base.insert(
+                    name.to_ascii_lowercase(),
+                    (rule.normalized(), FlagSource::File),
+                );
+            }
+        }
+        if let Ok(value) = std::env::var("JUICEBOX_FEATURE_FLAGS") {
+            for (name, rule) in parse_flag_list(&value) {
+                base.insert(name, (rule, FlagSource::Env));
+            }
+        }
+        debug!(flags = base.len(), "loaded feature flags");
+        Ok(Self::new(base))
+    }
+
+    /// The rule in force for `name`, if any source defines it.
+    pub fn rule(&self, name: &str) -> Option<FlagRule> {
+        if let Some(rule) = self.overrides.load().get(name) {
+            return Some(rule.clone());
+        }
+        self.base.get(name).map(|(rule, _)| rule.clone())
+    }
+
+    /// Whether `name` is on for the owner `key`, or `None` when no source
+    /// defines the flag and the caller should use its own default.
+    pub fn check(&self, name: &str, key: Option<&str>) -> Option<bool> {
+        self.rule(name).map(|rule| rule.applies_to(name, key))
+    }
+
+    /// Whether `name` is on for the owner `key`. Undefined flags are off.
+    pub fn is_enabled(&self, name: &str, key: Option<&str>) -> bool {
+        self.check(name, key).unwrap_or(false)
+    }
+
+    pub fn list(&self) -> Vec<FlagState> {
+        let mut merged: BTreeMap<String, (FlagRule, FlagSource)> = self
+            .base
+            .iter()
+            .map(|(name, entry)| (name.clone(), entry.clone()))
+            .collect();
+        for (name, rule) in self.overrides.load().iter() {
+            merged.insert(name.clone(), (rule.clone(), FlagSource::Admin));
+        }
+        merged
+            .into_iter()
+            .map(|(name, (rule, source))| FlagState { name, rule, source })
+            .collect()
+    }
+
+    pub fn set_override(&self, name: &str, rule: FlagRule) {
+        let mut next = HashMap::clone(&self.overrides.load());
+        next.insert(name.to_ascii_lowercase(), rule.normalized());
+        self.overrides.store(Arc::new(next));
+    }
+
+    /// Drop the admin override for `name`, falling back to the file and
+    /// environment. Returns whether there was one.
+    pub fn clear_override(&self, name: &str) -> bool {
+        let mut next = HashMap::clone(&self.overrides.load());
+        let removed = next.remove(name).is_some();
+        self.overrides.store(Arc::new(next));
+        removed
+    }
+}
+
+impl AppState {
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn load_feature_flags(&self) -> anyhow::Result<()> {
+        let mut overrides = HashMap::new();
+        for (name, value) in self.kv.load_hash("feature_flags").await? {
+            match serde_json::from_str::<FlagRule>(&value) {
+                Ok(rule) => {
+                    overrides.insert(name, rule.normalized());
+                }
+                Err(err) => warn!(?err, name, "skipping malformed feature flag"),
+            }
+        }
+        info!(overrides = overrides.len(), "loaded feature flag overrides");
+        self.flags.overrides.store(Arc::new(overrides));
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn persist_feature_flags(&self) {
+        let snapshot = self.flags.overrides.load_full();
+        let mut encoded = Vec::with_capacity(snapshot.len());
+        for (name, rule) in snapshot.iter() {
+            match serde_json::to_string(rule) {
+                Ok(value) => encoded.push((name.clone(), value)),
+                Err(err) => {
+                    error!(?err, name, "failed to serialize feature flag");
+                    return;
+                }
+            }
+        }
+        if let Err(err) = self.kv.replace_hash("feature_flags", &encoded).await {
+            error!(?err, "failed to persist feature flags to key-value store");
+            return;
+        }
+        debug!(
+            count = encoded.len(),
+            "persisted feature flags to key-value store"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_env_flags_and_buckets_stably() {
+        let flags = parse_flag_list("streaming_uploads=25%, New_Store=on,old=off,bad=maybe");
+        assert_eq!(flags.len(), 3);
+        assert_eq!(flags["streaming_uploads"].rollout, Some(25.0));
+        assert!(flags["new_store"].enabled);
+        assert!(!flags["old"].enabled);
+
+        let rule = &flags["streaming_uploads"];
+        let included = (0..1000)
+            .filter(|i| rule.applies_to("streaming_uploads", Some(&format!("owner-{i}"))))
+            .count();
+        assert!((200..300).contains(&included), "{included}");
+        assert!(!rule.applies_to("streaming_uploads", None));
+        assert_eq!(
+            rollout_bucket("streaming_uploads", "owner-1"),
+            rollout_bucket("streaming_uploads", "owner-1")
+        );
+    }
+}
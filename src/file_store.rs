@@ -0,0 +1,506 @@
+use anyhow::{Context, Result, anyhow};
+use async_trait::async_trait;
+use axum::body::Bytes;
+use futures_util::TryStreamExt;
+use hmac::{Hmac, Mac};
+use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::io::{ErrorKind, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use time::OffsetDateTime;
+use tokio::fs;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio_util::io::StreamReader;
+use tracing::{debug, trace, warn};
+
+/// A streamed file body from [`FileStore::open`].
+pub type FileReader = Pin<Box<dyn AsyncRead + Send>>;
+
+/// [`FileStore::write`] or [`FileStore::import`] found `name` already
+/// stored and left it alone.
+#[derive(Debug)]
+pub struct NameTaken(pub String);
+
+impl fmt::Display for NameTaken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "storage name {} is already taken", self.0)
+    }
+}
+
+impl std::error::Error for NameTaken {}
+
+/// Where uploaded file bodies live. Metadata stays in the [`KvStore`];
+/// this only holds the bytes, keyed by storage name.
+///
+/// [`KvStore`]: crate::state::KvStore
+#[async_trait]
+pub trait FileStore: Send + Sync {
+    /// Short name of the backend, reported by `/api/version`.
+    fn backend_name(&self) -> &'static str;
+    /// Store `bytes` as a new object `name`. Fails with [`NameTaken`] rather
+    /// than replace an existing one.
+    async fn write(&self, name: &str, bytes: Bytes) -> Result<()>;
+    /// Move the local file at `src` into the store as a new object `name`.
+    /// `src` is gone afterwards on success and untouched on [`NameTaken`].
+    async fn import(&self, name: &str, src: &Path) -> Result<()>;
+    /// Move `name` out of the store into the local file `dest`.
+    async fn export(&self, name: &str, dest: &Path) -> Result<()>;
+    /// Full contents of `name`, or `None` when it does not exist.
+    async fn read(&self, name: &str) -> Result<Option<Bytes>>;
+    /// Stream the contents of `name` without holding it in memory, or `None`
+    /// when it does not exist.
+    async fn open(&self, name: &str) -> Result<Option<FileReader>>;
+    /// Bytes `offset..offset + length` of `name`, fetched without reading
+    /// what comes before them. `None` when `name` does not exist or ends
+    /// before the range does.
+    async fn read_range(&self, name: &str, offset: u64, length: u64) -> Result<Option<Vec<u8>>>;
+    /// Size of `name` in bytes, or `None` when it does not exist.
+    async fn size(&self, name: &str) -> Result<Option<u64>>;
+    /// Remove `name`. Removing a missing object is not an error.
+    async fn delete(&self, name: &str) -> Result<()>;
+
+    async fn exists(&self, name: &str) -> bool {
+        matches!(self.size(name).await, Ok(Some(_)))
+    }
+
+    /// When `name` was last written, in Unix seconds, if the backend knows.
+    async fn modified(&self, _name: &str) -> Result<Option<u64>> {
+        Ok(None)
+    }
+}
+
+/// File bodies kept as plain files in the upload directory.
+pub struct LocalFileStore {
+    root: PathBuf,
+}
+
+impl LocalFileStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.root.join(name)
+    }
+}
+
+#[async_trait]
+impl FileStore for LocalFileStore {
+    fn backend_name(&self) -> &'static str {
+        "local"
+    }
+
+    async fn write(&self, name: &str, bytes: Bytes) -> Result<()> {
+        let path = self.path(name);
+        let mut file = create_new(&path, name).await?;
+        if let Err(err) = async {
+            file.write_all(&bytes).await?;
+            file.flush().await
+        }
+        .await
+        {
+            let _ = fs::remove_file(&path).await;
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    async fn import(&self, name: &str, src: &Path) -> Result<()> {
+        // A hard link never replaces its target, unlike rename.
+        let dest = self.path(name);
+        match fs::hard_link(src, &dest).await {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                return Err(NameTaken(name.to_string()).into());
+            }
+            Err(err) => {
+                debug!(?err, name, "hard link failed, copying into storage");
+                let mut file = create_new(&dest, name).await?;
+                let copied = async {
+                    let mut from = fs::File::open(src).await?;
+                    tokio::io::copy(&mut from, &mut file).await?;
+                    file.flush().await
+                }
+                .await;
+                if let Err(err) = copied {
+                    let _ = fs::remove_file(&dest).await;
+                    return Err(err.into());
+                }
+            }
+        }
+        if let Err(err) = fs::remove_file(src).await {
+            warn!(?err, path = ?src, "failed to remove source after import");
+        }
+        Ok(())
+    }
+
+    async fn export(&self, name: &str, dest: &Path) -> Result<()> {
+        fs::rename(self.path(name), dest).await?;
+        Ok(())
+    }
+
+    async fn read(&self, name: &str) -> Result<Option<Bytes>> {
+        match fs::read(self.path(name)).await {
+            Ok(bytes) => Ok(Some(Bytes::from(bytes))),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn open(&self, name: &str) -> Result<Option<FileReader>> {
+        match fs::File::open(self.path(name)).await {
+            Ok(file) => Ok(Some(Box::pin(file))),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn read_range(&self, name: &str, offset: u64, length: u64) -> Result<Option<Vec<u8>>> {
+        let mut file = match fs::File::open(self.path(name)).await {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        file.seek(SeekFrom::Start(offset)).await?;
+        let mut buf = vec![0u8; length as usize];
+        match file.read_exact(&mut buf).await {
+            Ok(_) => Ok(Some(buf)),
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn size(&self, name: &str) -> Result<Option<u64>> {
+        match fs::metadata(self.path(name)).await {
+            Ok(md) => Ok(Some(md.len())),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn delete(&self, name: &str) -> Result<()> {
+        match fs::remove_file(self.path(name)).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn modified(&self, name: &str) -> Result<Option<u64>> {
+        match fs::metadata(self.path(name)).await {
+            Ok(md) => Ok(md
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Open `path` for writing only if nothing is there yet.
+async fn create_new(path: &Path, name: &str) -> Result<fs::File> {
+    match fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .await
+    {
+        Ok(file) => Ok(file),
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+            Err(NameTaken(name.to_string()).into())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Status of a conditional PUT that lost to an existing object. Some
+/// providers answer `409` while a competing write is in progress.
+fn put_conflict(status: StatusCode) -> bool {
+    status == StatusCode::PRECONDITION_FAILED || status == StatusCode::CONFLICT
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+const EMPTY_PAYLOAD_SHA256: &str =
+    "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+/// Connection settings for [`S3FileStore`].
+#[derive(Clone, Debug)]
+pub struct S3Config {
+    pub bucket: String,
+    pub region: String,
+    /// Custom endpoint for MinIO and other S3-compatible services. AWS is
+    /// used when unset.
+    pub endpoint: Option<String>,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Prepended to every object key, e.g. `juicebox/`.
+    pub prefix: String,
+    /// Address buckets as `endpoint/bucket/key` rather than `bucket.endpoint/key`.
+    pub path_style: bool,
+}
+
+/// File bodies kept in an S3 bucket (or MinIO), signed with SigV4.
+pub struct S3FileStore {
+    config: S3Config,
+    client: reqwest::Client,
+}
+
+impl S3FileStore {
+    pub fn new(config: S3Config) -> Result<Self> {
+        if config.bucket.is_empty() {
+            return Err(anyhow!("S3 bucket name is empty"));
+        }
+        let client = reqwest::Client::builder()
+            .build()
+            .context("failed to build S3 HTTP client")?;
+        Ok(Self { config, client })
+    }
+
+    /// Scheme and host to send requests to, plus the path leading to the key.
+    fn base(&self) -> (String, String, String) {
+        let endpoint = self
+            .config
+            .endpoint
+            .clone()
+            .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", self.config.region));
+        let endpoint = endpoint.trim_end_matches('/');
+        let (scheme, host) = endpoint.split_once("://").unwrap_or(("https", endpoint));
+        if self.config.path_style {
+            (
+                scheme.to_string(),
+                host.to_string(),
+                format!("/{}", self.config.bucket),
+            )
+        } else {
+            (
+                scheme.to_string(),
+                format!("{}.{host}", self.config.bucket),
+                String::new(),
+            )
+        }
+    }
+
+    fn object_path(&self, name: &str) -> (String, String) {
+        let (scheme, host, bucket_path) = self.base();
+        let key = format!("{}{name}", self.config.prefix);
+        let encoded: Vec<String> = key
+            .split('/')
+            .map(|segment| urlencoding::encode(segment).into_owned())
+            .collect();
+        let path = format!("{bucket_path}/{}", encoded.join("/"));
+        (format!("{scheme}://{host}{path}"), path)
+    }
+
+    /// Build a SigV4-signed request for `name`.
+    fn request(
+        &self,
+        method: reqwest::Method,
+        name: &str,
+        payload_sha256: &str,
+    ) -> reqwest::RequestBuilder {
+        let (url, path) = self.object_path(name);
+        let (_, host, _) = self.base();
+        let now = OffsetDateTime::now_utc();
+        let date = format!(
+            "{:04}{:02}{:02}",
+            now.year(),
+            u8::from(now.month()),
+            now.day()
+        );
+        let amz_date = format!(
+            "{date}T{:02}{:02}{:02}Z",
+            now.hour(),
+            now.minute(),
+            now.second()
+        );
+        let canonical_request = format!(
+            "{method}\n{path}\n\nhost:{host}\nx-amz-content-sha256:{payload_sha256}\nx-amz-date:{amz_date}\n\nhost;x-amz-content-sha256;x-amz-date\n{payload_sha256}"
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", self.config.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{:x}",
+            Sha256::digest(canonical_request.as_bytes())
+        );
+        let signature = sigv4_signature(
+            &self.config.secret_access_key,
+            &date,
+            &self.config.region,
+            &string_to_sign,
+        );
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={signature}",
+            self.config.access_key_id
+        );
+        trace!(%method, %url, "signed S3 request");
+        self.client
+            .request(method, url)
+            .header("x-amz-content-sha256", payload_sha256)
+            .header("x-amz-date", amz_date)
+            .header(reqwest::header::AUTHORIZATION, authorization)
+    }
+
+    async fn fetch(&self, name: &str) -> Result<Option<reqwest::Response>> {
+        let resp = self
+            .request(reqwest::Method::GET, name, EMPTY_PAYLOAD_SHA256)
+            .send()
+            .await?;
+        match resp.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(Some(resp)),
+            status => Err(anyhow!("S3 GET {name} failed: {status}")),
+        }
+    }
+}
+
+/// Hex SigV4 signature of `string_to_sign` for the S3 service, using the
+/// signing key derived from `secret` for `date` (`YYYYMMDD`) and `region`.
+pub(crate) fn sigv4_signature(
+    secret: &str,
+    date: &str,
+    region: &str,
+    string_to_sign: &str,
+) -> String {
+    let mut key = hmac_sha256(format!("AWS4{secret}").as_bytes(), date.as_bytes());
+    for part in [region, "s3", "aws4_request"] {
+        key = hmac_sha256(&key, part.as_bytes());
+    }
+    hex(&hmac_sha256(&key, string_to_sign.as_bytes()))
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("hmac accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[async_trait]
+impl FileStore for S3FileStore {
+    fn backend_name(&self) -> &'static str {
+        "s3"
+    }
+
+    async fn write(&self, name: &str, bytes: Bytes) -> Result<()> {
+        let payload_sha256 = format!("{:x}", Sha256::digest(&bytes));
+        let resp = self
+            .request(reqwest::Method::PUT, name, &payload_sha256)
+            .header(reqwest::header::IF_NONE_MATCH, "*")
+            .body(bytes)
+            .send()
+            .await?;
+        if put_conflict(resp.status()) {
+            return Err(NameTaken(name.to_string()).into());
+        }
+        if !resp.status().is_success() {
+            return Err(anyhow!("S3 PUT {name} failed: {}", resp.status()));
+        }
+        debug!(name, "stored object in S3");
+        Ok(())
+    }
+
+    async fn import(&self, name: &str, src: &Path) -> Result<()> {
+        let file = fs::File::open(src).await?;
+        let len = file.metadata().await?.len();
+        let resp = self
+            .request(reqwest::Method::PUT, name, "UNSIGNED-PAYLOAD")
+            .header(reqwest::header::CONTENT_LENGTH, len)
+            .header(reqwest::header::IF_NONE_MATCH, "*")
+            .body(reqwest::Body::from(file))
+            .send()
+            .await?;
+        if put_conflict(resp.status()) {
+            return Err(NameTaken(name.to_string()).into());
+        }
+        if !resp.status().is_success() {
+            return Err(anyhow!("S3 PUT {name} failed: {}", resp.status()));
+        }
+        if let Err(err) = fs::remove_file(src).await {
+            warn!(?err, path = ?src, "failed to remove local copy after S3 upload");
+        }
+        debug!(name, size = len, "uploaded file to S3");
+        Ok(())
+    }
+
+    async fn export(&self, name: &str, dest: &Path) -> Result<()> {
+        let Some(mut resp) = self.fetch(name).await? else {
+            return Err(anyhow!("object {name} not found in S3"));
+        };
+        let mut out = fs::File::create(dest).await?;
+        while let Some(chunk) = resp.chunk().await? {
+            out.write_all(&chunk).await?;
+        }
+        out.flush().await?;
+        self.delete(name).await
+    }
+
+    async fn read(&self, name: &str) -> Result<Option<Bytes>> {
+        match self.fetch(name).await? {
+            Some(resp) => Ok(Some(resp.bytes().await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn open(&self, name: &str) -> Result<Option<FileReader>> {
+        let Some(resp) = self.fetch(name).await? else {
+            return Ok(None);
+        };
+        let stream = resp.bytes_stream().map_err(std::io::Error::other);
+        Ok(Some(Box::pin(StreamReader::new(stream))))
+    }
+
+    async fn read_range(&self, name: &str, offset: u64, length: u64) -> Result<Option<Vec<u8>>> {
+        let last = offset + length.saturating_sub(1);
+        let resp = self
+            .request(reqwest::Method::GET, name, EMPTY_PAYLOAD_SHA256)
+            .header(reqwest::header::RANGE, format!("bytes={offset}-{last}"))
+            .send()
+            .await?;
+        // A server that ignores `Range` answers 200 with the whole object.
+        let start = match resp.status() {
+            StatusCode::NOT_FOUND | StatusCode::RANGE_NOT_SATISFIABLE => return Ok(None),
+            StatusCode::PARTIAL_CONTENT => 0,
+            status if status.is_success() => offset as usize,
+            status => return Err(anyhow!("S3 GET {name} (range) failed: {status}")),
+        };
+        let body = resp.bytes().await?;
+        Ok(body
+            .get(start..start + length as usize)
+            .map(|range| range.to_vec()))
+    }
+
+    async fn size(&self, name: &str) -> Result<Option<u64>> {
+        let resp = self
+            .request(reqwest::Method::HEAD, name, EMPTY_PAYLOAD_SHA256)
+            .send()
+            .await?;
+        match resp.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(resp
+                .headers()
+                .get(reqwest::header::CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok())),
+            status => Err(anyhow!("S3 HEAD {name} failed: {status}")),
+        }
+    }
+
+    async fn delete(&self, name: &str) -> Result<()> {
+        let resp = self
+            .request(reqwest::Method::DELETE, name, EMPTY_PAYLOAD_SHA256)
+            .send()
+            .await?;
+        match resp.status() {
+            StatusCode::NOT_FOUND => Ok(()),
+            status if status.is_success() => Ok(()),
+            status => Err(anyhow!("S3 DELETE {name} failed: {status}")),
+        }
+    }
+}
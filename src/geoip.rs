@@ -0,0 +1,135 @@
+//! Country and ASN lookups from MaxMind databases (GeoLite2/GeoIP2 Country,
+//! City and ASN, or anything else in the MaxMind DB format), for bans that
+//! cover a whole country or autonomous system. Both databases are optional
+//! and read into memory once at startup.
+
+use anyhow::{Context, Result};
+use maxminddb::{MaxMindDBError, Reader, geoip2};
+use serde::Deserialize;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+use crate::state::BanSubject;
+
+/// One MaxMind DB file, held in memory.
+pub struct MaxMindDb {
+    reader: Reader<Vec<u8>>,
+}
+
+impl MaxMindDb {
+    pub fn open(path: &Path) -> Result<Self> {
+        let buf =
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        Self::from_bytes(buf).with_context(|| format!("{} is not a MaxMind DB", path.display()))
+    }
+
+    pub fn from_bytes(buf: Vec<u8>) -> Result<Self> {
+        Ok(Self {
+            reader: Reader::from_source(buf)?,
+        })
+    }
+
+    pub fn database_type(&self) -> &str {
+        &self.reader.metadata.database_type
+    }
+
+    /// The record for the network containing `addr`, if any.
+    pub fn lookup<'a, T: Deserialize<'a>>(&'a self, addr: IpAddr) -> Result<Option<T>> {
+        let addr = addr.to_canonical();
+        if addr.is_ipv6() && self.reader.metadata.ip_version == 4 {
+            return Ok(None);
+        }
+        match self.reader.lookup(addr) {
+            Ok(record) => Ok(Some(record)),
+            Err(MaxMindDBError::AddressNotFoundError(_)) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// What the databases know about one address.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GeoInfo {
+    /// ISO 3166-1 alpha-2 code, uppercase.
+    pub country: Option<String>,
+    pub asn: Option<u32>,
+}
+
+/// The optional country and ASN databases.
+#[derive(Default)]
+pub struct GeoIp {
+    country: Option<MaxMindDb>,
+    asn: Option<MaxMindDb>,
+}
+
+impl GeoIp {
+    pub fn new(country: Option<MaxMindDb>, asn: Option<MaxMindDb>) -> Self {
+        Self { country, asn }
+    }
+
+    /// Open `JUICEBOX_GEOIP_COUNTRY_DB` and `JUICEBOX_GEOIP_ASN_DB`. Either may
+    /// be unset; one that is set but unreadable is an error.
+    pub fn from_env() -> Result<Self> {
+        let open = |name: &str| -> Result<Option<MaxMindDb>> {
+            let Some(path) = std::env::var(name)
+                .ok()
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+            else {
+                return Ok(None);
+            };
+            let db = MaxMindDb::open(&PathBuf::from(&path))
+                .with_context(|| format!("invalid {name}"))?;
+            info!(
+                path,
+                database_type = db.database_type(),
+                "loaded geoip database"
+            );
+            Ok(Some(db))
+        };
+        Ok(Self::new(
+            open("JUICEBOX_GEOIP_COUNTRY_DB")?,
+            open("JUICEBOX_GEOIP_ASN_DB")?,
+        ))
+    }
+
+    pub fn country_database(&self) -> Option<&str> {
+        self.country.as_ref().map(MaxMindDb::database_type)
+    }
+
+    pub fn asn_database(&self) -> Option<&str> {
+        self.asn.as_ref().map(MaxMindDb::database_type)
+    }
+
+    /// Whether a ban on `subject` could ever match, i.e. the database it
+    /// needs is loaded.
+    pub fn can_match(&self, subject: &BanSubject) -> bool {
+        match subject {
+            BanSubject::Country { .. } => self.country.is_some(),
+            BanSubject::Asn { .. } => self.asn.is_some(),
+            _ => true,
+        }
+    }
+
+    pub fn lookup(&self, addr: IpAddr) -> GeoInfo {
+        let country = record::<geoip2::Country>(&self.country, addr).and_then(|record| {
+            [record.country, record.registered_country]
+                .into_iter()
+                .flatten()
+                .find_map(|country| country.iso_code.map(str::to_ascii_uppercase))
+        });
+        let asn = record::<geoip2::Asn>(&self.asn, addr)
+            .and_then(|record| record.autonomous_system_number);
+        GeoInfo { country, asn }
+    }
+}
+
+/// `addr`'s record in `db`, when it is loaded and knows the address.
+fn record<'a, T: Deserialize<'a>>(db: &'a Option<MaxMindDb>, addr: IpAddr) -> Option<T> {
+    db.as_ref()?
+        .lookup(addr)
+        .inspect_err(|err| debug!(%addr, ?err, "geoip lookup failed"))
+        .ok()
+        .flatten()
+}
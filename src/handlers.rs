@@ -1,56 +1,124 @@
 use axum::{
     Router, middleware,
-    routing::{delete, get, post, put},
+    routing::{delete, get, head, post, put},
 };
 use tower_http::services::ServeDir;
 use tracing::info;
 
+use crate::cluster;
+use crate::cors;
 use crate::state::AppState;
 
+pub mod accounts;
 pub mod admin;
+pub mod admin_list;
 pub mod debug;
 pub mod delete;
 pub mod hosting;
+pub mod lookup;
+pub mod notifications;
+pub mod offline;
+pub mod presign;
 pub mod reports;
+pub mod s3;
 pub mod security;
+pub mod stats;
+pub mod telemetry;
+pub mod tus;
 pub mod upload;
 pub mod web;
 
+pub use accounts::{
+    AccountCredentials, AccountResponse, account_login_handler, account_logout_handler,
+    account_me_handler, account_register_handler,
+};
 pub use admin::{
-    AdminAuthForm, AdminFileDeleteForm, AdminReportDeleteForm, BanForm, UnbanForm,
-    admin_file_delete_handler, admin_files_handler, admin_report_delete_handler,
-    admin_reports_handler, auth_get_handler, auth_post_handler, auth_post_json_handler,
+    AdminAuthForm, AdminFileDeleteForm, AdminQuarantineForm, AdminReportDeleteForm,
+    ApiTokenCreateRequest, ApiTokenCreated, BanForm, ShadowToggleRequest, UnbanForm,
+    admin_audit_handler, admin_bans_export_handler, admin_bans_import_handler, admin_drain_handler,
+    admin_file_api_delete_handler, admin_file_delete_handler, admin_files_api_delete_handler,
+    admin_files_api_handler, admin_files_handler, admin_flag_clear_handler, admin_flag_set_handler,
+    admin_flags_handler, admin_quarantine_action_handler, admin_quarantine_handler,
+    admin_reindex_start_handler, admin_reindex_status_handler, admin_report_ban_handler,
+    admin_report_delete_handler, admin_report_status_handler, admin_reports_handler,
+    admin_runtime_handler, admin_runtime_update_handler, admin_shadow_handler,
+    admin_shadow_toggle_handler, admin_token_create_handler, admin_token_revoke_handler,
+    admin_tokens_handler, auth_get_handler, auth_post_handler, auth_post_json_handler,
     ban_page_handler, ban_post_handler, is_admin_handler, unban_post_handler,
 };
 pub use debug::block_debug_endpoints;
 pub use delete::{
-    SimpleDeleteForm, delete_handler, simple_delete_handler, simple_delete_post_handler,
+    BulkDeleteRequest, BulkDeleteResponse, SimpleDeleteForm, bulk_delete_handler, delete_handler,
+    simple_delete_handler, simple_delete_post_handler,
+};
+pub use hosting::{
+    ConfigResponse, LinkStatusResponse, config_handler, download_page_handler, fetch_file_handler,
+    file_handler, link_status_handler, readyz_handler, transparency_log_handler, version_handler,
+};
+pub use lookup::{
+    LookupChallengeRequest, LookupChallengeResponse, LookupVerifyRequest, LookupVerifyResponse,
+    lookup_challenge_handler, lookup_verify_handler,
+};
+pub use notifications::{OwnerSocketQuery, owner_socket_handler};
+pub use offline::{
+    delete_queued_upload_handler, offline_manifest_handler, register_queued_upload_handler,
+    service_worker_handler,
+};
+pub use presign::{
+    PRESIGN_DEFAULT_SECS, PRESIGN_MAX_SECS, PresignQuery, PresignRequest, PresignResponse,
+    Presigned, presign_handler, sign_download,
+};
+pub use reports::{EmailPrivacy, ReportForm, ReportRecordEmail, report_handler};
+pub use s3::{
+    ListObjectsQuery, s3_delete_object_handler, s3_get_object_handler, s3_list_objects_handler,
+    s3_put_object_handler, s3_secret_access_key,
 };
-pub use hosting::{ConfigResponse, config_handler, fetch_file_handler, file_handler};
-pub use reports::{ReportForm, ReportRecordEmail, report_handler};
 pub use security::{add_cache_headers, add_security_headers, ban_gate};
+pub use stats::{admin_stats_handler, stats_json_handler, stats_page_handler};
+pub use tus::{
+    tus_create_handler, tus_delete_handler, tus_head_handler, tus_options_handler,
+    tus_patch_handler,
+};
 pub use upload::{
-    CheckHashQuery, ChunkCompleteRequest, ChunkInitRequest, ChunkInitResponse, FileMetaEntry,
-    ListResponse, UploadResponse, cancel_chunk_upload_handler, checkhash_handler,
+    CheckHashQuery, ChunkCompleteRequest, ChunkInitRequest, ChunkInitResponse, ChunkSessionEntry,
+    ChunkSessionsResponse, ChunkStatusResponse, DuplicateMeta, DuplicateResponse, FileMetaEntry,
+    ListResponse, PASTE_MAX_BYTES, PasteQuery, PasteResponse, RemovedFileEntry, UploadResponse,
+    UploadedFileEntry, allow_options, cancel_chunk_upload_handler, checkhash_handler,
     chunk_cancel_options_handler, chunk_complete_options_handler, chunk_part_options_handler,
     chunk_status_handler, complete_chunk_upload_handler, init_chunk_options_handler,
-    init_chunk_upload_handler, list_handler, simple_list_handler, simple_upload_handler,
-    upload_chunk_part_handler, upload_get_handler, upload_handler, upload_head_handler,
-    upload_options_handler,
+    init_chunk_upload_handler, list_chunk_sessions_handler, list_handler, paste_binary_handler,
+    simple_list_handler, simple_upload_handler, upload_chunk_part_handler, upload_get_handler,
+    upload_handler, upload_head_handler, upload_options_handler,
 };
 pub use web::{
     LangQuery, SimpleQuery, banned_handler, debug_ip_handler, faq_handler,
-    report_page_handler_i18n, root_handler, simple_handler, terms_handler, trusted_handler,
-    visitor_debug_handler,
+    report_page_handler_i18n, root_handler, simple_events_handler, simple_handler, terms_handler,
+    trusted_handler, visitor_debug_handler,
 };
 
+#[cfg(not(feature = "embedded-assets"))]
+fn static_dir_service(root: &std::path::Path, dir: &str) -> ServeDir {
+    ServeDir::new(root.join(dir))
+}
+
+/// Falls back to the embedded copy of `public/<dir>` for files missing on disk.
+#[cfg(feature = "embedded-assets")]
+fn static_dir_service(
+    root: &std::path::Path,
+    dir: &'static str,
+) -> ServeDir<axum::routing::MethodRouter> {
+    ServeDir::new(root.join(dir)).fallback(get(move |uri: axum::http::Uri| async move {
+        hosting::serve_embedded_asset(&format!("{dir}{}", uri.path()))
+    }))
+}
+
 #[tracing::instrument(level = "info", skip(state))]
 pub fn build_router(state: AppState) -> Router {
     let static_root = state.static_dir.clone();
     info!(?static_root, "Constructing application router");
-    let css_service = ServeDir::new(static_root.join("css"));
-    let js_service = ServeDir::new(static_root.join("js"));
-    let dist_service = ServeDir::new(static_root.join("dist"));
+    let css_service = static_dir_service(&static_root, "css");
+    let js_service = static_dir_service(&static_root, "js");
+    let dist_service = static_dir_service(&static_root, "dist");
     let mut router = Router::new()
         .route("/checkhash", get(checkhash_handler))
         .route(
@@ -64,7 +132,14 @@ pub fn build_router(state: AppState) -> Router {
             "/chunk/init",
             post(init_chunk_upload_handler).options(init_chunk_options_handler),
         )
-        .route("/chunk/{id}/status", get(chunk_status_handler))
+        .route(
+            "/chunk/sessions",
+            get(list_chunk_sessions_handler).options(|| allow_options("GET, HEAD, OPTIONS")),
+        )
+        .route(
+            "/chunk/{id}/status",
+            get(chunk_status_handler).options(|| allow_options("GET, HEAD, OPTIONS")),
+        )
         .route(
             "/chunk/{id}/complete",
             post(complete_chunk_upload_handler).options(chunk_complete_options_handler),
@@ -77,18 +152,79 @@ pub fn build_router(state: AppState) -> Router {
             "/chunk/{id}/{index}",
             put(upload_chunk_part_handler).options(chunk_part_options_handler),
         )
+        .route(
+            "/api/paste-binary",
+            post(paste_binary_handler).options(|| allow_options("POST, OPTIONS")),
+        )
         .route("/list", get(list_handler))
         .route("/mine", get(list_handler))
+        .route("/api/v1/accounts/register", post(account_register_handler))
+        .route("/api/v1/accounts/login", post(account_login_handler))
+        .route("/api/v1/accounts/logout", post(account_logout_handler))
+        .route("/api/v1/accounts/me", get(account_me_handler))
+        .route(
+            "/api/v1/files/delete",
+            post(bulk_delete_handler).options(|| allow_options("POST, OPTIONS")),
+        )
+        .route(
+            "/api/v1/files/lookup",
+            post(lookup_challenge_handler).options(|| allow_options("POST, OPTIONS")),
+        )
+        .route(
+            "/api/v1/files/lookup/verify",
+            post(lookup_verify_handler).options(|| allow_options("POST, OPTIONS")),
+        )
+        .route(
+            "/api/v1/files/{name}/status",
+            get(link_status_handler).options(|| allow_options("GET, HEAD, OPTIONS")),
+        )
+        .route(
+            "/api/v1/files/{name}/presign",
+            post(presign_handler).options(|| allow_options("POST, OPTIONS")),
+        )
+        .route(
+            "/tus",
+            post(tus_create_handler).options(tus_options_handler),
+        )
+        .route(
+            "/tus/",
+            post(tus_create_handler).options(tus_options_handler),
+        )
+        .route(
+            "/tus/{id}",
+            head(tus_head_handler)
+                .patch(tus_patch_handler)
+                .delete(tus_delete_handler)
+                .options(tus_options_handler),
+        )
+        .route("/s3/{bucket}", get(s3_list_objects_handler))
+        .route("/s3/{bucket}/", get(s3_list_objects_handler))
+        .route(
+            "/s3/{bucket}/{*key}",
+            get(s3_get_object_handler)
+                .put(s3_put_object_handler)
+                .delete(s3_delete_object_handler),
+        )
         .route("/f/{file}", get(fetch_file_handler).delete(delete_handler))
-        .route("/d/{file}", delete(delete_handler))
+        .route(
+            "/d/{file}",
+            get(download_page_handler).delete(delete_handler),
+        )
         .route(
             "/report",
             get(report_page_handler_i18n).post(report_handler),
         )
         .route("/unban", post(unban_post_handler))
         .route("/healthz", get(|| async { "ok" }))
+        .route("/readyz", get(readyz_handler))
         .route("/simple", get(simple_handler))
+        .route("/simple/events", get(simple_events_handler))
+        .route("/ws", get(owner_socket_handler))
         .route("/simple/upload", post(simple_upload_handler))
+        .route("/simple/queue", post(register_queued_upload_handler))
+        .route("/simple/queue/{id}", delete(delete_queued_upload_handler))
+        .route("/sw.js", get(service_worker_handler))
+        .route("/offline-manifest.json", get(offline_manifest_handler))
         .route(
             "/simple/delete",
             get(simple_delete_handler).post(simple_delete_post_handler),
@@ -108,9 +244,59 @@ pub fn build_router(state: AppState) -> Router {
             "/admin/reports",
             get(admin_reports_handler).post(admin_report_delete_handler),
         )
+        .route("/admin/reports/ban", post(admin_report_ban_handler))
+        .route("/admin/reports/status", post(admin_report_status_handler))
+        .route(
+            "/admin/quarantine",
+            get(admin_quarantine_handler).post(admin_quarantine_action_handler),
+        )
+        .route("/admin/audit", get(admin_audit_handler))
+        .route("/admin/stats", get(admin_stats_handler))
+        .route("/admin/drain", post(admin_drain_handler))
+        .route(
+            "/api/admin/v1/runtime",
+            get(admin_runtime_handler).patch(admin_runtime_update_handler),
+        )
+        .route(
+            "/api/admin/v1/shadow",
+            get(admin_shadow_handler).post(admin_shadow_toggle_handler),
+        )
+        .route(
+            "/api/admin/v1/reindex",
+            get(admin_reindex_status_handler).post(admin_reindex_start_handler),
+        )
+        .route(
+            "/api/admin/v1/files",
+            get(admin_files_api_handler).delete(admin_files_api_delete_handler),
+        )
+        .route(
+            "/api/admin/v1/files/{name}",
+            delete(admin_file_api_delete_handler),
+        )
+        .route("/api/admin/v1/flags", get(admin_flags_handler))
+        .route(
+            "/api/admin/v1/flags/{name}",
+            put(admin_flag_set_handler).delete(admin_flag_clear_handler),
+        )
+        .route(
+            "/api/admin/v1/bans",
+            get(admin_bans_export_handler).post(admin_bans_import_handler),
+        )
+        .route(
+            "/api/admin/v1/tokens",
+            get(admin_tokens_handler).post(admin_token_create_handler),
+        )
+        .route(
+            "/api/admin/v1/tokens/{id}",
+            delete(admin_token_revoke_handler),
+        )
+        .route("/stats", get(stats_page_handler))
+        .route("/api/stats", get(stats_json_handler))
         .route("/faq", get(faq_handler))
         .route("/terms", get(terms_handler))
         .route("/api/config", get(config_handler))
+        .route("/api/version", get(version_handler))
+        .route("/transparency.log", get(transparency_log_handler))
         .nest_service("/css", css_service.clone())
         .nest_service("/js", js_service.clone())
         .nest_service("/dist", dist_service.clone())
@@ -128,10 +314,23 @@ pub fn build_router(state: AppState) -> Router {
             .route("/debug/profile/raw", get(debug::debug_profile_pprof));
     }
 
-    router = router.layer(middleware::from_fn_with_state(
-        state.clone(),
-        debug::block_debug_endpoints,
-    ));
+    router = router
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            debug::block_debug_endpoints,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            cluster::advertise_node,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            cors::apply_cors,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            telemetry::name_transaction,
+        ));
 
     info!("Application router configured with static assets and handlers");
     router
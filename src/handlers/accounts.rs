@@ -0,0 +1,183 @@
+//! Optional registered accounts. A logged-in client owns what it uploads by
+//! account rather than by IP hash, so `/list`, deletes and presigned links
+//! follow the account across networks.
+
+use axum::Json;
+use axum::extract::{ConnectInfo, State};
+use axum::http::header::{CACHE_CONTROL, SET_COOKIE};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr as ClientAddr;
+use tracing::{info, warn};
+
+use crate::accounts::{ACCOUNT_COOKIE, ACCOUNT_SESSION_TTL, Account, AccountError};
+use crate::handlers::admin::is_https;
+use crate::state::AppState;
+use crate::util::{get_cookie, json_error, real_client_ip};
+
+#[derive(Deserialize)]
+pub struct AccountCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+/// What the account endpoints say about the logged-in account.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct AccountResponse {
+    pub username: String,
+    pub created: u64,
+    /// Live files owned by the account.
+    pub files: usize,
+}
+
+impl AccountResponse {
+    fn new(state: &AppState, account: &Account) -> Self {
+        let owner_hash = account.owner_hash();
+        let now = state.now_secs();
+        let files = state
+            .owners
+            .iter()
+            .filter(|entry| entry.owner_hash == owner_hash && entry.expires > now)
+            .count();
+        Self {
+            username: account.username.clone(),
+            created: account.created,
+            files,
+        }
+    }
+}
+
+fn accounts_disabled() -> Response {
+    json_error(
+        StatusCode::NOT_FOUND,
+        "accounts_disabled",
+        "accounts are not enabled",
+    )
+}
+
+fn session_cookie(headers: &HeaderMap, token: &str, max_age: u64) -> HeaderValue {
+    let mut cookie =
+        format!("{ACCOUNT_COOKIE}={token}; Path=/; HttpOnly; Max-Age={max_age}; SameSite=Lax");
+    if is_https(headers) {
+        cookie.push_str("; Secure");
+    }
+    HeaderValue::from_str(&cookie).expect("cookie is ascii")
+}
+
+async fn logged_in(state: &AppState, headers: &HeaderMap, account: &Account) -> Response {
+    let token = state.accounts.create_session(account);
+    state.persist_accounts().await;
+    let mut resp = Json(AccountResponse::new(state, account)).into_response();
+    let h = resp.headers_mut();
+    h.insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    h.append(
+        SET_COOKIE,
+        session_cookie(headers, &token, ACCOUNT_SESSION_TTL),
+    );
+    resp
+}
+
+#[axum::debug_handler]
+#[tracing::instrument(name = "accounts.register", skip_all)]
+pub async fn account_register_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<ClientAddr>,
+    headers: HeaderMap,
+    Json(req): Json<AccountCredentials>,
+) -> Response {
+    if !state.accounts.is_enabled() {
+        return accounts_disabled();
+    }
+    let ip = real_client_ip(&headers, &addr);
+    if state.is_banned(&ip).await {
+        return json_error(StatusCode::FORBIDDEN, "banned", "ip banned");
+    }
+    let account = match state.accounts.register(&req.username, &req.password).await {
+        Ok(account) => account,
+        Err(err) => {
+            warn!(%err, "account registration rejected");
+            return match err {
+                AccountError::InvalidUsername => json_error(
+                    StatusCode::BAD_REQUEST,
+                    "invalid_username",
+                    "usernames are 3-32 letters, digits, '-', '_' or '.'",
+                ),
+                AccountError::WeakPassword => json_error(
+                    StatusCode::BAD_REQUEST,
+                    "weak_password",
+                    "password must be at least 8 characters",
+                ),
+                AccountError::UsernameTaken => json_error(
+                    StatusCode::CONFLICT,
+                    "username_taken",
+                    "username already registered",
+                ),
+                AccountError::Hashing => json_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "internal",
+                    "failed to create account",
+                ),
+            };
+        }
+    };
+    info!(username = %account.username, "account registered");
+    let mut resp = logged_in(&state, &headers, &account).await;
+    *resp.status_mut() = StatusCode::CREATED;
+    resp
+}
+
+#[axum::debug_handler]
+#[tracing::instrument(name = "accounts.login", skip_all)]
+pub async fn account_login_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<ClientAddr>,
+    headers: HeaderMap,
+    Json(req): Json<AccountCredentials>,
+) -> Response {
+    if !state.accounts.is_enabled() {
+        return accounts_disabled();
+    }
+    let ip = real_client_ip(&headers, &addr);
+    if state.is_banned(&ip).await {
+        return json_error(StatusCode::FORBIDDEN, "banned", "ip banned");
+    }
+    let Some(account) = state.accounts.verify(&req.username, &req.password).await else {
+        warn!("account login failed");
+        return json_error(
+            StatusCode::UNAUTHORIZED,
+            "invalid_credentials",
+            "wrong username or password",
+        );
+    };
+    info!(username = %account.username, "account logged in");
+    logged_in(&state, &headers, &account).await
+}
+
+pub async fn account_logout_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(token) = get_cookie(&headers, ACCOUNT_COOKIE)
+        && state.accounts.end_session(&token)
+    {
+        state.persist_accounts().await;
+    }
+    let mut resp = StatusCode::NO_CONTENT.into_response();
+    resp.headers_mut()
+        .append(SET_COOKIE, session_cookie(&headers, "", 0));
+    resp
+}
+
+pub async fn account_me_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if !state.accounts.is_enabled() {
+        return accounts_disabled();
+    }
+    let Some(account) = get_cookie(&headers, ACCOUNT_COOKIE)
+        .and_then(|token| state.accounts.session_account(&token))
+    else {
+        return json_error(StatusCode::UNAUTHORIZED, "not_logged_in", "log in first");
+    };
+    let mut resp = Json(AccountResponse::new(&state, &account)).into_response();
+    resp.headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    resp
+}
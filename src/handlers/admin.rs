@@ -1,28 +1,283 @@
 use axum::Json;
-use axum::extract::{Form, State};
-use axum::http::header::{CACHE_CONTROL, CONTENT_TYPE, EXPIRES, LOCATION, PRAGMA, SET_COOKIE};
+use axum::body::Bytes;
+use axum::extract::{Form, Path, Query, State};
+use axum::http::header::{
+    CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_TYPE, EXPIRES, LOCATION, PRAGMA, SET_COOKIE,
+};
 use axum::http::{HeaderMap, HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
-use serde::Deserialize;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tokio::fs;
-use tracing::{info, trace, warn};
+use std::collections::{HashMap, HashSet};
+use tera::{Context, Tera};
+use tracing::{debug, error, info, trace, warn};
 
-use crate::state::{AppState, BanSubject, IpBan};
-use crate::util::{ADMIN_SESSION_TTL, IpVersion, get_cookie, json_error, new_id, now_secs};
+use crate::assets::read_public;
+use crate::audit::{AuditAction, session_actor};
+use crate::ban_transfer::{self, BanList};
+use crate::build_info::BuildInfo;
+use crate::drain;
+use crate::feature_flags::FlagRule;
+use crate::handlers::admin_list::{AdminListQuery, SortOrder, controls_row};
+use crate::handlers::s3::s3_secret_access_key;
+use crate::reindex;
+use crate::request_id::current_request_id;
+use crate::runtime::RuntimeSummary;
+use crate::state::{
+    ApiToken, AppState, BanSubject, FileMeta, IpBan, ReportRecord, ReportStatus,
+    ReportTransitionError,
+};
+use crate::tombstones::RemovalReason;
+use crate::trace_sampling::SamplingUpdate;
+use crate::ttl_policy::parse_ttl_secs;
+use crate::util::{
+    ADMIN_SESSION_TTL, IpVersion, ascii_filename, bearer_token, display_original_name,
+    filename_warning, get_cookie, json_error, new_id,
+};
 
-fn is_https(headers: &HeaderMap) -> bool {
+/// Target of admin action log events, so they can be routed apart from the
+/// rest of the log.
+pub const AUDIT_LOG_TARGET: &str = "juicebox::audit";
+
+/// Compiled in so admin actions keep working when `public/` is missing or
+/// broken on a deploy.
+static ADMIN_FALLBACK: Lazy<Tera> = Lazy::new(|| {
+    let mut tera = Tera::default();
+    tera.add_raw_template(
+        "admin_fallback.html",
+        include_str!("../../templates/admin_fallback.html.tera"),
+    )
+    .expect("built-in admin fallback template");
+    tera
+});
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum AdminPage {
+    Auth,
+    Already,
+    Bans,
+    Files,
+    Reports,
+    ReportBan,
+    Quarantine,
+    Audit,
+}
+
+impl AdminPage {
+    const NAV: [AdminPage; 5] = [
+        AdminPage::Files,
+        AdminPage::Reports,
+        AdminPage::Bans,
+        AdminPage::Quarantine,
+        AdminPage::Audit,
+    ];
+
+    fn static_file(self) -> &'static str {
+        match self {
+            AdminPage::Auth => "admin_auth.html",
+            AdminPage::Already => "admin_already.html",
+            AdminPage::Bans => "admin_ban.html",
+            AdminPage::Files => "admin_files.html",
+            AdminPage::Reports => "admin_reports.html",
+            AdminPage::ReportBan => "admin_report_ban.html",
+            AdminPage::Quarantine => "admin_quarantine.html",
+            AdminPage::Audit => "admin_audit.html",
+        }
+    }
+
+    fn placeholder(self) -> Option<&'static str> {
+        match self {
+            AdminPage::Auth | AdminPage::Already => None,
+            AdminPage::Bans => Some("{{ROWS}}"),
+            AdminPage::Files => Some("{{FILE_ROWS}}"),
+            AdminPage::Reports => Some("{{REPORT_ROWS}}"),
+            AdminPage::ReportBan => Some("{{PREVIEW_ROWS}}"),
+            AdminPage::Quarantine => Some("{{QUARANTINE_ROWS}}"),
+            AdminPage::Audit => Some("{{AUDIT_ROWS}}"),
+        }
+    }
+
+    fn key(self) -> &'static str {
+        match self {
+            AdminPage::Auth => "auth",
+            AdminPage::Already => "already",
+            AdminPage::Bans => "bans",
+            AdminPage::Files => "files",
+            AdminPage::Reports => "reports",
+            AdminPage::ReportBan => "report_ban",
+            AdminPage::Quarantine => "quarantine",
+            AdminPage::Audit => "audit",
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            AdminPage::Auth => "Admin sign in",
+            AdminPage::Already => "Admin",
+            AdminPage::Bans => "Bans",
+            AdminPage::Files => "Files",
+            AdminPage::Reports => "Reports",
+            AdminPage::ReportBan => "Bulk ban preview",
+            AdminPage::Quarantine => "Quarantine",
+            AdminPage::Audit => "Audit log",
+        }
+    }
+
+    fn href(self) -> &'static str {
+        match self {
+            AdminPage::Auth | AdminPage::Already => "/auth",
+            AdminPage::Bans => "/admin/ban",
+            AdminPage::Files => "/admin/files",
+            AdminPage::Reports | AdminPage::ReportBan => "/admin/reports",
+            AdminPage::Quarantine => "/admin/quarantine",
+            AdminPage::Audit => "/admin/audit",
+        }
+    }
+
+    fn columns(self) -> &'static [&'static str] {
+        match self {
+            AdminPage::Auth | AdminPage::Already => &[],
+            AdminPage::Bans => &["Target", "Reason", "Time", "Hits", "Last hit", "Action"],
+            AdminPage::Files => &[
+                "File",
+                "Owner ID",
+                "Network",
+                "TTL",
+                "Bytes",
+                "Downloads",
+                "Action",
+            ],
+            AdminPage::Reports => &[
+                "File",
+                "Reason",
+                "Details",
+                "Reporter ID",
+                "Time",
+                "Status",
+                "Action",
+            ],
+            AdminPage::ReportBan => &["Owner ID", "Files", "Reports", "Status"],
+            AdminPage::Quarantine => &[
+                "File",
+                "Original name",
+                "Owner ID",
+                "Bytes",
+                "Source",
+                "Verdict",
+                "Details",
+                "Quarantined",
+                "Action",
+            ],
+            AdminPage::Audit => &["Time", "Action", "Actor", "Target", "Details"],
+        }
+    }
+}
+
+fn html_response(body: impl IntoResponse) -> Response {
+    (
+        StatusCode::OK,
+        [(CONTENT_TYPE, HeaderValue::from_static("text/html"))],
+        body,
+    )
+        .into_response()
+}
+
+/// Placeholder in the signed-in admin pages replaced with the build summary.
+const BUILD_INFO_PLACEHOLDER: &str = "{{BUILD_INFO}}";
+
+/// Serve `public/<page>` with `rows` substituted into its placeholder, or the
+/// built-in fallback when the static file cannot be read.
+/// Escaped display name for admin tables. Names that could spoof their
+/// extension get a warning with their ASCII form underneath.
+fn original_name_cell(raw: &str) -> String {
+    let name = htmlescape::encode_minimal(&display_original_name(raw));
+    match filename_warning(raw) {
+        Some(warning) => format!(
+            "{name}<br><small class=name-warning data-warning={code} title=\"{reason}\">&#9888; {ascii}</small>",
+            code = warning.as_str(),
+            reason = warning.describe(),
+            ascii = htmlescape::encode_minimal(&ascii_filename(&display_original_name(raw))),
+        ),
+        None => name,
+    }
+}
+
+async fn render_admin_page(state: &AppState, page: AdminPage, rows: &str) -> Response {
+    let build_info = BuildInfo::for_state(state).summary();
+    match read_public(&state.static_dir, page.static_file()).await {
+        Some(bytes) => {
+            let mut body = String::from_utf8_lossy(&bytes).into_owned();
+            if let Some(placeholder) = page.placeholder() {
+                body = body.replace(placeholder, rows);
+            }
+            body = body.replace(
+                BUILD_INFO_PLACEHOLDER,
+                &htmlescape::encode_minimal(&build_info),
+            );
+            html_response(body)
+        }
+        None => {
+            warn!(
+                page = page.static_file(),
+                "admin page missing; serving built-in fallback"
+            );
+            render_admin_fallback(page, rows, &build_info)
+        }
+    }
+}
+
+fn render_admin_fallback(page: AdminPage, rows: &str, build_info: &str) -> Response {
+    let signed_in = !matches!(page, AdminPage::Auth);
+    let nav: Vec<_> = AdminPage::NAV
+        .iter()
+        .filter(|_| signed_in)
+        .map(|p| {
+            json!({
+                "href": p.href(),
+                "label": p.title(),
+                "current": *p == page,
+            })
+        })
+        .chain(std::iter::once(
+            json!({ "href": "/", "label": "Home", "current": false }),
+        ))
+        .collect();
+    let mut ctx = Context::new();
+    ctx.insert("page", page.key());
+    ctx.insert("title", page.title());
+    ctx.insert("nav", &nav);
+    ctx.insert("columns", page.columns());
+    ctx.insert("rows", rows);
+    if signed_in {
+        ctx.insert("build_info", build_info);
+    }
+    match ADMIN_FALLBACK.render("admin_fallback.html", &ctx) {
+        Ok(body) => html_response(body),
+        Err(err) => {
+            error!(?err, "failed to render built-in admin fallback");
+            json_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "missing_template",
+                "admin template missing",
+            )
+        }
+    }
+}
+
+pub(crate) fn is_https(headers: &HeaderMap) -> bool {
+    if crate::tls::serving_tls() {
+        return true;
+    }
     if let Some(v) = headers
         .get("x-forwarded-proto")
         .and_then(|v| v.to_str().ok())
-    {
-        if v.split(',')
+        && v.split(',')
             .next()
             .map(|s| s.trim().eq_ignore_ascii_case("https"))
             .unwrap_or(false)
-        {
-            return true;
-        }
+    {
+        return true;
     }
     if let Some(v) = headers
         .get(axum::http::header::FORWARDED)
@@ -46,6 +301,10 @@ fn is_https(headers: &HeaderMap) -> bool {
 pub struct BanForm {
     pub ip: String,
     pub reason: Option<String>,
+    /// Also take down every file the banned address uploaded.
+    pub remove_files: Option<String>,
+    /// How long the ban lasts, like `12h` or `7d`; empty bans for good.
+    pub duration: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -61,6 +320,52 @@ pub struct AdminAuthForm {
 #[derive(Deserialize)]
 pub struct AdminFileDeleteForm {
     pub file: String,
+    /// A [`RemovalReason`] code; defaults to `other`.
+    pub reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AdminFilesQuery {
+    /// Only files owned by this owner hash.
+    pub owner: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct BanExportQuery {
+    /// `json` (default) or `csv`.
+    pub format: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AdminFileDeleteQuery {
+    /// A [`RemovalReason`] code; defaults to `other`.
+    pub reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AdminFilesDeleteRequest {
+    pub files: Vec<String>,
+    /// A [`RemovalReason`] code; defaults to `other`.
+    pub reason: Option<String>,
+}
+
+/// One hosted file as the admin JSON API reports it.
+#[derive(Serialize)]
+pub struct AdminFileEntry {
+    pub name: String,
+    pub original: String,
+    pub owner_hash: String,
+    pub size: u64,
+    pub created: u64,
+    pub expires: u64,
+    /// SHA-256 of the contents; empty for entries not yet reindexed.
+    pub hash: String,
+    pub downloads: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_downloads: Option<u32>,
+    pub private: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network_class: Option<&'static str>,
 }
 
 #[derive(Deserialize)]
@@ -68,7 +373,37 @@ pub struct AdminReportDeleteForm {
     pub idx: usize,
 }
 
-pub async fn ban_page_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+#[derive(Deserialize)]
+pub struct AdminReportStatusForm {
+    pub id: String,
+    pub status: String,
+    /// Resolution note, kept with the report.
+    pub note: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AdminReportBanForm {
+    /// Report reason to match, e.g. `malware`.
+    pub reason: String,
+    /// How far back to look.
+    pub hours: u64,
+    /// Comma-separated owner hashes accepted on the preview. Absent when
+    /// asking for the preview itself.
+    pub owners: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AdminQuarantineForm {
+    pub file: String,
+    pub action: String,
+    pub confirm: Option<String>,
+}
+
+pub async fn ban_page_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<AdminListQuery>,
+) -> Response {
     trace!("rendering ban page");
     if let Some(tok) = get_cookie(&headers, "adm") {
         if !state.is_admin(&tok).await {
@@ -80,35 +415,52 @@ pub async fn ban_page_handler(State(state): State<AppState>, headers: HeaderMap)
         return json_error(StatusCode::UNAUTHORIZED, "not_admin", "auth required");
     }
     let bans = state.bans.read().await.clone();
-    let rows: String = bans
-        .iter()
+    let now = state.now_secs();
+    let mut matching: Vec<(IpBan, u64)> = bans
+        .into_iter()
+        .filter(|b| {
+            query.matches_owner(&b.subject.key())
+                && query.matches_text([b.label.as_deref().unwrap_or_default(), b.reason.as_str()])
+        })
         .map(|b| {
+            let hits = state.ban_hits.get(&b.subject.key()).hits;
+            (b, hits)
+        })
+        .collect();
+    let sort = query.sort_key(&BAN_SORTS.map(|(key, _)| key));
+    let order = query.order(SortOrder::Desc);
+    matching.sort_by(|(a, a_hits), (b, b_hits)| {
+        let ord = match sort {
+            "hits" => a_hits.cmp(b_hits),
+            _ => a.time.cmp(&b.time),
+        }
+        .then_with(|| a.subject.key().cmp(&b.subject.key()));
+        match order {
+            SortOrder::Asc => ord,
+            SortOrder::Desc => ord.reverse(),
+        }
+    });
+    let (matching, pager) = query.paginate(matching);
+    let mut rows = controls_row("/admin/ban", &query, &pager, &BAN_SORTS, order, 7);
+    rows += &matching
+        .iter()
+        .map(|(b, _)| {
             let subject_label = describe_ban_subject(b);
             let subject_key = b.subject.key();
             let reason_enc = htmlescape::encode_minimal(&b.reason);
             let subject_enc = htmlescape::encode_minimal(&subject_label);
-            let key_enc = htmlescape::encode_minimal(subject_key);
-            format!("<tr><td>{}</td><td>{}</td><td>{}</td><td><form method=post action=/unban style=margin:0><input type=hidden name=key value=\"{}\"><button type=submit class=del aria-label=\"Unban {}\">Unban</button></form></td></tr>", subject_enc, reason_enc, b.time, key_enc, subject_enc)
+            let key_enc = htmlescape::encode_minimal(&subject_key);
+            let hits = state.ban_hits.get(&subject_key);
+            let last_hit = hits
+                .last_hit
+                .map_or_else(|| "never".to_string(), |t| t.to_string());
+            let expires = b
+                .expires
+                .map_or_else(|| "never".to_string(), |t| t.to_string());
+            format!("<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td data-hits={}>{} <small>({:.1}/day)</small></td><td>{}</td><td><form method=post action=/unban style=margin:0><input type=hidden name=key value=\"{}\"><button type=submit class=del aria-label=\"Unban {}\">Unban</button></form></td></tr>", subject_enc, reason_enc, b.time, expires, hits.hits, hits.hits, hits.per_day(b.time, now), last_hit, key_enc, subject_enc)
         })
-        .collect();
-    let path = state.static_dir.join("admin_ban.html");
-    match fs::read(&path).await {
-        Ok(bytes) => {
-            let mut body = String::from_utf8_lossy(&bytes).into_owned();
-            body = body.replace("{{ROWS}}", &rows);
-            (
-                StatusCode::OK,
-                [(CONTENT_TYPE, HeaderValue::from_static("text/html"))],
-                body,
-            )
-                .into_response()
-        }
-        Err(_) => json_error(
-            StatusCode::NOT_FOUND,
-            "missing_template",
-            "ban template missing",
-        ),
-    }
+        .collect::<String>();
+    render_admin_page(&state, AdminPage::Bans, &rows).await
 }
 
 #[axum::debug_handler]
@@ -140,6 +492,34 @@ pub async fn ban_post_handler(
             "unable to interpret target",
         );
     };
+    if !state.geoip.can_match(&subject) {
+        warn!(
+            target = input,
+            "ban submission rejected: no geoip database for target"
+        );
+        return json_error(
+            StatusCode::BAD_REQUEST,
+            "geoip_unavailable",
+            "country and ASN bans need the matching GeoIP database configured",
+        );
+    }
+    let duration = frm.duration.as_deref().map(str::trim).unwrap_or_default();
+    let expires = if duration.is_empty() {
+        None
+    } else {
+        let Some(secs) = parse_ttl_secs(duration) else {
+            warn!(
+                target = input,
+                duration, "ban submission rejected: invalid duration"
+            );
+            return json_error(
+                StatusCode::BAD_REQUEST,
+                "invalid_duration",
+                "duration must look like 90, 30m, 12h or 7d",
+            );
+        };
+        Some(state.now_secs().saturating_add(secs))
+    };
     let reason_trimmed = frm.reason.as_deref().map(str::trim).unwrap_or_default();
     let reason = reason_trimmed.to_string();
     let ban = IpBan {
@@ -147,10 +527,61 @@ pub async fn ban_post_handler(
         label: Some(input.to_string()),
         reason,
         time: 0,
+        expires,
+    };
+    let remove_hash = match &ban.subject {
+        BanSubject::Exact { hash } if frm.remove_files.is_some() => Some(hash.clone()),
+        _ => None,
     };
     state.add_ban(ban).await;
     state.persist_bans().await;
-    info!(target = input, reason = reason_trimmed, "ban added");
+    info!(
+        target = input,
+        reason = reason_trimmed,
+        ?expires,
+        "ban added"
+    );
+    let actor = audit_actor(&state, &headers).await;
+    let detail = if duration.is_empty() {
+        reason_trimmed.to_string()
+    } else {
+        format!("{reason_trimmed} (for {duration})")
+    };
+    state
+        .record_audit(
+            AuditAction::Ban,
+            &actor,
+            Some(input),
+            Some(detail.trim_start()),
+        )
+        .await;
+    if let Some(owner_hash) = remove_hash {
+        // Network bans cover addresses that are not stored, so only exact
+        // bans can find the files they should take down.
+        let files: Vec<String> = state
+            .owners
+            .iter()
+            .filter(|entry| entry.value().owner_hash == owner_hash)
+            .map(|entry| entry.key().clone())
+            .collect();
+        for file in &files {
+            if state.remove_file_for(file, RemovalReason::Banned).await {
+                state
+                    .record_audit(
+                        AuditAction::FileDelete,
+                        &actor,
+                        Some(file),
+                        Some(RemovalReason::Banned.as_str()),
+                    )
+                    .await;
+            }
+        }
+        info!(
+            target = input,
+            removed = files.len(),
+            "removed banned uploader's files"
+        );
+    }
     (
         StatusCode::SEE_OTHER,
         [(LOCATION, HeaderValue::from_static("/admin/ban"))],
@@ -182,6 +613,10 @@ pub async fn unban_post_handler(
     state.remove_ban(key).await;
     state.persist_bans().await;
     info!(ban_key = key, "ban removed");
+    let actor = audit_actor(&state, &headers).await;
+    state
+        .record_audit(AuditAction::Unban, &actor, Some(key), None)
+        .await;
     (
         StatusCode::SEE_OTHER,
         [(LOCATION, HeaderValue::from_static("/admin/ban"))],
@@ -191,40 +626,12 @@ pub async fn unban_post_handler(
 
 pub async fn auth_get_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
     trace!("serving admin auth page");
-    if let Some(tok) = get_cookie(&headers, "adm") {
-        if state.is_admin(&tok).await {
-            let already_path = state.static_dir.join("admin_already.html");
-            if let Ok(bytes) = fs::read(&already_path).await {
-                return (
-                    StatusCode::OK,
-                    [(CONTENT_TYPE, HeaderValue::from_static("text/html"))],
-                    bytes,
-                )
-                    .into_response();
-            }
-            return (
-                StatusCode::OK,
-                [(CONTENT_TYPE, HeaderValue::from_static("text/html"))],
-                "<html><body><p>Already admin.</p><a href=/isadmin>Check</a></body></html>",
-            )
-                .into_response();
-        }
-    }
-    let tpl_path = state.static_dir.join("admin_auth.html");
-    match fs::read(&tpl_path).await {
-        Ok(bytes) => (
-            StatusCode::OK,
-            [(CONTENT_TYPE, HeaderValue::from_static("text/html"))],
-            bytes,
-        )
-            .into_response(),
-        Err(_) => (
-            StatusCode::OK,
-            [(CONTENT_TYPE, HeaderValue::from_static("text/html"))],
-            "<html><body><form method=post action=/auth><input type=password name=key autofocus placeholder=Admin+Key><button type=submit>Auth</button></form></body></html>",
-        )
-            .into_response(),
+    if let Some(tok) = get_cookie(&headers, "adm")
+        && state.is_admin(&tok).await
+    {
+        return render_admin_page(&state, AdminPage::Already, "").await;
     }
+    render_admin_page(&state, AdminPage::Auth, "").await
 }
 
 pub async fn auth_post_handler(
@@ -281,194 +688,1068 @@ pub async fn auth_post_json_handler(
             h.append(SET_COOKIE, HeaderValue::from_str(&cookie).unwrap());
         }
         info!("admin auth success (json)");
+        state
+            .record_audit(AuditAction::AuthSuccess, &session_actor(&token), None, None)
+            .await;
         return resp;
     }
     warn!("admin auth (json) failed: invalid key");
+    state
+        .record_audit(
+            AuditAction::AuthFailure,
+            ANONYMOUS_ACTOR,
+            None,
+            Some("invalid key"),
+        )
+        .await;
     json_error(StatusCode::UNAUTHORIZED, "invalid_key", "invalid key")
 }
 
 pub async fn is_admin_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
     trace!("checking admin session status");
-    if let Some(tok) = get_cookie(&headers, "adm") {
-        if state.is_admin(&tok).await {
-            return (StatusCode::OK, Json(json!({"admin": true}))).into_response();
-        }
+    if let Some(tok) = get_cookie(&headers, "adm")
+        && state.is_admin(&tok).await
+    {
+        return (StatusCode::OK, Json(json!({"admin": true}))).into_response();
     }
     (StatusCode::OK, Json(json!({"admin": false}))).into_response()
 }
 
-pub async fn admin_files_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
-    trace!("rendering admin files view");
+/// Effective runtime configuration, for checking a deployment remotely.
+pub async fn admin_runtime_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
     if let Some(tok) = get_cookie(&headers, "adm") {
         if !state.is_admin(&tok).await {
-            warn!("admin files access denied: invalid session");
+            warn!("admin runtime access denied: invalid session");
             return json_error(StatusCode::UNAUTHORIZED, "not_admin", "auth required");
         }
     } else {
-        warn!("admin files access denied: missing session");
+        warn!("admin runtime access denied: missing session");
         return json_error(StatusCode::UNAUTHORIZED, "not_admin", "auth required");
     }
-    let mut rows = String::new();
-    let now = now_secs();
-    for entry in state.owners.iter() {
-        let file = entry.key();
-        let meta = entry.value();
-        let path = state.upload_dir.join(file);
-        let size = match fs::metadata(&path).await {
-            Ok(md) => md.len(),
-            Err(_) => 0,
-        };
-        let remain = if meta.expires > now {
-            meta.expires - now
-        } else {
-            0
-        };
-        let human = if remain >= 86400 {
-            format!("{}d", remain / 86400)
-        } else if remain >= 3600 {
-            format!("{}h", remain / 3600)
-        } else if remain >= 60 {
-            format!("{}m", remain / 60)
-        } else {
-            format!("{}s", remain)
-        };
-        let file_href = format!("/f/{}", urlencoding::encode(file));
-        let file_label = htmlescape::encode_minimal(file);
-        let owner_label = htmlescape::encode_minimal(&short_hash(&meta.owner_hash));
-        let file_attr = htmlescape::encode_minimal(file);
-        rows.push_str(&format!("<tr><td><a href=\"{href}\" target=_blank rel=noopener>{label}</a></td><td>{owner}</td><td data-exp=\"{exp}\">{human}</td><td>{size}</td><td><form method=post action=/admin/files style=margin:0><input type=hidden name=file value=\"{file_attr}\"><button type=submit class=del data-file=\"{file_attr}\">Delete</button></form></td></tr>",
-            href = file_href,
-            label = file_label,
-            owner = owner_label,
-            exp = meta.expires,
-            human = human,
-            size = size,
-            file_attr = file_attr,
-        ));
-    }
-    let tpl_path = state.static_dir.join("admin_files.html");
-    match fs::read(&tpl_path).await {
-        Ok(bytes) => {
-            let mut body = String::from_utf8_lossy(&bytes).into_owned();
-            body = body.replace("{{FILE_ROWS}}", &rows);
-            (
-                StatusCode::OK,
-                [(CONTENT_TYPE, HeaderValue::from_static("text/html"))],
-                body,
-            )
-                .into_response()
-        }
-        Err(_) => json_error(
-            StatusCode::NOT_FOUND,
-            "missing_template",
-            "admin files template missing",
-        ),
-    }
+    (
+        [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+        Json(RuntimeSummary::collect(&state)),
+    )
+        .into_response()
 }
 
-#[axum::debug_handler]
-pub async fn admin_file_delete_handler(
+/// Adjust Sentry sampling without a restart, e.g. to trace everything during
+/// an incident. Changes last until the process exits.
+pub async fn admin_runtime_update_handler(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Form(frm): Form<AdminFileDeleteForm>,
+    Json(update): Json<SamplingUpdate>,
 ) -> Response {
-    trace!(file = %frm.file, "admin file delete requested");
-    if let Some(tok) = get_cookie(&headers, "adm") {
-        if !state.is_admin(&tok).await {
-            warn!(file = %frm.file, "admin file delete rejected: invalid session");
-            return json_error(StatusCode::UNAUTHORIZED, "not_admin", "auth required");
-        }
-    } else {
-        warn!(file = %frm.file, "admin file delete rejected: missing session");
-        return json_error(StatusCode::UNAUTHORIZED, "not_admin", "auth required");
+    if let Some(denied) = require_admin(&state, &headers, "runtime update").await {
+        return denied;
     }
-    let file = frm.file.trim();
-    if file.is_empty() || file.contains('/') || file.contains('\\') {
-        warn!(file, "admin file delete rejected: invalid name");
-        return json_error(StatusCode::BAD_REQUEST, "bad_file", "invalid file");
+    if let Err(err) = update.apply(&state.trace_sampler) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "code": "invalid_sampling",
+                "message": err.to_string(),
+                "request_id": current_request_id(),
+            })),
+        )
+            .into_response();
     }
-    state.owners.remove(file);
-    let _ = fs::remove_file(state.upload_dir.join(file)).await;
-    state.persist_owners().await;
-    info!(file, "admin deleted file");
+    let status = state.trace_sampler.status();
+    info!(
+        target: AUDIT_LOG_TARGET,
+        action = "runtime_update",
+        traces_sample_rate_min = status.floor,
+        traces_sample_rate = status.ceiling,
+        traces_error_budget = status.error_budget,
+        error_sample_rate = status.error_sample_rate,
+        "sampling rates changed"
+    );
+    let actor = audit_actor(&state, &headers).await;
+    let detail = format!(
+        "traces {}..{}, error budget {}, errors {}",
+        status.floor, status.ceiling, status.error_budget, status.error_sample_rate
+    );
+    state
+        .record_audit(AuditAction::RuntimeUpdate, &actor, None, Some(&detail))
+        .await;
     (
-        StatusCode::SEE_OTHER,
-        [(LOCATION, HeaderValue::from_static("/admin/files"))],
+        [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+        Json(RuntimeSummary::collect(&state)),
     )
         .into_response()
 }
 
-pub async fn admin_reports_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
-    trace!("rendering admin reports view");
-    if let Some(tok) = get_cookie(&headers, "adm") {
-        if !state.is_admin(&tok).await {
-            warn!("admin reports access denied: invalid session");
-            return json_error(StatusCode::UNAUTHORIZED, "not_admin", "auth required");
+#[derive(Deserialize, Default)]
+pub struct ApiTokenCreateRequest {
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ApiTokenCreated {
+    /// The bearer secret. It is not stored and cannot be shown again.
+    pub token: String,
+    /// Secret access key for the S3 API, whose access key id is the token id.
+    pub s3_secret_access_key: String,
+    #[serde(flatten)]
+    pub record: ApiToken,
+}
+
+/// How the admin credentials on a request checked out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum AdminCheck {
+    Granted,
+    /// Neither a bearer token nor an admin cookie was sent.
+    Missing,
+    /// Credentials were sent and rejected; already logged and audited.
+    Denied,
+}
+
+/// The admin credential check: an admin session cookie, or the admin key (or
+/// a session token) as `Authorization: Bearer` for scripts. Rejected
+/// credentials are logged and recorded as an auth failure in the audit log,
+/// whichever endpoint they were tried against.
+pub(crate) async fn check_admin(state: &AppState, headers: &HeaderMap, what: &str) -> AdminCheck {
+    let failure = if let Some(secret) = bearer_token(headers) {
+        let key = state.admin_key.read().await.clone();
+        if (!key.is_empty() && subtle_equals(secret.as_bytes(), key.as_bytes()))
+            || state.is_admin(secret).await
+        {
+            return AdminCheck::Granted;
         }
+        "invalid bearer token"
     } else {
-        warn!("admin reports access denied: missing session");
-        return json_error(StatusCode::UNAUTHORIZED, "not_admin", "auth required");
-    }
-    let reports = state.reports.read().await.clone();
-    let mut rows = String::new();
-    for (idx, r) in reports.iter().enumerate() {
-        rows.push_str(&format!("<tr><td><a href=\"/{file}\" target=_blank rel=noopener>{file}</a></td><td>{reason}</td><td>{details}</td><td>{reporter}</td><td>{time}</td><td><form method=post action=/admin/reports style=margin:0><input type=hidden name=idx value=\"{idx}\"><button type=submit class=del data-idx=\"{idx}\">Remove</button></form></td></tr>",
-            file=htmlescape::encode_minimal(&r.file),
-            reason=htmlescape::encode_minimal(&r.reason),
-            details=htmlescape::encode_minimal(&r.details),
-            reporter=htmlescape::encode_minimal(&short_hash(&r.reporter_hash)),
-            time=r.time,
-            idx=idx));
+        match get_cookie(headers, "adm") {
+            Some(tok) if state.is_admin(&tok).await => return AdminCheck::Granted,
+            Some(_) => "invalid session",
+            None => return AdminCheck::Missing,
+        }
+    };
+    warn!(what, "admin access denied: {failure}");
+    state
+        .record_audit(
+            AuditAction::AuthFailure,
+            ANONYMOUS_ACTOR,
+            None,
+            Some(&format!("{failure} for {what}")),
+        )
+        .await;
+    AdminCheck::Denied
+}
+
+/// Admin check for the JSON endpoints; see [`check_admin`].
+async fn require_admin(state: &AppState, headers: &HeaderMap, what: &str) -> Option<Response> {
+    match check_admin(state, headers, what).await {
+        AdminCheck::Granted => return None,
+        AdminCheck::Missing => warn!(what, "admin access denied: missing session"),
+        AdminCheck::Denied => {}
     }
-    let tpl_path = state.static_dir.join("admin_reports.html");
-    match fs::read(&tpl_path).await {
-        Ok(bytes) => {
-            let mut body = String::from_utf8_lossy(&bytes).into_owned();
-            body = body.replace("{{REPORT_ROWS}}", &rows);
-            (
-                StatusCode::OK,
-                [(CONTENT_TYPE, HeaderValue::from_static("text/html"))],
-                body,
-            )
-                .into_response()
+    Some(json_error(
+        StatusCode::UNAUTHORIZED,
+        "not_admin",
+        "auth required",
+    ))
+}
+
+/// Actor recorded for a failed sign-in.
+const ANONYMOUS_ACTOR: &str = "anonymous";
+
+/// Who an already authorised admin request came from, for the audit log:
+/// the session in the bearer token or cookie, or the admin key itself.
+async fn audit_actor(state: &AppState, headers: &HeaderMap) -> String {
+    if let Some(secret) = bearer_token(headers) {
+        let key = state.admin_key.read().await.clone();
+        if !key.is_empty() && subtle_equals(secret.as_bytes(), key.as_bytes()) {
+            return "admin_key".to_string();
         }
-        Err(_) => json_error(
-            StatusCode::NOT_FOUND,
-            "missing_template",
-            "admin reports template missing",
-        ),
+        return session_actor(secret);
     }
+    get_cookie(headers, "adm")
+        .map(|token| session_actor(&token))
+        .unwrap_or_else(|| ANONYMOUS_ACTOR.to_string())
 }
 
-#[axum::debug_handler]
-pub async fn admin_report_delete_handler(
+/// Stop taking uploads, let running ones finish, then shut down. Deploys
+/// call this instead of sending SIGTERM straight away.
+pub async fn admin_drain_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(denied) = require_admin(&state, &headers, "drain").await {
+        return denied;
+    }
+    let started = state.drain.begin();
+    let in_flight = drain::in_flight_uploads(&state);
+    if started {
+        info!(
+            target: AUDIT_LOG_TARGET,
+            action = "drain",
+            in_flight,
+            timeout_secs = state.drain.timeout().as_secs(),
+            "drain started"
+        );
+        let actor = audit_actor(&state, &headers).await;
+        state
+            .record_audit(AuditAction::Drain, &actor, None, None)
+            .await;
+        tokio::spawn(drain::run(state.clone()));
+    } else {
+        debug!(in_flight, "drain already under way");
+    }
+    (
+        StatusCode::ACCEPTED,
+        [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+        Json(json!({
+            "draining": true,
+            "already_draining": !started,
+            "in_flight": in_flight,
+            "timeout_secs": state.drain.timeout().as_secs(),
+        })),
+    )
+        .into_response()
+}
+
+/// Start backfilling hashes, sizes and creation times for old metadata.
+pub async fn admin_reindex_start_handler(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Form(frm): Form<AdminReportDeleteForm>,
 ) -> Response {
-    trace!(index = frm.idx, "admin report delete requested");
-    if let Some(tok) = get_cookie(&headers, "adm") {
-        if !state.is_admin(&tok).await {
-            warn!(
-                idx = frm.idx,
-                "admin report delete rejected: invalid session"
-            );
-            return json_error(StatusCode::UNAUTHORIZED, "not_admin", "auth required");
-        }
-    } else {
-        warn!(
-            idx = frm.idx,
-            "admin report delete rejected: missing session"
-        );
+    if let Some(denied) = require_admin(&state, &headers, "reindex").await {
+        return denied;
+    }
+    if !state.reindex.begin() {
+        return (
+            StatusCode::CONFLICT,
+            [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+            Json(json!({
+                "code": "reindex_running",
+                "message": "A reindex is already running",
+                "progress": state.reindex.progress(),
+                "request_id": current_request_id(),
+            })),
+        )
+            .into_response();
+    }
+    info!(
+        target: AUDIT_LOG_TARGET,
+        action = "reindex",
+        bytes_per_sec = state.reindex.bytes_per_sec(),
+        "reindex started"
+    );
+    let actor = audit_actor(&state, &headers).await;
+    state
+        .record_audit(AuditAction::Reindex, &actor, None, None)
+        .await;
+    tokio::spawn(reindex::run(state.clone()));
+    (
+        StatusCode::ACCEPTED,
+        [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+        Json(json!({ "started": true, "bytes_per_sec": state.reindex.bytes_per_sec() })),
+    )
+        .into_response()
+}
+
+/// Progress of the current or last reindex.
+pub async fn admin_reindex_status_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(denied) = require_admin(&state, &headers, "reindex").await {
+        return denied;
+    }
+    (
+        [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+        Json(state.reindex.progress()),
+    )
+        .into_response()
+}
+
+/// List API tokens. Secrets are never included.
+pub async fn admin_tokens_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(denied) = require_admin(&state, &headers, "api tokens").await {
+        return denied;
+    }
+    (
+        [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+        Json(json!({ "tokens": state.api_tokens.list() })),
+    )
+        .into_response()
+}
+
+/// Issue an API token for scripted uploads.
+pub async fn admin_token_create_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    req: Option<Json<ApiTokenCreateRequest>>,
+) -> Response {
+    if let Some(denied) = require_admin(&state, &headers, "api token create").await {
+        return denied;
+    }
+    let label = req
+        .and_then(|Json(req)| req.label)
+        .map(|label| label.trim().to_string())
+        .filter(|label| !label.is_empty());
+    let (token, record) = state.api_tokens.issue(label);
+    state.persist_api_tokens().await;
+    info!(token_id = %record.id, "api token issued");
+    let actor = audit_actor(&state, &headers).await;
+    state
+        .record_audit(
+            AuditAction::TokenCreate,
+            &actor,
+            Some(&record.id),
+            record.label.as_deref(),
+        )
+        .await;
+    (
+        StatusCode::CREATED,
+        [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+        Json(ApiTokenCreated {
+            token,
+            s3_secret_access_key: s3_secret_access_key(&state, &record.id),
+            record,
+        }),
+    )
+        .into_response()
+}
+
+/// Revoke an API token. Files uploaded with it stay until they expire.
+pub async fn admin_token_revoke_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    if let Some(denied) = require_admin(&state, &headers, "api token revoke").await {
+        return denied;
+    }
+    if state.api_tokens.revoke(&id).is_none() {
+        return json_error(StatusCode::NOT_FOUND, "not_found", "api token not found");
+    }
+    state.persist_api_tokens().await;
+    info!(token_id = %id, "api token revoked");
+    let actor = audit_actor(&state, &headers).await;
+    state
+        .record_audit(AuditAction::TokenRevoke, &actor, Some(&id), None)
+        .await;
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// The whole ban list, oldest first, as JSON or `?format=csv`.
+pub async fn admin_bans_export_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<BanExportQuery>,
+) -> Response {
+    if let Some(denied) = require_admin(&state, &headers, "ban export").await {
+        return denied;
+    }
+    let mut bans = state.bans.read().await.clone();
+    bans.sort_by(|a, b| {
+        a.time
+            .cmp(&b.time)
+            .then_with(|| a.subject.key().cmp(&b.subject.key()))
+    });
+    info!(count = bans.len(), format = ?query.format, "ban list exported");
+    match query.format.as_deref().map(str::trim) {
+        None | Some("") | Some("json") => (
+            [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+            Json(BanList { bans }),
+        )
+            .into_response(),
+        Some("csv") => (
+            [
+                (CACHE_CONTROL, HeaderValue::from_static("no-store")),
+                (
+                    CONTENT_TYPE,
+                    HeaderValue::from_static("text/csv; charset=utf-8"),
+                ),
+                (
+                    CONTENT_DISPOSITION,
+                    HeaderValue::from_static("attachment; filename=\"juicebox-bans.csv\""),
+                ),
+            ],
+            ban_transfer::to_csv(&bans),
+        )
+            .into_response(),
+        Some(_) => json_error(
+            StatusCode::BAD_REQUEST,
+            "invalid_format",
+            "format must be json or csv",
+        ),
+    }
+}
+
+/// Merge an exported ban list into this one by ban key. Takes the JSON
+/// export (or a bare array of bans), or the CSV export when sent as
+/// `text/csv`.
+pub async fn admin_bans_import_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if let Some(denied) = require_admin(&state, &headers, "ban import").await {
+        return denied;
+    }
+    let is_csv = headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.trim_start().to_ascii_lowercase().starts_with("text/csv"));
+    let parsed = if is_csv {
+        std::str::from_utf8(&body)
+            .map_err(|_| "csv is not utf-8".to_string())
+            .and_then(ban_transfer::parse_csv)
+    } else {
+        serde_json::from_slice::<BanList>(&body)
+            .map(|list| list.bans)
+            .or_else(|_| serde_json::from_slice(&body))
+            .map_err(|err| err.to_string())
+    };
+    let bans = match parsed {
+        Ok(bans) => bans,
+        Err(err) => {
+            warn!(error = %err, csv = is_csv, "ban import rejected");
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "code": "invalid_import",
+                    "message": err,
+                    "request_id": current_request_id(),
+                })),
+            )
+                .into_response();
+        }
+    };
+    let summary = state.import_bans(bans).await;
+    let actor = audit_actor(&state, &headers).await;
+    let detail = format!(
+        "added {}, updated {}, unchanged {}, expired {}",
+        summary.added, summary.updated, summary.unchanged, summary.expired
+    );
+    state
+        .record_audit(AuditAction::BanImport, &actor, None, Some(&detail))
+        .await;
+    (
+        [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+        Json(summary),
+    )
+        .into_response()
+}
+
+#[derive(Deserialize)]
+pub struct ShadowToggleRequest {
+    pub enabled: bool,
+}
+
+/// Request mirroring settings and counters.
+pub async fn admin_shadow_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(denied) = require_admin(&state, &headers, "shadow status").await {
+        return denied;
+    }
+    (
+        [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+        Json(state.shadow.status()),
+    )
+        .into_response()
+}
+
+/// Kill switch for request mirroring. Not persisted: a restart goes back to
+/// what the environment says.
+pub async fn admin_shadow_toggle_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<ShadowToggleRequest>,
+) -> Response {
+    if let Some(denied) = require_admin(&state, &headers, "shadow toggle").await {
+        return denied;
+    }
+    state.shadow.set_enabled(req.enabled);
+    info!(enabled = req.enabled, "request mirroring toggled");
+    let actor = audit_actor(&state, &headers).await;
+    let detail = if req.enabled { "enabled" } else { "disabled" };
+    state
+        .record_audit(AuditAction::ShadowToggle, &actor, None, Some(detail))
+        .await;
+    (
+        [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+        Json(state.shadow.status()),
+    )
+        .into_response()
+}
+
+/// Feature flags with the rule in force and where it came from.
+pub async fn admin_flags_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(denied) = require_admin(&state, &headers, "feature flags").await {
+        return denied;
+    }
+    (
+        [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+        Json(state.flags.list()),
+    )
+        .into_response()
+}
+
+/// Override a flag at runtime. Overrides are persisted and win over the
+/// flags file and environment.
+pub async fn admin_flag_set_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+    Json(rule): Json<FlagRule>,
+) -> Response {
+    if let Some(denied) = require_admin(&state, &headers, "feature flag update").await {
+        return denied;
+    }
+    let name = name.trim().to_ascii_lowercase();
+    if name.is_empty() {
+        return json_error(
+            StatusCode::BAD_REQUEST,
+            "invalid_flag",
+            "flag name required",
+        );
+    }
+    info!(flag = %name, enabled = rule.enabled, rollout = ?rule.rollout, "feature flag overridden");
+    let actor = audit_actor(&state, &headers).await;
+    let detail = serde_json::to_string(&rule).unwrap_or_default();
+    state
+        .record_audit(AuditAction::FlagSet, &actor, Some(&name), Some(&detail))
+        .await;
+    state.flags.set_override(&name, rule);
+    state.persist_feature_flags().await;
+    (
+        [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+        Json(state.flags.list()),
+    )
+        .into_response()
+}
+
+/// Drop a runtime override, falling back to the flags file and environment.
+pub async fn admin_flag_clear_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> Response {
+    if let Some(denied) = require_admin(&state, &headers, "feature flag reset").await {
+        return denied;
+    }
+    if !state.flags.clear_override(&name.to_ascii_lowercase()) {
+        return json_error(StatusCode::NOT_FOUND, "not_found", "no override for flag");
+    }
+    info!(flag = %name, "feature flag override cleared");
+    let actor = audit_actor(&state, &headers).await;
+    state
+        .record_audit(AuditAction::FlagClear, &actor, Some(&name), None)
+        .await;
+    state.persist_feature_flags().await;
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// A [`RemovalReason`] code, defaulting to `other`; `None` if unknown.
+fn parse_removal_reason(raw: Option<&str>) -> Option<RemovalReason> {
+    match raw.map(str::trim) {
+        None | Some("") => Some(RemovalReason::Other),
+        Some(raw) => RemovalReason::parse(raw),
+    }
+}
+
+fn is_plain_file_name(file: &str) -> bool {
+    !file.is_empty() && !file.contains('/') && !file.contains('\\')
+}
+
+/// Hosted files as JSON, newest first, optionally for one owner.
+pub async fn admin_files_api_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<AdminFilesQuery>,
+) -> Response {
+    if let Some(denied) = require_admin(&state, &headers, "files api").await {
+        return denied;
+    }
+    let owner = query
+        .owner
+        .as_deref()
+        .map(str::trim)
+        .filter(|o| !o.is_empty());
+    let entries: Vec<(String, FileMeta)> = state
+        .owners
+        .iter()
+        .filter(|e| owner.is_none_or(|owner| e.value().owner_hash == owner))
+        .map(|e| (e.key().clone(), e.value().clone()))
+        .collect();
+    let mut files = Vec::with_capacity(entries.len());
+    for (name, meta) in entries {
+        // Entries from before sizes were recorded read as 0 until the next
+        // storage sweep; ask the store instead.
+        let size = if meta.size > 0 {
+            meta.size
+        } else {
+            state
+                .file_store
+                .size(&name)
+                .await
+                .ok()
+                .flatten()
+                .unwrap_or(0)
+        };
+        files.push(AdminFileEntry {
+            original: meta.original,
+            owner_hash: meta.owner_hash,
+            size,
+            created: meta.created,
+            expires: meta.expires,
+            hash: meta.hash,
+            downloads: meta.downloads,
+            max_downloads: meta.max_downloads,
+            private: meta.private,
+            network_class: meta.network_class.map(|class| class.as_str()),
+            name,
+        });
+    }
+    files.sort_by(|a, b| b.created.cmp(&a.created).then_with(|| a.name.cmp(&b.name)));
+    let total_bytes: u64 = files.iter().map(|f| f.size).sum();
+    (
+        [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+        Json(json!({
+            "files": files,
+            "count": files.len(),
+            "total_bytes": total_bytes,
+        })),
+    )
+        .into_response()
+}
+
+/// Take `files` down for `reason`, returning the names that were hosted.
+async fn admin_remove_files(
+    state: &AppState,
+    actor: &str,
+    files: &[String],
+    reason: RemovalReason,
+) -> (Vec<String>, Vec<String>) {
+    let mut deleted = Vec::new();
+    let mut missing = Vec::new();
+    for file in files {
+        if state.remove_file_for(file, reason).await {
+            info!(
+                target: AUDIT_LOG_TARGET,
+                action = "file_delete",
+                file = %file,
+                reason = reason.as_str(),
+                "admin deleted file via api"
+            );
+            state
+                .record_audit(
+                    AuditAction::FileDelete,
+                    actor,
+                    Some(file),
+                    Some(reason.as_str()),
+                )
+                .await;
+            deleted.push(file.clone());
+        } else {
+            missing.push(file.clone());
+        }
+    }
+    (deleted, missing)
+}
+
+/// Delete one hosted file. `?reason=` takes a [`RemovalReason`] code.
+pub async fn admin_file_api_delete_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+    Query(query): Query<AdminFileDeleteQuery>,
+) -> Response {
+    if let Some(denied) = require_admin(&state, &headers, "files api delete").await {
+        return denied;
+    }
+    if !is_plain_file_name(&name) {
+        return json_error(StatusCode::BAD_REQUEST, "bad_file", "invalid file");
+    }
+    let Some(reason) = parse_removal_reason(query.reason.as_deref()) else {
+        return json_error(StatusCode::BAD_REQUEST, "bad_reason", "unknown reason");
+    };
+    let actor = audit_actor(&state, &headers).await;
+    let (deleted, _) =
+        admin_remove_files(&state, &actor, std::slice::from_ref(&name), reason).await;
+    if deleted.is_empty() {
+        return json_error(StatusCode::NOT_FOUND, "not_found", "file not found");
+    }
+    Json(json!({ "deleted": deleted, "reason": reason.as_str() })).into_response()
+}
+
+/// Delete several hosted files at once: `{"files": [...], "reason": "spam"}`.
+/// Names that are not hosted are listed under `missing`.
+pub async fn admin_files_api_delete_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<AdminFilesDeleteRequest>,
+) -> Response {
+    if let Some(denied) = require_admin(&state, &headers, "files api delete").await {
+        return denied;
+    }
+    if req.files.is_empty() {
+        return json_error(StatusCode::BAD_REQUEST, "missing", "no files given");
+    }
+    if !req.files.iter().all(|file| is_plain_file_name(file)) {
+        return json_error(StatusCode::BAD_REQUEST, "bad_file", "invalid file");
+    }
+    let Some(reason) = parse_removal_reason(req.reason.as_deref()) else {
+        return json_error(StatusCode::BAD_REQUEST, "bad_reason", "unknown reason");
+    };
+    let actor = audit_actor(&state, &headers).await;
+    let (deleted, missing) = admin_remove_files(&state, &actor, &req.files, reason).await;
+    Json(json!({
+        "deleted": deleted,
+        "missing": missing,
+        "reason": reason.as_str(),
+    }))
+    .into_response()
+}
+
+pub async fn admin_files_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<AdminListQuery>,
+) -> Response {
+    trace!("rendering admin files view");
+    if let Some(tok) = get_cookie(&headers, "adm") {
+        if !state.is_admin(&tok).await {
+            warn!("admin files access denied: invalid session");
+            return json_error(StatusCode::UNAUTHORIZED, "not_admin", "auth required");
+        }
+    } else {
+        warn!("admin files access denied: missing session");
         return json_error(StatusCode::UNAUTHORIZED, "not_admin", "auth required");
     }
-    let idx = frm.idx;
+    let mut rows = String::new();
+    let now = state.now_secs();
+    let reasons: String = RemovalReason::ADMIN_CHOICES
+        .iter()
+        .map(|reason| format!("<option value={0}>{0}</option>", reason.as_str()))
+        .collect();
+    let mut entries: Vec<(String, FileMeta)> = state
+        .owners
+        .iter()
+        .filter(|e| {
+            query.matches_owner(&e.value().owner_hash)
+                && query.matches_text([e.key().as_str(), e.value().original.as_str()])
+        })
+        .map(|e| (e.key().clone(), e.value().clone()))
+        .collect();
+    // Sizes come from the metadata so sorting does not stat every file;
+    // entries not yet backfilled sort as 0.
+    let sort = query.sort_key(&FILE_SORTS.map(|(key, _)| key));
+    let order = query.order(if sort == "name" {
+        SortOrder::Asc
+    } else {
+        SortOrder::Desc
+    });
+    entries.sort_by(|(a_name, a), (b_name, b)| {
+        let ord = match sort {
+            "size" => a.size.cmp(&b.size),
+            "expires" => a.expires.cmp(&b.expires),
+            "downloads" => a.downloads.cmp(&b.downloads),
+            "name" => a_name.cmp(b_name),
+            _ => a.created.cmp(&b.created),
+        }
+        .then_with(|| a_name.cmp(b_name));
+        match order {
+            SortOrder::Asc => ord,
+            SortOrder::Desc => ord.reverse(),
+        }
+    });
+    let (entries, pager) = query.paginate(entries);
+    rows.push_str(&controls_row(
+        "/admin/files",
+        &query,
+        &pager,
+        &FILE_SORTS,
+        order,
+        7,
+    ));
+    for (file, meta) in &entries {
+        let size = if meta.size > 0 {
+            meta.size
+        } else {
+            state
+                .file_store
+                .size(file)
+                .await
+                .ok()
+                .flatten()
+                .unwrap_or(0)
+        };
+        let remain = meta.expires.saturating_sub(now);
+        let human = if remain >= 86400 {
+            format!("{}d", remain / 86400)
+        } else if remain >= 3600 {
+            format!("{}h", remain / 3600)
+        } else if remain >= 60 {
+            format!("{}m", remain / 60)
+        } else {
+            format!("{}s", remain)
+        };
+        let file_href = format!("/f/{}", urlencoding::encode(file));
+        let file_label = htmlescape::encode_minimal(file);
+        let owner_label = htmlescape::encode_minimal(&short_hash(&meta.owner_hash));
+        let file_attr = htmlescape::encode_minimal(file);
+        rows.push_str(&format!("<tr><td><a href=\"{href}\" target=_blank rel=noopener>{label}</a><br>{original}</td><td>{owner}</td><td>{network}</td><td data-exp=\"{exp}\">{human}</td><td>{size}</td><td>{downloads}</td><td><form method=post action=/admin/files style=margin:0><input type=hidden name=file value=\"{file_attr}\"><select name=reason aria-label=\"Removal reason\">{reasons}</select><button type=submit class=del data-file=\"{file_attr}\">Delete</button></form></td></tr>",
+            href = file_href,
+            label = file_label,
+            original = original_name_cell(&meta.original),
+            owner = owner_label,
+            network = meta.network_class.map_or("", |class| class.as_str()),
+            exp = meta.expires,
+            human = human,
+            size = size,
+            downloads = meta.downloads,
+            file_attr = file_attr,
+            reasons = reasons,
+        ));
+    }
+    render_admin_page(&state, AdminPage::Files, &rows).await
+}
+
+#[axum::debug_handler]
+pub async fn admin_file_delete_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(frm): Form<AdminFileDeleteForm>,
+) -> Response {
+    trace!(file = %frm.file, "admin file delete requested");
+    if let Some(tok) = get_cookie(&headers, "adm") {
+        if !state.is_admin(&tok).await {
+            warn!(file = %frm.file, "admin file delete rejected: invalid session");
+            return json_error(StatusCode::UNAUTHORIZED, "not_admin", "auth required");
+        }
+    } else {
+        warn!(file = %frm.file, "admin file delete rejected: missing session");
+        return json_error(StatusCode::UNAUTHORIZED, "not_admin", "auth required");
+    }
+    let file = frm.file.trim();
+    if !is_plain_file_name(file) {
+        warn!(file, "admin file delete rejected: invalid name");
+        return json_error(StatusCode::BAD_REQUEST, "bad_file", "invalid file");
+    }
+    let reason = match frm.reason.as_deref().map(str::trim) {
+        None | Some("") => RemovalReason::Other,
+        Some(raw) => match RemovalReason::parse(raw) {
+            Some(reason) => reason,
+            None => {
+                warn!(
+                    file,
+                    reason = raw,
+                    "admin file delete rejected: unknown reason"
+                );
+                return json_error(StatusCode::BAD_REQUEST, "bad_reason", "unknown reason");
+            }
+        },
+    };
+    if !state.remove_file_for(file, reason).await
+        && let Err(err) = state.file_store.delete(file).await
     {
-        let mut reports = state.reports.write().await;
-        if idx < reports.len() {
-            reports.remove(idx);
-            info!(idx, "admin removed report");
+        warn!(
+            ?err,
+            file, "failed to remove admin-deleted file from storage"
+        );
+    }
+    info!(file, reason = reason.as_str(), "admin deleted file");
+    let actor = audit_actor(&state, &headers).await;
+    state
+        .record_audit(
+            AuditAction::FileDelete,
+            &actor,
+            Some(file),
+            Some(reason.as_str()),
+        )
+        .await;
+    (
+        StatusCode::SEE_OTHER,
+        [(LOCATION, HeaderValue::from_static("/admin/files"))],
+    )
+        .into_response()
+}
+
+/// Sort columns of the files view; the first is the default.
+const FILE_SORTS: [(&str, &str); 5] = [
+    ("created", "Newest"),
+    ("size", "Size"),
+    ("expires", "Expiry"),
+    ("downloads", "Downloads"),
+    ("name", "Name"),
+];
+const REPORT_SORTS: [(&str, &str); 2] = [("time", "Time"), ("file", "File")];
+const BAN_SORTS: [(&str, &str); 2] = [("time", "Time"), ("hits", "Hits")];
+const AUDIT_SORTS: [(&str, &str); 2] = [("time", "Time"), ("action", "Action")];
+
+/// Longest resolution note accepted, in bytes.
+const REPORT_NOTE_MAX_BYTES: usize = 2000;
+
+fn report_status_options(current: ReportStatus) -> String {
+    ReportStatus::ALL
+        .iter()
+        .filter(|s| **s == current || current.can_transition_to(**s))
+        .map(|s| {
+            format!(
+                "<option value={value}{selected}>{value}</option>",
+                value = s.as_str(),
+                selected = if *s == current { " selected" } else { "" },
+            )
+        })
+        .collect()
+}
+
+pub async fn admin_reports_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<AdminListQuery>,
+) -> Response {
+    trace!("rendering admin reports view");
+    if let Some(tok) = get_cookie(&headers, "adm") {
+        if !state.is_admin(&tok).await {
+            warn!("admin reports access denied: invalid session");
+            return json_error(StatusCode::UNAUTHORIZED, "not_admin", "auth required");
         }
+    } else {
+        warn!("admin reports access denied: missing session");
+        return json_error(StatusCode::UNAUTHORIZED, "not_admin", "auth required");
+    }
+    let filter = match query.status.as_deref().map(str::trim) {
+        None | Some("") | Some("all") => None,
+        Some(raw) => match ReportStatus::parse(raw) {
+            Some(status) => Some(status),
+            None => {
+                return json_error(StatusCode::BAD_REQUEST, "bad_status", "unknown status");
+            }
+        },
+    };
+    let reports = state.reports.read().await.clone();
+    let mut rows = String::new();
+    let links: Vec<String> = std::iter::once(("all", filter.is_none()))
+        .chain(
+            ReportStatus::ALL
+                .iter()
+                .map(|s| (s.as_str(), filter == Some(*s))),
+        )
+        .map(|(key, current)| {
+            let count = match ReportStatus::parse(key) {
+                Some(status) => reports.iter().filter(|r| r.status == status).count(),
+                None => reports.len(),
+            };
+            if current {
+                format!("<strong>{key} ({count})</strong>")
+            } else {
+                format!("<a href=\"/admin/reports?status={key}\">{key} ({count})</a>")
+            }
+        })
+        .collect();
+    rows.push_str(&format!(
+        "<tr><td colspan=7>Show: {}</td></tr>",
+        links.join(" · ")
+    ));
+    let mut matching: Vec<(usize, &ReportRecord)> = reports
+        .iter()
+        .enumerate()
+        .filter(|(_, r)| {
+            filter.is_none_or(|status| r.status == status)
+                && query.matches_text([r.file.as_str(), r.reason.as_str(), r.details.as_str()])
+                && (query.owner().is_none()
+                    || state
+                        .owners
+                        .get(&r.file)
+                        .is_some_and(|meta| query.matches_owner(&meta.owner_hash)))
+        })
+        .collect();
+    let sort = query.sort_key(&REPORT_SORTS.map(|(key, _)| key));
+    let order = query.order(if sort == "file" {
+        SortOrder::Asc
+    } else {
+        SortOrder::Desc
+    });
+    matching.sort_by(|(a_idx, a), (b_idx, b)| {
+        let ord = match sort {
+            "file" => a.file.cmp(&b.file),
+            _ => a.time.cmp(&b.time),
+        }
+        .then_with(|| a_idx.cmp(b_idx));
+        match order {
+            SortOrder::Asc => ord,
+            SortOrder::Desc => ord.reverse(),
+        }
+    });
+    let (matching, pager) = query.paginate(matching);
+    rows.push_str(&controls_row(
+        "/admin/reports",
+        &query,
+        &pager,
+        &REPORT_SORTS,
+        order,
+        7,
+    ));
+    // `idx` stays the position in the full list, which is what delete takes.
+    for (idx, r) in matching {
+        let resolution = r
+            .resolution
+            .as_deref()
+            .map(|note| {
+                format!(
+                    "<br><span class=small>{}</span>",
+                    htmlescape::encode_minimal(note)
+                )
+            })
+            .unwrap_or_default();
+        rows.push_str(&format!("<tr><td><a href=\"/{file}\" target=_blank rel=noopener>{file}</a></td><td>{reason}</td><td>{details}</td><td>{reporter}</td><td>{time}</td><td>{status}{resolution}</td><td><form method=post action=/admin/reports/status style=margin:0><input type=hidden name=id value=\"{id}\"><select name=status aria-label=\"Report status\">{options}</select> <input name=note maxlength={note_max} placeholder=\"Resolution note\" aria-label=\"Resolution note\"> <button type=submit>Update</button></form><form method=post action=/admin/reports style=margin:0><input type=hidden name=idx value=\"{idx}\"><button type=submit class=del data-idx=\"{idx}\">Remove</button></form></td></tr>",
+            file=htmlescape::encode_minimal(&r.file),
+            reason=htmlescape::encode_minimal(&r.reason),
+            details=htmlescape::encode_minimal(&r.details),
+            reporter=htmlescape::encode_minimal(&short_hash(&r.reporter_hash)),
+            time=r.time,
+            status=r.status.as_str(),
+            resolution=resolution,
+            id=htmlescape::encode_minimal(&r.id),
+            options=report_status_options(r.status),
+            note_max=REPORT_NOTE_MAX_BYTES,
+            idx=idx));
+    }
+    render_admin_page(&state, AdminPage::Reports, &rows).await
+}
+
+#[axum::debug_handler]
+pub async fn admin_report_delete_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(frm): Form<AdminReportDeleteForm>,
+) -> Response {
+    trace!(index = frm.idx, "admin report delete requested");
+    if let Some(tok) = get_cookie(&headers, "adm") {
+        if !state.is_admin(&tok).await {
+            warn!(
+                idx = frm.idx,
+                "admin report delete rejected: invalid session"
+            );
+            return json_error(StatusCode::UNAUTHORIZED, "not_admin", "auth required");
+        }
+    } else {
+        warn!(
+            idx = frm.idx,
+            "admin report delete rejected: missing session"
+        );
+        return json_error(StatusCode::UNAUTHORIZED, "not_admin", "auth required");
+    }
+    let idx = frm.idx;
+    let removed = {
+        let mut reports = state.reports.write().await;
+        (idx < reports.len()).then(|| reports.remove(idx))
+    };
+    if let Some(report) = removed {
+        info!(idx, "admin removed report");
+        let actor = audit_actor(&state, &headers).await;
+        let detail = format!("{}: {}", report.file, report.reason);
+        state
+            .record_audit(
+                AuditAction::ReportDelete,
+                &actor,
+                Some(&report.id),
+                Some(&detail),
+            )
+            .await;
     }
     state.persist_reports().await;
     (
@@ -478,6 +1759,478 @@ pub async fn admin_report_delete_handler(
         .into_response()
 }
 
+/// Move a report to another status, e.g. `reviewing` or `resolved`, with an
+/// optional resolution note.
+#[axum::debug_handler]
+pub async fn admin_report_status_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(frm): Form<AdminReportStatusForm>,
+) -> Response {
+    trace!(id = %frm.id, status = %frm.status, "admin report status change requested");
+    if let Some(denied) = require_admin(&state, &headers, "report status").await {
+        return denied;
+    }
+    let Some(status) = ReportStatus::parse(&frm.status) else {
+        warn!(id = %frm.id, status = %frm.status, "admin report status rejected: unknown status");
+        return json_error(StatusCode::BAD_REQUEST, "bad_status", "unknown status");
+    };
+    let note = frm
+        .note
+        .as_deref()
+        .map(str::trim)
+        .filter(|note| !note.is_empty())
+        .map(str::to_string);
+    if note
+        .as_ref()
+        .is_some_and(|note| note.len() > REPORT_NOTE_MAX_BYTES)
+    {
+        return json_error(
+            StatusCode::BAD_REQUEST,
+            "note_too_long",
+            "resolution note is too long",
+        );
+    }
+    let report = match state.transition_report(frm.id.trim(), status, note).await {
+        Ok(report) => report,
+        Err(ReportTransitionError::NotFound) => {
+            return json_error(StatusCode::NOT_FOUND, "not_found", "report not found");
+        }
+        Err(ReportTransitionError::NotAllowed(current)) => {
+            warn!(
+                id = %frm.id,
+                from = current.as_str(),
+                to = status.as_str(),
+                "admin report status rejected: transition not allowed"
+            );
+            return json_error(
+                StatusCode::CONFLICT,
+                "bad_transition",
+                "report cannot move to that status",
+            );
+        }
+    };
+    info!(
+        target: AUDIT_LOG_TARGET,
+        action = "report_status",
+        id = %report.id,
+        file = %report.file,
+        status = report.status.as_str(),
+        "report status changed"
+    );
+    let actor = audit_actor(&state, &headers).await;
+    let detail = match &report.resolution {
+        Some(note) => format!("{}: {note}", report.status.as_str()),
+        None => report.status.as_str().to_string(),
+    };
+    state
+        .record_audit(
+            AuditAction::ReportStatus,
+            &actor,
+            Some(&report.id),
+            Some(&detail),
+        )
+        .await;
+    (
+        StatusCode::SEE_OTHER,
+        [(LOCATION, HeaderValue::from_static("/admin/reports"))],
+    )
+        .into_response()
+}
+
+pub async fn admin_quarantine_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response {
+    trace!("rendering admin quarantine view");
+    if let Some(tok) = get_cookie(&headers, "adm") {
+        if !state.is_admin(&tok).await {
+            warn!("admin quarantine access denied: invalid session");
+            return json_error(StatusCode::UNAUTHORIZED, "not_admin", "auth required");
+        }
+    } else {
+        warn!("admin quarantine access denied: missing session");
+        return json_error(StatusCode::UNAUTHORIZED, "not_admin", "auth required");
+    }
+    let mut rows = String::new();
+    for r in state.quarantine.records().await {
+        let file_attr = htmlescape::encode_minimal(&r.file);
+        rows.push_str(&format!("<tr><td>{file}</td><td>{original}</td><td>{owner}</td><td>{size}</td><td>{source}</td><td>{verdict}</td><td>{details}</td><td>{time}</td><td><form method=post action=/admin/quarantine style=margin:0><input type=hidden name=file value=\"{file_attr}\"><label class=small><input type=checkbox name=confirm value=1> confirm</label> <button type=submit name=action value=release>Release</button></form><form method=post action=/admin/quarantine style=margin:0><input type=hidden name=file value=\"{file_attr}\"><button type=submit name=action value=delete class=del>Delete</button> <button type=submit name=action value=ban class=del>Delete &amp; ban owner</button></form></td></tr>",
+            file = file_attr,
+            original = original_name_cell(&r.original),
+            owner = htmlescape::encode_minimal(&short_hash(&r.owner_hash)),
+            size = r.size,
+            source = htmlescape::encode_minimal(&r.source),
+            verdict = htmlescape::encode_minimal(&r.verdict),
+            details = htmlescape::encode_minimal(&r.details),
+            time = r.quarantined_at,
+            file_attr = file_attr,
+        ));
+    }
+    render_admin_page(&state, AdminPage::Quarantine, &rows).await
+}
+
+#[axum::debug_handler]
+pub async fn admin_quarantine_action_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(frm): Form<AdminQuarantineForm>,
+) -> Response {
+    trace!(file = %frm.file, action = %frm.action, "admin quarantine action requested");
+    if let Some(tok) = get_cookie(&headers, "adm") {
+        if !state.is_admin(&tok).await {
+            warn!(file = %frm.file, "admin quarantine action rejected: invalid session");
+            return json_error(StatusCode::UNAUTHORIZED, "not_admin", "auth required");
+        }
+    } else {
+        warn!(file = %frm.file, "admin quarantine action rejected: missing session");
+        return json_error(StatusCode::UNAUTHORIZED, "not_admin", "auth required");
+    }
+    let file = frm.file.trim();
+    let Some(record) = state.quarantine.get(file).await else {
+        warn!(file, "admin quarantine action rejected: unknown file");
+        return json_error(StatusCode::NOT_FOUND, "not_found", "file not quarantined");
+    };
+    match frm.action.as_str() {
+        "release" => {
+            // Releasing puts the file straight back on /f/, so it needs an
+            // explicit second confirmation rather than a single click.
+            if frm
+                .confirm
+                .as_deref()
+                .map(str::trim)
+                .unwrap_or_default()
+                .is_empty()
+            {
+                warn!(file, "admin quarantine release rejected: not confirmed");
+                return json_error(
+                    StatusCode::BAD_REQUEST,
+                    "confirm_required",
+                    "tick confirm to release a quarantined file",
+                );
+            }
+            if let Err(err) = state.release_quarantined(file).await {
+                error!(?err, file, "failed to release quarantined file");
+                return json_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "release_failed",
+                    "failed to release file",
+                );
+            }
+            info!(file, "admin released quarantined file");
+            let actor = audit_actor(&state, &headers).await;
+            state
+                .record_audit(AuditAction::QuarantineRelease, &actor, Some(file), None)
+                .await;
+        }
+        "delete" => {
+            state.delete_quarantined(file).await;
+            info!(file, "admin deleted quarantined file");
+            let actor = audit_actor(&state, &headers).await;
+            state
+                .record_audit(
+                    AuditAction::FileDelete,
+                    &actor,
+                    Some(file),
+                    Some("quarantined"),
+                )
+                .await;
+        }
+        "ban" => {
+            state
+                .add_ban(IpBan {
+                    subject: BanSubject::Exact {
+                        hash: record.owner_hash.clone(),
+                    },
+                    label: Some(format!("quarantine: {}", record.file)),
+                    reason: record.verdict.clone(),
+                    time: 0,
+                    expires: None,
+                })
+                .await;
+            state.persist_bans().await;
+            state.delete_quarantined(file).await;
+            info!(file, "admin banned owner of quarantined file");
+            let actor = audit_actor(&state, &headers).await;
+            state
+                .record_audit(
+                    AuditAction::Ban,
+                    &actor,
+                    Some(&record.owner_hash),
+                    Some(&record.verdict),
+                )
+                .await;
+            state
+                .record_audit(
+                    AuditAction::FileDelete,
+                    &actor,
+                    Some(file),
+                    Some("quarantined"),
+                )
+                .await;
+        }
+        other => {
+            warn!(
+                file,
+                action = other,
+                "admin quarantine action rejected: unknown action"
+            );
+            return json_error(StatusCode::BAD_REQUEST, "bad_action", "unknown action");
+        }
+    }
+    (
+        StatusCode::SEE_OTHER,
+        [(LOCATION, HeaderValue::from_static("/admin/quarantine"))],
+    )
+        .into_response()
+}
+
+/// Admin actions and sign-in attempts, newest first. `owner` matches the
+/// start of an entry's target, e.g. a banned owner's ID.
+pub async fn admin_audit_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<AdminListQuery>,
+) -> Response {
+    trace!("rendering admin audit log");
+    if let Some(denied) = require_admin(&state, &headers, "audit log").await {
+        return denied;
+    }
+    let mut entries: Vec<_> = state
+        .audit_log
+        .entries()
+        .await
+        .into_iter()
+        .enumerate()
+        .filter(|(_, e)| {
+            query.matches_owner(e.target.as_deref().unwrap_or_default())
+                && query.matches_text([
+                    e.action.as_str(),
+                    e.actor.as_str(),
+                    e.target.as_deref().unwrap_or_default(),
+                    e.detail.as_deref().unwrap_or_default(),
+                ])
+        })
+        .collect();
+    let sort = query.sort_key(&AUDIT_SORTS.map(|(key, _)| key));
+    let order = query.order(SortOrder::Desc);
+    // Entries are appended in order, so the position breaks ties between
+    // actions taken in the same second.
+    entries.sort_by(|(a_pos, a), (b_pos, b)| {
+        let ord = match sort {
+            "action" => a.action.as_str().cmp(b.action.as_str()),
+            _ => a.time.cmp(&b.time),
+        }
+        .then_with(|| a_pos.cmp(b_pos));
+        match order {
+            SortOrder::Asc => ord,
+            SortOrder::Desc => ord.reverse(),
+        }
+    });
+    let (entries, pager) = query.paginate(entries);
+    let mut rows = controls_row("/admin/audit", &query, &pager, &AUDIT_SORTS, order, 5);
+    for (_, e) in &entries {
+        rows.push_str(&format!(
+            "<tr><td>{time}</td><td data-action={action}>{action}</td><td>{actor}</td><td>{target}</td><td>{detail}</td></tr>",
+            time = e.time,
+            action = e.action.as_str(),
+            actor = htmlescape::encode_minimal(&e.actor),
+            target = htmlescape::encode_minimal(e.target.as_deref().unwrap_or_default()),
+            detail = htmlescape::encode_minimal(e.detail.as_deref().unwrap_or_default()),
+        ));
+    }
+    render_admin_page(&state, AdminPage::Audit, &rows).await
+}
+
+/// Longest look-back a bulk ban accepts, in hours.
+const REPORT_BAN_MAX_HOURS: u64 = 30 * 24;
+
+/// An owner a bulk ban would hit.
+struct ReportBanCandidate {
+    owner_hash: String,
+    files: usize,
+    reports: usize,
+    banned: bool,
+}
+
+/// Owners of files reported for `reason` since `since`, most reported first.
+/// A report's file is traced to its owner while it is hosted, quarantined or
+/// still has a removal tombstone; reports for files gone past that are
+/// skipped.
+async fn report_ban_candidates(
+    state: &AppState,
+    reason: &str,
+    since: u64,
+) -> Vec<ReportBanCandidate> {
+    let now = state.now_secs();
+    let reported: Vec<String> = state
+        .reports
+        .read()
+        .await
+        .iter()
+        .filter(|r| {
+            r.time >= since
+                && r.status != ReportStatus::Rejected
+                && r.reason.eq_ignore_ascii_case(reason)
+        })
+        .map(|r| r.file.clone())
+        .collect();
+    let mut by_owner: HashMap<String, (HashSet<String>, usize)> = HashMap::new();
+    for file in reported {
+        let owner = match state.owners.get(&file) {
+            Some(meta) => Some(meta.owner_hash.clone()),
+            None => match state.tombstones.removal(&file, now) {
+                Some(removal) => Some(removal.owner_hash),
+                None => state.quarantine.get(&file).await.map(|r| r.owner_hash),
+            },
+        };
+        let Some(owner) = owner.filter(|o| !o.is_empty()) else {
+            continue;
+        };
+        let entry = by_owner.entry(owner).or_default();
+        entry.0.insert(file);
+        entry.1 += 1;
+    }
+    let bans = state.bans.read().await;
+    let mut candidates: Vec<ReportBanCandidate> = by_owner
+        .into_iter()
+        .map(|(owner_hash, (files, reports))| ReportBanCandidate {
+            banned: bans.iter().any(|b| b.subject.key() == owner_hash),
+            owner_hash,
+            files: files.len(),
+            reports,
+        })
+        .collect();
+    candidates.sort_by(|a, b| {
+        b.reports
+            .cmp(&a.reports)
+            .then_with(|| a.owner_hash.cmp(&b.owner_hash))
+    });
+    candidates
+}
+
+/// Ban every owner of files reported for one reason within a window. The
+/// first post renders a preview of who would be banned; the preview's
+/// confirm button posts the accepted owners back, and only those that still
+/// match are banned.
+#[axum::debug_handler]
+pub async fn admin_report_ban_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(frm): Form<AdminReportBanForm>,
+) -> Response {
+    trace!(reason = %frm.reason, hours = frm.hours, "admin report bulk ban requested");
+    if let Some(tok) = get_cookie(&headers, "adm") {
+        if !state.is_admin(&tok).await {
+            warn!("admin report bulk ban rejected: invalid session");
+            return json_error(StatusCode::UNAUTHORIZED, "not_admin", "auth required");
+        }
+    } else {
+        warn!("admin report bulk ban rejected: missing session");
+        return json_error(StatusCode::UNAUTHORIZED, "not_admin", "auth required");
+    }
+    let reason = frm.reason.trim();
+    if reason.is_empty() {
+        return json_error(StatusCode::BAD_REQUEST, "missing", "missing report reason");
+    }
+    if frm.hours == 0 || frm.hours > REPORT_BAN_MAX_HOURS {
+        return json_error(
+            StatusCode::BAD_REQUEST,
+            "bad_window",
+            "hours must be between 1 and 720",
+        );
+    }
+    let since = state.now_secs().saturating_sub(frm.hours * 3600);
+    let candidates = report_ban_candidates(&state, reason, since).await;
+
+    let Some(accepted) = frm.owners.as_deref() else {
+        let reason_attr = htmlescape::encode_minimal(reason);
+        let mut rows = String::new();
+        for c in &candidates {
+            rows.push_str(&format!(
+                "<tr><td title=\"{owner}\">{short}</td><td>{files}</td><td>{reports}</td><td>{status}</td></tr>",
+                owner = htmlescape::encode_minimal(&c.owner_hash),
+                short = htmlescape::encode_minimal(&short_hash(&c.owner_hash)),
+                files = c.files,
+                reports = c.reports,
+                status = if c.banned { "already banned" } else { "will be banned" },
+            ));
+        }
+        let to_ban: Vec<&str> = candidates
+            .iter()
+            .filter(|c| !c.banned)
+            .map(|c| c.owner_hash.as_str())
+            .collect();
+        if to_ban.is_empty() {
+            rows.push_str("<tr><td colspan=4>No owners to ban.</td></tr>");
+        } else {
+            rows.push_str(&format!(
+                "<tr><td colspan=4><form method=post action=/admin/reports/ban style=margin:0><input type=hidden name=reason value=\"{reason_attr}\"><input type=hidden name=hours value={hours}><input type=hidden name=owners value=\"{owners}\"><button type=submit class=del>Ban {count} owner{plural}</button> <a href=/admin/reports>Cancel</a></form></td></tr>",
+                hours = frm.hours,
+                owners = to_ban.join(","),
+                count = to_ban.len(),
+                plural = if to_ban.len() == 1 { "" } else { "s" },
+            ));
+        }
+        debug!(
+            reason,
+            hours = frm.hours,
+            owners = to_ban.len(),
+            "admin report bulk ban previewed"
+        );
+        return render_admin_page(&state, AdminPage::ReportBan, &rows).await;
+    };
+
+    let accepted: HashSet<&str> = accepted.split(',').map(str::trim).collect();
+    let actor = audit_actor(&state, &headers).await;
+    let detail = format!("reported for {reason}");
+    let mut banned = 0usize;
+    for c in candidates
+        .iter()
+        .filter(|c| !c.banned && accepted.contains(c.owner_hash.as_str()))
+    {
+        state
+            .add_ban(IpBan {
+                subject: BanSubject::Exact {
+                    hash: c.owner_hash.clone(),
+                },
+                label: Some(format!("reports: {reason}")),
+                reason: format!("reported for {reason}"),
+                time: 0,
+                expires: None,
+            })
+            .await;
+        info!(
+            target: AUDIT_LOG_TARGET,
+            action = "ban",
+            owner_hash = %c.owner_hash,
+            files = c.files,
+            reports = c.reports,
+            report_reason = reason,
+            hours = frm.hours,
+            "owner banned from reports"
+        );
+        state
+            .record_audit(AuditAction::Ban, &actor, Some(&c.owner_hash), Some(&detail))
+            .await;
+        banned += 1;
+    }
+    if banned > 0 {
+        state.persist_bans().await;
+    }
+    info!(
+        reason,
+        hours = frm.hours,
+        banned,
+        "admin report bulk ban applied"
+    );
+    (
+        StatusCode::SEE_OTHER,
+        [(LOCATION, HeaderValue::from_static("/admin/ban"))],
+    )
+        .into_response()
+}
+
 fn subtle_equals(a: &[u8], b: &[u8]) -> bool {
     if a.len() != b.len() {
         return false;
@@ -516,6 +2269,8 @@ fn describe_subject(subject: &BanSubject) -> String {
             version_label(*version),
             short_hash(hash)
         ),
+        BanSubject::Country { code } => format!("Country {code}"),
+        BanSubject::Asn { number } => format!("AS{number}"),
     }
 }
 
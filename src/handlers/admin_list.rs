@@ -0,0 +1,217 @@
+//! Paging, sorting and search shared by the admin list views. Everything is
+//! carried in the query string, so a filtered page can be bookmarked and the
+//! controls work without JavaScript.
+
+use serde::Deserialize;
+
+/// Rows per page unless `per_page` asks for another size.
+pub const ADMIN_PAGE_SIZE: usize = 50;
+/// Largest `per_page` accepted.
+pub const ADMIN_MAX_PAGE_SIZE: usize = 500;
+
+#[derive(Deserialize, Default, Clone, Debug)]
+pub struct AdminListQuery {
+    /// 1-based page number.
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+    /// Column to sort by; each view lists the ones it knows.
+    pub sort: Option<String>,
+    /// `asc` or `desc`; each sort column has its own default.
+    pub order: Option<String>,
+    /// Case-insensitive substring of a file name (or ban label and reason).
+    pub q: Option<String>,
+    /// Owner hash, or a prefix of one as shown in the tables.
+    pub owner: Option<String>,
+    /// Report status, on the reports view.
+    pub status: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SortOrder::Asc => "asc",
+            SortOrder::Desc => "desc",
+        }
+    }
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+impl AdminListQuery {
+    /// The search text, lowercased.
+    pub fn search(&self) -> Option<String> {
+        non_empty(&self.q).map(str::to_lowercase)
+    }
+
+    /// Whether any filter narrows the view.
+    pub fn is_filtered(&self) -> bool {
+        [&self.q, &self.owner, &self.status]
+            .into_iter()
+            .any(|value| non_empty(value).is_some())
+    }
+
+    pub fn owner(&self) -> Option<&str> {
+        non_empty(&self.owner)
+    }
+
+    /// Whether `owner_hash` matches the owner filter; always true without one.
+    pub fn matches_owner(&self, owner_hash: &str) -> bool {
+        self.owner()
+            .is_none_or(|owner| owner_hash.starts_with(owner))
+    }
+
+    /// Whether any of `fields` contains the search text; always true without
+    /// one.
+    pub fn matches_text<'a>(&self, fields: impl IntoIterator<Item = &'a str>) -> bool {
+        match self.search() {
+            None => true,
+            Some(needle) => fields
+                .into_iter()
+                .any(|field| field.to_lowercase().contains(&needle)),
+        }
+    }
+
+    /// The sort column, if it is one of `allowed`; otherwise the first.
+    pub fn sort_key<'a>(&self, allowed: &[&'a str]) -> &'a str {
+        non_empty(&self.sort)
+            .and_then(|sort| allowed.iter().find(|key| key.eq_ignore_ascii_case(sort)))
+            .copied()
+            .unwrap_or(allowed[0])
+    }
+
+    pub fn order(&self, default: SortOrder) -> SortOrder {
+        match non_empty(&self.order) {
+            Some(o) if o.eq_ignore_ascii_case("asc") => SortOrder::Asc,
+            Some(o) if o.eq_ignore_ascii_case("desc") => SortOrder::Desc,
+            _ => default,
+        }
+    }
+
+    /// Cut `items`, already filtered and sorted, down to the requested page.
+    pub fn paginate<T>(&self, items: Vec<T>) -> (Vec<T>, Pager) {
+        let per_page = self
+            .per_page
+            .unwrap_or(ADMIN_PAGE_SIZE)
+            .clamp(1, ADMIN_MAX_PAGE_SIZE);
+        let total = items.len();
+        let pages = total.div_ceil(per_page).max(1);
+        let page = self.page.unwrap_or(1).clamp(1, pages);
+        let rows = items
+            .into_iter()
+            .skip((page - 1) * per_page)
+            .take(per_page)
+            .collect();
+        (
+            rows,
+            Pager {
+                page,
+                per_page,
+                total,
+                pages,
+            },
+        )
+    }
+
+    /// Query string for this view with `page` swapped in.
+    fn with_page(&self, page: usize, per_page: usize) -> String {
+        let mut params = vec![format!("page={page}")];
+        if per_page != ADMIN_PAGE_SIZE {
+            params.push(format!("per_page={per_page}"));
+        }
+        for (key, value) in [
+            ("sort", &self.sort),
+            ("order", &self.order),
+            ("q", &self.q),
+            ("owner", &self.owner),
+            ("status", &self.status),
+        ] {
+            if let Some(value) = non_empty(value) {
+                params.push(format!("{key}={}", urlencoding::encode(value)));
+            }
+        }
+        params.join("&")
+    }
+}
+
+/// Where a paginated view is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pager {
+    pub page: usize,
+    pub per_page: usize,
+    /// Rows matching the filters, across all pages.
+    pub total: usize,
+    pub pages: usize,
+}
+
+/// One `<select>` option per `(value, label)`, with `current` selected.
+fn options(choices: &[(&str, &str)], current: &str) -> String {
+    choices
+        .iter()
+        .map(|(value, label)| {
+            format!(
+                "<option value={value}{selected}>{label}</option>",
+                selected = if *value == current { " selected" } else { "" },
+            )
+        })
+        .collect()
+}
+
+/// Table row holding the search form and page links for a view at `path`.
+/// `sorts` pairs each sort key with its label; the first is the default.
+/// Empty for a view with nothing in it, which keeps the "no entries" note.
+pub fn controls_row(
+    path: &str,
+    query: &AdminListQuery,
+    pager: &Pager,
+    sorts: &[(&str, &str)],
+    order: SortOrder,
+    colspan: usize,
+) -> String {
+    if pager.total == 0 && !query.is_filtered() {
+        return String::new();
+    }
+    let keys: Vec<&str> = sorts.iter().map(|(key, _)| *key).collect();
+    let attr = |value: &Option<String>| htmlescape::encode_minimal(non_empty(value).unwrap_or(""));
+    let status = non_empty(&query.status)
+        .map(|status| {
+            format!(
+                "<input type=hidden name=status value=\"{}\">",
+                htmlescape::encode_minimal(status)
+            )
+        })
+        .unwrap_or_default();
+    let mut nav = format!(
+        "Page {} of {} · {} matching",
+        pager.page, pager.pages, pager.total
+    );
+    if pager.page > 1 {
+        nav.push_str(&format!(
+            " · <a href=\"{path}?{}\" rel=prev>Previous</a>",
+            htmlescape::encode_minimal(&query.with_page(pager.page - 1, pager.per_page))
+        ));
+    }
+    if pager.page < pager.pages {
+        nav.push_str(&format!(
+            " · <a href=\"{path}?{}\" rel=next>Next</a>",
+            htmlescape::encode_minimal(&query.with_page(pager.page + 1, pager.per_page))
+        ));
+    }
+    format!(
+        "<tr><td colspan={colspan}><form method=get action={path} style=margin:0>{status}<input name=q value=\"{q}\" placeholder=\"Search\" aria-label=\"Search\"> <input name=owner value=\"{owner}\" placeholder=\"Owner ID\" aria-label=\"Owner ID\" size=12> <select name=sort aria-label=\"Sort by\">{sorts}</select> <select name=order aria-label=\"Order\">{orders}</select> <button type=submit>Filter</button></form><span class=small>{nav}</span></td></tr>",
+        q = attr(&query.q),
+        owner = attr(&query.owner),
+        sorts = options(sorts, query.sort_key(&keys)),
+        orders = options(
+            &[("desc", "Descending"), ("asc", "Ascending")],
+            order.as_str()
+        ),
+    )
+}
@@ -1,18 +1,26 @@
+use axum::Json;
 use axum::extract::{ConnectInfo, Form, Path, Query, State};
-use axum::http::{HeaderMap, StatusCode};
+use axum::http::header::CACHE_CONTROL;
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::env;
 use std::net::SocketAddr as ClientAddr;
-use tokio::fs;
 use tracing::{debug, info, trace, warn};
 
+use crate::handlers::upload::request_owner;
 use crate::state::{AppState, cleanup_expired};
 use crate::util::{PROD_HOST, json_error, real_client_ip};
+use crate::webhooks::{DELETED_BY_OWNER, WebhookEvent};
 
 #[derive(Deserialize)]
 pub struct SimpleDeleteForm {
+    #[serde(default)]
     pub f: String,
+    /// Set by the "delete all" button on `/simple`.
+    #[serde(default)]
+    pub all: Option<String>,
 }
 
 #[axum::debug_handler]
@@ -34,8 +42,9 @@ pub async fn delete_handler(
         warn!(%ip, file, "delete rejected: banned ip");
         return json_error(StatusCode::FORBIDDEN, "banned", "ip banned");
     }
-    let Some((_, owner_hash)) = state.hash_ip(&ip) else {
-        return (StatusCode::NOT_FOUND, "not found").into_response();
+    let owner_hash = match request_owner(&state, &headers, &ip).await {
+        Ok(owner_hash) => owner_hash,
+        Err(resp) => return resp,
     };
     if file.contains('/') || file.contains("..") || file.contains('\\') {
         warn!(%ip, file, "delete rejected: invalid file name");
@@ -46,10 +55,14 @@ pub async fn delete_handler(
         Some(meta) if meta.value().owner_hash == owner_hash => {}
         _ => return (StatusCode::NOT_FOUND, "not found").into_response(),
     }
-    state.owners.remove(&file);
-    let path = state.upload_dir.join(&file);
-    let _ = fs::remove_file(&path).await;
+    state.remove_owner(&file);
+    if let Err(err) = state.file_store.delete(&file).await {
+        warn!(?err, file, "failed to remove deleted file from storage");
+    }
     state.persist_owners().await;
+    state
+        .webhooks
+        .emit(WebhookEvent::file_deleted(&file, DELETED_BY_OWNER));
     info!(%ip, file, owner_hash = %owner_hash, "file delete completed");
     // attempt to purge Cloudflare cache for this file in the background
     let file_clone = file.clone();
@@ -81,9 +94,32 @@ pub async fn simple_delete_post_handler(
     Form(frm): Form<SimpleDeleteForm>,
 ) -> Response {
     trace!(file = %frm.f, "simple delete POST request received");
+    if frm.all.is_some() {
+        return handle_simple_delete_all(state, addr, headers).await;
+    }
     handle_simple_delete(state, addr, headers, frm.f).await
 }
 
+async fn handle_simple_delete_all(
+    state: AppState,
+    addr: ClientAddr,
+    headers: HeaderMap,
+) -> Response {
+    let ip = real_client_ip(&headers, &addr);
+    let message = match request_owner(&state, &headers, &ip).await.ok() {
+        Some(_) if state.is_banned(&ip).await => "Access denied.".to_string(),
+        Some(owner_hash) => {
+            let names = owned_file_names(&state, &owner_hash);
+            let (_, removed) = delete_owned_files(&state, &owner_hash, names).await;
+            info!(%ip, owner_hash = %owner_hash, deleted = removed.len(), "simple delete all completed");
+            format!("Deleted {} file(s).", removed.len())
+        }
+        None => "File not found or not owned by you.".to_string(),
+    };
+    let url = format!("/simple?m={}", urlencoding::encode(&message));
+    (StatusCode::SEE_OTHER, [(axum::http::header::LOCATION, url)]).into_response()
+}
+
 async fn handle_simple_delete(
     state: AppState,
     addr: ClientAddr,
@@ -92,7 +128,7 @@ async fn handle_simple_delete(
 ) -> Response {
     let ip = real_client_ip(&headers, &addr);
     trace!(%ip, file = %f, "simple delete handling");
-    let Some((_, owner_hash)) = state.hash_ip(&ip) else {
+    let Ok(owner_hash) = request_owner(&state, &headers, &ip).await else {
         let url = format!(
             "/simple?m={}",
             urlencoding::encode("File not found or not owned by you.")
@@ -105,16 +141,24 @@ async fn handle_simple_delete(
         let url = format!("/simple?m={}", urlencoding::encode("Invalid file name."));
         return (StatusCode::SEE_OTHER, [(axum::http::header::LOCATION, url)]).into_response();
     }
-    let can_delete = match state.owners.get(fname) {
-        Some(meta) if meta.value().owner_hash == owner_hash => true,
-        _ => false,
-    };
+    let can_delete = matches!(
+        state.owners.get(fname),
+        Some(meta) if meta.value().owner_hash == owner_hash
+    );
     if can_delete {
         debug!(file = fname, owner_hash = %owner_hash, "simple delete: removing owned file");
-        state.owners.remove(fname);
-        let path = state.upload_dir.join(fname);
-        let _ = fs::remove_file(&path).await;
+        state.remove_owner(fname);
+        if let Err(err) = state.file_store.delete(fname).await {
+            warn!(
+                ?err,
+                file = fname,
+                "failed to remove deleted file from storage"
+            );
+        }
         state.persist_owners().await;
+        state
+            .webhooks
+            .emit(WebhookEvent::file_deleted(fname, DELETED_BY_OWNER));
         info!(%ip, file = fname, owner_hash = %owner_hash, "simple delete completed");
         // background purge for Cloudflare
         let fname_clone = fname.to_string();
@@ -140,6 +184,174 @@ async fn handle_simple_delete(
     }
 }
 
+/// Most names accepted by one bulk delete request.
+pub const MAX_BULK_DELETE: usize = 1000;
+
+#[derive(Deserialize, Default)]
+pub struct BulkDeleteRequest {
+    /// Storage names, or the share URLs returned by `/list`.
+    #[serde(default)]
+    pub files: Vec<String>,
+    /// Delete every file held by the caller; `files` is ignored.
+    #[serde(default)]
+    pub all: bool,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkDeleteStatus {
+    Deleted,
+    NotFound,
+    Invalid,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct BulkDeleteResult {
+    pub file: String,
+    pub status: BulkDeleteStatus,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct BulkDeleteResponse {
+    pub deleted: usize,
+    pub results: Vec<BulkDeleteResult>,
+}
+
+/// Accept either a bare storage name or a share URL ending in one.
+fn bulk_delete_name(raw: &str) -> Option<&str> {
+    let raw = raw.trim();
+    let name = if raw.contains("://") {
+        raw.rsplit('/').next()?
+    } else {
+        raw
+    };
+    let invalid =
+        name.is_empty() || name.contains('/') || name.contains("..") || name.contains('\\');
+    (!invalid).then_some(name)
+}
+
+/// Remove every name in `requested` owned by `owner_hash` with a single
+/// owners persist, then drop the bodies and purge them from the CDN.
+async fn delete_owned_files(
+    state: &AppState,
+    owner_hash: &str,
+    requested: Vec<String>,
+) -> (Vec<BulkDeleteResult>, Vec<String>) {
+    let mut seen = HashSet::new();
+    let mut results = Vec::with_capacity(requested.len());
+    let mut removed = Vec::new();
+    for raw in requested {
+        let Some(name) = bulk_delete_name(&raw).map(str::to_string) else {
+            results.push(BulkDeleteResult {
+                file: raw,
+                status: BulkDeleteStatus::Invalid,
+            });
+            continue;
+        };
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+        let owned = state
+            .owners
+            .remove_if(&name, |_, meta| meta.owner_hash == owner_hash)
+            .is_some();
+        let status = if owned {
+            removed.push(name.clone());
+            BulkDeleteStatus::Deleted
+        } else {
+            BulkDeleteStatus::NotFound
+        };
+        results.push(BulkDeleteResult { file: name, status });
+    }
+    if removed.is_empty() {
+        return (results, removed);
+    }
+    state.persist_owners().await;
+    for file in &removed {
+        if let Err(err) = state.file_store.delete(file).await {
+            warn!(?err, file, "failed to remove deleted file from storage");
+        }
+        state
+            .webhooks
+            .emit(WebhookEvent::file_deleted(file, DELETED_BY_OWNER));
+    }
+    let purge = removed.clone();
+    tokio::spawn(async move {
+        for file in purge {
+            if let Err(e) = purge_cloudflare_file(&file).await {
+                warn!(file = %file, error = %e, "cloudflare purge failed");
+            }
+        }
+    });
+    (results, removed)
+}
+
+fn owned_file_names(state: &AppState, owner_hash: &str) -> Vec<String> {
+    state
+        .owners_snapshot()
+        .files_for(owner_hash)
+        .iter()
+        .map(|(file, _)| file.clone())
+        .collect()
+}
+
+#[axum::debug_handler]
+#[tracing::instrument(
+    name = "files.bulk_delete",
+    skip(state, headers, req),
+    fields(client_ip = tracing::field::Empty)
+)]
+pub async fn bulk_delete_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<ClientAddr>,
+    headers: HeaderMap,
+    Json(req): Json<BulkDeleteRequest>,
+) -> Response {
+    let ip = real_client_ip(&headers, &addr);
+    tracing::Span::current().record("client_ip", tracing::field::display(&ip));
+    if state.is_banned(&ip).await {
+        warn!(%ip, "bulk delete rejected: banned ip");
+        return json_error(StatusCode::FORBIDDEN, "banned", "ip banned");
+    }
+    let owner_hash = match request_owner(&state, &headers, &ip).await {
+        Ok(owner_hash) => owner_hash,
+        Err(resp) => return resp,
+    };
+    if !req.all && req.files.is_empty() {
+        return json_error(
+            StatusCode::BAD_REQUEST,
+            "no_files",
+            "pass a list of files or all=true",
+        );
+    }
+    if req.files.len() > MAX_BULK_DELETE {
+        return json_error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "too_many_files",
+            "too many files in one request",
+        );
+    }
+    cleanup_expired(&state).await;
+
+    let requested = if req.all {
+        owned_file_names(&state, &owner_hash)
+    } else {
+        req.files
+    };
+    let (results, removed) = delete_owned_files(&state, &owner_hash, requested).await;
+    info!(%ip, owner_hash = %owner_hash, deleted = removed.len(), requested = results.len(), "bulk delete completed");
+    let mut resp = Json(BulkDeleteResponse {
+        deleted: removed.len(),
+        results,
+    })
+    .into_response();
+    resp.headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    resp
+}
+
 async fn purge_cloudflare_file(fname: &str) -> Result<(), anyhow::Error> {
     // Expect CLOUDFLARE_ZONE_ID and CLOUDFLARE_API_TOKEN to be set in env; if not, no-op.
     let zone_id = match env::var("CLOUDFLARE_ZONE_ID") {
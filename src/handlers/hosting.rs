@@ -1,43 +1,59 @@
-use axum::Json;
-use axum::extract::{Path, State};
+use axum::extract::{ConnectInfo, Path, Query, State};
 use axum::http::header::{
-    ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_TYPE, EXPIRES, VARY,
+    ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_ENCODING, CONTENT_TYPE, ETAG,
+    EXPIRES, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, VARY,
 };
-use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode};
 use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
 use mime_guess::MimeGuess;
-use serde::Serialize;
-use std::env;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::net::SocketAddr;
 use tokio::fs;
-use tracing::{debug, info, trace, warn};
+use tracing::{debug, error, info, trace, warn};
 
-use crate::state::{AppState, cleanup_expired};
-use crate::util::{format_bytes, json_error, max_file_bytes, now_secs};
+use crate::assets::embedded_public;
+use crate::build_info::BuildInfo;
+use crate::digest_fields::{self, CONTENT_DIGEST, REPR_DIGEST};
+use crate::feature_flags::STREAMING_UPLOADS;
+use crate::handlers::presign::{PresignQuery, Presigned};
+use crate::handlers::upload::request_owner;
+use crate::handlers::web::{LangQuery, render_tera_page};
+use crate::state::{AppState, DownloadClaim, cleanup_expired};
+use crate::tombstones::Removal;
+use crate::util::{
+    SHARE_LINK_PREFIX, format_bytes, inline_content_disposition, json_error, max_file_bytes,
+    real_client_ip, streaming_uploads_enabled,
+};
+use crate::webhooks::{DELETED_AT_DOWNLOAD_LIMIT, WebhookEvent};
 
 #[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
 pub struct ConfigResponse {
     pub max_file_bytes: u64,
     pub max_file_size_str: String,
     pub enable_streaming_uploads: bool,
+    pub share_link_prefix: &'static str,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub telemetry: Option<FrontendTelemetry>,
 }
 
 #[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
 pub struct FrontendTelemetry {
     pub sentry: FrontendSentryTelemetry,
 }
 
 #[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
 pub struct FrontendSentryTelemetry {
     pub enabled: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub dsn: Option<String>,
     pub release: String,
     pub environment: String,
-    #[serde(rename = "traces_sample_rate")]
     pub traces_sample_rate: f32,
-    #[serde(rename = "profiles_sample_rate")]
     pub profiles_sample_rate: f32,
     #[serde(default)]
     pub trace_propagation_targets: Vec<String>,
@@ -47,7 +63,11 @@ pub struct FrontendSentryTelemetry {
 #[tracing::instrument(name = "files.fetch", skip(state), fields(file = %file))]
 pub async fn fetch_file_handler(
     State(state): State<AppState>,
+    connect: Option<Extension<ConnectInfo<SocketAddr>>>,
     Path(file): Path<String>,
+    Query(presign): Query<PresignQuery>,
+    method: Method,
+    req_headers: HeaderMap,
 ) -> Response {
     trace!(file = %file, "fetch file request received");
     if file.contains('/') {
@@ -55,100 +75,471 @@ pub async fn fetch_file_handler(
         return (StatusCode::BAD_REQUEST, "bad file").into_response();
     }
     cleanup_expired(&state).await;
-    let now = now_secs();
-    let (exists, expired, meta_expires) = {
+    let now = state.now_secs();
+    let (exists, expired, meta_expires, display_name, content_hash, created, limited, private) = {
         if let Some(m) = state.owners.get(&file) {
             let m = m.value();
-            (true, m.expires <= now, m.expires)
+            (
+                true,
+                m.expires <= now,
+                m.expires,
+                m.display_name(),
+                m.hash.clone(),
+                m.created,
+                m.max_downloads.is_some(),
+                m.private,
+            )
         } else {
-            (false, true, 0)
+            (
+                false,
+                true,
+                0,
+                String::new(),
+                String::new(),
+                0,
+                false,
+                false,
+            )
         }
     };
     if !exists || expired {
-        debug!(file = %file, expired, "fetch request for missing or expired file");
+        let expired_at = if exists {
+            Some(meta_expires)
+        } else {
+            state.tombstones.expired_at(&file, now)
+        };
+        if let Some(expired_at) = expired_at {
+            debug!(file = %file, expired_at, "fetch request for expired file");
+            return expired_file_response(expired_at);
+        }
+        if let Some(removal) = owned_removal(&state, connect, &req_headers, &file, now).await {
+            debug!(file = %file, reason = removal.reason.as_str(), "fetch request for removed file");
+            return removed_file_response(&removal);
+        }
+        debug!(file = %file, "fetch request for missing file");
         return (StatusCode::NOT_FOUND, "not found").into_response();
     }
-    let file_path = state.upload_dir.join(&file);
-    if !file_path.exists() {
-        warn!(path = ?file_path, "fetch request missing file on disk");
-        return (StatusCode::NOT_FOUND, "not found").into_response();
+    let presigned = presign.check(&state, &file, now);
+    match presigned {
+        Presigned::Invalid => {
+            debug!(file = %file, "fetch rejected: bad or expired signature");
+            return json_error(
+                StatusCode::FORBIDDEN,
+                "invalid_signature",
+                "link signature is invalid or expired",
+            );
+        }
+        // Private files look missing without a signature.
+        Presigned::Absent if private => {
+            debug!(file = %file, "fetch rejected: private file without signature");
+            return (StatusCode::NOT_FOUND, "not found").into_response();
+        }
+        _ => {}
     }
-    match fs::read(&file_path).await {
-        Ok(bytes) => {
-            let mime = MimeGuess::from_path(&file_path).first_or_octet_stream();
-            let mut headers = HeaderMap::new();
-            headers.insert(CONTENT_TYPE, mime.as_ref().parse().unwrap());
-            if meta_expires > now {
-                let remaining = meta_expires - now;
-                // If the object expires far in the future, mark it immutable so CDNs cache aggressively.
-                // Otherwise use the remaining TTL as max-age.
-                if remaining > 60 * 60 * 24 * 7 {
-                    // more than 7 days -> long cache
-                    headers.insert(
-                        CACHE_CONTROL,
-                        HeaderValue::from_static("public, max-age=31536000, immutable"),
-                    );
-                } else {
-                    headers.insert(
-                        CACHE_CONTROL,
-                        HeaderValue::from_str(&format!("public, max-age={}", remaining)).unwrap(),
-                    );
-                }
-                let exp_time = std::time::SystemTime::UNIX_EPOCH
-                    + std::time::Duration::from_secs(meta_expires);
-                headers.insert(
-                    EXPIRES,
-                    HeaderValue::from_str(&httpdate::fmt_http_date(exp_time)).unwrap(),
+    let mime = MimeGuess::from_path(&file).first_or_octet_stream();
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, mime.as_ref().parse().unwrap());
+    if !display_name.is_empty()
+        && let Ok(value) = HeaderValue::from_str(&inline_content_disposition(&display_name))
+    {
+        headers.insert(CONTENT_DISPOSITION, value);
+    }
+    if limited {
+        // Every response of a download-limited file must reach the origin,
+        // or caches would hand it out past its limit.
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    } else if let Presigned::Valid(link_expires) = presigned {
+        // Shared caches must not keep serving the file after the link lapses.
+        headers.insert(
+            CACHE_CONTROL,
+            HeaderValue::from_str(&format!("private, max-age={}", link_expires - now)).unwrap(),
+        );
+    } else if meta_expires > now {
+        let remaining = meta_expires - now;
+        // If the object expires far in the future, mark it immutable so CDNs cache aggressively.
+        // Otherwise use the remaining TTL as max-age.
+        if remaining > 60 * 60 * 24 * 7 {
+            // more than 7 days -> long cache
+            headers.insert(
+                CACHE_CONTROL,
+                HeaderValue::from_static("public, max-age=31536000, immutable"),
+            );
+        } else {
+            headers.insert(
+                CACHE_CONTROL,
+                HeaderValue::from_str(&format!("public, max-age={}", remaining)).unwrap(),
+            );
+        }
+        let exp_time =
+            std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(meta_expires);
+        headers.insert(
+            EXPIRES,
+            HeaderValue::from_str(&httpdate::fmt_http_date(exp_time)).unwrap(),
+        );
+    }
+    let etag = if content_hash.is_empty() {
+        match state.file_store.size(&file).await {
+            Ok(Some(size)) => size_time_etag(size, created),
+            Ok(None) => {
+                warn!(file = %file, "fetch request missing file in storage");
+                return (StatusCode::NOT_FOUND, "not found").into_response();
+            }
+            Err(err) => {
+                error!(?err, file = %file, "failed to stat file in storage");
+                return json_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "fs_error",
+                    "cant read file",
                 );
             }
+        }
+    } else {
+        format!("\"{content_hash}\"")
+    };
+    apply_validators(&mut headers, &etag, created);
+    if !limited && is_not_modified(&req_headers, &etag, created) {
+        debug!(file = %file, "fetch request not modified");
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+    // Files are served as stored, so the representation and content
+    // digests are both the upload's SHA-256.
+    if let Some(digest) = digest_fields::sha256_field(&content_hash) {
+        headers.insert(CONTENT_DIGEST, digest.clone());
+        headers.insert(REPR_DIGEST, digest);
+    }
+    match state.file_store.read(&file).await {
+        Ok(None) => {
+            warn!(file = %file, "fetch request missing file in storage");
+            (StatusCode::NOT_FOUND, "not found").into_response()
+        }
+        Ok(Some(bytes)) => {
+            if method != Method::HEAD {
+                match state.record_download(&file) {
+                    DownloadClaim::Exhausted => {
+                        debug!(file = %file, "fetch request after download limit");
+                        return (StatusCode::NOT_FOUND, "not found").into_response();
+                    }
+                    DownloadClaim::Remaining(0) => {
+                        state.remove_owner(&file);
+                        if let Err(err) = state.file_store.delete(&file).await {
+                            warn!(?err, file = %file, "failed to delete file after last download");
+                        }
+                        state
+                            .webhooks
+                            .emit(WebhookEvent::file_deleted(&file, DELETED_AT_DOWNLOAD_LIMIT));
+                        info!(file = %file, "download limit reached; file deleted");
+                    }
+                    DownloadClaim::Remaining(left) => {
+                        debug!(file = %file, left, "download counted");
+                    }
+                    DownloadClaim::Unlimited => {}
+                }
+                state.persist_owners().await;
+            }
             info!(file = %file, size = bytes.len(), "serving file");
             (headers, bytes).into_response()
         }
-        Err(_) => json_error(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "fs_error",
-            "cant read file",
-        ),
+        Err(err) => {
+            error!(?err, file = %file, "failed to read file from storage");
+            json_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "fs_error",
+                "cant read file",
+            )
+        }
     }
 }
 
-pub async fn file_handler(
+/// Validator for content without a known hash, from its size and
+/// modification time.
+fn size_time_etag(size: u64, modified: u64) -> String {
+    format!("\"{size:x}-{modified:x}\"")
+}
+
+fn apply_validators(headers: &mut HeaderMap, etag: &str, modified: u64) {
+    if let Ok(value) = HeaderValue::from_str(etag) {
+        headers.insert(ETAG, value);
+    }
+    let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(modified);
+    if let Ok(value) = HeaderValue::from_str(&httpdate::fmt_http_date(modified)) {
+        headers.insert(LAST_MODIFIED, value);
+    }
+}
+
+/// Whether a GET can be answered with `304 Not Modified`. `If-None-Match`
+/// wins over `If-Modified-Since` when both are sent (RFC 9110 §13.1.3).
+fn is_not_modified(req_headers: &HeaderMap, etag: &str, modified: u64) -> bool {
+    if let Some(value) = req_headers.get(IF_NONE_MATCH) {
+        let Ok(value) = value.to_str() else {
+            return false;
+        };
+        // Weak comparison: W/ prefixes are ignored on both sides.
+        let ours = etag.trim_start_matches("W/");
+        return value
+            .split(',')
+            .map(str::trim)
+            .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == ours);
+    }
+    req_headers
+        .get(IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| httpdate::parse_http_date(v).ok())
+        .and_then(|since| since.duration_since(std::time::UNIX_EPOCH).ok())
+        .is_some_and(|since| modified <= since.as_secs())
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct LinkStatusResponse {
+    pub name: String,
+    /// Whether `/f/{name}` would currently serve the file.
+    pub exists: bool,
+    pub expired: bool,
+    pub quarantined: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Expiry time for live files, or when an expired file went away.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+    /// Downloads left for files uploaded with `max_downloads`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub downloads_left: Option<u32>,
+}
+
+/// Metadata-only check of a shared link for bots that validate links before
+/// posting them. Never reads the file body and has its own rate limit.
+#[tracing::instrument(name = "files.status", skip(state, headers, addr), fields(file = %name))]
+pub async fn link_status_handler(
     State(state): State<AppState>,
-    Path(path): Path<String>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
+    Path(name): Path<String>,
 ) -> Response {
-    let rel = path.trim_start_matches('/');
-    if rel.contains("..") || rel.contains('\\') {
-        warn!(path = %path, "static file request rejected: traversal attempt");
-        return (StatusCode::BAD_REQUEST, "bad path").into_response();
+    let client_ip = real_client_ip(&headers, &addr);
+    if !state.link_status_limiter.check(&client_ip).await {
+        return json_error(StatusCode::TOO_MANY_REQUESTS, "rate_limited", "slow down");
     }
-    let mut candidate = state.static_dir.join(rel);
-    if !candidate.exists() {
-        if !rel.is_empty() && !rel.contains('.') {
-            let alt = state.static_dir.join(format!("{}.html", rel));
-            if alt.exists() {
-                candidate = alt;
-            } else {
-                debug!(request = %rel, "static asset not found");
-                return (StatusCode::NOT_FOUND, "not found").into_response();
+    if name.contains('/') || name.contains("..") {
+        return json_error(StatusCode::BAD_REQUEST, "invalid_name", "invalid file name");
+    }
+    let now = state.now_secs();
+    let mut status = LinkStatusResponse {
+        name: name.clone(),
+        exists: false,
+        expired: false,
+        quarantined: false,
+        size: None,
+        expires: None,
+        downloads_left: None,
+    };
+    let meta = state.owners.get(&name).map(|m| m.value().clone());
+    match meta {
+        Some(meta) if meta.private => debug!(file = %name, "status check for private file"),
+        Some(meta) if meta.expires <= now => {
+            status.expired = true;
+            status.expires = Some(meta.expires);
+        }
+        Some(meta) => match state.file_store.size(&name).await {
+            Ok(Some(size)) => {
+                status.exists = true;
+                status.size = Some(size);
+                status.expires = Some(meta.expires);
+                status.downloads_left = meta.max_downloads;
+            }
+            Ok(None) => debug!(file = %name, "status check for file missing in storage"),
+            Err(err) => {
+                error!(?err, file = %name, "failed to stat file for status check");
+                return json_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "fs_error",
+                    "cant read file",
+                );
+            }
+        },
+        None => {
+            if let Some(expired_at) = state.tombstones.expired_at(&name, now) {
+                status.expired = true;
+                status.expires = Some(expired_at);
+            } else if state.quarantine.get(&name).await.is_some() {
+                status.quarantined = true;
             }
-        } else {
-            debug!(request = %rel, "static asset not found");
-            return (StatusCode::NOT_FOUND, "not found").into_response();
         }
     }
-    let accept = headers
-        .get(ACCEPT_ENCODING)
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("");
-    let wants_br = accept.contains("br");
-    let wants_gzip = accept.contains("gzip");
+    trace!(file = %name, exists = status.exists, "link status served");
+    (
+        [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+        Json(status),
+    )
+        .into_response()
+}
+
+/// Human-readable UTC date for an expiry timestamp.
+fn format_expiry_date(secs: u64) -> String {
+    httpdate::fmt_http_date(std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs))
+}
+
+/// `410 Gone` for a name that expired recently, so dead links read
+/// differently from ones that never existed.
+fn expired_file_response(expired_at: u64) -> Response {
+    (
+        StatusCode::GONE,
+        [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+        format!("this file expired on {}", format_expiry_date(expired_at)),
+    )
+        .into_response()
+}
+
+/// `410 Gone` telling the owner of a file an admin removed why it went.
+fn removed_file_response(removal: &Removal) -> Response {
+    (
+        StatusCode::GONE,
+        [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+        format!(
+            "this file was {} on {}",
+            removal.reason.message(),
+            format_expiry_date(removal.at)
+        ),
+    )
+        .into_response()
+}
+
+/// Who the request acts for, when the peer address is known.
+async fn connection_owner(
+    state: &AppState,
+    connect: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: &HeaderMap,
+) -> Option<String> {
+    let Extension(ConnectInfo(addr)) = connect?;
+    request_owner(state, headers, &real_client_ip(headers, &addr))
+        .await
+        .ok()
+}
+
+/// The removal record for `file`, but only when its owner is asking; anyone
+/// else gets the plain 404 a removed file has always had.
+async fn owned_removal(
+    state: &AppState,
+    connect: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: &HeaderMap,
+    file: &str,
+    now: u64,
+) -> Option<Removal> {
+    let removal = state.tombstones.removal(file, now)?;
+    let owner = connection_owner(state, connect, headers).await?;
+    (owner == removal.owner_hash).then_some(removal)
+}
+
+/// Interstitial shown for shared `/d/{file}` links: what the file is, when it
+/// expires, and how to report it, before anything is downloaded.
+#[tracing::instrument(name = "files.download_page", skip(state, query), fields(file = %file))]
+pub async fn download_page_handler(
+    State(state): State<AppState>,
+    connect: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: HeaderMap,
+    Path(file): Path<String>,
+    Query(query): Query<LangQuery>,
+) -> Response {
+    if file.contains('/') {
+        warn!(file = %file, "download page rejected: invalid path");
+        return (StatusCode::BAD_REQUEST, "bad file").into_response();
+    }
+    let now = state.now_secs();
+    let lang = query.lang.as_deref().unwrap_or("en");
+    let meta = state.owners.get(&file).map(|m| m.value().clone());
+    let expired = match &meta {
+        Some(m) if m.expires <= now => Some((m.expires, m.display_name())),
+        Some(_) => None,
+        None => state
+            .tombstones
+            .expired_at(&file, now)
+            .map(|at| (at, String::new())),
+    };
+    if let Some((expired_at, display_name)) = expired {
+        debug!(file = %file, expired_at, "download page for expired file");
+        let value = json!({
+            "name": file,
+            "display_name": if display_name.is_empty() { file.clone() } else { display_name },
+            "expired": true,
+            "expired_at": expired_at,
+            "expired_on": format_expiry_date(expired_at),
+        });
+        let mut resp =
+            render_tera_page(&state, "download.html.tera", lang, Some(("file", &value))).await;
+        if resp.status() == StatusCode::OK {
+            *resp.status_mut() = StatusCode::GONE;
+        }
+        resp.headers_mut()
+            .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        return resp;
+    }
+    if meta.is_none()
+        && let Some(removal) = owned_removal(&state, connect, &headers, &file, now).await
+    {
+        debug!(file = %file, reason = removal.reason.as_str(), "download page for removed file");
+        let value = json!({
+            "name": file,
+            "display_name": file,
+            "removed": true,
+            "removed_at": removal.at,
+            "removed_on": format_expiry_date(removal.at),
+            "removed_reason": removal.reason.as_str(),
+            "removed_message": removal.reason.message(),
+        });
+        let mut resp =
+            render_tera_page(&state, "download.html.tera", lang, Some(("file", &value))).await;
+        if resp.status() == StatusCode::OK {
+            *resp.status_mut() = StatusCode::GONE;
+        }
+        resp.headers_mut()
+            .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        return resp;
+    }
+    let Some(meta) = meta.filter(|m| !m.private) else {
+        debug!(file = %file, "download page for missing or private file");
+        return (StatusCode::NOT_FOUND, "not found").into_response();
+    };
+    let Ok(Some(size)) = state.file_store.size(&file).await else {
+        warn!(file = %file, "download page for file missing in storage");
+        return (StatusCode::NOT_FOUND, "not found").into_response();
+    };
+    let display_name = meta.display_name();
+    let encoded = urlencoding::encode(&file);
+    let remaining = meta.expires - now;
+    let expires_in = if remaining >= 86400 {
+        format!("{}d", remaining / 86400)
+    } else if remaining >= 3600 {
+        format!("{}h", remaining / 3600)
+    } else if remaining >= 60 {
+        format!("{}m", remaining / 60)
+    } else {
+        format!("{}s", remaining)
+    };
+    let value = json!({
+        "name": file,
+        "display_name": if display_name.is_empty() { file.clone() } else { display_name },
+        "size": format_bytes(size),
+        "size_bytes": size,
+        "mime": MimeGuess::from_path(&file).first_or_octet_stream().as_ref(),
+        "expires": meta.expires,
+        "expires_in": expires_in,
+        "direct_url": format!("/f/{encoded}"),
+        "report_url": format!("/report?file={encoded}"),
+        "downloads_left": meta.max_downloads,
+    });
+    let mut resp =
+        render_tera_page(&state, "download.html.tera", lang, Some(("file", &value))).await;
+    resp.headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    resp
+}
 
-    let mime = MimeGuess::from_path(&candidate).first_or_octet_stream();
+/// Content type plus cache headers for a static asset at `path`.
+fn static_asset_headers(path: &std::path::Path) -> HeaderMap {
+    let mime = MimeGuess::from_path(path).first_or_octet_stream();
     let mut resp_headers = HeaderMap::new();
     resp_headers.insert(CONTENT_TYPE, mime.as_ref().parse().unwrap());
 
-    if let Some(ext) = candidate.extension().and_then(|e| e.to_str()) {
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
         let cacheable = matches!(
             ext.to_ascii_lowercase().as_str(),
             "css"
@@ -174,59 +565,160 @@ pub async fn file_handler(
                 EXPIRES,
                 HeaderValue::from_str(&httpdate::fmt_http_date(exp_time)).unwrap(),
             );
-            trace!(path = ?candidate, max_age, "applied cache headers to static asset");
+            trace!(?path, max_age, "applied cache headers to static asset");
         }
     }
+    resp_headers
+}
 
-    if wants_br {
-        if let Some(ext) = candidate.extension().and_then(|e| e.to_str()) {
-            let br_path = candidate.with_extension(format!("{ext}.br"));
-            if fs::metadata(&br_path).await.is_ok() {
-                match fs::read(&br_path).await {
-                    Ok(bytes) => {
-                        resp_headers.insert(CONTENT_ENCODING, HeaderValue::from_static("br"));
-                        resp_headers.insert(VARY, HeaderValue::from_static("Accept-Encoding"));
-                        debug!(path = ?br_path, encoding = "br", "serving precompressed asset");
-                        return (resp_headers, bytes).into_response();
-                    }
-                    Err(_) => {}
-                }
+/// Serve `rel` (or `rel.html`) from the assets compiled into the binary, when
+/// the file is missing from the static dir.
+pub fn serve_embedded_asset(rel: &str) -> Response {
+    let html = (!rel.is_empty() && !rel.contains('.')).then(|| format!("{rel}.html"));
+    let found = embedded_public(rel)
+        .map(|bytes| (rel.to_string(), bytes))
+        .or_else(|| html.and_then(|name| embedded_public(&name).map(|bytes| (name, bytes))));
+    let Some((name, bytes)) = found else {
+        debug!(request = %rel, "static asset not found");
+        return (StatusCode::NOT_FOUND, "not found").into_response();
+    };
+    let resp_headers = static_asset_headers(std::path::Path::new(&name));
+    debug!(asset = %name, size = bytes.len(), "serving embedded static asset");
+    (resp_headers, bytes).into_response()
+}
+
+pub async fn file_handler(
+    State(state): State<AppState>,
+    Path(path): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let rel = path.trim_start_matches('/');
+    if rel.contains("..") || rel.contains('\\') {
+        warn!(path = %path, "static file request rejected: traversal attempt");
+        return (StatusCode::BAD_REQUEST, "bad path").into_response();
+    }
+    let mut candidate = state.static_dir.join(rel);
+    if !candidate.exists() {
+        if !rel.is_empty() && !rel.contains('.') {
+            let alt = state.static_dir.join(format!("{}.html", rel));
+            if alt.exists() {
+                candidate = alt;
+            } else {
+                return serve_embedded_asset(rel);
             }
+        } else {
+            return serve_embedded_asset(rel);
         }
     }
-    if wants_gzip {
-        if let Some(ext) = candidate.extension().and_then(|e| e.to_str()) {
-            let gz_path = candidate.with_extension(format!("{ext}.gz"));
-            if fs::metadata(&gz_path).await.is_ok() {
-                match fs::read(&gz_path).await {
-                    Ok(bytes) => {
-                        resp_headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
-                        resp_headers.insert(VARY, HeaderValue::from_static("Accept-Encoding"));
-                        debug!(path = ?gz_path, encoding = "gzip", "serving precompressed asset");
-                        return (resp_headers, bytes).into_response();
-                    }
-                    Err(_) => {}
-                }
-            }
+    let accept = headers
+        .get(ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    let wants_br = accept.contains("br");
+    let wants_gzip = accept.contains("gzip");
+
+    let mut resp_headers = static_asset_headers(&candidate);
+
+    // Serve a precompressed sibling when the client accepts it. Each encoding
+    // gets its own validator since the bytes differ.
+    let mut served = candidate.clone();
+    for (wanted, suffix, encoding) in [(wants_br, "br", "br"), (wants_gzip, "gz", "gzip")] {
+        if !wanted {
+            continue;
+        }
+        let Some(ext) = candidate.extension().and_then(|e| e.to_str()) else {
+            break;
+        };
+        let compressed = candidate.with_extension(format!("{ext}.{suffix}"));
+        if fs::metadata(&compressed).await.is_ok() {
+            resp_headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding));
+            resp_headers.insert(VARY, HeaderValue::from_static("Accept-Encoding"));
+            served = compressed;
+            break;
+        }
+    }
+
+    if let Ok(md) = fs::metadata(&served).await {
+        let modified = md
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let etag = size_time_etag(md.len(), modified);
+        apply_validators(&mut resp_headers, &etag, modified);
+        if is_not_modified(&headers, &etag, modified) {
+            debug!(path = ?served, "static asset not modified");
+            return (StatusCode::NOT_MODIFIED, resp_headers).into_response();
         }
     }
 
-    match fs::read(&candidate).await {
+    match fs::read(&served).await {
         Ok(bytes) => {
-            info!(path = ?candidate, size = bytes.len(), "serving static asset");
+            match resp_headers.get(CONTENT_ENCODING) {
+                Some(encoding) => {
+                    debug!(path = ?served, ?encoding, "serving precompressed asset")
+                }
+                None => info!(path = ?served, size = bytes.len(), "serving static asset"),
+            }
             (resp_headers, bytes).into_response()
         }
         Err(err) => {
-            warn!(?err, path = ?candidate, "failed to read static asset");
+            warn!(?err, path = ?served, "failed to read static asset");
             (StatusCode::INTERNAL_SERVER_ERROR, "cant read file").into_response()
         }
     }
 }
 
-pub async fn config_handler(State(state): State<AppState>) -> Response {
-    let streaming_opt_in = env::var("ENABLE_STREAMING_UPLOADS")
-        .map(|v| matches!(v.to_ascii_lowercase().as_str(), "1" | "true" | "yes" | "on"))
-        .unwrap_or(false);
+pub async fn version_handler(State(state): State<AppState>) -> Response {
+    let info = BuildInfo::for_state(&state);
+    trace!(version = info.version, commit = ?info.commit, "serving version");
+    (
+        [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+        Json(info),
+    )
+        .into_response()
+}
+
+/// Readiness for load balancers: `503` while draining, otherwise `200`.
+/// A metadata store running on its fallback still serves, so it shows up as
+/// `degraded` rather than failing the check.
+pub async fn readyz_handler(State(state): State<AppState>) -> Response {
+    let failover = state.kv.failover_status();
+    let (code, status) = if state.drain.is_draining() {
+        (StatusCode::SERVICE_UNAVAILABLE, "draining")
+    } else if failover.as_ref().is_some_and(|f| f.degraded()) {
+        (StatusCode::OK, "degraded")
+    } else {
+        (StatusCode::OK, "ready")
+    };
+    trace!(status, "serving readiness");
+    (
+        code,
+        [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+        Json(json!({
+            "status": status,
+            "metadata_store": {
+                "backend": state.kv.backend_name(),
+                "failover": failover,
+            },
+        })),
+    )
+        .into_response()
+}
+
+pub async fn config_handler(
+    State(state): State<AppState>,
+    connect: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: HeaderMap,
+) -> Response {
+    // Rollouts are keyed by owner; without a peer address only fully rolled
+    // out flags apply.
+    let owner = connection_owner(&state, connect, &headers).await;
+    let streaming_opt_in = state
+        .flags
+        .check(STREAMING_UPLOADS, owner.as_deref())
+        .unwrap_or_else(streaming_uploads_enabled);
     let telemetry = state.telemetry.as_ref();
     let sentry_enabled = telemetry.sentry_enabled();
     let telemetry_payload = FrontendTelemetry {
@@ -244,6 +736,7 @@ pub async fn config_handler(State(state): State<AppState>) -> Response {
         max_file_bytes: max_file_bytes(),
         max_file_size_str: format_bytes(max_file_bytes()),
         enable_streaming_uploads: streaming_opt_in,
+        share_link_prefix: *SHARE_LINK_PREFIX,
         telemetry: Some(telemetry_payload),
     };
     debug!(
@@ -254,3 +747,31 @@ pub async fn config_handler(State(state): State<AppState>) -> Response {
     );
     Json(resp).into_response()
 }
+
+#[tracing::instrument(name = "transparency.log", skip(state))]
+pub async fn transparency_log_handler(State(state): State<AppState>) -> Response {
+    match state.transparency.contents().await {
+        Ok(bytes) => {
+            debug!(size = bytes.len(), "serving transparency log");
+            (
+                [
+                    (
+                        CONTENT_TYPE,
+                        HeaderValue::from_static("text/plain; charset=utf-8"),
+                    ),
+                    (CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+                ],
+                bytes,
+            )
+                .into_response()
+        }
+        Err(err) => {
+            warn!(?err, "failed to read transparency log");
+            json_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "transparency_read",
+                "failed to read transparency log",
+            )
+        }
+    }
+}
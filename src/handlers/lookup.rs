@@ -0,0 +1,301 @@
+//! Dedup lookups that require proof of possession. Unlike `/checkhash`, which
+//! answers for any hash, a caller first gets a challenge naming a random byte
+//! range of the content and must return `sha256(nonce || bytes[range])` before
+//! learning whether the file is hosted. Challenges are HMAC-signed and bound to
+//! the caller, so no server-side state is kept between the two requests.
+
+use axum::Json;
+use axum::extract::{ConnectInfo, State};
+use axum::http::header::CACHE_CONTROL;
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr as ClientAddr;
+use tracing::{debug, error, info, warn};
+
+use crate::state::AppState;
+use crate::util::{
+    json_error, looks_like_hash, max_file_bytes, qualify_path, real_client_ip, share_path,
+};
+
+/// Largest byte range a challenge asks the client to hash.
+pub const LOOKUP_SAMPLE_BYTES: u64 = 64 * 1024;
+/// Seconds a challenge stays valid.
+pub const LOOKUP_CHALLENGE_TTL_SECS: u64 = 120;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct LookupChallengeRequest {
+    /// SHA-256 of the whole file, hex encoded.
+    pub hash: String,
+    pub size: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct LookupChallengeResponse {
+    /// Opaque token to send back with the proof.
+    pub challenge: String,
+    /// Hex bytes to prepend to the range before hashing.
+    pub nonce: String,
+    pub offset: u64,
+    pub length: u64,
+    pub expires: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct LookupVerifyRequest {
+    pub challenge: String,
+    /// Hex `sha256(nonce || file[offset..offset + length])`.
+    pub proof: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct LookupVerifyResponse {
+    pub found: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+}
+
+/// Fields carried inside a signed challenge token.
+struct Challenge {
+    hash: String,
+    size: u64,
+    offset: u64,
+    length: u64,
+    expires: u64,
+    nonce: Vec<u8>,
+}
+
+impl Challenge {
+    fn payload(&self) -> String {
+        format!(
+            "{}:{}:{}:{}:{}:{}",
+            self.hash,
+            self.size,
+            self.offset,
+            self.length,
+            self.expires,
+            hex(&self.nonce)
+        )
+    }
+
+    fn parse(payload: &str) -> Option<Self> {
+        let mut parts = payload.split(':');
+        let challenge = Self {
+            hash: parts.next()?.to_string(),
+            size: parts.next()?.parse().ok()?,
+            offset: parts.next()?.parse().ok()?,
+            length: parts.next()?.parse().ok()?,
+            expires: parts.next()?.parse().ok()?,
+            nonce: unhex(parts.next()?)?,
+        };
+        parts.next().is_none().then_some(challenge)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn unhex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+const CHALLENGE_PURPOSE: &str = "lookup-challenge";
+
+/// What a challenge token signs: the challenge, bound to who asked for it.
+fn challenge_signed_bytes(payload: &str, owner_hash: &str) -> Vec<u8> {
+    format!("{payload}\n{owner_hash}").into_bytes()
+}
+
+fn sign_challenge(state: &AppState, challenge: &Challenge, owner_hash: &str) -> String {
+    let payload = challenge.payload();
+    let sig = state.signing_keys.sign(
+        CHALLENGE_PURPOSE,
+        &challenge_signed_bytes(&payload, owner_hash),
+    );
+    format!("{}.{sig}", BASE64.encode(payload))
+}
+
+/// The challenge in `token`, if it was issued to `owner_hash` and is unexpired.
+fn open_challenge(state: &AppState, token: &str, owner_hash: &str, now: u64) -> Option<Challenge> {
+    let (payload, sig) = token.split_once('.')?;
+    let payload = String::from_utf8(BASE64.decode(payload).ok()?).ok()?;
+    state
+        .signing_keys
+        .verify(
+            CHALLENGE_PURPOSE,
+            &challenge_signed_bytes(&payload, owner_hash),
+            sig,
+        )
+        .then_some(())?;
+    Challenge::parse(&payload).filter(|c| c.expires > now)
+}
+
+fn fingerprint_failed() -> Response {
+    json_error(
+        StatusCode::FORBIDDEN,
+        "invalid_ip",
+        "unable to fingerprint client",
+    )
+}
+
+fn no_store(body: impl Serialize) -> Response {
+    let mut resp = Json(body).into_response();
+    resp.headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    resp
+}
+
+/// Issue a possession challenge for `hash`. The range is picked from the size
+/// the caller declares, so the answer looks the same whether or not the file
+/// is hosted.
+#[tracing::instrument(name = "files.lookup.challenge", skip_all)]
+pub async fn lookup_challenge_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<ClientAddr>,
+    headers: HeaderMap,
+    Json(req): Json<LookupChallengeRequest>,
+) -> Response {
+    let client_ip = real_client_ip(&headers, &addr);
+    if state.is_banned(&client_ip).await {
+        return json_error(StatusCode::FORBIDDEN, "banned", "ip banned");
+    }
+    let Some(owner_hash) = state.hash_ip_to_string(&client_ip) else {
+        return fingerprint_failed();
+    };
+    let hash = req.hash.trim().to_ascii_lowercase();
+    if !looks_like_hash(&hash) {
+        return json_error(
+            StatusCode::BAD_REQUEST,
+            "invalid_hash",
+            "expected a sha256 hex digest",
+        );
+    }
+    if req.size == 0 || req.size > max_file_bytes() {
+        return json_error(StatusCode::BAD_REQUEST, "invalid_size", "size out of range");
+    }
+    let mut rng = rand::thread_rng();
+    let length = req.size.min(LOOKUP_SAMPLE_BYTES);
+    let challenge = Challenge {
+        hash,
+        size: req.size,
+        offset: rng.gen_range(0..=req.size - length),
+        length,
+        expires: state.now_secs() + LOOKUP_CHALLENGE_TTL_SECS,
+        nonce: rng.r#gen::<[u8; 16]>().to_vec(),
+    };
+    debug!(
+        offset = challenge.offset,
+        length = challenge.length,
+        "issued lookup challenge"
+    );
+    no_store(LookupChallengeResponse {
+        challenge: sign_challenge(&state, &challenge, &owner_hash),
+        nonce: hex(&challenge.nonce),
+        offset: challenge.offset,
+        length: challenge.length,
+        expires: challenge.expires,
+    })
+}
+
+/// Check a possession proof and, when it matches a hosted file, hand back
+/// that file's link. Wrong proofs and unknown hashes get the same answer.
+#[tracing::instrument(name = "files.lookup.verify", skip_all)]
+pub async fn lookup_verify_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<ClientAddr>,
+    headers: HeaderMap,
+    Json(req): Json<LookupVerifyRequest>,
+) -> Response {
+    let client_ip = real_client_ip(&headers, &addr);
+    if state.is_banned(&client_ip).await {
+        return json_error(StatusCode::FORBIDDEN, "banned", "ip banned");
+    }
+    let Some(owner_hash) = state.hash_ip_to_string(&client_ip) else {
+        return fingerprint_failed();
+    };
+    let now = state.now_secs();
+    let Some(challenge) = open_challenge(&state, req.challenge.trim(), &owner_hash, now) else {
+        return json_error(
+            StatusCode::BAD_REQUEST,
+            "invalid_challenge",
+            "challenge is invalid or expired",
+        );
+    };
+    let not_found = || {
+        no_store(LookupVerifyResponse {
+            found: false,
+            file: None,
+            url: None,
+            expires: None,
+        })
+    };
+    let hosted = {
+        let snapshot = state.owners_snapshot();
+        snapshot.file_with_hash(&challenge.hash).map(str::to_string)
+    };
+    let Some(file) = hosted else {
+        return not_found();
+    };
+    let Some(expires) = state
+        .owners
+        .get(&file)
+        .map(|m| m.expires)
+        .filter(|expires| *expires > now)
+    else {
+        return not_found();
+    };
+    match state.file_store.size(&file).await {
+        Ok(Some(size)) if size == challenge.size => {}
+        Ok(_) => return not_found(),
+        Err(err) => {
+            error!(?err, file = %file, "failed to stat file for lookup proof");
+            return not_found();
+        }
+    }
+    let sample = match state
+        .file_store
+        .read_range(&file, challenge.offset, challenge.length)
+        .await
+    {
+        Ok(Some(sample)) => sample,
+        Ok(None) => return not_found(),
+        Err(err) => {
+            error!(?err, file = %file, "failed to read file for lookup proof");
+            return not_found();
+        }
+    };
+    let mut hasher = Sha256::new();
+    hasher.update(&challenge.nonce);
+    hasher.update(&sample);
+    let expected = format!("{:x}", hasher.finalize());
+    if !req.proof.trim().eq_ignore_ascii_case(&expected) {
+        warn!(file = %file, "lookup proof did not match hosted content");
+        return not_found();
+    }
+    info!(file = %file, "lookup proof accepted");
+    no_store(LookupVerifyResponse {
+        found: true,
+        url: Some(qualify_path(&state, &share_path(&file))),
+        file: Some(file),
+        expires: Some(expires),
+    })
+}
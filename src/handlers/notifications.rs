@@ -0,0 +1,143 @@
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{ConnectInfo, Query, State};
+use axum::http::header::ORIGIN;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::Response;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, warn};
+
+use crate::accounts::ACCOUNT_COOKIE;
+use crate::cors;
+use crate::owner_events::{EXPIRY_CHECK_INTERVAL, EXPIRY_WARNING_SECS, OwnerEvent};
+use crate::state::AppState;
+use crate::util::{bearer_token, get_cookie, json_error, real_client_ip};
+
+#[derive(Debug, Default, Deserialize)]
+pub struct OwnerSocketQuery {
+    /// API token, for clients that cannot set `Authorization` on a
+    /// WebSocket handshake (browsers).
+    pub token: Option<String>,
+}
+
+/// `/ws`: pushes [`OwnerEvent`]s for the caller's files as JSON text
+/// messages. Browsers do not apply CORS to WebSocket handshakes, so one from
+/// another origin is refused unless that origin is in the upload allow-list.
+/// The caller must prove who they are with an API token (header or
+/// `?token=`) or, from the same origin only, an account session; an IP hash
+/// is not enough, since any page the visitor opens shares their address.
+pub async fn owner_socket_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<OwnerSocketQuery>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let client_ip = real_client_ip(&headers, &addr);
+    if state.is_banned(&client_ip).await {
+        return json_error(StatusCode::FORBIDDEN, "banned", "banned");
+    }
+    let same_origin = cors::is_same_origin(&headers);
+    if !same_origin {
+        let origin = headers
+            .get(ORIGIN)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        if !state.cors.upload.permits(origin) {
+            warn!(%client_ip, origin, "owner socket rejected: origin not allowed");
+            return json_error(
+                StatusCode::FORBIDDEN,
+                "origin_not_allowed",
+                "origin not allowed",
+            );
+        }
+    }
+    let secret = query.token.as_deref().or_else(|| bearer_token(&headers));
+    let owner_hash = if let Some(secret) = secret {
+        match state.api_tokens.authenticate(secret) {
+            Some(token) => token.owner_hash,
+            None => {
+                warn!(%client_ip, "owner socket rejected: unknown api token");
+                return json_error(
+                    StatusCode::UNAUTHORIZED,
+                    "invalid_token",
+                    "api token not recognised",
+                );
+            }
+        }
+    } else if let Some(account) = (same_origin && state.accounts.is_enabled())
+        .then(|| get_cookie(&headers, ACCOUNT_COOKIE))
+        .flatten()
+        .and_then(|session| state.accounts.session_account(&session))
+    {
+        account.owner_hash()
+    } else {
+        debug!(%client_ip, "owner socket rejected: no credential");
+        return json_error(
+            StatusCode::UNAUTHORIZED,
+            "auth_required",
+            "an api token or account session is required",
+        );
+    };
+    ws.on_upgrade(move |socket| serve_owner_socket(state, owner_hash, socket))
+}
+
+async fn serve_owner_socket(state: AppState, owner_hash: String, mut socket: WebSocket) {
+    let mut notices = state.owner_events.subscribe();
+    let mut interval = tokio::time::interval(EXPIRY_CHECK_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut warned = HashSet::new();
+    debug!(owner_hash = %owner_hash, "owner socket opened");
+    loop {
+        let events = tokio::select! {
+            notice = notices.recv() => match notice {
+                Ok(notice) if notice.owner_hash == owner_hash => vec![notice.event.clone()],
+                Ok(_) => continue,
+                Err(RecvError::Lagged(missed)) => {
+                    debug!(owner_hash = %owner_hash, missed, "owner socket fell behind");
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            },
+            _ = interval.tick() => expiring_files(&state, &owner_hash, &mut warned),
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+        };
+        for event in events {
+            let Ok(text) = serde_json::to_string(&event) else {
+                continue;
+            };
+            if socket.send(Message::Text(text.into())).await.is_err() {
+                debug!(owner_hash = %owner_hash, "owner socket closed while sending");
+                return;
+            }
+        }
+    }
+    debug!(owner_hash = %owner_hash, "owner socket closed");
+}
+
+/// `file.expiring` for each owned file now within [`EXPIRY_WARNING_SECS`] of
+/// expiring, once per file per connection.
+fn expiring_files(
+    state: &AppState,
+    owner_hash: &str,
+    warned: &mut HashSet<String>,
+) -> Vec<OwnerEvent> {
+    let now = state.now_secs();
+    let snapshot = state.owners_snapshot();
+    let owned = snapshot.files_for(owner_hash);
+    warned.retain(|file| owned.iter().any(|(owned, _)| owned == file));
+    owned
+        .iter()
+        .filter(|(_, meta)| meta.expires > now && meta.expires - now <= EXPIRY_WARNING_SECS)
+        .filter(|(file, _)| warned.insert(file.clone()))
+        .map(|(file, meta)| OwnerEvent::FileExpiring {
+            file: file.clone(),
+            expires: meta.expires,
+        })
+        .collect()
+}
@@ -0,0 +1,280 @@
+use axum::Json;
+use axum::extract::{ConnectInfo, Path, State};
+use axum::http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use tracing::{debug, error, info, trace, warn};
+
+use crate::handlers::web::load_asset_manifest;
+use crate::state::{AppState, QueuedUpload, QueuedUploadItem};
+use crate::util::{is_forbidden_extension, json_error, max_file_bytes, new_id, real_client_ip};
+
+/// How long a queued-upload registration stays valid before the worker must
+/// register again.
+pub const QUEUED_UPLOAD_TTL: u64 = 24 * 3600;
+/// Upper bound on items a single registration may describe.
+pub const MAX_QUEUED_ITEMS: usize = 32;
+/// Live registrations kept per client; registering past this replaces the
+/// oldest, which belongs to a worker that never released it.
+pub const MAX_QUEUED_REGISTRATIONS: usize = 4;
+/// Longest file name, in bytes, a registration may carry.
+pub const MAX_QUEUED_NAME_BYTES: usize = 1024;
+
+const DEFAULT_CSS_BUNDLE: &str = "/dist/app.css";
+const SIMPLE_FAVICON: &str = "/img/favicon.png";
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct OfflineManifest {
+    pub version: String,
+    pub assets: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct QueueRegisterRequest {
+    pub items: Vec<QueuedUploadItem>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct QueueRejection {
+    pub index: usize,
+    pub reason: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct QueueRegisterResponse {
+    pub id: String,
+    pub accepted: Vec<usize>,
+    pub rejected: Vec<QueueRejection>,
+    pub expires: u64,
+}
+
+/// Assets the simple page needs to render offline, with fingerprinted bundle
+/// names taken from `dist/manifest.json` when present. The version changes
+/// whenever a fingerprint does, so old caches are dropped on activation.
+pub async fn offline_manifest(state: &AppState) -> OfflineManifest {
+    let css_bundle = load_asset_manifest(state)
+        .await
+        .and_then(|manifest| manifest.get("css").cloned())
+        .unwrap_or_else(|| DEFAULT_CSS_BUNDLE.to_string());
+    let assets = vec![
+        "/simple".to_string(),
+        css_bundle,
+        SIMPLE_FAVICON.to_string(),
+    ];
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+    for asset in &assets {
+        hasher.update(b"\n");
+        hasher.update(asset.as_bytes());
+    }
+    let digest = format!("{:x}", hasher.finalize());
+    OfflineManifest {
+        version: digest[..12].to_string(),
+        assets,
+    }
+}
+
+pub async fn offline_manifest_handler(State(state): State<AppState>) -> Response {
+    let manifest = offline_manifest(&state).await;
+    trace!(version = %manifest.version, "serving offline manifest");
+    (
+        [(CACHE_CONTROL, HeaderValue::from_static("no-cache"))],
+        Json(manifest),
+    )
+        .into_response()
+}
+
+#[tracing::instrument(name = "offline.service_worker", skip(state))]
+pub async fn service_worker_handler(State(state): State<AppState>) -> Response {
+    let manifest = offline_manifest(&state).await;
+    let mut ctx = tera::Context::new();
+    ctx.insert("version", &manifest.version);
+    ctx.insert(
+        "assets",
+        &serde_json::to_string(&manifest.assets).unwrap_or_else(|_| "[]".to_string()),
+    );
+    match state.tera.render("sw.js.tera", &ctx) {
+        Ok(script) => {
+            debug!(version = %manifest.version, "rendered service worker");
+            (
+                [
+                    (
+                        CONTENT_TYPE,
+                        HeaderValue::from_static("application/javascript; charset=utf-8"),
+                    ),
+                    (CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+                    (
+                        HeaderName::from_static("service-worker-allowed"),
+                        HeaderValue::from_static("/"),
+                    ),
+                ],
+                script,
+            )
+                .into_response()
+        }
+        Err(err) => {
+            error!(?err, "failed to render service worker template");
+            json_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "template",
+                "failed to render service worker",
+            )
+        }
+    }
+}
+
+fn prune_queued_uploads(state: &AppState, now: u64) {
+    state
+        .queued_uploads
+        .retain(|_, queued| queued.expires > now);
+}
+
+/// Make room for one more registration from `owner_hash` and return how many
+/// files its remaining ones still hold slots for.
+fn reserve_queue_room(state: &AppState, owner_hash: &str) -> usize {
+    let mut held: Vec<(u64, String, usize)> = state
+        .queued_uploads
+        .iter()
+        .filter(|entry| entry.value().owner_hash == owner_hash)
+        .map(|entry| {
+            let queued = entry.value();
+            (queued.created, entry.key().clone(), queued.items.len())
+        })
+        .collect();
+    held.sort();
+    let excess = (held.len() + 1).saturating_sub(MAX_QUEUED_REGISTRATIONS);
+    for (_, id, _) in held.drain(..excess) {
+        state.queued_uploads.remove(&id);
+        debug!(queue_id = %id, "replaced oldest queued offline upload");
+    }
+    held.iter().map(|(_, _, items)| items).sum()
+}
+
+#[axum::debug_handler]
+#[tracing::instrument(name = "offline.queue.register", skip(state, headers, req), fields(items = req.items.len()))]
+pub async fn register_queued_upload_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<QueueRegisterRequest>,
+) -> Response {
+    let client_ip = real_client_ip(&headers, &addr);
+    if state.is_banned(&client_ip).await {
+        warn!(%client_ip, "queued upload registration rejected: banned ip");
+        return json_error(StatusCode::FORBIDDEN, "banned", "ip banned");
+    }
+    let Some(owner_hash) = state.hash_ip_to_string(&client_ip) else {
+        warn!(%client_ip, "queued upload registration rejected: unable to hash ip");
+        return json_error(
+            StatusCode::FORBIDDEN,
+            "invalid_ip",
+            "unable to fingerprint client",
+        );
+    };
+    if req.items.is_empty() {
+        return json_error(StatusCode::BAD_REQUEST, "no_files", "no files were queued");
+    }
+    if req.items.len() > MAX_QUEUED_ITEMS {
+        return json_error(
+            StatusCode::BAD_REQUEST,
+            "too_many_items",
+            "too many files queued",
+        );
+    }
+
+    let now = state.now_secs();
+    prune_queued_uploads(&state, now);
+    // Files other registrations are about to upload already have their slots.
+    let reserved = reserve_queue_room(&state, &owner_hash);
+    let mut slots = state
+        .remaining_file_slots(&owner_hash, now)
+        .saturating_sub(reserved);
+    let mut accepted = Vec::new();
+    let mut rejected = Vec::new();
+    for (index, item) in req.items.iter().enumerate() {
+        let reason = if item.size == 0 {
+            Some("empty")
+        } else if item.size > max_file_bytes() {
+            Some("too_large")
+        } else if item.name.len() > MAX_QUEUED_NAME_BYTES {
+            Some("name_too_long")
+        } else if is_forbidden_extension(&item.name) {
+            Some("bad_filetype")
+        } else if slots == 0 {
+            Some("file_limit")
+        } else {
+            None
+        };
+        match reason {
+            Some(reason) => rejected.push(QueueRejection { index, reason }),
+            None => {
+                slots -= 1;
+                accepted.push(index);
+            }
+        }
+    }
+
+    let id = new_id();
+    let expires = now + QUEUED_UPLOAD_TTL;
+    let items = accepted.iter().map(|&i| req.items[i].clone()).collect();
+    state.queued_uploads.insert(
+        id.clone(),
+        QueuedUpload {
+            owner_hash: owner_hash.clone(),
+            items,
+            created: now,
+            expires,
+        },
+    );
+    info!(
+        owner_hash = %owner_hash,
+        queue_id = %id,
+        accepted = accepted.len(),
+        rejected = rejected.len(),
+        "registered queued offline upload"
+    );
+    Json(QueueRegisterResponse {
+        id,
+        accepted,
+        rejected,
+        expires,
+    })
+    .into_response()
+}
+
+#[axum::debug_handler]
+pub async fn delete_queued_upload_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    let client_ip = real_client_ip(&headers, &addr);
+    let Some(owner_hash) = state.hash_ip_to_string(&client_ip) else {
+        return json_error(
+            StatusCode::FORBIDDEN,
+            "invalid_ip",
+            "unable to fingerprint client",
+        );
+    };
+    let Some(entry) = state.queued_uploads.get(&id) else {
+        return json_error(StatusCode::NOT_FOUND, "queue", "queued upload not found");
+    };
+    if entry.value().owner_hash != owner_hash {
+        return json_error(
+            StatusCode::FORBIDDEN,
+            "forbidden",
+            "queued upload belongs to another client",
+        );
+    }
+    drop(entry);
+    state.queued_uploads.remove(&id);
+    debug!(queue_id = %id, "released queued offline upload");
+    StatusCode::NO_CONTENT.into_response()
+}
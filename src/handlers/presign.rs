@@ -0,0 +1,142 @@
+//! Presigned, time-limited download links. An owner mints
+//! `/f/{name}?exp=…&sig=…`, which keeps working until `exp` even for private
+//! files whose permanent link is refused. The signature comes from
+//! [`AppState::signing_keys`], so links need no server-side state and keep
+//! working across a key rotation for as long as the old key is kept.
+
+use axum::Json;
+use axum::extract::{ConnectInfo, Path, State};
+use axum::http::header::CACHE_CONTROL;
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr as ClientAddr;
+use tracing::{debug, info};
+
+use crate::handlers::upload::request_owner;
+use crate::state::AppState;
+use crate::util::{json_error, qualify_path, real_client_ip};
+
+/// Lifetime of a presigned link when the request does not ask for one.
+pub const PRESIGN_DEFAULT_SECS: u64 = 60 * 60;
+/// Longest lifetime a presigned link can have. Links never outlive the file.
+pub const PRESIGN_MAX_SECS: u64 = 7 * 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub struct PresignRequest {
+    /// Seconds the link should work for.
+    #[serde(default)]
+    pub expires_in: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct PresignResponse {
+    pub url: String,
+    pub expires: u64,
+}
+
+/// Signature parameters accepted on `/f/{name}`.
+#[derive(Deserialize, Default, Debug)]
+pub struct PresignQuery {
+    pub exp: Option<u64>,
+    pub sig: Option<String>,
+}
+
+/// What the signature parameters on a download request amount to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Presigned {
+    /// No signature was sent.
+    Absent,
+    /// A valid signature, good until this time.
+    Valid(u64),
+    /// A signature was sent but is wrong or expired.
+    Invalid,
+}
+
+const PRESIGN_PURPOSE: &str = "presign";
+
+fn presign_payload(file: &str, exp: u64) -> String {
+    format!("{file}\n{exp}")
+}
+
+/// Signature for downloading `file` until `exp`.
+pub fn sign_download(state: &AppState, file: &str, exp: u64) -> String {
+    state
+        .signing_keys
+        .sign(PRESIGN_PURPOSE, presign_payload(file, exp).as_bytes())
+}
+
+impl PresignQuery {
+    pub fn check(&self, state: &AppState, file: &str, now: u64) -> Presigned {
+        let (exp, sig) = match (self.exp, self.sig.as_deref()) {
+            (None, None) => return Presigned::Absent,
+            (Some(exp), Some(sig)) => (exp, sig),
+            _ => return Presigned::Invalid,
+        };
+        if exp <= now {
+            return Presigned::Invalid;
+        }
+        let payload = presign_payload(file, exp);
+        if state
+            .signing_keys
+            .verify(PRESIGN_PURPOSE, payload.as_bytes(), sig)
+        {
+            Presigned::Valid(exp)
+        } else {
+            Presigned::Invalid
+        }
+    }
+}
+
+/// Mint a presigned link to one of the caller's files.
+#[tracing::instrument(name = "files.presign", skip(state, headers, addr, req), fields(file = %name))]
+pub async fn presign_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<ClientAddr>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+    req: Option<Json<PresignRequest>>,
+) -> Response {
+    let client_ip = real_client_ip(&headers, &addr);
+    if state.is_banned(&client_ip).await {
+        return json_error(StatusCode::FORBIDDEN, "banned", "ip banned");
+    }
+    let owner_hash = match request_owner(&state, &headers, &client_ip).await {
+        Ok(owner_hash) => owner_hash,
+        Err(resp) => return resp,
+    };
+    let now = state.now_secs();
+    let file_expires = state
+        .owners
+        .get(&name)
+        .filter(|m| m.owner_hash == owner_hash && m.expires > now)
+        .map(|m| m.expires);
+    let Some(file_expires) = file_expires else {
+        debug!(file = %name, "presign rejected: not an owned live file");
+        return json_error(StatusCode::NOT_FOUND, "not_found", "file not found");
+    };
+    let lifetime = req
+        .and_then(|Json(req)| req.expires_in)
+        .unwrap_or(PRESIGN_DEFAULT_SECS);
+    if lifetime == 0 || lifetime > PRESIGN_MAX_SECS {
+        return json_error(
+            StatusCode::BAD_REQUEST,
+            "invalid_expiry",
+            "expires_in must be between 1 second and 7 days",
+        );
+    }
+    let exp = (now + lifetime).min(file_expires);
+    let sig = sign_download(&state, &name, exp);
+    let path = format!("f/{}?exp={exp}&sig={sig}", urlencoding::encode(&name));
+    info!(file = %name, exp, "minted presigned link");
+    let mut resp = Json(PresignResponse {
+        url: qualify_path(&state, &format!("/{path}")),
+        expires: exp,
+    })
+    .into_response();
+    resp.headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    resp
+}
@@ -2,14 +2,86 @@ use axum::extract::{ConnectInfo, Form, State};
 use axum::http::HeaderMap;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
-use serde::Deserialize;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
 use time::OffsetDateTime;
 use tracing::{debug, info, trace, warn};
 
-use crate::state::{AppState, ReportRecord};
-use crate::util::{json_error, now_secs, real_client_ip};
+use crate::owner_events::OwnerEvent;
+use crate::state::{AppState, FileMeta, ReportRecord, ReportStatus};
+use crate::util::{json_error, new_id, real_client_ip};
+use crate::webhooks::WebhookEvent;
 
-#[derive(Clone, Debug)]
+type HmacSha256 = Hmac<Sha256>;
+
+/// How owner and reporter hashes appear in report emails. Those go out
+/// through a third-party mail provider, where a full hash would be a stable
+/// identifier for the person behind it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EmailPrivacy {
+    /// Full hashes, usable in the admin ban form.
+    #[default]
+    Full,
+    /// The first 8 characters, enough to tell reports apart by eye.
+    Truncate,
+    /// A keyed digest of the hash: the same person always gets the same
+    /// value, but it matches nothing stored.
+    Hmac,
+}
+
+/// Characters kept by [`EmailPrivacy::Truncate`].
+const TRUNCATED_HASH_CHARS: usize = 8;
+
+impl EmailPrivacy {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "" | "full" | "off" => Some(Self::Full),
+            "truncate" => Some(Self::Truncate),
+            "hmac" => Some(Self::Hmac),
+            _ => None,
+        }
+    }
+
+    /// Read `JUICEBOX_EMAIL_PRIVACY`. An unknown value redacts with `hmac`
+    /// rather than sending full hashes.
+    pub fn from_env() -> Self {
+        let Ok(raw) = std::env::var("JUICEBOX_EMAIL_PRIVACY") else {
+            return Self::Full;
+        };
+        Self::parse(&raw).unwrap_or_else(|| {
+            warn!(value = %raw, "unknown JUICEBOX_EMAIL_PRIVACY; using hmac");
+            Self::Hmac
+        })
+    }
+
+    pub fn redacts(self) -> bool {
+        self != Self::Full
+    }
+
+    /// The form of `hash` to put in an email. Empty stays empty.
+    pub fn apply(self, state: &AppState, hash: &str) -> String {
+        if hash.is_empty() {
+            return String::new();
+        }
+        match self {
+            Self::Full => hash.to_string(),
+            Self::Truncate => hash.chars().take(TRUNCATED_HASH_CHARS).collect(),
+            Self::Hmac => {
+                let mut mac = HmacSha256::new_from_slice(state.ip_hash_secret.as_slice())
+                    .expect("HMAC key initialization should accept arbitrary key length");
+                mac.update(b"email\n");
+                mac.update(hash.as_bytes());
+                mac.finalize().into_bytes()[..8]
+                    .iter()
+                    .map(|b| format!("{b:02x}"))
+                    .collect()
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
 pub struct ReportRecordEmail {
     pub file: String,
     pub reason: String,
@@ -19,11 +91,16 @@ pub struct ReportRecordEmail {
     pub iso_time: String,
     pub owner_hash: String,
     pub original_name: String,
+    /// Set when the original name looks crafted to spoof its extension.
+    pub name_warning: Option<String>,
     pub expires: u64,
     pub size: u64,
     pub report_index: usize,
     pub total_reports_for_file: usize,
     pub total_reports: usize,
+    /// `owner_hash` and `reporter_hash` were redacted by [`EmailPrivacy`],
+    /// so they cannot be used to ban.
+    pub identifiers_redacted: bool,
 }
 
 #[derive(Deserialize)]
@@ -60,7 +137,7 @@ pub async fn report_handler(
             "unable to fingerprint client",
         );
     };
-    let now = now_secs();
+    let now = state.now_secs();
     let mut file_name = form.file.trim().to_string();
     if state.owners.get(&file_name).is_none() && !file_name.contains('.') {
         let prefix = format!("{file_name}.");
@@ -83,30 +160,36 @@ pub async fn report_handler(
         }
     }
     let record = ReportRecord {
+        id: new_id(),
         file: file_name.clone(),
         reason: form.reason.clone(),
         details: form.details.clone().unwrap_or_default(),
         reporter_hash: reporter_hash.clone(),
         time: now,
+        status: ReportStatus::Open,
+        resolution: None,
+        status_changed_at: None,
     };
     debug!(file = %record.file, reporter = %record.reporter_hash, "report record created");
-    let (owner_hash, original_name, expires, size) = {
-        if let Some(meta) = state.owners.get(&record.file) {
-            let meta = meta.value();
-            let path = state.upload_dir.join(&record.file);
-            let sz = tokio::fs::metadata(&path)
+    let meta = state.owners.get(&record.file).map(|m| m.value().clone());
+    let name_warning = meta.as_ref().and_then(FileMeta::name_warning_note);
+    let (owner_hash, original_name, expires, size) = match meta {
+        Some(meta) => {
+            let sz = state
+                .file_store
+                .size(&record.file)
                 .await
-                .map(|m| m.len())
+                .ok()
+                .flatten()
                 .unwrap_or(0);
             (
                 meta.owner_hash.clone(),
-                meta.original.clone(),
+                meta.display_name(),
                 meta.expires,
                 sz,
             )
-        } else {
-            (String::new(), String::new(), 0u64, 0u64)
         }
+        None => (String::new(), String::new(), 0u64, 0u64),
     };
     let (report_index, total_reports_for_file, total_reports) = {
         let mut reports = state.reports.write().await;
@@ -117,7 +200,16 @@ pub async fn report_handler(
         (idx, count_file, total)
     };
     state.persist_reports().await;
+    state.webhooks.emit(WebhookEvent::report_created(&record));
+    state.owner_events.publish(
+        &owner_hash,
+        OwnerEvent::FileReported {
+            file: record.file.clone(),
+            reason: record.reason.clone(),
+        },
+    );
     if let Some(tx) = &state.email_tx {
+        let privacy = state.email_privacy;
         let iso = OffsetDateTime::from_unix_timestamp(now as i64)
             .map(|t| {
                 t.format(&time::format_description::well_known::Rfc3339)
@@ -129,16 +221,18 @@ pub async fn report_handler(
                 file: record.file.clone(),
                 reason: record.reason.clone(),
                 details: record.details.clone(),
-                reporter_hash: record.reporter_hash.clone(),
+                reporter_hash: privacy.apply(&state, &record.reporter_hash),
                 time: record.time,
                 iso_time: iso,
-                owner_hash,
+                owner_hash: privacy.apply(&state, &owner_hash),
                 original_name,
+                name_warning,
                 expires,
                 size,
                 report_index,
                 total_reports_for_file,
                 total_reports,
+                identifiers_redacted: privacy.redacts(),
             })
             .await
         {
@@ -0,0 +1,962 @@
+//! A minimal S3-compatible API under `/s3/` so rclone, restic and other S3
+//! tooling can store files without a bespoke client. Every API token owns one
+//! bucket, named after the token id. Requests are signed with SigV4 using the
+//! token id as the access key id and [`s3_secret_access_key`] as the secret.
+//!
+//! Objects are ordinary files owned by the token, with the S3 key kept as the
+//! original name, so TTLs, quotas, screening and `/list` all apply as usual.
+//! Only path-style `PutObject`, `GetObject` (with a single byte range),
+//! `HeadObject`, `DeleteObject`, `HeadBucket` and `ListObjectsV2` exist.
+
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Path, Query, State};
+use axum::http::header::{
+    ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, HOST, LAST_MODIFIED, RANGE,
+};
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use hmac::{Hmac, Mac};
+use http_body_util::BodyExt;
+use mime_guess::MimeGuess;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::net::SocketAddr as ClientAddr;
+use std::ops::Bound;
+use std::time::{Duration, UNIX_EPOCH};
+use time::{Date, Month, OffsetDateTime, PrimitiveDateTime, Time};
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, error, info, warn};
+
+use crate::content_scan::{Rejection, ScanContent, ScanInput};
+use crate::file_store::sigv4_signature;
+use crate::handlers::upload::SpooledField;
+use crate::quarantine::Screening;
+use crate::request_id::current_request_id;
+use crate::state::{
+    ApiToken, AppState, FileMeta, NewFileBody, cleanup_expired, spawn_integrity_check,
+};
+use crate::ttl_policy::ClientAttributes;
+use crate::upload_stats::UploadMethod;
+use crate::util::{
+    MAX_ACTIVE_FILES_PER_IP, display_original_name, is_forbidden_extension, make_storage_name,
+    max_file_bytes, real_client_ip,
+};
+use crate::webhooks::{DELETED_BY_OWNER, WebhookEvent};
+
+/// Furthest `x-amz-date` may be from our clock, as on AWS.
+const MAX_CLOCK_SKEW_SECS: u64 = 15 * 60;
+/// Most entries one `ListObjectsV2` page returns.
+const MAX_LIST_KEYS: usize = 1000;
+/// Longest object key S3 allows, in bytes.
+const MAX_KEY_BYTES: usize = 1024;
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+const CONTENT_SHA256: &str = "x-amz-content-sha256";
+const AMZ_DATE: &str = "x-amz-date";
+/// Requested lifetime of a stored object, as a TTL code.
+const META_TTL: &str = "x-amz-meta-ttl";
+const XML_HEADER: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// SigV4 secret access key for the token `token_id`. Like presigned links it
+/// is keyed with the IP hashing secret, so it is never stored and changes if
+/// that secret does.
+pub fn s3_secret_access_key(state: &AppState, token_id: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(state.ip_hash_secret.as_slice())
+        .expect("HMAC key initialization should accept arbitrary key length");
+    mac.update(b"s3\n");
+    mac.update(token_id.as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn s3_error(status: StatusCode, code: &'static str, message: &'static str) -> Response {
+    let body = format!(
+        "{XML_HEADER}<Error><Code>{code}</Code><Message>{message}</Message><RequestId>{}</RequestId></Error>",
+        current_request_id().unwrap_or_default()
+    );
+    (status, [(CONTENT_TYPE, "application/xml")], body).into_response()
+}
+
+/// The S3 form of a content scanner's [`Rejection`].
+fn rejection_error(rejection: Rejection) -> Response {
+    let code = match rejection.status {
+        StatusCode::FORBIDDEN => "AccessDenied",
+        StatusCode::SERVICE_UNAVAILABLE => "ServiceUnavailable",
+        status if status.is_server_error() => "InternalError",
+        _ => "InvalidArgument",
+    };
+    s3_error(rejection.status, code, rejection.message)
+}
+
+fn xml(text: &str) -> String {
+    htmlescape::encode_minimal(text)
+}
+
+/// The parts of an `AWS4-HMAC-SHA256` `Authorization` header.
+struct SigV4Auth<'a> {
+    access_key: &'a str,
+    date: &'a str,
+    region: &'a str,
+    signed_headers: &'a str,
+    signature: &'a str,
+}
+
+fn parse_authorization(value: &str) -> Option<SigV4Auth<'_>> {
+    let rest = value.strip_prefix("AWS4-HMAC-SHA256 ")?;
+    let (mut credential, mut signed_headers, mut signature) = (None, None, None);
+    for part in rest.split(',') {
+        match part.trim().split_once('=')? {
+            ("Credential", value) => credential = Some(value),
+            ("SignedHeaders", value) => signed_headers = Some(value),
+            ("Signature", value) => signature = Some(value),
+            _ => {}
+        }
+    }
+    let mut scope = credential?.split('/');
+    let access_key = scope.next()?;
+    let date = scope.next()?;
+    let region = scope.next()?;
+    if scope.next()? != "s3" || scope.next()? != "aws4_request" || scope.next().is_some() {
+        return None;
+    }
+    Some(SigV4Auth {
+        access_key,
+        date,
+        region,
+        signed_headers: signed_headers?,
+        signature: signature?,
+    })
+}
+
+/// Seconds since the epoch for an `x-amz-date` such as `20130524T000000Z`.
+fn parse_amz_date(value: &str) -> Option<u64> {
+    if value.len() != 16 || !value.is_ascii() || &value[8..9] != "T" || &value[15..] != "Z" {
+        return None;
+    }
+    let num = |from: usize, to: usize| value[from..to].parse::<u16>().ok();
+    let date = Date::from_calendar_date(
+        i32::from(num(0, 4)?),
+        Month::try_from(u8::try_from(num(4, 6)?).ok()?).ok()?,
+        u8::try_from(num(6, 8)?).ok()?,
+    )
+    .ok()?;
+    let time = Time::from_hms(
+        u8::try_from(num(9, 11)?).ok()?,
+        u8::try_from(num(11, 13)?).ok()?,
+        u8::try_from(num(13, 15)?).ok()?,
+    )
+    .ok()?;
+    u64::try_from(
+        PrimitiveDateTime::new(date, time)
+            .assume_utc()
+            .unix_timestamp(),
+    )
+    .ok()
+}
+
+/// Percent-encode the way SigV4 wants: everything but unreserved characters.
+fn aws_encode(raw: &str) -> String {
+    let decoded = urlencoding::decode(raw)
+        .map(|s| s.into_owned())
+        .unwrap_or_else(|_| raw.to_string());
+    urlencoding::encode(&decoded).into_owned()
+}
+
+/// The query string in SigV4 canonical form: names and values re-encoded,
+/// then sorted.
+fn canonical_query(raw: Option<&str>) -> String {
+    let mut pairs: Vec<(String, String)> = raw
+        .unwrap_or_default()
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (aws_encode(name), aws_encode(value))
+        })
+        .collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// `name:value` lines for every signed header. HTTP/2 requests carry the
+/// host in the URI rather than a `Host` header.
+fn canonical_headers(headers: &HeaderMap, uri: &Uri, signed: &str) -> Option<String> {
+    let mut lines = String::new();
+    for name in signed.split(';') {
+        let mut values = Vec::new();
+        for value in headers.get_all(name) {
+            let value = value.to_str().ok()?;
+            values.push(value.split_whitespace().collect::<Vec<_>>().join(" "));
+        }
+        if values.is_empty() && name == HOST.as_str() {
+            values.push(uri.authority()?.to_string());
+        }
+        if values.is_empty() {
+            return None;
+        }
+        lines.push_str(&format!("{name}:{}\n", values.join(",")));
+    }
+    Some(lines)
+}
+
+fn same_signature(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Status, code and message of a refused request.
+type S3Failure = (StatusCode, &'static str, &'static str);
+
+/// Check the SigV4 signature on a request and return the token it was signed
+/// with. The payload hash is only compared against the body by
+/// [`s3_put_object_handler`], once the body has been read.
+fn authenticate(
+    state: &AppState,
+    method: &Method,
+    uri: &Uri,
+    headers: &HeaderMap,
+) -> Result<ApiToken, S3Failure> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let Some(auth) = header("authorization").and_then(parse_authorization) else {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "AccessDenied",
+            "a SigV4 Authorization header is required",
+        ));
+    };
+    let Some(token) = state.api_tokens.get(auth.access_key) else {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "InvalidAccessKeyId",
+            "the access key id is not a live api token",
+        ));
+    };
+    let amz_date = header(AMZ_DATE).unwrap_or_default();
+    let Some(signed_at) = parse_amz_date(amz_date).filter(|_| amz_date.starts_with(auth.date))
+    else {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "AccessDenied",
+            "x-amz-date is missing or does not match the credential scope",
+        ));
+    };
+    if signed_at.abs_diff(state.now_secs()) > MAX_CLOCK_SKEW_SECS {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "RequestTimeTooSkewed",
+            "the request time is too far from the server time",
+        ));
+    }
+    let Some(payload_hash) = header(CONTENT_SHA256) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "InvalidRequest",
+            "x-amz-content-sha256 header is required",
+        ));
+    };
+    let signed = auth.signed_headers;
+    let Some(canonical_headers) = canonical_headers(headers, uri, signed)
+        .filter(|_| signed.split(';').any(|name| name == HOST.as_str()))
+    else {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "AccessDenied",
+            "signed headers are missing from the request",
+        ));
+    };
+    let canonical_request = format!(
+        "{method}\n{}\n{}\n{canonical_headers}\n{signed}\n{payload_hash}",
+        uri.path(),
+        canonical_query(uri.query()),
+    );
+    let scope = format!("{}/{}/s3/aws4_request", auth.date, auth.region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{:x}",
+        Sha256::digest(canonical_request.as_bytes())
+    );
+    let expected = sigv4_signature(
+        &s3_secret_access_key(state, &token.id),
+        auth.date,
+        auth.region,
+        &string_to_sign,
+    );
+    if !same_signature(&expected, auth.signature) {
+        debug!(token_id = %token.id, "s3 request rejected: signature mismatch");
+        return Err((
+            StatusCode::FORBIDDEN,
+            "SignatureDoesNotMatch",
+            "the request signature does not match",
+        ));
+    }
+    Ok(token)
+}
+
+/// Ban, signature and bucket checks shared by every operation. Returns the
+/// client address and the token the request acts for.
+async fn authorize(
+    state: &AppState,
+    addr: &ClientAddr,
+    method: &Method,
+    uri: &Uri,
+    headers: &HeaderMap,
+    bucket: &str,
+) -> Result<(String, ApiToken), Response> {
+    let client_ip = real_client_ip(headers, addr);
+    if state.is_banned(&client_ip).await {
+        warn!(%client_ip, "s3 request rejected: banned ip");
+        return Err(s3_error(StatusCode::FORBIDDEN, "AccessDenied", "ip banned"));
+    }
+    let token = authenticate(state, method, uri, headers).map_err(|(status, code, message)| {
+        warn!(%client_ip, code, "s3 request rejected: authentication failed");
+        s3_error(status, code, message)
+    })?;
+    if token.id != bucket {
+        return Err(s3_error(
+            StatusCode::NOT_FOUND,
+            "NoSuchBucket",
+            "each api token only has the bucket named after its id",
+        ));
+    }
+    Ok((client_ip, token))
+}
+
+/// Live files of `owner_hash` stored under `key`, newest first.
+fn objects_named(
+    state: &AppState,
+    owner_hash: &str,
+    key: &str,
+    now: u64,
+) -> Vec<(String, FileMeta)> {
+    let mut found: Vec<(String, FileMeta)> = state
+        .owners_snapshot()
+        .files_for(owner_hash)
+        .iter()
+        .filter(|(_, meta)| meta.original == key && meta.expires > now)
+        .cloned()
+        .collect();
+    found.sort_by_key(|(_, meta)| std::cmp::Reverse(meta.created));
+    found
+}
+
+/// Drop the files backing an overwritten or deleted object.
+async fn remove_objects(state: &AppState, objects: &[(String, FileMeta)]) {
+    for (file, _) in objects {
+        state.remove_owner(file);
+        state
+            .webhooks
+            .emit(WebhookEvent::file_deleted(file, DELETED_BY_OWNER));
+        if let Err(err) = state.file_store.delete(file).await {
+            warn!(
+                ?err,
+                file, "failed to remove replaced s3 object from storage"
+            );
+        }
+    }
+}
+
+fn iso8601(secs: u64) -> String {
+    let at = OffsetDateTime::from_unix_timestamp(secs as i64).unwrap_or(OffsetDateTime::UNIX_EPOCH);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.000Z",
+        at.year(),
+        u8::from(at.month()),
+        at.day(),
+        at.hour(),
+        at.minute(),
+        at.second()
+    )
+}
+
+/// The inclusive byte range a `Range: bytes=…` header asks for. Malformed
+/// headers and multiple ranges fall back to the whole object; `Err` means
+/// the range lies outside it.
+fn requested_range(headers: &HeaderMap, len: u64) -> Result<Option<(u64, u64)>, ()> {
+    let Some(spec) = headers
+        .get(RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().strip_prefix("bytes="))
+        .filter(|spec| !spec.contains(','))
+    else {
+        return Ok(None);
+    };
+    let Some((start, end)) = spec.split_once('-') else {
+        return Ok(None);
+    };
+    let last = len.checked_sub(1);
+    let range = match (start.trim(), end.trim()) {
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => return Err(()),
+            Ok(n) => last.map(|last| (len.saturating_sub(n), last)),
+            Err(_) => return Ok(None),
+        },
+        (start, end) => {
+            let Ok(start) = start.parse::<u64>() else {
+                return Ok(None);
+            };
+            let end = if end.is_empty() {
+                last
+            } else {
+                match end.parse::<u64>() {
+                    Ok(end) if end >= start => last.map(|last| end.min(last)),
+                    _ => return Ok(None),
+                }
+            };
+            end.map(|end| (start, end))
+        }
+    };
+    match range {
+        Some((start, end)) if start <= end => Ok(Some((start, end))),
+        _ => Err(()),
+    }
+}
+
+#[axum::debug_handler]
+#[tracing::instrument(name = "s3.put_object", skip(state, headers, body), fields(bucket = %bucket, key = %key))]
+pub async fn s3_put_object_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<ClientAddr>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    Path((bucket, key)): Path<(String, String)>,
+    mut body: Body,
+) -> Response {
+    let (client_ip, token) = match authorize(&state, &addr, &method, &uri, &headers, &bucket).await
+    {
+        Ok(found) => found,
+        Err(resp) => return resp,
+    };
+    if headers.contains_key("x-amz-copy-source") {
+        return s3_error(
+            StatusCode::NOT_IMPLEMENTED,
+            "NotImplemented",
+            "CopyObject is not supported",
+        );
+    }
+    let payload_hash = headers
+        .get(CONTENT_SHA256)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or(UNSIGNED_PAYLOAD)
+        .to_string();
+    if payload_hash.starts_with("STREAMING-") {
+        return s3_error(
+            StatusCode::NOT_IMPLEMENTED,
+            "NotImplemented",
+            "aws-chunked uploads are not supported; send UNSIGNED-PAYLOAD or the payload hash",
+        );
+    }
+    if key.len() > MAX_KEY_BYTES {
+        return s3_error(
+            StatusCode::BAD_REQUEST,
+            "KeyTooLongError",
+            "object key is longer than 1024 bytes",
+        );
+    }
+    if is_forbidden_extension(&key) {
+        warn!(%client_ip, "s3 put rejected: forbidden file extension");
+        return s3_error(
+            StatusCode::BAD_REQUEST,
+            "InvalidArgument",
+            "File type not allowed",
+        );
+    }
+    if state.drain.is_draining() {
+        warn!(%client_ip, "s3 put rejected: server draining");
+        return s3_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "ServiceUnavailable",
+            "server is restarting, try again shortly",
+        );
+    }
+    if state.storage.is_full() {
+        warn!(%client_ip, "s3 put rejected: storage full");
+        return s3_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "ServiceUnavailable",
+            "server storage is full, try again later",
+        );
+    }
+    let (mut spooled, mut file) = match SpooledField::create(&state, Some(key.clone())).await {
+        Ok(created) => created,
+        Err(err) => {
+            error!(?err, "failed to create spooled s3 object");
+            return s3_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "InternalError",
+                "failed to store object",
+            );
+        }
+    };
+    let mut hasher = Sha256::new();
+    while let Some(frame) = body.frame().await {
+        let Ok(frame) = frame else {
+            return s3_error(
+                StatusCode::BAD_REQUEST,
+                "IncompleteBody",
+                "the request body ended early",
+            );
+        };
+        let Ok(data) = frame.into_data() else {
+            continue;
+        };
+        if let Err(err) = spooled.push(&mut file, &mut hasher, &data).await {
+            error!(?err, path = ?spooled.path, "failed writing spooled s3 object");
+            return s3_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "InternalError",
+                "failed to store object",
+            );
+        }
+        if spooled.size > max_file_bytes() {
+            return s3_error(
+                StatusCode::BAD_REQUEST,
+                "EntityTooLarge",
+                "object exceeds configured max size",
+            );
+        }
+    }
+    if let Err(err) = file.flush().await {
+        error!(?err, path = ?spooled.path, "failed flushing spooled s3 object");
+        return s3_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "InternalError",
+            "failed to store object",
+        );
+    }
+    drop(file);
+    let hash = format!("{:x}", hasher.finalize());
+    if payload_hash != UNSIGNED_PAYLOAD && !payload_hash.eq_ignore_ascii_case(&hash) {
+        warn!(%client_ip, "s3 put rejected: payload hash mismatch");
+        return s3_error(
+            StatusCode::BAD_REQUEST,
+            "XAmzContentSHA256Mismatch",
+            "the body does not match x-amz-content-sha256",
+        );
+    }
+    let scanned = state
+        .content_scanners
+        .scan(&ScanInput {
+            name: Some(&key),
+            sample: &spooled.sniff,
+            size: spooled.size,
+            content: ScanContent::File(&spooled.path),
+        })
+        .await;
+    if let Err(rejection) = scanned {
+        warn!(%client_ip, scanner = rejection.scanner, "s3 put rejected by content scanner");
+        return rejection_error(rejection);
+    }
+
+    cleanup_expired(&state).await;
+    let now = state.now_secs();
+    let owner_hash = token.owner_hash;
+    let size = spooled.size;
+    let replaced = objects_named(&state, &owner_hash, &key, now);
+    if replaced.is_empty() && state.remaining_file_slots(&owner_hash, now) == 0 {
+        warn!(owner_hash = %owner_hash, "s3 put rejected: active file limit reached");
+        return s3_error(
+            StatusCode::FORBIDDEN,
+            "QuotaExceeded",
+            "active file limit reached",
+        );
+    }
+    let freed: u64 = replaced.iter().map(|(_, meta)| meta.size).sum();
+    if !state.owner_quota_allows(&owner_hash, size.saturating_sub(freed), now) {
+        warn!(owner_hash = %owner_hash, "s3 put rejected: owner quota reached");
+        return s3_error(
+            StatusCode::FORBIDDEN,
+            "QuotaExceeded",
+            "storage quota reached",
+        );
+    }
+    let storage_name = match state
+        .store_new_file(
+            make_storage_name(Some(&key)),
+            NewFileBody::Spooled(&spooled.path),
+        )
+        .await
+    {
+        Ok(name) => name,
+        Err(err) => {
+            error!(?err, owner_hash = %owner_hash, "failed to write s3 object");
+            return s3_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "InternalError",
+                "failed to store object",
+            );
+        }
+    };
+    let attrs = ClientAttributes::from_request(&state, &headers, &addr, &client_ip);
+    let requested_ttl = headers.get(META_TTL).and_then(|v| v.to_str().ok());
+    let ttl_policy = state.ttl_policy.resolve(&attrs, requested_ttl, "24h");
+    let public = headers
+        .get("x-amz-acl")
+        .is_some_and(|v| v.as_bytes() == b"public-read");
+    state.insert_owner(
+        storage_name.clone(),
+        FileMeta {
+            hash: hash.clone(),
+            created: now,
+            expires: now + ttl_policy.ttl,
+            owner_hash: owner_hash.clone(),
+            original: key.clone(),
+            original_display: display_original_name(&key),
+            max_downloads: None,
+            downloads: 0,
+            private: !public,
+            ttl_shortened_from: None,
+            guest: false,
+            network_class: Some(attrs.network),
+            size,
+        },
+    );
+    remove_objects(&state, &replaced).await;
+    if state.reserved_file_slots(&owner_hash, state.now_secs()) > MAX_ACTIVE_FILES_PER_IP {
+        state.remove_owner(&storage_name);
+        let _ = state.file_store.delete(&storage_name).await;
+        state.persist_owners().await;
+        warn!(owner_hash = %owner_hash, file = %storage_name, "s3 put rejected: active file limit reached (post-write)");
+        return s3_error(
+            StatusCode::FORBIDDEN,
+            "QuotaExceeded",
+            "active file limit reached",
+        );
+    }
+    info!(owner_hash = %owner_hash, file = %storage_name, size, "s3 object stored");
+    state.storage.record_stored(size);
+    state.transparency.record(&hash, size).await;
+    match state.screen_upload(&storage_name, &hash).await {
+        Screening::Passed => {
+            state.notify_upload_completed(&storage_name);
+            state.record_upload_client(&headers, UploadMethod::S3, size, None);
+        }
+        Screening::Held => {
+            state.persist_owners().await;
+            return s3_error(
+                StatusCode::FORBIDDEN,
+                "AccessDenied",
+                "upload was not accepted",
+            );
+        }
+        Screening::Infected(signature) => {
+            state.persist_owners().await;
+            warn!(owner_hash = %owner_hash, file = %storage_name, %signature, "s3 put rejected: virus detected");
+            return s3_error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "VirusDetected",
+                "upload rejected: a virus was detected",
+            );
+        }
+    }
+    state.persist_owners().await;
+    spawn_integrity_check(state.clone());
+    let mut resp = StatusCode::OK.into_response();
+    if let Ok(etag) = HeaderValue::from_str(&format!("\"{hash}\"")) {
+        resp.headers_mut().insert(ETAG, etag);
+    }
+    resp
+}
+
+#[axum::debug_handler]
+#[tracing::instrument(name = "s3.get_object", skip(state, headers), fields(bucket = %bucket, key = %key))]
+pub async fn s3_get_object_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<ClientAddr>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    Path((bucket, key)): Path<(String, String)>,
+) -> Response {
+    let (_, token) = match authorize(&state, &addr, &method, &uri, &headers, &bucket).await {
+        Ok(found) => found,
+        Err(resp) => return resp,
+    };
+    cleanup_expired(&state).await;
+    let now = state.now_secs();
+    let no_such_key = || s3_error(StatusCode::NOT_FOUND, "NoSuchKey", "object not found");
+    let Some((file, meta)) = objects_named(&state, &token.owner_hash, &key, now)
+        .into_iter()
+        .next()
+    else {
+        return no_such_key();
+    };
+    let mut out = HeaderMap::new();
+    let mime = MimeGuess::from_path(&key).first_or_octet_stream();
+    if let Ok(value) = HeaderValue::from_str(mime.as_ref()) {
+        out.insert(CONTENT_TYPE, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&format!("\"{}\"", meta.hash)) {
+        out.insert(ETAG, value);
+    }
+    let modified = UNIX_EPOCH + Duration::from_secs(meta.created);
+    if let Ok(value) = HeaderValue::from_str(&httpdate::fmt_http_date(modified)) {
+        out.insert(LAST_MODIFIED, value);
+    }
+    out.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if method == Method::HEAD {
+        return match state.file_store.size(&file).await {
+            Ok(Some(size)) => {
+                out.insert(CONTENT_LENGTH, HeaderValue::from(size));
+                (StatusCode::OK, out).into_response()
+            }
+            Ok(None) => no_such_key(),
+            Err(err) => {
+                error!(?err, file = %file, "failed to stat s3 object");
+                s3_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "InternalError",
+                    "cant read object",
+                )
+            }
+        };
+    }
+    let bytes = match state.file_store.read(&file).await {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => {
+            warn!(file = %file, "s3 object missing from storage");
+            return no_such_key();
+        }
+        Err(err) => {
+            error!(?err, file = %file, "failed to read s3 object");
+            return s3_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "InternalError",
+                "cant read object",
+            );
+        }
+    };
+    let len = bytes.len() as u64;
+    match requested_range(&headers, len) {
+        Ok(None) => (StatusCode::OK, out, bytes).into_response(),
+        Ok(Some((start, end))) => {
+            if let Ok(value) = HeaderValue::from_str(&format!("bytes {start}-{end}/{len}")) {
+                out.insert(CONTENT_RANGE, value);
+            }
+            let part = bytes.slice(start as usize..=end as usize);
+            (StatusCode::PARTIAL_CONTENT, out, part).into_response()
+        }
+        Err(()) => s3_error(
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            "InvalidRange",
+            "the requested range is not satisfiable",
+        ),
+    }
+}
+
+#[axum::debug_handler]
+#[tracing::instrument(name = "s3.delete_object", skip(state, headers), fields(bucket = %bucket, key = %key))]
+pub async fn s3_delete_object_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<ClientAddr>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    Path((bucket, key)): Path<(String, String)>,
+) -> Response {
+    let (client_ip, token) = match authorize(&state, &addr, &method, &uri, &headers, &bucket).await
+    {
+        Ok(found) => found,
+        Err(resp) => return resp,
+    };
+    let objects = objects_named(&state, &token.owner_hash, &key, state.now_secs());
+    // Deleting a missing key succeeds, as on S3.
+    if !objects.is_empty() {
+        remove_objects(&state, &objects).await;
+        state.persist_owners().await;
+        info!(%client_ip, owner_hash = %token.owner_hash, "s3 object deleted");
+    }
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// `ListObjectsV2` parameters. Anything else on a bucket `GET` is refused.
+#[derive(Deserialize, Default, Debug)]
+pub struct ListObjectsQuery {
+    #[serde(rename = "list-type")]
+    pub list_type: Option<String>,
+    #[serde(default)]
+    pub prefix: String,
+    #[serde(default)]
+    pub delimiter: String,
+    #[serde(rename = "max-keys")]
+    pub max_keys: Option<usize>,
+    #[serde(rename = "continuation-token")]
+    pub continuation_token: Option<String>,
+    #[serde(rename = "start-after")]
+    pub start_after: Option<String>,
+    #[serde(rename = "encoding-type")]
+    pub encoding_type: Option<String>,
+}
+
+/// `ListObjectsV2`, or `HeadBucket` when the method is `HEAD`. Continuation
+/// tokens are the last key or common prefix of the previous page.
+#[axum::debug_handler]
+#[tracing::instrument(name = "s3.list_objects", skip(state, headers), fields(bucket = %bucket))]
+pub async fn s3_list_objects_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<ClientAddr>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    Path(bucket): Path<String>,
+    Query(query): Query<ListObjectsQuery>,
+) -> Response {
+    let (_, token) = match authorize(&state, &addr, &method, &uri, &headers, &bucket).await {
+        Ok(found) => found,
+        Err(resp) => return resp,
+    };
+    if method == Method::HEAD {
+        return StatusCode::OK.into_response();
+    }
+    if query.list_type.as_deref() != Some("2") {
+        return s3_error(
+            StatusCode::NOT_IMPLEMENTED,
+            "NotImplemented",
+            "only ListObjectsV2 (list-type=2) is supported",
+        );
+    }
+    let token_after = match query.continuation_token.as_deref() {
+        Some(raw) => match BASE64
+            .decode(raw)
+            .ok()
+            .and_then(|b| String::from_utf8(b).ok())
+        {
+            Some(after) => Some(after),
+            None => {
+                return s3_error(
+                    StatusCode::BAD_REQUEST,
+                    "InvalidArgument",
+                    "the continuation token is not valid",
+                );
+            }
+        },
+        None => None,
+    };
+    cleanup_expired(&state).await;
+    let now = state.now_secs();
+    let snapshot = state.owners_snapshot();
+    // Newest file per key, so the listing agrees with GetObject.
+    let mut objects: BTreeMap<&str, &FileMeta> = BTreeMap::new();
+    for (_, meta) in snapshot.files_for(&token.owner_hash) {
+        if meta.expires <= now || meta.original.is_empty() {
+            continue;
+        }
+        let newest = objects
+            .get(meta.original.as_str())
+            .is_none_or(|seen| seen.created < meta.created);
+        if newest {
+            objects.insert(meta.original.as_str(), meta);
+        }
+    }
+
+    let prefix = query.prefix.as_str();
+    let delimiter = query.delimiter.as_str();
+    let max_keys = query.max_keys.unwrap_or(MAX_LIST_KEYS).min(MAX_LIST_KEYS);
+    let after = token_after
+        .as_deref()
+        .or(query.start_after.as_deref())
+        .unwrap_or_default();
+    // A page that ended on a common prefix resumes past everything under it.
+    let skip_under = (token_after.is_some() && !delimiter.is_empty() && after.ends_with(delimiter))
+        .then_some(after);
+    let mut contents: Vec<(&str, &FileMeta)> = Vec::new();
+    let mut common_prefixes: Vec<&str> = Vec::new();
+    let mut last = None;
+    let mut truncated = false;
+    for (&key, &meta) in objects.range::<&str, _>((Bound::Excluded(after), Bound::Unbounded)) {
+        if !key.starts_with(prefix) || skip_under.is_some_and(|under| key.starts_with(under)) {
+            continue;
+        }
+        let rolled_up = (!delimiter.is_empty())
+            .then(|| key[prefix.len()..].find(delimiter))
+            .flatten()
+            .map(|at| &key[..prefix.len() + at + delimiter.len()]);
+        if rolled_up.is_some() && rolled_up == common_prefixes.last().copied() {
+            continue;
+        }
+        if contents.len() + common_prefixes.len() >= max_keys {
+            truncated = true;
+            break;
+        }
+        match rolled_up {
+            Some(common) => {
+                common_prefixes.push(common);
+                last = Some(common);
+            }
+            None => {
+                contents.push((key, meta));
+                last = Some(key);
+            }
+        }
+    }
+
+    let url_encoded = query.encoding_type.as_deref() == Some("url");
+    let encode = |text: &str| {
+        if url_encoded {
+            text.split('/')
+                .map(|part| urlencoding::encode(part).into_owned())
+                .collect::<Vec<_>>()
+                .join("/")
+        } else {
+            xml(text)
+        }
+    };
+    let mut body = format!(
+        "{XML_HEADER}<ListBucketResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\"><Name>{}</Name><Prefix>{}</Prefix><KeyCount>{}</KeyCount><MaxKeys>{max_keys}</MaxKeys><IsTruncated>{truncated}</IsTruncated>",
+        xml(&bucket),
+        encode(prefix),
+        contents.len() + common_prefixes.len(),
+    );
+    if !delimiter.is_empty() {
+        body.push_str(&format!("<Delimiter>{}</Delimiter>", encode(delimiter)));
+    }
+    if url_encoded {
+        body.push_str("<EncodingType>url</EncodingType>");
+    }
+    if let Some(token) = &query.continuation_token {
+        body.push_str(&format!(
+            "<ContinuationToken>{}</ContinuationToken>",
+            xml(token)
+        ));
+    }
+    if let Some(start_after) = &query.start_after {
+        body.push_str(&format!("<StartAfter>{}</StartAfter>", encode(start_after)));
+    }
+    if truncated && let Some(last) = last {
+        body.push_str(&format!(
+            "<NextContinuationToken>{}</NextContinuationToken>",
+            BASE64.encode(last)
+        ));
+    }
+    for (key, meta) in &contents {
+        body.push_str(&format!(
+            "<Contents><Key>{}</Key><LastModified>{}</LastModified><ETag>&quot;{}&quot;</ETag><Size>{}</Size><StorageClass>STANDARD</StorageClass></Contents>",
+            encode(key),
+            iso8601(meta.created),
+            xml(&meta.hash),
+            meta.size,
+        ));
+    }
+    for common in &common_prefixes {
+        body.push_str(&format!(
+            "<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>",
+            encode(common)
+        ));
+    }
+    body.push_str("</ListBucketResult>");
+    (StatusCode::OK, [(CONTENT_TYPE, "application/xml")], body).into_response()
+}
@@ -100,43 +100,47 @@ pub async fn ban_gate(
         return next.run(req).await;
     }
     warn!(%ip, path, "ban gate blocked request");
-    let (reason, time, label) = match state.find_ban_for_input(&ip).await {
-        Some(ban) => (ban.reason.clone(), ban.time, ban_label(&ban)),
+    let (reason, time, expires, label) = match state.find_ban_for_input(&ip).await {
+        Some(ban) => {
+            state.ban_hits.record(&ban.subject.key(), state.now_secs());
+            (ban.reason.clone(), ban.time, ban.expires, ban_label(&ban))
+        }
         #[allow(non_snake_case)]
-        None => (String::new(), 0, short_hash(&ip)),
+        None => (String::new(), 0, None, short_hash(&ip)),
     };
     let safe_reason = htmlescape::encode_minimal(&reason);
-    let time_line = if time > 0 {
+    let mut time_line = if time > 0 {
         format!("<br><span class=code>Time: {time}</span>")
     } else {
         String::new()
     };
+    if let Some(expires) = expires {
+        time_line.push_str(&format!("<br><span class=code>Until: {expires}</span>"));
+    }
     let mut ctx = Context::new();
     ctx.insert("IP", &label);
     ctx.insert("REASON", &safe_reason);
     ctx.insert("TIME_LINE", &time_line);
     debug!(%ip, reason = %safe_reason, "rendering banned template via tera");
     match state.tera.render("banned.html.tera", &ctx) {
-        Ok(body) => {
-            return (
-                StatusCode::FORBIDDEN,
-                [(CONTENT_TYPE, HeaderValue::from_static("text/html"))],
-                body,
-            )
-                .into_response();
-        }
+        Ok(body) => (
+            StatusCode::FORBIDDEN,
+            [(CONTENT_TYPE, HeaderValue::from_static("text/html"))],
+            body,
+        )
+            .into_response(),
         Err(e) => {
             warn!(%ip, error = ?e, "failed to render banned template, serving fallback");
             let fallback = format!(
                 "<html><body><h1>Banned</h1><p>{}</p><p>{}</p></body></html>",
                 safe_reason, label
             );
-            return (
+            (
                 StatusCode::FORBIDDEN,
                 [(CONTENT_TYPE, HeaderValue::from_static("text/html"))],
                 fallback,
             )
-                .into_response();
+                .into_response()
         }
     }
 }
@@ -165,7 +169,7 @@ fn ban_label(ban: &IpBan) -> String {
     if let Some(label) = ban.label.as_ref().filter(|l| !l.trim().is_empty()) {
         return label.trim().to_string();
     }
-    short_hash(ban.subject.key())
+    short_hash(&ban.subject.key())
 }
 
 fn short_hash(value: &str) -> String {
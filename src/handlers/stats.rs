@@ -0,0 +1,205 @@
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::http::header::CACHE_CONTROL;
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use rand::Rng;
+use serde::Serialize;
+use std::collections::HashSet;
+use tokio::sync::RwLock;
+use tracing::{debug, trace, warn};
+
+use crate::connections::ConnectionStats;
+use crate::handlers::web::{LangQuery, render_tera_page};
+use crate::state::AppState;
+use crate::upload_stats::UploadStatsSnapshot;
+use crate::util::{get_cookie, json_error};
+
+/// Public figures are recomputed at most once per window. Holding the jittered
+/// values fixed stops a client from averaging repeated requests to strip the
+/// noise back out.
+pub const PUBLIC_STATS_WINDOW: u64 = 15 * 60;
+/// File counts are published as multiples of this.
+pub const FILE_COUNT_GRANULARITY: u64 = 10;
+
+const STORAGE_BUCKETS: &[(u64, &str)] = &[
+    (100 * 1024 * 1024, "under 100 MB"),
+    (1024 * 1024 * 1024, "100 MB – 1 GB"),
+    (10 * 1024 * 1024 * 1024, "1 – 10 GB"),
+    (100 * 1024 * 1024 * 1024, "10 – 100 GB"),
+    (1024 * 1024 * 1024 * 1024, "100 GB – 1 TB"),
+];
+const STORAGE_TOP_BUCKET: &str = "over 1 TB";
+
+/// Exact figures, only ever shown to admins.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct AdminStats {
+    pub files: u64,
+    pub owners: u64,
+    pub storage_bytes: u64,
+    pub chunk_sessions: u64,
+    pub quarantined: u64,
+    pub uptime_secs: u64,
+    pub connections: ConnectionStats,
+    /// How completed uploads were made, for tuning client defaults.
+    pub uploads: UploadStatsSnapshot,
+}
+
+/// Coarse, jittered view of [`AdminStats`] that is safe to publish.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct PublicStats {
+    pub files_hosted: u64,
+    pub storage_used: &'static str,
+    pub uptime_hours: u64,
+    pub generated: u64,
+}
+
+#[derive(Default)]
+pub struct PublicStatsCache {
+    current: RwLock<Option<PublicStats>>,
+}
+
+pub async fn admin_stats(state: &AppState) -> AdminStats {
+    let mut files = 0u64;
+    let mut storage_bytes = 0u64;
+    let mut owners = HashSet::new();
+    // Recorded sizes rather than a stat per file, so this never waits on
+    // storage; entries the storage sweep has not sized yet count as 0.
+    for entry in state.owners.iter() {
+        let meta = entry.value();
+        files += 1;
+        storage_bytes += meta.size;
+        owners.insert(meta.owner_hash.clone());
+    }
+    AdminStats {
+        files,
+        owners: owners.len() as u64,
+        storage_bytes,
+        chunk_sessions: state.chunk_sessions.len() as u64,
+        quarantined: state.quarantine.records().await.len() as u64,
+        uptime_secs: state.now_secs().saturating_sub(state.started_at),
+        connections: state.connections.stats(),
+        uploads: state.upload_stats.snapshot(),
+    }
+}
+
+/// Round `value` to the nearest `granularity` after adding uniform noise of up
+/// to half a step either way, so small changes (one upload) rarely move it.
+pub fn jittered_round(value: u64, granularity: u64, rng: &mut impl Rng) -> u64 {
+    let half = (granularity / 2) as i64;
+    let noisy = (value as i64 + rng.gen_range(-half..=half)).max(0) as u64;
+    (noisy + granularity / 2) / granularity * granularity
+}
+
+pub fn storage_bucket(bytes: u64) -> &'static str {
+    STORAGE_BUCKETS
+        .iter()
+        .find(|(limit, _)| bytes < *limit)
+        .map(|(_, label)| *label)
+        .unwrap_or(STORAGE_TOP_BUCKET)
+}
+
+pub fn publish_stats(stats: &AdminStats, now: u64, rng: &mut impl Rng) -> PublicStats {
+    // Up to 5% noise before bucketing so a total sitting on a boundary does
+    // not flip buckets on a single upload.
+    let spread = stats.storage_bytes / 20;
+    let storage = stats
+        .storage_bytes
+        .saturating_add(rng.gen_range(0..=spread));
+    PublicStats {
+        files_hosted: jittered_round(stats.files, FILE_COUNT_GRANULARITY, rng),
+        storage_used: storage_bucket(storage.saturating_sub(spread / 2)),
+        uptime_hours: stats.uptime_secs / 3600,
+        generated: now - now % PUBLIC_STATS_WINDOW,
+    }
+}
+
+pub async fn public_stats(state: &AppState) -> PublicStats {
+    let now = state.now_secs();
+    if let Some(stats) = state.public_stats.current.read().await.as_ref()
+        && now < stats.generated + PUBLIC_STATS_WINDOW
+    {
+        trace!("serving cached public stats");
+        return stats.clone();
+    }
+    // Computed before taking the write lock so readers of the cached figures
+    // never wait on it; a concurrent refresh for the same window wins.
+    let exact = admin_stats(state).await;
+    let stats = publish_stats(&exact, now, &mut rand::thread_rng());
+    let mut current = state.public_stats.current.write().await;
+    if let Some(cached) = current.as_ref()
+        && now < cached.generated + PUBLIC_STATS_WINDOW
+    {
+        return cached.clone();
+    }
+    debug!(
+        files_hosted = stats.files_hosted,
+        storage = stats.storage_used,
+        "refreshed public stats"
+    );
+    *current = Some(stats.clone());
+    stats
+}
+
+pub async fn stats_json_handler(State(state): State<AppState>) -> Response {
+    let stats = public_stats(&state).await;
+    (
+        [(
+            CACHE_CONTROL,
+            HeaderValue::from_static("public, max-age=300"),
+        )],
+        Json(stats),
+    )
+        .into_response()
+}
+
+pub async fn stats_page_handler(
+    State(state): State<AppState>,
+    Query(query): Query<LangQuery>,
+) -> Response {
+    let lang = query.lang.as_deref().unwrap_or("en");
+    trace!(lang, "rendering stats page");
+    let stats = public_stats(&state).await;
+    let value = serde_json::to_value(&stats).unwrap_or_default();
+    render_tera_page(&state, "stats.html.tera", lang, Some(("stats", &value))).await
+}
+
+pub async fn admin_stats_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(tok) = get_cookie(&headers, "adm") {
+        if !state.is_admin(&tok).await {
+            warn!("admin stats access denied: invalid session");
+            return json_error(StatusCode::UNAUTHORIZED, "not_admin", "auth required");
+        }
+    } else {
+        warn!("admin stats access denied: missing session");
+        return json_error(StatusCode::UNAUTHORIZED, "not_admin", "auth required");
+    }
+    Json(admin_stats(&state).await).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn jitter_stays_on_grid_and_near_value() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..200 {
+            let out = jittered_round(137, FILE_COUNT_GRANULARITY, &mut rng);
+            assert_eq!(out % FILE_COUNT_GRANULARITY, 0);
+            assert!((130..=150).contains(&out), "{out}");
+        }
+        assert_eq!(jittered_round(0, FILE_COUNT_GRANULARITY, &mut rng) % 10, 0);
+    }
+
+    #[test]
+    fn storage_is_bucketed() {
+        assert_eq!(storage_bucket(0), "under 100 MB");
+        assert_eq!(storage_bucket(5 * 1024 * 1024 * 1024), "1 – 10 GB");
+        assert_eq!(storage_bucket(u64::MAX), STORAGE_TOP_BUCKET);
+    }
+}
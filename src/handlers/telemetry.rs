@@ -0,0 +1,235 @@
+use axum::body::Body;
+use axum::extract::{ConnectInfo, MatchedPath, State};
+use axum::http::{HeaderMap, HeaderValue, Method, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use tracing::{trace, warn};
+
+use crate::state::AppState;
+use crate::util::real_client_ip;
+
+/// Routes dropped from Sentry tracing unless `SENTRY_IGNORED_ROUTES` says
+/// otherwise. A trailing `*` matches any path with that prefix.
+pub const DEFAULT_IGNORED_ROUTES: &[&str] = &[
+    "/healthz",
+    "/readyz",
+    "/css/*",
+    "/js/*",
+    "/dist/*",
+    "/img/*",
+    "/favicon*",
+    "/robots.txt",
+    "/sitemap.xml",
+];
+
+/// Tag carrying the chunk session id on every transaction of a chunked upload.
+pub const UPLOAD_SESSION_TAG: &str = "upload.session";
+
+/// Length of the owner-hash prefix attached to transactions; enough to group
+/// by client without making the full identifier searchable.
+const OWNER_HASH_TAG_LEN: usize = 8;
+
+/// Request extension inserted when the client sent `Sec-GPC: 1` or `DNT: 1`
+/// and the policy honours those signals.
+#[derive(Clone, Copy, Debug)]
+pub struct PrivacyOptOut;
+
+pub fn has_privacy_signal(headers: &HeaderMap) -> bool {
+    ["sec-gpc", "dnt"].iter().any(|name| {
+        headers
+            .get(*name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim() == "1")
+            .unwrap_or(false)
+    })
+}
+
+pub fn route_is_ignored(path: &str, patterns: &[String]) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => path.starts_with(prefix),
+            None => path == pattern,
+        })
+}
+
+/// Upload flavour for transaction tagging, keyed on the matched route.
+pub fn upload_method(method: &Method, route: &str) -> Option<&'static str> {
+    match (method, route) {
+        (&Method::POST, "/upload") => Some("multipart"),
+        (&Method::POST, "/simple/upload") => Some("simple"),
+        (&Method::POST, "/api/paste-binary") => Some("paste"),
+        (&Method::POST, "/chunk/init")
+        | (&Method::PUT, "/chunk/{id}/{index}")
+        | (&Method::POST, "/chunk/{id}/complete") => Some("chunked"),
+        _ => None,
+    }
+}
+
+/// Session id for `/chunk/{id}/...` requests, excluding `/chunk/init`.
+pub fn chunk_session_id(path: &str) -> Option<&str> {
+    let rest = path.strip_prefix("/chunk/")?;
+    let (id, _) = rest.split_once('/')?;
+    (!id.is_empty() && id != "init").then_some(id)
+}
+
+/// `sentry-trace` value for the transaction or span active on this request.
+pub fn current_sentry_trace() -> Option<String> {
+    sentry::configure_scope(|scope| scope.get_span())
+        .and_then(|span| span.iter_headers().next().map(|(_, value)| value))
+}
+
+/// Convert a `sentry-trace` value into a W3C `traceparent` header value.
+pub fn sentry_trace_to_traceparent(sentry_trace: &str) -> Option<String> {
+    let mut parts = sentry_trace.split('-');
+    let trace_id = parts.next().filter(|t| t.len() == 32)?;
+    let span_id = parts.next().filter(|s| s.len() == 16)?;
+    let flags = if parts.next() == Some("1") {
+        "01"
+    } else {
+        "00"
+    };
+    Some(format!("00-{trace_id}-{span_id}-{flags}"))
+}
+
+/// Attach the chunk session id to the current request's transaction.
+pub fn tag_upload_session(session_id: &str) {
+    sentry::configure_scope(|scope| {
+        if let Some(span) = scope.get_span() {
+            span.set_tag(UPLOAD_SESSION_TAG, session_id);
+        }
+    });
+}
+
+/// Start a transaction for background work belonging to a chunked upload and
+/// bind it to the current hub, continuing the trace opened by `/chunk/init`.
+pub fn start_upload_transaction(
+    name: &str,
+    session_id: &str,
+    trace_parent: Option<&str>,
+) -> sentry::TransactionOrSpan {
+    let headers = trace_parent.map(|value| ("sentry-trace", value));
+    let ctx = sentry::TransactionContext::continue_from_headers(name, "upload.background", headers);
+    let transaction: sentry::TransactionOrSpan = sentry::start_transaction(ctx).into();
+    transaction.set_tag(UPLOAD_SESSION_TAG, session_id);
+    sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone())));
+    transaction
+}
+
+fn is_upload_request(method: &Method, path: &str) -> bool {
+    method == Method::POST && matches!(path, "/upload" | "/simple/upload" | "/chunk/init")
+}
+
+/// Incoming `sentry-trace` value carrying a negative sampling decision, so the
+/// transaction started by `SentryHttpLayer` for this request is discarded.
+fn unsampled_sentry_trace() -> String {
+    format!(
+        "{}-{}-0",
+        sentry::protocol::TraceId::default(),
+        sentry::protocol::SpanId::default()
+    )
+}
+
+fn suppress_transaction(req: &mut Request<Body>) {
+    match HeaderValue::from_str(&unsampled_sentry_trace()) {
+        Ok(value) => {
+            req.headers_mut().insert("sentry-trace", value);
+        }
+        Err(err) => warn!(?err, "failed to build unsampled sentry-trace header"),
+    }
+    req.headers_mut().remove("baggage");
+}
+
+/// Requests for an existing chunk session without their own trace header join
+/// the trace started at `/chunk/init`, inheriting its sampling decision.
+fn continue_chunk_trace(state: &AppState, req: &mut Request<Body>) {
+    if req.headers().contains_key("sentry-trace") {
+        return;
+    }
+    let Some(trace_parent) = chunk_session_id(req.uri().path())
+        .and_then(|id| state.chunk_sessions.get(id))
+        .and_then(|session| session.trace_parent.clone())
+    else {
+        return;
+    };
+    match HeaderValue::from_str(&trace_parent) {
+        Ok(value) => {
+            req.headers_mut().insert("sentry-trace", value);
+        }
+        Err(err) => warn!(?err, "stored chunk trace parent is not a valid header"),
+    }
+}
+
+/// Must be layered outside `SentryHttpLayer`: the transaction's sampling
+/// decision is taken from the request headers when it starts. Handles both
+/// client privacy signals and operator-configured noisy routes.
+pub async fn telemetry_gate(
+    State(state): State<AppState>,
+    mut req: Request<Body>,
+    next: Next,
+) -> Response {
+    let opted_out = state.telemetry.respect_privacy_signals && has_privacy_signal(req.headers());
+    if opted_out {
+        req.extensions_mut().insert(PrivacyOptOut);
+        suppress_transaction(&mut req);
+        trace!(path = %req.uri().path(), "privacy signal honoured; telemetry suppressed");
+    } else {
+        state.analytics.requests.fetch_add(1, Ordering::Relaxed);
+        if is_upload_request(req.method(), req.uri().path()) {
+            state.analytics.uploads.fetch_add(1, Ordering::Relaxed);
+        }
+        if route_is_ignored(req.uri().path(), &state.telemetry.ignored_routes) {
+            suppress_transaction(&mut req);
+        } else {
+            continue_chunk_trace(&state, &mut req);
+        }
+    }
+    let resp = next.run(req).await;
+    state
+        .trace_sampler
+        .record(state.now_secs(), resp.status().is_server_error());
+    resp
+}
+
+/// Runs inside the router where `MatchedPath` is known: renames the current
+/// transaction after the route template and attaches aggregation tags.
+pub async fn name_transaction(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string());
+    if let Some(route) = route.as_deref() {
+        tracing::Span::current().record("matched_path", route);
+        let owner_prefix = req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| real_client_ip(req.headers(), addr))
+            .and_then(|ip| state.hash_ip_to_string(&ip))
+            .map(|hash| hash.chars().take(OWNER_HASH_TAG_LEN).collect::<String>());
+        let upload = upload_method(req.method(), route);
+        let name = format!("{} {}", req.method(), route);
+        let sample_rate = state.trace_sampler.rate();
+        sentry::configure_scope(|scope| {
+            // The rate in force when this request arrived, on its events too.
+            scope.set_tag("traces_sample_rate", sample_rate);
+            if let Some(span) = scope.get_span() {
+                span.set_name(&name);
+                span.set_tag("http.route", route);
+                span.set_tag("traces_sample_rate", sample_rate);
+                if let Some(prefix) = owner_prefix.as_deref() {
+                    span.set_tag("owner_hash.prefix", prefix);
+                }
+                if let Some(method) = upload {
+                    span.set_tag("upload.method", method);
+                }
+            }
+        });
+    }
+    next.run(req).await
+}
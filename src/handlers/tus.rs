@@ -0,0 +1,422 @@
+//! tus 1.0 resumable uploads (<https://tus.io/protocols/resumable-upload>)
+//! layered on the chunk session machinery: creation registers a
+//! [`ChunkSession`], `PATCH` appends bytes into its chunk files, and the last
+//! byte runs the same assembly as `/chunk/{id}/complete`.
+
+use axum::body::{Body, to_bytes};
+use axum::extract::{ConnectInfo, Path, State};
+use axum::http::header::{ALLOW, CACHE_CONTROL, CONTENT_TYPE, LOCATION};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use http_body_util::BodyExt;
+use std::net::SocketAddr as ClientAddr;
+use std::sync::Arc;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, info, warn};
+
+use crate::handlers::upload::{
+    ChunkInitRequest, UploadResponse, create_chunk_session, expected_chunk_len,
+    finalize_chunk_session, flag_enabled, invalid_max_downloads, parse_max_downloads,
+    phase_conflict, request_owner, sync_stored_chunks,
+};
+use crate::i18n::Locale;
+use crate::state::{AppState, ChunkPhase, ChunkSession};
+use crate::ttl_policy::ClientAttributes;
+use crate::upload_stats::UploadMethod;
+use crate::util::{json_error, max_file_bytes, qualify_path, real_client_ip, share_path};
+
+pub const TUS_VERSION: &str = "1.0.0";
+pub const TUS_EXTENSIONS: &str = "creation,termination";
+
+const TUS_RESUMABLE: HeaderName = HeaderName::from_static("tus-resumable");
+const TUS_VERSION_HEADER: HeaderName = HeaderName::from_static("tus-version");
+const TUS_EXTENSION: HeaderName = HeaderName::from_static("tus-extension");
+const TUS_MAX_SIZE: HeaderName = HeaderName::from_static("tus-max-size");
+const UPLOAD_OFFSET: HeaderName = HeaderName::from_static("upload-offset");
+const UPLOAD_LENGTH: HeaderName = HeaderName::from_static("upload-length");
+const UPLOAD_METADATA: HeaderName = HeaderName::from_static("upload-metadata");
+/// Share URL of the finished file, sent with the `PATCH` that completes it.
+const JUICEBOX_FILE: HeaderName = HeaderName::from_static("x-juicebox-file");
+const OFFSET_OCTET_STREAM: &str = "application/offset+octet-stream";
+
+/// Stamp `Tus-Resumable` on every response, errors included.
+fn tus(mut resp: Response) -> Response {
+    resp.headers_mut()
+        .insert(TUS_RESUMABLE, HeaderValue::from_static(TUS_VERSION));
+    resp
+}
+
+fn empty(status: StatusCode) -> Response {
+    tus(status.into_response())
+}
+
+/// `None` when the client speaks our protocol version; otherwise the 412 to
+/// send back.
+fn check_version(headers: &HeaderMap) -> Option<Response> {
+    let version = headers.get(TUS_RESUMABLE).and_then(|v| v.to_str().ok());
+    if version == Some(TUS_VERSION) {
+        return None;
+    }
+    let mut resp = empty(StatusCode::PRECONDITION_FAILED);
+    resp.headers_mut()
+        .insert(TUS_VERSION_HEADER, HeaderValue::from_static(TUS_VERSION));
+    Some(resp)
+}
+
+fn header_u64(headers: &HeaderMap, name: &HeaderName) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+/// Decode `Upload-Metadata`: comma-separated `key base64(value)` pairs, where
+/// the value may be omitted.
+fn parse_metadata(raw: &str) -> Vec<(String, String)> {
+    raw.split(',')
+        .filter_map(|pair| {
+            let pair = pair.trim();
+            let (key, encoded) = pair.split_once(' ').unwrap_or((pair, ""));
+            if key.is_empty() {
+                return None;
+            }
+            let value = String::from_utf8(BASE64.decode(encoded.trim()).ok()?).ok()?;
+            Some((key.to_string(), value))
+        })
+        .collect()
+}
+
+/// Bytes received so far: every leading chunk marked received plus whatever
+/// has been appended to the next one.
+async fn upload_offset(session: &ChunkSession) -> u64 {
+    let done = session
+        .received
+        .read()
+        .await
+        .iter()
+        .take_while(|r| **r)
+        .count() as u32;
+    if done >= session.total_chunks {
+        return session.total_bytes;
+    }
+    let partial = fs::metadata(session.chunk_path(done))
+        .await
+        .map(|m| m.len())
+        .unwrap_or(0)
+        .min(expected_chunk_len(session, done));
+    u64::from(done) * session.chunk_size + partial
+}
+
+fn with_offset(mut resp: Response, offset: u64) -> Response {
+    resp.headers_mut()
+        .insert(UPLOAD_OFFSET, HeaderValue::from(offset));
+    resp
+}
+
+/// Resolve the caller and the session they are addressing, enforcing bans
+/// and ownership the same way the chunk API does.
+async fn owned_session(
+    state: &AppState,
+    headers: &HeaderMap,
+    addr: &ClientAddr,
+    id: &str,
+) -> Result<(String, Arc<ChunkSession>), Response> {
+    let client_ip = real_client_ip(headers, addr);
+    if state.is_banned(&client_ip).await {
+        return Err(tus(json_error(
+            StatusCode::FORBIDDEN,
+            "banned",
+            "ip banned",
+        )));
+    }
+    let owner_hash = request_owner(state, headers, &client_ip)
+        .await
+        .map_err(tus)?;
+    let Some(session) = state.chunk_session(id).await else {
+        return Err(empty(StatusCode::NOT_FOUND));
+    };
+    if session.owner_hash != owner_hash {
+        return Err(tus(json_error(
+            StatusCode::FORBIDDEN,
+            "not_owner",
+            "upload session not owned by ip",
+        )));
+    }
+    sync_stored_chunks(&session).await;
+    Ok((client_ip, session))
+}
+
+pub async fn tus_options_handler() -> Response {
+    let mut resp = empty(StatusCode::NO_CONTENT);
+    let headers = resp.headers_mut();
+    headers.insert(
+        ALLOW,
+        HeaderValue::from_static("POST, HEAD, PATCH, DELETE, OPTIONS"),
+    );
+    headers.insert(TUS_VERSION_HEADER, HeaderValue::from_static(TUS_VERSION));
+    headers.insert(TUS_EXTENSION, HeaderValue::from_static(TUS_EXTENSIONS));
+    headers.insert(TUS_MAX_SIZE, HeaderValue::from(max_file_bytes()));
+    resp
+}
+
+#[axum::debug_handler]
+#[tracing::instrument(name = "upload.tus.create", skip(state, headers))]
+pub async fn tus_create_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<ClientAddr>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(resp) = check_version(&headers) {
+        return resp;
+    }
+    let Some(size) = header_u64(&headers, &UPLOAD_LENGTH) else {
+        return tus(json_error(
+            StatusCode::BAD_REQUEST,
+            "upload_length",
+            "Upload-Length header required",
+        ));
+    };
+    if size > max_file_bytes() {
+        return tus(json_error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "too_large",
+            "file exceeds configured max size",
+        ));
+    }
+    let metadata = headers
+        .get(UPLOAD_METADATA)
+        .and_then(|v| v.to_str().ok())
+        .map(parse_metadata)
+        .unwrap_or_default();
+    let meta = |key: &str| {
+        metadata
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.clone())
+            .filter(|v| !v.is_empty())
+    };
+    let max_downloads = match parse_max_downloads(&meta("max_downloads").unwrap_or_default()) {
+        Ok(value) => value,
+        Err(_) => return tus(invalid_max_downloads()),
+    };
+    let req = ChunkInitRequest {
+        filename: meta("filename")
+            .or_else(|| meta("name"))
+            .unwrap_or_else(|| "upload.bin".to_string()),
+        size,
+        ttl: meta("ttl"),
+        chunk_size: None,
+        hash: meta("hash"),
+        max_downloads,
+        private: meta("private").is_some_and(|v| flag_enabled(&v)),
+    };
+    let client_ip = real_client_ip(&headers, &addr);
+    let attrs = ClientAttributes::from_request(&state, &headers, &addr, &client_ip);
+    let created = match create_chunk_session(&state, &headers, &client_ip, &attrs, &req).await {
+        Ok(created) => created,
+        Err(resp) => return tus(resp),
+    };
+    info!(%client_ip, session_id = %created.session_id, size, "tus upload created");
+    let mut resp = empty(StatusCode::CREATED);
+    if let Ok(location) = HeaderValue::from_str(&format!("/tus/{}", created.session_id)) {
+        resp.headers_mut().insert(LOCATION, location);
+    }
+    resp
+}
+
+#[axum::debug_handler]
+pub async fn tus_head_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<ClientAddr>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    if let Some(resp) = check_version(&headers) {
+        return resp;
+    }
+    let (_, session) = match owned_session(&state, &headers, &addr, &id).await {
+        Ok(found) => found,
+        Err(resp) => return resp,
+    };
+    let offset = upload_offset(&session).await;
+    let mut resp = with_offset(empty(StatusCode::OK), offset);
+    let headers = resp.headers_mut();
+    headers.insert(UPLOAD_LENGTH, HeaderValue::from(session.total_bytes));
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    resp
+}
+
+#[axum::debug_handler]
+#[tracing::instrument(name = "upload.tus.patch", skip(state, headers, body))]
+pub async fn tus_patch_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<ClientAddr>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    mut body: Body,
+) -> Response {
+    if let Some(resp) = check_version(&headers) {
+        return resp;
+    }
+    let content_type = headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok());
+    if content_type != Some(OFFSET_OCTET_STREAM) {
+        return empty(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+    let Some(claimed) = header_u64(&headers, &UPLOAD_OFFSET) else {
+        return tus(json_error(
+            StatusCode::BAD_REQUEST,
+            "upload_offset",
+            "Upload-Offset header required",
+        ));
+    };
+    let (client_ip, session) = match owned_session(&state, &headers, &addr, &id).await {
+        Ok(found) => found,
+        Err(resp) => return resp,
+    };
+    let Ok(_append) = session.append_lock.try_lock() else {
+        return tus(json_error(
+            StatusCode::CONFLICT,
+            "upload_busy",
+            "another request is appending to this upload",
+        ));
+    };
+    session.touch();
+    if let Err(err) = session.transition(ChunkPhase::Receiving) {
+        return tus(phase_conflict(err.from));
+    }
+    let mut offset = upload_offset(&session).await;
+    if claimed != offset {
+        debug!(session_id = %id, claimed, offset, "tus patch rejected: offset mismatch");
+        return with_offset(empty(StatusCode::CONFLICT), offset);
+    }
+
+    let mut open: Option<(u32, fs::File)> = None;
+    let mut failure = None;
+    'body: while let Some(frame) = body.frame().await {
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(err) => {
+                debug!(session_id = %id, ?err, offset, "tus patch body ended early");
+                failure = Some(empty(StatusCode::BAD_REQUEST));
+                break;
+            }
+        };
+        let Ok(mut data) = frame.into_data() else {
+            continue;
+        };
+        while !data.is_empty() {
+            if offset >= session.total_bytes {
+                failure = Some(tus(json_error(
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    "upload_length",
+                    "body exceeds Upload-Length",
+                )));
+                break 'body;
+            }
+            let index = (offset / session.chunk_size) as u32;
+            let within = offset % session.chunk_size;
+            let expected = expected_chunk_len(&session, index);
+            let take = (expected - within).min(data.len() as u64) as usize;
+            if open.as_ref().map(|(i, _)| *i) != Some(index) {
+                let path = session.chunk_path(index);
+                let file = if within == 0 {
+                    fs::File::create(&path).await
+                } else {
+                    fs::OpenOptions::new().append(true).open(&path).await
+                };
+                match file {
+                    Ok(file) => open = Some((index, file)),
+                    Err(err) => {
+                        warn!(?err, session_id = %id, chunk = index, "tus patch: failed to open chunk");
+                        failure = Some(empty(StatusCode::INTERNAL_SERVER_ERROR));
+                        break 'body;
+                    }
+                }
+            }
+            let (_, file) = open.as_mut().expect("chunk file opened above");
+            let piece = data.split_to(take);
+            if let Err(err) = file.write_all(&piece).await {
+                warn!(?err, session_id = %id, chunk = index, "tus patch: failed to write chunk");
+                failure = Some(empty(StatusCode::INTERNAL_SERVER_ERROR));
+                break 'body;
+            }
+            offset += take as u64;
+            if within + take as u64 == expected {
+                let (_, mut file) = open.take().expect("chunk file opened above");
+                if let Err(err) = file.flush().await {
+                    warn!(?err, session_id = %id, chunk = index, "tus patch: failed to flush chunk");
+                    failure = Some(empty(StatusCode::INTERNAL_SERVER_ERROR));
+                    break 'body;
+                }
+                if let Some(entry) = session.received.write().await.get_mut(index as usize) {
+                    *entry = true;
+                }
+            }
+        }
+    }
+    if let Some((_, mut file)) = open.take() {
+        let _ = file.flush().await;
+    }
+    if let Err(err) = state.persist_chunk_session(&id, session.as_ref()).await {
+        warn!(?err, session_id = %id, "failed to persist chunk session after tus patch");
+    }
+    if let Some(resp) = failure {
+        return with_offset(resp, upload_offset(&session).await);
+    }
+    if offset < session.total_bytes {
+        return with_offset(empty(StatusCode::NO_CONTENT), offset);
+    }
+
+    debug!(session_id = %id, "tus upload complete; assembling");
+    let finished = finalize_chunk_session(
+        &state,
+        &client_ip,
+        &id,
+        session.clone(),
+        None,
+        &Locale::default(),
+    )
+    .await;
+    if !finished.status().is_success() {
+        return tus(finished);
+    }
+    state.record_upload_client(
+        &headers,
+        UploadMethod::Tus,
+        session.total_bytes,
+        Some(session.chunk_size),
+    );
+    let uploaded = to_bytes(finished.into_body(), 64 * 1024)
+        .await
+        .ok()
+        .and_then(|body| serde_json::from_slice::<UploadResponse>(&body).ok());
+    let mut resp = with_offset(empty(StatusCode::NO_CONTENT), offset);
+    if let Some(file) = uploaded.and_then(|u| u.files.into_iter().next())
+        && let Ok(value) = HeaderValue::from_str(&qualify_path(&state, &share_path(&file)))
+    {
+        resp.headers_mut().insert(JUICEBOX_FILE, value);
+    }
+    resp
+}
+
+#[axum::debug_handler]
+pub async fn tus_delete_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<ClientAddr>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    if let Some(resp) = check_version(&headers) {
+        return resp;
+    }
+    let (client_ip, session) = match owned_session(&state, &headers, &addr, &id).await {
+        Ok(found) => found,
+        Err(resp) => return resp,
+    };
+    if let Err(err) = session.transition(ChunkPhase::Failed) {
+        return tus(phase_conflict(err.from));
+    }
+    state.remove_chunk_session(&id).await;
+    info!(%client_ip, session_id = %id, "tus upload terminated");
+    empty(StatusCode::NO_CONTENT)
+}
@@ -1,30 +1,50 @@
 use axum::Json;
-use axum::body::Bytes;
+use axum::body::Body;
 use axum::extract::{ConnectInfo, Multipart, Path, Query as AxumQuery, State};
-use axum::http::header::{ALLOW, CACHE_CONTROL, PRAGMA};
-use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::http::header::{
+    ALLOW, CACHE_CONTROL, CONTENT_LENGTH, CONTENT_RANGE, PRAGMA, RANGE, RETRY_AFTER,
+};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
+use http_body_util::BodyExt;
 use infer;
 use mime_guess::mime;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use sha2::{Digest, Sha256};
 use std::net::SocketAddr as ClientAddr;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use tokio::fs;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::sync::{Mutex, RwLock};
-use tracing::{debug, error, info, trace, warn};
-
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::{Mutex, RwLock, Semaphore, mpsc};
+use tokio::task::JoinSet;
+use tracing::{Instrument, debug, error, info, trace, warn};
+
+use crate::accounts::ACCOUNT_COOKIE;
+use crate::content_scan::{Rejection, SAMPLE_BYTES, ScanContent, ScanInput};
+use crate::digest_fields::{self, CONTENT_DIGEST, DigestCheck, DigestError, REPR_DIGEST};
+use crate::handlers::telemetry::{
+    current_sentry_trace, sentry_trace_to_traceparent, start_upload_transaction, tag_upload_session,
+};
+use crate::i18n::{Locale, LocaleQuery};
+use crate::owner_events::OwnerEvent;
+use crate::quarantine::Screening;
+use crate::reload;
+use crate::request_id::current_request_id;
 use crate::state::{
-    AppState, ChunkSession, FileMeta, ReconcileReport, check_storage_integrity, cleanup_expired,
+    AppState, ChunkLifecycle, ChunkPhase, ChunkSession, FileMeta, ListQuery, NewFileBody,
+    ReconcileReport, SlotTier, assembly_temp_path, check_storage_integrity, cleanup_expired,
     spawn_integrity_check, verify_user_entries_with_report,
 };
+use crate::ttl_policy::{ClientAttributes, EffectiveTtl};
+use crate::upload_stats::UploadMethod;
 use crate::util::{
-    FORBIDDEN_EXTENSIONS, MAX_ACTIVE_FILES_PER_IP, is_forbidden_extension, json_error,
-    make_storage_name, max_file_bytes, new_id, now_secs, qualify_path, real_client_ip,
-    ttl_to_duration,
+    MAX_ACTIVE_FILES_PER_IP, MAX_GUEST_FILES_PER_OWNER, bearer_token, display_original_name,
+    format_bytes, get_cookie, is_forbidden_extension, json_error, make_storage_name,
+    max_file_bytes, new_id, qualify_path, real_client_ip, share_path,
 };
 
 #[derive(Deserialize)]
@@ -33,22 +53,118 @@ pub struct CheckHashQuery {
 }
 
 #[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub struct UploadResponse {
     pub files: Vec<String>,
     pub truncated: bool,
     pub remaining: usize,
     #[serde(default)]
     pub limit_reached: bool,
+    /// TTL policy applied to the upload.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_policy: Option<EffectiveTtl>,
+    /// Size and expiry of each file in `files`, formatted for the request's
+    /// language.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub uploaded: Vec<UploadedFileEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct UploadedFileEntry {
+    pub file: String,
+    pub size: u64,
+    pub expires: u64,
+    /// e.g. "1.5 MB".
+    pub size_human: String,
+    /// e.g. "in 3 days".
+    pub expires_human: String,
+}
+
+/// [`UploadedFileEntry`]s for the stored files among `files`.
+fn uploaded_entries(state: &AppState, locale: &Locale, files: &[String]) -> Vec<UploadedFileEntry> {
+    let now = state.now_secs();
+    files
+        .iter()
+        .filter_map(|file| {
+            let meta = state.owners.get(file)?;
+            Some(UploadedFileEntry {
+                file: file.clone(),
+                size: meta.size,
+                expires: meta.expires,
+                size_human: locale.size(meta.size),
+                expires_human: locale.expires_in(meta.expires, now),
+            })
+        })
+        .collect()
 }
 
 #[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
 pub struct ListResponse {
     pub files: Vec<String>,
     pub metas: Vec<FileMetaEntry>,
     pub reconcile: Option<ReconcileReport>,
+    /// Files the owner holds in total, across all pages.
+    pub total: usize,
+    pub offset: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_offset: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    /// Files an admin took down recently, with the reason.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<RemovedFileEntry>,
 }
 
 #[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct RemovedFileEntry {
+    pub file: String,
+    pub removed_at: u64,
+    /// Stable code, e.g. `malware`.
+    pub reason: &'static str,
+    /// e.g. "removed for ToS violation: malware".
+    pub message: String,
+}
+
+/// Body of the `409` sent when an upload's hash matches a hosted file. Only
+/// what the uploader can already derive from the content is included; the
+/// other upload's owner, name and counters stay private.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct DuplicateResponse {
+    pub duplicate: bool,
+    pub file: String,
+    pub meta: DuplicateMeta,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct DuplicateMeta {
+    pub hash: String,
+    pub size: u64,
+    pub created: u64,
+    pub expires: u64,
+}
+
+impl DuplicateResponse {
+    pub fn new(file: &str, meta: &FileMeta) -> Self {
+        Self {
+            duplicate: true,
+            file: file.to_string(),
+            meta: DuplicateMeta {
+                hash: meta.hash.clone(),
+                size: meta.size,
+                created: meta.created,
+                expires: meta.expires,
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
 pub struct FileMetaEntry {
     pub file: String,
     pub expires: u64,
@@ -58,33 +174,94 @@ pub struct FileMetaEntry {
     pub total: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub set: Option<u64>,
+    #[serde(default)]
+    pub downloads: u64,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub private: bool,
+    /// Expiry before storage pressure shortened it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shortened_from: Option<u64>,
+    pub size: u64,
+    /// `size` in the request's language, e.g. "1,5 Mo".
+    pub size_human: String,
+    /// Time left in the request's language, e.g. "in 3 days".
+    pub expires_human: String,
 }
 
 const DEFAULT_CHUNK_SIZE: u64 = 8 * 1024 * 1024; // 8 MiB
 const MIN_CHUNK_SIZE: u64 = 64 * 1024; // 64 KiB
 const MAX_CHUNK_SIZE: u64 = 32 * 1024 * 1024; // 32 MiB
 const MAX_TOTAL_CHUNKS: u64 = 20_000;
-const INFER_SAMPLE_BYTES: usize = 8 * 1024;
+/// Copy buffer used while assembling chunks into the final file.
+const ASSEMBLY_COPY_BYTES: usize = 64 * 1024;
+/// Chunks copied into the final file at once by one assembly.
+const ASSEMBLY_PARALLELISM: usize = 4;
+/// Copy buffers a chunk may get ahead of the hash before its copy waits.
+/// Kept at one so an assembly holds at most two buffers per copy, about
+/// half a MiB in all.
+const ASSEMBLY_HASH_BACKLOG: usize = 1;
+
+/// Parse the `max_downloads` upload option; blank means unlimited and zero is
+/// rejected.
+pub(crate) fn parse_max_downloads(raw: &str) -> Result<Option<u32>, std::num::ParseIntError> {
+    let raw = raw.trim();
+    if raw.is_empty() {
+        return Ok(None);
+    }
+    raw.parse::<NonZeroU32>().map(|n| Some(n.get()))
+}
+
+/// Whether a form or query flag such as `private` is switched on.
+pub(crate) fn flag_enabled(raw: &str) -> bool {
+    matches!(
+        raw.trim().to_ascii_lowercase().as_str(),
+        "1" | "true" | "yes" | "on"
+    )
+}
+
+pub(crate) fn invalid_max_downloads() -> Response {
+    json_error(
+        StatusCode::BAD_REQUEST,
+        "invalid_max_downloads",
+        "max_downloads must be a positive integer",
+    )
+}
 
 #[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub struct ChunkInitRequest {
     pub filename: String,
     pub size: u64,
     pub ttl: Option<String>,
     pub chunk_size: Option<u64>,
     pub hash: Option<String>,
+    /// Delete the file after this many downloads.
+    #[serde(default)]
+    pub max_downloads: Option<u32>,
+    /// Serve the file only through presigned links.
+    #[serde(default)]
+    pub private: bool,
 }
 
 #[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub struct ChunkInitResponse {
     pub session_id: String,
     pub chunk_size: u64,
     pub total_chunks: u32,
     pub expires: u64,
     pub storage_name: String,
+    /// TTL policy applied to the upload.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_policy: Option<EffectiveTtl>,
+    /// W3C trace context of the init request, for clients that want to tie
+    /// their own spans to the upload's trace.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub traceparent: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub struct ChunkCompleteRequest {
     pub hash: Option<String>,
 }
@@ -100,11 +277,52 @@ pub struct ChunkCompletePath {
     id: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
 pub struct ChunkStatusResponse {
     pub total_chunks: u32,
     pub assembled_chunks: u32,
     pub completed: bool,
+    pub state: ChunkPhase,
+    /// One flag per chunk, set once the chunk is stored in full.
+    pub received: Vec<bool>,
+    /// Chunks still to upload, as inclusive `[first, last]` index runs.
+    pub missing: Vec<[u32; 2]>,
+}
+
+/// Indices of the unset flags in `received`, collapsed into inclusive
+/// `[first, last]` runs.
+fn missing_chunk_runs(received: &[bool]) -> Vec<[u32; 2]> {
+    let mut runs: Vec<[u32; 2]> = Vec::new();
+    for (idx, _) in received.iter().enumerate().filter(|(_, got)| !**got) {
+        let idx = idx as u32;
+        match runs.last_mut() {
+            Some(run) if run[1] + 1 == idx => run[1] = idx,
+            _ => runs.push([idx, idx]),
+        }
+    }
+    runs
+}
+
+/// One of the caller's unfinished chunk sessions, with enough to resume it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub struct ChunkSessionEntry {
+    pub session_id: String,
+    pub filename: String,
+    pub size: u64,
+    pub chunk_size: u64,
+    pub total_chunks: u32,
+    pub received: Vec<bool>,
+    /// When the session is dropped unless another request arrives first.
+    pub expires: u64,
+    pub state: ChunkPhase,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct ChunkSessionsResponse {
+    pub sessions: Vec<ChunkSessionEntry>,
 }
 
 #[axum::debug_handler]
@@ -127,6 +345,43 @@ pub async fn chunk_cancel_options_handler(Path(_): Path<ChunkCompletePath>) -> R
     empty_response_with_allow(StatusCode::NO_CONTENT, "DELETE, OPTIONS")
 }
 
+/// Hex SHA-256 of a whole chunk, sent on a chunk part `PUT` so a corrupt
+/// chunk is refused then rather than at completion.
+pub const CHUNK_SHA256: HeaderName = HeaderName::from_static("x-chunk-sha256");
+
+/// An `X-Chunk-SHA256` value, lowercased, or `None` when it is not 64 hex
+/// digits.
+fn parse_chunk_sha256(value: &HeaderValue) -> Option<String> {
+    value
+        .to_str()
+        .ok()
+        .map(str::trim)
+        .filter(|hex| hex.len() == 64 && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+        .map(str::to_ascii_lowercase)
+}
+
+/// `400` for a request body that fails its `Content-Digest`/`Repr-Digest`.
+fn digest_error_response(err: DigestError) -> Response {
+    match err {
+        DigestError::Malformed(_) => json_error(
+            StatusCode::BAD_REQUEST,
+            "invalid_digest",
+            "malformed Content-Digest or Repr-Digest header",
+        ),
+        DigestError::Mismatch(_) => json_error(
+            StatusCode::BAD_REQUEST,
+            "digest_mismatch",
+            "body does not match its digest",
+        ),
+    }
+}
+
+/// `204` answering `OPTIONS` for a route that takes `methods`; CORS
+/// preflights reflect the `Allow` it sends.
+pub async fn allow_options(methods: &'static str) -> Response {
+    empty_response_with_allow(StatusCode::NO_CONTENT, methods)
+}
+
 fn empty_response_with_allow(status: StatusCode, methods: &str) -> Response {
     let mut headers = HeaderMap::new();
     if let Ok(value) = HeaderValue::from_str(methods) {
@@ -155,6 +410,52 @@ pub async fn upload_get_handler() -> Response {
     empty_response_with_allow(StatusCode::METHOD_NOT_ALLOWED, "POST, HEAD, OPTIONS")
 }
 
+/// Sent while an admin drain is under way, before a restart.
+fn draining_response() -> Response {
+    let mut resp = json_error(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "draining",
+        "server is restarting, try again shortly",
+    );
+    resp.headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from_static("30"));
+    resp
+}
+
+fn rejection_response(rejection: Rejection) -> Response {
+    json_error(rejection.status, rejection.code, rejection.message)
+}
+
+/// An upload clamd flagged. The file has been quarantined.
+fn virus_detected_response() -> Response {
+    json_error(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "virus_detected",
+        "upload rejected: a virus was detected",
+    )
+}
+
+fn storage_full_response() -> Response {
+    json_error(
+        StatusCode::INSUFFICIENT_STORAGE,
+        "storage_full",
+        "server storage is full, try again later",
+    )
+}
+
+fn owner_quota_response(state: &AppState) -> Response {
+    let quota = format_bytes(state.storage.limits().owner_bytes);
+    (
+        StatusCode::INSUFFICIENT_STORAGE,
+        Json(json!({
+            "code": "owner_quota",
+            "message": format!("Storage quota of {quota} reached. Delete an existing upload to free space."),
+            "request_id": current_request_id(),
+        })),
+    )
+        .into_response()
+}
+
 fn file_limit_response() -> Response {
     let message = format!(
         "Active file limit reached. Delete an existing upload to free one of the {MAX_ACTIVE_FILES_PER_IP} slots."
@@ -164,11 +465,42 @@ fn file_limit_response() -> Response {
         Json(json!({
             "code": "file_limit",
             "message": message,
+            "request_id": current_request_id(),
         })),
     )
         .into_response()
 }
 
+fn guest_limit_response() -> Response {
+    let message = format!(
+        "Guest upload limit reached. Guest files expire within an hour; at most {MAX_GUEST_FILES_PER_OWNER} can be live at once."
+    );
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(json!({
+            "code": "guest_limit",
+            "message": message,
+            "request_id": current_request_id(),
+        })),
+    )
+        .into_response()
+}
+
+fn slot_limit_response(tier: SlotTier) -> Response {
+    match tier {
+        SlotTier::Standard => file_limit_response(),
+        SlotTier::Guest => guest_limit_response(),
+    }
+}
+
+fn guest_too_large_response() -> Response {
+    json_error(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        "guest_too_large",
+        "guest uploads are limited to 5MB",
+    )
+}
+
 fn compute_chunk_layout(total_size: u64, requested: Option<u64>) -> Option<(u64, u32)> {
     if total_size == 0 {
         return None;
@@ -181,17 +513,17 @@ fn compute_chunk_layout(total_size: u64, requested: Option<u64>) -> Option<(u64,
     if chunk == 0 {
         chunk = total_size;
     }
-    let mut total_chunks = ((total_size + chunk - 1) / chunk) as u64;
+    let mut total_chunks = total_size.div_ceil(chunk);
     if total_chunks == 0 {
         return None;
     }
     if total_chunks > MAX_TOTAL_CHUNKS {
-        chunk = ((total_size + MAX_TOTAL_CHUNKS - 1) / MAX_TOTAL_CHUNKS).max(1);
+        chunk = total_size.div_ceil(MAX_TOTAL_CHUNKS).max(1);
         chunk = chunk.clamp(MIN_CHUNK_SIZE, MAX_CHUNK_SIZE);
         if chunk > total_size {
             chunk = total_size;
         }
-        total_chunks = ((total_size + chunk - 1) / chunk) as u64;
+        total_chunks = total_size.div_ceil(chunk);
         if total_chunks == 0 || total_chunks > MAX_TOTAL_CHUNKS {
             return None;
         }
@@ -199,7 +531,7 @@ fn compute_chunk_layout(total_size: u64, requested: Option<u64>) -> Option<(u64,
     Some((chunk, total_chunks as u32))
 }
 
-fn expected_chunk_len(session: &ChunkSession, index: u32) -> u64 {
+pub(crate) fn expected_chunk_len(session: &ChunkSession, index: u32) -> u64 {
     if session.total_chunks == 0 {
         return 0;
     }
@@ -211,12 +543,458 @@ fn expected_chunk_len(session: &ChunkSession, index: u32) -> u64 {
     }
 }
 
-fn find_duplicate_by_hash(state: &AppState, hash: &str) -> Option<(String, FileMeta)> {
-    state
-        .owners
+/// Mark chunks that are fully on disk as received. Another replica sharing
+/// the chunk directory may have stored them since this one last looked.
+pub(crate) async fn sync_stored_chunks(session: &ChunkSession) {
+    let missing: Vec<u32> = session
+        .received
+        .read()
+        .await
         .iter()
-        .find(|entry| entry.value().hash == hash)
-        .map(|entry| (entry.key().clone(), entry.value().clone()))
+        .enumerate()
+        .filter(|(_, received)| !**received)
+        .map(|(index, _)| index as u32)
+        .collect();
+    for index in missing {
+        let stored = fs::metadata(session.chunk_path(index))
+            .await
+            .map(|m| m.len())
+            .unwrap_or(0);
+        let expected = expected_chunk_len(session, index);
+        if expected > 0
+            && stored == expected
+            && let Some(entry) = session.received.write().await.get_mut(index as usize)
+        {
+            *entry = true;
+        }
+    }
+}
+
+/// Whether the session's chunk layout adds up to its declared size: every
+/// chunk but the last is `chunk_size` long and the last holds the rest.
+fn layout_matches_size(session: &ChunkSession) -> bool {
+    if session.total_chunks == 0 || session.chunk_size == 0 {
+        return false;
+    }
+    let full = session.chunk_size * (session.total_chunks as u64 - 1);
+    session.total_bytes > full && session.total_bytes - full <= session.chunk_size
+}
+
+fn size_mismatch() -> Response {
+    json_error(
+        StatusCode::BAD_REQUEST,
+        "size_mismatch",
+        "assembled size does not match declared size",
+    )
+}
+
+/// Error for a request that does not fit the session's current phase.
+pub(crate) fn phase_conflict(phase: ChunkPhase) -> Response {
+    match phase {
+        ChunkPhase::Finalized => json_error(
+            StatusCode::BAD_REQUEST,
+            "completed",
+            "upload session already completed",
+        ),
+        ChunkPhase::Failed => json_error(
+            StatusCode::CONFLICT,
+            "failed",
+            "upload session was cancelled or failed",
+        ),
+        ChunkPhase::Assembling | ChunkPhase::Verifying => json_error(
+            StatusCode::CONFLICT,
+            "assembling",
+            "upload session is being assembled",
+        ),
+        ChunkPhase::Init | ChunkPhase::Receiving => json_error(
+            StatusCode::CONFLICT,
+            "chunk_state",
+            "upload session is not ready",
+        ),
+    }
+}
+
+/// Owns the `.part` file for the duration of an assembly. Any exit that does
+/// not reach [`AssemblyGuard::disarm`] (an error return, the request future
+/// being dropped on client disconnect or shutdown, or a panic) removes the temp
+/// file and hands the session back so completion can be retried.
+struct AssemblyGuard {
+    session: Arc<ChunkSession>,
+    tmp_path: PathBuf,
+    armed: bool,
+}
+
+impl AssemblyGuard {
+    fn new(session: Arc<ChunkSession>, tmp_path: PathBuf) -> Self {
+        Self {
+            session,
+            tmp_path,
+            armed: true,
+        }
+    }
+
+    fn disarm(&mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for AssemblyGuard {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        if let Err(err) = std::fs::remove_file(&self.tmp_path)
+            && err.kind() != std::io::ErrorKind::NotFound
+        {
+            warn!(?err, path = ?self.tmp_path, "failed to remove partial assembly");
+        }
+        // A cancel that got there first leaves the session failed.
+        if let Err(err) = self.session.lifecycle.reopen() {
+            debug!(%err, "chunk session not reopened");
+        }
+    }
+}
+
+/// Why copying chunks into the `.part` file stopped.
+enum AssemblyError {
+    Cancelled,
+    Missing(u32, std::io::Error),
+    Read(u32, std::io::Error),
+    Length {
+        chunk: u32,
+        actual: u64,
+        expected: u64,
+    },
+    Write(u32, std::io::Error),
+    Task(tokio::task::JoinError),
+}
+
+impl AssemblyError {
+    fn into_response(self, session_id: &str) -> Response {
+        match self {
+            AssemblyError::Cancelled => {
+                info!(session_id = %session_id, "chunk assembly aborted: session cancelled");
+                phase_conflict(ChunkPhase::Failed)
+            }
+            AssemblyError::Missing(chunk, err) => {
+                error!(?err, session_id = %session_id, chunk, "missing chunk during assembly");
+                json_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "chunk_missing",
+                    "missing chunk during assembly",
+                )
+            }
+            AssemblyError::Read(chunk, err) => {
+                error!(?err, session_id = %session_id, chunk, "failed reading chunk");
+                json_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "chunk_read",
+                    "failed reading chunk data",
+                )
+            }
+            AssemblyError::Length {
+                chunk,
+                actual,
+                expected,
+            } => {
+                error!(actual, expected, session_id = %session_id, chunk, "chunk length mismatch during assembly");
+                json_error(
+                    StatusCode::BAD_REQUEST,
+                    "chunk_size",
+                    "chunk length mismatch",
+                )
+            }
+            AssemblyError::Write(chunk, err) => {
+                error!(?err, session_id = %session_id, chunk, "failed writing assembled file");
+                json_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "write",
+                    "failed writing assembled file",
+                )
+            }
+            AssemblyError::Task(err) => {
+                error!(?err, session_id = %session_id, "chunk copy task failed");
+                json_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "write",
+                    "failed writing assembled file",
+                )
+            }
+        }
+    }
+}
+
+/// What assembling a session's chunks produced besides the file itself.
+struct Assembled {
+    /// Hex SHA-256 of the whole upload.
+    digest: String,
+    /// The first [`SAMPLE_BYTES`], for type sniffing.
+    sample: Vec<u8>,
+    bytes: u64,
+}
+
+/// Write every chunk of `session` into the preallocated `tmp_path` at its
+/// offset, [`ASSEMBLY_PARALLELISM`] at a time. Each copy hands the bytes it
+/// read on to the hasher, which takes the chunks in order, so every chunk
+/// file is read once. A copy ahead of the hash waits once it has
+/// [`ASSEMBLY_HASH_BACKLOG`] buffers queued, so the copies behind the chunk
+/// being hashed mostly sit idle: the upload's hash is one digest over the
+/// whole file (see [`hash_in_order`]) and cannot be taken out of order, and
+/// bounded memory matters more than the copy speed given up.
+async fn assemble_chunks(
+    session: &Arc<ChunkSession>,
+    tmp_path: &std::path::Path,
+) -> Result<Assembled, AssemblyError> {
+    // Reading exactly the expected length would silently drop trailing bytes
+    // of an oversized chunk file, so check sizes before anything is written.
+    for idx in 0..session.total_chunks {
+        let expected = expected_chunk_len(session, idx);
+        let actual = fs::metadata(session.chunk_path(idx))
+            .await
+            .map_err(|err| AssemblyError::Missing(idx, err))?
+            .len();
+        if actual != expected {
+            return Err(AssemblyError::Length {
+                chunk: idx,
+                actual,
+                expected,
+            });
+        }
+    }
+    let (order_tx, order_rx) = mpsc::unbounded_channel();
+    let copying = async move {
+        let slots = Arc::new(Semaphore::new(ASSEMBLY_PARALLELISM));
+        let mut copies = JoinSet::new();
+        for idx in 0..session.total_chunks {
+            let slot = slots
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("assembly semaphore is never closed");
+            if session.phase() == ChunkPhase::Failed {
+                return Err(AssemblyError::Cancelled);
+            }
+            let (feed, chunk_rx) = mpsc::channel(ASSEMBLY_HASH_BACKLOG);
+            let _ = order_tx.send(chunk_rx);
+            let session = session.clone();
+            let tmp_path = tmp_path.to_path_buf();
+            copies.spawn(async move {
+                copy_chunk_at(&session, idx, &tmp_path, feed).await?;
+                session.assembled_chunks.fetch_add(1, Ordering::Relaxed);
+                drop(slot);
+                Ok(())
+            });
+            while let Some(done) = copies.try_join_next() {
+                done.map_err(AssemblyError::Task)??;
+            }
+        }
+        drop(order_tx);
+        while let Some(done) = copies.join_next().await {
+            done.map_err(AssemblyError::Task)??;
+        }
+        Ok(())
+    };
+    let hashing = async { Ok(hash_in_order(order_rx).await) };
+    let ((), assembled) = tokio::try_join!(copying, hashing)?;
+    Ok(assembled)
+}
+
+/// Copy chunk `idx` into `tmp_path` at `idx * chunk_size`, passing each
+/// buffer written to `feed`.
+async fn copy_chunk_at(
+    session: &ChunkSession,
+    idx: u32,
+    tmp_path: &std::path::Path,
+    feed: mpsc::Sender<Vec<u8>>,
+) -> Result<(), AssemblyError> {
+    let expected = expected_chunk_len(session, idx);
+    let mut chunk = fs::File::open(session.chunk_path(idx))
+        .await
+        .map_err(|err| AssemblyError::Missing(idx, err))?;
+    let write_err = |err| AssemblyError::Write(idx, err);
+    let mut out = fs::OpenOptions::new()
+        .write(true)
+        .open(tmp_path)
+        .await
+        .map_err(write_err)?;
+    out.seek(std::io::SeekFrom::Start(
+        u64::from(idx) * session.chunk_size,
+    ))
+    .await
+    .map_err(write_err)?;
+    // Stream through fixed buffers so memory stays flat whatever the chunk
+    // size.
+    let mut remaining = expected;
+    while remaining > 0 {
+        let mut buf = vec![0u8; remaining.min(ASSEMBLY_COPY_BYTES as u64) as usize];
+        let read = match chunk.read(&mut buf).await {
+            Ok(0) => {
+                return Err(AssemblyError::Length {
+                    chunk: idx,
+                    actual: expected - remaining,
+                    expected,
+                });
+            }
+            Ok(read) => read,
+            Err(err) => return Err(AssemblyError::Read(idx, err)),
+        };
+        buf.truncate(read);
+        out.write_all(&buf).await.map_err(write_err)?;
+        remaining -= read as u64;
+        // The hasher only goes away when the assembly is being abandoned.
+        let _ = feed.send(buf).await;
+    }
+    out.flush().await.map_err(write_err)
+}
+
+/// Hash the chunks' bytes front to back as their copies hand them over and
+/// keep the leading sample. This stays one SHA-256 over the whole upload so
+/// deduplication, `/api/v1/files/lookup` and the hash blocklist match a
+/// chunked file the same as one sent in a single request.
+async fn hash_in_order(mut chunks: mpsc::UnboundedReceiver<mpsc::Receiver<Vec<u8>>>) -> Assembled {
+    let mut hasher = Sha256::new();
+    let mut sample = Vec::with_capacity(SAMPLE_BYTES);
+    let mut bytes = 0u64;
+    while let Some(mut chunk) = chunks.recv().await {
+        while let Some(data) = chunk.recv().await {
+            if sample.len() < SAMPLE_BYTES {
+                let take = data.len().min(SAMPLE_BYTES - sample.len());
+                sample.extend_from_slice(&data[..take]);
+            }
+            hasher.update(&data);
+            bytes += data.len() as u64;
+        }
+    }
+    Assembled {
+        digest: format!("{:x}", hasher.finalize()),
+        sample,
+        bytes,
+    }
+}
+
+/// An upload body written to a `.part` file in the upload directory as it
+/// arrives. The temp file is removed on drop unless it was moved into the
+/// store first.
+pub(crate) struct SpooledField {
+    pub(crate) original_name: Option<String>,
+    pub(crate) path: PathBuf,
+    /// Bytes received for the field, including any past the size limit.
+    pub(crate) size: u64,
+    /// SHA-256 of the contents; only meaningful when `size` is within the limit.
+    pub(crate) hash: String,
+    /// Leading bytes handed to the content scanners.
+    pub(crate) sniff: Vec<u8>,
+}
+
+impl Drop for SpooledField {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.path)
+            && err.kind() != std::io::ErrorKind::NotFound
+        {
+            warn!(?err, path = ?self.path, "failed to remove spooled upload");
+        }
+    }
+}
+
+impl SpooledField {
+    /// An empty `.part` file in the upload directory for an upload the client
+    /// called `original_name`.
+    pub(crate) async fn create(
+        state: &AppState,
+        original_name: Option<String>,
+    ) -> std::io::Result<(Self, fs::File)> {
+        let path = assembly_temp_path(&state.upload_dir, &format!("upload-{}", new_id()));
+        let file = fs::File::create(&path).await?;
+        let spooled = SpooledField {
+            original_name,
+            path,
+            size: 0,
+            hash: String::new(),
+            sniff: Vec::with_capacity(SAMPLE_BYTES),
+        };
+        Ok((spooled, file))
+    }
+
+    /// Count `chunk` and, while the upload is within `max_file_bytes()`,
+    /// hash it, keep its start for the scanners and write it to `file`.
+    pub(crate) async fn push(
+        &mut self,
+        file: &mut fs::File,
+        hasher: &mut Sha256,
+        chunk: &[u8],
+    ) -> std::io::Result<()> {
+        self.size += chunk.len() as u64;
+        if self.size > max_file_bytes() {
+            return Ok(());
+        }
+        if self.sniff.len() < SAMPLE_BYTES {
+            let take = std::cmp::min(chunk.len(), SAMPLE_BYTES - self.sniff.len());
+            self.sniff.extend_from_slice(&chunk[..take]);
+        }
+        hasher.update(chunk);
+        file.write_all(chunk).await
+    }
+}
+
+/// Streams a file field to disk, hashing incrementally. Bytes past
+/// `max_file_bytes()` are counted but not written, so the caller can still
+/// report the file as too large once the rest of the body is drained.
+async fn spool_field(
+    state: &AppState,
+    mut field: axum::extract::multipart::Field<'_>,
+) -> Result<SpooledField, Response> {
+    let original_name = field.file_name().map(|s| s.to_string());
+    let (mut spooled, mut file) = match SpooledField::create(state, original_name).await {
+        Ok(created) => created,
+        Err(err) => {
+            error!(?err, "failed to create spooled upload");
+            return Err(json_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "write",
+                "failed to store upload",
+            ));
+        }
+    };
+    let mut hasher = Sha256::new();
+    loop {
+        let chunk = match field.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(e) => {
+                warn!(error = ?e, "multipart stream error while spooling field");
+                return Err(json_error(
+                    StatusCode::BAD_REQUEST,
+                    "multipart_error",
+                    "Invalid or incomplete multipart request",
+                ));
+            }
+        };
+        if let Err(err) = spooled.push(&mut file, &mut hasher, &chunk).await {
+            error!(?err, path = ?spooled.path, "failed writing spooled upload");
+            return Err(json_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "write",
+                "failed to store upload",
+            ));
+        }
+    }
+    if let Err(err) = file.flush().await {
+        error!(?err, path = ?spooled.path, "failed flushing spooled upload");
+        return Err(json_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "write",
+            "failed to store upload",
+        ));
+    }
+    spooled.hash = format!("{:x}", hasher.finalize());
+    Ok(spooled)
+}
+
+fn find_duplicate_by_hash(state: &AppState, hash: &str) -> Option<DuplicateResponse> {
+    let snapshot = state.owners_snapshot();
+    let file = snapshot.file_with_hash(hash)?;
+    let meta = state.owners.get(file)?;
+    Some(DuplicateResponse::new(file, meta.value()))
 }
 
 #[axum::debug_handler]
@@ -228,35 +1006,88 @@ pub async fn init_chunk_upload_handler(
 ) -> Response {
     let client_ip = real_client_ip(&headers, &addr);
     trace!(%client_ip, "chunk upload init request received");
-    if state.is_banned(&client_ip).await {
-        warn!(%client_ip, "chunk upload init rejected: banned ip");
-        return json_error(StatusCode::FORBIDDEN, "banned", "ip banned");
+    let attrs = ClientAttributes::from_request(&state, &headers, &addr, &client_ip);
+    match create_chunk_session(&state, &headers, &client_ip, &attrs, &req).await {
+        Ok(created) => Json(created).into_response(),
+        Err(resp) => resp,
     }
-    let owner_hash = if let Some(hash) = state.hash_ip_to_string(&client_ip) {
-        hash
-    } else {
-        warn!(%client_ip, "chunk upload init failed: unable to hash ip");
-        return json_error(
+}
+
+/// Who a request acts for: the owner of the `Authorization: Bearer` API token
+/// when one is sent, then the logged-in account, otherwise the client's IP
+/// hash. A token that is not recognised is refused rather than falling back
+/// to the IP; a stale account cookie just falls back.
+pub(crate) async fn request_owner(
+    state: &AppState,
+    headers: &HeaderMap,
+    client_ip: &str,
+) -> Result<String, Response> {
+    if let Some(secret) = bearer_token(headers) {
+        return match state.api_tokens.authenticate(secret) {
+            Some(token) => {
+                trace!(token_id = %token.id, "upload authenticated with api token");
+                Ok(token.owner_hash)
+            }
+            None => {
+                warn!(%client_ip, "request rejected: unknown api token");
+                Err(json_error(
+                    StatusCode::UNAUTHORIZED,
+                    "invalid_token",
+                    "api token not recognised",
+                ))
+            }
+        };
+    }
+    if state.accounts.is_enabled()
+        && let Some(account) = get_cookie(headers, ACCOUNT_COOKIE)
+            .and_then(|token| state.accounts.session_account(&token))
+    {
+        trace!(username = %account.username, "request authenticated with account session");
+        return Ok(account.owner_hash());
+    }
+    state.hash_ip_to_string(client_ip).ok_or_else(|| {
+        warn!(%client_ip, "request rejected: unable to hash ip");
+        json_error(
             StatusCode::FORBIDDEN,
             "invalid_ip",
             "unable to fingerprint client",
-        );
-    };
+        )
+    })
+}
+
+/// Validate `req` and register a new chunk session for `client_ip`. Shared by
+/// the chunk API and tus upload creation.
+pub(crate) async fn create_chunk_session(
+    state: &AppState,
+    headers: &HeaderMap,
+    client_ip: &str,
+    attrs: &ClientAttributes,
+    req: &ChunkInitRequest,
+) -> Result<ChunkInitResponse, Response> {
+    if state.is_banned(client_ip).await {
+        warn!(%client_ip, "chunk upload init rejected: banned ip");
+        return Err(json_error(StatusCode::FORBIDDEN, "banned", "ip banned"));
+    }
+    let owner_hash = request_owner(state, headers, client_ip).await?;
     if is_forbidden_extension(&req.filename) {
         warn!(
             %client_ip,
             filename = %req.filename,
             "chunk upload init rejected: forbidden file extension"
         );
-        return json_error(
+        return Err(json_error(
             StatusCode::BAD_REQUEST,
             "bad_filetype",
             "File type not allowed",
-        );
+        ));
     }
     if req.size == 0 {
         warn!(%client_ip, "chunk upload init rejected: empty size");
-        return json_error(StatusCode::BAD_REQUEST, "empty", "file size required");
+        return Err(json_error(
+            StatusCode::BAD_REQUEST,
+            "empty",
+            "file size required",
+        ));
     }
     if req.size > max_file_bytes() {
         warn!(
@@ -265,17 +1096,32 @@ pub async fn init_chunk_upload_handler(
             limit = max_file_bytes(),
             "chunk upload init rejected: size over limit"
         );
-        return json_error(
+        return Err(json_error(
             StatusCode::PAYLOAD_TOO_LARGE,
             "too_large",
             "file exceeds configured max size",
-        );
+        ));
+    }
+    if req.max_downloads == Some(0) {
+        return Err(invalid_max_downloads());
+    }
+    if state.drain.is_draining() {
+        warn!(%client_ip, "chunk upload init rejected: server draining");
+        return Err(draining_response());
+    }
+    if state.storage.is_full() {
+        warn!(%client_ip, "chunk upload init rejected: storage full");
+        return Err(storage_full_response());
+    }
+    cleanup_expired(state).await;
+    let now = state.now_secs();
+    if !state.owner_quota_allows(&owner_hash, req.size, now) {
+        warn!(owner_hash = %owner_hash, size = req.size, "chunk upload init rejected: owner quota reached");
+        return Err(owner_quota_response(state));
     }
-    cleanup_expired(&state).await;
-    let now = now_secs();
     let ttl_code = req.ttl.clone().unwrap_or_else(|| "24h".to_string());
-    let ttl = ttl_to_duration(&ttl_code).as_secs();
-    let expires = now + ttl;
+    let ttl_policy = state.ttl_policy.resolve(attrs, req.ttl.as_deref(), "24h");
+    let expires = now + ttl_policy.ttl;
 
     let (chunk_size, total_chunks) =
         if let Some(layout) = compute_chunk_layout(req.size, req.chunk_size) {
@@ -287,55 +1133,57 @@ pub async fn init_chunk_upload_handler(
                 requested_chunk = ?req.chunk_size,
                 "chunk upload init rejected: unable to compute chunk layout"
             );
-            return json_error(
+            return Err(json_error(
                 StatusCode::BAD_REQUEST,
                 "chunk_layout",
                 "unable to compute chunk layout",
-            );
+            ));
         };
 
-    if let Some(hash) = req.hash.as_ref() {
-        if let Some((file, meta)) = find_duplicate_by_hash(&state, hash) {
-            info!(%client_ip, file = %file, "chunk upload init detected duplicate hash");
-            return (
-                StatusCode::CONFLICT,
-                Json(json!({
-                    "duplicate": true,
-                    "file": file,
-                    "meta": meta,
-                })),
-            )
-                .into_response();
-        }
+    if let Some(hash) = req.hash.as_ref()
+        && let Some(duplicate) = find_duplicate_by_hash(state, hash)
+    {
+        info!(%client_ip, file = %duplicate.file, "chunk upload init detected duplicate hash");
+        return Err((StatusCode::CONFLICT, Json(duplicate)).into_response());
     }
 
     if state.remaining_file_slots(owner_hash.as_str(), now) == 0 {
         warn!(owner_hash = %owner_hash, "chunk upload rejected: active file limit reached");
-        return file_limit_response();
+        return Err(file_limit_response());
     }
 
     let session_id = new_id();
-    let storage_name = make_storage_name(Some(&req.filename));
+    tag_upload_session(&session_id);
+    let trace_parent = current_sentry_trace();
+    let Some(storage_name) = state.unreserved_storage_name(make_storage_name(Some(&req.filename)))
+    else {
+        error!(%client_ip, "chunk upload init failed: no free storage name");
+        return Err(json_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "storage_name",
+            "failed to allocate a storage name",
+        ));
+    };
     if is_forbidden_extension(&storage_name) {
         warn!(
             %client_ip,
             storage = %storage_name,
             "chunk upload init rejected: forbidden storage extension"
         );
-        return json_error(
+        return Err(json_error(
             StatusCode::BAD_REQUEST,
             "bad_filetype",
             "File type not allowed",
-        );
+        ));
     }
     let storage_dir_path = state.chunk_dir.join(&session_id);
     if let Err(err) = fs::create_dir_all(&storage_dir_path).await {
         error!(?err, session_id = %session_id, dir = ?storage_dir_path, "failed to create chunk directory");
-        return json_error(
+        return Err(json_error(
             StatusCode::INTERNAL_SERVER_ERROR,
             "chunk_dir",
             "failed to initialize chunk upload",
-        );
+        ));
     }
 
     let session = Arc::new(ChunkSession {
@@ -348,23 +1196,29 @@ pub async fn init_chunk_upload_handler(
         chunk_size,
         total_chunks,
         hash: req.hash.clone(),
+        max_downloads: req.max_downloads,
+        private: req.private,
         storage_dir: Arc::new(storage_dir_path),
         created: now,
         received: RwLock::new(vec![false; total_chunks as usize]),
-        completed: AtomicBool::new(false),
+        chunk_hashes: RwLock::new(vec![None; total_chunks as usize]),
+        lifecycle: ChunkLifecycle::new(ChunkPhase::Init),
         last_update: AtomicU64::new(now),
         persist_lock: Mutex::new(()),
+        append_lock: Mutex::new(()),
         assembled_chunks: AtomicU32::new(0),
+        trace_parent: trace_parent.clone(),
+        node: Some(state.node_id.to_string()),
     });
     state
         .chunk_sessions
         .insert(session_id.clone(), session.clone());
-    let post_insert_now = now_secs();
+    let post_insert_now = state.now_secs();
     let reserved_after = state.reserved_file_slots(session.owner_hash.as_str(), post_insert_now);
     if reserved_after > MAX_ACTIVE_FILES_PER_IP {
         state.remove_chunk_session(&session_id).await;
         warn!(owner_hash = %session.owner_hash, "chunk upload rejected after init: active file limit reached");
-        return file_limit_response();
+        return Err(file_limit_response());
     }
     if let Err(err) = state
         .persist_chunk_session(&session_id, session.as_ref())
@@ -372,11 +1226,11 @@ pub async fn init_chunk_upload_handler(
     {
         error!(?err, session_id = %session_id, "failed to persist chunk session metadata");
         state.remove_chunk_session(&session_id).await;
-        return json_error(
+        return Err(json_error(
             StatusCode::INTERNAL_SERVER_ERROR,
             "chunk_dir",
             "failed to initialize chunk upload",
-        );
+        ));
     }
 
     info!(
@@ -390,49 +1244,73 @@ pub async fn init_chunk_upload_handler(
         "chunk upload session initialized"
     );
 
-    Json(ChunkInitResponse {
+    Ok(ChunkInitResponse {
         session_id,
         chunk_size,
         total_chunks,
         expires,
         storage_name: storage_name.clone(),
+        ttl_policy: Some(ttl_policy),
+        traceparent: trace_parent
+            .as_deref()
+            .and_then(sentry_trace_to_traceparent),
     })
-    .into_response()
+}
+
+/// Parse `Content-Range: bytes first-last/complete`. Unknown lengths (`*`)
+/// are not accepted, since every chunk's length is fixed at init.
+fn parse_content_range(value: &str) -> Option<(u64, u64, u64)> {
+    let spec = value.trim().strip_prefix("bytes ")?;
+    let (range, complete) = spec.split_once('/')?;
+    let (first, last) = range.split_once('-')?;
+    let first = first.trim().parse().ok()?;
+    let last = last.trim().parse().ok()?;
+    let complete = complete.trim().parse().ok()?;
+    (first <= last).then_some((first, last, complete))
+}
+
+/// `Range: bytes=0-{len - 1}`, telling the client which prefix of a chunk is
+/// stored and where a retry can resume.
+fn with_stored_range(mut resp: Response, len: u64) -> Response {
+    if len > 0
+        && let Ok(value) = HeaderValue::from_str(&format!("bytes=0-{}", len - 1))
+    {
+        resp.headers_mut().insert(RANGE, value);
+    }
+    resp
 }
 
 #[axum::debug_handler]
+#[tracing::instrument(
+    name = "upload.chunk.part",
+    skip(state, headers, body),
+    fields(session = %params.id, index = params.index)
+)]
 pub async fn upload_chunk_part_handler(
     State(state): State<AppState>,
     ConnectInfo(addr): ConnectInfo<ClientAddr>,
     headers: HeaderMap,
     Path(params): Path<ChunkPathParams>,
-    body: Bytes,
+    mut body: Body,
 ) -> Response {
     let client_ip = real_client_ip(&headers, &addr);
-    trace!(%client_ip, session_id = %params.id, index = params.index, size = body.len(), "chunk upload part received");
+    trace!(%client_ip, session_id = %params.id, index = params.index, "chunk upload part received");
+    tag_upload_session(&params.id);
     if state.is_banned(&client_ip).await {
         warn!(%client_ip, session_id = %params.id, "chunk upload part rejected: banned ip");
         return json_error(StatusCode::FORBIDDEN, "banned", "ip banned");
     }
-    let owner_hash = if let Some(hash) = state.hash_ip_to_string(&client_ip) {
-        hash
-    } else {
-        warn!(%client_ip, "chunk upload part rejected: unable to hash ip");
-        return json_error(
-            StatusCode::FORBIDDEN,
-            "invalid_ip",
-            "unable to fingerprint client",
-        );
+    let owner_hash = match request_owner(&state, &headers, &client_ip).await {
+        Ok(owner) => owner,
+        Err(resp) => return resp,
     };
-    let Some(session_ref) = state.chunk_sessions.get(&params.id) else {
+    let Some(session) = state.chunk_session(&params.id).await else {
         return json_error(
             StatusCode::NOT_FOUND,
             "chunk_session",
             "upload session not found",
         );
     };
-    let session = session_ref.value().clone();
-    drop(session_ref);
     session.touch();
     if session.owner_hash != owner_hash {
         return json_error(
@@ -441,12 +1319,9 @@ pub async fn upload_chunk_part_handler(
             "upload session not owned by ip",
         );
     }
-    if session.is_completed() {
-        return json_error(
-            StatusCode::BAD_REQUEST,
-            "completed",
-            "upload session already completed",
-        );
+    if let Err(err) = session.transition(ChunkPhase::Receiving) {
+        debug!(session_id = %params.id, %err, "chunk upload part rejected: session not receiving");
+        return phase_conflict(err.from);
     }
     if params.index >= session.total_chunks {
         return json_error(
@@ -459,24 +1334,255 @@ pub async fn upload_chunk_part_handler(
     if expected == 0 {
         return json_error(StatusCode::BAD_REQUEST, "chunk_size", "invalid chunk size");
     }
-    if body.is_empty() || body.len() as u64 != expected {
-        warn!(session_id = %params.id, owner_hash = %owner_hash, ?expected, got = body.len(), "chunk upload part rejected: length mismatch");
+    let range = match headers.get(CONTENT_RANGE) {
+        None => None,
+        Some(value) => match value.to_str().ok().and_then(parse_content_range) {
+            Some((first, last, complete)) if complete == expected && last < expected => {
+                Some((first, last))
+            }
+            Some(_) => {
+                return json_error(
+                    StatusCode::RANGE_NOT_SATISFIABLE,
+                    "chunk_range",
+                    "Content-Range does not fit this chunk",
+                );
+            }
+            None => {
+                return json_error(
+                    StatusCode::BAD_REQUEST,
+                    "chunk_range",
+                    "malformed Content-Range header",
+                );
+            }
+        },
+    };
+    let (start, len) = match range {
+        Some((first, last)) => (first, last - first + 1),
+        None => (0, expected),
+    };
+    let declared = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok());
+    if declared.is_some_and(|declared| declared != len) {
+        warn!(session_id = %params.id, owner_hash = %owner_hash, ?expected, ?declared, "chunk upload part rejected: length mismatch");
         return json_error(
             StatusCode::BAD_REQUEST,
             "chunk_size",
             "chunk length mismatch",
         );
     }
-    let chunk_path = session
-        .storage_dir
-        .join(format!("{:06}.chunk", params.index));
-    if let Err(err) = fs::write(&chunk_path, &body).await {
-        error!(?err, session_id = %params.id, chunk_index = params.index, path = ?chunk_path, "failed to persist chunk");
-        return json_error(
+    // Without a range the body is the whole chunk, so both fields describe
+    // it. A range carries its own `Content-Digest`; `Repr-Digest` is checked
+    // against the whole chunk once it is complete.
+    let checks = if range.is_some() {
+        DigestCheck::new(&headers, &[CONTENT_DIGEST]).and_then(|content| {
+            let repr = DigestCheck::new(&headers, &[REPR_DIGEST])?;
+            Ok((content, Some(repr).filter(|check| !check.is_empty())))
+        })
+    } else {
+        DigestCheck::new(&headers, &[CONTENT_DIGEST, REPR_DIGEST]).map(|content| (content, None))
+    };
+    let (mut content_check, repr_check) = match checks {
+        Ok(checks) => checks,
+        Err(err) => return digest_error_response(err),
+    };
+    let expected_sha256 = match headers.get(CHUNK_SHA256).map(parse_chunk_sha256) {
+        None => None,
+        Some(Some(hash)) => Some(hash),
+        Some(None) => {
+            return json_error(
+                StatusCode::BAD_REQUEST,
+                "invalid_chunk_hash",
+                "malformed X-Chunk-SHA256 header",
+            );
+        }
+    };
+
+    let chunk_path = session.chunk_path(params.index);
+    let stored = if session
+        .received
+        .read()
+        .await
+        .get(params.index as usize)
+        .copied()
+        .unwrap_or(false)
+    {
+        expected
+    } else {
+        fs::metadata(&chunk_path)
+            .await
+            .map(|m| m.len())
+            .unwrap_or(0)
+            .min(expected)
+    };
+    if start > stored {
+        debug!(session_id = %params.id, chunk_index = params.index, start, stored, "chunk upload part rejected: range leaves a gap");
+        return with_stored_range(
+            json_error(
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                "chunk_range",
+                "range starts past the bytes stored for this chunk",
+            ),
+            stored,
+        );
+    }
+    // `X-Chunk-SHA256` covers the whole chunk: hashed as it arrives when the
+    // body starts at byte 0, read back otherwise.
+    let mut chunk_hasher = expected_sha256
+        .as_ref()
+        .filter(|_| start == 0)
+        .map(|_| Sha256::new());
+    // The chunk is rewritten from `start`, so it is not received until the
+    // new bytes are in.
+    if let Some(entry) = session
+        .received
+        .write()
+        .await
+        .get_mut(params.index as usize)
+    {
+        *entry = false;
+    }
+    let opened = if start == 0 {
+        fs::File::create(&chunk_path).await
+    } else {
+        match fs::OpenOptions::new().append(true).open(&chunk_path).await {
+            Ok(file) => file.set_len(start).await.map(|_| file),
+            Err(err) => Err(err),
+        }
+    };
+    let mut file = match opened {
+        Ok(file) => file,
+        Err(err) => {
+            error!(?err, session_id = %params.id, chunk_index = params.index, path = ?chunk_path, "failed to open chunk");
+            return json_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "chunk_write",
+                "failed to write chunk",
+            );
+        }
+    };
+
+    let write_failed = || {
+        json_error(
             StatusCode::INTERNAL_SERVER_ERROR,
             "chunk_write",
             "failed to write chunk",
-        );
+        )
+    };
+    let mut written = 0u64;
+    let mut interrupted = false;
+    while let Some(frame) = body.frame().await {
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(err) => {
+                debug!(session_id = %params.id, chunk_index = params.index, ?err, written, "chunk upload part body ended early");
+                interrupted = true;
+                break;
+            }
+        };
+        let Ok(data) = frame.into_data() else {
+            continue;
+        };
+        if written + data.len() as u64 > len {
+            let _ = file.set_len(start).await;
+            warn!(session_id = %params.id, owner_hash = %owner_hash, ?expected, "chunk upload part rejected: body longer than the chunk");
+            return json_error(
+                StatusCode::BAD_REQUEST,
+                "chunk_size",
+                "chunk length mismatch",
+            );
+        }
+        if let Err(err) = file.write_all(&data).await {
+            error!(?err, session_id = %params.id, chunk_index = params.index, path = ?chunk_path, "failed to persist chunk");
+            return write_failed();
+        }
+        content_check.update(&data);
+        if let Some(hasher) = chunk_hasher.as_mut() {
+            hasher.update(&data);
+        }
+        written += data.len() as u64;
+    }
+    if let Err(err) = file.flush().await {
+        error!(?err, session_id = %params.id, chunk_index = params.index, path = ?chunk_path, "failed to flush chunk");
+        return write_failed();
+    }
+    if interrupted || written < len {
+        // Keep what arrived; a retry with `Content-Range` only sends the rest.
+        warn!(session_id = %params.id, chunk_index = params.index, written, ?expected, interrupted, "chunk upload part incomplete");
+        let resp = if interrupted {
+            json_error(
+                StatusCode::BAD_REQUEST,
+                "chunk_incomplete",
+                "chunk body ended before the declared length",
+            )
+        } else {
+            json_error(
+                StatusCode::BAD_REQUEST,
+                "chunk_size",
+                "chunk length mismatch",
+            )
+        };
+        return with_stored_range(resp, start + written);
+    }
+    if let Err(err) = content_check.finish() {
+        let _ = file.set_len(start).await;
+        warn!(session_id = %params.id, chunk_index = params.index, %err, "chunk upload part rejected: digest check failed");
+        return digest_error_response(err);
+    }
+    drop(file);
+
+    let end = start + len;
+    let read_back = repr_check.is_some() || (expected_sha256.is_some() && chunk_hasher.is_none());
+    if end == expected && read_back {
+        let chunk = match fs::read(&chunk_path).await {
+            Ok(chunk) => chunk,
+            Err(err) => {
+                error!(?err, session_id = %params.id, chunk_index = params.index, path = ?chunk_path, "failed to read chunk back");
+                return write_failed();
+            }
+        };
+        if let Some(mut check) = repr_check {
+            check.update(&chunk);
+            if let Err(err) = check.finish() {
+                let _ = fs::remove_file(&chunk_path).await;
+                warn!(session_id = %params.id, chunk_index = params.index, %err, "chunk rejected: assembled chunk does not match its digest");
+                return digest_error_response(err);
+            }
+        }
+        if expected_sha256.is_some() && chunk_hasher.is_none() {
+            chunk_hasher = Some(Sha256::new_with_prefix(&chunk));
+        }
+    }
+    if end == expected
+        && let (Some(wanted), Some(hasher)) = (expected_sha256.as_deref(), chunk_hasher)
+    {
+        let actual = format!("{:x}", hasher.finalize());
+        if actual != wanted {
+            let _ = fs::remove_file(&chunk_path).await;
+            warn!(session_id = %params.id, chunk_index = params.index, expected = %wanted, %actual, "chunk rejected: does not match X-Chunk-SHA256");
+            return json_error(
+                StatusCode::BAD_REQUEST,
+                "chunk_hash_mismatch",
+                "chunk does not match X-Chunk-SHA256",
+            );
+        }
+    }
+    if end < expected {
+        debug!(session_id = %params.id, chunk_index = params.index, end, ?expected, "chunk upload part stored; chunk not complete yet");
+        let resp = Response::builder()
+            .status(StatusCode::ACCEPTED)
+            .body(Body::empty())
+            .unwrap();
+        return with_stored_range(resp, end);
+    }
+    if let Some(entry) = session
+        .chunk_hashes
+        .write()
+        .await
+        .get_mut(params.index as usize)
+    {
+        *entry = expected_sha256;
     }
     {
         let mut received = session.received.write().await;
@@ -494,18 +1600,19 @@ pub async fn upload_chunk_part_handler(
         session_id = %params.id,
         owner_hash = %owner_hash,
         chunk_index = params.index,
+        resumed_from = start,
         "chunk upload part stored"
     );
     Response::builder()
         .status(StatusCode::NO_CONTENT)
-        .body(axum::body::Body::empty())
+        .body(Body::empty())
         .unwrap()
 }
 
 #[axum::debug_handler]
 #[tracing::instrument(
     name = "upload.chunk.complete",
-    skip(state, headers, req),
+    skip(state, headers, locale, req),
     fields(
         client_ip = tracing::field::Empty,
         session = %path.id,
@@ -519,35 +1626,29 @@ pub async fn complete_chunk_upload_handler(
     ConnectInfo(addr): ConnectInfo<ClientAddr>,
     headers: HeaderMap,
     Path(path): Path<ChunkCompletePath>,
+    AxumQuery(locale): AxumQuery<LocaleQuery>,
     Json(req): Json<ChunkCompleteRequest>,
 ) -> Response {
     let client_ip = real_client_ip(&headers, &addr);
     tracing::Span::current().record("client_ip", tracing::field::display(&client_ip));
     trace!(%client_ip, session_id = %path.id, "chunk completion requested");
+    tag_upload_session(&path.id);
     if state.is_banned(&client_ip).await {
         warn!(%client_ip, session_id = %path.id, "chunk completion rejected: banned ip");
         return json_error(StatusCode::FORBIDDEN, "banned", "ip banned");
     }
-    let owner_hash = if let Some(hash) = state.hash_ip_to_string(&client_ip) {
-        hash
-    } else {
-        warn!(%client_ip, session_id = %path.id, "chunk completion rejected: unable to hash ip");
-        return json_error(
-            StatusCode::FORBIDDEN,
-            "invalid_ip",
-            "unable to fingerprint client",
-        );
+    let owner_hash = match request_owner(&state, &headers, &client_ip).await {
+        Ok(owner) => owner,
+        Err(resp) => return resp,
     };
     tracing::Span::current().record("owner_hash", tracing::field::display(&owner_hash));
-    let Some(session_entry) = state.chunk_sessions.get(&path.id) else {
+    let Some(session) = state.chunk_session(&path.id).await else {
         return json_error(
             StatusCode::NOT_FOUND,
             "chunk_session",
             "upload session not found",
         );
     };
-    let session = session_entry.value().clone();
-    drop(session_entry);
     if session.owner_hash != owner_hash {
         warn!(session_id = %path.id, owner_hash = %owner_hash, "chunk completion rejected: ownership mismatch");
         return json_error(
@@ -556,35 +1657,60 @@ pub async fn complete_chunk_upload_handler(
             "upload session not owned by ip",
         );
     }
+    let locale = Locale::for_request(&headers, &locale).await;
+    let (size, chunk_size) = (session.total_bytes, session.chunk_size);
+    let resp = finalize_chunk_session(
+        &state,
+        &client_ip,
+        &path.id,
+        session,
+        req.hash.as_deref(),
+        &locale,
+    )
+    .await;
+    if resp.status().is_success() {
+        state.record_upload_client(&headers, UploadMethod::Chunked, size, Some(chunk_size));
+    }
+    resp
+}
+
+/// Assemble a fully received session into the file store and register the
+/// file. Shared by the chunk API and tus once the last byte arrives.
+pub(crate) async fn finalize_chunk_session(
+    state: &AppState,
+    client_ip: &str,
+    session_id: &str,
+    session: Arc<ChunkSession>,
+    expected_hash: Option<&str>,
+    locale: &Locale,
+) -> Response {
     if is_forbidden_extension(&session.original_name)
         || is_forbidden_extension(&session.storage_name)
     {
         warn!(
-            session_id = %path.id,
-            owner_hash = %owner_hash,
+            session_id = %session_id,
+            owner_hash = %session.owner_hash,
             original = %session.original_name,
             storage = %session.storage_name,
             "chunk completion rejected: forbidden file extension"
         );
-        state.remove_chunk_session(&path.id).await;
+        let _ = session.transition(ChunkPhase::Failed);
+        state.remove_chunk_session(session_id).await;
         return json_error(
             StatusCode::BAD_REQUEST,
             "bad_filetype",
             "File type not allowed",
         );
     }
-    if session.is_completed() {
-        debug!(session_id = %path.id, "chunk completion called on already completed session");
-        return json_error(
-            StatusCode::BAD_REQUEST,
-            "completed",
-            "upload session already completed",
-        );
+    if session.phase().is_terminal() {
+        debug!(session_id = %session_id, phase = ?session.phase(), "chunk completion called on finished session");
+        return phase_conflict(session.phase());
     }
+    sync_stored_chunks(&session).await;
     {
         let received = session.received.read().await;
         if received.iter().any(|r| !*r) {
-            warn!(session_id = %path.id, "chunk completion rejected: missing chunks");
+            warn!(session_id = %session_id, "chunk completion rejected: missing chunks");
             return json_error(
                 StatusCode::BAD_REQUEST,
                 "incomplete",
@@ -592,8 +1718,33 @@ pub async fn complete_chunk_upload_handler(
             );
         }
     }
-    let ttl = ttl_to_duration(&session.ttl_code).as_secs();
-    let expires = session.created + ttl;
+    if !layout_matches_size(&session) {
+        warn!(
+            session_id = %session_id,
+            total_bytes = session.total_bytes,
+            chunk_size = session.chunk_size,
+            total_chunks = session.total_chunks,
+            "chunk completion rejected: chunk layout does not add up to declared size"
+        );
+        let _ = session.transition(ChunkPhase::Failed);
+        state.remove_chunk_session(session_id).await;
+        return size_mismatch();
+    }
+    // Claiming the session here is what makes a second concurrent complete
+    // (or one racing a cancel) lose instead of assembling the file twice.
+    if let Err(err) = session.transition(ChunkPhase::Assembling) {
+        warn!(session_id = %session_id, %err, "chunk completion rejected: session not receiving");
+        return phase_conflict(err.from);
+    }
+    let tmp_path = assembly_temp_path(&state.upload_dir, &session.storage_name);
+    let mut guard = AssemblyGuard::new(session.clone(), tmp_path.clone());
+    if let Err(err) = state
+        .persist_chunk_session(session_id, session.as_ref())
+        .await
+    {
+        warn!(?err, session_id = %session_id, "failed to persist assembling chunk session");
+    }
+    let expires = session.expires;
     let permit = match state.upload_sem.clone().acquire_owned().await {
         Ok(p) => p,
         Err(_) => {
@@ -606,158 +1757,105 @@ pub async fn complete_chunk_upload_handler(
     };
     let storage_name = session.storage_name.clone();
     tracing::Span::current().record("storage", tracing::field::display(&storage_name));
-    let final_path = state.upload_dir.join(&storage_name);
-    let mut tmp_path = final_path.clone();
-    tmp_path.set_extension("part");
     let start = tokio::time::Instant::now();
-    let mut file = match fs::File::create(&tmp_path).await {
-        Ok(f) => f,
-        Err(err) => {
-            drop(permit);
-            error!(?err, ?tmp_path, session_id = %path.id, "failed to create assembled file");
-            return json_error(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "final_create",
-                "failed to assemble upload",
-            );
-        }
+    // Size the output up front so every chunk can be written at its offset.
+    let created = match fs::File::create(&tmp_path).await {
+        Ok(file) => file.set_len(session.total_bytes).await,
+        Err(err) => Err(err),
     };
+    if let Err(err) = created {
+        drop(permit);
+        error!(?err, ?tmp_path, session_id = %session_id, "failed to create assembled file");
+        return json_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "final_create",
+            "failed to assemble upload",
+        );
+    }
     session.assembled_chunks.store(0, Ordering::Relaxed);
-    let mut hasher = Sha256::new();
-    let mut detector_buf = Vec::with_capacity(INFER_SAMPLE_BYTES);
-    let mut chunk_buf = Vec::with_capacity(session.chunk_size as usize);
     let open_elapsed = start.elapsed();
-    debug!(session = %path.id, elapsed_ms = open_elapsed.as_millis(), "chunk completion: file create ready");
-    for idx in 0..session.total_chunks {
-        let chunk_start = tokio::time::Instant::now();
-        let chunk_path = session.storage_dir.join(format!("{:06}.chunk", idx));
-        let mut chunk_file = match fs::File::open(&chunk_path).await {
-            Ok(f) => f,
-            Err(err) => {
-                drop(permit);
-                let _ = fs::remove_file(&tmp_path).await;
-                error!(?err, ?chunk_path, session_id = %path.id, chunk = idx, "missing chunk during assembly");
-                return json_error(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "chunk_missing",
-                    "missing chunk during assembly",
-                );
-            }
-        };
-        let expected_len = expected_chunk_len(&session, idx);
-        chunk_buf.resize(expected_len as usize, 0);
-        if let Err(err) = chunk_file.read_exact(&mut chunk_buf).await {
-            drop(permit);
-            let _ = fs::remove_file(&tmp_path).await;
-            let code = if err.kind() == std::io::ErrorKind::UnexpectedEof {
-                error!(
-                    actual = chunk_buf.len(),
-                    expected = expected_len,
-                    ?chunk_path,
-                    session_id = %path.id,
-                    chunk = idx,
-                    "chunk length mismatch during assembly"
-                );
-                json_error(
-                    StatusCode::BAD_REQUEST,
-                    "chunk_size",
-                    "chunk length mismatch",
-                )
-            } else {
-                error!(?err, ?chunk_path, session_id = %path.id, chunk = idx, "failed reading chunk");
-                json_error(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "chunk_read",
-                    "failed reading chunk data",
-                )
-            };
-            return code;
-        }
-        if detector_buf.len() < INFER_SAMPLE_BYTES {
-            let take = std::cmp::min(chunk_buf.len(), INFER_SAMPLE_BYTES - detector_buf.len());
-            detector_buf.extend_from_slice(&chunk_buf[..take]);
-        }
-        if let Err(err) = file.write_all(&chunk_buf).await {
+    debug!(session = %session_id, elapsed_ms = open_elapsed.as_millis(), "chunk completion: file create ready");
+    let assembled = match assemble_chunks(&session, &tmp_path).await {
+        Ok(assembled) => assembled,
+        Err(err) => {
             drop(permit);
-            let _ = fs::remove_file(&tmp_path).await;
-            error!(?err, ?chunk_path, session_id = %path.id, chunk = idx, "failed writing assembled file");
-            return json_error(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "write",
-                "failed writing assembled file",
-            );
+            return err.into_response(session_id);
         }
-        hasher.update(&chunk_buf);
-        session.assembled_chunks.store(
-            std::cmp::min(idx + 1, session.total_chunks),
-            Ordering::Relaxed,
-        );
-        let chunk_elapsed = chunk_start.elapsed();
-        if chunk_elapsed.as_millis() >= 25 {
-            warn!(session = %path.id, chunk = idx, elapsed_ms = chunk_elapsed.as_millis(), "chunk completion: slow chunk assembly");
-        }
-    }
+    };
     let assemble_elapsed = start.elapsed();
-    debug!(session = %path.id, elapsed_ms = assemble_elapsed.as_millis(), "chunk completion: chunks assembled");
-    if let Some(kind) = infer::get(&detector_buf) {
-        let ext = kind.extension();
-        if FORBIDDEN_EXTENSIONS.contains(&ext) {
-            let detected_ext = ext.to_string();
-            let detected_mime = kind.mime_type().to_string();
-            drop(file);
-            drop(permit);
-            let _ = fs::remove_file(&tmp_path).await;
-            state.remove_chunk_session(&path.id).await;
-            warn!(
-                session = %path.id,
-                storage = %storage_name,
-                detected_ext = %detected_ext,
-                detected_mime = %detected_mime,
-                "chunk completion rejected: forbidden file content"
-            );
-            return json_error(
-                StatusCode::BAD_REQUEST,
-                "bad_filetype",
-                "File type not allowed (forbidden content)",
-            );
-        }
-    }
-    if file.flush().await.is_err() {
+    debug!(session = %session_id, elapsed_ms = assemble_elapsed.as_millis(), "chunk completion: chunks assembled");
+    let assembled_bytes = assembled.bytes;
+    if assembled_bytes != session.total_bytes {
         drop(permit);
-        let _ = fs::remove_file(&tmp_path).await;
-        return json_error(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "flush",
-            "failed finalizing upload",
+        warn!(
+            session_id = %session_id,
+            assembled_bytes,
+            declared = session.total_bytes,
+            "chunk completion rejected: assembled size differs from declared size"
         );
+        let _ = session.transition(ChunkPhase::Failed);
+        state.remove_chunk_session(session_id).await;
+        return size_mismatch();
     }
-    if fs::rename(&tmp_path, &final_path).await.is_err() {
+    let scanned = state
+        .content_scanners
+        .scan(&ScanInput {
+            name: Some(&session.original_name),
+            sample: &assembled.sample,
+            size: assembled_bytes,
+            content: ScanContent::File(&tmp_path),
+        })
+        .await;
+    if let Err(rejection) = scanned {
         drop(permit);
         let _ = fs::remove_file(&tmp_path).await;
-        return json_error(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "move",
-            "failed finalizing upload",
+        let _ = session.transition(ChunkPhase::Failed);
+        state.remove_chunk_session(session_id).await;
+        warn!(
+            session = %session_id,
+            storage = %storage_name,
+            scanner = rejection.scanner,
+            "chunk completion rejected by content scanner"
         );
+        return rejection_response(rejection);
     }
+    let storage_name = match state
+        .store_new_file(storage_name, NewFileBody::Spooled(&tmp_path))
+        .await
+    {
+        Ok(name) => name,
+        Err(err) => {
+            drop(permit);
+            error!(?err, ?tmp_path, session_id = %session_id, "failed to move assembled file into storage");
+            return json_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "move",
+                "failed finalizing upload",
+            );
+        }
+    };
+    tracing::Span::current().record("storage", tracing::field::display(&storage_name));
+    guard.disarm();
     drop(permit);
+    if let Err(err) = session.transition(ChunkPhase::Verifying) {
+        let _ = state.file_store.delete(&storage_name).await;
+        warn!(session_id = %session_id, %err, "chunk completion aborted after assembly");
+        return phase_conflict(err.from);
+    }
     session
         .assembled_chunks
         .store(session.total_chunks, Ordering::Relaxed);
     let finalize_elapsed = start.elapsed();
-    debug!(session = %path.id, elapsed_ms = finalize_elapsed.as_millis(), "chunk completion: file moved");
+    debug!(session = %session_id, elapsed_ms = finalize_elapsed.as_millis(), "chunk completion: file moved");
 
-    let digest = format!("{:x}", hasher.finalize());
-    let expected_hash = req
-        .hash
-        .as_ref()
-        .or_else(|| session.hash.as_ref())
-        .map(|s| s.as_str());
+    let digest = assembled.digest;
+    let expected_hash = expected_hash.or(session.hash.as_deref());
     if let Some(exp) = expected_hash {
         tracing::Span::current().record("expected_hash", tracing::field::display(exp));
         if exp != digest {
-            let _ = fs::remove_file(&final_path).await;
-            state.remove_chunk_session(&path.id).await;
+            let _ = state.file_store.delete(&storage_name).await;
+            let _ = session.transition(ChunkPhase::Failed);
+            state.remove_chunk_session(session_id).await;
             return json_error(
                 StatusCode::BAD_REQUEST,
                 "hash_mismatch",
@@ -765,67 +1863,127 @@ pub async fn complete_chunk_upload_handler(
             );
         }
     }
-    if let Some((existing, meta)) = find_duplicate_by_hash(&state, &digest) {
-        let _ = fs::remove_file(&final_path).await;
-        state.remove_chunk_session(&path.id).await;
-        return (
-            StatusCode::CONFLICT,
-            Json(json!({
-                "duplicate": true,
-                "file": existing,
-                "meta": meta,
-            })),
-        )
-            .into_response();
+    if let Some(duplicate) = find_duplicate_by_hash(state, &digest) {
+        let _ = state.file_store.delete(&storage_name).await;
+        let _ = session.transition(ChunkPhase::Failed);
+        state.remove_chunk_session(session_id).await;
+        return (StatusCode::CONFLICT, Json(duplicate)).into_response();
     }
 
     let meta = FileMeta {
         owner_hash: session.owner_hash.clone(),
         expires,
         original: session.original_name.clone(),
-        created: now_secs(),
+        original_display: display_original_name(&session.original_name),
+        created: state.now_secs(),
         hash: digest.clone(),
+        max_downloads: session.max_downloads,
+        downloads: 0,
+        private: session.private,
+        ttl_shortened_from: None,
+        guest: false,
+        network_class: Some(state.network_class(client_ip)),
+        size: session.total_bytes,
     };
-    session.mark_completed();
+    if let Err(err) = session.mark_completed() {
+        let _ = state.file_store.delete(&storage_name).await;
+        warn!(session_id = %session_id, %err, "chunk completion aborted before finalizing");
+        return phase_conflict(err.from);
+    }
     if let Err(err) = state
-        .persist_chunk_session(&path.id, session.as_ref())
+        .persist_chunk_session(session_id, session.as_ref())
         .await
     {
-        warn!(?err, session_id = %path.id, "failed to persist completed chunk session before cleanup");
+        warn!(?err, session_id = %session_id, "failed to persist completed chunk session before cleanup");
     }
-    state.owners.insert(storage_name.clone(), meta);
+    state.insert_owner(storage_name.clone(), meta);
+    state.storage.record_stored(session.total_bytes);
+    state
+        .transparency
+        .record(&digest, session.total_bytes)
+        .await;
+    let screening = state.screen_upload(&storage_name, &digest).await;
     let persist_start = tokio::time::Instant::now();
-    state.spawn_persist_owners();
+    spawn_completion_jobs(
+        state.clone(),
+        session_id.to_string(),
+        session.trace_parent.clone(),
+    );
     let persist_latency = persist_start.elapsed();
-    debug!(session = %path.id, elapsed_us = persist_latency.as_micros(), "chunk completion: spawned owner persist");
+    debug!(session = %session_id, elapsed_us = persist_latency.as_micros(), "chunk completion: spawned owner persist");
     let cleanup_start = tokio::time::Instant::now();
-    state.remove_chunk_session(&path.id).await;
+    state.remove_chunk_session(session_id).await;
     let cleanup_elapsed = cleanup_start.elapsed();
     if cleanup_elapsed.as_millis() >= 50 {
-        warn!(session = %path.id, elapsed_ms = cleanup_elapsed.as_millis(), "chunk completion: slow cleanup");
+        warn!(session = %session_id, elapsed_ms = cleanup_elapsed.as_millis(), "chunk completion: slow cleanup");
     } else {
-        debug!(session = %path.id, elapsed_ms = cleanup_elapsed.as_millis(), "chunk completion: cleanup complete");
+        debug!(session = %session_id, elapsed_ms = cleanup_elapsed.as_millis(), "chunk completion: cleanup complete");
     }
     info!(
         %client_ip,
-        session_id = %path.id,
+        session_id = %session_id,
         storage = %storage_name,
         size = session.total_bytes,
         hash = %digest,
         total_ms = start.elapsed().as_millis(),
         "chunk completion finished"
     );
-    spawn_integrity_check(state.clone());
 
+    if let Screening::Infected(signature) = &screening {
+        warn!(session_id = %session_id, storage = %storage_name, %signature, "chunked upload rejected: virus detected");
+        return virus_detected_response();
+    }
+    let files = if screening.is_quarantined() {
+        Vec::new()
+    } else {
+        state.notify_upload_completed(&storage_name);
+        state.owner_events.publish(
+            &session.owner_hash,
+            OwnerEvent::AssemblyCompleted {
+                session_id: session_id.to_string(),
+                file: storage_name.clone(),
+            },
+        );
+        vec![storage_name]
+    };
     Json(UploadResponse {
-        files: vec![storage_name],
+        uploaded: uploaded_entries(state, locale, &files),
+        files,
         truncated: false,
         remaining: 0,
         limit_reached: false,
+        ttl_policy: None,
     })
     .into_response()
 }
 
+/// Persist owners and re-check storage after a chunked upload completes, under
+/// its own transaction in the upload's trace so the work shows up alongside the
+/// requests that led to it.
+fn spawn_completion_jobs(state: AppState, session_id: String, trace_parent: Option<String>) {
+    let hub = sentry::Hub::new_from_top(sentry::Hub::current());
+    tokio::spawn(sentry::SentryFutureExt::bind_hub(
+        async move {
+            let transaction = start_upload_transaction(
+                "upload.chunk.finalize",
+                &session_id,
+                trace_parent.as_deref(),
+            );
+            // Created after the transaction is bound so it nests under it
+            // rather than under the already finished request.
+            let span = tracing::info_span!("upload.chunk.finalize", session = %session_id);
+            async {
+                state.persist_owners().await;
+                check_storage_integrity(&state).await;
+            }
+            .instrument(span)
+            .await;
+            transaction.finish();
+        },
+        hub,
+    ));
+}
+
 #[axum::debug_handler]
 pub async fn cancel_chunk_upload_handler(
     State(state): State<AppState>,
@@ -835,35 +1993,33 @@ pub async fn cancel_chunk_upload_handler(
 ) -> Response {
     let client_ip = real_client_ip(&headers, &addr);
     trace!(%client_ip, session_id = %path.id, "chunk cancel requested");
+    tag_upload_session(&path.id);
     if state.is_banned(&client_ip).await {
         warn!(%client_ip, session_id = %path.id, "chunk cancel rejected: banned ip");
         return json_error(StatusCode::FORBIDDEN, "banned", "ip banned");
     }
-    let owner_hash = if let Some(hash) = state.hash_ip_to_string(&client_ip) {
-        hash
-    } else {
-        warn!(%client_ip, session_id = %path.id, "chunk cancel rejected: unable to hash ip");
-        return json_error(
-            StatusCode::FORBIDDEN,
-            "invalid_ip",
-            "unable to fingerprint client",
-        );
+    let owner_hash = match request_owner(&state, &headers, &client_ip).await {
+        Ok(owner) => owner,
+        Err(resp) => return resp,
     };
-    let Some(entry) = state.chunk_sessions.get(&path.id) else {
+    let Some(session) = state.chunk_session(&path.id).await else {
         return json_error(
             StatusCode::NOT_FOUND,
             "chunk_session",
             "upload session not found",
         );
     };
-    if entry.value().owner_hash != owner_hash {
+    if session.owner_hash != owner_hash {
         return json_error(
             StatusCode::FORBIDDEN,
             "not_owner",
             "upload session not owned by ip",
         );
     }
-    drop(entry);
+    if let Err(err) = session.transition(ChunkPhase::Failed) {
+        debug!(session_id = %path.id, %err, "chunk cancel rejected");
+        return phase_conflict(err.from);
+    }
     state.remove_chunk_session(&path.id).await;
     info!(%client_ip, session_id = %path.id, "chunk session cancelled");
     Response::builder()
@@ -891,30 +2047,23 @@ pub async fn chunk_status_handler(
     let client_ip = real_client_ip(&headers, &addr);
     tracing::Span::current().record("client_ip", tracing::field::display(&client_ip));
     trace!(%client_ip, session_id = %path.id, "chunk status requested");
+    tag_upload_session(&path.id);
     if state.is_banned(&client_ip).await {
         warn!(%client_ip, session_id = %path.id, "chunk status rejected: banned ip");
         return json_error(StatusCode::FORBIDDEN, "banned", "ip banned");
     }
-    let owner_hash = if let Some(hash) = state.hash_ip_to_string(&client_ip) {
-        hash
-    } else {
-        warn!(%client_ip, session_id = %path.id, "chunk status rejected: unable to hash ip");
-        return json_error(
-            StatusCode::FORBIDDEN,
-            "invalid_ip",
-            "unable to fingerprint client",
-        );
+    let owner_hash = match request_owner(&state, &headers, &client_ip).await {
+        Ok(owner) => owner,
+        Err(resp) => return resp,
     };
     tracing::Span::current().record("owner_hash", tracing::field::display(&owner_hash));
-    let Some(session_entry) = state.chunk_sessions.get(&path.id) else {
+    let Some(session) = state.chunk_session(&path.id).await else {
         return json_error(
             StatusCode::NOT_FOUND,
             "chunk_session",
             "upload session not found",
         );
     };
-    let session = session_entry.value().clone();
-    drop(session_entry);
     if session.owner_hash != owner_hash {
         return json_error(
             StatusCode::FORBIDDEN,
@@ -924,11 +2073,79 @@ pub async fn chunk_status_handler(
     }
     let total = session.total_chunks;
     let assembled = session.assembled_chunks.load(Ordering::Relaxed).min(total);
-    debug!(%client_ip, session_id = %path.id, total, assembled, completed = session.is_completed(), "chunk status returned");
+    let received = session.received.read().await.clone();
+    let missing = missing_chunk_runs(&received);
+    debug!(%client_ip, session_id = %path.id, total, assembled, missing_runs = missing.len(), completed = session.is_completed(), "chunk status returned");
     Json(ChunkStatusResponse {
         total_chunks: total,
         assembled_chunks: assembled,
         completed: session.is_completed() && assembled >= total,
+        state: session.phase(),
+        received,
+        missing,
+    })
+    .into_response()
+}
+
+/// `GET /chunk/sessions`: the caller's chunk and tus sessions that have not
+/// finished, oldest first, so a client that lost track of them can resume.
+#[axum::debug_handler]
+#[tracing::instrument(
+    name = "upload.chunk.sessions",
+    skip(state, headers),
+    fields(client_ip = tracing::field::Empty, owner_hash = tracing::field::Empty)
+)]
+pub async fn list_chunk_sessions_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<ClientAddr>,
+    headers: HeaderMap,
+) -> Response {
+    let client_ip = real_client_ip(&headers, &addr);
+    tracing::Span::current().record("client_ip", tracing::field::display(&client_ip));
+    if state.is_banned(&client_ip).await {
+        warn!(%client_ip, "chunk session list rejected: banned ip");
+        return json_error(StatusCode::FORBIDDEN, "banned", "ip banned");
+    }
+    let owner_hash = match request_owner(&state, &headers, &client_ip).await {
+        Ok(owner) => owner,
+        Err(resp) => return resp,
+    };
+    tracing::Span::current().record("owner_hash", tracing::field::display(&owner_hash));
+    let now = state.now_secs();
+    let owned: Vec<(String, Arc<ChunkSession>)> = state
+        .chunk_sessions
+        .iter()
+        .filter(|entry| entry.value().owner_hash == owner_hash)
+        .map(|entry| (entry.key().clone(), entry.value().clone()))
+        .collect();
+    let mut sessions = Vec::with_capacity(owned.len());
+    for (session_id, session) in owned {
+        let state = session.phase();
+        let expires = session.abandoned_at();
+        if matches!(state, ChunkPhase::Finalized | ChunkPhase::Failed) || expires <= now {
+            continue;
+        }
+        sessions.push((
+            session.created,
+            ChunkSessionEntry {
+                session_id,
+                filename: session.original_name.clone(),
+                size: session.total_bytes,
+                chunk_size: session.chunk_size,
+                total_chunks: session.total_chunks,
+                received: session.received.read().await.clone(),
+                expires,
+                state,
+            },
+        ));
+    }
+    sessions.sort_by(|a, b| {
+        a.0.cmp(&b.0)
+            .then_with(|| a.1.session_id.cmp(&b.1.session_id))
+    });
+    debug!(%client_ip, count = sessions.len(), "chunk sessions listed");
+    Json(ChunkSessionsResponse {
+        sessions: sessions.into_iter().map(|(_, entry)| entry).collect(),
     })
     .into_response()
 }
@@ -939,9 +2156,9 @@ pub async fn checkhash_handler(
     AxumQuery(query): AxumQuery<CheckHashQuery>,
 ) -> Response {
     let exists = state
-        .owners
-        .iter()
-        .any(|entry| entry.value().hash == query.hash);
+        .owners_snapshot()
+        .file_with_hash(&query.hash)
+        .is_some();
     debug!(hash = %query.hash, exists, "hash check performed");
     Json(json!({ "exists": exists })).into_response()
 }
@@ -949,13 +2166,14 @@ pub async fn checkhash_handler(
 #[axum::debug_handler]
 #[tracing::instrument(
     name = "upload.multipart",
-    skip(state, headers, multipart),
+    skip(state, headers, locale, multipart),
     fields(client_ip = tracing::field::Empty)
 )]
 pub async fn upload_handler(
     State(state): State<AppState>,
     ConnectInfo(addr): ConnectInfo<ClientAddr>,
     headers: HeaderMap,
+    AxumQuery(locale): AxumQuery<LocaleQuery>,
     mut multipart: Multipart,
 ) -> Response {
     let client_ip = real_client_ip(&headers, &addr);
@@ -965,16 +2183,18 @@ pub async fn upload_handler(
         warn!(%client_ip, "upload rejected: banned ip");
         return json_error(StatusCode::FORBIDDEN, "banned", "ip banned");
     }
-    let owner_hash = if let Some(hash) = state.hash_ip_to_string(&client_ip) {
-        hash
-    } else {
-        warn!(%client_ip, "upload rejected: unable to hash ip");
-        return json_error(
-            StatusCode::FORBIDDEN,
-            "invalid_ip",
-            "unable to fingerprint client",
-        );
+    let owner_hash = match request_owner(&state, &headers, &client_ip).await {
+        Ok(owner) => owner,
+        Err(resp) => return resp,
     };
+    if state.drain.is_draining() {
+        warn!(%client_ip, "upload rejected: server draining");
+        return draining_response();
+    }
+    if state.storage.is_full() {
+        warn!(%client_ip, "upload rejected: storage full");
+        return storage_full_response();
+    }
     let sem = state.upload_sem.clone();
     let _permit = match sem.try_acquire_owned() {
         Ok(p) => p,
@@ -987,10 +2207,11 @@ pub async fn upload_handler(
         }
     };
 
-    let mut ttl_code = "24h".to_string();
-    let mut files_to_process = Vec::new();
+    let mut ttl_code = None;
+    let mut max_downloads = None;
+    let mut private = false;
+    let mut guest = false;
     let mut pending_files = Vec::new();
-    let mut forbidden_error: Option<String> = None;
 
     loop {
         let field = match multipart.next_field().await {
@@ -1011,73 +2232,57 @@ pub async fn upload_handler(
             continue;
         };
         if name == "ttl" {
-            if let Ok(data) = field.bytes().await {
-                if let Ok(s) = std::str::from_utf8(&data) {
-                    ttl_code = s.to_string();
-                }
+            if let Ok(data) = field.bytes().await
+                && let Ok(s) = std::str::from_utf8(&data)
+            {
+                ttl_code = Some(s.to_string());
             }
             continue;
         }
-        if name.starts_with("file") {
-            let original_name = field.file_name().map(|s| s.to_string());
-            let content_type = field.content_type().map(|m| m.to_string());
-            if let Ok(data) = field.bytes().await {
-                if !data.is_empty() {
-                    pending_files.push((original_name, content_type, data));
-                }
+        if name == "max_downloads" {
+            let raw = field.text().await.unwrap_or_default();
+            match parse_max_downloads(&raw) {
+                Ok(value) => max_downloads = value,
+                Err(_) => return invalid_max_downloads(),
             }
+            continue;
         }
-    }
-
-    let mut has_forbidden = false;
-    for (original_name, _content_type, data) in &pending_files {
-        if let Some(orig) = original_name {
-            if is_forbidden_extension(orig) {
-                tracing::warn!(?original_name, "Upload rejected: forbidden file extension");
-                forbidden_error = Some("File type not allowed (forbidden extension)".to_string());
-                has_forbidden = true;
-                break;
-            }
+        if name == "private" {
+            private = flag_enabled(&field.text().await.unwrap_or_default());
+            continue;
         }
-        let is_forbidden_content = if let Some(kind) = infer::get(&data) {
-            let ext = kind.extension();
-            FORBIDDEN_EXTENSIONS.contains(&ext)
-        } else {
-            false
-        };
-        if is_forbidden_content {
-            tracing::warn!(
-                ?original_name,
-                "Upload rejected: forbidden file content detected by infer"
-            );
-            forbidden_error = Some("File type not allowed (forbidden content)".to_string());
-            has_forbidden = true;
-            break;
+        if name == "guest" {
+            guest = flag_enabled(&field.text().await.unwrap_or_default());
+            continue;
+        }
+        if name.starts_with("file") {
+            let spooled = match spool_field(&state, field).await {
+                Ok(spooled) => spooled,
+                Err(resp) => return resp,
+            };
+            if spooled.size > 0 {
+                pending_files.push(spooled);
+            }
         }
     }
 
-    for (original_name, _content_type, data) in pending_files {
-        files_to_process.push((original_name, data));
-    }
-
-    if has_forbidden {
-        let msg = forbidden_error.unwrap_or_else(|| "File type not allowed".to_string());
-        return json_error(
-            StatusCode::BAD_REQUEST,
-            "bad_filetype",
-            match msg.as_str() {
-                "File type not allowed (forbidden content)" => {
-                    "File type not allowed (forbidden content)"
-                }
-                "File type not allowed (forbidden MIME type)" => {
-                    "File type not allowed (forbidden MIME type)"
-                }
-                _ => "File type not allowed",
-            },
-        );
+    for spooled in &pending_files {
+        let scanned = state
+            .content_scanners
+            .scan(&ScanInput {
+                name: spooled.original_name.as_deref(),
+                sample: &spooled.sniff,
+                size: spooled.size,
+                content: ScanContent::File(&spooled.path),
+            })
+            .await;
+        if let Err(rejection) = scanned {
+            tracing::warn!(original_name = ?spooled.original_name, scanner = rejection.scanner, "Upload rejected by content scanner");
+            return rejection_response(rejection);
+        }
     }
 
-    if files_to_process.is_empty() {
+    if pending_files.is_empty() {
         return json_error(
             StatusCode::BAD_REQUEST,
             "no_files",
@@ -1085,38 +2290,45 @@ pub async fn upload_handler(
         );
     }
 
+    let tier = SlotTier::requested(guest);
+    if tier == SlotTier::Guest && !pending_files.iter().all(|f| tier.admits(f.size)) {
+        tracing::warn!(owner_hash = %owner_hash, "Upload rejected: too large for guest tier");
+        return guest_too_large_response();
+    }
     cleanup_expired(&state).await;
-    let now = now_secs();
-    let mut slots_remaining = state.remaining_file_slots(owner_hash.as_str(), now);
+    let now = state.now_secs();
+    let mut slots_remaining = state.remaining_slots_in(owner_hash.as_str(), tier, now);
     if slots_remaining == 0 {
-        tracing::warn!(owner_hash = %owner_hash, "Upload rejected: active file limit reached");
-        return file_limit_response();
+        tracing::warn!(owner_hash = %owner_hash, ?tier, "Upload rejected: active file limit reached");
+        return slot_limit_response(tier);
+    }
+    let incoming: u64 = pending_files.iter().map(|f| f.size).sum();
+    if !state.owner_quota_allows(owner_hash.as_str(), incoming, now) {
+        tracing::warn!(owner_hash = %owner_hash, incoming, "Upload rejected: owner quota reached");
+        return owner_quota_response(&state);
     }
-    let ttl = ttl_to_duration(&ttl_code).as_secs();
-    let expires = now + ttl;
+    let attrs = ClientAttributes::from_request(&state, &headers, &addr, &client_ip);
+    let ttl_policy = tier.apply(state.ttl_policy.resolve(&attrs, ttl_code.as_deref(), "24h"));
+    let expires = now + ttl_policy.ttl;
     let mut saved_files = Vec::new();
     let mut duplicate_info = None;
     let mut limit_reached = false;
+    let mut virus_detected = false;
 
-    for (original_name, data) in &files_to_process {
+    for spooled in &pending_files {
+        let original_name = &spooled.original_name;
         if slots_remaining == 0 {
             limit_reached = true;
             break;
         }
-        if data.len() as u64 > max_file_bytes() {
-            tracing::warn!(owner_hash = %owner_hash, ?original_name, size = data.len(), "Upload rejected: file too large");
+        if spooled.size > max_file_bytes() {
+            tracing::warn!(owner_hash = %owner_hash, ?original_name, size = spooled.size, "Upload rejected: file too large");
             continue;
         }
-        let mut hasher = Sha256::new();
-        hasher.update(&data);
-        let hash = format!("{:x}", hasher.finalize());
-        if let Some(entry) = state.owners.iter().find(|entry| entry.value().hash == hash) {
-            tracing::info!(owner_hash = %owner_hash, ?original_name, file = %entry.key(), "Duplicate upload detected");
-            duplicate_info = Some(json!({
-                "duplicate": true,
-                "file": entry.key(),
-                "meta": entry.value()
-            }));
+        let hash = spooled.hash.clone();
+        if let Some(duplicate) = find_duplicate_by_hash(&state, &hash) {
+            tracing::info!(owner_hash = %owner_hash, ?original_name, file = %duplicate.file, "Duplicate upload detected");
+            duplicate_info = Some(duplicate);
             continue;
         }
         let storage_name = make_storage_name(original_name.as_deref());
@@ -1124,29 +2336,53 @@ pub async fn upload_handler(
             tracing::warn!(owner_hash = %owner_hash, ?original_name, file = %storage_name, "Upload rejected: forbidden extension");
             continue;
         }
-        let path = state.upload_dir.join(&storage_name);
-        if fs::write(&path, data).await.is_ok() {
+        if let Ok(storage_name) = state
+            .store_new_file(storage_name.clone(), NewFileBody::Spooled(&spooled.path))
+            .await
+        {
             let meta = FileMeta {
                 hash: hash.clone(),
                 created: now,
                 expires,
                 owner_hash: owner_hash.clone(),
                 original: original_name.clone().unwrap_or_default(),
+                original_display: display_original_name(original_name.as_deref().unwrap_or("")),
+                max_downloads,
+                downloads: 0,
+                private,
+                ttl_shortened_from: None,
+                guest: tier == SlotTier::Guest,
+                network_class: Some(attrs.network),
+                size: spooled.size,
             };
-            state.owners.insert(storage_name.clone(), meta);
-            let check_now = now_secs();
-            let total_reserved = state.reserved_file_slots(owner_hash.as_str(), check_now);
-            if total_reserved > MAX_ACTIVE_FILES_PER_IP {
-                state.owners.remove(&storage_name);
-                let _ = fs::remove_file(&path).await;
+            state.insert_owner(storage_name.clone(), meta);
+            let check_now = state.now_secs();
+            let total_reserved = state.reserved_slots_in(owner_hash.as_str(), tier, check_now);
+            if total_reserved > tier.limit() {
+                state.remove_owner(&storage_name);
+                let _ = state.file_store.delete(&storage_name).await;
                 tracing::warn!(
                     owner_hash = %owner_hash,
                     file = %storage_name,
                     "Upload rejected: active file limit reached (post-write)",
                 );
-                return file_limit_response();
+                return slot_limit_response(tier);
+            }
+            tracing::info!(owner_hash = %owner_hash, file = %storage_name, size = spooled.size, "File uploaded successfully");
+            state.storage.record_stored(spooled.size);
+            state.transparency.record(&hash, spooled.size).await;
+            match state.screen_upload(&storage_name, &hash).await {
+                Screening::Passed => {
+                    state.notify_upload_completed(&storage_name);
+                    state.record_upload_client(&headers, UploadMethod::Form, spooled.size, None);
+                }
+                Screening::Held => continue,
+                Screening::Infected(signature) => {
+                    tracing::warn!(owner_hash = %owner_hash, file = %storage_name, %signature, "Upload rejected: virus detected");
+                    virus_detected = true;
+                    continue;
+                }
             }
-            tracing::info!(owner_hash = %owner_hash, file = %storage_name, size = data.len(), "File uploaded successfully");
             saved_files.push(storage_name.clone());
             slots_remaining = slots_remaining.saturating_sub(1);
         } else {
@@ -1157,20 +2393,274 @@ pub async fn upload_handler(
     state.persist_owners().await;
     spawn_integrity_check(state.clone());
 
+    if virus_detected {
+        return virus_detected_response();
+    }
     if let Some(dup) = duplicate_info {
         return (StatusCode::CONFLICT, Json(dup)).into_response();
     }
 
-    let truncated = saved_files.len() < files_to_process.len();
-    let remaining = files_to_process.len() - saved_files.len();
+    let truncated = saved_files.len() < pending_files.len();
+    let remaining = pending_files.len() - saved_files.len();
+    let locale = Locale::for_request(&headers, &locale).await;
 
     let mut resp = (
         StatusCode::OK,
         Json(UploadResponse {
+            uploaded: uploaded_entries(&state, &locale, &saved_files),
             files: saved_files,
             truncated,
             remaining,
             limit_reached,
+            ttl_policy: Some(ttl_policy),
+        }),
+    )
+        .into_response();
+    resp.headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    resp
+}
+
+/// Largest body accepted by `/api/paste-binary`.
+pub const PASTE_MAX_BYTES: usize = 256 * 1024;
+
+#[derive(Debug, Deserialize)]
+pub struct PasteQuery {
+    pub name: Option<String>,
+    pub ttl: Option<String>,
+    pub max_downloads: Option<u32>,
+    pub private: Option<String>,
+    /// Store the paste in the guest tier.
+    pub guest: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct PasteResponse {
+    pub file: String,
+    pub url: String,
+    pub expires: u64,
+    /// TTL policy applied to the paste.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_policy: Option<EffectiveTtl>,
+    /// Size in the request's language, e.g. "12 KB".
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub size_human: String,
+    /// Time left in the request's language, e.g. "in 1 hour".
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub expires_human: String,
+}
+
+/// Extension to give a nameless paste: sniffed from the bytes, else taken from
+/// the declared content type.
+fn paste_extension(data: &[u8], content_type: Option<&str>) -> Option<String> {
+    if let Some(kind) = infer::get(data) {
+        return Some(kind.extension().to_string());
+    }
+    let essence = content_type?.split(';').next()?.trim();
+    mime_guess::get_mime_extensions_str(essence)
+        .and_then(|exts| exts.first())
+        .map(|ext| ext.to_string())
+}
+
+/// Single round-trip upload for small raw bodies such as pasted screenshots.
+///
+/// The body is the file itself (no multipart), capped at [`PASTE_MAX_BYTES`]
+/// and held in memory, so it bypasses the upload semaphore. `name`, `ttl`,
+/// `max_downloads` and `private` come from the query string; without a name
+/// the extension is inferred.
+#[axum::debug_handler]
+#[tracing::instrument(
+    name = "upload.paste",
+    skip(state, headers, locale, body),
+    fields(client_ip = tracing::field::Empty)
+)]
+pub async fn paste_binary_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<ClientAddr>,
+    headers: HeaderMap,
+    AxumQuery(query): AxumQuery<PasteQuery>,
+    AxumQuery(locale): AxumQuery<LocaleQuery>,
+    body: axum::body::Body,
+) -> Response {
+    let client_ip = real_client_ip(&headers, &addr);
+    tracing::Span::current().record("client_ip", tracing::field::display(&client_ip));
+    if state.is_banned(&client_ip).await {
+        warn!(%client_ip, "paste rejected: banned ip");
+        return json_error(StatusCode::FORBIDDEN, "banned", "ip banned");
+    }
+    if query.max_downloads == Some(0) {
+        return invalid_max_downloads();
+    }
+    if state.drain.is_draining() {
+        warn!(%client_ip, "paste rejected: server draining");
+        return draining_response();
+    }
+    if state.storage.is_full() {
+        warn!(%client_ip, "paste rejected: storage full");
+        return storage_full_response();
+    }
+    let owner_hash = match request_owner(&state, &headers, &client_ip).await {
+        Ok(owner_hash) => owner_hash,
+        Err(resp) => return resp,
+    };
+    let too_large = || {
+        json_error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "too_large",
+            "paste exceeds 256KB; use /upload instead",
+        )
+    };
+    let declared_len = headers
+        .get(axum::http::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if declared_len.is_some_and(|len| len > PASTE_MAX_BYTES) {
+        return too_large();
+    }
+    let data = match axum::body::to_bytes(body, PASTE_MAX_BYTES).await {
+        Ok(data) => data,
+        Err(_) => return too_large(),
+    };
+    if data.is_empty() {
+        return json_error(StatusCode::BAD_REQUEST, "no_files", "paste body is empty");
+    }
+    if let Err(err) = digest_fields::verify_body(&headers, &data) {
+        warn!(%client_ip, %err, "paste rejected: digest check failed");
+        return digest_error_response(err);
+    }
+
+    let content_type = headers
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    let original_name = query
+        .name
+        .filter(|n| !n.trim().is_empty())
+        .or_else(|| paste_extension(&data, content_type).map(|ext| format!("paste.{ext}")));
+    let scanned = state
+        .content_scanners
+        .scan(&ScanInput {
+            name: original_name.as_deref(),
+            sample: &data[..data.len().min(SAMPLE_BYTES)],
+            size: data.len() as u64,
+            content: ScanContent::Memory(&data),
+        })
+        .await;
+    if let Err(rejection) = scanned {
+        warn!(
+            ?original_name,
+            scanner = rejection.scanner,
+            "paste rejected by content scanner"
+        );
+        return rejection_response(rejection);
+    }
+
+    let tier = SlotTier::requested(query.guest.as_deref().is_some_and(flag_enabled));
+    cleanup_expired(&state).await;
+    let now = state.now_secs();
+    if state.remaining_slots_in(owner_hash.as_str(), tier, now) == 0 {
+        warn!(owner_hash = %owner_hash, ?tier, "paste rejected: active file limit reached");
+        return slot_limit_response(tier);
+    }
+    if !state.owner_quota_allows(owner_hash.as_str(), data.len() as u64, now) {
+        warn!(owner_hash = %owner_hash, "paste rejected: owner quota reached");
+        return owner_quota_response(&state);
+    }
+    let hash = format!("{:x}", Sha256::digest(&data));
+    if let Some(duplicate) = find_duplicate_by_hash(&state, &hash) {
+        info!(owner_hash = %owner_hash, file = %duplicate.file, "duplicate paste detected");
+        return (StatusCode::CONFLICT, Json(duplicate)).into_response();
+    }
+    let storage_name = make_storage_name(original_name.as_deref());
+    if is_forbidden_extension(&storage_name) {
+        warn!(owner_hash = %owner_hash, file = %storage_name, "paste rejected: forbidden extension");
+        return json_error(
+            StatusCode::BAD_REQUEST,
+            "bad_filetype",
+            "File type not allowed",
+        );
+    }
+    let size = data.len() as u64;
+    let storage_name = match state
+        .store_new_file(storage_name, NewFileBody::Bytes(data))
+        .await
+    {
+        Ok(name) => name,
+        Err(err) => {
+            error!(?err, owner_hash = %owner_hash, "failed to write paste");
+            return json_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "write",
+                "failed to store upload",
+            );
+        }
+    };
+    let attrs = ClientAttributes::from_request(&state, &headers, &addr, &client_ip);
+    let ttl_policy = tier.apply(
+        state
+            .ttl_policy
+            .resolve(&attrs, query.ttl.as_deref(), "24h"),
+    );
+    let expires = now + ttl_policy.ttl;
+    state.insert_owner(
+        storage_name.clone(),
+        FileMeta {
+            hash: hash.clone(),
+            created: now,
+            expires,
+            owner_hash: owner_hash.clone(),
+            original: original_name.clone().unwrap_or_default(),
+            original_display: display_original_name(original_name.as_deref().unwrap_or("")),
+            max_downloads: query.max_downloads,
+            downloads: 0,
+            private: query.private.as_deref().is_some_and(flag_enabled),
+            ttl_shortened_from: None,
+            guest: tier == SlotTier::Guest,
+            network_class: Some(attrs.network),
+            size,
+        },
+    );
+    if state.reserved_slots_in(owner_hash.as_str(), tier, state.now_secs()) > tier.limit() {
+        state.remove_owner(&storage_name);
+        let _ = state.file_store.delete(&storage_name).await;
+        warn!(owner_hash = %owner_hash, file = %storage_name, "paste rejected: active file limit reached (post-write)");
+        return slot_limit_response(tier);
+    }
+    info!(owner_hash = %owner_hash, file = %storage_name, size, "paste uploaded successfully");
+    state.storage.record_stored(size);
+    state.transparency.record(&hash, size).await;
+    match state.screen_upload(&storage_name, &hash).await {
+        Screening::Passed => {
+            state.notify_upload_completed(&storage_name);
+            state.record_upload_client(&headers, UploadMethod::Paste, size, None);
+        }
+        Screening::Held => {
+            state.persist_owners().await;
+            return json_error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "rejected",
+                "upload was not accepted",
+            );
+        }
+        Screening::Infected(signature) => {
+            state.persist_owners().await;
+            warn!(owner_hash = %owner_hash, file = %storage_name, %signature, "paste rejected: virus detected");
+            return virus_detected_response();
+        }
+    }
+    state.persist_owners().await;
+    spawn_integrity_check(state.clone());
+    let locale = Locale::for_request(&headers, &locale).await;
+
+    let mut resp = (
+        StatusCode::OK,
+        Json(PasteResponse {
+            url: qualify_path(&state, &share_path(&storage_name)),
+            file: storage_name,
+            expires,
+            ttl_policy: Some(ttl_policy),
+            size_human: locale.size(size),
+            expires_human: locale.expires_in(expires, state.now_secs()),
         }),
     )
         .into_response();
@@ -1182,13 +2672,15 @@ pub async fn upload_handler(
 #[axum::debug_handler]
 #[tracing::instrument(
     name = "files.list",
-    skip(state, headers),
+    skip(state, headers, locale),
     fields(client_ip = tracing::field::Empty)
 )]
 pub async fn list_handler(
     State(state): State<AppState>,
     ConnectInfo(addr): ConnectInfo<ClientAddr>,
     headers: HeaderMap,
+    AxumQuery(query): AxumQuery<ListQuery>,
+    AxumQuery(locale): AxumQuery<LocaleQuery>,
 ) -> Response {
     if state.is_banned(&real_client_ip(&headers, &addr)).await {
         return json_error(StatusCode::FORBIDDEN, "banned", "ip banned");
@@ -1196,55 +2688,55 @@ pub async fn list_handler(
     cleanup_expired(&state).await;
     let client_ip = real_client_ip(&headers, &addr);
     tracing::Span::current().record("client_ip", tracing::field::display(&client_ip));
-    let Some(owner_hash) = state.hash_ip_to_string(&client_ip) else {
-        return json_error(
-            StatusCode::FORBIDDEN,
-            "invalid_ip",
-            "unable to fingerprint client",
-        );
+    let owner_hash = match request_owner(&state, &headers, &client_ip).await {
+        Ok(owner_hash) => owner_hash,
+        Err(resp) => return resp,
     };
     let reconcile_report = verify_user_entries_with_report(&state, &owner_hash).await;
     cleanup_expired(&state).await;
     check_storage_integrity(&state).await;
-    let mut files: Vec<(String, u64, String, u64, u64)> = state
-        .owners
+    let snapshot = state.owners_snapshot();
+    let page = snapshot.page_for(&owner_hash, &query);
+    let locale = Locale::for_request(&headers, &locale).await;
+    let now = state.now_secs();
+    let metas: Vec<FileMetaEntry> = page
+        .files
         .iter()
-        .filter_map(|entry| {
-            let m = entry.value();
-            if m.owner_hash == owner_hash {
-                let set = m.created;
-                let total = if m.expires > set { m.expires - set } else { 0 };
-                Some((
-                    entry.key().clone(),
-                    m.expires,
-                    m.original.clone(),
-                    total,
-                    set,
-                ))
-            } else {
-                None
-            }
+        .map(|(file, m)| FileMetaEntry {
+            file: qualify_path(&state, &format!("f/{}", file)),
+            expires: m.expires,
+            original: m.display_name(),
+            total: Some(m.expires.saturating_sub(m.created)),
+            set: Some(m.created),
+            downloads: m.downloads,
+            private: m.private,
+            shortened_from: m.ttl_shortened_from,
+            size: m.size,
+            size_human: locale.size(m.size),
+            expires_human: locale.expires_in(m.expires, now),
         })
         .collect();
-    files.sort_by(|a, b| a.0.cmp(&b.0));
-    let only_names: Vec<String> = files
-        .iter()
-        .map(|(n, _, _, _, _)| qualify_path(&state, &format!("f/{}", n)))
-        .collect();
-    let metas: Vec<FileMetaEntry> = files
+    let removed = state
+        .tombstones
+        .removals_for(&owner_hash, state.now_secs())
         .into_iter()
-        .map(|(n, e, o, t, s)| FileMetaEntry {
-            file: qualify_path(&state, &format!("f/{}", n)),
-            expires: e,
-            original: o,
-            total: Some(t),
-            set: Some(s),
+        .map(|(file, removal)| RemovedFileEntry {
+            file: qualify_path(&state, &format!("f/{}", file)),
+            removed_at: removal.at,
+            reason: removal.reason.as_str(),
+            message: removal.reason.message(),
         })
         .collect();
+    let only_names: Vec<String> = metas.iter().map(|m| m.file.clone()).collect();
     let body = Json(ListResponse {
         files: only_names,
         metas,
         reconcile: reconcile_report,
+        total: page.total,
+        offset: page.offset,
+        next_offset: page.next_offset,
+        next_cursor: page.next_cursor,
+        removed,
     });
     let mut resp = body.into_response();
     resp.headers_mut()
@@ -1275,17 +2767,19 @@ pub async fn simple_upload_handler(
         warn!(%ip, "simple upload rejected: banned ip");
         return json_error(StatusCode::FORBIDDEN, "banned", "ip banned");
     }
-    let owner_hash = if let Some(hash) = state.hash_ip_to_string(&ip) {
-        hash
-    } else {
-        warn!(%ip, "simple upload rejected: unable to hash ip");
-        return json_error(
-            StatusCode::FORBIDDEN,
-            "invalid_ip",
-            "unable to fingerprint client",
-        );
+    let owner_hash = match request_owner(&state, &headers, &ip).await {
+        Ok(owner_hash) => owner_hash,
+        Err(resp) => return resp,
     };
-    let mut ttl_code = "3d".to_string();
+    if state.drain.is_draining() {
+        warn!(%ip, "simple upload rejected: server draining");
+        return draining_response();
+    }
+    if state.storage.is_full() {
+        warn!(%ip, "simple upload rejected: storage full");
+        return storage_full_response();
+    }
+    let mut ttl_code = None;
     let mut files_to_process = Vec::new();
     let mut forbidden_error: Option<String> = None;
     let mut has_forbidden = false;
@@ -1310,10 +2804,10 @@ pub async fn simple_upload_handler(
         };
 
         if name == "ttl" {
-            if let Ok(data) = field.bytes().await {
-                if let Ok(s) = std::str::from_utf8(&data) {
-                    ttl_code = s.to_string();
-                }
+            if let Ok(data) = field.bytes().await
+                && let Ok(s) = std::str::from_utf8(&data)
+            {
+                ttl_code = Some(s.to_string());
             }
             continue;
         }
@@ -1321,56 +2815,58 @@ pub async fn simple_upload_handler(
         if name == "file" || name.starts_with("file") {
             let original_name = field.file_name().map(|s| s.to_string());
             let content_type = field.content_type().map(|m| m.to_string());
-            if let Ok(data) = field.bytes().await {
-                if !data.is_empty() {
-                    let forbidden_mimes: Vec<mime::Mime> = FORBIDDEN_EXTENSIONS
-                        .iter()
-                        .flat_map(|ext| {
-                            mime_guess::from_ext(ext)
-                                .iter()
-                                .filter_map(|m| m.essence_str().parse().ok())
-                        })
-                        .collect();
-                    let mime_type = if let Some(ref ct) = content_type {
-                        ct.parse::<mime::Mime>().ok()
-                    } else if let Some(ref orig) = original_name {
-                        mime_guess::from_path(orig)
-                            .first_raw()
-                            .and_then(|m| m.parse().ok())
-                    } else {
-                        None
-                    };
-                    let is_forbidden_content = if let Some(kind) = infer::get(&data) {
-                        let ext = kind.extension();
-                        FORBIDDEN_EXTENSIONS.contains(&ext)
-                    } else {
-                        false
-                    };
-                    if is_forbidden_content {
-                        tracing::warn!(
-                            ?original_name,
-                            "Simple upload rejected: forbidden file content detected by infer"
-                        );
-                        forbidden_error =
-                            Some("File type not allowed (forbidden content)".to_string());
-                        has_forbidden = true;
-                        break;
-                    }
-                    if let Some(mime) = &mime_type {
-                        if forbidden_mimes.iter().any(|forb| forb == mime) {
-                            tracing::warn!(
-                                ?original_name,
-                                mime = %mime,
-                                "Simple upload rejected: forbidden MIME type"
-                            );
-                            forbidden_error =
-                                Some("File type not allowed (forbidden MIME type)".to_string());
-                            has_forbidden = true;
-                            break;
-                        }
-                    }
-                    files_to_process.push((original_name, data));
+            if let Ok(data) = field.bytes().await
+                && !data.is_empty()
+            {
+                let forbidden_mimes: Vec<mime::Mime> = reload::current()
+                    .forbidden_extensions
+                    .iter()
+                    .flat_map(|ext| {
+                        mime_guess::from_ext(ext)
+                            .iter()
+                            .filter_map(|m| m.essence_str().parse().ok())
+                    })
+                    .collect();
+                let mime_type = if let Some(ref ct) = content_type {
+                    ct.parse::<mime::Mime>().ok()
+                } else if let Some(ref orig) = original_name {
+                    mime_guess::from_path(orig)
+                        .first_raw()
+                        .and_then(|m| m.parse().ok())
+                } else {
+                    None
+                };
+                let scanned = state
+                    .content_scanners
+                    .scan(&ScanInput {
+                        name: original_name.as_deref(),
+                        sample: &data[..data.len().min(SAMPLE_BYTES)],
+                        size: data.len() as u64,
+                        content: ScanContent::Memory(&data),
+                    })
+                    .await;
+                if let Err(rejection) = scanned {
+                    tracing::warn!(
+                        ?original_name,
+                        scanner = rejection.scanner,
+                        "Simple upload rejected by content scanner"
+                    );
+                    return rejection_response(rejection);
                 }
+                if let Some(mime) = &mime_type
+                    && forbidden_mimes.iter().any(|forb| forb == mime)
+                {
+                    tracing::warn!(
+                        ?original_name,
+                        mime = %mime,
+                        "Simple upload rejected: forbidden MIME type"
+                    );
+                    forbidden_error =
+                        Some("File type not allowed (forbidden MIME type)".to_string());
+                    has_forbidden = true;
+                    break;
+                }
+                files_to_process.push((original_name, data));
             }
         }
     }
@@ -1401,54 +2897,97 @@ pub async fn simple_upload_handler(
     }
 
     cleanup_expired(&state).await;
-    let now = now_secs();
+    let now = state.now_secs();
     let mut slots_remaining = state.remaining_file_slots(owner_hash.as_str(), now);
     if slots_remaining == 0 {
         tracing::warn!(owner_hash = %owner_hash, "Simple upload rejected: active file limit reached");
         return file_limit_response();
     }
+    let incoming: u64 = files_to_process
+        .iter()
+        .map(|(_, data)| data.len() as u64)
+        .sum();
+    if !state.owner_quota_allows(owner_hash.as_str(), incoming, now) {
+        tracing::warn!(owner_hash = %owner_hash, incoming, "Simple upload rejected: owner quota reached");
+        return owner_quota_response(&state);
+    }
 
-    let expires = now + ttl_to_duration(&ttl_code).as_secs();
+    let attrs = ClientAttributes::from_request(&state, &headers, &addr, &ip);
+    let expires = now
+        + state
+            .ttl_policy
+            .resolve(&attrs, ttl_code.as_deref(), "3d")
+            .ttl;
     let mut saved_files: Vec<String> = Vec::new();
     let mut limit_reached = false;
+    let mut virus_detected = false;
 
     for (original_name, data) in &files_to_process {
         if slots_remaining == 0 {
             limit_reached = true;
             break;
         }
-        let created = now_secs();
+        let created = state.now_secs();
         if data.len() as u64 > max_file_bytes() {
             tracing::warn!(owner_hash = %owner_hash, ?original_name, size = data.len(), "Simple upload rejected: file too large");
             continue;
         }
         let mut hasher = Sha256::new();
-        hasher.update(&data);
+        hasher.update(data);
         let hash = format!("{:x}", hasher.finalize());
         let storage_name = make_storage_name(original_name.as_deref());
         if is_forbidden_extension(&storage_name) {
             tracing::warn!(owner_hash = %owner_hash, ?original_name, file = %storage_name, "Simple upload rejected: forbidden extension");
             continue;
         }
-        let path = state.upload_dir.join(&storage_name);
-        if fs::write(&path, data).await.is_ok() {
+        if let Ok(storage_name) = state
+            .store_new_file(storage_name.clone(), NewFileBody::Bytes(data.clone()))
+            .await
+        {
             let meta = FileMeta {
                 owner_hash: owner_hash.clone(),
                 expires,
                 original: original_name.clone().unwrap_or_default(),
+                original_display: display_original_name(original_name.as_deref().unwrap_or("")),
                 created,
                 hash: hash.clone(),
+                max_downloads: None,
+                downloads: 0,
+                private: false,
+                ttl_shortened_from: None,
+                guest: false,
+                network_class: Some(attrs.network),
+                size: data.len() as u64,
             };
-            state.owners.insert(storage_name.clone(), meta);
-            let check_now = now_secs();
+            state.insert_owner(storage_name.clone(), meta);
+            let check_now = state.now_secs();
             let total_reserved = state.reserved_file_slots(owner_hash.as_str(), check_now);
             if total_reserved > MAX_ACTIVE_FILES_PER_IP {
-                state.owners.remove(storage_name.as_str());
-                let _ = fs::remove_file(&path).await;
+                state.remove_owner(storage_name.as_str());
+                let _ = state.file_store.delete(&storage_name).await;
                 tracing::warn!(owner_hash = %owner_hash, file = %storage_name, "Simple upload rejected: active file limit reached (post-write)");
                 limit_reached = true;
                 break;
             }
+            state.storage.record_stored(data.len() as u64);
+            state.transparency.record(&hash, data.len() as u64).await;
+            match state.screen_upload(&storage_name, &hash).await {
+                Screening::Passed => {
+                    state.notify_upload_completed(&storage_name);
+                    state.record_upload_client(
+                        &headers,
+                        UploadMethod::Simple,
+                        data.len() as u64,
+                        None,
+                    );
+                }
+                Screening::Held => continue,
+                Screening::Infected(signature) => {
+                    tracing::warn!(owner_hash = %owner_hash, file = %storage_name, %signature, "Upload rejected: virus detected");
+                    virus_detected = true;
+                    continue;
+                }
+            }
             tracing::info!(owner_hash = %owner_hash, file = %storage_name, size = data.len(), "Simple file uploaded successfully");
             saved_files.push(storage_name.clone());
             slots_remaining = slots_remaining.saturating_sub(1);
@@ -1467,7 +3006,9 @@ pub async fn simple_upload_handler(
     spawn_integrity_check(state.clone());
 
     let truncated = saved_files.len() < files_to_process.len();
-    let msg = if limit_reached {
+    let msg = if virus_detected {
+        "A virus was detected; the infected file was rejected.".to_string()
+    } else if limit_reached {
         format!(
             "Some files were discarded because you reached the {} active file limit.",
             MAX_ACTIVE_FILES_PER_IP
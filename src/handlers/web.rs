@@ -1,20 +1,26 @@
 use axum::Json;
 use axum::extract::{ConnectInfo, Query, State};
-use axum::http::HeaderMap;
 use axum::http::StatusCode;
+use axum::http::header::CACHE_CONTROL;
+use axum::http::{HeaderMap, HeaderValue};
+use axum::response::sse::{Event, KeepAlive, Sse};
 use axum::response::{IntoResponse, Response};
-use serde::Deserialize;
+use futures_util::{StreamExt, stream};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
 use std::net::SocketAddr;
+use std::time::Duration;
 use tera::Context;
-use tokio::fs;
 use tracing::{debug, error, trace, warn};
 
-use crate::state::{AppState, BanSubject};
+use crate::assets::{read_public, read_translation};
+use crate::handlers::admin::{AdminCheck, check_admin};
+use crate::state::{AppState, BanSubject, ListQuery, ListSort, SortOrder};
 use crate::util::{
     IpVersion, MAX_ACTIVE_FILES_PER_IP, extract_client_ip, format_bytes, headers_trusted,
-    max_file_bytes, now_secs, qualify_path, real_client_ip,
+    json_error, max_file_bytes, qualify_path, real_client_ip, share_path,
 };
 
 #[derive(Deserialize)]
@@ -27,25 +33,36 @@ pub struct LangQuery {
     pub lang: Option<String>,
     pub m: Option<String>,
     pub deleted: Option<String>,
+    /// Prefills the report form when arriving from a download page.
+    pub file: Option<String>,
+}
+
+pub(crate) async fn load_asset_manifest(state: &AppState) -> Option<HashMap<String, String>> {
+    let manifest_path = "dist/manifest.json";
+    let Some(bytes) = read_public(&state.static_dir, manifest_path).await else {
+        debug!(path = manifest_path, "asset manifest not available");
+        return None;
+    };
+    match serde_json::from_slice::<HashMap<String, String>>(&bytes) {
+        Ok(manifest_map) => Some(manifest_map),
+        Err(err) => {
+            warn!(?err, path = manifest_path, "failed to parse asset manifest");
+            None
+        }
+    }
 }
 
 async fn apply_manifest_assets(state: &AppState, ctx: &mut Context) {
-    let manifest_path = state.static_dir.join("dist/manifest.json");
-    match fs::read_to_string(&manifest_path).await {
-        Ok(manifest_str) => match serde_json::from_str::<HashMap<String, String>>(&manifest_str) {
-            Ok(manifest_map) => {
-                if let Some(app_bundle) = manifest_map.get("app") {
-                    ctx.insert("app_bundle", app_bundle);
-                }
-                if let Some(css_bundle) = manifest_map.get("css") {
-                    ctx.insert("css_bundle", css_bundle);
-                }
-                trace!(path = ?manifest_path, "applied manifest assets");
-            }
-            Err(err) => warn!(?err, path = ?manifest_path, "failed to parse asset manifest"),
-        },
-        Err(err) => debug!(?err, path = ?manifest_path, "asset manifest not available"),
+    let Some(manifest_map) = load_asset_manifest(state).await else {
+        return;
+    };
+    if let Some(app_bundle) = manifest_map.get("app") {
+        ctx.insert("app_bundle", app_bundle);
+    }
+    if let Some(css_bundle) = manifest_map.get("css") {
+        ctx.insert("css_bundle", css_bundle);
     }
+    trace!("applied manifest assets");
 }
 
 #[tracing::instrument(name = "web.root", skip(state), fields(lang = %query.lang.as_deref().unwrap_or("en")))]
@@ -137,17 +154,43 @@ pub async fn trusted_handler(
     }
 }
 
+/// How this server sees the caller. Anyone gets whether their forwarding
+/// headers are trusted; admins also get hashes, headers, owned files and any
+/// matching ban. Never cached and limited per client either way.
 pub async fn visitor_debug_handler(
     State(state): State<AppState>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
+) -> Response {
+    let real_ip = real_client_ip(&headers, &addr);
+    let mut response = if !state.visitor_debug_limiter.check(&real_ip).await {
+        json_error(StatusCode::TOO_MANY_REQUESTS, "rate_limited", "slow down")
+    } else if check_admin(&state, &headers, "visitor debug").await == AdminCheck::Granted {
+        visitor_debug_report(&state, &addr, &headers).await
+    } else {
+        // Rejected credentials get the public answer too, so only a
+        // working key or session changes the response.
+        let trusted = headers_trusted(&headers, Some(addr.ip()));
+        trace!(real_ip, trusted, "public visitor debug requested");
+        Json(json!({ "headers_trusted": trusted })).into_response()
+    };
+    response
+        .headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    response
+}
+
+async fn visitor_debug_report(
+    state: &AppState,
+    addr: &SocketAddr,
+    headers: &HeaderMap,
 ) -> Response {
     const MAX_FILE_PREVIEW: usize = 20;
 
     let edge_ip = addr.ip().to_string();
-    let real_ip = real_client_ip(&headers, &addr);
-    let extracted_ip = extract_client_ip(&headers, Some(addr.ip()));
-    let trusted = headers_trusted(&headers, Some(addr.ip()));
+    let real_ip = real_client_ip(headers, addr);
+    let extracted_ip = extract_client_ip(headers, Some(addr.ip()));
+    let trusted = headers_trusted(headers, Some(addr.ip()));
     trace!(
         edge_ip,
         real_ip, extracted_ip, trusted, "visitor debug requested"
@@ -197,35 +240,30 @@ pub async fn visitor_debug_handler(
 
     let mut header_dump: HashMap<String, Vec<String>> = HashMap::new();
     for (name, value) in headers.iter() {
-        header_dump
-            .entry(name.to_string())
-            .or_insert_with(Vec::new)
-            .push(
-                value
-                    .to_str()
-                    .map(|s| s.to_string())
-                    .unwrap_or_else(|_| format!("{:?}", value)),
-            );
+        header_dump.entry(name.to_string()).or_default().push(
+            value
+                .to_str()
+                .map(|s| s.to_string())
+                .unwrap_or_else(|_| format!("{:?}", value)),
+        );
     }
 
-    let now = now_secs();
+    let now = state.now_secs();
     let mut owned_files = Vec::new();
     let mut owned_total = 0usize;
     if let Some(owner_hash_value) = owner_hash.as_ref() {
-        for entry in state.owners.iter() {
-            if entry.value().owner_hash == *owner_hash_value {
-                owned_total += 1;
-                if owned_files.len() < MAX_FILE_PREVIEW {
-                    owned_files.push(json!({
-                        "file": entry.key().clone(),
-                        "original": entry.value().original,
-                        "created": entry.value().created,
-                        "expires": entry.value().expires,
-                        "seconds_until_expiry": entry.value().expires.saturating_sub(now),
-                        "content_hash": entry.value().hash,
-                    }));
-                }
-            }
+        let snapshot = state.owners_snapshot();
+        let owned = snapshot.files_for(owner_hash_value);
+        owned_total = owned.len();
+        for (file, meta) in owned.iter().take(MAX_FILE_PREVIEW) {
+            owned_files.push(json!({
+                "file": file,
+                "original": meta.original,
+                "created": meta.created,
+                "expires": meta.expires,
+                "seconds_until_expiry": meta.expires.saturating_sub(now),
+                "content_hash": meta.hash,
+            }));
         }
     }
     let files_truncated = owned_total > owned_files.len();
@@ -253,6 +291,8 @@ pub async fn visitor_debug_handler(
                     "version": version_label(version),
                 })
             }
+            BanSubject::Country { code } => json!({ "mode": "country", "code": code }),
+            BanSubject::Asn { number } => json!({ "mode": "asn", "number": number }),
         };
         json!({
             "reason": ban.reason,
@@ -321,7 +361,183 @@ pub async fn report_page_handler_i18n(
 ) -> Response {
     let lang = query.lang.as_deref().unwrap_or("en");
     trace!(lang, "rendering report page");
-    render_tera_page(&state, "report.html.tera", lang, None).await
+    let file = query
+        .file
+        .as_deref()
+        .map(|f| tera::Value::String(f.chars().take(80).collect()));
+    render_tera_page(
+        &state,
+        "report.html.tera",
+        lang,
+        file.as_ref().map(|f| ("file", f)),
+    )
+    .await
+}
+
+/// Rows shown per page on `/simple` unless `limit` is given.
+const SIMPLE_PAGE_SIZE: usize = 50;
+
+/// Coarse remaining-time label used by the simple file table.
+fn format_remaining(expires: u64, now: u64) -> String {
+    if now >= expires {
+        return "expired".to_string();
+    }
+    let expires_in = expires - now;
+    if expires_in >= 86400 {
+        format!("{}d", expires_in / 86400)
+    } else if expires_in >= 3600 {
+        format!("{}h", expires_in / 3600)
+    } else if expires_in >= 60 {
+        format!("{}m", expires_in / 60)
+    } else {
+        format!("{}s", expires_in)
+    }
+}
+
+/// Seconds between expiry ticks on `/simple/events`.
+const SIMPLE_EVENTS_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Serialize)]
+struct ExpiryTickFile {
+    file: String,
+    expires: u64,
+    remaining: String,
+}
+
+#[derive(Serialize)]
+struct ExpiryTick {
+    now: u64,
+    files: Vec<ExpiryTickFile>,
+}
+
+struct ExpiryStream {
+    state: AppState,
+    client_ip: String,
+    owner_hash: String,
+    seen: HashSet<String>,
+    interval: tokio::time::Interval,
+}
+
+impl ExpiryStream {
+    /// Waits for the next tick and returns its events, or `None` once the
+    /// caller has been banned and the stream should end.
+    async fn next_events(&mut self) -> Option<Vec<Event>> {
+        self.interval.tick().await;
+        if self.state.is_banned(&self.client_ip).await {
+            debug!(client_ip = %self.client_ip, "closing expiry stream for banned client");
+            return None;
+        }
+        let now = self.state.now_secs();
+        let snapshot = self.state.owners_snapshot();
+        let owned = snapshot.files_for(&self.owner_hash);
+        let current: HashSet<String> = owned.iter().map(|(file, _)| file.clone()).collect();
+        let mut events: Vec<Event> = self
+            .seen
+            .difference(&current)
+            .map(|file| {
+                Event::default()
+                    .event("deleted")
+                    .json_data(json!({ "file": file }))
+                    .unwrap_or_default()
+            })
+            .collect();
+        let tick = ExpiryTick {
+            now,
+            files: owned
+                .iter()
+                .map(|(file, meta)| ExpiryTickFile {
+                    file: file.clone(),
+                    expires: meta.expires,
+                    remaining: format_remaining(meta.expires, now),
+                })
+                .collect(),
+        };
+        events.push(
+            Event::default()
+                .event("expiry")
+                .json_data(&tick)
+                .unwrap_or_default(),
+        );
+        self.seen = current;
+        Some(events)
+    }
+}
+
+/// Server-sent events keeping the simple page's remaining-time column current.
+///
+/// Emits an `expiry` event with every owned file on each tick and a `deleted`
+/// event for files that disappeared since the previous tick. The page renders
+/// the same values statically, so clients without `EventSource` lose nothing.
+pub async fn simple_events_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Response {
+    let client_ip = real_client_ip(&headers, &addr);
+    if state.is_banned(&client_ip).await {
+        return (StatusCode::FORBIDDEN, "banned").into_response();
+    }
+    let Some(owner_hash) = state.hash_ip_to_string(&client_ip) else {
+        warn!(%client_ip, "expiry stream denied: unable to hash ip");
+        return (StatusCode::FORBIDDEN, "unable to fingerprint client").into_response();
+    };
+    let mut interval = tokio::time::interval(SIMPLE_EVENTS_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let seen = state
+        .owners_snapshot()
+        .files_for(&owner_hash)
+        .iter()
+        .map(|(file, _)| file.clone())
+        .collect();
+    let ticks = ExpiryStream {
+        state,
+        client_ip,
+        owner_hash,
+        seen,
+        interval,
+    };
+    let events = stream::unfold(ticks, |mut ticks| async move {
+        let events = ticks.next_events().await?;
+        Some((
+            stream::iter(events.into_iter().map(Ok::<_, Infallible>)),
+            ticks,
+        ))
+    })
+    .flatten();
+    let mut resp = Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response();
+    resp.headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    resp
+}
+
+/// Previous/next links for the no-JS file table, keeping sort and language.
+fn simple_pager(lang: &str, query: &ListQuery, offset: usize, next: Option<usize>) -> String {
+    let limit = query.limit.unwrap_or(SIMPLE_PAGE_SIZE);
+    let sort = match query.sort {
+        ListSort::Name => "name",
+        ListSort::Expires => "expires",
+        ListSort::Created => "created",
+    };
+    let order = match query.order {
+        SortOrder::Asc => "asc",
+        SortOrder::Desc => "desc",
+    };
+    let link = |offset: usize, label: &str| {
+        format!(
+            "<a href=\"/simple?lang={}&amp;sort={sort}&amp;order={order}&amp;limit={limit}&amp;offset={offset}\">{label}</a>",
+            urlencoding::encode(lang)
+        )
+    };
+    let mut links = Vec::new();
+    if offset > 0 {
+        links.push(link(offset.saturating_sub(limit), "&laquo; Previous"));
+    }
+    if let Some(next) = next {
+        links.push(link(next, "Next &raquo;"));
+    }
+    links.join(" | ")
 }
 
 pub async fn simple_handler(
@@ -329,11 +545,13 @@ pub async fn simple_handler(
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
     Query(query): Query<LangQuery>,
+    Query(mut page_query): Query<ListQuery>,
 ) -> Response {
     let lang = query.lang.as_deref().unwrap_or("en");
     trace!(lang, "rendering simple upload page");
+    page_query.limit.get_or_insert(SIMPLE_PAGE_SIZE);
 
-    let message = if let Some(_) = query.deleted {
+    let message = if query.deleted.is_some() {
         Some("File DEleted Successfully.".to_string())
     } else {
         query.m.clone()
@@ -353,47 +571,34 @@ pub async fn simple_handler(
                 .into_response();
         }
     };
-    let mut files: Vec<(String, u64, String)> = state
-        .owners
+    let snapshot = state.owners_snapshot();
+    let page = snapshot.page_for(&owner_hash, &page_query);
+    let files: Vec<(String, u64, String)> = page
+        .files
         .iter()
-        .filter_map(|entry| {
-            let m = entry.value();
-            if m.owner_hash == owner_hash {
-                Some((entry.key().clone(), m.expires, m.original.clone()))
-            } else {
-                None
-            }
-        })
+        .map(|(file, m)| (file.clone(), m.expires, m.display_name()))
         .collect();
-    files.sort_by(|a, b| a.0.cmp(&b.0));
-    let now = now_secs();
+    let pager = simple_pager(lang, &page_query, page.offset, page.next_offset);
+    let now = state.now_secs();
     let mut rows = String::new();
     for (fname, expires, original) in &files {
-        let url = qualify_path(&state, &format!("f/{}", fname));
-        let expired = now >= *expires;
-        let expires_in = if *expires > now { *expires - now } else { 0 };
-        let human = if expired {
-            "expired".to_string()
-        } else if expires_in >= 86400 {
-            format!("{}d", expires_in / 86400)
-        } else if expires_in >= 3600 {
-            format!("{}h", expires_in / 3600)
-        } else if expires_in >= 60 {
-            format!("{}m", expires_in / 60)
-        } else {
-            format!("{}s", expires_in)
-        };
+        let url = qualify_path(&state, &share_path(fname));
+        let human = format_remaining(*expires, now);
+        let escaped_name = htmlescape::encode_minimal(fname);
         rows.push_str(&format!(
-            "<tr><td><a href=\"{}\" data-lang-skip=\"true\">{}</a></td><td>{}</td><td><a href=\"/simple/delete?f={}\" class=delete-link>Delete</a></td></tr>",
+            "<tr data-file=\"{}\"><td><a href=\"{}\" data-lang-skip=\"true\">{}</a></td><td data-expires=\"{}\">{}</td><td><a href=\"/simple/delete?f={}\" class=delete-link>Delete</a></td></tr>",
+            escaped_name,
             url,
             htmlescape::encode_minimal(original),
+            expires,
             human,
-            htmlescape::encode_minimal(fname)
+            escaped_name
         ));
     }
     let mut ctx = tera::Context::new();
     ctx.insert("lang", lang);
     ctx.insert("ROWS", &rows);
+    ctx.insert("PAGER", &pager);
     if let Some(msg) = message {
         ctx.insert("MESSAGE", &msg);
     }
@@ -433,21 +638,21 @@ pub async fn banned_handler(
 // piece of shit function
 // todo: avoid using println, use tracing
 pub async fn load_translation_map(lang: &str) -> HashMap<String, String> {
-    let lang_file = format!("translations/lang_{}.toml", lang);
+    let lang_file = format!("lang_{}.toml", lang);
     println!("[i18n] Attempting to load translation file: {}", lang_file);
-    let content = match fs::read_to_string(&lang_file).await {
-        Ok(s) => {
+    let content = match read_translation(&lang_file).await {
+        Some(s) => {
             println!("[i18n] Loaded file: {}", lang_file);
             s
         }
-        Err(e) => {
+        None => {
             println!(
-                "[i18n] Failed to load {}: {}. Falling back to lang_en.toml",
-                lang_file, e
+                "[i18n] Failed to load {}. Falling back to lang_en.toml",
+                lang_file
             );
-            match fs::read_to_string("translations/lang_en.toml").await {
-                Ok(s) => s,
-                Err(_e2) => {
+            match read_translation("lang_en.toml").await {
+                Some(s) => s,
+                None => {
                     println!("[i18n] Failed to load fallback"); // ur fucked
                     String::new()
                 }
@@ -0,0 +1,225 @@
+//! Server-side formatting of expiry and size strings in the visitor's
+//! language, so thin clients (the CLI, scripts, simple frontends) can show
+//! "in 3 days" or "1,5 Mo" without each shipping their own plural rules.
+//!
+//! Strings come from the same `translations/lang_<code>.toml` files as the
+//! HTML pages. A request's language is its `lang` query parameter, else the
+//! first `Accept-Language` tag; anything without a translation file gets
+//! English.
+
+use axum::http::HeaderMap;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+use crate::assets::read_translation;
+
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+/// Loaded locales by language code; translation files are read once.
+static LOCALES: Lazy<DashMap<String, Arc<Locale>>> = Lazy::new(DashMap::new);
+
+/// `?lang=` on API routes that return display strings.
+#[derive(Deserialize, Debug, Default)]
+pub struct LocaleQuery {
+    pub lang: Option<String>,
+}
+
+/// CLDR plural category, as far as the shipped languages need it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Plural {
+    One,
+    Few,
+    Many,
+    Other,
+}
+
+impl Plural {
+    pub fn for_count(lang: &str, n: u64) -> Self {
+        match lang {
+            "uk" | "ru" | "be" => {
+                let (rem10, rem100) = (n % 10, n % 100);
+                if rem10 == 1 && rem100 != 11 {
+                    Plural::One
+                } else if (2..=4).contains(&rem10) && !(12..=14).contains(&rem100) {
+                    Plural::Few
+                } else {
+                    Plural::Many
+                }
+            }
+            "fr" if n <= 1 => Plural::One,
+            _ if n == 1 => Plural::One,
+            _ => Plural::Other,
+        }
+    }
+
+    fn suffix(self) -> &'static str {
+        match self {
+            Plural::One => "one",
+            Plural::Few => "few",
+            Plural::Many => "many",
+            Plural::Other => "other",
+        }
+    }
+}
+
+/// English strings used when a translation lacks a key.
+fn fallback(key: &str) -> Option<&'static str> {
+    Some(match key {
+        "time_expired" => "expired",
+        "time_in_second_one" => "in {n} second",
+        "time_in_second_other" => "in {n} seconds",
+        "time_in_minute_one" => "in {n} minute",
+        "time_in_minute_other" => "in {n} minutes",
+        "time_in_hour_one" => "in {n} hour",
+        "time_in_hour_other" => "in {n} hours",
+        "time_in_day_one" => "in {n} day",
+        "time_in_day_other" => "in {n} days",
+        "size_units" => "B KB MB GB TB",
+        "decimal_separator" => ".",
+        _ => return None,
+    })
+}
+
+/// The display strings of one language.
+#[derive(Debug)]
+pub struct Locale {
+    lang: String,
+    strings: HashMap<String, String>,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Locale::new(DEFAULT_LANGUAGE, HashMap::new())
+    }
+}
+
+impl Locale {
+    pub fn new(lang: &str, strings: HashMap<String, String>) -> Self {
+        Self {
+            lang: lang.to_string(),
+            strings,
+        }
+    }
+
+    /// The locale for `lang`, falling back to English when there is no
+    /// translation file for it.
+    pub async fn load(lang: &str) -> Arc<Locale> {
+        if let Some(locale) = LOCALES.get(lang) {
+            return locale.clone();
+        }
+        let locale = match read_translation(&format!("lang_{lang}.toml")).await {
+            Some(content) => {
+                let strings = toml::from_str(&content).unwrap_or_else(|err| {
+                    warn!(lang, %err, "failed to parse translation file");
+                    HashMap::new()
+                });
+                Arc::new(Locale::new(lang, strings))
+            }
+            None if lang == DEFAULT_LANGUAGE => Arc::new(Locale::default()),
+            None => {
+                debug!(lang, "no translation for language, using english");
+                Box::pin(Locale::load(DEFAULT_LANGUAGE)).await
+            }
+        };
+        LOCALES.entry(lang.to_string()).or_insert(locale).clone()
+    }
+
+    /// The locale a request asked for.
+    pub async fn for_request(headers: &HeaderMap, query: &LocaleQuery) -> Arc<Locale> {
+        Locale::load(&request_language(headers, query.lang.as_deref())).await
+    }
+
+    pub fn lang(&self) -> &str {
+        &self.lang
+    }
+
+    fn text<'a>(&'a self, key: &'a str) -> &'a str {
+        self.strings
+            .get(key)
+            .map(String::as_str)
+            .or_else(|| fallback(key))
+            .unwrap_or(key)
+    }
+
+    fn plural(&self, stem: &str, n: u64) -> String {
+        let category = Plural::for_count(&self.lang, n);
+        let key = format!("{stem}_{}", category.suffix());
+        let template = match self.strings.get(&key) {
+            Some(text) => text.as_str(),
+            None => self
+                .strings
+                .get(&format!("{stem}_other"))
+                .map(String::as_str)
+                .or_else(|| fallback(&format!("{stem}_{}", Plural::for_count("en", n).suffix())))
+                .unwrap_or(stem),
+        };
+        template.replace("{n}", &n.to_string())
+    }
+
+    /// Time until `expires`, in the largest whole unit: "in 3 days".
+    pub fn expires_in(&self, expires: u64, now: u64) -> String {
+        if now >= expires {
+            return self.text("time_expired").to_string();
+        }
+        let left = expires - now;
+        let (stem, n) = if left >= 86400 {
+            ("time_in_day", left / 86400)
+        } else if left >= 3600 {
+            ("time_in_hour", left / 3600)
+        } else if left >= 60 {
+            ("time_in_minute", left / 60)
+        } else {
+            ("time_in_second", left)
+        };
+        self.plural(stem, n)
+    }
+
+    /// `bytes` in binary units with the language's unit names and decimal
+    /// separator: one decimal below 10 of a unit, whole numbers above.
+    pub fn size(&self, bytes: u64) -> String {
+        let units: Vec<&str> = self.text("size_units").split_whitespace().collect();
+        let mut value = bytes as f64;
+        let mut unit = 0;
+        while value >= 1024.0 && unit + 1 < units.len() {
+            value /= 1024.0;
+            unit += 1;
+        }
+        let number = if unit == 0 || value >= 10.0 {
+            format!("{value:.0}")
+        } else {
+            format!("{value:.1}").replace('.', self.text("decimal_separator"))
+        };
+        match units.get(unit) {
+            Some(name) => format!("{number} {name}"),
+            None => number,
+        }
+    }
+}
+
+/// `lang` when given, else the primary subtag of the first `Accept-Language`
+/// entry; only plain two or three letter codes are accepted.
+pub fn request_language(headers: &HeaderMap, lang: Option<&str>) -> String {
+    let from_header = || {
+        headers
+            .get(axum::http::header::ACCEPT_LANGUAGE)?
+            .to_str()
+            .ok()?
+            .split(',')
+            .next()?
+            .split(';')
+            .next()?
+            .trim()
+            .split(['-', '_'])
+            .next()
+            .map(str::to_string)
+    };
+    lang.map(str::to_string)
+        .or_else(from_header)
+        .map(|tag| tag.trim().to_ascii_lowercase())
+        .filter(|tag| (2..=3).contains(&tag.len()) && tag.bytes().all(|b| b.is_ascii_lowercase()))
+        .unwrap_or_else(|| DEFAULT_LANGUAGE.to_string())
+}
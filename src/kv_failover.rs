@@ -0,0 +1,344 @@
+//! A metadata store that keeps working while its primary is down. Writes go
+//! to the primary and are mirrored to a fallback store. Once a circuit
+//! breaker sees the primary failing, reads and writes move to the fallback,
+//! and writes queue up to be replayed to the primary, in order, once it
+//! answers again.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex as AsyncMutex, Notify};
+use tokio::task::JoinHandle;
+use tracing::{Instrument, debug, info, warn};
+
+use crate::state::{AppState, KvStore};
+
+/// Consecutive primary failures that open the breaker, unless
+/// `JUICEBOX_METADATA_FAILOVER_THRESHOLD` says otherwise.
+pub const DEFAULT_FAILOVER_THRESHOLD: u32 = 3;
+/// How long an open breaker keeps the primary out of use before trying it
+/// again, unless `JUICEBOX_METADATA_FAILOVER_COOLDOWN_SECS` says otherwise.
+pub const DEFAULT_FAILOVER_COOLDOWN_SECS: u64 = 30;
+/// How often queued writes are offered back to the primary.
+pub const REPLAY_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// The primary is in use.
+    Closed,
+    /// The primary failed too often and is left alone until the cooldown ends.
+    Open,
+    /// The cooldown is over and the next call tries the primary again.
+    HalfOpen,
+}
+
+/// Counts consecutive failures of one backend and stops calling it for a
+/// while once they reach a threshold.
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    failures: AtomicU32,
+    opened_at: Mutex<Option<Instant>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cooldown,
+            failures: AtomicU32::new(0),
+            opened_at: Mutex::new(None),
+        }
+    }
+
+    pub fn state(&self) -> BreakerState {
+        match *self.opened_at.lock().unwrap_or_else(|e| e.into_inner()) {
+            None => BreakerState::Closed,
+            Some(at) if at.elapsed() >= self.cooldown => BreakerState::HalfOpen,
+            Some(_) => BreakerState::Open,
+        }
+    }
+
+    /// Whether the backend may be called now.
+    pub fn allows(&self) -> bool {
+        self.state() != BreakerState::Open
+    }
+
+    pub fn record_success(&self) {
+        self.failures.store(0, Ordering::Relaxed);
+        *self.opened_at.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    /// Count a failure. Returns `true` if this one opened the breaker.
+    pub fn record_failure(&self) -> bool {
+        let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures < self.threshold {
+            return false;
+        }
+        let mut opened_at = self.opened_at.lock().unwrap_or_else(|e| e.into_inner());
+        let opened = opened_at.is_none();
+        // A failed trial after the cooldown starts a new one.
+        *opened_at = Some(Instant::now());
+        opened
+    }
+}
+
+/// What `/readyz` reports about a failing-over store.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct FailoverStatus {
+    pub primary: &'static str,
+    pub fallback: &'static str,
+    pub breaker: BreakerState,
+    /// Writes held on the fallback, waiting for the primary.
+    pub pending_writes: usize,
+    /// Times the breaker has opened since startup.
+    pub failovers: u64,
+    /// Writes replayed to the primary since startup.
+    pub replayed: u64,
+}
+
+impl FailoverStatus {
+    /// Whether reads and writes are being served by the fallback.
+    pub fn degraded(&self) -> bool {
+        self.breaker != BreakerState::Closed || self.pending_writes > 0
+    }
+}
+
+enum PendingWrite {
+    ReplaceHash {
+        key: String,
+        entries: Vec<(String, String)>,
+    },
+    ReplaceList {
+        key: String,
+        values: Vec<String>,
+    },
+    AppendList {
+        key: String,
+        value: String,
+    },
+}
+
+impl PendingWrite {
+    fn key(&self) -> &str {
+        match self {
+            PendingWrite::ReplaceHash { key, .. }
+            | PendingWrite::ReplaceList { key, .. }
+            | PendingWrite::AppendList { key, .. } => key,
+        }
+    }
+
+    async fn apply(&self, store: &dyn KvStore) -> Result<()> {
+        match self {
+            PendingWrite::ReplaceHash { key, entries } => store.replace_hash(key, entries).await,
+            PendingWrite::ReplaceList { key, values } => store.replace_list(key, values).await,
+            PendingWrite::AppendList { key, value } => store.append_list(key, value).await,
+        }
+    }
+}
+
+pub struct FailoverStore {
+    primary: Arc<dyn KvStore>,
+    fallback: Arc<dyn KvStore>,
+    breaker: CircuitBreaker,
+    /// Writes the primary has not seen yet, oldest first. Held for the
+    /// whole of every write so the primary gets them in the same order.
+    pending: AsyncMutex<VecDeque<PendingWrite>>,
+    pending_len: AtomicUsize,
+    failovers: AtomicU64,
+    replayed: AtomicU64,
+}
+
+impl FailoverStore {
+    pub fn new(
+        primary: Arc<dyn KvStore>,
+        fallback: Arc<dyn KvStore>,
+        breaker: CircuitBreaker,
+    ) -> Self {
+        Self {
+            primary,
+            fallback,
+            breaker,
+            pending: AsyncMutex::new(VecDeque::new()),
+            pending_len: AtomicUsize::new(0),
+            failovers: AtomicU64::new(0),
+            replayed: AtomicU64::new(0),
+        }
+    }
+
+    fn primary_failed(&self, err: &anyhow::Error, op: &str) {
+        warn!(
+            ?err,
+            op,
+            primary = self.primary.backend_name(),
+            "metadata store primary failed"
+        );
+        if self.breaker.record_failure() {
+            self.failovers.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                primary = self.primary.backend_name(),
+                fallback = self.fallback.backend_name(),
+                "metadata store failing over"
+            );
+        }
+    }
+
+    /// Replay queued writes to the primary until it fails or catches up.
+    async fn replay(&self, pending: &mut VecDeque<PendingWrite>) {
+        if pending.is_empty() || !self.breaker.allows() {
+            return;
+        }
+        while let Some(write) = pending.front() {
+            if let Err(err) = write.apply(&*self.primary).await {
+                self.primary_failed(&err, "replay");
+                self.pending_len.store(pending.len(), Ordering::Relaxed);
+                return;
+            }
+            pending.pop_front();
+            self.replayed.fetch_add(1, Ordering::Relaxed);
+        }
+        self.pending_len.store(0, Ordering::Relaxed);
+        self.breaker.record_success();
+        info!(
+            primary = self.primary.backend_name(),
+            "metadata store primary caught up"
+        );
+    }
+
+    async fn write(&self, write: PendingWrite, op: &str) -> Result<()> {
+        let mut pending = self.pending.lock().await;
+        self.replay(&mut pending).await;
+        if pending.is_empty() && self.breaker.allows() {
+            match write.apply(&*self.primary).await {
+                Ok(()) => {
+                    self.breaker.record_success();
+                    if let Err(err) = write.apply(&*self.fallback).await {
+                        warn!(?err, op, "failed to mirror write to metadata fallback");
+                    }
+                    return Ok(());
+                }
+                Err(err) => self.primary_failed(&err, op),
+            }
+        }
+        write.apply(&*self.fallback).await?;
+        // A replace makes every earlier write to the same key moot.
+        if !matches!(write, PendingWrite::AppendList { .. }) {
+            pending.retain(|queued| queued.key() != write.key());
+        }
+        pending.push_back(write);
+        self.pending_len.store(pending.len(), Ordering::Relaxed);
+        debug!(
+            op,
+            pending = pending.len(),
+            "write held for metadata primary"
+        );
+        Ok(())
+    }
+
+    /// Whether reads should try the primary: only when it has every write.
+    fn read_primary(&self) -> bool {
+        self.pending_len.load(Ordering::Relaxed) == 0 && self.breaker.allows()
+    }
+}
+
+#[async_trait]
+impl KvStore for FailoverStore {
+    fn backend_name(&self) -> &'static str {
+        self.primary.backend_name()
+    }
+
+    async fn replace_hash(&self, key: &str, entries: &[(String, String)]) -> Result<()> {
+        let write = PendingWrite::ReplaceHash {
+            key: key.to_string(),
+            entries: entries.to_vec(),
+        };
+        self.write(write, "replace_hash").await
+    }
+
+    async fn load_hash(&self, key: &str) -> Result<Vec<(String, String)>> {
+        if self.read_primary() {
+            match self.primary.load_hash(key).await {
+                Ok(entries) => {
+                    self.breaker.record_success();
+                    return Ok(entries);
+                }
+                Err(err) => self.primary_failed(&err, "load_hash"),
+            }
+        }
+        self.fallback.load_hash(key).await
+    }
+
+    async fn replace_list(&self, key: &str, values: &[String]) -> Result<()> {
+        let write = PendingWrite::ReplaceList {
+            key: key.to_string(),
+            values: values.to_vec(),
+        };
+        self.write(write, "replace_list").await
+    }
+
+    async fn load_list(&self, key: &str) -> Result<Vec<String>> {
+        if self.read_primary() {
+            match self.primary.load_list(key).await {
+                Ok(values) => {
+                    self.breaker.record_success();
+                    return Ok(values);
+                }
+                Err(err) => self.primary_failed(&err, "load_list"),
+            }
+        }
+        self.fallback.load_list(key).await
+    }
+
+    async fn append_list(&self, key: &str, value: &str) -> Result<()> {
+        let write = PendingWrite::AppendList {
+            key: key.to_string(),
+            value: value.to_string(),
+        };
+        self.write(write, "append_list").await
+    }
+
+    fn failover_status(&self) -> Option<FailoverStatus> {
+        Some(FailoverStatus {
+            primary: self.primary.backend_name(),
+            fallback: self.fallback.backend_name(),
+            breaker: self.breaker.state(),
+            pending_writes: self.pending_len.load(Ordering::Relaxed),
+            failovers: self.failovers.load(Ordering::Relaxed),
+            replayed: self.replayed.load(Ordering::Relaxed),
+        })
+    }
+
+    async fn replay_pending(&self) {
+        let mut pending = self.pending.lock().await;
+        self.replay(&mut pending).await;
+    }
+}
+
+impl AppState {
+    /// Offer held writes back to the primary every few seconds, so it catches
+    /// up even when nothing is being written. `None` without a fallback.
+    pub fn spawn_kv_replayer(&self, shutdown: Arc<Notify>) -> Option<JoinHandle<()>> {
+        self.kv.failover_status()?;
+        let kv = self.kv.clone();
+        Some(tokio::spawn(
+            async move {
+                let mut interval = tokio::time::interval(REPLAY_INTERVAL);
+                loop {
+                    tokio::select! {
+                        _ = shutdown.notified() => break,
+                        _ = interval.tick() => kv.replay_pending().await,
+                    }
+                }
+                // One last try so a recovered primary is not left behind.
+                kv.replay_pending().await;
+                debug!("metadata replay task stopped");
+            }
+            .instrument(tracing::info_span!("maintenance.kv_replay")),
+        ))
+    }
+}
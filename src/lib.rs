@@ -1,4 +1,51 @@
+pub mod access_log;
+pub mod accounts;
+pub mod assets;
+pub mod audit;
+pub mod ban_hits;
+pub mod ban_transfer;
+pub mod build_info;
+pub mod clamav;
+pub mod cli;
+pub mod cluster;
+pub mod config;
+pub mod connections;
+pub mod content_scan;
+pub mod cors;
+pub mod digest_fields;
+pub mod drain;
+pub mod email;
+pub mod email_queue;
+pub mod feature_flags;
+pub mod file_store;
+pub mod geoip;
 pub mod handlers;
+pub mod i18n;
+pub mod kv_failover;
+pub mod migrate;
+pub mod network_class;
+pub mod owner_events;
+pub mod quarantine;
 pub mod rate_limit;
+pub mod reindex;
+pub mod reload;
+pub mod report_chat;
+pub mod request_id;
+pub mod runtime;
+pub mod server;
+pub mod shadow;
+pub mod startup;
 pub mod state;
+pub mod storage_pressure;
+pub mod telemetry_config;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod tls;
+pub mod tombstones;
+pub mod trace_sampling;
+pub mod transparency;
+pub mod ttl_policy;
+pub mod upload_stats;
+pub mod upload_watch;
 pub mod util;
+pub mod webhooks;
@@ -0,0 +1,426 @@
+//! Loading metadata written by older releases. Each loader prefers what is
+//! already in the key-value store; only when the store holds nothing does it
+//! read the legacy JSON file, upgrading old shapes and hashing raw client
+//! addresses on the way. The returned flag says whether the file was used,
+//! so the caller knows to write the result back to the store.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::Path;
+use tokio::fs;
+use tracing::{debug, warn};
+
+use crate::state::{BanSubject, FileMeta, IpBan, KvStore, ReportRecord, ReportStatus};
+use crate::util::{
+    IpVersion, hash_ip_string, hash_network_from_cidr, looks_like_hash, new_id, ttl_to_duration,
+};
+
+#[tracing::instrument(skip(secret, kv, now))]
+pub async fn load_owners_with_migration(
+    path: &Path,
+    secret: &[u8],
+    kv: &dyn KvStore,
+    now: u64,
+) -> anyhow::Result<(HashMap<String, FileMeta>, bool)> {
+    {
+        let entries = kv.load_hash("owners").await?;
+        if !entries.is_empty() {
+            let mut map = HashMap::with_capacity(entries.len());
+            for (file, payload) in entries {
+                match serde_json::from_str::<FileMeta>(&payload) {
+                    Ok(meta) => {
+                        map.insert(file, meta);
+                    }
+                    Err(err) => warn!(?err, file, "ignoring malformed stored owner entry"),
+                }
+            }
+            return Ok((map, false));
+        }
+    }
+
+    let data = match fs::read(path).await {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok((HashMap::new(), false)),
+        Err(err) => return Err(err.into()),
+    };
+    if data.is_empty() {
+        return Ok((HashMap::new(), false));
+    }
+    if let Ok(mut map) = serde_json::from_slice::<HashMap<String, FileMeta>>(&data) {
+        let mut changed = false;
+        for meta in map.values_mut() {
+            if !looks_like_hash(&meta.owner_hash)
+                && let Some((_, hash)) = hash_ip_string(secret, &meta.owner_hash)
+            {
+                meta.owner_hash = hash;
+                changed = true;
+            }
+        }
+        if changed {
+            debug!("normalized owner hashes during migration");
+        }
+        return Ok((map, true));
+    }
+    if let Ok(old_map) = serde_json::from_slice::<HashMap<String, String>>(&data) {
+        let default_exp = now + ttl_to_duration("3d").as_secs();
+        let mut map = HashMap::new();
+        let mut changed = false;
+        for (file, owner) in old_map {
+            let owner_hash = if let Some((_, hash)) = hash_ip_string(secret, &owner) {
+                changed = true;
+                hash
+            } else {
+                owner
+            };
+            map.insert(
+                file,
+                FileMeta {
+                    owner_hash,
+                    expires: default_exp,
+                    original: String::new(),
+                    original_display: String::new(),
+                    created: now,
+                    hash: String::new(),
+                    max_downloads: None,
+                    downloads: 0,
+                    private: false,
+                    ttl_shortened_from: None,
+                    guest: false,
+                    network_class: None,
+                    size: 0,
+                },
+            );
+        }
+        if changed {
+            debug!("migrated legacy owner metadata to hashed entries");
+        }
+        return Ok((map, true));
+    }
+    Ok((HashMap::new(), false))
+}
+
+#[derive(Deserialize)]
+struct LegacyReportRecord {
+    file: String,
+    reason: String,
+    #[serde(default)]
+    details: String,
+    #[serde(alias = "reporter_hash")]
+    ip: String,
+    time: u64,
+}
+
+/// Give reports stored before ids existed one. Returns whether any changed.
+pub fn assign_report_ids(reports: &mut [ReportRecord]) -> bool {
+    let mut assigned = false;
+    for report in reports.iter_mut().filter(|r| r.id.is_empty()) {
+        report.id = new_id();
+        assigned = true;
+    }
+    assigned
+}
+
+#[tracing::instrument(skip(secret, kv))]
+pub async fn load_reports_with_migration(
+    path: &Path,
+    secret: &[u8],
+    kv: &dyn KvStore,
+) -> anyhow::Result<(Vec<ReportRecord>, bool)> {
+    {
+        let entries = kv.load_list("reports").await?;
+        if !entries.is_empty() {
+            let mut reports = Vec::with_capacity(entries.len());
+            for payload in entries {
+                match serde_json::from_str::<ReportRecord>(&payload) {
+                    Ok(report) => reports.push(report),
+                    Err(err) => warn!(?err, "ignoring malformed stored report entry"),
+                }
+            }
+            let assigned = assign_report_ids(&mut reports);
+            return Ok((reports, assigned));
+        }
+    }
+
+    let data = match fs::read(path).await {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok((Vec::new(), false)),
+        Err(err) => return Err(err.into()),
+    };
+    if data.is_empty() {
+        return Ok((Vec::new(), false));
+    }
+    if let Ok(mut reports) = serde_json::from_slice::<Vec<ReportRecord>>(&data) {
+        let mut changed = assign_report_ids(&mut reports);
+        for report in reports.iter_mut() {
+            if !looks_like_hash(&report.reporter_hash)
+                && let Some((_, hash)) = hash_ip_string(secret, &report.reporter_hash)
+            {
+                report.reporter_hash = hash;
+                changed = true;
+            }
+        }
+        if changed {
+            debug!("normalized legacy report hashes during migration");
+        }
+        return Ok((reports, true));
+    }
+    if let Ok(raw_reports) = serde_json::from_slice::<Vec<LegacyReportRecord>>(&data) {
+        let mut reports = Vec::with_capacity(raw_reports.len());
+        let mut migrated_any = false;
+        for raw in raw_reports {
+            let (reporter_hash, migrated) = if looks_like_hash(&raw.ip) {
+                (raw.ip, false)
+            } else if let Some((_, hash)) = hash_ip_string(secret, &raw.ip) {
+                (hash, true)
+            } else {
+                (raw.ip, false)
+            };
+            if migrated {
+                migrated_any = true;
+            }
+            reports.push(ReportRecord {
+                id: new_id(),
+                file: raw.file,
+                reason: raw.reason,
+                details: raw.details,
+                reporter_hash,
+                time: raw.time,
+                status: ReportStatus::Open,
+                resolution: None,
+                status_changed_at: None,
+            });
+        }
+        if migrated_any {
+            debug!("migrated legacy report records to hashed format");
+        }
+        return Ok((reports, true));
+    }
+    Ok((Vec::new(), false))
+}
+
+#[tracing::instrument(skip(kv))]
+pub async fn load_admin_sessions_with_migration(
+    path: &Path,
+    kv: &dyn KvStore,
+) -> anyhow::Result<(HashMap<String, u64>, bool)> {
+    let stored_sessions: HashMap<String, u64> = kv
+        .load_hash("admin_sessions")
+        .await?
+        .into_iter()
+        .filter_map(|(token, expires)| Some((token, expires.parse().ok()?)))
+        .collect();
+    if !stored_sessions.is_empty() {
+        return Ok((stored_sessions, false));
+    }
+
+    let data = match fs::read(path).await {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok((HashMap::new(), false)),
+        Err(err) => return Err(err.into()),
+    };
+    if data.is_empty() {
+        return Ok((HashMap::new(), false));
+    }
+    match serde_json::from_slice::<HashMap<String, u64>>(&data) {
+        Ok(map) => Ok((map, true)),
+        Err(err) => {
+            warn!(
+                ?err,
+                "failed to parse admin sessions file during migration; starting clean"
+            );
+            Ok((HashMap::new(), false))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct LegacyIpBan {
+    subject: LegacyBanSubject,
+    #[serde(default)]
+    label: Option<String>,
+    reason: String,
+    time: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+enum LegacyBanSubject {
+    Exact {
+        #[serde(default)]
+        hash: Option<String>,
+        #[serde(default)]
+        ip: Option<String>,
+    },
+    Network {
+        #[serde(default)]
+        hash: Option<String>,
+        #[serde(default)]
+        cidr: Option<String>,
+        #[serde(default)]
+        ip: Option<String>,
+        #[serde(default)]
+        prefix: Option<u8>,
+        #[serde(default)]
+        version: Option<IpVersion>,
+    },
+}
+
+#[tracing::instrument(skip(secret, kv))]
+pub async fn load_bans_with_migration(
+    path: &Path,
+    secret: &[u8],
+    kv: &dyn KvStore,
+) -> anyhow::Result<(Vec<IpBan>, bool)> {
+    {
+        let entries = kv.load_hash("bans").await?;
+        if !entries.is_empty() {
+            let mut bans = Vec::with_capacity(entries.len());
+            for (_, payload) in entries {
+                match serde_json::from_str::<IpBan>(&payload) {
+                    Ok(ban) => bans.push(ban),
+                    Err(err) => warn!(?err, "ignoring malformed stored ban entry"),
+                }
+            }
+            return Ok((bans, false));
+        }
+    }
+
+    let data = match fs::read(path).await {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok((Vec::new(), false)),
+        Err(err) => return Err(err.into()),
+    };
+    if data.is_empty() {
+        return Ok((Vec::new(), false));
+    }
+    if let Ok(mut bans) = serde_json::from_slice::<Vec<IpBan>>(&data) {
+        let mut changed = false;
+        for ban in bans.iter_mut() {
+            match &mut ban.subject {
+                BanSubject::Exact { hash } => {
+                    if !looks_like_hash(hash)
+                        && let Some((_, new_hash)) = hash_ip_string(secret, hash)
+                    {
+                        *hash = new_hash;
+                        changed = true;
+                    }
+                }
+                BanSubject::Network {
+                    hash,
+                    prefix,
+                    version,
+                } => {
+                    if !looks_like_hash(hash) {
+                        let cidr = format!("{}/{}", hash, prefix);
+                        if let Some((ver, pre, new_hash)) = hash_network_from_cidr(secret, &cidr) {
+                            *version = ver;
+                            *prefix = pre;
+                            *hash = new_hash;
+                            changed = true;
+                        }
+                    }
+                }
+                BanSubject::Country { .. } | BanSubject::Asn { .. } => {}
+            }
+        }
+        if changed {
+            debug!("normalized legacy ban entries during migration");
+        }
+        return Ok((bans, true));
+    }
+    if let Ok(raw_bans) = serde_json::from_slice::<Vec<LegacyIpBan>>(&data) {
+        let mut bans = Vec::with_capacity(raw_bans.len());
+        let mut migrated_any = false;
+        for raw in raw_bans {
+            let subject = match raw.subject {
+                LegacyBanSubject::Exact { hash, ip } => {
+                    let value = hash.or(ip).unwrap_or_default();
+                    let (final_hash, migrated) = if looks_like_hash(&value) {
+                        (value, false)
+                    } else if let Some((_, new_hash)) = hash_ip_string(secret, &value) {
+                        (new_hash, true)
+                    } else {
+                        (value, false)
+                    };
+                    if migrated {
+                        migrated_any = true;
+                    }
+                    BanSubject::Exact { hash: final_hash }
+                }
+                LegacyBanSubject::Network {
+                    hash,
+                    cidr,
+                    ip,
+                    prefix,
+                    version,
+                } => {
+                    let mut migrated = false;
+                    let from_cidr = cidr
+                        .as_ref()
+                        .and_then(|c| hash_network_from_cidr(secret, c));
+                    let (version, prefix, final_hash) =
+                        if let Some((ver, pre, new_hash)) = from_cidr {
+                            migrated = true;
+                            (ver, pre, new_hash)
+                        } else if let (Some(ip), Some(pre)) = (ip.as_ref(), prefix) {
+                            let cidr_string = format!("{}/{}", ip, pre);
+                            if let Some((ver, pre, new_hash)) =
+                                hash_network_from_cidr(secret, &cidr_string)
+                            {
+                                migrated = true;
+                                (ver, pre, new_hash)
+                            } else {
+                                let ver = version.unwrap_or_else(|| {
+                                    if ip.contains(':') {
+                                        IpVersion::V6
+                                    } else {
+                                        IpVersion::V4
+                                    }
+                                });
+                                (ver, pre, hash.clone().unwrap_or_else(|| ip.clone()))
+                            }
+                        } else if let Some(existing) = hash {
+                            let ver = version.unwrap_or(IpVersion::V4);
+                            let pre = prefix.unwrap_or(match ver {
+                                IpVersion::V4 => 32,
+                                IpVersion::V6 => 128,
+                            });
+                            if looks_like_hash(&existing) {
+                                (ver, pre, existing)
+                            } else if let Some((ver2, pre2, new_hash)) =
+                                hash_network_from_cidr(secret, &format!("{}/{}", existing, pre))
+                            {
+                                migrated = true;
+                                (ver2, pre2, new_hash)
+                            } else {
+                                (ver, pre, existing)
+                            }
+                        } else {
+                            (IpVersion::V4, 32, String::new())
+                        };
+                    if migrated {
+                        migrated_any = true;
+                    }
+                    BanSubject::Network {
+                        hash: final_hash,
+                        prefix,
+                        version,
+                    }
+                }
+            };
+            bans.push(IpBan {
+                subject,
+                label: raw.label,
+                reason: raw.reason,
+                time: raw.time,
+                expires: None,
+            });
+        }
+        if migrated_any {
+            debug!("migrated legacy ban records to hashed format");
+        }
+        return Ok((bans, true));
+    }
+    Ok((Vec::new(), false))
+}
@@ -0,0 +1,352 @@
+use arc_swap::ArcSwap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tracing::{Instrument, debug, info, warn};
+
+use crate::state::AppState;
+
+/// How often the Tor and hosting lists are fetched again, unless
+/// `JUICEBOX_NETWORK_LIST_REFRESH_SECS` says otherwise.
+pub const DEFAULT_NETWORK_LIST_REFRESH_SECS: u64 = 6 * 60 * 60;
+
+/// What kind of network a client connects from.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum NetworkClass {
+    /// Not on any list.
+    #[default]
+    Residential,
+    /// A VPN, proxy or datacenter range from the hosting list.
+    Hosting,
+    /// A Tor exit relay.
+    Tor,
+}
+
+impl NetworkClass {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NetworkClass::Residential => "residential",
+            NetworkClass::Hosting => "hosting",
+            NetworkClass::Tor => "tor",
+        }
+    }
+
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "residential" => Some(NetworkClass::Residential),
+            "hosting" | "vpn" => Some(NetworkClass::Hosting),
+            "tor" => Some(NetworkClass::Tor),
+            _ => None,
+        }
+    }
+}
+
+/// Where the lists come from and what the other subsystems do with them.
+#[derive(Clone, Debug, Default)]
+pub struct NetworkListConfig {
+    /// URL or local path of the Tor exit list (one address per line).
+    pub tor_source: Option<String>,
+    /// URL or local path of the VPN/hosting list (addresses or CIDRs).
+    pub hosting_source: Option<String>,
+    pub refresh: Duration,
+    /// Rate-limit tokens one request from a non-residential network costs.
+    pub nonresidential_rate_cost: u32,
+    /// Classes whose uploads are held in quarantine for review.
+    pub premoderate: HashSet<NetworkClass>,
+}
+
+impl NetworkListConfig {
+    /// Read `JUICEBOX_TOR_EXIT_LIST`, `JUICEBOX_HOSTING_LIST`,
+    /// `JUICEBOX_NETWORK_LIST_REFRESH_SECS`, `JUICEBOX_NONRESIDENTIAL_RATE_COST`
+    /// and `JUICEBOX_PREMODERATE_NETWORKS`.
+    pub fn from_env() -> Self {
+        let source = |key: &str| {
+            std::env::var(key)
+                .ok()
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+        let number = |key: &str| {
+            std::env::var(key)
+                .ok()
+                .and_then(|v| v.trim().parse::<u64>().ok())
+        };
+        let premoderate = std::env::var("JUICEBOX_PREMODERATE_NETWORKS")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(NetworkClass::parse)
+            .filter(|class| *class != NetworkClass::Residential)
+            .collect();
+        Self {
+            tor_source: source("JUICEBOX_TOR_EXIT_LIST"),
+            hosting_source: source("JUICEBOX_HOSTING_LIST"),
+            refresh: Duration::from_secs(
+                number("JUICEBOX_NETWORK_LIST_REFRESH_SECS")
+                    .filter(|secs| *secs > 0)
+                    .unwrap_or(DEFAULT_NETWORK_LIST_REFRESH_SECS),
+            ),
+            nonresidential_rate_cost: number("JUICEBOX_NONRESIDENTIAL_RATE_COST")
+                .map_or(1, |cost| cost.clamp(1, u32::MAX as u64) as u32),
+            premoderate,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.tor_source.is_some() || self.hosting_source.is_some()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Cidr {
+    base: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    fn parse(raw: &str) -> Option<Self> {
+        let (base, prefix) = match raw.split_once('/') {
+            Some((base, prefix)) => (base.parse::<IpAddr>().ok()?, prefix.parse::<u8>().ok()?),
+            None => {
+                let base = raw.parse::<IpAddr>().ok()?;
+                (base, if base.is_ipv4() { 32 } else { 128 })
+            }
+        };
+        let max = if base.is_ipv4() { 32 } else { 128 };
+        (prefix <= max).then_some(Self { base, prefix })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.base, ip) {
+            (IpAddr::V4(base), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(base) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(base), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(base) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Parse a list of addresses and CIDR ranges, one per line, ignoring `#`
+/// comments and anything it cannot read.
+fn parse_ranges(contents: &str) -> Vec<Cidr> {
+    contents
+        .lines()
+        .filter_map(|line| line.split('#').next())
+        .filter_map(|line| line.split_whitespace().next())
+        .filter_map(Cidr::parse)
+        .collect()
+}
+
+#[derive(Default)]
+struct Lists {
+    tor: HashSet<IpAddr>,
+    hosting: Vec<Cidr>,
+}
+
+/// What `/api/admin/v1/runtime` reports about the network lists.
+#[derive(Serialize, Debug, Clone)]
+pub struct NetworkListSummary {
+    pub enabled: bool,
+    pub tor_exits: usize,
+    pub hosting_ranges: usize,
+    pub refresh_secs: u64,
+    pub nonresidential_rate_cost: u32,
+    pub premoderate: Vec<NetworkClass>,
+}
+
+/// The current Tor exit and hosting lists, swapped wholesale on each refresh
+/// so lookups never wait on a fetch.
+pub struct NetworkLists {
+    config: NetworkListConfig,
+    lists: ArcSwap<Lists>,
+}
+
+impl Default for NetworkLists {
+    fn default() -> Self {
+        Self::new(NetworkListConfig::default())
+    }
+}
+
+impl NetworkLists {
+    pub fn new(config: NetworkListConfig) -> Self {
+        Self {
+            config,
+            lists: ArcSwap::from_pointee(Lists::default()),
+        }
+    }
+
+    pub fn config(&self) -> &NetworkListConfig {
+        &self.config
+    }
+
+    pub fn classify(&self, ip: IpAddr) -> NetworkClass {
+        let lists = self.lists.load();
+        if lists.tor.contains(&ip) {
+            NetworkClass::Tor
+        } else if lists.hosting.iter().any(|range| range.contains(ip)) {
+            NetworkClass::Hosting
+        } else {
+            NetworkClass::Residential
+        }
+    }
+
+    /// Replace the lists with freshly read contents. `None` keeps the
+    /// current copy of that list.
+    pub fn replace(&self, tor: Option<&str>, hosting: Option<&str>) {
+        let current = self.lists.load();
+        let tor = tor.map_or_else(
+            || current.tor.clone(),
+            |text| {
+                parse_ranges(text)
+                    .into_iter()
+                    .filter(|c| c.prefix == if c.base.is_ipv4() { 32 } else { 128 })
+                    .map(|c| c.base)
+                    .collect()
+            },
+        );
+        let hosting = hosting.map_or_else(|| current.hosting.clone(), parse_ranges);
+        debug!(
+            tor = tor.len(),
+            hosting = hosting.len(),
+            "network lists replaced"
+        );
+        self.lists.store(Arc::new(Lists { tor, hosting }));
+    }
+
+    /// Entries in the Tor and hosting lists.
+    pub fn sizes(&self) -> (usize, usize) {
+        let lists = self.lists.load();
+        (lists.tor.len(), lists.hosting.len())
+    }
+
+    /// Rate-limit tokens a request from `ip` costs.
+    pub fn rate_cost(&self, ip: IpAddr) -> u32 {
+        if self.config.nonresidential_rate_cost <= 1 {
+            return 1;
+        }
+        match self.classify(ip) {
+            NetworkClass::Residential => 1,
+            _ => self.config.nonresidential_rate_cost,
+        }
+    }
+
+    pub fn premoderates(&self, class: NetworkClass) -> bool {
+        self.config.premoderate.contains(&class)
+    }
+
+    pub fn summary(&self) -> NetworkListSummary {
+        let (tor_exits, hosting_ranges) = self.sizes();
+        let mut premoderate: Vec<_> = self.config.premoderate.iter().copied().collect();
+        premoderate.sort_by_key(|class| class.as_str());
+        NetworkListSummary {
+            enabled: self.config.is_enabled(),
+            tor_exits,
+            hosting_ranges,
+            refresh_secs: self.config.refresh.as_secs(),
+            nonresidential_rate_cost: self.config.nonresidential_rate_cost,
+            premoderate,
+        }
+    }
+}
+
+async fn read_source(client: &reqwest::Client, source: &str) -> anyhow::Result<String> {
+    if source.starts_with("http://") || source.starts_with("https://") {
+        Ok(client
+            .get(source)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?)
+    } else {
+        Ok(tokio::fs::read_to_string(source).await?)
+    }
+}
+
+impl AppState {
+    /// Network class of a client IP as returned by `real_client_ip`.
+    /// Anything that does not parse as an address counts as residential.
+    pub fn network_class(&self, client_ip: &str) -> NetworkClass {
+        client_ip
+            .parse::<IpAddr>()
+            .map_or(NetworkClass::Residential, |ip| self.networks.classify(ip))
+    }
+
+    /// Fetch every configured list once. A list that fails to load keeps its
+    /// previous contents.
+    #[tracing::instrument(level = "debug", skip(self, client))]
+    pub async fn refresh_network_lists(&self, client: &reqwest::Client) {
+        let config = self.networks.config();
+        let mut fetched = [None, None];
+        for (slot, source) in fetched
+            .iter_mut()
+            .zip([&config.tor_source, &config.hosting_source])
+        {
+            let Some(source) = source else { continue };
+            match read_source(client, source).await {
+                Ok(text) => *slot = Some(text),
+                Err(err) => warn!(?err, source, "failed to fetch network list"),
+            }
+        }
+        let [tor, hosting] = fetched;
+        self.networks.replace(tor.as_deref(), hosting.as_deref());
+        let (tor, hosting) = self.networks.sizes();
+        info!(tor, hosting, "network lists refreshed");
+    }
+
+    /// Refresh the lists now and then every `refresh` until shutdown.
+    pub fn spawn_network_list_refresher(&self, shutdown: Arc<Notify>) -> Option<JoinHandle<()>> {
+        if !self.networks.config().is_enabled() {
+            return None;
+        }
+        let state = self.clone();
+        let every = self.networks.config().refresh;
+        Some(tokio::spawn(
+            async move {
+                let client = reqwest::Client::builder()
+                    .timeout(Duration::from_secs(30))
+                    .build()
+                    .unwrap_or_default();
+                let mut interval = tokio::time::interval(every);
+                loop {
+                    tokio::select! {
+                        _ = shutdown.notified() => break,
+                        _ = interval.tick() => state.refresh_network_lists(&client).await,
+                    }
+                }
+                debug!("network list refresher stopped");
+            }
+            .instrument(tracing::info_span!("maintenance.network_lists")),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cidr_ranges_match_both_families() {
+        let range = Cidr::parse("198.51.100.0/22").unwrap();
+        assert!(range.contains("198.51.103.255".parse().unwrap()));
+        assert!(!range.contains("198.51.104.0".parse().unwrap()));
+        let v6 = Cidr::parse("2001:db8::/33").unwrap();
+        assert!(v6.contains("2001:db8:7fff::1".parse().unwrap()));
+        assert!(!v6.contains("2001:db8:8000::1".parse().unwrap()));
+        assert!(
+            Cidr::parse("0.0.0.0/0")
+                .unwrap()
+                .contains("8.8.8.8".parse().unwrap())
+        );
+        assert!(Cidr::parse("10.0.0.0/33").is_none());
+    }
+}
@@ -0,0 +1,72 @@
+//! Live notifications for file owners: events that concern one owner's files
+//! are published here and fanned out to that owner's `/ws` connections.
+//! Nothing is stored; an owner with no open connection simply misses them.
+
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// Events buffered for slow connections before they start missing some.
+pub const OWNER_EVENTS_CAPACITY: usize = 256;
+/// How close to expiry a file is when its owner is warned.
+pub const EXPIRY_WARNING_SECS: u64 = 10 * 60;
+/// How often each connection looks for files about to expire.
+pub const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Something an owner may want to hear about while they have the site open.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "event")]
+pub enum OwnerEvent {
+    #[serde(rename = "file.expiring")]
+    FileExpiring { file: String, expires: u64 },
+    #[serde(rename = "file.reported")]
+    FileReported { file: String, reason: String },
+    #[serde(rename = "assembly.completed")]
+    AssemblyCompleted { session_id: String, file: String },
+}
+
+/// An event and the owner it is for.
+#[derive(Clone, Debug)]
+pub struct OwnerNotice {
+    pub owner_hash: String,
+    pub event: OwnerEvent,
+}
+
+pub struct OwnerEvents {
+    tx: broadcast::Sender<Arc<OwnerNotice>>,
+}
+
+impl Default for OwnerEvents {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OwnerEvents {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(OWNER_EVENTS_CAPACITY);
+        Self { tx }
+    }
+
+    /// Send `event` to every open connection for `owner_hash`.
+    pub fn publish(&self, owner_hash: &str, event: OwnerEvent) {
+        if owner_hash.is_empty() || self.tx.receiver_count() == 0 {
+            return;
+        }
+        let _ = self.tx.send(Arc::new(OwnerNotice {
+            owner_hash: owner_hash.to_string(),
+            event,
+        }));
+    }
+
+    /// Every event published from now on, for all owners; callers filter.
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<OwnerNotice>> {
+        self.tx.subscribe()
+    }
+
+    /// Open `/ws` connections.
+    pub fn listeners(&self) -> usize {
+        self.tx.receiver_count()
+    }
+}
@@ -0,0 +1,364 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::sync::RwLock;
+use tracing::{debug, error, info, warn};
+
+use crate::clamav::ScanVerdict;
+use crate::state::{AppState, FileMeta};
+use crate::util::{display_original_name, looks_like_hash};
+
+/// Which integration flagged a file.
+pub const SOURCE_HASH_LIST: &str = "hash_list";
+/// Held for review because of the network it was uploaded from.
+pub const SOURCE_NETWORK: &str = "network";
+/// Flagged by the clamd virus scanner.
+pub const SOURCE_CLAMAV: &str = "clamav";
+
+/// Outcome of [`AppState::screen_upload`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Screening {
+    /// The upload stays hosted.
+    Passed,
+    /// Quarantined for review: a listed hash, a pre-moderated network, or a
+    /// scan that failed while scanning is fail-closed.
+    Held,
+    /// Quarantined because clamd found this signature.
+    Infected(String),
+}
+
+impl Screening {
+    pub fn is_quarantined(&self) -> bool {
+        !matches!(self, Screening::Passed)
+    }
+}
+
+/// A flagged upload held out of the file store until an admin decides
+/// what to do with it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct QuarantineRecord {
+    pub file: String,
+    pub original: String,
+    pub owner_hash: String,
+    pub hash: String,
+    pub size: u64,
+    pub created: u64,
+    pub expires: u64,
+    pub quarantined_at: u64,
+    pub source: String,
+    pub verdict: String,
+    #[serde(default)]
+    pub details: String,
+    #[serde(default)]
+    pub private: bool,
+    #[serde(default)]
+    pub guest: bool,
+    #[serde(default)]
+    pub max_downloads: Option<u32>,
+    #[serde(default)]
+    pub downloads: u64,
+}
+
+/// Quarantined files live in their own directory so no static or file route
+/// can serve them, and their metadata is kept apart from `owners`.
+pub struct Quarantine {
+    dir: PathBuf,
+    records: RwLock<HashMap<String, QuarantineRecord>>,
+    blocklist: RwLock<HashSet<String>>,
+}
+
+/// Parse a hash list: one lowercase or uppercase sha256 per line, `#` comments
+/// and anything after the first whitespace ignored.
+pub fn parse_hash_list(contents: &str) -> HashSet<String> {
+    contents
+        .lines()
+        .filter_map(|line| line.split('#').next())
+        .filter_map(|line| line.split_whitespace().next())
+        .map(|hash| hash.to_ascii_lowercase())
+        .filter(|hash| looks_like_hash(hash))
+        .collect()
+}
+
+impl Quarantine {
+    /// Create the quarantine, loading the hash list at `blocklist_path` when
+    /// it exists.
+    pub fn open(dir: PathBuf, blocklist_path: &Path) -> Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        let blocklist = match std::fs::read_to_string(blocklist_path) {
+            Ok(contents) => parse_hash_list(&contents),
+            Err(err) if err.kind() == ErrorKind::NotFound => HashSet::new(),
+            Err(err) => return Err(err.into()),
+        };
+        debug!(dir = ?dir, hashes = blocklist.len(), "opened quarantine");
+        Ok(Self {
+            dir,
+            records: RwLock::new(HashMap::new()),
+            blocklist: RwLock::new(blocklist),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub async fn records(&self) -> Vec<QuarantineRecord> {
+        let mut records: Vec<QuarantineRecord> =
+            self.records.read().await.values().cloned().collect();
+        records.sort_by_key(|r| std::cmp::Reverse(r.quarantined_at));
+        records
+    }
+
+    pub async fn get(&self, file: &str) -> Option<QuarantineRecord> {
+        self.records.read().await.get(file).cloned()
+    }
+
+    pub async fn is_blocklisted(&self, hash: &str) -> bool {
+        self.blocklist
+            .read()
+            .await
+            .contains(&hash.to_ascii_lowercase())
+    }
+
+    pub async fn add_blocklisted_hashes(&self, hashes: impl IntoIterator<Item = String>) {
+        let mut blocklist = self.blocklist.write().await;
+        blocklist.extend(hashes.into_iter().map(|h| h.to_ascii_lowercase()));
+    }
+}
+
+impl AppState {
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn load_quarantine(&self) -> Result<()> {
+        let entries = self.kv.load_hash("quarantine").await?;
+        let mut records = self.quarantine.records.write().await;
+        for (file, value) in entries {
+            match serde_json::from_str::<QuarantineRecord>(&value) {
+                Ok(record) => {
+                    records.insert(file, record);
+                }
+                Err(err) => warn!(?err, file, "skipping malformed quarantine record"),
+            }
+        }
+        info!(count = records.len(), "loaded quarantine records");
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn persist_quarantine(&self) {
+        let snapshot = self.quarantine.records.read().await.clone();
+        let mut encoded = Vec::with_capacity(snapshot.len());
+        for (file, record) in snapshot.iter() {
+            match serde_json::to_string(record) {
+                Ok(value) => encoded.push((file.clone(), value)),
+                Err(err) => {
+                    error!(?err, file, "failed to serialize quarantine record");
+                    return;
+                }
+            }
+        }
+        if let Err(err) = self.kv.replace_hash("quarantine", &encoded).await {
+            error!(?err, "failed to persist quarantine to key-value store");
+            return;
+        }
+        debug!(
+            count = encoded.len(),
+            "persisted quarantine to key-value store"
+        );
+    }
+
+    /// Pull a hosted file out of service: move it into the quarantine
+    /// directory, drop it from `owners` and record the verdict.
+    #[tracing::instrument(level = "info", skip(self, details))]
+    pub async fn quarantine_file(
+        &self,
+        file: &str,
+        source: &str,
+        verdict: &str,
+        details: &str,
+    ) -> Result<()> {
+        let Some((_, meta)) = self.remove_owner(file) else {
+            return Err(anyhow!("file {file} is not hosted"));
+        };
+        let dst = self.quarantine.dir.join(file);
+        let size = self.file_store.size(file).await.ok().flatten().unwrap_or(0);
+        if let Err(err) = self.file_store.export(file, &dst).await {
+            self.insert_owner(file.to_string(), meta);
+            return Err(err);
+        }
+        let record = QuarantineRecord {
+            file: file.to_string(),
+            original: meta.original,
+            owner_hash: meta.owner_hash,
+            hash: meta.hash,
+            size,
+            created: meta.created,
+            expires: meta.expires,
+            quarantined_at: self.now_secs(),
+            source: source.to_string(),
+            verdict: verdict.to_string(),
+            details: details.to_string(),
+            private: meta.private,
+            guest: meta.guest,
+            max_downloads: meta.max_downloads,
+            downloads: meta.downloads,
+        };
+        self.quarantine
+            .records
+            .write()
+            .await
+            .insert(file.to_string(), record);
+        self.persist_owners().await;
+        self.persist_quarantine().await;
+        warn!(file, source, verdict, "file quarantined");
+        Ok(())
+    }
+
+    /// Check a freshly stored upload against the hash list, the networks
+    /// held for pre-moderation and clamd, quarantining it on a match. Upload
+    /// handlers call this before answering, so nobody has the file's name
+    /// until it has been screened.
+    pub async fn screen_upload(&self, file: &str, hash: &str) -> Screening {
+        let (source, verdict, screening) = if self.quarantine.is_blocklisted(hash).await {
+            (
+                SOURCE_HASH_LIST,
+                "blocklisted hash".to_string(),
+                Screening::Held,
+            )
+        } else if let Some(class) = self
+            .owners
+            .get(file)
+            .and_then(|meta| meta.network_class)
+            .filter(|class| self.networks.premoderates(*class))
+        {
+            (SOURCE_NETWORK, class.as_str().to_string(), Screening::Held)
+        } else {
+            match self.scan_upload(file).await {
+                Screening::Passed => return Screening::Passed,
+                Screening::Infected(signature) => (
+                    SOURCE_CLAMAV,
+                    signature.clone(),
+                    Screening::Infected(signature),
+                ),
+                Screening::Held => (SOURCE_CLAMAV, "scan failed".to_string(), Screening::Held),
+            }
+        };
+        match self.quarantine_file(file, source, &verdict, hash).await {
+            Ok(()) => screening,
+            Err(err) => {
+                // A flagged upload must never stay hosted, so when it cannot
+                // be held for review it is dropped instead.
+                error!(?err, file, "failed to quarantine upload; discarding it");
+                self.discard_upload(file).await;
+                screening
+            }
+        }
+    }
+
+    /// Remove a flagged upload's metadata and body outright.
+    async fn discard_upload(&self, file: &str) {
+        if self.remove_owner(file).is_some() {
+            self.persist_owners().await;
+        }
+        if let Err(err) = self.file_store.delete(file).await {
+            error!(?err, file, "failed to delete flagged upload");
+        }
+    }
+
+    /// Run `file` past clamd when scanning is configured. A failed scan
+    /// passes the file unless scanning is fail-closed.
+    async fn scan_upload(&self, file: &str) -> Screening {
+        let Some(clamd) = self.clamd.as_deref() else {
+            return Screening::Passed;
+        };
+        let scanned = match self.file_store.open(file).await {
+            Ok(Some(body)) => clamd.scan(body).await,
+            Ok(None) => Err(anyhow!("file {file} is not in the file store")),
+            Err(err) => Err(err),
+        };
+        match scanned {
+            Ok(ScanVerdict::Clean) => {
+                debug!(file, "clamd scan clean");
+                Screening::Passed
+            }
+            Ok(ScanVerdict::Infected(signature)) => Screening::Infected(signature),
+            Err(err) if clamd.is_fail_closed() => {
+                error!(error = %format!("{err:#}"), file, "clamd scan failed; holding upload");
+                Screening::Held
+            }
+            Err(err) => {
+                error!(error = %format!("{err:#}"), file, "clamd scan failed; letting upload through");
+                Screening::Passed
+            }
+        }
+    }
+
+    /// Return a quarantined file to service with its original metadata.
+    #[tracing::instrument(level = "info", skip(self))]
+    pub async fn release_quarantined(&self, file: &str) -> Result<QuarantineRecord> {
+        let mut records = self.quarantine.records.write().await;
+        let Some(record) = records.get(file).cloned() else {
+            return Err(anyhow!("file {file} is not quarantined"));
+        };
+        self.file_store
+            .import(file, &self.quarantine.dir.join(file))
+            .await?;
+        records.remove(file);
+        drop(records);
+        self.insert_owner(
+            file.to_string(),
+            FileMeta {
+                owner_hash: record.owner_hash.clone(),
+                expires: record.expires,
+                original: record.original.clone(),
+                original_display: display_original_name(&record.original),
+                created: record.created,
+                hash: record.hash.clone(),
+                max_downloads: record.max_downloads,
+                downloads: record.downloads,
+                private: record.private,
+                ttl_shortened_from: None,
+                guest: record.guest,
+                network_class: None,
+                size: record.size,
+            },
+        );
+        self.persist_owners().await;
+        self.persist_quarantine().await;
+        info!(file, "quarantined file released");
+        Ok(record)
+    }
+
+    /// Permanently remove a quarantined file and its record.
+    #[tracing::instrument(level = "info", skip(self))]
+    pub async fn delete_quarantined(&self, file: &str) -> Option<QuarantineRecord> {
+        let record = self.quarantine.records.write().await.remove(file)?;
+        if let Err(err) = fs::remove_file(self.quarantine.dir.join(file)).await
+            && err.kind() != ErrorKind::NotFound
+        {
+            warn!(?err, file, "failed to remove quarantined file");
+        }
+        self.persist_quarantine().await;
+        info!(file, "quarantined file deleted");
+        Some(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_hash_list;
+
+    #[test]
+    fn parses_hash_list_with_comments() {
+        let upper = "A".repeat(64);
+        let contents = format!(
+            "# known bad\n{upper}  eicar\n\nnot-a-hash\n{} # trailing\n",
+            "b".repeat(64)
+        );
+        let parsed = parse_hash_list(&contents);
+        assert_eq!(parsed.len(), 2);
+        assert!(parsed.contains(&"a".repeat(64)));
+        assert!(parsed.contains(&"b".repeat(64)));
+    }
+}
@@ -1,23 +1,87 @@
-use crate::util::{extract_client_ip, json_error};
+use crate::network_class::NetworkLists;
+use crate::reload::{self, HotSettings};
+use crate::state::ApiTokens;
+use crate::util::{bearer_token, extract_client_ip, json_error};
 use axum::extract::ConnectInfo;
-use axum::http::StatusCode;
+use axum::http::header::RETRY_AFTER;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
 use axum::{body::Body, http::Request, response::Response};
 use std::net::SocketAddr as ClientAddr;
 use std::{
     collections::HashMap,
+    fmt,
     future::Future,
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
     time::{Duration, Instant},
 };
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, watch};
 use tower::{Layer, Service};
 
+#[derive(Clone, Copy)]
 struct RateLimitConfig {
     capacity: u32,
-    refill_per_second: u32,
+    refill_per_second: f64,
 }
+
+/// Where a limiter takes its burst and refill rate from.
+#[derive(Clone)]
+enum RateLimitSource {
+    Fixed(RateLimitConfig),
+    /// The general limit, which follows `JUICEBOX_RATE_LIMIT_*` on reload.
+    Hot(watch::Receiver<Arc<HotSettings>>),
+}
+pub const RATE_LIMIT_LIMIT: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+pub const RATE_LIMIT_REMAINING: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+pub const RATE_LIMIT_RESET: HeaderName = HeaderName::from_static("x-ratelimit-reset");
+
+/// The outcome of one check against a bucket, with what a client needs to
+/// pace itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateDecision {
+    pub allowed: bool,
+    /// The bucket's capacity.
+    pub limit: u32,
+    /// Whole tokens left once this request is paid for.
+    pub remaining: u32,
+    /// Seconds until the bucket is full again.
+    pub reset_secs: u64,
+    /// Seconds until the request could be afforded; zero when allowed.
+    pub retry_after_secs: u64,
+}
+
+impl RateDecision {
+    /// Rejected, or with a tenth of the bucket or less left.
+    pub fn is_near_limit(&self) -> bool {
+        !self.allowed || self.remaining.saturating_mul(10) <= self.limit
+    }
+
+    /// Set `X-RateLimit-*`, plus `Retry-After` when the request was refused.
+    /// Reset and retry times are in seconds from now.
+    pub fn apply_headers(&self, headers: &mut HeaderMap) {
+        headers.insert(RATE_LIMIT_LIMIT, HeaderValue::from(self.limit));
+        headers.insert(RATE_LIMIT_REMAINING, HeaderValue::from(self.remaining));
+        headers.insert(RATE_LIMIT_RESET, HeaderValue::from(self.reset_secs));
+        if !self.allowed {
+            headers.insert(RETRY_AFTER, HeaderValue::from(self.retry_after_secs.max(1)));
+        }
+    }
+}
+
+/// Whole seconds for `tokens` to refill; a bucket that never refills only
+/// starts over once the cleanup sweep drops it, after the configured idle
+/// window.
+fn refill_secs(tokens: f64, refill_per_second: f64) -> u64 {
+    if tokens <= 0.0 {
+        0
+    } else if refill_per_second <= 0.0 {
+        reload::current().rate_limit_idle_secs
+    } else {
+        (tokens / refill_per_second).ceil() as u64
+    }
+}
+
 #[derive(Clone, Debug)]
 struct RateBucket {
     tokens: f64,
@@ -27,36 +91,119 @@ struct RateBucket {
 #[derive(Clone)]
 pub struct RateLimiterInner {
     buckets: Arc<RwLock<HashMap<String, RateBucket>>>,
-    cfg: Arc<RateLimitConfig>,
+    source: RateLimitSource,
 }
 impl RateLimiterInner {
     pub fn new(capacity: u32, refill_per_second: u32) -> Self {
         Self {
             buckets: Arc::new(RwLock::new(HashMap::new())),
-            cfg: Arc::new(RateLimitConfig {
+            source: RateLimitSource::Fixed(RateLimitConfig {
                 capacity,
-                refill_per_second,
+                refill_per_second: refill_per_second as f64,
             }),
         }
     }
+
+    /// A limiter whose burst and refill rate track the reloadable settings.
+    pub fn hot() -> Self {
+        Self {
+            buckets: Arc::new(RwLock::new(HashMap::new())),
+            source: RateLimitSource::Hot(reload::subscribe()),
+        }
+    }
+
+    fn config(&self) -> RateLimitConfig {
+        match &self.source {
+            RateLimitSource::Fixed(cfg) => *cfg,
+            RateLimitSource::Hot(rx) => {
+                let settings = rx.borrow();
+                RateLimitConfig {
+                    capacity: settings.rate_limit_burst,
+                    refill_per_second: settings.rate_limit_refill_per_sec as f64,
+                }
+            }
+        }
+    }
     pub async fn check(&self, ip: &str) -> bool {
+        self.check_cost(ip, 1).await
+    }
+    /// Take `cost` tokens from `key`'s bucket if it has them.
+    pub async fn check_cost(&self, key: &str, cost: u32) -> bool {
+        self.decide(key, cost).await.allowed
+    }
+    /// Take `cost` tokens from `key`'s general bucket, reporting how full it
+    /// is left.
+    pub async fn decide(&self, key: &str, cost: u32) -> RateDecision {
+        self.take(key, cost, self.config()).await
+    }
+    /// `policy`'s limit, with any `JUICEBOX_RATE_LIMIT_ROUTES` override when
+    /// this limiter follows the reloadable settings.
+    pub fn route_limit(&self, policy: &RoutePolicy) -> RouteLimit {
+        match &self.source {
+            RateLimitSource::Fixed(_) => policy.limit,
+            RateLimitSource::Hot(rx) => rx
+                .borrow()
+                .rate_limit_routes
+                .get(policy.name)
+                .copied()
+                .unwrap_or(policy.limit),
+        }
+    }
+    /// Like [`decide`](Self::decide), but against `policy`'s own bucket for
+    /// `key` rather than the general one. `None` when the route is exempt.
+    pub async fn check_route(
+        &self,
+        policy: &RoutePolicy,
+        key: &str,
+        cost: u32,
+    ) -> Option<RateDecision> {
+        match self.route_limit(policy) {
+            RouteLimit::Exempt => None,
+            RouteLimit::General => Some(self.decide(key, cost).await),
+            RouteLimit::Own {
+                capacity,
+                refill_per_minute,
+            } => {
+                let cfg = RateLimitConfig {
+                    capacity,
+                    refill_per_second: refill_per_minute as f64 / 60.0,
+                };
+                Some(
+                    self.take(&format!("{}:{key}", policy.name), cost, cfg)
+                        .await,
+                )
+            }
+        }
+    }
+    async fn take(&self, key: &str, cost: u32, cfg: RateLimitConfig) -> RateDecision {
+        let cost = cost as f64;
         let mut map = self.buckets.write().await;
-        let entry = map.entry(ip.to_string()).or_insert(RateBucket {
-            tokens: self.cfg.capacity as f64,
+        let entry = map.entry(key.to_string()).or_insert(RateBucket {
+            tokens: cfg.capacity as f64,
             last: Instant::now(),
         });
         let now = Instant::now();
         let elapsed = now.duration_since(entry.last).as_secs_f64();
         if elapsed > 0.0 {
-            let refill = elapsed * self.cfg.refill_per_second as f64;
-            entry.tokens = (entry.tokens + refill).min(self.cfg.capacity as f64);
+            let refill = elapsed * cfg.refill_per_second;
+            entry.tokens = (entry.tokens + refill).min(cfg.capacity as f64);
             entry.last = now;
         }
-        if entry.tokens >= 1.0 {
-            entry.tokens -= 1.0;
-            true
-        } else {
-            false
+        let allowed = entry.tokens >= cost;
+        if allowed {
+            entry.tokens -= cost;
+        }
+        let capacity = cfg.capacity as f64;
+        RateDecision {
+            allowed,
+            limit: cfg.capacity,
+            remaining: entry.tokens.max(0.0) as u32,
+            reset_secs: refill_secs(capacity - entry.tokens, cfg.refill_per_second),
+            retry_after_secs: if allowed {
+                0
+            } else {
+                refill_secs(cost - entry.tokens, cfg.refill_per_second)
+            },
         }
     }
     pub async fn prune_idle(&self, max_idle: Duration) {
@@ -69,15 +216,31 @@ impl RateLimiterInner {
 #[derive(Clone)]
 pub struct RateLimitLayer {
     limiter: RateLimiterInner,
+    api_tokens: Option<Arc<ApiTokens>>,
+    networks: Option<Arc<NetworkLists>>,
 }
 impl RateLimitLayer {
     pub fn new(capacity: u32, refill_per_second: u32) -> Self {
+        Self::from_inner(RateLimiterInner::new(capacity, refill_per_second))
+    }
+    pub fn from_inner(limiter: RateLimiterInner) -> Self {
         Self {
-            limiter: RateLimiterInner::new(capacity, refill_per_second),
+            limiter,
+            api_tokens: None,
+            networks: None,
         }
     }
-    pub fn from_inner(limiter: RateLimiterInner) -> Self {
-        Self { limiter }
+    /// Give upload requests carrying a valid API token a bucket per token
+    /// instead of sharing the client IP's.
+    pub fn with_api_tokens(mut self, tokens: Arc<ApiTokens>) -> Self {
+        self.api_tokens = Some(tokens);
+        self
+    }
+    /// Charge requests from Tor and hosting networks the configured
+    /// non-residential cost instead of one token.
+    pub fn with_network_lists(mut self, networks: Arc<NetworkLists>) -> Self {
+        self.networks = Some(networks);
+        self
     }
     pub fn handle(&self) -> RateLimiterInner {
         self.limiter.clone()
@@ -89,6 +252,8 @@ impl<S> Layer<S> for RateLimitLayer {
         RateLimitService {
             inner,
             limiter: self.limiter.clone(),
+            api_tokens: self.api_tokens.clone(),
+            networks: self.networks.clone(),
         }
     }
 }
@@ -97,6 +262,8 @@ impl<S> Layer<S> for RateLimitLayer {
 pub struct RateLimitService<S> {
     inner: S,
     limiter: RateLimiterInner,
+    api_tokens: Option<Arc<ApiTokens>>,
+    networks: Option<Arc<NetworkLists>>,
 }
 impl<S> Service<Request<Body>> for RateLimitService<S>
 where
@@ -114,34 +281,260 @@ where
         let limiter = self.limiter.clone();
         let mut inner = self.inner.clone();
         let path = req.uri().path().to_string();
-        // Bypass rate limiting for core static assets (css/js) so ban page renders correctly
-        if path.starts_with("/css/") || path.starts_with("/js/") {
+        let policy = route_policy(&path);
+        if limiter.route_limit(policy) == RouteLimit::Exempt {
             return Box::pin(async move { inner.call(req).await });
         }
         let edge_ip = req
             .extensions()
             .get::<ConnectInfo<ClientAddr>>()
             .map(|c| c.0.ip());
-        let header_ip = {
-            let h = req.headers();
-            extract_client_ip(h, edge_ip)
+        let token_id = self
+            .api_tokens
+            .as_ref()
+            .filter(|_| is_upload_path(&path))
+            .and_then(|tokens| tokens.authenticate(bearer_token(req.headers())?))
+            .map(|token| token.id);
+        let (bucket, cost) = match token_id {
+            Some(id) => (format!("token:{id}"), 1),
+            None => {
+                let ip = extract_client_ip(req.headers(), edge_ip);
+                let cost = match (&self.networks, ip.parse()) {
+                    (Some(networks), Ok(addr)) => networks.rate_cost(addr),
+                    _ => 1,
+                };
+                (ip, cost)
+            }
         };
         Box::pin(async move {
-            if !limiter.check(&header_ip).await {
-                return Ok(json_error(
-                    StatusCode::TOO_MANY_REQUESTS,
-                    "rate_limited",
-                    "slow down",
-                ));
+            let Some(decision) = limiter.check_route(policy, &bucket, cost).await else {
+                return inner.call(req).await;
+            };
+            if !decision.allowed {
+                let mut resp =
+                    json_error(StatusCode::TOO_MANY_REQUESTS, "rate_limited", "slow down");
+                decision.apply_headers(resp.headers_mut());
+                return Ok(resp);
             }
-            inner.call(req).await
+            let mut resp = inner.call(req).await?;
+            if decision.is_near_limit() {
+                decision.apply_headers(resp.headers_mut());
+            }
+            Ok(resp)
+        })
+    }
+}
+
+/// Requests a client may burst before being limited, unless
+/// `JUICEBOX_RATE_LIMIT_BURST` says otherwise.
+pub const RATE_LIMIT_BURST: u32 = 180;
+/// Tokens refilled per second for each client, unless
+/// `JUICEBOX_RATE_LIMIT_REFILL_PER_SEC` says otherwise.
+pub const RATE_LIMIT_REFILL_PER_SEC: u32 = 3;
+
+/// Seconds a client's bucket may sit idle before the cleanup sweep drops
+/// it, unless `JUICEBOX_RATE_LIMIT_IDLE_SECS` says otherwise.
+pub const RATE_LIMIT_IDLE_SECS: u64 = 1800;
+
+/// Burst allowed for `/api/v1/files/{name}/status`, counted apart from the
+/// general limit so link checkers don't eat into a client's upload budget.
+pub const LINK_STATUS_RATE_LIMIT_BURST: u32 = 60;
+/// Link status checks refilled per second for each client.
+pub const LINK_STATUS_RATE_LIMIT_REFILL_PER_SEC: u32 = 1;
+
+/// Burst allowed for `/visitor-debug`, which hashes addresses and walks the
+/// owner index on every call.
+pub const VISITOR_DEBUG_RATE_LIMIT_BURST: u32 = 10;
+/// Visitor debug requests refilled per second for each client.
+pub const VISITOR_DEBUG_RATE_LIMIT_REFILL_PER_SEC: u32 = 1;
+
+/// How the general layer limits one family of routes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RouteLimit {
+    /// Not limited by the layer at all.
+    Exempt,
+    /// Drawn from the client's general bucket.
+    General,
+    /// A bucket per client kept apart from the general one.
+    Own {
+        capacity: u32,
+        refill_per_minute: u32,
+    },
+}
+
+/// Written as `exempt`, `general` or `capacity/refill_per_minute`, the form
+/// `JUICEBOX_RATE_LIMIT_ROUTES` takes.
+impl fmt::Display for RouteLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteLimit::Exempt => f.write_str("exempt"),
+            RouteLimit::General => f.write_str("general"),
+            RouteLimit::Own {
+                capacity,
+                refill_per_minute,
+            } => write!(f, "{capacity}/{refill_per_minute}"),
+        }
+    }
+}
+
+impl std::str::FromStr for RouteLimit {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let raw = raw.trim();
+        match raw.to_ascii_lowercase().as_str() {
+            "exempt" => return Ok(RouteLimit::Exempt),
+            "general" => return Ok(RouteLimit::General),
+            _ => {}
+        }
+        let (capacity, refill) = raw
+            .split_once('/')
+            .ok_or_else(|| format!("expected exempt, general or capacity/refill, got {raw:?}"))?;
+        let number = |part: &str| {
+            part.trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or_else(|| format!("{part:?} is not a positive number"))
+        };
+        Ok(RouteLimit::Own {
+            capacity: number(capacity)?,
+            refill_per_minute: number(refill)?,
         })
     }
 }
 
+/// A route family and the limit the layer applies to it.
+#[derive(Debug)]
+pub struct RoutePolicy {
+    /// Names the policy in logs and prefixes its bucket keys.
+    pub name: &'static str,
+    matches: fn(&str) -> bool,
+    pub limit: RouteLimit,
+}
+
+impl RoutePolicy {
+    pub fn matches(&self, path: &str) -> bool {
+        (self.matches)(path)
+    }
+}
+
+/// Every per-route limit, checked in order; the first match wins and
+/// anything unmatched falls through to [`DEFAULT_ROUTE_POLICY`].
+pub const ROUTE_POLICIES: &[RoutePolicy] = &[
+    // Core static assets stay reachable so the ban page renders correctly.
+    RoutePolicy {
+        name: "static",
+        matches: |path| path.starts_with("/css/") || path.starts_with("/js/"),
+        limit: RouteLimit::Exempt,
+    },
+    // Link status checks have their own limiter in the handler.
+    RoutePolicy {
+        name: "link_status",
+        matches: is_link_status_path,
+        limit: RouteLimit::Exempt,
+    },
+    RoutePolicy {
+        name: "report",
+        matches: |path| path == "/report",
+        limit: RouteLimit::Own {
+            capacity: 5,
+            refill_per_minute: 6,
+        },
+    },
+    RoutePolicy {
+        name: "auth",
+        matches: is_auth_path,
+        limit: RouteLimit::Own {
+            capacity: 10,
+            refill_per_minute: 10,
+        },
+    },
+    // Downloads are cheap and pages embed many of them.
+    RoutePolicy {
+        name: "files",
+        matches: |path| path.starts_with("/f/") || path.starts_with("/d/"),
+        limit: RouteLimit::Own {
+            capacity: 600,
+            refill_per_minute: 1200,
+        },
+    },
+];
+
+/// The policy for every route [`ROUTE_POLICIES`] does not name.
+pub const DEFAULT_ROUTE_POLICY: RoutePolicy = RoutePolicy {
+    name: "general",
+    matches: |_| true,
+    limit: RouteLimit::General,
+};
+
+/// The policy named `name`, including [`DEFAULT_ROUTE_POLICY`].
+pub fn named_route_policy(name: &str) -> Option<&'static RoutePolicy> {
+    ROUTE_POLICIES
+        .iter()
+        .chain(std::iter::once(&DEFAULT_ROUTE_POLICY))
+        .find(|policy| policy.name == name)
+}
+
+/// Every policy's limit once `settings` overrides are applied, in table
+/// order.
+pub fn effective_route_limits(settings: &HotSettings) -> Vec<(&'static str, RouteLimit)> {
+    ROUTE_POLICIES
+        .iter()
+        .map(|policy| {
+            let limit = settings
+                .rate_limit_routes
+                .get(policy.name)
+                .copied()
+                .unwrap_or(policy.limit);
+            (policy.name, limit)
+        })
+        .collect()
+}
+
+/// The policy that governs requests to `path`.
+pub fn route_policy(path: &str) -> &'static RoutePolicy {
+    ROUTE_POLICIES
+        .iter()
+        .find(|policy| policy.matches(path))
+        .unwrap_or(&DEFAULT_ROUTE_POLICY)
+}
+
+/// Admin and account sign-in, where guessing is the threat.
+fn is_auth_path(path: &str) -> bool {
+    matches!(
+        path,
+        "/auth" | "/auth/json" | "/api/v1/accounts/login" | "/api/v1/accounts/register"
+    )
+}
+
+fn is_link_status_path(path: &str) -> bool {
+    path.strip_prefix("/api/v1/files/")
+        .and_then(|rest| rest.strip_suffix("/status"))
+        .is_some_and(|name| !name.is_empty() && !name.contains('/'))
+}
+
+/// Routes that accept API tokens: multipart, chunked and tus uploads.
+fn is_upload_path(path: &str) -> bool {
+    path == "/upload" || path.starts_with("/chunk/") || path == "/tus" || path.starts_with("/tus/")
+}
+
+pub fn build_link_status_limiter() -> RateLimiterInner {
+    RateLimiterInner::new(
+        LINK_STATUS_RATE_LIMIT_BURST,
+        LINK_STATUS_RATE_LIMIT_REFILL_PER_SEC,
+    )
+}
+
+pub fn build_visitor_debug_limiter() -> RateLimiterInner {
+    RateLimiterInner::new(
+        VISITOR_DEBUG_RATE_LIMIT_BURST,
+        VISITOR_DEBUG_RATE_LIMIT_REFILL_PER_SEC,
+    )
+}
+
 pub fn build_rate_limiter() -> (RateLimitLayer, RateLimiterInner) {
-    // Less strict: allow 180 requests burst, refill 3 tokens/sec
-    let limiter = RateLimiterInner::new(180, 3);
+    let limiter = RateLimiterInner::hot();
     (RateLimitLayer::from_inner(limiter.clone()), limiter)
 }
 
@@ -164,4 +557,95 @@ mod tests {
         let buckets = limiter.buckets.read().await;
         assert!(!buckets.contains_key("198.51.100.1"));
     }
+
+    #[test]
+    fn link_status_paths_skip_general_limit() {
+        assert!(is_link_status_path("/api/v1/files/abc.png/status"));
+        assert!(!is_link_status_path("/api/v1/files//status"));
+        assert!(!is_link_status_path("/api/v1/files/a/b/status"));
+        assert!(!is_link_status_path("/api/v1/files/delete"));
+    }
+
+    #[test]
+    fn route_policies_pick_the_first_match() {
+        assert_eq!(route_policy("/css/app.css").name, "static");
+        assert_eq!(
+            route_policy("/api/v1/files/a.png/status").name,
+            "link_status"
+        );
+        assert_eq!(route_policy("/report").name, "report");
+        assert_eq!(route_policy("/auth/json").name, "auth");
+        assert_eq!(route_policy("/f/abc.png").name, "files");
+        assert_eq!(route_policy("/upload").name, "general");
+        assert_eq!(route_policy("/reports").name, "general");
+    }
+
+    #[tokio::test]
+    async fn route_buckets_are_kept_apart_from_the_general_bucket() {
+        let limiter = RateLimiterInner::new(1, 0);
+        let report = route_policy("/report");
+        assert!(limiter.check("198.51.100.2").await);
+        assert!(!limiter.check("198.51.100.2").await);
+        for _ in 0..5 {
+            let decision = limiter.check_route(report, "198.51.100.2", 1).await;
+            assert!(decision.unwrap().allowed);
+        }
+        let decision = limiter.check_route(report, "198.51.100.2", 1).await;
+        assert!(!decision.unwrap().allowed);
+        let exempt = route_policy("/css/app.css");
+        assert_eq!(limiter.check_route(exempt, "198.51.100.2", 1).await, None);
+    }
+
+    #[tokio::test]
+    async fn decisions_report_remaining_tokens_and_refill_times() {
+        let limiter = RateLimiterInner::new(10, 2);
+        let first = limiter.decide("198.51.100.3", 1).await;
+        assert!(first.allowed);
+        assert_eq!((first.limit, first.remaining), (10, 9));
+        assert_eq!(first.reset_secs, 1);
+        assert!(!first.is_near_limit());
+
+        let last = limiter.decide("198.51.100.3", 8).await;
+        assert!(last.allowed && last.is_near_limit());
+        assert_eq!(last.remaining, 1);
+
+        let refused = limiter.decide("198.51.100.3", 4).await;
+        assert!(!refused.allowed);
+        assert_eq!(refused.retry_after_secs, 2);
+        assert_eq!(refused.reset_secs, 5);
+        let mut headers = HeaderMap::new();
+        refused.apply_headers(&mut headers);
+        assert_eq!(headers[RETRY_AFTER], "2");
+        assert_eq!(headers[RATE_LIMIT_REMAINING], "1");
+    }
+
+    #[tokio::test]
+    async fn buckets_that_never_refill_reset_after_the_configured_idle_window() {
+        reload::update(|settings| settings.rate_limit_idle_secs = 600);
+        let limiter = RateLimiterInner::new(1, 0);
+        assert!(limiter.decide("198.51.100.4", 1).await.allowed);
+        let refused = limiter.decide("198.51.100.4", 1).await;
+        assert!(!refused.allowed);
+        assert_eq!((refused.reset_secs, refused.retry_after_secs), (600, 600));
+    }
+
+    #[test]
+    fn route_limits_round_trip_through_their_env_form() {
+        for raw in ["exempt", "general", "5/6"] {
+            assert_eq!(raw.parse::<RouteLimit>().unwrap().to_string(), raw);
+        }
+        assert_eq!(" General ".parse(), Ok(RouteLimit::General));
+        assert!("5".parse::<RouteLimit>().is_err());
+        assert!("0/6".parse::<RouteLimit>().is_err());
+        assert!("5/x".parse::<RouteLimit>().is_err());
+    }
+
+    #[test]
+    fn api_tokens_only_apply_to_upload_paths() {
+        assert!(is_upload_path("/upload"));
+        assert!(is_upload_path("/chunk/init"));
+        assert!(is_upload_path("/tus/abc"));
+        assert!(!is_upload_path("/uploads"));
+        assert!(!is_upload_path("/f/abc.png"));
+    }
 }
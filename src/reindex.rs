@@ -0,0 +1,298 @@
+//! One-shot, admin-triggered backfill for metadata written before hashes and
+//! sizes were recorded. Entries migrated from the legacy owner map carry an
+//! empty hash, a zero size and the migration time as `created`; the job reads
+//! each body back, hashes it at a capped rate and takes the blob's
+//! modification time as the creation time, so dedup, quotas and storage
+//! accounting see old files like new ones.
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use crate::state::AppState;
+
+/// Default read rate while hashing, so a reindex does not starve downloads.
+pub const DEFAULT_REINDEX_BYTES_PER_SEC: u64 = 32 * 1024 * 1024;
+/// Entries updated between two owner metadata writes.
+const PERSIST_EVERY: usize = 100;
+const READ_BUF: usize = 64 * 1024;
+
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReindexState {
+    #[default]
+    Idle,
+    Running,
+    Finished,
+    /// Stopped early because the server started draining.
+    Interrupted,
+}
+
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReindexProgress {
+    pub state: ReindexState,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+    /// Entries needing work when the job started.
+    pub total: usize,
+    pub scanned: usize,
+    pub hashed: usize,
+    pub sized: usize,
+    pub dated: usize,
+    /// Entries whose body no longer exists.
+    pub missing: usize,
+    pub failed: usize,
+    pub bytes_hashed: u64,
+}
+
+pub struct Reindex {
+    running: AtomicBool,
+    progress: Mutex<ReindexProgress>,
+    bytes_per_sec: u64,
+}
+
+impl Default for Reindex {
+    fn default() -> Self {
+        Self::new(DEFAULT_REINDEX_BYTES_PER_SEC)
+    }
+}
+
+impl Reindex {
+    /// `bytes_per_sec` of 0 hashes without throttling.
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            running: AtomicBool::new(false),
+            progress: Mutex::new(ReindexProgress::default()),
+            bytes_per_sec,
+        }
+    }
+
+    /// Read `JUICEBOX_REINDEX_BYTES_PER_SEC`.
+    pub fn from_env() -> Self {
+        let rate = std::env::var("JUICEBOX_REINDEX_BYTES_PER_SEC")
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .unwrap_or(DEFAULT_REINDEX_BYTES_PER_SEC);
+        Self::new(rate)
+    }
+
+    pub fn bytes_per_sec(&self) -> u64 {
+        self.bytes_per_sec
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    /// Claim the job. Returns `false` if a reindex is already running.
+    pub fn begin(&self) -> bool {
+        !self.running.swap(true, Ordering::SeqCst)
+    }
+
+    pub fn progress(&self) -> ReindexProgress {
+        self.progress.lock().unwrap().clone()
+    }
+
+    fn update(&self, f: impl FnOnce(&mut ReindexProgress)) {
+        f(&mut self.progress.lock().unwrap());
+    }
+}
+
+/// Whether an entry is missing anything the reindex fills in.
+fn needs_reindex(hash: &str, size: u64, created: u64) -> bool {
+    hash.is_empty() || size == 0 || created == 0
+}
+
+/// Backfill every entry [`needs_reindex`] picks, then record the outcome.
+/// Call after [`Reindex::begin`].
+pub async fn run(state: AppState) {
+    let job = state.reindex.clone();
+    let pending: Vec<String> = state
+        .owners
+        .iter()
+        .filter(|e| needs_reindex(&e.value().hash, e.value().size, e.value().created))
+        .map(|e| e.key().clone())
+        .collect();
+    job.update(|p| {
+        *p = ReindexProgress {
+            state: ReindexState::Running,
+            started_at: Some(state.now_secs()),
+            total: pending.len(),
+            ..Default::default()
+        }
+    });
+    info!(total = pending.len(), "reindex started");
+
+    let throttle_start = Instant::now();
+    let mut bytes_read = 0u64;
+    let mut unsaved = 0;
+    let mut interrupted = false;
+    for name in pending {
+        if state.drain.is_draining() {
+            interrupted = true;
+            break;
+        }
+        let Some(meta) = state.owners.get(&name).map(|m| m.clone()) else {
+            job.update(|p| p.scanned += 1);
+            continue;
+        };
+        let size = match state.file_store.size(&name).await {
+            Ok(Some(size)) => size,
+            Ok(None) => {
+                job.update(|p| {
+                    p.scanned += 1;
+                    p.missing += 1;
+                });
+                continue;
+            }
+            Err(err) => {
+                warn!(?err, name, "reindex: failed to stat body");
+                job.update(|p| {
+                    p.scanned += 1;
+                    p.failed += 1;
+                });
+                continue;
+            }
+        };
+
+        let mut hash = None;
+        if meta.hash.is_empty() {
+            match hash_body(
+                &state,
+                &name,
+                job.bytes_per_sec,
+                throttle_start,
+                &mut bytes_read,
+            )
+            .await
+            {
+                Ok(Some((digest, read))) => {
+                    job.update(|p| p.bytes_hashed += read);
+                    hash = Some(digest);
+                }
+                Ok(None) => {
+                    job.update(|p| {
+                        p.scanned += 1;
+                        p.missing += 1;
+                    });
+                    continue;
+                }
+                Err(err) => {
+                    warn!(?err, name, "reindex: failed to hash body");
+                    job.update(|p| {
+                        p.scanned += 1;
+                        p.failed += 1;
+                    });
+                    continue;
+                }
+            }
+        }
+        // Legacy entries were stamped with the migration time; the blob's
+        // modification time is closer to when it was really uploaded.
+        let legacy = meta.hash.is_empty() && meta.original.is_empty();
+        let created = if legacy || meta.created == 0 {
+            match state.file_store.modified(&name).await {
+                Ok(Some(mtime)) if meta.created == 0 || mtime < meta.created => Some(mtime),
+                _ => None,
+            }
+        } else {
+            None
+        };
+
+        let (mut hashed, mut sized, mut dated) = (false, false, false);
+        if let Some(mut entry) = state.owners.get_mut(&name) {
+            if let Some(digest) = hash
+                && entry.hash.is_empty()
+            {
+                entry.hash = digest;
+                hashed = true;
+            }
+            if entry.size != size {
+                entry.size = size;
+                sized = true;
+            }
+            if let Some(created) = created {
+                entry.created = created;
+                dated = true;
+            }
+        }
+        job.update(|p| {
+            p.scanned += 1;
+            p.hashed += hashed as usize;
+            p.sized += sized as usize;
+            p.dated += dated as usize;
+        });
+        if hashed || sized || dated {
+            unsaved += 1;
+            if unsaved >= PERSIST_EVERY {
+                state.persist_owners().await;
+                unsaved = 0;
+            }
+        }
+    }
+    if unsaved > 0 {
+        state.persist_owners().await;
+    }
+    state.owners_index.invalidate();
+
+    let finished_at = state.now_secs();
+    job.update(|p| {
+        p.state = if interrupted {
+            ReindexState::Interrupted
+        } else {
+            ReindexState::Finished
+        };
+        p.finished_at = Some(finished_at);
+    });
+    let progress = job.progress();
+    info!(
+        scanned = progress.scanned,
+        hashed = progress.hashed,
+        sized = progress.sized,
+        dated = progress.dated,
+        missing = progress.missing,
+        failed = progress.failed,
+        interrupted,
+        "reindex complete"
+    );
+    job.running.store(false, Ordering::SeqCst);
+}
+
+/// SHA-256 of the stored body, read no faster than `bytes_per_sec` measured
+/// across the whole job. `None` when the body has gone.
+async fn hash_body(
+    state: &AppState,
+    name: &str,
+    bytes_per_sec: u64,
+    start: Instant,
+    total_read: &mut u64,
+) -> anyhow::Result<Option<(String, u64)>> {
+    let Some(mut reader) = state.file_store.open(name).await? else {
+        return Ok(None);
+    };
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; READ_BUF];
+    let mut read = 0u64;
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        read += n as u64;
+        *total_read += n as u64;
+        if bytes_per_sec > 0 {
+            let due = Duration::from_secs_f64(*total_read as f64 / bytes_per_sec as f64);
+            let elapsed = start.elapsed();
+            if due > elapsed {
+                tokio::time::sleep(due - elapsed).await;
+            }
+        }
+    }
+    Ok(Some((format!("{:x}", hasher.finalize()), read)))
+}
@@ -0,0 +1,281 @@
+use axum::extract::{DefaultBodyLimit, Request};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use once_cell::sync::Lazy;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::watch;
+use tower::{Layer, ServiceExt};
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::rate_limit::{
+    RATE_LIMIT_BURST, RATE_LIMIT_IDLE_SECS, RATE_LIMIT_REFILL_PER_SEC, RouteLimit,
+    effective_route_limits, named_route_policy,
+};
+use crate::util::{FORBIDDEN_EXTENSIONS, parse_size_bytes};
+
+/// Upload size limit when `MAX_FILE_SIZE` is unset.
+pub const DEFAULT_MAX_FILE_BYTES: u64 = 500 * 1024 * 1024;
+
+/// Env vars behind [`HotSettings`]; the rest of the configuration needs a
+/// restart.
+pub const HOT_ENV_VARS: [&str; 8] = [
+    "JUICEBOX_FORBIDDEN_EXTENSIONS",
+    "TRUST_PROXY_HEADERS",
+    "TRUSTED_PROXY_CIDRS",
+    "MAX_FILE_SIZE",
+    "JUICEBOX_RATE_LIMIT_BURST",
+    "JUICEBOX_RATE_LIMIT_REFILL_PER_SEC",
+    "JUICEBOX_RATE_LIMIT_IDLE_SECS",
+    "JUICEBOX_RATE_LIMIT_ROUTES",
+];
+
+/// Settings that can change while the server runs. Translations need no
+/// entry: they are read from disk for every page.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HotSettings {
+    /// Lowercase extensions refused on upload.
+    pub forbidden_extensions: Vec<String>,
+    /// Honour forwarded client IP headers from trusted proxies.
+    pub allow_proxy_headers: bool,
+    /// CIDRs whose forwarded headers are trusted; empty trusts any peer.
+    pub trusted_proxies: Vec<String>,
+    pub max_file_bytes: u64,
+    pub rate_limit_burst: u32,
+    pub rate_limit_refill_per_sec: u32,
+    /// How long an untouched client bucket is kept.
+    pub rate_limit_idle_secs: u64,
+    /// Per-route limits replacing the defaults in
+    /// [`ROUTE_POLICIES`](crate::rate_limit::ROUTE_POLICIES), by policy name.
+    pub rate_limit_routes: BTreeMap<String, RouteLimit>,
+}
+
+impl Default for HotSettings {
+    fn default() -> Self {
+        Self {
+            forbidden_extensions: FORBIDDEN_EXTENSIONS.iter().map(|e| e.to_string()).collect(),
+            allow_proxy_headers: false,
+            trusted_proxies: Vec::new(),
+            max_file_bytes: DEFAULT_MAX_FILE_BYTES,
+            rate_limit_burst: RATE_LIMIT_BURST,
+            rate_limit_refill_per_sec: RATE_LIMIT_REFILL_PER_SEC,
+            rate_limit_idle_secs: RATE_LIMIT_IDLE_SECS,
+            rate_limit_routes: BTreeMap::new(),
+        }
+    }
+}
+
+fn list(raw: &str) -> Vec<String> {
+    raw.split(',')
+        .map(|item| item.trim().trim_start_matches('.').to_ascii_lowercase())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Parse `name=limit` pairs, skipping (with a warning) unknown policies and
+/// limits that don't parse.
+fn route_limits(raw: &str) -> BTreeMap<String, RouteLimit> {
+    let mut limits = BTreeMap::new();
+    for item in raw
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+    {
+        let Some((name, limit)) = item.split_once('=') else {
+            warn!(item, "ignoring rate limit route without `=`");
+            continue;
+        };
+        let name = name.trim();
+        let Some(policy) = named_route_policy(name) else {
+            warn!(policy = name, "ignoring unknown rate limit route");
+            continue;
+        };
+        match limit.parse() {
+            Ok(limit) => {
+                limits.insert(policy.name.to_string(), limit);
+            }
+            Err(err) => warn!(policy = name, %err, "ignoring bad rate limit route"),
+        }
+    }
+    limits
+}
+
+impl HotSettings {
+    /// Build from env-style values, where `lookup` returns the raw value of
+    /// one of [`HOT_ENV_VARS`]. Unparseable values keep the default.
+    pub fn resolve(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let defaults = Self::default();
+        let positive = |name: &str, default: u32| {
+            lookup(name)
+                .and_then(|v| v.trim().parse::<u32>().ok())
+                .filter(|v| *v > 0)
+                .unwrap_or(default)
+        };
+        Self {
+            forbidden_extensions: lookup("JUICEBOX_FORBIDDEN_EXTENSIONS")
+                .map(|raw| list(&raw))
+                .unwrap_or(defaults.forbidden_extensions),
+            allow_proxy_headers: lookup("TRUST_PROXY_HEADERS").is_some_and(|v| {
+                matches!(
+                    v.trim().to_ascii_lowercase().as_str(),
+                    "1" | "true" | "yes" | "on"
+                )
+            }),
+            trusted_proxies: lookup("TRUSTED_PROXY_CIDRS")
+                .map(|raw| {
+                    raw.split(',')
+                        .map(str::trim)
+                        .filter(|cidr| !cidr.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            max_file_bytes: lookup("MAX_FILE_SIZE")
+                .and_then(|v| parse_size_bytes(&v))
+                .unwrap_or(defaults.max_file_bytes),
+            rate_limit_burst: positive("JUICEBOX_RATE_LIMIT_BURST", defaults.rate_limit_burst),
+            rate_limit_refill_per_sec: positive(
+                "JUICEBOX_RATE_LIMIT_REFILL_PER_SEC",
+                defaults.rate_limit_refill_per_sec,
+            ),
+            rate_limit_idle_secs: lookup("JUICEBOX_RATE_LIMIT_IDLE_SECS")
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .filter(|v| *v > 0)
+                .unwrap_or(defaults.rate_limit_idle_secs),
+            rate_limit_routes: lookup("JUICEBOX_RATE_LIMIT_ROUTES")
+                .map(|raw| route_limits(&raw))
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::resolve(|name| std::env::var(name).ok())
+    }
+
+    pub fn is_forbidden_extension(&self, ext: &str) -> bool {
+        self.forbidden_extensions
+            .iter()
+            .any(|forbidden| forbidden.eq_ignore_ascii_case(ext))
+    }
+}
+
+static SETTINGS: Lazy<watch::Sender<Arc<HotSettings>>> =
+    Lazy::new(|| watch::Sender::new(Arc::new(HotSettings::from_env())));
+
+/// The settings in force right now.
+pub fn current() -> Arc<HotSettings> {
+    SETTINGS.borrow().clone()
+}
+
+/// A receiver that sees every published change.
+pub fn subscribe() -> watch::Receiver<Arc<HotSettings>> {
+    SETTINGS.subscribe()
+}
+
+/// Replace the settings everywhere. Returns whether anything changed.
+pub fn publish(settings: HotSettings) -> bool {
+    SETTINGS.send_if_modified(|current| {
+        if **current == settings {
+            return false;
+        }
+        *current = Arc::new(settings);
+        true
+    })
+}
+
+/// Change the settings in place, e.g. from tests.
+pub fn update(f: impl FnOnce(&mut HotSettings)) {
+    let mut settings = (*current()).clone();
+    f(&mut settings);
+    publish(settings);
+}
+
+/// Re-reads the config file on demand. Hot vars that were set in the real
+/// environment (or `.env`) at startup keep winning over the file, since a
+/// running process cannot see its environment change.
+pub struct Reloader {
+    pinned: HashSet<&'static str>,
+}
+
+impl Reloader {
+    /// Call before [`Config::apply_to_env`] so file values are not mistaken
+    /// for real env vars.
+    pub fn from_env() -> Self {
+        Self {
+            pinned: HOT_ENV_VARS
+                .into_iter()
+                .filter(|name| std::env::var_os(name).is_some())
+                .collect(),
+        }
+    }
+
+    /// Settings from `config` with pinned env vars applied on top.
+    pub fn settings_for(&self, config: &Config) -> HotSettings {
+        let file: HashMap<&str, String> = config.env_vars().into_iter().collect();
+        HotSettings::resolve(|name| {
+            if self.pinned.contains(name) {
+                std::env::var(name).ok()
+            } else {
+                file.get(name).cloned()
+            }
+        })
+    }
+
+    /// Load the config file again and publish the result. A file that fails
+    /// to load leaves the current settings alone.
+    pub fn reload(&self) {
+        match Config::load() {
+            Ok(config) => {
+                let settings = self.settings_for(&config);
+                if publish(settings.clone()) {
+                    info!(
+                        max_file_bytes = settings.max_file_bytes,
+                        forbidden_extensions = settings.forbidden_extensions.len(),
+                        allow_proxy_headers = settings.allow_proxy_headers,
+                        trusted_proxies = settings.trusted_proxies.len(),
+                        rate_limit_burst = settings.rate_limit_burst,
+                        rate_limit_refill_per_sec = settings.rate_limit_refill_per_sec,
+                        rate_limit_idle_secs = settings.rate_limit_idle_secs,
+                        rate_limit_routes = ?effective_route_limits(&settings),
+                        "configuration reloaded"
+                    );
+                } else {
+                    info!("configuration reloaded; nothing changed");
+                }
+            }
+            Err(err) => warn!(error = %format!("{err:#}"), "configuration reload failed"),
+        }
+    }
+
+    /// Reload on every SIGHUP until the process exits.
+    #[cfg(unix)]
+    pub async fn run_on_sighup(self) {
+        use tokio::signal::unix::{SignalKind, signal};
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(stream) => stream,
+            Err(err) => {
+                warn!(
+                    ?err,
+                    "cannot listen for SIGHUP; configuration reload disabled"
+                );
+                return;
+            }
+        };
+        while hangups.recv().await.is_some() {
+            info!("SIGHUP received; reloading configuration");
+            self.reload();
+        }
+    }
+}
+
+/// Applies the current [`HotSettings::max_file_bytes`] as the request body
+/// limit, in place of a fixed `DefaultBodyLimit` layer.
+pub async fn body_limit(req: Request, next: Next) -> Response {
+    let limit = current().max_file_bytes as usize;
+    match DefaultBodyLimit::max(limit).layer(next).oneshot(req).await {
+        Ok(resp) => resp,
+        Err(never) => match never {},
+    }
+    .into_response()
+}
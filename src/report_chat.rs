@@ -0,0 +1,265 @@
+//! Report notifications for chat: posts a summary of each report, with links
+//! into the admin pages, to a Discord or Slack incoming webhook. Fed from the
+//! same channel as the Mailgun worker, so either or both can be enabled.
+
+use serde::Serialize;
+use serde_json::{Value, json};
+use tracing::warn;
+
+use crate::handlers::ReportRecordEmail;
+use crate::util::PROD_HOST;
+
+/// Longest `details` text put in a message; Discord rejects embed fields
+/// over 1024 characters.
+pub const MAX_DETAILS_CHARS: usize = 1000;
+const DISCORD_EMBED_COLOR: u32 = 0xff9800;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChatKind {
+    Discord,
+    Slack,
+}
+
+impl ChatKind {
+    fn parse(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "discord" => Some(ChatKind::Discord),
+            "slack" => Some(ChatKind::Slack),
+            _ => None,
+        }
+    }
+
+    /// Discord webhooks live under `discord.com/api/webhooks`; anything else
+    /// is taken to speak Slack's format, which most other chat tools accept.
+    fn infer(url: &str) -> Self {
+        let host = url
+            .split_once("://")
+            .map_or(url, |(_, rest)| rest)
+            .split(['/', ':'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if host == "discord.com"
+            || host == "discordapp.com"
+            || host.ends_with(".discord.com")
+            || host.ends_with(".discordapp.com")
+        {
+            ChatKind::Discord
+        } else {
+            ChatKind::Slack
+        }
+    }
+}
+
+/// Admin links for one report, on the canonical host.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ReportLinks {
+    pub file: String,
+    pub admin_files: String,
+    pub admin_reports: String,
+    /// Only when the owner hash was not redacted, since it is the ban key.
+    pub ban: Option<String>,
+}
+
+impl ReportLinks {
+    pub fn for_report(ev: &ReportRecordEmail) -> Self {
+        let canonical = PROD_HOST.as_str();
+        Self {
+            file: format!("https://{canonical}/f/{}", ev.file),
+            admin_files: format!("https://{canonical}/admin/files"),
+            admin_reports: format!("https://{canonical}/admin/reports"),
+            ban: (!ev.owner_hash.is_empty() && !ev.identifiers_redacted)
+                .then(|| format!("https://{canonical}/admin/ban?ip={}", ev.owner_hash)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ReportChat {
+    pub kind: ChatKind,
+    pub url: String,
+}
+
+impl ReportChat {
+    /// Read `REPORT_CHAT_WEBHOOK_URL` and, optionally, `REPORT_CHAT_KIND`
+    /// (`discord` or `slack`; guessed from the URL when unset).
+    pub fn from_env() -> Option<Self> {
+        let url = std::env::var("REPORT_CHAT_WEBHOOK_URL").ok()?;
+        let kind = std::env::var("REPORT_CHAT_KIND").ok();
+        Self::new(&url, kind.as_deref())
+    }
+
+    /// `None` unless `url` is http(s).
+    pub fn new(url: &str, kind: Option<&str>) -> Option<Self> {
+        let url = url.trim();
+        if !(url.starts_with("https://") || url.starts_with("http://")) {
+            if !url.is_empty() {
+                warn!("ignoring report chat webhook that is not http(s)");
+            }
+            return None;
+        }
+        let kind = match kind.filter(|k| !k.trim().is_empty()) {
+            Some(raw) => ChatKind::parse(raw).unwrap_or_else(|| {
+                warn!(
+                    kind = raw,
+                    "unknown REPORT_CHAT_KIND, guessing from the url"
+                );
+                ChatKind::infer(url)
+            }),
+            None => ChatKind::infer(url),
+        };
+        Some(Self {
+            kind,
+            url: url.to_string(),
+        })
+    }
+
+    /// The webhook body for one report.
+    pub fn payload(&self, ev: &ReportRecordEmail) -> Value {
+        let links = ReportLinks::for_report(ev);
+        match self.kind {
+            ChatKind::Discord => discord_payload(ev, &links),
+            ChatKind::Slack => slack_payload(ev, &links),
+        }
+    }
+
+    pub async fn send(
+        &self,
+        client: &reqwest::Client,
+        ev: &ReportRecordEmail,
+    ) -> anyhow::Result<()> {
+        let resp = client
+            .post(&self.url)
+            .json(&self.payload(ev))
+            .send()
+            .await?;
+        let status = resp.status();
+        if !status.is_success() {
+            let body = resp.text().await.unwrap_or_default();
+            anyhow::bail!("status={status} body={body}");
+        }
+        Ok(())
+    }
+}
+
+fn details(ev: &ReportRecordEmail) -> String {
+    if ev.details.is_empty() {
+        return "(none)".to_string();
+    }
+    let mut out: String = ev.details.chars().take(MAX_DETAILS_CHARS).collect();
+    if out.len() < ev.details.len() {
+        out.push('…');
+    }
+    out
+}
+
+fn summary(ev: &ReportRecordEmail) -> String {
+    format!("New report: {} ({})", ev.file, ev.reason)
+}
+
+fn discord_payload(ev: &ReportRecordEmail, links: &ReportLinks) -> Value {
+    let field = |name: &str, value: String, inline: bool| {
+        json!({
+            "name": name,
+            "value": value,
+            "inline": inline,
+        })
+    };
+    let mut fields = vec![
+        field("Reason", ev.reason.clone(), true),
+        field("Size (bytes)", ev.size.to_string(), true),
+        field(
+            "Reports",
+            format!(
+                "{} for file, {} total",
+                ev.total_reports_for_file, ev.total_reports
+            ),
+            true,
+        ),
+        field("Original Name", ev.original_name.clone(), false),
+    ];
+    if let Some(warning) = &ev.name_warning {
+        fields.push(field("Name Warning", warning.clone(), false));
+    }
+    fields.push(field("Reporter Hash IP", ev.reporter_hash.clone(), true));
+    fields.push(field("Owner Hash IP", ev.owner_hash.clone(), true));
+    fields.push(field("Details", details(ev), false));
+    let mut actions = vec![
+        format!("[Manage Files]({})", links.admin_files),
+        format!("[View Reports]({})", links.admin_reports),
+    ];
+    if let Some(ban) = &links.ban {
+        actions.push(format!("[Ban Owner IP]({ban})"));
+    }
+    fields.push(field("Admin", actions.join(" · "), false));
+    json!({
+        "content": summary(ev),
+        "allowed_mentions": { "parse": [] },
+        "embeds": [{
+            "title": format!("Open file {}", ev.file),
+            "url": links.file,
+            "color": DISCORD_EMBED_COLOR,
+            "timestamp": ev.iso_time,
+            "fields": fields,
+        }],
+    })
+}
+
+/// Slack treats `&`, `<` and `>` as markup in mrkdwn text.
+fn slack_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn slack_payload(ev: &ReportRecordEmail, links: &ReportLinks) -> Value {
+    let field = |name: &str, value: &str| {
+        json!({
+            "type": "mrkdwn",
+            "text": format!("*{name}*\n{}", slack_escape(value)),
+        })
+    };
+    let mut fields = vec![
+        field("Reason", &ev.reason),
+        field("Size (bytes)", &ev.size.to_string()),
+        field(
+            "Reports",
+            &format!(
+                "{} for file, {} total",
+                ev.total_reports_for_file, ev.total_reports
+            ),
+        ),
+        field("Original Name", &ev.original_name),
+        field("Reporter Hash IP", &ev.reporter_hash),
+        field("Owner Hash IP", &ev.owner_hash),
+    ];
+    if let Some(warning) = &ev.name_warning {
+        fields.push(field("Name Warning", warning));
+    }
+    let button = |text: &str, url: &str| {
+        json!({
+            "type": "button",
+            "text": { "type": "plain_text", "text": text },
+            "url": url,
+        })
+    };
+    let mut buttons = vec![
+        button("Open File", &links.file),
+        button("Manage Files", &links.admin_files),
+        button("View Reports", &links.admin_reports),
+    ];
+    if let Some(ban) = &links.ban {
+        buttons.push(button("Ban Owner IP", ban));
+    }
+    let headline = format!("*{}*", slack_escape(&summary(ev)));
+    let details = format!("*Details*\n{}", slack_escape(&details(ev)));
+    json!({
+        "text": summary(ev),
+        "blocks": [
+            { "type": "section", "text": { "type": "mrkdwn", "text": headline } },
+            { "type": "section", "fields": fields },
+            { "type": "section", "text": { "type": "mrkdwn", "text": details } },
+            { "type": "actions", "elements": buttons },
+        ],
+    })
+}
@@ -0,0 +1,59 @@
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Request};
+use axum::http::{HeaderName, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use std::net::SocketAddr;
+
+use crate::util::{headers_trusted, new_id};
+
+/// Carries the request ID in both directions.
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+/// Longest forwarded ID that is kept; anything longer gets a fresh one.
+const MAX_FORWARDED_ID_LEN: usize = 128;
+
+/// The ID of the request being handled, stored in its extensions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+tokio::task_local! {
+    static CURRENT: String;
+}
+
+/// ID of the request the calling task is serving, when called inside
+/// [`assign_request_id`].
+pub fn current_request_id() -> Option<String> {
+    CURRENT.try_with(Clone::clone).ok()
+}
+
+/// An `X-Request-Id` set by a trusted proxy, so one ID follows the request
+/// through every hop. Clients cannot pick their own.
+fn forwarded_id(req: &Request<Body>) -> Option<String> {
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    if !headers_trusted(req.headers(), peer) {
+        return None;
+    }
+    let value = req.headers().get(&REQUEST_ID_HEADER)?.to_str().ok()?.trim();
+    let valid = !value.is_empty()
+        && value.len() <= MAX_FORWARDED_ID_LEN
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'));
+    valid.then(|| value.to_string())
+}
+
+/// Outermost middleware: gives each request an ID, exposes it to the
+/// tracing span and to `json_error` bodies, and returns it as
+/// `X-Request-Id` so users can quote it when reporting a problem.
+pub async fn assign_request_id(mut req: Request<Body>, next: Next) -> Response {
+    let id = forwarded_id(&req).unwrap_or_else(new_id);
+    req.extensions_mut().insert(RequestId(id.clone()));
+    let mut resp = CURRENT.scope(id.clone(), next.run(req)).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        resp.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    resp
+}
@@ -0,0 +1,183 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::build_info::BuildInfo;
+use crate::network_class::NetworkListSummary;
+use crate::rate_limit::effective_route_limits;
+use crate::reload;
+use crate::state::AppState;
+use crate::storage_pressure::StorageLimits;
+use crate::trace_sampling::TraceSamplingStatus;
+use crate::util::{
+    LISTEN_ADDR, MAX_ACTIVE_FILES_PER_IP, PROD_HOST, SHARE_LINK_PREFIX, UPLOAD_CONCURRENCY,
+    max_filename_chars, streaming_uploads_enabled,
+};
+
+/// Effective configuration after env parsing, logged once at startup and
+/// served to admins at `/api/admin/v1/runtime`.
+#[derive(Serialize, Debug, Clone)]
+pub struct RuntimeSummary {
+    pub build: BuildInfo,
+    pub production: bool,
+    pub listen_addr: String,
+    pub prod_host: String,
+    pub started_at: u64,
+    pub config_file: Option<String>,
+    pub dirs: RuntimeDirs,
+    pub backends: RuntimeBackends,
+    pub features: RuntimeFeatures,
+    pub limits: RuntimeLimits,
+    pub trace_sampling: TraceSamplingStatus,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RuntimeDirs {
+    pub static_dir: String,
+    pub upload_dir: String,
+    pub data_dir: String,
+    pub chunk_dir: String,
+    pub quarantine_dir: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RuntimeBackends {
+    pub kv: &'static str,
+    pub file_store: &'static str,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RuntimeFeatures {
+    pub streaming_uploads: bool,
+    pub share_links: &'static str,
+    pub report_email: bool,
+    pub sentry: bool,
+    pub sentry_environment: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RuntimeLimits {
+    pub max_file_bytes: u64,
+    pub max_filename_chars: usize,
+    pub upload_concurrency: usize,
+    pub max_active_files_per_ip: usize,
+    pub rate_limit_burst: u32,
+    pub rate_limit_refill_per_sec: u32,
+    pub rate_limit_idle_secs: u64,
+    /// Each route policy's limit, as `exempt`, `general` or
+    /// `capacity/refill_per_minute`.
+    pub rate_limit_routes: BTreeMap<&'static str, String>,
+    pub owners_persist_debounce_secs: u64,
+    pub max_connections: usize,
+    pub max_connections_per_ip: usize,
+    pub storage: StorageLimits,
+    pub ttl_policy_rules: usize,
+    pub tor_exits: usize,
+    pub network_lists: NetworkListSummary,
+}
+
+fn display(path: &Path) -> String {
+    path.display().to_string()
+}
+
+impl RuntimeSummary {
+    pub fn collect(state: &AppState) -> Self {
+        let data_dir = state
+            .metadata_path
+            .parent()
+            .map(display)
+            .unwrap_or_default();
+        let hot = reload::current();
+        Self {
+            build: BuildInfo::for_state(state),
+            production: state.production,
+            listen_addr: LISTEN_ADDR.to_string(),
+            prod_host: PROD_HOST.clone(),
+            started_at: state.started_at,
+            config_file: state.config.source.as_deref().map(display),
+            dirs: RuntimeDirs {
+                static_dir: display(&state.static_dir),
+                upload_dir: display(&state.upload_dir),
+                data_dir,
+                chunk_dir: display(&state.chunk_dir),
+                quarantine_dir: display(state.quarantine.dir()),
+            },
+            backends: RuntimeBackends {
+                kv: state.kv.backend_name(),
+                file_store: state.file_store.backend_name(),
+            },
+            features: RuntimeFeatures {
+                streaming_uploads: streaming_uploads_enabled(),
+                share_links: if *SHARE_LINK_PREFIX == "f" {
+                    "direct"
+                } else {
+                    "page"
+                },
+                report_email: state.email_tx.is_some(),
+                sentry: state.telemetry.sentry_enabled(),
+                sentry_environment: state.telemetry.environment.clone(),
+            },
+            limits: RuntimeLimits {
+                max_file_bytes: hot.max_file_bytes,
+                max_filename_chars: max_filename_chars(),
+                upload_concurrency: UPLOAD_CONCURRENCY,
+                max_active_files_per_ip: MAX_ACTIVE_FILES_PER_IP,
+                rate_limit_burst: hot.rate_limit_burst,
+                rate_limit_refill_per_sec: hot.rate_limit_refill_per_sec,
+                rate_limit_idle_secs: hot.rate_limit_idle_secs,
+                rate_limit_routes: effective_route_limits(&hot)
+                    .into_iter()
+                    .map(|(name, limit)| (name, limit.to_string()))
+                    .collect(),
+                owners_persist_debounce_secs: state.owners_persister.debounce().as_secs(),
+                max_connections: state.connections.limits().max_total,
+                max_connections_per_ip: state.connections.limits().max_per_ip,
+                storage: state.storage.limits(),
+                ttl_policy_rules: state.ttl_policy.rule_count(),
+                tor_exits: state.ttl_policy.tor_exit_count(),
+                network_lists: state.networks.summary(),
+            },
+            trace_sampling: state.trace_sampler.status(),
+        }
+    }
+
+    /// Emit the summary as a single structured startup event.
+    pub fn log(&self) {
+        tracing::info!(
+            version = self.build.version,
+            commit = self.build.commit.unwrap_or("unknown"),
+            built_at = %self.build.built_at_iso,
+            cargo_features = ?self.build.features,
+            production = self.production,
+            listen_addr = %self.listen_addr,
+            prod_host = %self.prod_host,
+            static_dir = %self.dirs.static_dir,
+            upload_dir = %self.dirs.upload_dir,
+            data_dir = %self.dirs.data_dir,
+            chunk_dir = %self.dirs.chunk_dir,
+            quarantine_dir = %self.dirs.quarantine_dir,
+            kv_backend = self.backends.kv,
+            file_store = self.backends.file_store,
+            streaming_uploads = self.features.streaming_uploads,
+            share_links = self.features.share_links,
+            report_email = self.features.report_email,
+            sentry = self.features.sentry,
+            sentry_environment = %self.features.sentry_environment,
+            max_file_bytes = self.limits.max_file_bytes,
+            max_filename_chars = self.limits.max_filename_chars,
+            upload_concurrency = self.limits.upload_concurrency,
+            max_active_files_per_ip = self.limits.max_active_files_per_ip,
+            rate_limit_burst = self.limits.rate_limit_burst,
+            rate_limit_refill_per_sec = self.limits.rate_limit_refill_per_sec,
+            rate_limit_idle_secs = self.limits.rate_limit_idle_secs,
+            rate_limit_routes = ?self.limits.rate_limit_routes,
+            owners_persist_debounce_secs = self.limits.owners_persist_debounce_secs,
+            max_connections = self.limits.max_connections,
+            max_connections_per_ip = self.limits.max_connections_per_ip,
+            traces_sample_rate_floor = self.trace_sampling.floor,
+            traces_sample_rate_ceiling = self.trace_sampling.ceiling,
+            traces_error_budget = self.trace_sampling.error_budget,
+            "juicebox starting"
+        );
+    }
+}
@@ -0,0 +1,502 @@
+//! The HTTP server around an [`AppState`]: the middleware stack, the
+//! maintenance tasks and graceful shutdown. The `juicebox` binary is one
+//! user; anything embedding the crate (a larger binary, an integration test)
+//! can run the same server without copying its wiring, over a state it
+//! opens from the environment with [`Server::open`] or assembles itself
+//! with [`crate::startup::StateParts`].
+//!
+//! ```no_run
+//! # async fn demo(state: juicebox::state::AppState) -> anyhow::Result<()> {
+//! use juicebox::server::Server;
+//!
+//! let running = Server::new(state)
+//!     .listen("127.0.0.1:0".parse()?)
+//!     .handle_signals(false)
+//!     .start()
+//!     .await?;
+//! println!("listening on {}", running.local_addr());
+//! running.shutdown().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use anyhow::Context;
+use axum::http::{Request, Response};
+use axum::{Router, middleware};
+use axum_server::Handle;
+use futures_util::future::BoxFuture;
+use sentry_tower::{NewSentryLayer, SentryHttpLayer};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::signal::ctrl_c;
+#[cfg(unix)]
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tower_http::{compression::CompressionLayer, trace::TraceLayer};
+use tracing::field::Empty;
+use tracing::{Instrument, error, info, info_span, warn};
+
+use crate::access_log::access_log;
+use crate::config::Config;
+use crate::connections::{TrackConnections, connection_gate};
+use crate::handlers::telemetry::telemetry_gate;
+use crate::handlers::{add_cache_headers, add_security_headers, ban_gate, build_router};
+use crate::rate_limit::{RateLimiterInner, build_rate_limiter};
+use crate::reload::{self, body_limit};
+use crate::request_id::{RequestId, assign_request_id};
+use crate::runtime::RuntimeSummary;
+use crate::shadow::shadow_gate;
+use crate::startup::open_state;
+use crate::state::{AppState, TelemetryState, cleanup_expired};
+use crate::tls::{self, TlsAcceptor};
+use crate::trace_sampling::TraceSampler;
+use crate::util::LISTEN_ADDR;
+
+/// How often expired files, stale sessions and idle limiter entries are
+/// swept.
+pub const CLEANUP_INTERVAL: Duration = Duration::from_secs(600);
+
+type RouterMap = Box<dyn FnOnce(Router) -> Router + Send>;
+type BackgroundTask = Box<dyn FnOnce(Arc<Notify>) -> BoxFuture<'static, ()> + Send>;
+
+/// Builder for a server over an already opened [`AppState`].
+pub struct Server {
+    state: AppState,
+    addr: SocketAddr,
+    tls: Option<TlsAcceptor>,
+    access_log: bool,
+    handle_signals: bool,
+    router_maps: Vec<RouterMap>,
+    tasks: Vec<(&'static str, BackgroundTask)>,
+}
+
+impl Server {
+    pub fn new(state: AppState) -> Self {
+        Self {
+            state,
+            addr: LISTEN_ADDR,
+            tls: None,
+            access_log: false,
+            handle_signals: true,
+            router_maps: Vec::new(),
+            tasks: Vec::new(),
+        }
+    }
+
+    /// A server over the state the environment configures, opened as the
+    /// `juicebox` binary opens it; see [`open_state`].
+    pub async fn open(
+        config: Config,
+        production: bool,
+        telemetry: TelemetryState,
+        trace_sampler: Arc<TraceSampler>,
+    ) -> anyhow::Result<Self> {
+        Ok(Self::new(
+            open_state(config, production, telemetry, trace_sampler).await?,
+        ))
+    }
+
+    /// Listen on `addr` instead of [`LISTEN_ADDR`]. Port 0 picks a free one;
+    /// see [`RunningServer::local_addr`].
+    pub fn listen(mut self, addr: SocketAddr) -> Self {
+        self.addr = addr;
+        self
+    }
+
+    /// Serve HTTPS with `acceptor`, reloading its certificate as it changes.
+    pub fn tls(mut self, acceptor: Option<TlsAcceptor>) -> Self {
+        self.tls = acceptor;
+        self
+    }
+
+    /// Write one JSON access log line per request.
+    pub fn access_log(mut self, enabled: bool) -> Self {
+        self.access_log = enabled;
+        self
+    }
+
+    /// Shut down on Ctrl-C and SIGTERM. On by default; embedders that own
+    /// the process's signals turn it off and call
+    /// [`RunningServer::shutdown`] instead.
+    pub fn handle_signals(mut self, enabled: bool) -> Self {
+        self.handle_signals = enabled;
+        self
+    }
+
+    /// Change the application router before the standard middleware is
+    /// wrapped around it, e.g. to merge extra routes or add a layer.
+    pub fn map_router(mut self, f: impl FnOnce(Router) -> Router + Send + 'static) -> Self {
+        self.router_maps.push(Box::new(f));
+        self
+    }
+
+    /// Run `task` alongside the server. It receives the shutdown notifier
+    /// and must return soon after it fires; shutdown waits for it.
+    pub fn background<F, Fut>(mut self, name: &'static str, task: F) -> Self
+    where
+        F: FnOnce(Arc<Notify>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.tasks
+            .push((name, Box::new(move |notify| Box::pin(task(notify)))));
+        self
+    }
+
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    /// The state to serve, for setup that has to happen before
+    /// [`Server::start`], e.g. loading the admin key.
+    pub fn state_mut(&mut self) -> &mut AppState {
+        &mut self.state
+    }
+
+    /// The application router with the full middleware stack, as served.
+    fn app(&mut self, rate_layer: crate::rate_limit::RateLimitLayer) -> Router {
+        let state = self.state.clone();
+        let mut router = build_router(state.clone());
+        for map in self.router_maps.drain(..) {
+            router = map(router);
+        }
+        let app: Router = router
+            .layer(
+                TraceLayer::new_for_http()
+                    .make_span_with(|request: &Request<_>| {
+                        let request_id = request
+                            .extensions()
+                            .get::<RequestId>()
+                            .map(|id| id.0.as_str())
+                            .unwrap_or_default();
+                        // matched_path is filled in by the router once a route is chosen
+                        tracing::info_span!(
+                            "http.server.request",
+                            method = %request.method(),
+                            request_id = %request_id,
+                            matched_path = Empty,
+                            uri = %request.uri(),
+                            http.status_code = Empty,
+                            latency_ms = Empty
+                        )
+                    })
+                    .on_request(|request: &Request<_>, span: &tracing::Span| {
+                        tracing::info!(
+                            parent: span,
+                            method = %request.method(),
+                            uri = %request.uri(),
+                            "HTTP request received"
+                        );
+                    })
+                    .on_response(
+                        |response: &Response<_>, latency: Duration, span: &tracing::Span| {
+                            span.record("http.status_code", response.status().as_u16() as i64);
+                            span.record("latency_ms", latency.as_millis() as i64);
+                            tracing::info!(
+                                parent: span,
+                                status = %response.status(),
+                                latency_ms = latency.as_millis(),
+                                "HTTP response dispatched"
+                            );
+                        },
+                    ),
+            )
+            // Each request gets its own hub before the transaction is started on it.
+            .layer(SentryHttpLayer::new().enable_transaction())
+            .layer(NewSentryLayer::new_from_top())
+            .layer(middleware::from_fn_with_state(state.clone(), shadow_gate))
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                telemetry_gate,
+            ))
+            .layer(CompressionLayer::new())
+            .layer(middleware::from_fn(add_cache_headers))
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                add_security_headers,
+            ))
+            .layer(middleware::from_fn_with_state(state.clone(), ban_gate))
+            .layer(rate_layer)
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                connection_gate,
+            ));
+        let app = if self.access_log {
+            app.layer(middleware::from_fn_with_state(state.clone(), access_log))
+        } else {
+            app
+        };
+        app.layer(middleware::from_fn(body_limit))
+            .layer(middleware::from_fn(assign_request_id))
+    }
+
+    /// Start serving in the background and return once the listener is
+    /// bound.
+    pub async fn start(mut self) -> anyhow::Result<RunningServer> {
+        let state = self.state.clone();
+        let notify = Arc::new(Notify::new());
+        let (rate_layer, rate) = build_rate_limiter();
+        let rate_layer = rate_layer
+            .with_api_tokens(state.api_tokens.clone())
+            .with_network_lists(state.networks.clone());
+        let app = self.app(rate_layer);
+
+        let mut tasks: Vec<(&'static str, JoinHandle<()>)> = vec![
+            (
+                "owners persister",
+                state.spawn_owners_persister(notify.clone()),
+            ),
+            (
+                "cleanup task",
+                spawn_cleanup(state.clone(), rate.clone(), notify.clone()),
+            ),
+        ];
+        if let Some(handle) = state.spawn_network_list_refresher(notify.clone()) {
+            tasks.push(("network list refresher", handle));
+        }
+        if let Some(handle) = state.spawn_upload_watcher(notify.clone()) {
+            tasks.push(("upload directory watcher", handle));
+        }
+        if let Some(handle) = state.spawn_kv_replayer(notify.clone()) {
+            tasks.push(("metadata replay", handle));
+        }
+        for (name, task) in self.tasks {
+            tasks.push((name, tokio::spawn(task(notify.clone()))));
+        }
+
+        RuntimeSummary::collect(&state).log();
+        let handle = Handle::new();
+        let cancel = Arc::new(Notify::new());
+        let shutdown_task = tokio::spawn(
+            wait_for_shutdown(
+                state.clone(),
+                notify.clone(),
+                rate.clone(),
+                handle.clone(),
+                cancel.clone(),
+                self.handle_signals,
+            )
+            .instrument(info_span!("graceful_shutdown")),
+        );
+
+        let make_service = TrackConnections::new(
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+            state.connections.clone(),
+        );
+        let addr = self.addr;
+        let serve_handle = handle.clone();
+        let serve = match self.tls {
+            Some(acceptor) => {
+                info!(
+                    %addr,
+                    cert = %acceptor.settings().cert_path.display(),
+                    "serving HTTPS"
+                );
+                tokio::spawn(acceptor.clone().watch(tls::WATCH_INTERVAL));
+                tokio::spawn(async move {
+                    axum_server::bind(addr)
+                        .acceptor(acceptor)
+                        .handle(serve_handle)
+                        .serve(make_service)
+                        .await
+                })
+            }
+            None => tokio::spawn(async move {
+                axum_server::bind(addr)
+                    .handle(serve_handle)
+                    .serve(make_service)
+                    .await
+            }),
+        };
+        let local_addr = match handle.listening().await {
+            Some(local_addr) => local_addr,
+            None => {
+                cancel.notify_waiters();
+                notify.notify_waiters();
+                let err = match serve.await {
+                    Ok(Err(err)) => anyhow::Error::from(err),
+                    Ok(Ok(())) => anyhow::anyhow!("server stopped before listening"),
+                    Err(err) => anyhow::Error::from(err),
+                };
+                return Err(err).with_context(|| format!("failed to listen on {addr}"));
+            }
+        };
+        info!(%local_addr, "server listening");
+        Ok(RunningServer {
+            state,
+            local_addr,
+            handle,
+            notify,
+            cancel,
+            rate,
+            serve,
+            shutdown_task,
+            tasks,
+        })
+    }
+
+    /// Serve until a shutdown signal or a finished drain, then save state.
+    pub async fn run(self) -> anyhow::Result<()> {
+        self.start().await?.wait().await
+    }
+}
+
+/// A server started with [`Server::start`].
+pub struct RunningServer {
+    state: AppState,
+    local_addr: SocketAddr,
+    handle: Handle,
+    notify: Arc<Notify>,
+    cancel: Arc<Notify>,
+    rate: RateLimiterInner,
+    serve: JoinHandle<std::io::Result<()>>,
+    shutdown_task: JoinHandle<bool>,
+    tasks: Vec<(&'static str, JoinHandle<()>)>,
+}
+
+impl RunningServer {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    /// Stop accepting connections, then finish as [`RunningServer::wait`]
+    /// does.
+    pub async fn shutdown(self) -> anyhow::Result<()> {
+        self.handle.shutdown();
+        self.wait().await
+    }
+
+    /// Wait for the server to stop, stop the background tasks and save
+    /// state.
+    pub async fn wait(self) -> anyhow::Result<()> {
+        let served = match self.serve.await {
+            Ok(result) => result.context("server failed"),
+            Err(err) => Err(err).context("server task panicked"),
+        };
+        self.handle.shutdown();
+        self.notify.notify_waiters();
+        self.cancel.notify_waiters();
+        let shutdown_handled = match self.shutdown_task.await {
+            Ok(result) => result,
+            Err(err) => {
+                warn!(?err, "shutdown task terminated unexpectedly");
+                false
+            }
+        };
+        for (name, task) in self.tasks {
+            if let Err(err) = task.await {
+                warn!(?err, task = name, "background task terminated unexpectedly");
+            }
+        }
+        if !shutdown_handled {
+            persist_state(&self.state, &self.rate).await;
+        }
+        served
+    }
+}
+
+fn spawn_cleanup(state: AppState, rate: RateLimiterInner, shutdown: Arc<Notify>) -> JoinHandle<()> {
+    tokio::spawn(
+        async move {
+            let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = shutdown.notified() => {
+                        break;
+                    }
+                    _ = interval.tick() => {
+                        cleanup_expired(&state).await;
+                        state.enforce_storage_limits().await;
+                        state.cleanup_admin_sessions().await;
+                        state.cleanup_chunk_sessions().await;
+                        state.sweep_orphaned_chunk_dirs().await;
+                        state.prune_expired_bans().await;
+                        state.persist_ban_hits().await;
+                        state.persist_upload_stats().await;
+                        let idle = Duration::from_secs(reload::current().rate_limit_idle_secs);
+                        rate.prune_idle(idle).await;
+                        state.link_status_limiter.prune_idle(idle).await;
+                        state.visitor_debug_limiter.prune_idle(idle).await;
+                    }
+                }
+            }
+        }
+        .instrument(info_span!("maintenance.cleanup")),
+    )
+}
+
+/// Save everything held in memory before the process exits.
+async fn persist_state(state: &AppState, rate: &RateLimiterInner) {
+    state.cleanup_admin_sessions().await;
+    state.persist_admin_sessions().await;
+    state.persist_reports().await;
+    state.persist_bans().await;
+    state.persist_ban_hits().await;
+    state.persist_upload_stats().await;
+    state.flush_owners().await;
+    state.persist_all_chunk_sessions().await;
+    rate.prune_idle(Duration::from_secs(0)).await;
+}
+
+#[tracing::instrument(skip(state, notify, rate, handle, cancel))]
+async fn wait_for_shutdown(
+    state: AppState,
+    notify: Arc<Notify>,
+    rate: RateLimiterInner,
+    handle: Handle,
+    cancel: Arc<Notify>,
+    signals: bool,
+) -> bool {
+    let signal = async {
+        if signals {
+            listen_for_shutdown().await
+        } else {
+            std::future::pending().await
+        }
+    };
+    let triggered = tokio::select! {
+        _ = signal => true,
+        _ = state.drain.finished() => true,
+        _ = cancel.notified() => false,
+    };
+    if !triggered {
+        return false;
+    }
+    info!("shutdown signal received; commencing graceful shutdown");
+    notify.notify_waiters();
+    handle.shutdown();
+    persist_state(&state, &rate).await;
+    true
+}
+
+#[tracing::instrument(skip_all)]
+async fn listen_for_shutdown() {
+    let ctrl_c = async {
+        if let Err(err) = ctrl_c().await {
+            error!(?err, "failed to install ctrl+c handler"); // this shouldn't happen.
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(err) => error!(?err, "failed to install SIGTERM handler"), //this shouldn't happen either
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = async {
+        std::future::pending::<()>().await;
+    };
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
@@ -0,0 +1,299 @@
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::header::{ACCEPT, CONTENT_LENGTH, IF_MODIFIED_SINCE, IF_NONE_MATCH, RANGE};
+use axum::http::{HeaderMap, HeaderName, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+use serde::Serialize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tracing::{Instrument, debug, trace, warn};
+
+use crate::handlers::telemetry::PrivacyOptOut;
+use crate::state::AppState;
+
+/// Share of eligible requests mirrored unless `JUICEBOX_SHADOW_SAMPLE` says
+/// otherwise.
+pub const DEFAULT_SHADOW_SAMPLE: f64 = 0.01;
+/// Mirrored requests allowed in flight at once; the rest are dropped.
+pub const SHADOW_MAX_IN_FLIGHT: usize = 16;
+
+/// The only request headers a mirrored request carries. Cookies,
+/// authorization, client addresses and user agents never leave.
+const FORWARDED_HEADERS: [HeaderName; 4] = [ACCEPT, RANGE, IF_NONE_MATCH, IF_MODIFIED_SINCE];
+/// Query keys a mirrored request may carry. Anything else, such as the
+/// signature on a presigned link, keeps the request from being mirrored.
+const FORWARDED_QUERY_KEYS: [&str; 3] = ["offset", "limit", "cursor"];
+/// Marks mirrored requests so staging can tell them apart.
+pub const SHADOW_HEADER: &str = "x-juicebox-shadow";
+
+#[derive(Clone, Debug)]
+pub struct ShadowConfig {
+    /// Staging base URL, without a trailing slash.
+    pub base_url: Option<String>,
+    /// Fraction of eligible requests to mirror, from 0 to 1.
+    pub sample: f64,
+    pub timeout: Duration,
+}
+
+impl Default for ShadowConfig {
+    fn default() -> Self {
+        Self {
+            base_url: None,
+            sample: DEFAULT_SHADOW_SAMPLE,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl ShadowConfig {
+    /// Read `JUICEBOX_SHADOW_URL` and `JUICEBOX_SHADOW_SAMPLE`.
+    pub fn from_env() -> Self {
+        let base_url = std::env::var("JUICEBOX_SHADOW_URL")
+            .ok()
+            .map(|v| v.trim().trim_end_matches('/').to_string())
+            .filter(|v| v.starts_with("http://") || v.starts_with("https://"));
+        let sample = std::env::var("JUICEBOX_SHADOW_SAMPLE")
+            .ok()
+            .and_then(|v| v.trim().parse::<f64>().ok())
+            .filter(|v| v.is_finite())
+            .map_or(DEFAULT_SHADOW_SAMPLE, |v| v.clamp(0.0, 1.0));
+        Self {
+            base_url,
+            sample,
+            ..Self::default()
+        }
+    }
+}
+
+/// What `/api/admin/v1/shadow` reports.
+#[derive(Serialize, Debug, Clone)]
+pub struct ShadowStatus {
+    pub configured: bool,
+    pub enabled: bool,
+    pub base_url: Option<String>,
+    pub sample: f64,
+    pub mirrored: u64,
+    pub matched: u64,
+    pub mismatched: u64,
+    pub failed: u64,
+    pub dropped: u64,
+}
+
+/// Mirrors a sample of read-only requests to a staging deployment and
+/// compares its answers with ours. Off unless a staging URL is configured;
+/// admins can flip the kill switch at runtime without a restart.
+pub struct Shadow {
+    config: ShadowConfig,
+    enabled: AtomicBool,
+    client: reqwest::Client,
+    in_flight: Arc<Semaphore>,
+    mirrored: AtomicU64,
+    matched: AtomicU64,
+    mismatched: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl Default for Shadow {
+    fn default() -> Self {
+        Self::new(ShadowConfig::default())
+    }
+}
+
+impl Shadow {
+    pub fn new(config: ShadowConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap_or_default();
+        Self {
+            enabled: AtomicBool::new(config.base_url.is_some()),
+            config,
+            client,
+            in_flight: Arc::new(Semaphore::new(SHADOW_MAX_IN_FLIGHT)),
+            mirrored: AtomicU64::new(0),
+            matched: AtomicU64::new(0),
+            mismatched: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.base_url.is_some() && self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// The kill switch. Has no effect without a staging URL.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn status(&self) -> ShadowStatus {
+        ShadowStatus {
+            configured: self.config.base_url.is_some(),
+            enabled: self.is_enabled(),
+            base_url: self.config.base_url.clone(),
+            sample: self.config.sample,
+            mirrored: self.mirrored.load(Ordering::Relaxed),
+            matched: self.matched.load(Ordering::Relaxed),
+            mismatched: self.mismatched.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    fn sampled(&self) -> bool {
+        self.config.sample >= 1.0 || rand::random::<f64>() < self.config.sample
+    }
+}
+
+/// Whether a route is one we mirror: downloads, listings and config.
+fn is_mirrored_route(method: &Method, path: &str) -> bool {
+    if method != Method::GET && method != Method::HEAD {
+        return false;
+    }
+    matches!(path, "/list" | "/mine" | "/api/config")
+        || ["/f/", "/d/"].iter().any(|prefix| {
+            path.strip_prefix(prefix)
+                .is_some_and(|rest| !rest.is_empty() && !rest.contains('/'))
+        })
+}
+
+/// The path and query to send to staging, or `None` if the query carries
+/// anything outside [`FORWARDED_QUERY_KEYS`].
+fn scrubbed_target(path: &str, query: Option<&str>) -> Option<String> {
+    let Some(query) = query.filter(|q| !q.is_empty()) else {
+        return Some(path.to_string());
+    };
+    let clean = query.split('&').all(|pair| {
+        let key = pair.split('=').next().unwrap_or_default();
+        FORWARDED_QUERY_KEYS.contains(&key)
+    });
+    clean.then(|| format!("{path}?{query}"))
+}
+
+fn scrubbed_headers(headers: &HeaderMap) -> HeaderMap {
+    let mut out = HeaderMap::new();
+    for name in FORWARDED_HEADERS {
+        if let Some(value) = headers.get(&name) {
+            out.insert(name, value.clone());
+        }
+    }
+    out.insert(SHADOW_HEADER, "1".parse().expect("static header value"));
+    out
+}
+
+fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers.get(CONTENT_LENGTH)?.to_str().ok()?.parse().ok()
+}
+
+/// Middleware that mirrors eligible requests once our own response is ready.
+/// The mirror runs in the background and never delays or alters the client's
+/// response. Requests carrying a privacy signal are never mirrored.
+pub async fn shadow_gate(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let shadow = state.shadow.clone();
+    if !shadow.is_enabled()
+        || req.extensions().get::<PrivacyOptOut>().is_some()
+        || !is_mirrored_route(req.method(), req.uri().path())
+    {
+        return next.run(req).await;
+    }
+    let Some(target) = scrubbed_target(req.uri().path(), req.uri().query()) else {
+        trace!(path = %req.uri().path(), "not mirroring request with unlisted query keys");
+        return next.run(req).await;
+    };
+    if !shadow.sampled() {
+        return next.run(req).await;
+    }
+    let method = req.method().clone();
+    let headers = scrubbed_headers(req.headers());
+    let resp = next.run(req).await;
+    let Ok(permit) = shadow.in_flight.clone().try_acquire_owned() else {
+        shadow.dropped.fetch_add(1, Ordering::Relaxed);
+        return resp;
+    };
+    let primary = (resp.status(), content_length(resp.headers()));
+    let base = shadow.config.base_url.clone().unwrap_or_default();
+    tokio::spawn(
+        async move {
+            let _permit = permit;
+            mirror(&shadow, method, format!("{base}{target}"), headers, primary).await;
+        }
+        .instrument(tracing::debug_span!("shadow.mirror")),
+    );
+    resp
+}
+
+async fn mirror(
+    shadow: &Shadow,
+    method: Method,
+    url: String,
+    headers: HeaderMap,
+    primary: (StatusCode, Option<u64>),
+) {
+    shadow.mirrored.fetch_add(1, Ordering::Relaxed);
+    let result = shadow
+        .client
+        .request(method.clone(), &url)
+        .headers(headers)
+        .send()
+        .await;
+    let staged = match result {
+        Ok(resp) => (resp.status(), content_length(resp.headers())),
+        Err(err) => {
+            shadow.failed.fetch_add(1, Ordering::Relaxed);
+            debug!(?err, %method, "shadow request failed");
+            return;
+        }
+    };
+    let lengths_agree = match (primary.1, staged.1) {
+        (Some(ours), Some(theirs)) => ours == theirs,
+        _ => true,
+    };
+    if primary.0 == staged.0 && lengths_agree {
+        shadow.matched.fetch_add(1, Ordering::Relaxed);
+        trace!(%method, status = %primary.0, "shadow response matched");
+    } else {
+        shadow.mismatched.fetch_add(1, Ordering::Relaxed);
+        warn!(
+            %method,
+            path = url.split('?').next().unwrap_or_default(),
+            primary_status = %primary.0,
+            staging_status = %staged.0,
+            primary_length = ?primary.1,
+            staging_length = ?staged.1,
+            "shadow response differs from staging"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_read_routes_without_credentials_are_mirrored() {
+        assert!(is_mirrored_route(&Method::GET, "/f/abc.png"));
+        assert!(is_mirrored_route(&Method::HEAD, "/d/abc.png"));
+        assert!(is_mirrored_route(&Method::GET, "/api/config"));
+        assert!(!is_mirrored_route(&Method::DELETE, "/f/abc.png"));
+        assert!(!is_mirrored_route(&Method::GET, "/f/"));
+        assert!(!is_mirrored_route(&Method::POST, "/list"));
+        assert!(!is_mirrored_route(&Method::GET, "/api/admin/v1/runtime"));
+
+        assert_eq!(
+            scrubbed_target("/list", Some("offset=20&limit=10")).as_deref(),
+            Some("/list?offset=20&limit=10")
+        );
+        assert_eq!(scrubbed_target("/f/a", Some("exp=1&sig=x")), None);
+        assert_eq!(scrubbed_target("/f/a", None).as_deref(), Some("/f/a"));
+    }
+}
@@ -0,0 +1,738 @@
+//! Opening an [`AppState`]: where bodies and metadata live, the secrets,
+//! and everything loaded back from the stores. [`open_state`] does it from
+//! the environment, as the binary and [`Server::open`] do; [`StateParts`]
+//! is the same assembly for callers that bring their own stores.
+//!
+//! [`Server::open`]: crate::server::Server::open
+
+use anyhow::{Context, anyhow};
+use dashmap::DashMap;
+use redis::Client;
+use redis::aio::ConnectionManager;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::fs;
+use tokio::sync::{RwLock, Semaphore, mpsc};
+use tracing::{debug, info, warn};
+
+use crate::accounts::Accounts;
+use crate::assets;
+use crate::audit::AuditLog;
+use crate::ban_hits::BanHitCounters;
+use crate::clamav::Clamd;
+use crate::cluster::node_id_from_env;
+use crate::config::Config;
+use crate::connections::{ConnectionLimits, ConnectionTracker};
+use crate::content_scan::ContentScanners;
+use crate::cors::CorsConfig;
+use crate::drain::Drain;
+use crate::feature_flags::FeatureFlags;
+use crate::file_store::{FileStore, LocalFileStore, S3Config, S3FileStore};
+use crate::geoip::GeoIp;
+use crate::handlers::stats::PublicStatsCache;
+use crate::handlers::{EmailPrivacy, ReportRecordEmail};
+use crate::kv_failover::{
+    CircuitBreaker, DEFAULT_FAILOVER_COOLDOWN_SECS, DEFAULT_FAILOVER_THRESHOLD, FailoverStore,
+};
+use crate::migrate;
+use crate::network_class::{NetworkListConfig, NetworkLists};
+use crate::owner_events::OwnerEvents;
+use crate::quarantine::Quarantine;
+use crate::rate_limit::{build_link_status_limiter, build_visitor_debug_limiter};
+use crate::reindex::Reindex;
+use crate::shadow::{Shadow, ShadowConfig};
+use crate::state::{
+    ApiTokens, AppState, FileMeta, IpBan, KvStore, OWNERS_PERSIST_DEBOUNCE, OwnersIndex,
+    OwnersPersister, PostgresStore, RedisStore, ReportRecord, RequestAnalytics, SqliteStore,
+    TelemetryState,
+};
+use crate::storage_pressure::{StorageLimits, StorageWatchdog};
+use crate::tombstones::{DEFAULT_TOMBSTONE_GRACE_SECS, Tombstones};
+use crate::trace_sampling::TraceSampler;
+use crate::transparency::TransparencyLog;
+use crate::ttl_policy::TtlPolicy;
+use crate::upload_stats::UploadStats;
+use crate::util::{Clock, IP_SECRET_KEY_ID, SigningKeys, UPLOAD_CONCURRENCY, new_id, now_secs};
+use crate::webhooks::{WebhookConfig, Webhooks};
+
+const OWNERS_FILE: &str = "file_owners.json";
+const REPORTS_FILE: &str = "reports.json";
+const ADMIN_SESSIONS_FILE: &str = "admin_sessions.json";
+const ADMIN_KEY_FILE: &str = "admin_key.json";
+const BANS_FILE: &str = "ip_bans.json";
+const TRANSPARENCY_FILE: &str = "transparency.log";
+
+const DEFAULT_POSTGRES_POOL_SIZE: usize = 16;
+
+fn decode_hash_secret(raw: &str) -> anyhow::Result<Vec<u8>> {
+    let trimmed = raw.trim();
+    if trimmed.is_empty() {
+        return Err(anyhow!("IP_HASH_SECRET may not be empty"));
+    }
+    // fuck you
+    if trimmed.len().is_multiple_of(2) && trimmed.chars().all(|c| c.is_ascii_hexdigit()) {
+        let mut buf = Vec::with_capacity(trimmed.len() / 2);
+        for chunk in trimmed.as_bytes().chunks(2) {
+            let hi = (chunk[0] as char)
+                .to_digit(16)
+                .ok_or_else(|| anyhow!("IP_HASH_SECRET contains invalid hex"))?;
+            let lo = (chunk[1] as char)
+                .to_digit(16)
+                .ok_or_else(|| anyhow!("IP_HASH_SECRET contains invalid hex"))?;
+            buf.push(((hi << 4) | lo) as u8);
+        }
+        return Ok(buf);
+    }
+    Ok(trimmed.as_bytes().to_vec())
+}
+
+/// `IP_HASH_SECRET`, hex-decoded when it looks like hex.
+pub fn load_hash_secret_from_env() -> anyhow::Result<Vec<u8>> {
+    let raw = std::env::var("IP_HASH_SECRET")
+        .map_err(|_| anyhow!("IP_HASH_SECRET environment variable is required"))?;
+    let bytes = decode_hash_secret(&raw)?;
+    if bytes.len() < 16 {
+        return Err(anyhow!(
+            "IP_HASH_SECRET must be at least 16 bytes after decoding"
+        ));
+    }
+    Ok(bytes)
+}
+
+fn read_trimmed_env(key: &str) -> Option<String> {
+    std::env::var(key)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+fn resolve_owners_persist_debounce() -> Duration {
+    read_trimmed_env("JUICEBOX_OWNERS_PERSIST_DEBOUNCE_SECS")
+        .and_then(|value| value.parse::<u64>().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(OWNERS_PERSIST_DEBOUNCE)
+}
+
+/// Pick where file bodies are kept: `JUICEBOX_FILE_STORE=s3` selects a bucket
+/// configured through the `S3_*` variables, anything else the upload dir.
+pub fn resolve_file_store(upload_dir: &Path) -> anyhow::Result<Arc<dyn FileStore>> {
+    let backend = read_trimmed_env("JUICEBOX_FILE_STORE")
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    if !matches!(backend.as_str(), "s3" | "minio") {
+        return Ok(Arc::new(LocalFileStore::new(upload_dir)));
+    }
+    let endpoint = read_trimmed_env("S3_ENDPOINT");
+    let path_style = read_trimmed_env("S3_PATH_STYLE")
+        .map(|v| matches!(v.to_ascii_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+        .unwrap_or(endpoint.is_some());
+    let config = S3Config {
+        bucket: read_trimmed_env("S3_BUCKET")
+            .context("S3_BUCKET is required for the s3 file store")?,
+        region: read_trimmed_env("S3_REGION")
+            .or_else(|| read_trimmed_env("AWS_REGION"))
+            .unwrap_or_else(|| "us-east-1".to_string()),
+        endpoint,
+        access_key_id: read_trimmed_env("S3_ACCESS_KEY_ID")
+            .or_else(|| read_trimmed_env("AWS_ACCESS_KEY_ID"))
+            .context("S3_ACCESS_KEY_ID or AWS_ACCESS_KEY_ID is required for the s3 file store")?,
+        secret_access_key: read_trimmed_env("S3_SECRET_ACCESS_KEY")
+            .or_else(|| read_trimmed_env("AWS_SECRET_ACCESS_KEY"))
+            .context(
+                "S3_SECRET_ACCESS_KEY or AWS_SECRET_ACCESS_KEY is required for the s3 file store",
+            )?,
+        prefix: read_trimmed_env("S3_PREFIX").unwrap_or_default(),
+        path_style,
+    };
+    info!(
+        bucket = %config.bucket,
+        region = %config.region,
+        endpoint = ?config.endpoint,
+        path_style = config.path_style,
+        "using S3 file store"
+    );
+    Ok(Arc::new(S3FileStore::new(config)?))
+}
+
+/// Pick the metadata store from `JUICEBOX_METADATA_STORE`. Without it, Redis
+/// is used when a Redis URL is configured, then Postgres when a database URL
+/// is, and otherwise a SQLite file in the data directory. With
+/// `JUICEBOX_METADATA_FALLBACK` set, writes are mirrored to that store and it
+/// takes over while the primary is down.
+pub async fn resolve_kv_store(data_dir: &Path) -> anyhow::Result<Arc<dyn KvStore>> {
+    let redis_url =
+        read_trimmed_env("JUICEBOX_REDIS_URL").or_else(|| read_trimmed_env("REDIS_URL"));
+    let postgres_url =
+        read_trimmed_env("JUICEBOX_POSTGRES_URL").or_else(|| read_trimmed_env("DATABASE_URL"));
+    let backend = read_trimmed_env("JUICEBOX_METADATA_STORE")
+        .map(|v| v.to_ascii_lowercase())
+        .unwrap_or_else(|| {
+            if redis_url.is_some() {
+                "redis".to_string()
+            } else if postgres_url.is_some() {
+                "postgres".to_string()
+            } else {
+                "sqlite".to_string()
+            }
+        });
+    let primary = open_kv_backend(&backend, data_dir, &redis_url, &postgres_url).await?;
+    let Some(fallback) = read_trimmed_env("JUICEBOX_METADATA_FALLBACK") else {
+        return Ok(primary);
+    };
+    let fallback = fallback.to_ascii_lowercase();
+    let fallback = open_kv_backend(&fallback, data_dir, &redis_url, &postgres_url)
+        .await
+        .context("failed to open JUICEBOX_METADATA_FALLBACK")?;
+    if fallback.backend_name() == primary.backend_name() {
+        return Err(anyhow!(
+            "JUICEBOX_METADATA_FALLBACK must differ from the primary metadata store"
+        ));
+    }
+    let threshold = read_trimmed_env("JUICEBOX_METADATA_FAILOVER_THRESHOLD")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_FAILOVER_THRESHOLD);
+    let cooldown = read_trimmed_env("JUICEBOX_METADATA_FAILOVER_COOLDOWN_SECS")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_FAILOVER_COOLDOWN_SECS);
+    info!(
+        primary = primary.backend_name(),
+        fallback = fallback.backend_name(),
+        threshold,
+        cooldown_secs = cooldown,
+        "metadata store fails over"
+    );
+    Ok(Arc::new(FailoverStore::new(
+        primary,
+        fallback,
+        CircuitBreaker::new(threshold, Duration::from_secs(cooldown)),
+    )))
+}
+
+async fn open_kv_backend(
+    backend: &str,
+    data_dir: &Path,
+    redis_url: &Option<String>,
+    postgres_url: &Option<String>,
+) -> anyhow::Result<Arc<dyn KvStore>> {
+    match backend {
+        "redis" => {
+            let redis_url = redis_url.clone().context(
+                "JUICEBOX_REDIS_URL or REDIS_URL is required for the redis metadata store",
+            )?;
+            let redis_client = Client::open(redis_url.clone())
+                .with_context(|| format!("failed to create redis client for {redis_url}"))?;
+            let redis_manager = ConnectionManager::new(redis_client)
+                .await
+                .context("failed to establish redis connection")?;
+            let redis_prefix =
+                read_trimmed_env("JUICEBOX_REDIS_PREFIX").unwrap_or_else(|| "juicebox".to_string());
+            debug!(redis_url = %redis_url, prefix = %redis_prefix, "connected to redis");
+            Ok(Arc::new(RedisStore::new(
+                redis_prefix,
+                Arc::new(tokio::sync::Mutex::new(redis_manager)),
+            )))
+        }
+        "postgres" | "postgresql" => {
+            let postgres_url = postgres_url.as_deref().context(
+                "JUICEBOX_POSTGRES_URL or DATABASE_URL is required for the postgres metadata store",
+            )?;
+            let prefix = read_trimmed_env("JUICEBOX_POSTGRES_PREFIX")
+                .unwrap_or_else(|| "juicebox".to_string());
+            let pool_size = read_trimmed_env("JUICEBOX_POSTGRES_POOL_SIZE")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_POSTGRES_POOL_SIZE);
+            let store = PostgresStore::connect(postgres_url, prefix.clone(), pool_size)
+                .await
+                .context("failed to connect to postgres")?;
+            info!(%prefix, pool_size, "using postgres metadata store");
+            Ok(Arc::new(store))
+        }
+        "sqlite" => {
+            let path = read_trimmed_env("JUICEBOX_SQLITE_PATH")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| data_dir.join("juicebox.db"));
+            let store = SqliteStore::open(&path)
+                .with_context(|| format!("failed to open sqlite database {}", path.display()))?;
+            info!(path = %path.display(), "using sqlite metadata store");
+            Ok(Arc::new(store))
+        }
+        other => Err(anyhow!(
+            "unknown metadata store {other:?}; expected redis, postgres or sqlite"
+        )),
+    }
+}
+
+fn resolve_dir_path(root: Option<&Path>, env_key: &str, default_relative: &str) -> PathBuf {
+    if let Some(value) = read_trimmed_env(env_key) {
+        let candidate = PathBuf::from(&value);
+        if candidate.is_absolute() || root.is_none() {
+            return candidate;
+        }
+        if let Some(root) = root {
+            return root.join(candidate);
+        }
+    }
+    if let Some(root) = root {
+        if default_relative.is_empty() {
+            return root.to_path_buf();
+        }
+        return root.join(default_relative);
+    }
+    PathBuf::from(default_relative)
+}
+
+/// Where an instance keeps its files.
+#[derive(Clone, Debug)]
+pub struct StateDirs {
+    /// Static assets served under `/`.
+    pub static_dir: PathBuf,
+    /// File bodies, for the local file store.
+    pub upload_dir: PathBuf,
+    /// Legacy JSON metadata, the SQLite database and the transparency log.
+    pub data_dir: PathBuf,
+    /// Parts of chunked and tus uploads in progress.
+    pub chunk_dir: PathBuf,
+}
+
+impl StateDirs {
+    /// `JUICEBOX_PUBLIC_DIR`, and `JUICEBOX_DATA_DIR`, `JUICEBOX_UPLOAD_DIR`
+    /// and `JUICEBOX_CHUNK_DIR` relative to `JUICEBOX_STORAGE_ROOT`.
+    pub fn from_env() -> Self {
+        let storage_root = read_trimmed_env("JUICEBOX_STORAGE_ROOT").map(PathBuf::from);
+        let data_dir = resolve_dir_path(storage_root.as_deref(), "JUICEBOX_DATA_DIR", "data");
+        let chunk_dir = read_trimmed_env("JUICEBOX_CHUNK_DIR")
+            .map(|value| {
+                let candidate = PathBuf::from(&value);
+                if candidate.is_absolute() {
+                    data_dir.join(candidate)
+                } else {
+                    data_dir.join("chunks")
+                }
+            })
+            .unwrap_or_else(|| data_dir.join("chunks"));
+        Self {
+            static_dir: resolve_dir_path(None, "JUICEBOX_PUBLIC_DIR", "public"),
+            upload_dir: resolve_dir_path(storage_root.as_deref(), "JUICEBOX_UPLOAD_DIR", "files"),
+            data_dir,
+            chunk_dir,
+        }
+    }
+
+    /// The default layout under `root`: `public`, `files`, `data` and
+    /// `data/chunks`.
+    pub fn under(root: &Path) -> Self {
+        let data_dir = root.join("data");
+        Self {
+            static_dir: root.join("public"),
+            upload_dir: root.join("files"),
+            chunk_dir: data_dir.join("chunks"),
+            data_dir,
+        }
+    }
+
+    /// Create any of the directories that are missing.
+    pub fn create(&self) -> std::io::Result<()> {
+        for dir in [
+            &self.static_dir,
+            &self.upload_dir,
+            &self.data_dir,
+            &self.chunk_dir,
+        ] {
+            std::fs::create_dir_all(dir)?;
+        }
+        Ok(())
+    }
+}
+
+/// Everything an [`AppState`] is assembled from. [`StateParts::new`] fills
+/// in what a bare instance needs and [`StateParts::from_env`] what the
+/// environment configures; change any field before [`StateParts::assemble`].
+pub struct StateParts {
+    pub config: Config,
+    pub production: bool,
+    pub dirs: StateDirs,
+    pub kv: Arc<dyn KvStore>,
+    pub file_store: Arc<dyn FileStore>,
+    pub ip_hash_secret: Vec<u8>,
+    pub signing_keys: SigningKeys,
+    /// Usually loaded or created after assembly; see
+    /// [`AppState::load_or_create_admin_key`].
+    pub admin_key: String,
+    pub owners: HashMap<String, FileMeta>,
+    pub last_meta_mtime: SystemTime,
+    pub reports: Vec<ReportRecord>,
+    pub admin_sessions: HashMap<String, u64>,
+    pub bans: Vec<IpBan>,
+    pub mailgun_api_key: Option<String>,
+    pub mailgun_domain: Option<String>,
+    pub report_email_to: Option<String>,
+    pub report_email_from: Option<String>,
+    pub email_privacy: EmailPrivacy,
+    pub email_tx: Option<mpsc::Sender<ReportRecordEmail>>,
+    /// Glob the Tera templates are loaded from.
+    pub templates: String,
+    pub node_id: String,
+    pub owners_persist_debounce: Duration,
+    pub telemetry: Arc<TelemetryState>,
+    pub trace_sampler: Arc<TraceSampler>,
+    pub hash_blocklist: PathBuf,
+    pub clamd: Option<Clamd>,
+    pub content_scanners: ContentScanners,
+    pub connection_limits: ConnectionLimits,
+    pub drain: Drain,
+    pub reindex: Reindex,
+    pub tombstones: Tombstones,
+    pub storage_limits: StorageLimits,
+    pub accounts: Accounts,
+    pub shadow: Shadow,
+    pub webhooks: WebhookConfig,
+    pub ttl_policy: TtlPolicy,
+    pub flags: FeatureFlags,
+    pub networks: NetworkLists,
+    pub geoip: GeoIp,
+    pub cors: CorsConfig,
+    pub clock: Clock,
+}
+
+impl StateParts {
+    /// An instance over `dirs` and `kv` with bodies kept in the upload
+    /// directory, no metadata yet and every optional feature off.
+    pub fn new(
+        dirs: StateDirs,
+        kv: Arc<dyn KvStore>,
+        ip_hash_secret: Vec<u8>,
+        telemetry: Arc<TelemetryState>,
+    ) -> Self {
+        Self {
+            config: Config::default(),
+            production: false,
+            file_store: Arc::new(LocalFileStore::new(dirs.upload_dir.clone())),
+            hash_blocklist: dirs.data_dir.join("hash_blocklist.txt"),
+            dirs,
+            kv,
+            signing_keys: SigningKeys::single(IP_SECRET_KEY_ID, ip_hash_secret.clone()),
+            ip_hash_secret,
+            admin_key: String::new(),
+            owners: HashMap::new(),
+            last_meta_mtime: SystemTime::UNIX_EPOCH,
+            reports: Vec::new(),
+            admin_sessions: HashMap::new(),
+            bans: Vec::new(),
+            mailgun_api_key: None,
+            mailgun_domain: None,
+            report_email_to: None,
+            report_email_from: None,
+            email_privacy: EmailPrivacy::default(),
+            email_tx: None,
+            templates: assets::TEMPLATE_GLOB.to_string(),
+            node_id: new_id(),
+            owners_persist_debounce: OWNERS_PERSIST_DEBOUNCE,
+            telemetry,
+            trace_sampler: Arc::new(TraceSampler::default()),
+            clamd: None,
+            content_scanners: ContentScanners::default(),
+            connection_limits: ConnectionLimits::default(),
+            drain: Drain::default(),
+            reindex: Reindex::default(),
+            tombstones: Tombstones::new(DEFAULT_TOMBSTONE_GRACE_SECS),
+            storage_limits: StorageLimits::default(),
+            accounts: Accounts::default(),
+            shadow: Shadow::default(),
+            webhooks: WebhookConfig::default(),
+            ttl_policy: TtlPolicy::default(),
+            flags: FeatureFlags::default(),
+            networks: NetworkLists::default(),
+            geoip: GeoIp::default(),
+            cors: CorsConfig::default(),
+            clock: Clock::default(),
+        }
+    }
+
+    /// Resolve the directories, stores, secrets and features from the
+    /// environment, creating the directories on the way. Metadata is not
+    /// loaded; [`open_state`] does that.
+    pub async fn from_env(
+        config: Config,
+        production: bool,
+        telemetry: TelemetryState,
+        trace_sampler: Arc<TraceSampler>,
+    ) -> anyhow::Result<Self> {
+        let dirs = StateDirs::from_env();
+        dirs.create()?;
+        debug!(
+            static_dir = ?dirs.static_dir,
+            upload_dir = ?dirs.upload_dir,
+            data_dir = ?dirs.data_dir,
+            chunk_dir = ?dirs.chunk_dir,
+            "ensured storage directories exist"
+        );
+        let file_store = resolve_file_store(&dirs.upload_dir)?;
+        let kv = resolve_kv_store(&dirs.data_dir).await?;
+        let ip_hash_secret = load_hash_secret_from_env()?;
+        let signing_keys = SigningKeys::from_env(&ip_hash_secret)?;
+
+        let mailgun_api_key = std::env::var("MAILGUN_API_KEY").ok();
+        let mailgun_domain = std::env::var("MAILGUN_DOMAIN").ok();
+        let report_email_to = std::env::var("REPORT_EMAIL_TO").ok();
+        let report_email_from = std::env::var("REPORT_EMAIL_FROM").ok();
+        debug!(
+            mail_configured = mailgun_api_key.is_some()
+                && mailgun_domain.is_some()
+                && report_email_to.is_some()
+                && report_email_from.is_some(),
+            "email notification configuration evaluated"
+        );
+
+        let hash_blocklist = std::env::var("JUICEBOX_HASH_BLOCKLIST")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| dirs.data_dir.join("hash_blocklist.txt"));
+        let clamd = Clamd::from_env()?;
+        if let Some(clamd) = &clamd {
+            info!(
+                address = ?clamd.address(),
+                fail_closed = clamd.is_fail_closed(),
+                "scanning uploads with clamd"
+            );
+        }
+        let ttl_policy =
+            TtlPolicy::from_env(&dirs.data_dir).context("failed to load ttl policy")?;
+        let flags =
+            FeatureFlags::from_env(&dirs.data_dir).context("failed to load feature flags")?;
+        Ok(Self {
+            config,
+            production,
+            signing_keys,
+            mailgun_api_key,
+            mailgun_domain,
+            report_email_to,
+            report_email_from,
+            email_privacy: EmailPrivacy::from_env(),
+            node_id: node_id_from_env(),
+            owners_persist_debounce: resolve_owners_persist_debounce(),
+            trace_sampler,
+            hash_blocklist,
+            clamd,
+            connection_limits: ConnectionLimits::from_env(),
+            drain: Drain::from_env(),
+            reindex: Reindex::from_env(),
+            tombstones: Tombstones::from_env(),
+            storage_limits: StorageLimits::from_env(),
+            accounts: Accounts::from_env(),
+            shadow: Shadow::new(ShadowConfig::from_env()),
+            webhooks: WebhookConfig::from_env(),
+            ttl_policy,
+            flags,
+            networks: NetworkLists::new(NetworkListConfig::from_env()),
+            geoip: GeoIp::from_env()?,
+            cors: CorsConfig::from_env(),
+            file_store,
+            ..Self::new(dirs, kv, ip_hash_secret, Arc::new(telemetry))
+        })
+    }
+
+    /// Open the quarantine, transparency log and templates and put the
+    /// state together. Nothing is read from or written to the stores.
+    pub fn assemble(self) -> anyhow::Result<AppState> {
+        let data_dir = &self.dirs.data_dir;
+        let quarantine = Quarantine::open(data_dir.join("quarantine"), &self.hash_blocklist)
+            .context("failed to open quarantine")?;
+        let transparency = TransparencyLog::open(data_dir.join(TRANSPARENCY_FILE))
+            .context("failed to open transparency log")?;
+        let tera = assets::load_templates(&self.templates).context("failed to load templates")?;
+        Ok(AppState {
+            config: Arc::new(self.config),
+            upload_dir: Arc::new(self.dirs.upload_dir.clone()),
+            static_dir: Arc::new(self.dirs.static_dir.clone()),
+            metadata_path: Arc::new(data_dir.join(OWNERS_FILE)),
+            owners: Arc::new(DashMap::from_iter(self.owners)),
+            upload_sem: Arc::new(Semaphore::new(UPLOAD_CONCURRENCY)),
+            production: self.production,
+            last_meta_mtime: Arc::new(RwLock::new(self.last_meta_mtime)),
+            reports_path: Arc::new(data_dir.join(REPORTS_FILE)),
+            reports: Arc::new(RwLock::new(self.reports)),
+            admin_sessions_path: Arc::new(data_dir.join(ADMIN_SESSIONS_FILE)),
+            admin_sessions: Arc::new(RwLock::new(self.admin_sessions)),
+            admin_key_path: Arc::new(data_dir.join(ADMIN_KEY_FILE)),
+            admin_key: Arc::new(RwLock::new(self.admin_key)),
+            bans_path: Arc::new(data_dir.join(BANS_FILE)),
+            bans: Arc::new(RwLock::new(self.bans)),
+            ban_hits: Arc::new(BanHitCounters::new()),
+            upload_stats: Arc::new(UploadStats::new()),
+            audit_log: Arc::new(AuditLog::new()),
+            mailgun_api_key: self.mailgun_api_key,
+            mailgun_domain: self.mailgun_domain,
+            report_email_to: self.report_email_to,
+            report_email_from: self.report_email_from,
+            email_privacy: self.email_privacy,
+            email_tx: self.email_tx,
+            tera: Arc::new(tera),
+            chunk_dir: Arc::new(self.dirs.chunk_dir),
+            chunk_sessions: Arc::new(DashMap::new()),
+            node_id: self.node_id.into(),
+            ip_hash_secret: Arc::new(self.ip_hash_secret),
+            signing_keys: Arc::new(self.signing_keys),
+            owners_persist_lock: Arc::new(tokio::sync::Mutex::new(())),
+            owners_persister: Arc::new(OwnersPersister::new(self.owners_persist_debounce)),
+            owners_index: Arc::new(OwnersIndex::default()),
+            telemetry: self.telemetry,
+            kv: self.kv,
+            file_store: self.file_store,
+            transparency: Arc::new(transparency),
+            queued_uploads: Arc::new(DashMap::new()),
+            analytics: Arc::new(RequestAnalytics::default()),
+            quarantine: Arc::new(quarantine),
+            clamd: self.clamd.map(Arc::new),
+            content_scanners: Arc::new(self.content_scanners),
+            started_at: now_secs(),
+            public_stats: Arc::new(PublicStatsCache::default()),
+            connections: Arc::new(ConnectionTracker::new(self.connection_limits)),
+            drain: Arc::new(self.drain),
+            reindex: Arc::new(self.reindex),
+            tombstones: Arc::new(self.tombstones),
+            storage: Arc::new(StorageWatchdog::new(self.storage_limits)),
+            api_tokens: Arc::new(ApiTokens::default()),
+            accounts: Arc::new(self.accounts),
+            shadow: Arc::new(self.shadow),
+            webhooks: Arc::new(Webhooks::new(self.webhooks)),
+            owner_events: Arc::new(OwnerEvents::new()),
+            ttl_policy: Arc::new(self.ttl_policy),
+            flags: Arc::new(self.flags),
+            trace_sampler: self.trace_sampler,
+            networks: Arc::new(self.networks),
+            geoip: Arc::new(self.geoip),
+            cors: Arc::new(self.cors),
+            link_status_limiter: build_link_status_limiter(),
+            visitor_debug_limiter: build_visitor_debug_limiter(),
+            clock: Arc::new(self.clock),
+        })
+    }
+}
+
+/// Open the metadata store and file store, migrating legacy metadata on the
+/// way, and load everything a command needs into an [`AppState`].
+pub async fn open_state(
+    config: Config,
+    production: bool,
+    telemetry: TelemetryState,
+    trace_sampler: Arc<TraceSampler>,
+) -> anyhow::Result<AppState> {
+    let mut parts = StateParts::from_env(config, production, telemetry, trace_sampler).await?;
+    let data_dir = parts.dirs.data_dir.clone();
+    let metadata_path = data_dir.join(OWNERS_FILE);
+    let bans_path = data_dir.join(BANS_FILE);
+    // ensure bans file presence
+    let _ = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&bans_path)
+        .await;
+
+    let kv = parts.kv.clone();
+    let (owners_map, owners_migrated) = migrate::load_owners_with_migration(
+        &metadata_path,
+        &parts.ip_hash_secret,
+        kv.as_ref(),
+        now_secs(),
+    )
+    .await?;
+    let (reports_vec, reports_migrated) = migrate::load_reports_with_migration(
+        &data_dir.join(REPORTS_FILE),
+        &parts.ip_hash_secret,
+        kv.as_ref(),
+    )
+    .await?;
+    let (admin_sessions_map, admin_sessions_migrated) =
+        migrate::load_admin_sessions_with_migration(
+            &data_dir.join(ADMIN_SESSIONS_FILE),
+            kv.as_ref(),
+        )
+        .await?;
+    let (bans_vec, bans_migrated) =
+        migrate::load_bans_with_migration(&bans_path, &parts.ip_hash_secret, kv.as_ref()).await?;
+    info!(
+        owners = owners_map.len(),
+        migrated = owners_migrated,
+        "loaded owner metadata"
+    );
+    info!(
+        reports = reports_vec.len(),
+        migrated = reports_migrated,
+        "loaded reports metadata"
+    );
+    info!(
+        bans = bans_vec.len(),
+        migrated = bans_migrated,
+        "loaded ban metadata"
+    );
+    info!(
+        admin_sessions = admin_sessions_map.len(),
+        migrated = admin_sessions_migrated,
+        "loaded admin sessions"
+    );
+
+    let initial_mtime = fs::metadata(&metadata_path)
+        .await
+        .ok()
+        .and_then(|m| m.modified().ok())
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    debug!(?initial_mtime, "metadata last modified timestamp loaded");
+
+    parts.owners = owners_map;
+    parts.last_meta_mtime = initial_mtime;
+    parts.reports = reports_vec;
+    parts.admin_sessions = admin_sessions_map;
+    parts.bans = bans_vec;
+    let state = parts.assemble()?;
+
+    let storage_backend = state.kv.backend_name();
+    let file_store_backend = state.file_store.backend_name();
+    sentry::configure_scope(|scope| {
+        scope.set_tag("storage_backend", storage_backend);
+        scope.set_tag("file_store", file_store_backend);
+    });
+
+    if owners_migrated {
+        state.flush_owners().await;
+    }
+    if reports_migrated {
+        state.persist_reports().await;
+    }
+    if bans_migrated {
+        state.persist_bans().await;
+    }
+    if admin_sessions_migrated {
+        state.persist_admin_sessions().await;
+    }
+
+    if let Err(err) = state.load_quarantine().await {
+        warn!(?err, "failed to load quarantine records");
+    }
+    if let Err(err) = state.load_tombstones().await {
+        warn!(?err, "failed to load tombstones");
+    }
+    if let Err(err) = state.load_ban_hits().await {
+        warn!(?err, "failed to load ban hit counters");
+    }
+    if let Err(err) = state.load_upload_stats().await {
+        warn!(?err, "failed to load upload stats");
+    }
+    if let Err(err) = state.load_audit_log().await {
+        warn!(?err, "failed to load audit log");
+    }
+    if let Err(err) = state.load_api_tokens().await {
+        warn!(?err, "failed to load api tokens");
+    }
+    if let Err(err) = state.load_accounts().await {
+        warn!(?err, "failed to load accounts");
+    }
+    if let Err(err) = state.load_feature_flags().await {
+        warn!(?err, "failed to load feature flag overrides");
+    }
+
+    if let Err(err) = state.load_chunk_sessions_from_disk().await {
+        warn!(?err, "failed to restore chunk upload sessions from disk");
+    }
+    Ok(state)
+}
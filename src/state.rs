@@ -1,40 +1,251 @@
+use crate::accounts::Accounts;
+use crate::audit::AuditLog;
+use crate::ban_hits::BanHitCounters;
+use crate::clamav::Clamd;
+use crate::config::Config;
+use crate::connections::ConnectionTracker;
+use crate::content_scan::ContentScanners;
+use crate::cors::CorsConfig;
+use crate::drain::Drain;
+use crate::feature_flags::FeatureFlags;
+use crate::file_store::{FileStore, NameTaken};
+use crate::geoip::{GeoInfo, GeoIp};
+use crate::handlers::EmailPrivacy;
+use crate::handlers::stats::PublicStatsCache;
+use crate::kv_failover::FailoverStatus;
+use crate::network_class::{NetworkClass, NetworkLists};
+use crate::owner_events::OwnerEvents;
+use crate::quarantine::Quarantine;
+use crate::rate_limit::RateLimiterInner;
+use crate::reindex::Reindex;
+use crate::shadow::Shadow;
+use crate::storage_pressure::StorageWatchdog;
+use crate::tombstones::Tombstones;
+use crate::trace_sampling::TraceSampler;
+use crate::transparency::TransparencyLog;
+use crate::ttl_policy::{EffectiveTtl, TtlPolicy};
+use crate::upload_stats::UploadStats;
 use crate::util::{
-    ADMIN_KEY_TTL, ADMIN_SESSION_TTL, IpVersion, MAX_ACTIVE_FILES_PER_IP, hash_ip_addr,
-    hash_ip_string, hash_network_from_cidr, hash_network_from_ip, new_id, now_secs,
+    ADMIN_KEY_TTL, ADMIN_SESSION_TTL, Clock, GUEST_MAX_BYTES, GUEST_TTL_SECS, IpVersion,
+    MAX_ACTIVE_FILES_PER_IP, MAX_GUEST_FILES_PER_OWNER, NameWarning, SigningKeys, ascii_filename,
+    display_original_name, filename_warning, hash_ip_addr, hash_ip_string, hash_network_from_cidr,
+    hash_network_from_ip, make_storage_name, max_file_bytes, new_id, now_secs,
 };
+use crate::webhooks::{WebhookEvent, Webhooks};
 use anyhow::Result;
+use arc_swap::ArcSwap;
 use async_trait::async_trait;
+use axum::body::Bytes;
 use dashmap::DashMap;
 use redis::AsyncCommands;
 use redis::aio::ConnectionManager;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::OnceCell;
 use std::net::IpAddr;
-use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
-use std::{collections::HashMap, path::PathBuf, sync::Arc, time::SystemTime};
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
-use tokio::sync::{Mutex, RwLock, Semaphore};
-use tracing::{debug, error, info, trace, warn};
+use tokio::sync::{Mutex, Notify, RwLock, Semaphore};
+use tokio::task::JoinHandle;
+use tracing::{Instrument, debug, error, info, trace, warn};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct FileMeta {
     #[serde(alias = "owner")]
     pub owner_hash: String,
     pub expires: u64,
+    /// Filename exactly as the client sent it.
     #[serde(default)]
     pub original: String,
+    /// Normalised, length-capped form of `original` for rendering.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub original_display: String,
     #[serde(default = "now_secs")]
     pub created: u64,
     pub hash: String,
+    /// Downloads left before the file is deleted; `None` means unlimited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_downloads: Option<u32>,
+    /// Completed `GET /f/{name}` responses so far.
+    #[serde(default)]
+    pub downloads: u64,
+    /// Only served through presigned links.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub private: bool,
+    /// Original expiry of a file whose TTL was cut under storage pressure.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_shortened_from: Option<u64>,
+    /// Network the upload came from, when known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network_class: Option<NetworkClass>,
+    /// Stored bytes. Entries written before sizes were recorded read as 0
+    /// until the next storage sweep fills them in.
+    #[serde(default)]
+    pub size: u64,
+    /// Counted against the guest allowance instead of the active file limit.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub guest: bool,
+}
+
+impl FileMeta {
+    /// Display-safe original name, derived on the fly for records stored
+    /// before `original_display` existed.
+    pub fn display_name(&self) -> String {
+        if self.original_display.is_empty() && !self.original.is_empty() {
+            display_original_name(&self.original)
+        } else {
+            self.original_display.clone()
+        }
+    }
+
+    /// Why the original name could mislead a moderator, if it could.
+    pub fn name_warning(&self) -> Option<NameWarning> {
+        filename_warning(&self.original)
+    }
+
+    /// Moderator-facing note for a flagged name: the reason and the name's
+    /// ASCII form.
+    pub fn name_warning_note(&self) -> Option<String> {
+        self.name_warning().map(|warning| {
+            format!(
+                "{}; shown as {}",
+                warning.describe(),
+                ascii_filename(&self.display_name())
+            )
+        })
+    }
+}
+/// Which allowance a new file is counted against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlotTier {
+    /// The regular `MAX_ACTIVE_FILES_PER_IP` slots.
+    Standard,
+    /// A few small, short-lived files on top of the regular slots, so an
+    /// owner at their limit can still share a quick screenshot.
+    Guest,
 }
+
+impl SlotTier {
+    pub fn requested(guest: bool) -> Self {
+        if guest { Self::Guest } else { Self::Standard }
+    }
+
+    /// Live files one owner may hold in this tier.
+    pub fn limit(self) -> usize {
+        match self {
+            Self::Standard => MAX_ACTIVE_FILES_PER_IP,
+            Self::Guest => MAX_GUEST_FILES_PER_OWNER,
+        }
+    }
+
+    /// Whether a file of `size` bytes may use this tier.
+    pub fn admits(self, size: u64) -> bool {
+        match self {
+            Self::Standard => size <= max_file_bytes(),
+            Self::Guest => size <= GUEST_MAX_BYTES,
+        }
+    }
+
+    /// The TTL policy for a file in this tier.
+    pub fn apply(self, policy: EffectiveTtl) -> EffectiveTtl {
+        match self {
+            Self::Standard => policy,
+            Self::Guest => policy.capped(GUEST_TTL_SECS),
+        }
+    }
+}
+
+/// Result of [`AppState::record_download`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DownloadClaim {
+    /// The file has no download limit.
+    Unlimited,
+    /// The download was counted; this many remain.
+    Remaining(u32),
+    /// The limit was already used up (or the file is gone).
+    Exhausted,
+}
+
+/// Where a report is in admin review.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportStatus {
+    #[default]
+    Open,
+    Reviewing,
+    Resolved,
+    Rejected,
+}
+
+impl ReportStatus {
+    pub const ALL: [ReportStatus; 4] = [
+        ReportStatus::Open,
+        ReportStatus::Reviewing,
+        ReportStatus::Resolved,
+        ReportStatus::Rejected,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReportStatus::Open => "open",
+            ReportStatus::Reviewing => "reviewing",
+            ReportStatus::Resolved => "resolved",
+            ReportStatus::Rejected => "rejected",
+        }
+    }
+
+    pub fn parse(raw: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|status| status.as_str().eq_ignore_ascii_case(raw.trim()))
+    }
+
+    /// Resolved or rejected; the report needs no further action.
+    pub fn is_closed(self) -> bool {
+        matches!(self, ReportStatus::Resolved | ReportStatus::Rejected)
+    }
+
+    /// Open reports can move anywhere; closed ones can only be reopened.
+    pub fn can_transition_to(self, next: ReportStatus) -> bool {
+        self != next && (!self.is_closed() || next == ReportStatus::Open)
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ReportRecord {
+    /// Stable handle for admin actions. Records stored before ids existed
+    /// get one when loaded.
+    #[serde(default)]
+    pub id: String,
     pub file: String,
     pub reason: String,
     pub details: String,
     #[serde(alias = "ip")]
     pub reporter_hash: String,
     pub time: u64,
+    #[serde(default)]
+    pub status: ReportStatus,
+    /// Admin note recorded with the last status change.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_changed_at: Option<u64>,
+}
+
+/// Why [`AppState::transition_report`] refused a status change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportTransitionError {
+    NotFound,
+    /// Carries the report's current status.
+    NotAllowed(ReportStatus),
 }
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AdminKeyFile {
@@ -52,15 +263,50 @@ pub enum BanSubject {
         prefix: u8,
         version: IpVersion,
     },
+    /// Every address the GeoIP country database places in `code`, an
+    /// uppercase ISO 3166-1 alpha-2 code.
+    Country {
+        code: String,
+    },
+    /// Every address the GeoIP ASN database places in autonomous system
+    /// `number`.
+    Asn {
+        number: u32,
+    },
 }
 
 impl BanSubject {
-    pub fn key(&self) -> &str {
+    pub fn key(&self) -> Cow<'_, str> {
         match self {
-            BanSubject::Exact { hash } => hash,
-            BanSubject::Network { hash, .. } => hash,
+            BanSubject::Exact { hash } => Cow::Borrowed(hash),
+            BanSubject::Network { hash, .. } => Cow::Borrowed(hash),
+            BanSubject::Country { code } => Cow::Owned(format!("country:{code}")),
+            BanSubject::Asn { number } => Cow::Owned(format!("asn:{number}")),
         }
     }
+
+    /// Parse `country:XX`, `asn:N` or `ASN`, the forms admins type for
+    /// GeoIP bans.
+    pub fn parse_geo(input: &str) -> Option<Self> {
+        let input = input.trim();
+        let lower = input.to_ascii_lowercase();
+        if let Some(code) = lower.strip_prefix("country:") {
+            let code = code.trim();
+            return (code.len() == 2 && code.chars().all(|c| c.is_ascii_alphabetic())).then(|| {
+                BanSubject::Country {
+                    code: code.to_ascii_uppercase(),
+                }
+            });
+        }
+        let number = lower
+            .strip_prefix("asn:")
+            .or_else(|| lower.strip_prefix("as"))?;
+        number
+            .trim()
+            .parse()
+            .ok()
+            .map(|number| BanSubject::Asn { number })
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -70,6 +316,168 @@ pub struct IpBan {
     pub label: Option<String>,
     pub reason: String,
     pub time: u64,
+    /// When a temporary ban lapses; `None` bans for good.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+}
+
+impl IpBan {
+    /// Whether the ban still applies at `now`.
+    pub fn is_active(&self, now: u64) -> bool {
+        self.expires.is_none_or(|expires| now < expires)
+    }
+}
+
+/// What a ban check knows about the address (or raw hash) it was given.
+/// GeoIP is only consulted once a country or ASN ban needs it.
+struct BanProbe<'a> {
+    parsed_ip: Option<IpAddr>,
+    ip_hash: Option<String>,
+    /// The input itself, when it is a hash rather than an address.
+    direct: Option<&'a str>,
+    geo: OnceCell<GeoInfo>,
+}
+
+impl<'a> BanProbe<'a> {
+    fn new(state: &AppState, input: &'a str) -> Self {
+        let parsed_ip = input.parse::<IpAddr>().ok();
+        Self {
+            ip_hash: parsed_ip.as_ref().map(|addr| state.hash_ip_addr(addr).1),
+            direct: parsed_ip.is_none().then_some(input),
+            parsed_ip,
+            geo: OnceCell::new(),
+        }
+    }
+
+    fn geo(&self, state: &AppState) -> Option<&GeoInfo> {
+        let addr = self.parsed_ip?;
+        Some(self.geo.get_or_init(|| state.geoip.lookup(addr)))
+    }
+
+    fn matches(&self, state: &AppState, subject: &BanSubject) -> bool {
+        match subject {
+            BanSubject::Exact { hash } => {
+                self.ip_hash.as_ref() == Some(hash) || self.direct == Some(hash.as_str())
+            }
+            BanSubject::Network {
+                hash,
+                prefix,
+                version,
+            } => {
+                if let Some(addr) = self.parsed_ip.as_ref()
+                    && let Some((net_version, _, candidate)) =
+                        state.hash_network_for_ip(addr, *prefix)
+                    && net_version == *version
+                    && &candidate == hash
+                {
+                    return true;
+                }
+                self.direct == Some(hash.as_str())
+            }
+            BanSubject::Country { code } => self
+                .geo(state)
+                .is_some_and(|geo| geo.country.as_deref() == Some(code.as_str())),
+            BanSubject::Asn { number } => {
+                self.geo(state).is_some_and(|geo| geo.asn == Some(*number))
+            }
+        }
+    }
+}
+
+/// Prefix on API token secrets, so a leaked one is easy to recognise.
+pub const API_TOKEN_PREFIX: &str = "jbx_";
+
+/// A bearer token for scripted uploads. Files uploaded with it belong to
+/// `owner_hash` rather than to the uploader's IP hash, and its requests draw
+/// from their own rate bucket.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ApiToken {
+    pub id: String,
+    #[serde(default)]
+    pub label: Option<String>,
+    pub owner_hash: String,
+    pub created: u64,
+}
+
+/// Live API tokens, keyed by the SHA-256 digest of their secret. The secret
+/// itself is only shown once, when the token is issued.
+#[derive(Default)]
+pub struct ApiTokens {
+    by_digest: DashMap<String, ApiToken>,
+}
+
+impl ApiTokens {
+    fn digest(secret: &str) -> String {
+        use sha2::{Digest, Sha256};
+        Sha256::digest(secret.as_bytes())
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+
+    /// Mint a token, returning its secret alongside the stored record.
+    pub fn issue(&self, label: Option<String>) -> (String, ApiToken) {
+        use rand::Rng;
+        let bytes: [u8; 32] = rand::thread_rng().r#gen();
+        let secret: String = std::iter::once(API_TOKEN_PREFIX.to_string())
+            .chain(bytes.iter().map(|b| format!("{b:02x}")))
+            .collect();
+        let id = new_id();
+        let token = ApiToken {
+            owner_hash: format!("token:{id}"),
+            id,
+            label,
+            created: now_secs(),
+        };
+        self.by_digest.insert(Self::digest(&secret), token.clone());
+        (secret, token)
+    }
+
+    /// The token a bearer secret belongs to, if it is still live.
+    pub fn authenticate(&self, secret: &str) -> Option<ApiToken> {
+        if !secret.starts_with(API_TOKEN_PREFIX) {
+            return None;
+        }
+        self.by_digest
+            .get(&Self::digest(secret))
+            .map(|entry| entry.value().clone())
+    }
+
+    /// The live token with this id.
+    pub fn get(&self, id: &str) -> Option<ApiToken> {
+        self.by_digest
+            .iter()
+            .find(|entry| entry.value().id == id)
+            .map(|entry| entry.value().clone())
+    }
+
+    pub fn revoke(&self, id: &str) -> Option<ApiToken> {
+        let digest = self
+            .by_digest
+            .iter()
+            .find(|entry| entry.value().id == id)
+            .map(|entry| entry.key().clone())?;
+        self.by_digest.remove(&digest).map(|(_, token)| token)
+    }
+
+    /// All tokens, oldest first.
+    pub fn list(&self) -> Vec<ApiToken> {
+        let mut tokens: Vec<ApiToken> = self
+            .by_digest
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+        tokens.sort_by(|a, b| a.created.cmp(&b.created).then(a.id.cmp(&b.id)));
+        tokens
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_digest.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_digest.is_empty()
+    }
 }
 
 #[async_trait]
@@ -78,6 +486,17 @@ pub trait KvStore: Send + Sync {
     async fn load_hash(&self, key: &str) -> Result<Vec<(String, String)>>;
     async fn replace_list(&self, key: &str, values: &[String]) -> Result<()>;
     async fn load_list(&self, key: &str) -> Result<Vec<String>>;
+    /// Add `value` to the end of the list at `key` without rewriting it.
+    async fn append_list(&self, key: &str, value: &str) -> Result<()>;
+    /// Short name of the backend, reported by `/api/version`.
+    fn backend_name(&self) -> &'static str;
+    /// Primary and fallback health for a store that fails over, for
+    /// `/readyz`; `None` for a single backend.
+    fn failover_status(&self) -> Option<FailoverStatus> {
+        None
+    }
+    /// Send writes held during a failover back to the primary.
+    async fn replay_pending(&self) {}
 }
 
 pub struct RedisStore {
@@ -97,6 +516,10 @@ impl RedisStore {
 
 #[async_trait]
 impl KvStore for RedisStore {
+    fn backend_name(&self) -> &'static str {
+        "redis"
+    }
+
     async fn replace_hash(&self, key: &str, entries: &[(String, String)]) -> Result<()> {
         let redis_key = self.key(key);
         let mut conn = self.manager.lock().await;
@@ -146,6 +569,17 @@ impl KvStore for RedisStore {
         let entries: Vec<String> = conn.lrange(&redis_key, 0, -1).await?;
         Ok(entries)
     }
+
+    async fn append_list(&self, key: &str, value: &str) -> Result<()> {
+        let redis_key = self.key(key);
+        let mut conn = self.manager.lock().await;
+        redis::cmd("RPUSH")
+            .arg(&redis_key)
+            .arg(value)
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        Ok(())
+    }
 }
 
 #[derive(Default)]
@@ -171,6 +605,10 @@ impl MemoryStore {
 
 #[async_trait]
 impl KvStore for MemoryStore {
+    fn backend_name(&self) -> &'static str {
+        "memory"
+    }
+
     async fn replace_hash(&self, key: &str, entries: &[(String, String)]) -> Result<()> {
         let redis_key = self.key(key);
         let mut hashes = self.hashes.lock().await;
@@ -204,6 +642,308 @@ impl KvStore for MemoryStore {
         let lists = self.lists.lock().await;
         Ok(lists.get(&redis_key).cloned().unwrap_or_default())
     }
+
+    async fn append_list(&self, key: &str, value: &str) -> Result<()> {
+        let redis_key = self.key(key);
+        let mut lists = self.lists.lock().await;
+        lists.entry(redis_key).or_default().push(value.to_string());
+        Ok(())
+    }
+}
+
+/// Metadata kept in a single SQLite file, for installs without Redis.
+/// Hashes and lists live in two tables keyed by store key; every replace
+/// runs in one transaction, so a crash never leaves a half-written set.
+pub struct SqliteStore {
+    conn: Arc<std::sync::Mutex<rusqlite::Connection>>,
+}
+
+const SQLITE_SCHEMA_VERSION: i64 = 1;
+
+impl SqliteStore {
+    /// Open (or create) the database at `path` and bring its schema up to date.
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let conn = rusqlite::Connection::open(path.as_ref())?;
+        Self::init(conn)
+    }
+
+    /// A private in-memory database, mostly useful for tests.
+    pub fn in_memory() -> Result<Self> {
+        Self::init(rusqlite::Connection::open_in_memory()?)
+    }
+
+    fn init(conn: rusqlite::Connection) -> Result<Self> {
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version < 1 {
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS kv_hash (
+                     key TEXT NOT NULL,
+                     field TEXT NOT NULL,
+                     value TEXT NOT NULL,
+                     PRIMARY KEY (key, field)
+                 );
+                 CREATE TABLE IF NOT EXISTS kv_list (
+                     key TEXT NOT NULL,
+                     position INTEGER NOT NULL,
+                     value TEXT NOT NULL,
+                     PRIMARY KEY (key, position)
+                 );",
+            )?;
+        }
+        if version < SQLITE_SCHEMA_VERSION {
+            conn.pragma_update(None, "user_version", SQLITE_SCHEMA_VERSION)?;
+            debug!(
+                from = version,
+                to = SQLITE_SCHEMA_VERSION,
+                "migrated sqlite schema"
+            );
+        }
+        Ok(Self {
+            conn: Arc::new(std::sync::Mutex::new(conn)),
+        })
+    }
+
+    async fn with_conn<T, F>(&self, op: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut rusqlite::Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut guard = conn
+                .lock()
+                .map_err(|_| anyhow::anyhow!("sqlite connection poisoned"))?;
+            Ok(op(&mut guard)?)
+        })
+        .await?
+    }
+}
+
+#[async_trait]
+impl KvStore for SqliteStore {
+    fn backend_name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    async fn replace_hash(&self, key: &str, entries: &[(String, String)]) -> Result<()> {
+        let key = key.to_string();
+        let entries = entries.to_vec();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM kv_hash WHERE key = ?1", [&key])?;
+            {
+                let mut insert = tx.prepare(
+                    "INSERT OR REPLACE INTO kv_hash (key, field, value) VALUES (?1, ?2, ?3)",
+                )?;
+                for (field, value) in &entries {
+                    insert.execute((&key, field, value))?;
+                }
+            }
+            tx.commit()
+        })
+        .await
+    }
+
+    async fn load_hash(&self, key: &str) -> Result<Vec<(String, String)>> {
+        let key = key.to_string();
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare("SELECT field, value FROM kv_hash WHERE key = ?1")?;
+            let rows = stmt.query_map([&key], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect()
+        })
+        .await
+    }
+
+    async fn replace_list(&self, key: &str, values: &[String]) -> Result<()> {
+        let key = key.to_string();
+        let values = values.to_vec();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM kv_list WHERE key = ?1", [&key])?;
+            {
+                let mut insert =
+                    tx.prepare("INSERT INTO kv_list (key, position, value) VALUES (?1, ?2, ?3)")?;
+                for (position, value) in values.iter().enumerate() {
+                    insert.execute((&key, position as i64, value))?;
+                }
+            }
+            tx.commit()
+        })
+        .await
+    }
+
+    async fn load_list(&self, key: &str) -> Result<Vec<String>> {
+        let key = key.to_string();
+        self.with_conn(move |conn| {
+            let mut stmt =
+                conn.prepare("SELECT value FROM kv_list WHERE key = ?1 ORDER BY position")?;
+            let rows = stmt.query_map([&key], |row| row.get(0))?;
+            rows.collect()
+        })
+        .await
+    }
+
+    async fn append_list(&self, key: &str, value: &str) -> Result<()> {
+        let key = key.to_string();
+        let value = value.to_string();
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO kv_list (key, position, value)
+                 SELECT ?1, COALESCE(MAX(position) + 1, 0), ?2 FROM kv_list WHERE key = ?1",
+                (&key, &value),
+            )?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// Metadata shared between replicas through Postgres. Connections come from a
+/// pool, and every replace is a single transaction holding an advisory lock
+/// on the key: only changed hash fields are written and removed ones deleted,
+/// so concurrent writers never observe a half-applied set.
+pub struct PostgresStore {
+    prefix: String,
+    pool: deadpool_postgres::Pool,
+}
+
+impl PostgresStore {
+    /// Build a pool of up to `pool_size` connections to `url` and create the
+    /// tables on first use.
+    pub async fn connect(url: &str, prefix: String, pool_size: usize) -> Result<Self> {
+        let config: tokio_postgres::Config = url.parse()?;
+        let manager = deadpool_postgres::Manager::from_config(
+            config,
+            tokio_postgres::NoTls,
+            deadpool_postgres::ManagerConfig {
+                recycling_method: deadpool_postgres::RecyclingMethod::Fast,
+            },
+        );
+        let pool = deadpool_postgres::Pool::builder(manager)
+            .max_size(pool_size.max(1))
+            .build()?;
+        let client = pool.get().await?;
+        client
+            .batch_execute(
+                "CREATE TABLE IF NOT EXISTS juicebox_kv_hash (
+                     key TEXT NOT NULL,
+                     field TEXT NOT NULL,
+                     value TEXT NOT NULL,
+                     PRIMARY KEY (key, field)
+                 );
+                 CREATE TABLE IF NOT EXISTS juicebox_kv_list (
+                     key TEXT NOT NULL,
+                     position BIGINT NOT NULL,
+                     value TEXT NOT NULL,
+                     PRIMARY KEY (key, position)
+                 );",
+            )
+            .await?;
+        Ok(Self { prefix, pool })
+    }
+
+    fn key(&self, suffix: &str) -> String {
+        format!("{}:{suffix}", self.prefix)
+    }
+}
+
+#[async_trait]
+impl KvStore for PostgresStore {
+    fn backend_name(&self) -> &'static str {
+        "postgres"
+    }
+
+    async fn replace_hash(&self, key: &str, entries: &[(String, String)]) -> Result<()> {
+        let pg_key = self.key(key);
+        let (fields, values): (Vec<&str>, Vec<&str>) = entries
+            .iter()
+            .map(|(field, value)| (field.as_str(), value.as_str()))
+            .unzip();
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        tx.execute("SELECT pg_advisory_xact_lock(hashtext($1))", &[&pg_key])
+            .await?;
+        let removed = tx
+            .execute(
+                "DELETE FROM juicebox_kv_hash WHERE key = $1 AND NOT (field = ANY($2))",
+                &[&pg_key, &fields],
+            )
+            .await?;
+        let written = tx
+            .execute(
+                "INSERT INTO juicebox_kv_hash (key, field, value)
+                 SELECT $1, f, v FROM unnest($2::text[], $3::text[]) AS t(f, v)
+                 ON CONFLICT (key, field) DO UPDATE SET value = EXCLUDED.value
+                 WHERE juicebox_kv_hash.value IS DISTINCT FROM EXCLUDED.value",
+                &[&pg_key, &fields, &values],
+            )
+            .await?;
+        tx.commit().await?;
+        trace!(key = %pg_key, written, removed, "replaced postgres hash");
+        Ok(())
+    }
+
+    async fn load_hash(&self, key: &str) -> Result<Vec<(String, String)>> {
+        let pg_key = self.key(key);
+        let client = self.pool.get().await?;
+        let rows = client
+            .query(
+                "SELECT field, value FROM juicebox_kv_hash WHERE key = $1",
+                &[&pg_key],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+    }
+
+    async fn replace_list(&self, key: &str, values: &[String]) -> Result<()> {
+        let pg_key = self.key(key);
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        tx.execute("SELECT pg_advisory_xact_lock(hashtext($1))", &[&pg_key])
+            .await?;
+        tx.execute("DELETE FROM juicebox_kv_list WHERE key = $1", &[&pg_key])
+            .await?;
+        tx.execute(
+            "INSERT INTO juicebox_kv_list (key, position, value)
+             SELECT $1, t.position, t.value
+             FROM unnest($2::text[]) WITH ORDINALITY AS t(value, position)",
+            &[&pg_key, &values],
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn load_list(&self, key: &str) -> Result<Vec<String>> {
+        let pg_key = self.key(key);
+        let client = self.pool.get().await?;
+        let rows = client
+            .query(
+                "SELECT value FROM juicebox_kv_list WHERE key = $1 ORDER BY position",
+                &[&pg_key],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    async fn append_list(&self, key: &str, value: &str) -> Result<()> {
+        let pg_key = self.key(key);
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        tx.execute("SELECT pg_advisory_xact_lock(hashtext($1))", &[&pg_key])
+            .await?;
+        tx.execute(
+            "INSERT INTO juicebox_kv_list (key, position, value)
+             SELECT $1, COALESCE(MAX(position) + 1, 1), $2
+             FROM juicebox_kv_list WHERE key = $1",
+            &[&pg_key, &value],
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -215,6 +955,19 @@ pub struct TelemetryState {
     pub profiles_sample_rate: f32,
     pub error_sample_rate: f32,
     pub trace_propagation_targets: Vec<String>,
+    /// Honour `Sec-GPC` / `DNT` request headers by skipping tracing and
+    /// analytics for those requests.
+    pub respect_privacy_signals: bool,
+    /// Request paths never traced; a trailing `*` matches by prefix.
+    pub ignored_routes: Vec<String>,
+}
+
+/// Aggregate request counters. Requests carrying an honoured privacy signal
+/// are never counted.
+#[derive(Debug, Default)]
+pub struct RequestAnalytics {
+    pub requests: AtomicU64,
+    pub uploads: AtomicU64,
 }
 
 impl TelemetryState {
@@ -242,6 +995,56 @@ impl TelemetryState {
     }
 }
 
+#[cfg(test)]
+mod chunk_lifecycle_tests {
+    use super::{ChunkLifecycle, ChunkPhase};
+
+    #[test]
+    fn happy_path_transitions() {
+        let lifecycle = ChunkLifecycle::new(ChunkPhase::Init);
+        for next in [
+            ChunkPhase::Receiving,
+            ChunkPhase::Receiving,
+            ChunkPhase::Assembling,
+            ChunkPhase::Verifying,
+            ChunkPhase::Finalized,
+        ] {
+            lifecycle.transition(next).unwrap();
+        }
+        assert_eq!(lifecycle.get(), ChunkPhase::Finalized);
+        assert!(lifecycle.transition(ChunkPhase::Failed).is_err());
+    }
+
+    #[test]
+    fn second_assembly_and_post_cancel_completion_rejected() {
+        let lifecycle = ChunkLifecycle::new(ChunkPhase::Receiving);
+        lifecycle.transition(ChunkPhase::Assembling).unwrap();
+        let err = lifecycle.transition(ChunkPhase::Assembling).unwrap_err();
+        assert_eq!(err.from, ChunkPhase::Assembling);
+
+        lifecycle.transition(ChunkPhase::Failed).unwrap();
+        assert!(lifecycle.transition(ChunkPhase::Verifying).is_err());
+        assert!(lifecycle.transition(ChunkPhase::Receiving).is_err());
+        assert!(lifecycle.reopen().is_err());
+    }
+
+    #[test]
+    fn only_reopen_returns_assembly_to_receiving() {
+        let lifecycle = ChunkLifecycle::new(ChunkPhase::Receiving);
+        lifecycle.transition(ChunkPhase::Assembling).unwrap();
+        assert!(lifecycle.transition(ChunkPhase::Receiving).is_err());
+        lifecycle.reopen().unwrap();
+        assert_eq!(lifecycle.get(), ChunkPhase::Receiving);
+    }
+
+    #[test]
+    fn init_cannot_skip_to_assembly() {
+        let lifecycle = ChunkLifecycle::new(ChunkPhase::Init);
+        assert!(lifecycle.transition(ChunkPhase::Assembling).is_err());
+        assert_eq!(lifecycle.get(), ChunkPhase::Init);
+    }
+}
+
 #[cfg(test)]
 mod telemetry_tests {
     use super::TelemetryState;
@@ -256,6 +1059,8 @@ mod telemetry_tests {
             profiles_sample_rate: 1.0,
             error_sample_rate: 1.0,
             trace_propagation_targets: Vec::new(),
+            respect_privacy_signals: true,
+            ignored_routes: Vec::new(),
         };
         assert_eq!(
             state.sentry_connect_origin().as_deref(),
@@ -273,6 +1078,8 @@ mod telemetry_tests {
             profiles_sample_rate: 0.0,
             error_sample_rate: 0.0,
             trace_propagation_targets: Vec::new(),
+            respect_privacy_signals: true,
+            ignored_routes: Vec::new(),
         };
         assert!(state.sentry_connect_origin().is_none());
     }
@@ -287,6 +1094,8 @@ mod telemetry_tests {
             profiles_sample_rate: 0.0,
             error_sample_rate: 0.0,
             trace_propagation_targets: Vec::new(),
+            respect_privacy_signals: true,
+            ignored_routes: Vec::new(),
         };
         assert_eq!(
             state.sentry_connect_origin().as_deref(),
@@ -295,6 +1104,147 @@ mod telemetry_tests {
     }
 }
 
+/// Where a chunked upload is written while it is assembled, next to its final
+/// name in the upload directory.
+pub fn assembly_temp_path(upload_dir: &std::path::Path, storage_name: &str) -> PathBuf {
+    let mut path = upload_dir.join(storage_name);
+    path.set_extension("part");
+    path
+}
+
+/// Lifecycle of a chunked upload session. Terminal phases never change again;
+/// every other move goes through [`ChunkLifecycle::transition`].
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChunkPhase {
+    Init,
+    Receiving,
+    Assembling,
+    Verifying,
+    Finalized,
+    Failed,
+}
+
+impl ChunkPhase {
+    const ALL: [ChunkPhase; 6] = [
+        ChunkPhase::Init,
+        ChunkPhase::Receiving,
+        ChunkPhase::Assembling,
+        ChunkPhase::Verifying,
+        ChunkPhase::Finalized,
+        ChunkPhase::Failed,
+    ];
+
+    pub fn is_terminal(self) -> bool {
+        matches!(self, ChunkPhase::Finalized | ChunkPhase::Failed)
+    }
+
+    /// Allowed moves; anything may fail unless already terminal. Returning an
+    /// assembling session to `Receiving` is deliberately absent so that a part
+    /// upload can never do it; see [`ChunkLifecycle::reopen`].
+    pub fn can_transition_to(self, next: ChunkPhase) -> bool {
+        use ChunkPhase::*;
+        match (self, next) {
+            (Finalized | Failed, _) => false,
+            (_, Failed) => true,
+            (Init | Receiving, Receiving) => true,
+            (Receiving, Assembling) => true,
+            (Assembling, Verifying) => true,
+            (Verifying, Finalized) => true,
+            _ => false,
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        self as u8
+    }
+
+    fn from_u8(value: u8) -> Self {
+        Self::ALL
+            .get(value as usize)
+            .copied()
+            .unwrap_or(ChunkPhase::Failed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidTransition {
+    pub from: ChunkPhase,
+    pub to: ChunkPhase,
+}
+
+impl std::fmt::Display for InvalidTransition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid chunk session transition {:?} -> {:?}",
+            self.from, self.to
+        )
+    }
+}
+
+impl std::error::Error for InvalidTransition {}
+
+/// Atomic holder for a session's [`ChunkPhase`]; transitions are validated and
+/// applied with compare-and-swap so concurrent completes or a cancel racing an
+/// assembly cannot both win.
+#[derive(Debug)]
+pub struct ChunkLifecycle(AtomicU8);
+
+impl ChunkLifecycle {
+    pub fn new(phase: ChunkPhase) -> Self {
+        Self(AtomicU8::new(phase.as_u8()))
+    }
+
+    pub fn get(&self) -> ChunkPhase {
+        ChunkPhase::from_u8(self.0.load(Ordering::Acquire))
+    }
+
+    /// Move to `next`, returning the phase it left.
+    pub fn transition(&self, next: ChunkPhase) -> Result<ChunkPhase, InvalidTransition> {
+        let mut current = self.0.load(Ordering::Acquire);
+        loop {
+            let from = ChunkPhase::from_u8(current);
+            if !from.can_transition_to(next) {
+                return Err(InvalidTransition { from, to: next });
+            }
+            match self.0.compare_exchange(
+                current,
+                next.as_u8(),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return Ok(from),
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    /// Hand an assembling session back for another completion attempt after
+    /// a recoverable failure. Fails if a cancel got there first.
+    pub fn reopen(&self) -> Result<(), InvalidTransition> {
+        self.0
+            .compare_exchange(
+                ChunkPhase::Assembling.as_u8(),
+                ChunkPhase::Receiving.as_u8(),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .map(|_| ())
+            .map_err(|actual| InvalidTransition {
+                from: ChunkPhase::from_u8(actual),
+                to: ChunkPhase::Receiving,
+            })
+    }
+}
+
+/// How long a chunk session may go without a request before cleanup drops
+/// it.
+pub const CHUNK_SESSION_IDLE_SECS: u64 = 30 * 60;
+/// How long a chunk directory no session uses is left alone before cleanup
+/// removes it.
+pub const ORPHAN_CHUNK_DIR_GRACE_SECS: u64 = 60 * 60;
+
 #[derive(Debug)]
 pub struct ChunkSession {
     pub owner_hash: String,
@@ -306,13 +1256,28 @@ pub struct ChunkSession {
     pub chunk_size: u64,
     pub total_chunks: u32,
     pub hash: Option<String>,
+    /// Download limit to give the assembled file.
+    pub max_downloads: Option<u32>,
+    /// Whether the assembled file is private.
+    pub private: bool,
     pub storage_dir: Arc<PathBuf>,
     pub created: u64,
     pub received: RwLock<Vec<bool>>,
-    pub completed: AtomicBool,
+    /// Hex SHA-256 each received chunk was checked against, for chunks whose
+    /// `PUT` carried `X-Chunk-SHA256`.
+    pub chunk_hashes: RwLock<Vec<Option<String>>>,
+    pub lifecycle: ChunkLifecycle,
     pub last_update: AtomicU64,
     pub persist_lock: Mutex<()>,
+    /// Held by a tus `PATCH` while it appends, so two requests never write
+    /// at the same offset.
+    pub append_lock: Mutex<()>,
     pub assembled_chunks: AtomicU32,
+    /// `sentry-trace` value of the init request; later requests for this
+    /// session continue that trace.
+    pub trace_parent: Option<String>,
+    /// Node ID of the replica that created the session.
+    pub node: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -327,74 +1292,375 @@ struct ChunkSessionRecord {
     chunk_size: u64,
     total_chunks: u32,
     hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_downloads: Option<u32>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    private: bool,
     created: u64,
     received: Vec<bool>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    chunk_hashes: Vec<Option<String>>,
+    /// Legacy flag from before `phase` existed; still written for readers
+    /// of older versions.
+    #[serde(default)]
     completed: bool,
     last_update: u64,
     #[serde(default)]
-    assembled_chunks: u32,
+    assembled_chunks: u32,
+    #[serde(default)]
+    trace_parent: Option<String>,
+    #[serde(default)]
+    phase: Option<ChunkPhase>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    node: Option<String>,
+}
+
+impl ChunkSession {
+    /// On-disk location of chunk `index` inside the session directory.
+    pub fn chunk_path(&self, index: u32) -> PathBuf {
+        self.storage_dir.join(format!("{index:06}.chunk"))
+    }
+
+    pub fn touch(&self) {
+        self.last_update.store(now_secs(), Ordering::Relaxed);
+    }
+
+    /// When cleanup drops the session: at the file's expiry, or after
+    /// [`CHUNK_SESSION_IDLE_SECS`] without a request.
+    pub fn abandoned_at(&self) -> u64 {
+        self.last_update
+            .load(Ordering::Relaxed)
+            .saturating_add(CHUNK_SESSION_IDLE_SECS)
+            .min(self.expires)
+    }
+
+    pub fn phase(&self) -> ChunkPhase {
+        self.lifecycle.get()
+    }
+
+    pub fn transition(&self, next: ChunkPhase) -> Result<ChunkPhase, InvalidTransition> {
+        let from = self.lifecycle.transition(next)?;
+        trace!(?from, to = ?next, "chunk session transition");
+        self.touch();
+        Ok(from)
+    }
+
+    pub fn mark_completed(&self) -> Result<ChunkPhase, InvalidTransition> {
+        let from = self.transition(ChunkPhase::Finalized)?;
+        self.assembled_chunks
+            .store(self.total_chunks, Ordering::Relaxed);
+        Ok(from)
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.phase() == ChunkPhase::Finalized
+    }
+
+    async fn snapshot(&self) -> ChunkSessionRecord {
+        let received = self.received.read().await.clone();
+        let chunk_hashes = {
+            let hashes = self.chunk_hashes.read().await;
+            if hashes.iter().any(Option::is_some) {
+                hashes.clone()
+            } else {
+                Vec::new()
+            }
+        };
+        ChunkSessionRecord {
+            owner_hash: self.owner_hash.clone(),
+            original_name: self.original_name.clone(),
+            storage_name: self.storage_name.clone(),
+            ttl_code: self.ttl_code.clone(),
+            expires: self.expires,
+            total_bytes: self.total_bytes,
+            chunk_size: self.chunk_size,
+            total_chunks: self.total_chunks,
+            hash: self.hash.clone(),
+            max_downloads: self.max_downloads,
+            private: self.private,
+            created: self.created,
+            received,
+            chunk_hashes,
+            completed: self.is_completed(),
+            last_update: self.last_update.load(Ordering::Relaxed),
+            assembled_chunks: self.assembled_chunks.load(Ordering::Relaxed),
+            trace_parent: self.trace_parent.clone(),
+            phase: Some(self.phase()),
+            node: self.node.clone(),
+        }
+    }
+
+    fn from_record(record: ChunkSessionRecord, dir: PathBuf) -> Self {
+        let phase = match record.phase {
+            // An assembly interrupted by a restart never produced a file; the
+            // chunks are still on disk, so the client can complete again.
+            Some(ChunkPhase::Assembling | ChunkPhase::Verifying) => ChunkPhase::Receiving,
+            Some(phase) => phase,
+            None if record.completed => ChunkPhase::Finalized,
+            None if record.received.iter().any(|r| *r) => ChunkPhase::Receiving,
+            None => ChunkPhase::Init,
+        };
+        let mut chunk_hashes = record.chunk_hashes;
+        chunk_hashes.resize(record.total_chunks as usize, None);
+        Self {
+            owner_hash: record.owner_hash,
+            original_name: record.original_name,
+            storage_name: record.storage_name,
+            ttl_code: record.ttl_code,
+            expires: record.expires,
+            total_bytes: record.total_bytes,
+            chunk_size: record.chunk_size,
+            total_chunks: record.total_chunks,
+            hash: record.hash,
+            max_downloads: record.max_downloads,
+            private: record.private,
+            storage_dir: Arc::new(dir),
+            created: record.created,
+            received: RwLock::new(record.received),
+            chunk_hashes: RwLock::new(chunk_hashes),
+            lifecycle: ChunkLifecycle::new(phase),
+            last_update: AtomicU64::new(record.last_update),
+            persist_lock: Mutex::new(()),
+            append_lock: Mutex::new(()),
+            assembled_chunks: AtomicU32::new(record.assembled_chunks),
+            trace_parent: record.trace_parent,
+            node: record.node,
+        }
+    }
+}
+
+/// Uploads the simple page's service worker queued while offline and has
+/// registered for replay once connectivity returns.
+#[derive(Clone, Debug, Serialize)]
+pub struct QueuedUpload {
+    pub owner_hash: String,
+    pub items: Vec<QueuedUploadItem>,
+    pub created: u64,
+    pub expires: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QueuedUploadItem {
+    pub name: String,
+    pub size: u64,
+}
+
+/// Read-only per-owner and per-hash view of `owners`. List and duplicate
+/// checks read this instead of walking the live map while uploads write to it.
+#[derive(Default)]
+pub struct OwnersSnapshot {
+    by_owner: HashMap<String, Vec<(String, FileMeta)>>,
+    by_hash: HashMap<String, String>,
+}
+
+impl OwnersSnapshot {
+    fn build(owners: &DashMap<String, FileMeta>) -> Self {
+        let mut snapshot = Self::default();
+        for entry in owners.iter() {
+            let (file, meta) = (entry.key(), entry.value());
+            snapshot
+                .by_hash
+                .entry(meta.hash.clone())
+                .or_insert_with(|| file.clone());
+            snapshot
+                .by_owner
+                .entry(meta.owner_hash.clone())
+                .or_default()
+                .push((file.clone(), meta.clone()));
+        }
+        for files in snapshot.by_owner.values_mut() {
+            files.sort_by(|a, b| a.0.cmp(&b.0));
+        }
+        snapshot
+    }
+
+    /// Files held by `owner_hash`, sorted by storage name.
+    pub fn files_for(&self, owner_hash: &str) -> &[(String, FileMeta)] {
+        self.by_owner
+            .get(owner_hash)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Storage name of a file with this content hash, if any.
+    pub fn file_with_hash(&self, hash: &str) -> Option<&str> {
+        self.by_hash.get(hash).map(String::as_str)
+    }
+
+    /// One page of `owner_hash`'s files, sorted and sliced per `query`.
+    pub fn page_for(&self, owner_hash: &str, query: &ListQuery) -> OwnerFilesPage<'_> {
+        let mut files: Vec<&(String, FileMeta)> = self.files_for(owner_hash).iter().collect();
+        let sort = query.sort;
+        files.sort_by(|a, b| sort.key(a).cmp(&sort.key(b)));
+        if query.order == SortOrder::Desc {
+            files.reverse();
+        }
+        let total = files.len();
+        let start = match query.cursor.as_deref().and_then(parse_list_cursor) {
+            Some(after) => files
+                .iter()
+                .position(|entry| match query.order {
+                    SortOrder::Asc => sort.key(entry) > after,
+                    SortOrder::Desc => sort.key(entry) < after,
+                })
+                .unwrap_or(total),
+            None => query.offset.unwrap_or(0).min(total),
+        };
+        let end = match query.limit {
+            Some(limit) => start
+                .saturating_add(limit.clamp(1, MAX_LIST_PAGE))
+                .min(total),
+            None => total,
+        };
+        let next_cursor = (end < total).then(|| files[end - 1]).map(|entry| {
+            let (value, file) = sort.key(entry);
+            format!("{value}-{file}")
+        });
+        OwnerFilesPage {
+            files: files[start..end].to_vec(),
+            total,
+            offset: start,
+            next_offset: (end < total).then_some(end),
+            next_cursor,
+        }
+    }
+}
+
+/// Largest page `/list` hands out in one response.
+pub const MAX_LIST_PAGE: usize = 500;
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ListSort {
+    /// Storage name, the historical order.
+    #[default]
+    Name,
+    Expires,
+    Created,
+}
+
+impl ListSort {
+    /// Total ordering key; ties on the timestamp fall back to the storage name.
+    fn key<'a>(&self, (file, meta): &'a (String, FileMeta)) -> (u64, &'a str) {
+        match self {
+            ListSort::Name => (0, file),
+            ListSort::Expires => (meta.expires, file),
+            ListSort::Created => (meta.created, file),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Paging parameters accepted by `/list` and `/simple`. Without `limit` the
+/// whole list is returned. `cursor` takes precedence over `offset` and stays
+/// stable while files are added or expire between pages.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct ListQuery {
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub sort: ListSort,
+    #[serde(default)]
+    pub order: SortOrder,
 }
 
-impl ChunkSession {
-    pub fn touch(&self) {
-        self.last_update.store(now_secs(), Ordering::Relaxed);
-    }
+/// Cursors are `<sort value>-<storage name>` of the last entry served.
+fn parse_list_cursor(cursor: &str) -> Option<(u64, &str)> {
+    let (value, file) = cursor.split_once('-')?;
+    Some((value.parse().ok()?, file))
+}
 
-    pub fn mark_completed(&self) {
-        self.completed.store(true, Ordering::Relaxed);
-        self.assembled_chunks
-            .store(self.total_chunks, Ordering::Relaxed);
-        self.touch();
-    }
+pub struct OwnerFilesPage<'a> {
+    pub files: Vec<&'a (String, FileMeta)>,
+    pub total: usize,
+    pub offset: usize,
+    pub next_offset: Option<usize>,
+    pub next_cursor: Option<String>,
+}
 
-    pub fn is_completed(&self) -> bool {
-        self.completed.load(Ordering::Relaxed)
+/// Holds the current [`OwnersSnapshot`]. Writers only mark it stale; the next
+/// reader rebuilds it once for the whole batch of mutations.
+pub struct OwnersIndex {
+    current: ArcSwap<OwnersSnapshot>,
+    stale: AtomicBool,
+    rebuild: std::sync::Mutex<()>,
+}
+
+impl OwnersIndex {
+    pub fn invalidate(&self) {
+        self.stale.store(true, Ordering::Release);
     }
+}
 
-    async fn snapshot(&self) -> ChunkSessionRecord {
-        let received = self.received.read().await.clone();
-        ChunkSessionRecord {
-            owner_hash: self.owner_hash.clone(),
-            original_name: self.original_name.clone(),
-            storage_name: self.storage_name.clone(),
-            ttl_code: self.ttl_code.clone(),
-            expires: self.expires,
-            total_bytes: self.total_bytes,
-            chunk_size: self.chunk_size,
-            total_chunks: self.total_chunks,
-            hash: self.hash.clone(),
-            created: self.created,
-            received,
-            completed: self.completed.load(Ordering::Relaxed),
-            last_update: self.last_update.load(Ordering::Relaxed),
-            assembled_chunks: self.assembled_chunks.load(Ordering::Relaxed),
+impl Default for OwnersIndex {
+    fn default() -> Self {
+        Self {
+            current: ArcSwap::from_pointee(OwnersSnapshot::default()),
+            stale: AtomicBool::new(true),
+            rebuild: std::sync::Mutex::new(()),
         }
     }
+}
 
-    fn from_record(record: ChunkSessionRecord, dir: PathBuf) -> Self {
+/// Fresh names an upload tries before giving up on finding a free one.
+pub const STORAGE_NAME_ATTEMPTS: u32 = 8;
+
+/// The body of a new file for [`AppState::store_new_file`].
+pub enum NewFileBody<'a> {
+    Bytes(Bytes),
+    /// A local file, moved into the store.
+    Spooled(&'a Path),
+}
+
+/// Default window over which owners-metadata writes are coalesced.
+pub const OWNERS_PERSIST_DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// Coalesces owners-metadata writes. [`AppState::persist_owners`] only marks the
+/// map dirty while the persister task runs; the task rewrites the store at most
+/// once per debounce window and flushes whatever is pending when it stops.
+pub struct OwnersPersister {
+    dirty: AtomicBool,
+    running: AtomicBool,
+    wake: Notify,
+    debounce: Duration,
+}
+
+impl OwnersPersister {
+    pub fn new(debounce: Duration) -> Self {
         Self {
-            owner_hash: record.owner_hash,
-            original_name: record.original_name,
-            storage_name: record.storage_name,
-            ttl_code: record.ttl_code,
-            expires: record.expires,
-            total_bytes: record.total_bytes,
-            chunk_size: record.chunk_size,
-            total_chunks: record.total_chunks,
-            hash: record.hash,
-            storage_dir: Arc::new(dir),
-            created: record.created,
-            received: RwLock::new(record.received),
-            completed: AtomicBool::new(record.completed),
-            last_update: AtomicU64::new(record.last_update),
-            persist_lock: Mutex::new(()),
-            assembled_chunks: AtomicU32::new(record.assembled_chunks),
+            dirty: AtomicBool::new(false),
+            running: AtomicBool::new(false),
+            wake: Notify::new(),
+            debounce,
         }
     }
+
+    pub fn debounce(&self) -> Duration {
+        self.debounce
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.load(Ordering::Acquire)
+    }
+}
+
+impl Default for OwnersPersister {
+    fn default() -> Self {
+        Self::new(OWNERS_PERSIST_DEBOUNCE)
+    }
 }
 
 #[derive(Clone)]
 pub struct AppState {
+    pub config: Arc<Config>,
     pub upload_dir: Arc<PathBuf>,
     pub static_dir: Arc<PathBuf>,
     pub metadata_path: Arc<PathBuf>,
@@ -410,22 +1676,72 @@ pub struct AppState {
     pub admin_key: Arc<RwLock<String>>,
     pub bans_path: Arc<PathBuf>,
     pub bans: Arc<RwLock<Vec<IpBan>>>,
+    pub ban_hits: Arc<BanHitCounters>,
+    pub upload_stats: Arc<UploadStats>,
+    pub audit_log: Arc<AuditLog>,
     // email notification config
     pub mailgun_api_key: Option<String>,
     pub mailgun_domain: Option<String>,
     pub report_email_to: Option<String>,
     pub report_email_from: Option<String>,
+    pub email_privacy: EmailPrivacy,
     pub email_tx: Option<tokio::sync::mpsc::Sender<crate::handlers::ReportRecordEmail>>, // channel to worker
     pub tera: std::sync::Arc<tera::Tera>,
     pub chunk_dir: Arc<PathBuf>,
     pub chunk_sessions: Arc<DashMap<String, Arc<ChunkSession>>>,
+    /// This replica's name in `X-Juicebox-Node`.
+    pub node_id: Arc<str>,
     pub ip_hash_secret: Arc<Vec<u8>>,
+    /// Signs and checks presigned links and lookup challenges.
+    pub signing_keys: Arc<SigningKeys>,
     pub owners_persist_lock: Arc<Mutex<()>>,
+    pub owners_persister: Arc<OwnersPersister>,
+    pub owners_index: Arc<OwnersIndex>,
     pub telemetry: Arc<TelemetryState>,
     pub kv: Arc<dyn KvStore>,
+    pub file_store: Arc<dyn FileStore>,
+    pub transparency: Arc<TransparencyLog>,
+    pub queued_uploads: Arc<DashMap<String, QueuedUpload>>,
+    pub analytics: Arc<RequestAnalytics>,
+    pub quarantine: Arc<Quarantine>,
+    /// Scans finished uploads; `None` when `JUICEBOX_CLAMD_ADDRESS` is unset.
+    pub clamd: Option<Arc<Clamd>>,
+    /// Checks every upload passes before it is stored.
+    pub content_scanners: Arc<ContentScanners>,
+    pub started_at: u64,
+    pub public_stats: Arc<PublicStatsCache>,
+    pub connections: Arc<ConnectionTracker>,
+    pub drain: Arc<Drain>,
+    /// Admin-triggered backfill of hashes, sizes and creation times.
+    pub reindex: Arc<Reindex>,
+    pub tombstones: Arc<Tombstones>,
+    pub storage: Arc<StorageWatchdog>,
+    pub api_tokens: Arc<ApiTokens>,
+    pub accounts: Arc<Accounts>,
+    pub shadow: Arc<Shadow>,
+    /// Lifecycle events for `JUICEBOX_WEBHOOK_URLS`.
+    pub webhooks: Arc<Webhooks>,
+    /// Notifications for owners connected to `/ws`.
+    pub owner_events: Arc<OwnerEvents>,
+    pub ttl_policy: Arc<TtlPolicy>,
+    pub flags: Arc<FeatureFlags>,
+    pub trace_sampler: Arc<TraceSampler>,
+    pub networks: Arc<NetworkLists>,
+    /// Country and ASN databases for GeoIP bans.
+    pub geoip: Arc<GeoIp>,
+    /// Origins allowed to call uploads and the public API from a browser.
+    pub cors: Arc<CorsConfig>,
+    pub link_status_limiter: RateLimiterInner,
+    pub visitor_debug_limiter: RateLimiterInner,
+    pub clock: Arc<Clock>,
 }
 
 impl AppState {
+    /// Current time in seconds, from [`AppState::clock`].
+    pub fn now_secs(&self) -> u64 {
+        self.clock.now_secs()
+    }
+
     fn ip_hash_secret_bytes(&self) -> &[u8] {
         self.ip_hash_secret.as_ref()
     }
@@ -470,6 +1786,9 @@ impl AppState {
             let (_, hash) = self.hash_ip_addr(&addr);
             return Some(BanSubject::Exact { hash });
         }
+        if let Some(subject) = BanSubject::parse_geo(trimmed) {
+            return Some(subject);
+        }
         Some(BanSubject::Exact {
             hash: trimmed.to_string(),
         })
@@ -482,13 +1801,27 @@ impl AppState {
             .iter()
             .filter(|entry| {
                 let meta = entry.value();
-                meta.owner_hash.as_str() == owner_hash && meta.expires > now
+                meta.owner_hash.as_str() == owner_hash && meta.expires > now && !meta.guest
             })
             .count();
         trace!(owner_hash, count, "active file count computed");
         count
     }
 
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn guest_file_count(&self, owner_hash: &str, now: u64) -> usize {
+        let count = self
+            .owners
+            .iter()
+            .filter(|entry| {
+                let meta = entry.value();
+                meta.owner_hash.as_str() == owner_hash && meta.expires > now && meta.guest
+            })
+            .count();
+        trace!(owner_hash, count, "guest file count computed");
+        count
+    }
+
     #[tracing::instrument(level = "trace", skip(self))]
     pub fn pending_chunk_count(&self, owner_hash: &str) -> usize {
         let count = self
@@ -496,29 +1829,49 @@ impl AppState {
             .iter()
             .filter(|entry| {
                 let session = entry.value();
-                session.owner_hash.as_str() == owner_hash && !session.is_completed()
+                session.owner_hash.as_str() == owner_hash && !session.phase().is_terminal()
             })
             .count();
         trace!(owner_hash, count, "pending chunk count computed");
         count
     }
 
+    /// Slots of `tier` held by live files and, for the standard tier, open
+    /// chunk sessions.
     #[tracing::instrument(level = "debug", skip(self))]
-    pub fn reserved_file_slots(&self, owner_hash: &str, now: u64) -> usize {
-        let reserved =
-            self.active_file_count(owner_hash, now) + self.pending_chunk_count(owner_hash);
-        debug!(owner_hash, reserved, "reserved file slots computed");
+    pub fn reserved_slots_in(&self, owner_hash: &str, tier: SlotTier, now: u64) -> usize {
+        let reserved = match tier {
+            SlotTier::Standard => {
+                self.active_file_count(owner_hash, now) + self.pending_chunk_count(owner_hash)
+            }
+            SlotTier::Guest => self.guest_file_count(owner_hash, now),
+        };
+        debug!(owner_hash, ?tier, reserved, "reserved file slots computed");
         reserved
     }
 
     #[tracing::instrument(level = "debug", skip(self))]
-    pub fn remaining_file_slots(&self, owner_hash: &str, now: u64) -> usize {
-        let remaining =
-            MAX_ACTIVE_FILES_PER_IP.saturating_sub(self.reserved_file_slots(owner_hash, now));
-        debug!(owner_hash, remaining, "remaining file slots computed");
+    pub fn remaining_slots_in(&self, owner_hash: &str, tier: SlotTier, now: u64) -> usize {
+        let remaining = tier
+            .limit()
+            .saturating_sub(self.reserved_slots_in(owner_hash, tier, now));
+        debug!(
+            owner_hash,
+            ?tier,
+            remaining,
+            "remaining file slots computed"
+        );
         remaining
     }
 
+    pub fn reserved_file_slots(&self, owner_hash: &str, now: u64) -> usize {
+        self.reserved_slots_in(owner_hash, SlotTier::Standard, now)
+    }
+
+    pub fn remaining_file_slots(&self, owner_hash: &str, now: u64) -> usize {
+        self.remaining_slots_in(owner_hash, SlotTier::Standard, now)
+    }
+
     #[tracing::instrument(level = "debug", skip(self))]
     async fn persist_owners_inner(&self) {
         let owners: HashMap<String, FileMeta> = self
@@ -546,12 +1899,175 @@ impl AppState {
         );
     }
 
-    #[tracing::instrument(level = "debug", skip(self))]
+    /// Current read-only view of `owners`, rebuilt first if writers have
+    /// changed the map since it was taken.
+    pub fn owners_snapshot(&self) -> Arc<OwnersSnapshot> {
+        let index = &self.owners_index;
+        if index.stale.load(Ordering::Acquire) {
+            let _guard = index
+                .rebuild
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            // Cleared before the rebuild so writes racing with it re-mark it.
+            if index.stale.swap(false, Ordering::AcqRel) {
+                index
+                    .current
+                    .store(Arc::new(OwnersSnapshot::build(&self.owners)));
+                trace!("rebuilt owners snapshot");
+            }
+        }
+        index.current.load_full()
+    }
+
+    pub fn insert_owner(&self, file: String, meta: FileMeta) -> Option<FileMeta> {
+        let previous = self.owners.insert(file, meta);
+        self.owners_index.invalidate();
+        previous
+    }
+
+    pub fn remove_owner(&self, file: &str) -> Option<(String, FileMeta)> {
+        let removed = self.owners.remove(file);
+        if removed.is_some() {
+            self.owners_index.invalidate();
+        }
+        removed
+    }
+
+    /// Whether `name` belongs to a hosted file or an unfinished chunk
+    /// session.
+    pub fn storage_name_reserved(&self, name: &str) -> bool {
+        self.owners.contains_key(name)
+            || self
+                .chunk_sessions
+                .iter()
+                .any(|session| session.storage_name == name)
+    }
+
+    /// `proposed`, or a fresh name with the same extension when a hosted
+    /// file or chunk session already holds it.
+    pub fn unreserved_storage_name(&self, proposed: String) -> Option<String> {
+        let mut name = proposed;
+        for attempt in 1..=STORAGE_NAME_ATTEMPTS {
+            if !self.storage_name_reserved(&name) {
+                return Some(name);
+            }
+            warn!(file = %name, attempt, "storage name already reserved, picking another");
+            name = make_storage_name(Some(&name));
+        }
+        None
+    }
+
+    /// Store `body` as a new file, under `proposed` unless that name is
+    /// already hosted or stored, in which case under a fresh name with the
+    /// same extension. Never replaces another file's body; returns the name
+    /// used.
+    pub async fn store_new_file(&self, proposed: String, body: NewFileBody<'_>) -> Result<String> {
+        let mut name = proposed;
+        for attempt in 1..=STORAGE_NAME_ATTEMPTS {
+            if !self.owners.contains_key(&name) && !self.file_store.exists(&name).await {
+                let stored = match &body {
+                    NewFileBody::Bytes(bytes) => self.file_store.write(&name, bytes.clone()).await,
+                    NewFileBody::Spooled(path) => self.file_store.import(&name, path).await,
+                };
+                match stored {
+                    Ok(()) => return Ok(name),
+                    Err(err) if err.is::<NameTaken>() => {}
+                    Err(err) => return Err(err),
+                }
+            }
+            warn!(file = %name, attempt, "storage name already taken, picking another");
+            name = make_storage_name(Some(&name));
+        }
+        anyhow::bail!("no free storage name after {STORAGE_NAME_ATTEMPTS} attempts")
+    }
+
+    /// Count one download of `file`, and charge it against the download
+    /// limit if the file has one. Both happen under the map's shard lock, so
+    /// concurrent downloads can never claim more than the limit allows.
+    pub fn record_download(&self, file: &str) -> DownloadClaim {
+        let Some(mut meta) = self.owners.get_mut(file) else {
+            return DownloadClaim::Exhausted;
+        };
+        let claim = match meta.max_downloads {
+            None => DownloadClaim::Unlimited,
+            Some(0) => DownloadClaim::Exhausted,
+            Some(left) => {
+                meta.max_downloads = Some(left - 1);
+                DownloadClaim::Remaining(left - 1)
+            }
+        };
+        if claim != DownloadClaim::Exhausted {
+            meta.downloads = meta.downloads.saturating_add(1);
+        }
+        drop(meta);
+        self.owners_index.invalidate();
+        claim
+    }
+
+    /// Schedule a write of the owners metadata. With the persister task
+    /// running this only marks the map dirty; otherwise it writes immediately.
+    #[tracing::instrument(level = "trace", skip(self))]
     pub async fn persist_owners(&self) {
+        self.owners_index.invalidate();
+        let persister = &self.owners_persister;
+        if !persister.running.load(Ordering::Acquire) {
+            self.flush_owners().await;
+            return;
+        }
+        if !persister.dirty.swap(true, Ordering::AcqRel) {
+            trace!("owners metadata marked dirty");
+            persister.wake.notify_one();
+        }
+    }
+
+    /// Write the owners metadata now, clearing any pending debounced write.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn flush_owners(&self) {
         let _guard = self.owners_persist_lock.lock().await;
+        // Cleared before the snapshot so changes made mid-write re-mark it.
+        self.owners_persister.dirty.store(false, Ordering::Release);
         self.persist_owners_inner().await;
     }
 
+    /// Run the debounced owners persister until `shutdown` fires, then flush
+    /// anything still pending.
+    pub fn spawn_owners_persister(&self, shutdown: Arc<Notify>) -> JoinHandle<()> {
+        let state = self.clone();
+        let persister = self.owners_persister.clone();
+        persister.running.store(true, Ordering::Release);
+        tokio::spawn(
+            async move {
+                // Registered up front so a shutdown that lands mid-flush is
+                // not missed by `notify_waiters`.
+                let stop = shutdown.notified();
+                tokio::pin!(stop);
+                stop.as_mut().enable();
+                loop {
+                    tokio::select! {
+                        _ = &mut stop => break,
+                        _ = persister.wake.notified() => {}
+                    }
+                    let stopping = tokio::select! {
+                        _ = &mut stop => true,
+                        _ = tokio::time::sleep(persister.debounce) => false,
+                    };
+                    if persister.is_dirty() {
+                        state.flush_owners().await;
+                    }
+                    if stopping {
+                        break;
+                    }
+                }
+                persister.running.store(false, Ordering::Release);
+                if persister.is_dirty() {
+                    state.flush_owners().await;
+                }
+                debug!("owners persister stopped");
+            }
+            .instrument(tracing::info_span!("maintenance.persist_owners")),
+        )
+    }
+
     #[tracing::instrument(level = "debug", skip(self))]
     pub fn spawn_persist_owners(&self) {
         let state = self.clone();
@@ -559,6 +2075,32 @@ impl AppState {
             state.persist_owners().await;
         });
     }
+    /// Move report `id` to `status`, recording `note` as its resolution, and
+    /// persist the change. Returns the updated record.
+    pub async fn transition_report(
+        &self,
+        id: &str,
+        status: ReportStatus,
+        note: Option<String>,
+    ) -> Result<ReportRecord, ReportTransitionError> {
+        let updated = {
+            let mut reports = self.reports.write().await;
+            let report = reports
+                .iter_mut()
+                .find(|r| r.id == id)
+                .ok_or(ReportTransitionError::NotFound)?;
+            if !report.status.can_transition_to(status) {
+                return Err(ReportTransitionError::NotAllowed(report.status));
+            }
+            report.status = status;
+            report.resolution = note;
+            report.status_changed_at = Some(self.now_secs());
+            report.clone()
+        };
+        self.persist_reports().await;
+        Ok(updated)
+    }
+
     #[tracing::instrument(level = "debug", skip(self))]
     pub async fn persist_reports(&self) {
         let reports = self.reports.read().await.clone();
@@ -607,7 +2149,7 @@ impl AppState {
                 Err(err) => {
                     error!(
                         ?err,
-                        key = ban.subject.key(),
+                        key = %ban.subject.key(),
                         "failed to serialize ban for redis"
                     );
                     return;
@@ -621,6 +2163,42 @@ impl AppState {
         debug!(count = encoded.len(), "persisted bans to key-value store");
     }
 
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn load_api_tokens(&self) -> Result<()> {
+        for (digest, value) in self.kv.load_hash("api_tokens").await? {
+            match serde_json::from_str::<ApiToken>(&value) {
+                Ok(token) => {
+                    self.api_tokens.by_digest.insert(digest, token);
+                }
+                Err(err) => warn!(?err, "skipping malformed api token"),
+            }
+        }
+        info!(count = self.api_tokens.len(), "loaded api tokens");
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn persist_api_tokens(&self) {
+        let mut encoded = Vec::with_capacity(self.api_tokens.len());
+        for entry in self.api_tokens.by_digest.iter() {
+            match serde_json::to_string(entry.value()) {
+                Ok(value) => encoded.push((entry.key().clone(), value)),
+                Err(err) => {
+                    error!(?err, id = entry.value().id, "failed to serialize api token");
+                    return;
+                }
+            }
+        }
+        if let Err(err) = self.kv.replace_hash("api_tokens", &encoded).await {
+            error!(?err, "failed to persist api tokens to key-value store");
+            return;
+        }
+        debug!(
+            count = encoded.len(),
+            "persisted api tokens to key-value store"
+        );
+    }
+
     #[tracing::instrument(level = "debug", skip(self, session))]
     pub async fn persist_chunk_session(&self, id: &str, session: &ChunkSession) -> Result<()> {
         let _guard = session.persist_lock.lock().await;
@@ -661,6 +2239,34 @@ impl AppState {
     }
 
     #[tracing::instrument(level = "debug", skip(self))]
+    /// Remove what an assembly cut short by a restart left behind: the `.part`
+    /// file, and a final file that was moved into place but never registered.
+    /// The chunks themselves are kept so the client can complete again.
+    async fn clean_interrupted_assembly(&self, id: &str, record: &ChunkSessionRecord) {
+        let tmp = assembly_temp_path(&self.upload_dir, &record.storage_name);
+        match fs::remove_file(&tmp).await {
+            Ok(()) => {
+                info!(session_id = id, path = ?tmp, "removed partial assembly from interrupted upload")
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => {
+                warn!(?err, session_id = id, path = ?tmp, "failed to remove partial assembly")
+            }
+        }
+        if record.phase == Some(ChunkPhase::Verifying)
+            && !self.owners.contains_key(&record.storage_name)
+        {
+            match self.file_store.delete(&record.storage_name).await {
+                Ok(()) => {
+                    info!(session_id = id, file = %record.storage_name, "removed unverified assembly from interrupted upload")
+                }
+                Err(err) => {
+                    warn!(?err, session_id = id, file = %record.storage_name, "failed to remove unverified assembly")
+                }
+            }
+        }
+    }
+
     pub async fn load_chunk_sessions_from_disk(&self) -> Result<()> {
         let mut dirs = match fs::read_dir(&*self.chunk_dir).await {
             Ok(d) => d,
@@ -690,7 +2296,28 @@ impl AppState {
             };
             match serde_json::from_slice::<ChunkSessionRecord>(&bytes) {
                 Ok(record) => {
+                    let finished = match record.phase {
+                        Some(phase) => phase.is_terminal(),
+                        None => record.completed,
+                    };
+                    if finished {
+                        debug!(session_id = %id, phase = ?record.phase, "dropping finished chunk session left on disk");
+                        let _ = fs::remove_dir_all(entry.path()).await;
+                        continue;
+                    }
+                    let interrupted = matches!(
+                        record.phase,
+                        Some(ChunkPhase::Assembling | ChunkPhase::Verifying)
+                    );
+                    if interrupted {
+                        self.clean_interrupted_assembly(&id, &record).await;
+                    }
                     let session = Arc::new(ChunkSession::from_record(record, entry.path()));
+                    if interrupted
+                        && let Err(err) = self.persist_chunk_session(&id, session.as_ref()).await
+                    {
+                        warn!(?err, session_id = %id, "failed to persist reopened chunk session");
+                    }
                     self.chunk_sessions.insert(id, session);
                     loaded += 1;
                 }
@@ -723,11 +2350,11 @@ impl AppState {
     #[tracing::instrument(level = "trace", skip(self))]
     pub async fn is_admin(&self, token: &str) -> bool {
         let map = self.admin_sessions.read().await;
-        if let Some(exp) = map.get(token) {
-            if *exp > now_secs() {
-                trace!("admin session valid");
-                return true;
-            }
+        if let Some(exp) = map.get(token)
+            && *exp > self.now_secs()
+        {
+            trace!("admin session valid");
+            return true;
         }
         trace!("admin session missing or expired");
         false
@@ -736,14 +2363,14 @@ impl AppState {
     #[tracing::instrument(level = "debug", skip(self))]
     pub async fn create_admin_session(&self, token: String) {
         let mut map = self.admin_sessions.write().await;
-        map.insert(token, now_secs() + ADMIN_SESSION_TTL);
+        map.insert(token, self.now_secs() + ADMIN_SESSION_TTL);
         debug!(count = map.len(), "created admin session");
     }
 
     #[tracing::instrument(level = "debug", skip(self))]
     pub async fn cleanup_admin_sessions(&self) {
         let mut map = self.admin_sessions.write().await;
-        let now = now_secs();
+        let now = self.now_secs();
         map.retain(|_, exp| *exp > now);
         debug!(remaining = map.len(), "cleaned up admin sessions");
     }
@@ -751,7 +2378,7 @@ impl AppState {
     pub async fn load_or_create_admin_key(&self, path: &PathBuf) -> anyhow::Result<AdminKeyFile> {
         if let Ok(bytes) = fs::read(path).await {
             match serde_json::from_slice::<AdminKeyFile>(&bytes) {
-                Ok(parsed) if parsed.expires > now_secs() && !parsed.key.is_empty() => {
+                Ok(parsed) if parsed.expires > self.now_secs() && !parsed.key.is_empty() => {
                     debug!("loaded existing admin key");
                     return Ok(parsed);
                 }
@@ -766,16 +2393,16 @@ impl AppState {
         // Need to create / rotate
         let new = AdminKeyFile {
             key: new_id(),
-            expires: now_secs() + ADMIN_KEY_TTL,
+            expires: self.now_secs() + ADMIN_KEY_TTL,
         };
         let json = serde_json::to_vec_pretty(&new).map_err(|err| {
             error!(?err, "failed to serialize admin key");
             err
         })?;
-        if let Some(parent) = path.parent() {
-            if let Err(err) = fs::create_dir_all(parent).await {
-                error!(?err, dir = ?parent, "failed to create admin key directory");
-            }
+        if let Some(parent) = path.parent()
+            && let Err(err) = fs::create_dir_all(parent).await
+        {
+            error!(?err, dir = ?parent, "failed to create admin key directory");
         }
         fs::write(path, json).await.map_err(|err| {
             error!(?err, path = ?path, "failed to write admin key file");
@@ -787,126 +2414,133 @@ impl AppState {
 
     #[tracing::instrument(level = "debug", skip(self))]
     pub async fn is_banned(&self, ip: &str) -> bool {
-        let parsed_ip = ip.parse::<IpAddr>().ok();
-        let mut ip_hash = None;
-        if let Some(addr) = parsed_ip.as_ref() {
-            let (_, hash) = self.hash_ip_addr(addr);
-            ip_hash = Some(hash);
-        }
-        let direct_hash_input = if parsed_ip.is_none() { Some(ip) } else { None };
-
+        let probe = BanProbe::new(self, ip);
+        let now = self.now_secs();
         let bans = self.bans.read().await;
-        for ban in bans.iter() {
-            match &ban.subject {
-                BanSubject::Exact { hash } => {
-                    if ip_hash.as_ref().is_some_and(|candidate| candidate == hash) {
-                        return true;
-                    }
-                    if direct_hash_input.is_some_and(|candidate| candidate == hash) {
-                        return true;
-                    }
-                }
-                BanSubject::Network {
-                    hash,
-                    prefix,
-                    version,
-                } => {
-                    if let Some(addr) = parsed_ip.as_ref() {
-                        if let Some((net_version, _, candidate)) =
-                            self.hash_network_for_ip(addr, *prefix)
-                        {
-                            if net_version == *version && &candidate == hash {
-                                return true;
-                            }
-                        }
-                    }
-                    if direct_hash_input.is_some_and(|candidate| candidate == hash) {
-                        return true;
-                    }
-                }
-            }
-        }
-        if parsed_ip.is_none() {
+        let banned = bans
+            .iter()
+            .any(|ban| ban.is_active(now) && probe.matches(self, &ban.subject));
+        if probe.parsed_ip.is_none() {
             trace!(ip, "ban lookup completed (raw hash)");
         }
-        false
+        banned
     }
 
     #[tracing::instrument(level = "info", skip(self, ban))]
     pub async fn add_ban(&self, mut ban: IpBan) {
         if ban.time == 0 {
-            ban.time = now_secs();
+            ban.time = self.now_secs();
         }
         let key = ban.subject.key().to_string();
+        let now = self.now_secs();
         let mut bans = self.bans.write().await;
+        // A lapsed ban the cleanup task has not pruned yet gives way.
+        bans.retain(|b| b.subject.key() != key || b.is_active(now));
         if bans.iter().any(|b| b.subject.key() == key) {
             warn!(ban_key = key, "ban already exists; skipping");
             return;
         }
+        let event = WebhookEvent::ban_added(&ban);
         bans.push(ban);
         info!(ban_key = key, total = bans.len(), "ban added");
+        drop(bans);
+        self.webhooks.emit(event);
     }
 
     #[tracing::instrument(level = "info", skip(self))]
     pub async fn remove_ban(&self, key: &str) {
         let mut bans = self.bans.write().await;
         bans.retain(|b| b.subject.key() != key);
+        self.ban_hits.remove(key);
         info!(ban_key = key, remaining = bans.len(), "ban removed");
     }
 
+    /// Drop temporary bans that have run out. Returns how many went.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn prune_expired_bans(&self) -> usize {
+        let now = self.now_secs();
+        let mut bans = self.bans.write().await;
+        let before = bans.len();
+        bans.retain(|ban| {
+            let active = ban.is_active(now);
+            if !active {
+                self.ban_hits.remove(&ban.subject.key());
+                info!(ban_key = %ban.subject.key(), "temporary ban expired");
+            }
+            active
+        });
+        let removed = before - bans.len();
+        drop(bans);
+        if removed > 0 {
+            self.persist_bans().await;
+        }
+        removed
+    }
+
     #[tracing::instrument(level = "debug", skip(self))]
     pub async fn find_ban_for_input(&self, input: &str) -> Option<IpBan> {
-        let parsed_ip = input.parse::<IpAddr>().ok();
-        let ip_hash = parsed_ip.as_ref().map(|addr| self.hash_ip_addr(addr).1);
-        let direct_value = if parsed_ip.is_none() {
-            Some(input.to_string())
-        } else {
-            None
-        };
+        let probe = BanProbe::new(self, input);
+        let now = self.now_secs();
         let bans = self.bans.read().await;
-        for ban in bans.iter() {
-            match &ban.subject {
-                BanSubject::Exact { hash } => {
-                    if ip_hash.as_ref().is_some_and(|candidate| candidate == hash) {
-                        return Some(ban.clone());
-                    }
-                    if direct_value
-                        .as_ref()
-                        .is_some_and(|candidate| candidate == hash)
-                    {
-                        return Some(ban.clone());
-                    }
-                }
-                BanSubject::Network {
-                    hash,
-                    prefix,
-                    version,
-                } => {
-                    if let Some(addr) = parsed_ip.as_ref() {
-                        if let Some((net_version, _, candidate)) =
-                            self.hash_network_for_ip(addr, *prefix)
-                        {
-                            if net_version == *version && &candidate == hash {
-                                return Some(ban.clone());
-                            }
-                        }
-                    }
-                    if direct_value
-                        .as_ref()
-                        .is_some_and(|candidate| candidate == hash)
-                    {
-                        return Some(ban.clone());
-                    }
-                }
+        bans.iter()
+            .find(|ban| ban.is_active(now) && probe.matches(self, &ban.subject))
+            .cloned()
+    }
+
+    /// The chunk session `id`, reading it from the shared chunk directory when
+    /// another replica created it or this one has not seen it yet.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub async fn chunk_session(&self, id: &str) -> Option<Arc<ChunkSession>> {
+        if let Some(entry) = self.chunk_sessions.get(id) {
+            return Some(entry.value().clone());
+        }
+        if !crate::cluster::is_session_id(id) {
+            return None;
+        }
+        let dir = self.chunk_dir.join(id);
+        let bytes = fs::read(dir.join("session.json")).await.ok()?;
+        let record = match serde_json::from_slice::<ChunkSessionRecord>(&bytes) {
+            Ok(record) => record,
+            Err(err) => {
+                warn!(
+                    ?err,
+                    session_id = id,
+                    "failed to parse shared chunk session metadata"
+                );
+                return None;
             }
+        };
+        let phase = record.phase;
+        let finished = match phase {
+            Some(phase) => phase.is_terminal(),
+            None => record.completed,
+        };
+        if finished {
+            return None;
         }
-        None
+        let mut session = ChunkSession::from_record(record, dir);
+        // Unlike a restart, an assembly in progress here is still running on
+        // the replica that started it.
+        if let Some(phase @ (ChunkPhase::Assembling | ChunkPhase::Verifying)) = phase {
+            session.lifecycle = ChunkLifecycle::new(phase);
+        }
+        let session = self
+            .chunk_sessions
+            .entry(id.to_string())
+            .or_insert_with(|| Arc::new(session))
+            .value()
+            .clone();
+        info!(session_id = id, node = ?session.node, "adopted chunk session from shared storage");
+        Some(session)
     }
 
     #[tracing::instrument(level = "debug", skip(self))]
     pub async fn remove_chunk_session(&self, id: &str) {
         if let Some((_, session)) = self.chunk_sessions.remove(id) {
             let dir = (*session.storage_dir).clone();
+            // Drop the metadata first so no replica adopts the session again
+            // while the rest of the directory is being removed.
+            let _ = fs::remove_file(dir.join("session.json")).await;
             tokio::spawn(async move {
                 if let Err(err) = fs::remove_dir_all(&dir).await {
                     warn!(?err, path = ?dir, "failed to remove chunk session directory");
@@ -920,40 +2554,116 @@ impl AppState {
 
     #[tracing::instrument(level = "debug", skip(self))]
     pub async fn cleanup_chunk_sessions(&self) {
-        const STALE_GRACE: u64 = 30 * 60; // 30 minutes
-        let now = now_secs();
-        let mut expired_ids = Vec::new();
+        let now = self.now_secs();
+        let mut expired = Vec::new();
         for entry in self.chunk_sessions.iter() {
-            let session = entry.value();
-            let expired = session.expires <= now;
-            let idle = session
-                .last_update
-                .load(Ordering::Relaxed)
-                .saturating_add(STALE_GRACE)
-                <= now;
-            if expired || idle {
-                expired_ids.push(entry.key().clone());
+            if entry.value().abandoned_at() <= now {
+                expired.push((entry.key().clone(), entry.value().clone()));
             }
         }
-        for id in expired_ids.iter() {
-            self.remove_chunk_session(id).await;
+        let mut removed = 0usize;
+        for (id, session) in expired {
+            // Replicas sharing the chunk dir each cache the session, and the
+            // upload may have carried on through another one since this one
+            // last served it.
+            if session.expires > now
+                && let Some(touched) = last_modified_within(&session.storage_dir).await
+            {
+                session.last_update.fetch_max(touched, Ordering::Relaxed);
+                if session.abandoned_at() > now {
+                    trace!(session_id = %id, touched, "chunk session still active elsewhere");
+                    continue;
+                }
+            }
+            self.remove_chunk_session(&id).await;
+            removed += 1;
         }
-        let removed = expired_ids.len();
         if removed > 0 {
             info!(removed, "cleaned up stale chunk sessions");
         } else {
             trace!("no stale chunk sessions found");
         }
     }
+
+    /// Remove directories under the chunk dir that no session in memory
+    /// uses and that nothing has written to for
+    /// [`ORPHAN_CHUNK_DIR_GRACE_SECS`]: leftovers of a failed delete or of a
+    /// crash before `session.json` was written. Returns how many went.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn sweep_orphaned_chunk_dirs(&self) -> usize {
+        let mut dirs = match fs::read_dir(&*self.chunk_dir).await {
+            Ok(dirs) => dirs,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return 0,
+            Err(err) => {
+                warn!(?err, dir = ?self.chunk_dir, "failed to read chunk directory for sweep");
+                return 0;
+            }
+        };
+        let in_use: HashSet<PathBuf> = self
+            .chunk_sessions
+            .iter()
+            .map(|entry| (*entry.value().storage_dir).clone())
+            .collect();
+        let now = self.now_secs();
+        let mut removed = 0usize;
+        while let Ok(Some(entry)) = dirs.next_entry().await {
+            let path = entry.path();
+            if !entry.file_type().await.is_ok_and(|t| t.is_dir()) || in_use.contains(&path) {
+                continue;
+            }
+            // Another replica sharing the directory may still be writing to
+            // a session this one never loaded.
+            let Some(touched) = last_modified_within(&path).await else {
+                continue;
+            };
+            if touched.saturating_add(ORPHAN_CHUNK_DIR_GRACE_SECS) > now {
+                continue;
+            }
+            match fs::remove_dir_all(&path).await {
+                Ok(()) => {
+                    debug!(path = ?path, touched, "removed orphaned chunk directory");
+                    removed += 1;
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => warn!(?err, path = ?path, "failed to remove orphaned chunk directory"),
+            }
+        }
+        if removed > 0 {
+            info!(removed, "removed orphaned chunk directories");
+        } else {
+            trace!("no orphaned chunk directories found");
+        }
+        removed
+    }
+}
+
+/// Latest modification time, in Unix seconds, of `dir` and the entries
+/// directly inside it.
+async fn last_modified_within(dir: &Path) -> Option<u64> {
+    let secs = |meta: std::fs::Metadata| {
+        meta.modified()
+            .ok()?
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_secs())
+    };
+    let mut latest = secs(fs::metadata(dir).await.ok()?)?;
+    let mut entries = fs::read_dir(dir).await.ok()?;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if let Some(modified) = entry.metadata().await.ok().and_then(secs) {
+            latest = latest.max(modified);
+        }
+    }
+    Some(latest)
 }
 
 #[tracing::instrument(level = "debug", skip(state))]
 pub async fn check_storage_integrity(state: &AppState) {
+    let names: Vec<String> = state.owners.iter().map(|e| e.key().clone()).collect();
     let mut to_remove = Vec::new();
-    for entry in state.owners.iter() {
-        let fname = entry.key();
-        if !state.upload_dir.join(fname).exists() {
-            to_remove.push(fname.clone());
+    for fname in names {
+        if !state.file_store.exists(&fname).await {
+            to_remove.push(fname);
         }
     }
     if to_remove.is_empty() {
@@ -961,7 +2671,7 @@ pub async fn check_storage_integrity(state: &AppState) {
         return;
     }
     for f in &to_remove {
-        state.owners.remove(f);
+        state.remove_owner(f);
     }
     state.persist_owners().await;
     warn!(
@@ -979,27 +2689,42 @@ pub fn spawn_integrity_check(state: AppState) {
 
 #[tracing::instrument(level = "debug", skip(state))]
 pub async fn cleanup_expired(state: &AppState) {
-    let now = now_secs();
+    let now = state.now_secs();
     let mut to_delete = Vec::new();
     for entry in state.owners.iter() {
         let (file, meta) = (entry.key(), entry.value());
         if meta.expires <= now {
-            to_delete.push(file.clone());
+            to_delete.push((file.clone(), meta.expires));
         }
     }
+    let pruned = state.tombstones.prune(now);
     if to_delete.is_empty() {
+        if pruned > 0 {
+            state.persist_tombstones().await;
+        }
         trace!("no expired files found");
         return;
     }
-    for f in &to_delete {
-        state.owners.remove(f);
+    let mut buried = 0usize;
+    for (f, expires) in &to_delete {
+        state.remove_owner(f);
+        if state.tombstones.record(f, *expires) {
+            buried += 1;
+        }
+        state.webhooks.emit(WebhookEvent::FileExpired {
+            file: f.clone(),
+            expired_at: *expires,
+        });
     }
-    for f in &to_delete {
-        if let Err(err) = fs::remove_file(state.upload_dir.join(f)).await {
-            warn!(?err, file = f, "failed to remove expired file from disk");
+    for (f, _) in &to_delete {
+        if let Err(err) = state.file_store.delete(f).await {
+            warn!(?err, file = f, "failed to remove expired file from storage");
         }
     }
     state.persist_owners().await;
+    if buried > 0 || pruned > 0 {
+        state.persist_tombstones().await;
+    }
     info!(removed = to_delete.len(), "cleanup expired files completed");
 }
 
@@ -1013,11 +2738,12 @@ pub async fn reload_metadata_if_changed(state: &AppState) {
         }
     };
     state.owners.clear();
+    state.owners_index.invalidate();
     let mut restored = 0usize;
     for (file, payload) in entries {
         match serde_json::from_str::<FileMeta>(&payload) {
             Ok(meta) => {
-                state.owners.insert(file, meta);
+                state.insert_owner(file, meta);
                 restored += 1;
             }
             Err(err) => {
@@ -1095,7 +2821,7 @@ pub async fn verify_user_entries_with_report(
     }
 
     for (fname, meta) in &store_applied_payloads {
-        state.owners.insert(fname.clone(), meta.clone());
+        state.insert_owner(fname.clone(), meta.clone());
     }
 
     let mut restored = Vec::new();
@@ -1105,9 +2831,8 @@ pub async fn verify_user_entries_with_report(
             continue;
         }
         if state.owners.get(fname).is_none() {
-            let path = state.upload_dir.join(fname);
-            if tokio::fs::metadata(&path).await.is_ok() {
-                state.owners.insert(fname.clone(), meta_disk.clone());
+            if state.file_store.exists(fname).await {
+                state.insert_owner(fname.clone(), meta_disk.clone());
                 restored.push(fname.clone());
             } else {
                 stale_store.push(fname.clone());
@@ -0,0 +1,247 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::{debug, info, warn};
+
+use crate::state::AppState;
+
+/// Shortest remaining lifetime storage pressure can leave a file with, unless
+/// `JUICEBOX_STORAGE_MIN_TTL_SECS` says otherwise.
+pub const DEFAULT_STORAGE_MIN_TTL_SECS: u64 = 60 * 60;
+
+/// Byte budgets for stored files; `0` disables a limit. Above `soft_bytes`
+/// the watchdog shortens TTLs; at `hard_bytes` uploads are refused. No single
+/// owner may hold more than `owner_bytes`.
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+pub struct StorageLimits {
+    pub soft_bytes: u64,
+    pub hard_bytes: u64,
+    pub min_ttl_secs: u64,
+    pub owner_bytes: u64,
+}
+
+impl Default for StorageLimits {
+    fn default() -> Self {
+        Self {
+            soft_bytes: 0,
+            hard_bytes: 0,
+            min_ttl_secs: DEFAULT_STORAGE_MIN_TTL_SECS,
+            owner_bytes: 0,
+        }
+    }
+}
+
+impl StorageLimits {
+    /// Read `JUICEBOX_STORAGE_SOFT_LIMIT_BYTES`, `JUICEBOX_STORAGE_HARD_LIMIT_BYTES`,
+    /// `JUICEBOX_STORAGE_MIN_TTL_SECS` and `JUICEBOX_OWNER_QUOTA_BYTES`.
+    pub fn from_env() -> Self {
+        let read = |key: &str, default: u64| {
+            std::env::var(key)
+                .ok()
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            soft_bytes: read("JUICEBOX_STORAGE_SOFT_LIMIT_BYTES", 0),
+            hard_bytes: read("JUICEBOX_STORAGE_HARD_LIMIT_BYTES", 0),
+            min_ttl_secs: read(
+                "JUICEBOX_STORAGE_MIN_TTL_SECS",
+                DEFAULT_STORAGE_MIN_TTL_SECS,
+            ),
+            owner_bytes: read("JUICEBOX_OWNER_QUOTA_BYTES", 0),
+        }
+    }
+}
+
+/// Tracks how many bytes are stored against [`StorageLimits`]. The total is
+/// recomputed by each sweep and bumped by every stored upload in between, so
+/// the hard limit holds even during bursts.
+pub struct StorageWatchdog {
+    limits: StorageLimits,
+    used: AtomicU64,
+    shortened: AtomicU64,
+}
+
+impl StorageWatchdog {
+    pub fn new(limits: StorageLimits) -> Self {
+        Self {
+            limits,
+            used: AtomicU64::new(0),
+            shortened: AtomicU64::new(0),
+        }
+    }
+
+    pub fn limits(&self) -> StorageLimits {
+        self.limits
+    }
+
+    pub fn used_bytes(&self) -> u64 {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// TTLs shortened since startup.
+    pub fn shortened_total(&self) -> u64 {
+        self.shortened.load(Ordering::Relaxed)
+    }
+
+    pub fn record_stored(&self, bytes: u64) {
+        self.used.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn under_pressure(&self) -> bool {
+        self.limits.soft_bytes > 0 && self.used_bytes() > self.limits.soft_bytes
+    }
+
+    /// Whether new uploads must be refused.
+    pub fn is_full(&self) -> bool {
+        self.limits.hard_bytes > 0 && self.used_bytes() >= self.limits.hard_bytes
+    }
+}
+
+/// A stored file as seen by [`plan_shortening`].
+#[derive(Clone, Debug)]
+pub struct StoredFile {
+    pub name: String,
+    pub size: u64,
+    pub created: u64,
+    pub expires: u64,
+}
+
+/// New expiry times that bring `used` back under `soft_bytes`.
+///
+/// Files are taken largest-and-oldest first (by size times age) until their
+/// combined size covers the overage. Each one keeps the fraction
+/// `soft_bytes / used` of its remaining lifetime, but never less than
+/// `min_ttl_secs`, and no file is ever extended.
+pub fn plan_shortening(
+    files: &[StoredFile],
+    used: u64,
+    limits: &StorageLimits,
+    now: u64,
+) -> Vec<(String, u64)> {
+    if limits.soft_bytes == 0 || used <= limits.soft_bytes {
+        return Vec::new();
+    }
+    let excess = used - limits.soft_bytes;
+    let keep = limits.soft_bytes as f64 / used as f64;
+    let mut ranked: Vec<&StoredFile> = files.iter().filter(|f| f.expires > now).collect();
+    ranked.sort_by_key(|f| {
+        let age = now.saturating_sub(f.created) + 1;
+        std::cmp::Reverse(f.size as u128 * age as u128)
+    });
+    let mut covered = 0u64;
+    let mut plan = Vec::new();
+    for file in ranked {
+        if covered >= excess {
+            break;
+        }
+        covered = covered.saturating_add(file.size);
+        let remaining = file.expires - now;
+        let shortened = ((remaining as f64 * keep) as u64).max(limits.min_ttl_secs);
+        if shortened < remaining {
+            plan.push((file.name.clone(), now + shortened));
+        }
+    }
+    plan
+}
+
+impl AppState {
+    /// Recount stored bytes, recording each file's size on its metadata, and,
+    /// above the soft limit, shorten the TTLs [`plan_shortening`] picks. Shortened files remember their original
+    /// expiry so owners can see what happened. Returns how many changed.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn enforce_storage_limits(&self) -> usize {
+        let entries: Vec<(String, u64, u64)> = self
+            .owners
+            .iter()
+            .map(|e| (e.key().clone(), e.value().created, e.value().expires))
+            .collect();
+        let mut files = Vec::with_capacity(entries.len());
+        let mut used = 0u64;
+        let mut backfilled = 0;
+        for (name, created, expires) in entries {
+            if let Ok(Some(size)) = self.file_store.size(&name).await {
+                used += size;
+                if let Some(mut meta) = self.owners.get_mut(&name)
+                    && meta.size != size
+                {
+                    meta.size = size;
+                    backfilled += 1;
+                }
+                files.push(StoredFile {
+                    name,
+                    size,
+                    created,
+                    expires,
+                });
+            }
+        }
+        self.storage.used.store(used, Ordering::Relaxed);
+        let limits = self.storage.limits();
+        let plan = plan_shortening(&files, used, &limits, self.now_secs());
+        if plan.is_empty() {
+            debug!(used, soft = limits.soft_bytes, "storage within budget");
+        }
+        let mut changed = 0;
+        for (name, expires) in plan {
+            let Some(mut meta) = self.owners.get_mut(&name) else {
+                continue;
+            };
+            let current = meta.expires;
+            if expires < current {
+                meta.ttl_shortened_from.get_or_insert(current);
+                info!(file = %name, from = current, to = expires, "shortened ttl under storage pressure");
+                meta.expires = expires;
+                changed += 1;
+            }
+        }
+        if changed > 0 || backfilled > 0 {
+            debug!(backfilled, "recorded file sizes");
+            self.persist_owners().await;
+        }
+        if changed > 0 {
+            self.storage
+                .shortened
+                .fetch_add(changed as u64, Ordering::Relaxed);
+            warn!(
+                used,
+                soft = limits.soft_bytes,
+                changed,
+                "storage over soft limit; shortened file ttls"
+            );
+        }
+        changed
+    }
+
+    /// Bytes `owner_hash` holds: live files plus the declared size of its
+    /// unfinished chunk sessions.
+    pub fn owner_stored_bytes(&self, owner_hash: &str, now: u64) -> u64 {
+        let stored: u64 = self
+            .owners
+            .iter()
+            .filter(|e| e.owner_hash == owner_hash && e.expires > now)
+            .map(|e| e.size)
+            .sum();
+        let pending: u64 = self
+            .chunk_sessions
+            .iter()
+            .filter(|e| e.owner_hash == owner_hash && !e.phase().is_terminal())
+            .map(|e| e.total_bytes)
+            .sum();
+        stored + pending
+    }
+
+    /// Whether `owner_hash` can store `incoming` more bytes under the
+    /// per-owner quota.
+    pub fn owner_quota_allows(&self, owner_hash: &str, incoming: u64, now: u64) -> bool {
+        let quota = self.storage.limits().owner_bytes;
+        if quota == 0 {
+            return true;
+        }
+        let held = self.owner_stored_bytes(owner_hash, now);
+        let allowed = held.saturating_add(incoming) <= quota;
+        if !allowed {
+            debug!(owner_hash, held, incoming, quota, "owner quota exceeded");
+        }
+        allowed
+    }
+}
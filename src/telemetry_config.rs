@@ -0,0 +1,189 @@
+//! Sentry and tracing settings, read and checked once at startup. Malformed
+//! or out-of-range values are reported instead of silently falling back, so
+//! a typo in a sample rate does not quietly trace everything.
+
+use anyhow::{Result, bail};
+use std::borrow::Cow;
+
+use crate::build_info;
+use crate::handlers::telemetry::DEFAULT_IGNORED_ROUTES;
+use crate::state::TelemetryState;
+use crate::trace_sampling::{DEFAULT_ERROR_BUDGET, DEFAULT_TRACES_FLOOR, TraceSamplingConfig};
+
+/// Environment variables that may carry the deployed commit, in order of
+/// preference, when `SENTRY_RELEASE` is unset.
+const COMMIT_ENV_VARS: [&str; 7] = [
+    "SOURCE_VERSION",
+    "GIT_COMMIT",
+    "GIT_SHA",
+    "GITHUB_SHA",
+    "VERCEL_GIT_COMMIT_SHA",
+    "COMMIT_SHA",
+    "REVISION",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TelemetryConfig {
+    /// `None` when `SENTRY_DSN` is unset or `disabled`/`off`/`false`.
+    pub dsn: Option<String>,
+    pub release: String,
+    pub environment: String,
+    /// Share of error events sent (`SENTRY_SAMPLE_RATE`).
+    pub error_sample_rate: f32,
+    pub profiles_sample_rate: f32,
+    /// Adaptive traces sampling; its ceiling is `SENTRY_TRACES_SAMPLE_RATE`.
+    pub sampling: TraceSamplingConfig,
+    pub trace_propagation_targets: Vec<String>,
+    pub ignored_routes: Vec<String>,
+    pub respect_privacy_signals: bool,
+}
+
+impl TelemetryConfig {
+    pub fn from_env(production: bool) -> Result<Self> {
+        Self::from_vars(production, |name| std::env::var(name).ok())
+    }
+
+    /// Build from any variable lookup; `from_env` uses the process
+    /// environment.
+    pub fn from_vars(production: bool, var: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let get = |name: &str| {
+            var(name)
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+        let rate = |name: &str, default: f32| -> Result<f32> {
+            match get(name) {
+                None => Ok(default),
+                Some(raw) => match raw.parse::<f32>() {
+                    Ok(value) if (0.0..=1.0).contains(&value) => Ok(value),
+                    _ => bail!("{name} must be a number between 0 and 1, got {raw:?}"),
+                },
+            }
+        };
+
+        let traces_sample_rate = rate("SENTRY_TRACES_SAMPLE_RATE", 1.0)?;
+        let floor = match get("SENTRY_TRACES_SAMPLE_RATE_MIN") {
+            // The default floor follows a lowered ceiling; an explicit one
+            // above it is a mistake.
+            None => DEFAULT_TRACES_FLOOR.min(traces_sample_rate),
+            Some(_) => rate("SENTRY_TRACES_SAMPLE_RATE_MIN", DEFAULT_TRACES_FLOOR)?,
+        };
+        let sampling = TraceSamplingConfig {
+            floor,
+            ceiling: traces_sample_rate,
+            error_budget: match get("SENTRY_TRACES_ERROR_BUDGET") {
+                None => DEFAULT_ERROR_BUDGET,
+                Some(raw) => match raw.parse::<f64>() {
+                    Ok(value) if value > 0.0 && value <= 1.0 => value,
+                    _ => bail!(
+                        "SENTRY_TRACES_ERROR_BUDGET must be a number above 0 and at most 1, got {raw:?}"
+                    ),
+                },
+            },
+        };
+        sampling.validate()?;
+
+        let list = |name: &str| -> Option<Vec<String>> {
+            var(name).map(|raw| {
+                raw.split(',')
+                    .map(str::trim)
+                    .filter(|entry| !entry.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+        };
+        let trace_propagation_targets = list("SENTRY_TRACE_PROPAGATION_TARGETS")
+            .filter(|targets| !targets.is_empty())
+            .unwrap_or_else(|| vec!["^/".to_string()]);
+        let ignored_routes = list("SENTRY_IGNORED_ROUTES").unwrap_or_else(|| {
+            DEFAULT_IGNORED_ROUTES
+                .iter()
+                .map(|route| route.to_string())
+                .collect()
+        });
+        let respect_privacy_signals = match get("TELEMETRY_RESPECT_PRIVACY_SIGNALS") {
+            None => true,
+            Some(raw) => match raw.to_ascii_lowercase().as_str() {
+                "0" | "false" | "no" | "off" => false,
+                "1" | "true" | "yes" | "on" => true,
+                _ => bail!("TELEMETRY_RESPECT_PRIVACY_SIGNALS must be true or false, got {raw:?}"),
+            },
+        };
+
+        // Only enable Sentry if SENTRY_DSN is explicitly provided. Do not fall
+        // back to an embedded default DSN for security reasons.
+        let dsn = get("SENTRY_DSN").filter(|dsn| {
+            !["disabled", "off", "false"]
+                .iter()
+                .any(|off| dsn.eq_ignore_ascii_case(off))
+        });
+
+        Ok(Self {
+            dsn,
+            release: release(&get).into_owned(),
+            environment: get("SENTRY_ENV").unwrap_or_else(|| {
+                if production {
+                    "production".to_string()
+                } else {
+                    "development".to_string()
+                }
+            }),
+            error_sample_rate: rate("SENTRY_SAMPLE_RATE", 1.0)?,
+            profiles_sample_rate: rate("SENTRY_PROFILES_SAMPLE_RATE", traces_sample_rate)?,
+            sampling,
+            trace_propagation_targets,
+            ignored_routes,
+            respect_privacy_signals,
+        })
+    }
+
+    /// The most transactions traced, while errors spike.
+    pub fn traces_sample_rate(&self) -> f32 {
+        self.sampling.ceiling
+    }
+
+    /// What request handlers see of this configuration.
+    pub fn state(&self) -> TelemetryState {
+        TelemetryState {
+            sentry_dsn: self.dsn.clone(),
+            release: self.release.clone(),
+            environment: self.environment.clone(),
+            traces_sample_rate: self.traces_sample_rate(),
+            profiles_sample_rate: self.profiles_sample_rate,
+            error_sample_rate: self.error_sample_rate,
+            trace_propagation_targets: self.trace_propagation_targets.clone(),
+            respect_privacy_signals: self.respect_privacy_signals,
+            ignored_routes: self.ignored_routes.clone(),
+        }
+    }
+}
+
+/// `SENTRY_RELEASE`, else the crate version tagged with the deployed or
+/// built commit.
+fn release(get: &impl Fn(&str) -> Option<String>) -> Cow<'static, str> {
+    if let Some(release) = get("SENTRY_RELEASE") {
+        return Cow::Owned(release);
+    }
+    if let Some(commit_raw) = COMMIT_ENV_VARS.iter().find_map(|key| get(key)) {
+        let normalized: String = commit_raw
+            .chars()
+            .filter(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '-' | '_' | '.'))
+            .collect();
+        let candidate = if normalized.is_empty() {
+            commit_raw.clone()
+        } else {
+            normalized
+        };
+        let short_commit: String = candidate.chars().take(12).collect();
+        if !short_commit.is_empty() {
+            return Cow::Owned(format!("{}+{}", build_info::VERSION, short_commit));
+        }
+    }
+    if let Some(commit) = build_info::git_commit_short() {
+        return Cow::Owned(format!("{}+{}", build_info::VERSION, commit));
+    }
+    if let Some(release) = sentry::release_name!() {
+        return release;
+    }
+    Cow::Borrowed(build_info::VERSION)
+}
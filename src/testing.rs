@@ -0,0 +1,349 @@
+//! Building blocks for integration suites, behind the `testing` feature.
+//!
+//! [`AppStateBuilder`] assembles a complete [`AppState`] in a temporary
+//! directory with an in-memory key-value store, so a suite can drive the
+//! real router without a Redis, a Postgres or a mail provider:
+//!
+//! ```no_run
+//! # async fn demo() {
+//! use juicebox::testing::AppStateBuilder;
+//!
+//! let app = AppStateBuilder::new()
+//!     .manual_clock(1_700_000_000)
+//!     .with_file("hello.txt", b"hi", "203.0.113.7", 3600)
+//!     .with_ban("198.51.100.0/24", "abuse")
+//!     .build();
+//! let router = juicebox::handlers::build_router(app.state.clone());
+//! app.state.clock.advance(3601);
+//! # }
+//! ```
+
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tempfile::TempDir;
+use tokio::sync::mpsc;
+
+use crate::assets::TEMPLATE_GLOB;
+use crate::clamav::Clamd;
+use crate::content_scan::{ContentScanner, ContentScanners};
+use crate::cors::CorsConfig;
+use crate::geoip::GeoIp;
+use crate::handlers::{EmailPrivacy, ReportRecordEmail};
+use crate::startup::{StateDirs, StateParts};
+use crate::state::{AppState, FileMeta, IpBan, KvStore, MemoryStore, TelemetryState};
+use crate::util::{Clock, SigningKeys, display_original_name, hash_ip_string};
+use crate::webhooks::WebhookConfig;
+
+/// IP hash secret used unless [`AppStateBuilder::hash_secret`] says otherwise.
+pub const DEFAULT_TEST_HASH_SECRET: [u8; 32] = [0x11; 32];
+/// Admin key used unless [`AppStateBuilder::admin_key`] says otherwise.
+pub const DEFAULT_TEST_ADMIN_KEY: &str = "test_admin_key";
+/// Report notifications buffered before senders start waiting on the test.
+const NOTIFICATION_BUFFER: usize = 64;
+
+/// Telemetry settings with Sentry off and privacy signals honoured.
+pub fn test_telemetry_state() -> Arc<TelemetryState> {
+    Arc::new(TelemetryState {
+        sentry_dsn: None,
+        release: "test-release".to_string(),
+        environment: "test".to_string(),
+        traces_sample_rate: 0.0,
+        profiles_sample_rate: 0.0,
+        error_sample_rate: 0.0,
+        trace_propagation_targets: vec!["^/".to_string()],
+        respect_privacy_signals: true,
+        ignored_routes: vec!["/healthz".to_string(), "/css/*".to_string()],
+    })
+}
+
+/// A built instance and the pieces a test inspects alongside it.
+pub struct TestApp {
+    pub state: AppState,
+    /// Directory holding uploads, chunks and data files.
+    pub root: PathBuf,
+    /// Owns `root` unless the builder was pointed at an existing directory.
+    pub temp_dir: Option<TempDir>,
+    /// Report notifications the email worker would have sent. Dropping it
+    /// makes further sends fail, which the report handler tolerates.
+    pub notifications: mpsc::Receiver<ReportRecordEmail>,
+}
+
+struct FixtureFile {
+    name: String,
+    contents: Vec<u8>,
+    owner_ip: String,
+    ttl_secs: u64,
+}
+
+/// Builder for an [`AppState`] backed by a temporary directory.
+pub struct AppStateBuilder {
+    root: Option<PathBuf>,
+    hash_secret: Vec<u8>,
+    signing_keys: String,
+    admin_key: String,
+    mailgun: bool,
+    email_privacy: EmailPrivacy,
+    clamd: Option<Clamd>,
+    content_scanners: ContentScanners,
+    node_id: String,
+    webhooks: WebhookConfig,
+    cors: CorsConfig,
+    geoip: GeoIp,
+    templates: String,
+    clock: Option<u64>,
+    kv: Option<Arc<dyn KvStore>>,
+    owners: Vec<(String, FileMeta)>,
+    files: Vec<FixtureFile>,
+    bans: Vec<(String, String)>,
+}
+
+impl Default for AppStateBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AppStateBuilder {
+    pub fn new() -> Self {
+        Self {
+            root: None,
+            hash_secret: DEFAULT_TEST_HASH_SECRET.to_vec(),
+            signing_keys: String::new(),
+            admin_key: DEFAULT_TEST_ADMIN_KEY.to_string(),
+            mailgun: true,
+            email_privacy: EmailPrivacy::Full,
+            clamd: None,
+            content_scanners: ContentScanners::default(),
+            node_id: "test-node".to_string(),
+            webhooks: WebhookConfig::default(),
+            cors: CorsConfig::default(),
+            geoip: GeoIp::default(),
+            templates: TEMPLATE_GLOB.to_string(),
+            clock: None,
+            kv: None,
+            owners: Vec::new(),
+            files: Vec::new(),
+            bans: Vec::new(),
+        }
+    }
+
+    /// Use `root` instead of a fresh temporary directory, e.g. to bring up a
+    /// second instance over the files an earlier one left behind.
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = Some(root.into());
+        self
+    }
+
+    pub fn hash_secret(mut self, secret: &[u8]) -> Self {
+        self.hash_secret = secret.to_vec();
+        self
+    }
+
+    /// Key ring in `JUICEBOX_SIGNING_KEYS` form; by default the hash secret
+    /// signs.
+    pub fn signing_keys(mut self, keys: &str) -> Self {
+        self.signing_keys = keys.to_string();
+        self
+    }
+
+    pub fn admin_key(mut self, key: impl Into<String>) -> Self {
+        self.admin_key = key.into();
+        self
+    }
+
+    /// Leave the Mailgun settings unset, as a deployment without email would.
+    pub fn without_mailgun(mut self) -> Self {
+        self.mailgun = false;
+        self
+    }
+
+    /// How hashes appear in report notifications.
+    pub fn email_privacy(mut self, privacy: EmailPrivacy) -> Self {
+        self.email_privacy = privacy;
+        self
+    }
+
+    /// Scan uploads with `clamd`.
+    pub fn clamd(mut self, clamd: Clamd) -> Self {
+        self.clamd = Some(clamd);
+        self
+    }
+
+    /// Name this instance gives in `X-Juicebox-Node`.
+    pub fn node_id(mut self, node_id: impl Into<String>) -> Self {
+        self.node_id = node_id.into();
+        self
+    }
+
+    /// Send lifecycle webhooks as configured in `config`.
+    pub fn webhooks(mut self, config: WebhookConfig) -> Self {
+        self.webhooks = config;
+        self
+    }
+
+    /// Answer cross-origin requests as configured in `config`.
+    pub fn cors(mut self, config: CorsConfig) -> Self {
+        self.cors = config;
+        self
+    }
+
+    /// Look up countries and ASNs for GeoIP bans in `geoip`.
+    pub fn geoip(mut self, geoip: GeoIp) -> Self {
+        self.geoip = geoip;
+        self
+    }
+
+    /// Run `scanner` on uploads after the built-in checks.
+    pub fn content_scanner(mut self, scanner: Arc<dyn ContentScanner>) -> Self {
+        self.content_scanners = self.content_scanners.with(scanner);
+        self
+    }
+
+    /// Glob the templates are loaded from, relative to the working directory.
+    pub fn templates(mut self, glob: impl Into<String>) -> Self {
+        self.templates = glob.into();
+        self
+    }
+
+    /// Start a manual clock at `secs`; move it with `state.clock.advance`.
+    pub fn manual_clock(mut self, secs: u64) -> Self {
+        self.clock = Some(secs);
+        self
+    }
+
+    /// Use `kv` instead of a fresh in-memory store.
+    pub fn kv(mut self, kv: Arc<dyn KvStore>) -> Self {
+        self.kv = Some(kv);
+        self
+    }
+
+    /// Register `meta` for `file` without writing any contents.
+    pub fn with_owner(mut self, file: impl Into<String>, meta: FileMeta) -> Self {
+        self.owners.push((file.into(), meta));
+        self
+    }
+
+    /// Store `contents` as `name`, owned by `owner_ip` and expiring `ttl_secs`
+    /// after the clock's start.
+    pub fn with_file(
+        mut self,
+        name: impl Into<String>,
+        contents: &[u8],
+        owner_ip: impl Into<String>,
+        ttl_secs: u64,
+    ) -> Self {
+        self.files.push(FixtureFile {
+            name: name.into(),
+            contents: contents.to_vec(),
+            owner_ip: owner_ip.into(),
+            ttl_secs,
+        });
+        self
+    }
+
+    /// Ban an IP address or CIDR range.
+    pub fn with_ban(mut self, subject: impl Into<String>, reason: impl Into<String>) -> Self {
+        self.bans.push((subject.into(), reason.into()));
+        self
+    }
+
+    /// Create the directories and assemble the state.
+    ///
+    /// Panics on any setup failure, including an unparsable ban subject.
+    pub fn build(self) -> TestApp {
+        let (root, temp_dir) = match self.root {
+            Some(root) => (root, None),
+            None => {
+                let dir = tempfile::tempdir().expect("create temp dir");
+                (dir.path().to_path_buf(), Some(dir))
+            }
+        };
+        let dirs = StateDirs::under(&root);
+        dirs.create().expect("create test directory");
+        let clock = match self.clock {
+            Some(start) => Clock::manual(start),
+            None => Clock::System,
+        };
+        let (email_tx, notifications) = mpsc::channel(NOTIFICATION_BUFFER);
+        let mail = |value: &str| self.mailgun.then(|| value.to_string());
+        let kv = self
+            .kv
+            .unwrap_or_else(|| Arc::new(MemoryStore::new("test".to_string())));
+
+        let state = StateParts {
+            signing_keys: SigningKeys::parse(&self.signing_keys, &self.hash_secret)
+                .expect("invalid test signing keys"),
+            admin_key: self.admin_key,
+            mailgun_api_key: mail("test_mailgun_api_key"),
+            mailgun_domain: mail("test.mailgun.org"),
+            report_email_to: mail("to@example.com"),
+            report_email_from: mail("from@example.com"),
+            email_privacy: self.email_privacy,
+            email_tx: Some(email_tx),
+            templates: self.templates,
+            node_id: self.node_id,
+            clamd: self.clamd,
+            content_scanners: self.content_scanners,
+            webhooks: self.webhooks,
+            geoip: self.geoip,
+            cors: self.cors,
+            clock,
+            ..StateParts::new(dirs, kv, self.hash_secret, test_telemetry_state())
+        }
+        .assemble()
+        .expect("assemble test state");
+
+        for (file, meta) in self.owners {
+            state.insert_owner(file, meta);
+        }
+        let now = state.now_secs();
+        for fixture in self.files {
+            std::fs::write(state.upload_dir.join(&fixture.name), &fixture.contents)
+                .expect("write fixture file");
+            let owner_hash = hash_ip_string(&state.ip_hash_secret, &fixture.owner_ip)
+                .map(|(_, hash)| hash)
+                .expect("fixture owner must be an IP address");
+            state.insert_owner(
+                fixture.name.clone(),
+                FileMeta {
+                    owner_hash,
+                    expires: now + fixture.ttl_secs,
+                    original_display: display_original_name(&fixture.name),
+                    original: fixture.name,
+                    created: now,
+                    hash: format!("{:x}", Sha256::digest(&fixture.contents)),
+                    max_downloads: None,
+                    downloads: 0,
+                    private: false,
+                    ttl_shortened_from: None,
+                    guest: false,
+                    network_class: None,
+                    size: fixture.contents.len() as u64,
+                },
+            );
+        }
+        {
+            let mut bans = state.bans.try_write().expect("fresh ban list");
+            for (subject, reason) in self.bans {
+                let subject = state
+                    .ban_subject_from_input(&subject)
+                    .expect("fixture ban must be an IP address or CIDR range");
+                bans.push(IpBan {
+                    subject,
+                    label: None,
+                    reason,
+                    time: now,
+                    expires: None,
+                });
+            }
+        }
+
+        TestApp {
+            state,
+            root,
+            temp_dir,
+            notifications,
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use anyhow::{Context, bail};
+use arc_swap::ArcSwap;
+use axum_server::accept::Accept;
+use futures_util::future::BoxFuture;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::server::TlsStream;
+use tracing::{info, warn};
+
+/// Clients get this long to finish the handshake before the connection is
+/// dropped.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often the certificate files are checked for changes.
+pub const WATCH_INTERVAL: Duration = Duration::from_secs(60);
+
+static SERVING_TLS: AtomicBool = AtomicBool::new(false);
+
+/// Whether this process terminates TLS itself, so every request arrived over
+/// HTTPS even without forwarded headers.
+pub fn serving_tls() -> bool {
+    SERVING_TLS.load(Ordering::Relaxed)
+}
+
+/// `TLS_CERT_PATH` and `TLS_KEY_PATH`. Both unset means plain HTTP.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TlsSettings {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+impl TlsSettings {
+    pub fn resolve(lookup: impl Fn(&str) -> Option<String>) -> anyhow::Result<Option<Self>> {
+        let path = |name: &str| {
+            lookup(name)
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+                .map(PathBuf::from)
+        };
+        match (path("TLS_CERT_PATH"), path("TLS_KEY_PATH")) {
+            (None, None) => Ok(None),
+            (Some(cert_path), Some(key_path)) => Ok(Some(Self {
+                cert_path,
+                key_path,
+            })),
+            (Some(_), None) => bail!("TLS_CERT_PATH is set but TLS_KEY_PATH is not"),
+            (None, Some(_)) => bail!("TLS_KEY_PATH is set but TLS_CERT_PATH is not"),
+        }
+    }
+
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        Self::resolve(|name| std::env::var(name).ok())
+    }
+
+    /// Read and check the certificate chain and key.
+    pub fn load(&self) -> anyhow::Result<ServerConfig> {
+        let certs = CertificateDer::pem_file_iter(&self.cert_path)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .with_context(|| format!("failed to read {}", self.cert_path.display()))?;
+        if certs.is_empty() {
+            bail!("no certificates found in {}", self.cert_path.display());
+        }
+        let key = PrivateKeyDer::from_pem_file(&self.key_path)
+            .with_context(|| format!("failed to read {}", self.key_path.display()))?;
+        let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .context("unsupported TLS protocol versions")?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .context("certificate and key do not match")?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(config)
+    }
+
+    fn modified(&self) -> Option<(SystemTime, SystemTime)> {
+        let mtime = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        Some((mtime(&self.cert_path)?, mtime(&self.key_path)?))
+    }
+}
+
+/// Accepts TLS connections with whichever certificate was loaded last.
+/// Clones share the certificate, so a reload reaches the running server.
+#[derive(Clone)]
+pub struct TlsAcceptor {
+    settings: Arc<TlsSettings>,
+    config: Arc<ArcSwap<ServerConfig>>,
+    loaded: Arc<Mutex<Option<(SystemTime, SystemTime)>>>,
+}
+
+impl TlsAcceptor {
+    pub fn new(settings: TlsSettings) -> anyhow::Result<Self> {
+        let loaded = settings.modified();
+        let config = settings.load()?;
+        Ok(Self {
+            settings: Arc::new(settings),
+            config: Arc::new(ArcSwap::from_pointee(config)),
+            loaded: Arc::new(Mutex::new(loaded)),
+        })
+    }
+
+    pub fn settings(&self) -> &TlsSettings {
+        &self.settings
+    }
+
+    /// The configuration new connections are accepted with.
+    pub fn current(&self) -> Arc<ServerConfig> {
+        self.config.load_full()
+    }
+
+    /// Load the files again. A pair that fails to load keeps the old
+    /// certificate in service.
+    pub fn reload(&self) -> anyhow::Result<()> {
+        let modified = self.settings.modified();
+        let config = self.settings.load()?;
+        self.config.store(Arc::new(config));
+        *self.loaded.lock().unwrap_or_else(|e| e.into_inner()) = modified;
+        Ok(())
+    }
+
+    /// Reload when either file's modification time changed since the last
+    /// load. Returns whether a new certificate was installed.
+    pub fn reload_if_changed(&self) -> bool {
+        let modified = self.settings.modified();
+        if modified.is_none() || *self.loaded.lock().unwrap_or_else(|e| e.into_inner()) == modified
+        {
+            return false;
+        }
+        self.log_reload("certificate files changed")
+    }
+
+    fn log_reload(&self, cause: &str) -> bool {
+        match self.reload() {
+            Ok(()) => {
+                info!(
+                    cert = %self.settings.cert_path.display(),
+                    cause,
+                    "TLS certificate reloaded"
+                );
+                true
+            }
+            Err(err) => {
+                warn!(
+                    error = %format!("{err:#}"),
+                    cause,
+                    "TLS certificate reload failed; keeping the current certificate"
+                );
+                false
+            }
+        }
+    }
+
+    /// Mark the process as serving HTTPS, then reload the certificate on
+    /// SIGHUP or when the files change, until the process exits.
+    pub async fn watch(self, interval: Duration) {
+        SERVING_TLS.store(true, Ordering::Relaxed);
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        ticker.tick().await;
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{SignalKind, signal};
+            match signal(SignalKind::hangup()) {
+                Ok(mut hangups) => loop {
+                    tokio::select! {
+                        _ = ticker.tick() => {
+                            self.reload_if_changed();
+                        }
+                        Some(()) = hangups.recv() => {
+                            self.log_reload("SIGHUP");
+                        }
+                    }
+                },
+                Err(err) => warn!(
+                    ?err,
+                    "cannot listen for SIGHUP; TLS reload on signal disabled"
+                ),
+            }
+        }
+        loop {
+            ticker.tick().await;
+            self.reload_if_changed();
+        }
+    }
+}
+
+impl<S: Send + 'static> Accept<TcpStream, S> for TlsAcceptor {
+    type Stream = TlsStream<TcpStream>;
+    type Service = S;
+    type Future = BoxFuture<'static, io::Result<(Self::Stream, Self::Service)>>;
+
+    fn accept(&self, stream: TcpStream, service: S) -> Self::Future {
+        let acceptor = tokio_rustls::TlsAcceptor::from(self.current());
+        Box::pin(async move {
+            let stream = tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream))
+                .await
+                .map_err(|_| {
+                    io::Error::new(io::ErrorKind::TimedOut, "TLS handshake timed out")
+                })??;
+            Ok((stream, service))
+        })
+    }
+}
@@ -0,0 +1,294 @@
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, info, warn};
+
+use crate::state::AppState;
+use crate::webhooks::WebhookEvent;
+
+/// How long an expired file name keeps answering "expired" instead of a plain
+/// 404, unless `JUICEBOX_TOMBSTONE_GRACE_SECS` says otherwise.
+pub const DEFAULT_TOMBSTONE_GRACE_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Why an admin took a file down. The owner is told afterwards.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RemovalReason {
+    Malware,
+    Phishing,
+    Copyright,
+    Illegal,
+    Spam,
+    Abuse,
+    /// The uploader was banned and their files went with them.
+    Banned,
+    Other,
+}
+
+impl RemovalReason {
+    /// Reasons an admin can pick when deleting a single file.
+    pub const ADMIN_CHOICES: [RemovalReason; 7] = [
+        RemovalReason::Other,
+        RemovalReason::Malware,
+        RemovalReason::Phishing,
+        RemovalReason::Copyright,
+        RemovalReason::Illegal,
+        RemovalReason::Spam,
+        RemovalReason::Abuse,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RemovalReason::Malware => "malware",
+            RemovalReason::Phishing => "phishing",
+            RemovalReason::Copyright => "copyright",
+            RemovalReason::Illegal => "illegal",
+            RemovalReason::Spam => "spam",
+            RemovalReason::Abuse => "abuse",
+            RemovalReason::Banned => "banned",
+            RemovalReason::Other => "other",
+        }
+    }
+
+    pub fn parse(raw: &str) -> Option<Self> {
+        let raw = raw.trim();
+        [RemovalReason::Banned]
+            .into_iter()
+            .chain(Self::ADMIN_CHOICES)
+            .find(|reason| reason.as_str().eq_ignore_ascii_case(raw))
+    }
+
+    /// What the owner is shown in place of a plain 404.
+    pub fn message(self) -> String {
+        let violation = match self {
+            RemovalReason::Banned => return "removed because the uploader was banned".into(),
+            RemovalReason::Other => return "removed by an administrator".into(),
+            RemovalReason::Malware => "malware",
+            RemovalReason::Phishing => "phishing",
+            RemovalReason::Copyright => "copyright infringement",
+            RemovalReason::Illegal => "illegal content",
+            RemovalReason::Spam => "spam",
+            RemovalReason::Abuse => "abuse",
+        };
+        format!("removed for ToS violation: {violation}")
+    }
+}
+
+/// A file an admin removed, kept so its owner learns why.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Removal {
+    pub at: u64,
+    pub reason: RemovalReason,
+    pub owner_hash: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tombstone {
+    Expired(u64),
+    Removed(Removal),
+}
+
+impl Tombstone {
+    fn at(&self) -> u64 {
+        match self {
+            Tombstone::Expired(at) => *at,
+            Tombstone::Removed(removal) => removal.at,
+        }
+    }
+
+    /// Expiries keep the bare timestamp they were always stored as.
+    fn encode(&self) -> String {
+        match self {
+            Tombstone::Expired(at) => at.to_string(),
+            Tombstone::Removed(removal) => {
+                serde_json::to_string(removal).expect("removal serialises")
+            }
+        }
+    }
+
+    fn decode(raw: &str) -> Option<Self> {
+        if let Ok(at) = raw.parse::<u64>() {
+            return Some(Tombstone::Expired(at));
+        }
+        serde_json::from_str(raw).ok().map(Tombstone::Removed)
+    }
+}
+
+/// Recently expired or removed files, kept so dead links can say when and why
+/// the file went away rather than looking like they never existed. Expiries
+/// are public; removal reasons are only shown to the file's owner. Files their
+/// owner deleted still return a plain 404.
+pub struct Tombstones {
+    grace_secs: u64,
+    entries: DashMap<String, Tombstone>,
+}
+
+impl Tombstones {
+    /// A grace period of zero disables tombstones entirely.
+    pub fn new(grace_secs: u64) -> Self {
+        Self {
+            grace_secs,
+            entries: DashMap::new(),
+        }
+    }
+
+    /// Read `JUICEBOX_TOMBSTONE_GRACE_SECS`.
+    pub fn from_env() -> Self {
+        let grace = std::env::var("JUICEBOX_TOMBSTONE_GRACE_SECS")
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .unwrap_or(DEFAULT_TOMBSTONE_GRACE_SECS);
+        Self::new(grace)
+    }
+
+    pub fn grace_secs(&self) -> u64 {
+        self.grace_secs
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Remember that `file` expired at `expired_at`. Returns whether anything
+    /// was recorded.
+    pub fn record(&self, file: &str, expired_at: u64) -> bool {
+        if self.grace_secs == 0 {
+            return false;
+        }
+        self.entries
+            .insert(file.to_string(), Tombstone::Expired(expired_at));
+        true
+    }
+
+    /// Remember that an admin removed `owner_hash`'s `file` at `removed_at`.
+    /// Returns whether anything was recorded.
+    pub fn record_removal(
+        &self,
+        file: &str,
+        removed_at: u64,
+        reason: RemovalReason,
+        owner_hash: &str,
+    ) -> bool {
+        if self.grace_secs == 0 {
+            return false;
+        }
+        self.entries.insert(
+            file.to_string(),
+            Tombstone::Removed(Removal {
+                at: removed_at,
+                reason,
+                owner_hash: owner_hash.to_string(),
+            }),
+        );
+        true
+    }
+
+    fn live(&self, file: &str, now: u64) -> Option<Tombstone> {
+        let tombstone = self.entries.get(file)?.clone();
+        (now < tombstone.at().saturating_add(self.grace_secs)).then_some(tombstone)
+    }
+
+    /// When `file` expired, if that was within the grace period.
+    pub fn expired_at(&self, file: &str, now: u64) -> Option<u64> {
+        match self.live(file, now)? {
+            Tombstone::Expired(at) => Some(at),
+            Tombstone::Removed(_) => None,
+        }
+    }
+
+    /// Why `file` was removed, if that was within the grace period.
+    pub fn removal(&self, file: &str, now: u64) -> Option<Removal> {
+        match self.live(file, now)? {
+            Tombstone::Removed(removal) => Some(removal),
+            Tombstone::Expired(_) => None,
+        }
+    }
+
+    /// Files removed from `owner_hash` within the grace period, newest first.
+    pub fn removals_for(&self, owner_hash: &str, now: u64) -> Vec<(String, Removal)> {
+        let mut removed: Vec<(String, Removal)> = self
+            .entries
+            .iter()
+            .filter_map(|entry| match entry.value() {
+                Tombstone::Removed(removal)
+                    if removal.owner_hash == owner_hash
+                        && now < removal.at.saturating_add(self.grace_secs) =>
+                {
+                    Some((entry.key().clone(), removal.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+        removed.sort_by(|a, b| b.1.at.cmp(&a.1.at).then_with(|| a.0.cmp(&b.0)));
+        removed
+    }
+
+    /// Forget tombstones past the grace period, returning how many went.
+    pub fn prune(&self, now: u64) -> usize {
+        let before = self.entries.len();
+        self.entries
+            .retain(|_, tombstone| now < tombstone.at().saturating_add(self.grace_secs));
+        before - self.entries.len()
+    }
+}
+
+impl AppState {
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn load_tombstones(&self) -> anyhow::Result<()> {
+        let entries = self.kv.load_hash("tombstones").await?;
+        for (file, value) in entries {
+            match Tombstone::decode(&value) {
+                Some(tombstone) => {
+                    self.tombstones.entries.insert(file, tombstone);
+                }
+                None => warn!(file, "skipping malformed tombstone"),
+            }
+        }
+        info!(count = self.tombstones.len(), "loaded tombstones");
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn persist_tombstones(&self) {
+        let encoded: Vec<(String, String)> = self
+            .tombstones
+            .entries
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().encode()))
+            .collect();
+        if let Err(err) = self.kv.replace_hash("tombstones", &encoded).await {
+            error!(?err, "failed to persist tombstones to key-value store");
+            return;
+        }
+        debug!(
+            count = encoded.len(),
+            "persisted tombstones to key-value store"
+        );
+    }
+
+    /// Take `file` down for `reason`: drop it from `owners` and storage and
+    /// leave a tombstone telling its owner why. Returns whether it was hosted.
+    #[tracing::instrument(level = "info", skip(self))]
+    pub async fn remove_file_for(&self, file: &str, reason: RemovalReason) -> bool {
+        let Some((_, meta)) = self.remove_owner(file) else {
+            return false;
+        };
+        if let Err(err) = self.file_store.delete(file).await {
+            warn!(?err, file, "failed to remove file from storage");
+        }
+        let recorded =
+            self.tombstones
+                .record_removal(file, self.now_secs(), reason, &meta.owner_hash);
+        self.persist_owners().await;
+        if recorded {
+            self.persist_tombstones().await;
+        }
+        self.webhooks
+            .emit(WebhookEvent::file_deleted(file, reason.as_str()));
+        info!(file, reason = reason.as_str(), "file removed");
+        true
+    }
+}
@@ -0,0 +1,236 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Mutex, RwLock};
+
+/// Sample rate kept while no errors are seen, unless
+/// `SENTRY_TRACES_SAMPLE_RATE_MIN` says otherwise.
+pub const DEFAULT_TRACES_FLOOR: f32 = 0.05;
+/// Share of requests failing with a 5xx at which sampling reaches its
+/// ceiling, unless `SENTRY_TRACES_ERROR_BUDGET` says otherwise.
+pub const DEFAULT_ERROR_BUDGET: f64 = 0.02;
+/// Width of one bucket of the error-rate window.
+const BUCKET_SECS: u64 = 10;
+/// Buckets in the window, so the rate follows the last minute of traffic.
+const BUCKETS: usize = 6;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceSamplingConfig {
+    /// Rate while healthy.
+    pub floor: f32,
+    /// Rate once the error rate reaches the budget.
+    pub ceiling: f32,
+    /// Error rate, from 0 to 1, that counts as a full spike.
+    pub error_budget: f64,
+}
+
+impl Default for TraceSamplingConfig {
+    fn default() -> Self {
+        Self {
+            floor: DEFAULT_TRACES_FLOOR,
+            ceiling: 1.0,
+            error_budget: DEFAULT_ERROR_BUDGET,
+        }
+    }
+}
+
+impl TraceSamplingConfig {
+    /// Rates within 0..=1 with the floor at most the ceiling, and an error
+    /// budget above 0 and at most 1.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(0.0..=1.0).contains(&self.ceiling) {
+            anyhow::bail!("traces sample rate must be between 0 and 1");
+        }
+        if !(0.0..=self.ceiling).contains(&self.floor) {
+            anyhow::bail!("traces sample rate floor must be between 0 and the traces sample rate");
+        }
+        if !(self.error_budget > 0.0 && self.error_budget <= 1.0) {
+            anyhow::bail!("traces error budget must be above 0 and at most 1");
+        }
+        Ok(())
+    }
+}
+
+/// What `/api/admin/v1/runtime` reports under `trace_sampling`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TraceSamplingStatus {
+    /// Share of error events sent to Sentry.
+    pub error_sample_rate: f32,
+    pub floor: f32,
+    pub ceiling: f32,
+    pub error_budget: f64,
+    pub error_rate: f64,
+    pub effective_rate: f32,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Bucket {
+    slot: u64,
+    requests: u64,
+    errors: u64,
+}
+
+#[derive(Default)]
+struct Window {
+    buckets: [Bucket; BUCKETS],
+    error_rate: f64,
+}
+
+/// Picks the Sentry traces sample rate from the recent error rate: the floor
+/// while healthy, rising linearly to the ceiling as 5xx responses approach
+/// the error budget. The rate is recomputed as responses are recorded and
+/// read lock-free by the Sentry sampler.
+///
+/// The share of error events sent lives here as well, so admins can adjust
+/// both through `/api/admin/v1/runtime` without a restart.
+pub struct TraceSampler {
+    config: RwLock<TraceSamplingConfig>,
+    window: Mutex<Window>,
+    rate: AtomicU32,
+    error_sample_rate: AtomicU32,
+}
+
+impl Default for TraceSampler {
+    fn default() -> Self {
+        Self::new(TraceSamplingConfig::default())
+    }
+}
+
+impl TraceSampler {
+    pub fn new(config: TraceSamplingConfig) -> Self {
+        Self {
+            rate: AtomicU32::new(config.floor.to_bits()),
+            config: RwLock::new(config),
+            window: Mutex::new(Window::default()),
+            error_sample_rate: AtomicU32::new(1.0f32.to_bits()),
+        }
+    }
+
+    pub fn config(&self) -> TraceSamplingConfig {
+        self.config.read().expect("trace sampler poisoned").clone()
+    }
+
+    /// Replace floor, ceiling and budget; the rate follows at once.
+    pub fn set_config(&self, config: TraceSamplingConfig) -> anyhow::Result<()> {
+        config.validate()?;
+        let error_rate = self
+            .window
+            .lock()
+            .expect("trace sampler poisoned")
+            .error_rate;
+        *self.config.write().expect("trace sampler poisoned") = config;
+        let rate = self.rate_for(error_rate);
+        self.rate.store(rate.to_bits(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn error_sample_rate(&self) -> f32 {
+        f32::from_bits(self.error_sample_rate.load(Ordering::Relaxed))
+    }
+
+    /// Clamped to 0..=1.
+    pub fn set_error_sample_rate(&self, rate: f32) {
+        let rate = if rate.is_finite() {
+            rate.clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        self.error_sample_rate
+            .store(rate.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Whether to send one error event, at [`Self::error_sample_rate`].
+    pub fn keep_error_event(&self) -> bool {
+        let rate = self.error_sample_rate();
+        rate >= 1.0 || (rate > 0.0 && rand::random::<f32>() < rate)
+    }
+
+    /// The rate new transactions are sampled at.
+    pub fn rate(&self) -> f32 {
+        f32::from_bits(self.rate.load(Ordering::Relaxed))
+    }
+
+    /// Count one finished request at `now` (seconds).
+    pub fn record(&self, now: u64, server_error: bool) {
+        let slot = now / BUCKET_SECS;
+        let mut window = self.window.lock().expect("trace sampler poisoned");
+        let bucket = &mut window.buckets[(slot % BUCKETS as u64) as usize];
+        if bucket.slot != slot {
+            *bucket = Bucket {
+                slot,
+                ..Bucket::default()
+            };
+        }
+        bucket.requests += 1;
+        bucket.errors += u64::from(server_error);
+        let oldest = slot.saturating_sub(BUCKETS as u64 - 1);
+        let (requests, errors) = window
+            .buckets
+            .iter()
+            .filter(|b| b.slot >= oldest)
+            .fold((0, 0), |(r, e), b| (r + b.requests, e + b.errors));
+        window.error_rate = errors as f64 / requests.max(1) as f64;
+        let rate = self.rate_for(window.error_rate);
+        self.rate.store(rate.to_bits(), Ordering::Relaxed);
+    }
+
+    fn rate_for(&self, error_rate: f64) -> f32 {
+        let config = self.config.read().expect("trace sampler poisoned");
+        let pressure = (error_rate / config.error_budget).min(1.0) as f32;
+        config.floor + (config.ceiling - config.floor) * pressure
+    }
+
+    pub fn status(&self) -> TraceSamplingStatus {
+        let error_rate = self
+            .window
+            .lock()
+            .expect("trace sampler poisoned")
+            .error_rate;
+        let config = self.config();
+        TraceSamplingStatus {
+            error_sample_rate: self.error_sample_rate(),
+            floor: config.floor,
+            ceiling: config.ceiling,
+            error_budget: config.error_budget,
+            error_rate,
+            effective_rate: self.rate(),
+        }
+    }
+}
+
+/// Body of `PATCH /api/admin/v1/runtime`: sampling rates to change until
+/// the next restart. Fields left out keep their current value.
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SamplingUpdate {
+    pub traces_sample_rate: Option<f32>,
+    pub traces_sample_rate_min: Option<f32>,
+    pub traces_error_budget: Option<f64>,
+    pub error_sample_rate: Option<f32>,
+}
+
+impl SamplingUpdate {
+    /// Apply every change or, if any value is out of range, none.
+    pub fn apply(&self, sampler: &TraceSampler) -> anyhow::Result<()> {
+        let mut config = sampler.config();
+        if let Some(ceiling) = self.traces_sample_rate {
+            config.ceiling = ceiling;
+        }
+        if let Some(floor) = self.traces_sample_rate_min {
+            config.floor = floor;
+        }
+        if let Some(budget) = self.traces_error_budget {
+            config.error_budget = budget;
+        }
+        config.validate()?;
+        if let Some(rate) = self.error_sample_rate
+            && !(0.0..=1.0).contains(&rate)
+        {
+            anyhow::bail!("error sample rate must be between 0 and 1");
+        }
+        sampler.set_config(config)?;
+        if let Some(rate) = self.error_sample_rate {
+            sampler.set_error_sample_rate(rate);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,264 @@
+use anyhow::{Result, anyhow};
+use sha2::{Digest, Sha256};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+use crate::util::now_secs;
+
+/// Chain value preceding the first entry of a fresh log.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One accepted upload as recorded in the transparency log. Filenames and
+/// owner identifiers are deliberately absent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransparencyEntry {
+    pub seq: u64,
+    pub time: u64,
+    pub sha256: String,
+    pub size: u64,
+    pub chain: String,
+}
+
+impl TransparencyEntry {
+    fn payload(seq: u64, time: u64, sha256: &str, size: u64) -> String {
+        format!("{seq} {time} {sha256} {size}")
+    }
+
+    fn to_line(&self) -> String {
+        format!(
+            "{} {}\n",
+            Self::payload(self.seq, self.time, &self.sha256, self.size),
+            self.chain
+        )
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.split_ascii_whitespace();
+        let entry = Self {
+            seq: parts.next()?.parse().ok()?,
+            time: parts.next()?.parse().ok()?,
+            sha256: parts.next()?.to_string(),
+            size: parts.next()?.parse().ok()?,
+            chain: parts.next()?.to_string(),
+        };
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(entry)
+    }
+}
+
+/// Chain hash for an entry: `sha256(prev_chain "\n" "seq time sha256 size")`.
+pub fn chain_hash(prev: &str, seq: u64, time: u64, sha256: &str, size: u64) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(prev.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(TransparencyEntry::payload(seq, time, sha256, size).as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Walk every line of a log and confirm each chain value links to the previous
+/// one. Returns the last entry (if any), or the 1-based line number that broke
+/// the chain.
+pub fn verify_chain(contents: &str) -> std::result::Result<Option<TransparencyEntry>, usize> {
+    let mut prev = GENESIS_HASH.to_string();
+    let mut last = None;
+    let mut expected_seq = 1u64;
+    for (idx, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some(entry) = TransparencyEntry::parse(line) else {
+            return Err(idx + 1);
+        };
+        if entry.seq != expected_seq
+            || chain_hash(&prev, entry.seq, entry.time, &entry.sha256, entry.size) != entry.chain
+        {
+            return Err(idx + 1);
+        }
+        prev = entry.chain.clone();
+        expected_seq += 1;
+        last = Some(entry);
+    }
+    Ok(last)
+}
+
+struct ChainHead {
+    seq: u64,
+    chain: String,
+}
+
+/// Append-only, hash-chained record of `(timestamp, sha256, size)` for every
+/// upload the server accepted.
+pub struct TransparencyLog {
+    path: PathBuf,
+    head: Mutex<ChainHead>,
+}
+
+impl TransparencyLog {
+    /// Open (or lazily create) the log at `path`, resuming the chain from the
+    /// last valid entry on disk. A final line without its newline is what a
+    /// crash mid-append leaves behind; it is cut off rather than treated as
+    /// tampering, so only a complete line that breaks the chain is an error.
+    pub fn open(path: PathBuf) -> Result<Self> {
+        let mut contents = match std::fs::read(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+        if contents.last().is_some_and(|b| *b != b'\n') {
+            let complete = contents
+                .iter()
+                .rposition(|b| *b == b'\n')
+                .map_or(0, |idx| idx + 1);
+            warn!(
+                path = ?path,
+                dropped = contents.len() - complete,
+                "truncating incomplete final transparency log line"
+            );
+            std::fs::OpenOptions::new()
+                .write(true)
+                .open(&path)?
+                .set_len(complete as u64)?;
+            contents.truncate(complete);
+        }
+        let contents = String::from_utf8(contents)
+            .map_err(|_| anyhow!("transparency log {} is not UTF-8", path.display()))?;
+        let head = match verify_chain(&contents) {
+            Ok(Some(last)) => ChainHead {
+                seq: last.seq,
+                chain: last.chain,
+            },
+            Ok(None) => ChainHead {
+                seq: 0,
+                chain: GENESIS_HASH.to_string(),
+            },
+            Err(line) => {
+                return Err(anyhow!(
+                    "transparency log {} failed chain verification at line {line}",
+                    path.display()
+                ));
+            }
+        };
+        debug!(path = ?path, entries = head.seq, "opened transparency log");
+        Ok(Self {
+            path,
+            head: Mutex::new(head),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub async fn append(&self, sha256: &str, size: u64) -> Result<TransparencyEntry> {
+        let mut head = self.head.lock().await;
+        let seq = head.seq + 1;
+        let time = now_secs();
+        let entry = TransparencyEntry {
+            seq,
+            time,
+            sha256: sha256.to_string(),
+            size,
+            chain: chain_hash(&head.chain, seq, time, sha256, size),
+        };
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(entry.to_line().as_bytes()).await?;
+        file.flush().await?;
+        head.seq = seq;
+        head.chain = entry.chain.clone();
+        debug!(seq, size, "appended transparency log entry");
+        Ok(entry)
+    }
+
+    /// Current log contents, read under the append lock so a download never
+    /// observes a half-written line.
+    pub async fn contents(&self) -> Result<Vec<u8>> {
+        let _head = self.head.lock().await;
+        match fs::read(&self.path).await {
+            Ok(bytes) => Ok(bytes),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Record an accepted upload, logging instead of failing when the log
+    /// cannot be written so uploads are never rejected on its account.
+    pub async fn record(&self, sha256: &str, size: u64) {
+        if let Err(err) = self.append(sha256, size).await {
+            warn!(?err, path = ?self.path, "failed to append transparency log entry");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn append_chains_entries_and_resumes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("transparency.log");
+        let log = TransparencyLog::open(path.clone()).unwrap();
+        let first = log.append(&"a".repeat(64), 10).await.unwrap();
+        let second = log.append(&"b".repeat(64), 20).await.unwrap();
+        assert_eq!(first.seq, 1);
+        assert_eq!(second.seq, 2);
+        assert_eq!(
+            second.chain,
+            chain_hash(&first.chain, 2, second.time, &second.sha256, 20)
+        );
+
+        let reopened = TransparencyLog::open(path.clone()).unwrap();
+        let third = reopened.append(&"c".repeat(64), 30).await.unwrap();
+        assert_eq!(third.seq, 3);
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(verify_chain(&contents), Ok(Some(third)));
+    }
+
+    #[tokio::test]
+    async fn tampered_log_fails_verification() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("transparency.log");
+        let log = TransparencyLog::open(path.clone()).unwrap();
+        log.append(&"a".repeat(64), 10).await.unwrap();
+        log.append(&"b".repeat(64), 20).await.unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let tampered = contents.replacen(" 10 ", " 11 ", 1);
+        assert_eq!(verify_chain(&tampered), Err(1));
+        std::fs::write(&path, tampered).unwrap();
+        assert!(TransparencyLog::open(path).is_err());
+    }
+
+    #[tokio::test]
+    async fn torn_final_line_is_truncated_on_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("transparency.log");
+        let log = TransparencyLog::open(path.clone()).unwrap();
+        let first = log.append(&"a".repeat(64), 10).await.unwrap();
+        let intact = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, format!("{intact}2 1700000000 bbbb")).unwrap();
+
+        let reopened = TransparencyLog::open(path.clone()).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), intact);
+        let second = reopened.append(&"b".repeat(64), 20).await.unwrap();
+        assert_eq!(second.seq, 2);
+        assert_eq!(
+            second.chain,
+            chain_hash(&first.chain, 2, second.time, &second.sha256, 20)
+        );
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(verify_chain(&contents), Ok(Some(second)));
+    }
+}
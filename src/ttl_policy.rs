@@ -0,0 +1,264 @@
+use anyhow::{Context, bail};
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+use crate::network_class::NetworkClass;
+use crate::state::AppState;
+use crate::util::{headers_trusted, ttl_to_duration};
+
+/// What an upload policy rule can match on, gathered once per request.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClientAttributes {
+    /// ISO country code from `CF-IPCountry`, only read behind a trusted proxy.
+    pub country: Option<String>,
+    /// Primary subtag of the first `Accept-Language` entry, lowercased.
+    pub language: Option<String>,
+    /// Whether the client IP is a Tor exit, from the policy's own list or
+    /// the fetched network lists.
+    pub tor_exit: bool,
+    pub network: NetworkClass,
+}
+
+impl ClientAttributes {
+    pub fn from_request(
+        state: &AppState,
+        headers: &HeaderMap,
+        peer: &SocketAddr,
+        client_ip: &str,
+    ) -> Self {
+        let country = headers_trusted(headers, Some(peer.ip()))
+            .then(|| headers.get("CF-IPCountry")?.to_str().ok())
+            .flatten()
+            .map(|c| c.trim().to_ascii_uppercase())
+            .filter(|c| c.len() == 2 && c != "XX");
+        let language = headers
+            .get(axum::http::header::ACCEPT_LANGUAGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .and_then(|tag| tag.split(';').next())
+            .and_then(|tag| tag.trim().split('-').next())
+            .map(|tag| tag.to_ascii_lowercase())
+            .filter(|tag| !tag.is_empty() && tag != "*");
+        let network = state.network_class(client_ip);
+        let tor_exit = network == NetworkClass::Tor
+            || client_ip
+                .parse::<IpAddr>()
+                .is_ok_and(|ip| state.ttl_policy.tor_exits.contains(&ip));
+        Self {
+            country,
+            language,
+            tor_exit,
+            network,
+        }
+    }
+}
+
+/// One rule of the policy file. Every criterion that is given must match;
+/// a rule without criteria matches everyone.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TtlRule {
+    pub name: String,
+    #[serde(default)]
+    pub countries: Vec<String>,
+    #[serde(default)]
+    pub languages: Vec<String>,
+    #[serde(default)]
+    pub tor_exit: Option<bool>,
+    #[serde(default)]
+    pub networks: Vec<NetworkClass>,
+    #[serde(default)]
+    pub default_ttl: Option<String>,
+    #[serde(default)]
+    pub max_ttl: Option<String>,
+}
+
+impl TtlRule {
+    fn matches(&self, attrs: &ClientAttributes) -> bool {
+        let country = self.countries.is_empty()
+            || attrs
+                .country
+                .as_deref()
+                .is_some_and(|c| self.countries.iter().any(|r| r.eq_ignore_ascii_case(c)));
+        let language = self.languages.is_empty()
+            || attrs
+                .language
+                .as_deref()
+                .is_some_and(|l| self.languages.iter().any(|r| r.eq_ignore_ascii_case(l)));
+        let tor = self.tor_exit.is_none_or(|want| want == attrs.tor_exit);
+        let network = self.networks.is_empty() || self.networks.contains(&attrs.network);
+        country && language && tor && network
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct TtlPolicyFile {
+    #[serde(default)]
+    default_ttl: Option<String>,
+    #[serde(default)]
+    max_ttl: Option<String>,
+    /// Tor exit addresses, one per line; relative to the policy file.
+    #[serde(default)]
+    tor_exit_list: Option<PathBuf>,
+    #[serde(default)]
+    rules: Vec<TtlRule>,
+}
+
+#[derive(Clone, Debug)]
+struct CompiledRule {
+    rule: TtlRule,
+    default_secs: Option<u64>,
+    max_secs: Option<u64>,
+}
+
+/// Default and maximum upload TTLs by client attributes. The first matching
+/// rule wins; limits it leaves out fall back to the top-level ones, and
+/// without those uploads keep the TTL they asked for.
+#[derive(Clone, Debug, Default)]
+pub struct TtlPolicy {
+    default_secs: Option<u64>,
+    max_secs: Option<u64>,
+    rules: Vec<CompiledRule>,
+    tor_exits: HashSet<IpAddr>,
+}
+
+/// The policy applied to one upload, echoed back to the client.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EffectiveTtl {
+    /// Name of the matching rule, if any.
+    pub rule: Option<String>,
+    /// TTL used when the upload does not ask for one, in seconds.
+    pub default_ttl: u64,
+    /// Longest TTL allowed, in seconds.
+    pub max_ttl: Option<u64>,
+    /// TTL the upload was given, in seconds.
+    pub ttl: u64,
+}
+
+impl EffectiveTtl {
+    /// The same policy with every lifetime capped at `max` seconds.
+    pub fn capped(self, max: u64) -> Self {
+        Self {
+            default_ttl: self.default_ttl.min(max),
+            max_ttl: Some(self.max_ttl.map_or(max, |m| m.min(max))),
+            ttl: self.ttl.min(max),
+            ..self
+        }
+    }
+}
+
+/// Parse `90`, `30m`, `12h` or `7d` into seconds.
+pub fn parse_ttl_secs(raw: &str) -> Option<u64> {
+    let raw = raw.trim();
+    let (digits, unit) = match raw.char_indices().last()? {
+        (i, c) if c.is_ascii_alphabetic() => (&raw[..i], c.to_ascii_lowercase()),
+        _ => (raw, 's'),
+    };
+    let n = digits.parse::<u64>().ok().filter(|n| *n > 0)?;
+    let scale = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => return None,
+    };
+    n.checked_mul(scale)
+}
+
+fn parse_limit(field: &str, raw: Option<&String>) -> anyhow::Result<Option<u64>> {
+    raw.map(|raw| parse_ttl_secs(raw).with_context(|| format!("invalid {field} {raw:?}")))
+        .transpose()
+}
+
+impl TtlPolicy {
+    /// Read a policy file. See the README for its format.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("failed to read ttl policy {}", path.display()))?;
+        let file: TtlPolicyFile = serde_json::from_slice(&bytes)
+            .with_context(|| format!("failed to parse ttl policy {}", path.display()))?;
+        let mut policy = Self {
+            default_secs: parse_limit("default_ttl", file.default_ttl.as_ref())?,
+            max_secs: parse_limit("max_ttl", file.max_ttl.as_ref())?,
+            ..Self::default()
+        };
+        for rule in file.rules {
+            if rule.name.trim().is_empty() {
+                bail!("ttl policy rules need a name");
+            }
+            policy.rules.push(CompiledRule {
+                default_secs: parse_limit("default_ttl", rule.default_ttl.as_ref())?,
+                max_secs: parse_limit("max_ttl", rule.max_ttl.as_ref())?,
+                rule,
+            });
+        }
+        if let Some(list) = file.tor_exit_list {
+            let list = path.parent().unwrap_or(Path::new(".")).join(list);
+            let text = std::fs::read_to_string(&list)
+                .with_context(|| format!("failed to read tor exit list {}", list.display()))?;
+            policy.tor_exits = text
+                .lines()
+                .map(|line| line.split('#').next().unwrap_or("").trim())
+                .filter_map(|line| line.parse::<IpAddr>().ok())
+                .collect();
+        }
+        info!(
+            rules = policy.rules.len(),
+            tor_exits = policy.tor_exits.len(),
+            "loaded ttl policy"
+        );
+        Ok(policy)
+    }
+
+    /// Load `JUICEBOX_TTL_POLICY`, or `ttl_policy.json` in the data dir when
+    /// that exists. No file means no policy.
+    pub fn from_env(data_dir: &Path) -> anyhow::Result<Self> {
+        match std::env::var("JUICEBOX_TTL_POLICY") {
+            Ok(path) if !path.trim().is_empty() => Self::load(Path::new(path.trim())),
+            _ => {
+                let path = data_dir.join("ttl_policy.json");
+                if path.exists() {
+                    Self::load(&path)
+                } else {
+                    debug!("no ttl policy configured");
+                    Ok(Self::default())
+                }
+            }
+        }
+    }
+
+    pub fn rule_count(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn tor_exit_count(&self) -> usize {
+        self.tor_exits.len()
+    }
+
+    /// TTL for an upload that asked for `requested` (a TTL code such as `1d`),
+    /// falling back to `fallback_code` where no policy default applies.
+    pub fn resolve(
+        &self,
+        attrs: &ClientAttributes,
+        requested: Option<&str>,
+        fallback_code: &str,
+    ) -> EffectiveTtl {
+        let rule = self.rules.iter().find(|r| r.rule.matches(attrs));
+        let default_ttl = rule
+            .and_then(|r| r.default_secs)
+            .or(self.default_secs)
+            .unwrap_or_else(|| ttl_to_duration(fallback_code).as_secs());
+        let max_ttl = rule.and_then(|r| r.max_secs).or(self.max_secs);
+        let default_ttl = max_ttl.map_or(default_ttl, |max| default_ttl.min(max));
+        let asked = requested.map_or(default_ttl, |code| ttl_to_duration(code).as_secs());
+        EffectiveTtl {
+            rule: rule.map(|r| r.rule.name.clone()),
+            default_ttl,
+            max_ttl,
+            ttl: max_ttl.map_or(asked, |max| asked.min(max)),
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! Anonymous counters describing how clients upload: which API, what chunk
+//! size they picked, how big the file was and roughly which client sent it.
+//! Nothing here identifies a client; each completed upload only bumps one
+//! bucket per dimension, so defaults like the chunk size can be tuned from
+//! real traffic.
+
+use axum::http::HeaderMap;
+use axum::http::header::USER_AGENT;
+use dashmap::DashMap;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{debug, error, info, warn};
+
+use crate::handlers::telemetry::has_privacy_signal;
+use crate::state::AppState;
+
+/// Which API accepted the upload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UploadMethod {
+    /// Multipart form posted to `/upload`.
+    Form,
+    /// `/simple/upload`.
+    Simple,
+    Paste,
+    /// `/chunk/init` followed by chunk PUTs and a completion call.
+    Chunked,
+    Tus,
+    S3,
+}
+
+impl UploadMethod {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UploadMethod::Form => "form",
+            UploadMethod::Simple => "simple",
+            UploadMethod::Paste => "paste",
+            UploadMethod::Chunked => "chunked",
+            UploadMethod::Tus => "tus",
+            UploadMethod::S3 => "s3",
+        }
+    }
+}
+
+const KIB: u64 = 1024;
+const MIB: u64 = 1024 * KIB;
+const GIB: u64 = 1024 * MIB;
+
+const SIZE_BUCKETS: &[(u64, &str)] = &[
+    (100 * KIB, "<100KiB"),
+    (MIB, "<1MiB"),
+    (10 * MIB, "<10MiB"),
+    (100 * MIB, "<100MiB"),
+    (GIB, "<1GiB"),
+];
+const SIZE_TOP_BUCKET: &str = ">=1GiB";
+
+/// Chunk sizes are counted by the power of two they round up to, up to
+/// this; anything larger shares one bucket.
+const CHUNK_BUCKETS: &[(u64, &str)] = &[
+    (256 * KIB, "<=256KiB"),
+    (512 * KIB, "<=512KiB"),
+    (MIB, "<=1MiB"),
+    (2 * MIB, "<=2MiB"),
+    (4 * MIB, "<=4MiB"),
+    (8 * MIB, "<=8MiB"),
+    (16 * MIB, "<=16MiB"),
+    (32 * MIB, "<=32MiB"),
+    (64 * MIB, "<=64MiB"),
+];
+const CHUNK_TOP_BUCKET: &str = ">64MiB";
+
+pub fn size_bucket(bytes: u64) -> &'static str {
+    SIZE_BUCKETS
+        .iter()
+        .find(|(limit, _)| bytes < *limit)
+        .map(|(_, label)| *label)
+        .unwrap_or(SIZE_TOP_BUCKET)
+}
+
+pub fn chunk_size_bucket(bytes: u64) -> &'static str {
+    CHUNK_BUCKETS
+        .iter()
+        .find(|(limit, _)| bytes <= *limit)
+        .map(|(_, label)| *label)
+        .unwrap_or(CHUNK_TOP_BUCKET)
+}
+
+/// Coarse client family from a `User-Agent`. Order matters: Edge and
+/// Chrome both claim to be Safari, and Edge claims to be Chrome.
+pub fn agent_family(user_agent: Option<&str>) -> &'static str {
+    let Some(ua) = user_agent.map(str::trim).filter(|ua| !ua.is_empty()) else {
+        return "unknown";
+    };
+    let ua = ua.to_ascii_lowercase();
+    const FAMILIES: &[(&str, &str)] = &[
+        ("sharex", "sharex"),
+        ("curl/", "curl"),
+        ("wget/", "wget"),
+        ("python", "python"),
+        ("go-http-client", "go"),
+        ("okhttp", "okhttp"),
+        ("node", "node"),
+        ("axios", "node"),
+        ("firefox/", "firefox"),
+        ("edg/", "edge"),
+        ("chrome/", "chrome"),
+        ("chromium/", "chrome"),
+        ("safari/", "safari"),
+    ];
+    FAMILIES
+        .iter()
+        .find(|(needle, _)| ua.contains(needle))
+        .map(|(_, family)| *family)
+        .unwrap_or("other")
+}
+
+/// Totals per bucket for each dimension, as served to admins.
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct UploadStatsSnapshot {
+    pub total: u64,
+    pub by_method: BTreeMap<String, u64>,
+    /// Only uploads that chose a chunk size (chunked and tus).
+    pub by_chunk_size: BTreeMap<String, u64>,
+    pub by_file_size: BTreeMap<String, u64>,
+    pub by_client: BTreeMap<String, u64>,
+}
+
+const DIMENSIONS: &[&str] = &["method", "chunk_size", "file_size", "client"];
+
+/// Counters keyed `dimension:bucket`. Like the ban hit counters they live in
+/// memory and reach the store with the periodic cleanup and on shutdown.
+#[derive(Default)]
+pub struct UploadStats {
+    counts: DashMap<String, u64>,
+    dirty: AtomicBool,
+}
+
+impl UploadStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn bump(&self, dimension: &str, bucket: &str) {
+        *self
+            .counts
+            .entry(format!("{dimension}:{bucket}"))
+            .or_insert(0) += 1;
+    }
+
+    /// Count one completed upload of `size` bytes.
+    pub fn record(
+        &self,
+        method: UploadMethod,
+        size: u64,
+        chunk_size: Option<u64>,
+        user_agent: Option<&str>,
+    ) {
+        self.bump("method", method.as_str());
+        if let Some(chunk_size) = chunk_size {
+            self.bump("chunk_size", chunk_size_bucket(chunk_size));
+        }
+        self.bump("file_size", size_bucket(size));
+        self.bump("client", agent_family(user_agent));
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> UploadStatsSnapshot {
+        let mut snapshot = UploadStatsSnapshot::default();
+        for entry in self.counts.iter() {
+            let Some((dimension, bucket)) = entry.key().split_once(':') else {
+                continue;
+            };
+            let map = match dimension {
+                "method" => {
+                    snapshot.total += *entry.value();
+                    &mut snapshot.by_method
+                }
+                "chunk_size" => &mut snapshot.by_chunk_size,
+                "file_size" => &mut snapshot.by_file_size,
+                "client" => &mut snapshot.by_client,
+                _ => continue,
+            };
+            map.insert(bucket.to_string(), *entry.value());
+        }
+        snapshot
+    }
+}
+
+impl AppState {
+    /// Count a completed upload unless the client asked not to be measured.
+    pub fn record_upload_client(
+        &self,
+        headers: &HeaderMap,
+        method: UploadMethod,
+        size: u64,
+        chunk_size: Option<u64>,
+    ) {
+        if self.telemetry.respect_privacy_signals && has_privacy_signal(headers) {
+            return;
+        }
+        let user_agent = headers.get(USER_AGENT).and_then(|v| v.to_str().ok());
+        self.upload_stats
+            .record(method, size, chunk_size, user_agent);
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn load_upload_stats(&self) -> anyhow::Result<()> {
+        let entries = self.kv.load_hash("upload_stats").await?;
+        for (key, value) in entries {
+            let known = key
+                .split_once(':')
+                .is_some_and(|(dimension, _)| DIMENSIONS.contains(&dimension));
+            match value.parse::<u64>() {
+                Ok(count) if known => {
+                    self.upload_stats.counts.insert(key, count);
+                }
+                _ => warn!(key, "skipping malformed upload stats counter"),
+            }
+        }
+        info!(
+            count = self.upload_stats.counts.len(),
+            "loaded upload stats counters"
+        );
+        Ok(())
+    }
+
+    /// Write the counters back if any changed since the last write.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn persist_upload_stats(&self) {
+        if !self.upload_stats.dirty.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        let encoded: Vec<(String, String)> = self
+            .upload_stats
+            .counts
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().to_string()))
+            .collect();
+        if let Err(err) = self.kv.replace_hash("upload_stats", &encoded).await {
+            error!(?err, "failed to persist upload stats to key-value store");
+            self.upload_stats.dirty.store(true, Ordering::Relaxed);
+            return;
+        }
+        debug!(
+            count = encoded.len(),
+            "persisted upload stats to key-value store"
+        );
+    }
+}
@@ -0,0 +1,215 @@
+//! Notices file bodies removed from the upload directory behind the
+//! server's back, e.g. an operator deleting blobs by hand, and drops their
+//! metadata straight away instead of serving 404s until the next integrity
+//! pass.
+//!
+//! The directory is watched with `notify` (inotify, FSEvents, ...), so only
+//! the names a removal or rename event mentions are checked. When the OS
+//! drops events and asks for a rescan, the directory is listed once and
+//! compared against every hosted name.
+
+use notify::event::{EventKind, ModifyKind};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs;
+use tokio::sync::{Notify, mpsc};
+use tokio::task::JoinHandle;
+use tracing::{Instrument, debug, info, trace, warn};
+
+use crate::handlers::admin::AUDIT_LOG_TARGET;
+use crate::state::AppState;
+use crate::webhooks::WebhookEvent;
+
+/// `file.deleted` reason when the body disappeared from the upload
+/// directory without going through the server.
+pub const DELETED_EXTERNALLY: &str = "external";
+
+/// Whether `JUICEBOX_UPLOAD_WATCH` turns the watcher on (default: off).
+pub fn watch_enabled_from_env() -> bool {
+    std::env::var("JUICEBOX_UPLOAD_WATCH")
+        .map(|v| {
+            matches!(
+                v.trim().to_ascii_lowercase().as_str(),
+                "1" | "true" | "yes" | "on"
+            )
+        })
+        .unwrap_or(false)
+}
+
+/// What the upload directory's events ask to be checked.
+enum Pending {
+    Names(HashSet<String>),
+    Rescan,
+}
+
+/// Filesystem events for the upload directory. Dropping it stops watching.
+pub struct UploadWatcher {
+    dir: PathBuf,
+    events: mpsc::UnboundedReceiver<notify::Result<Event>>,
+    _watcher: RecommendedWatcher,
+}
+
+impl UploadWatcher {
+    pub fn new(dir: impl Into<PathBuf>) -> notify::Result<Self> {
+        let dir = dir.into();
+        let (tx, events) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = tx.send(event);
+        })?;
+        watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+        Ok(Self {
+            dir,
+            events,
+            _watcher: watcher,
+        })
+    }
+
+    /// Wait for the next removals from the directory and drop the metadata
+    /// of every hosted file whose body went with them. Returns the storage
+    /// names removed, or `None` once the watcher has stopped.
+    pub async fn next(&mut self, state: &AppState) -> Option<Vec<String>> {
+        let first = self.events.recv().await?;
+        let mut pending = Pending::Names(HashSet::new());
+        collect(first, &mut pending);
+        while let Ok(event) = self.events.try_recv() {
+            collect(event, &mut pending);
+        }
+        let names = match pending {
+            Pending::Names(names) => names.into_iter().collect(),
+            Pending::Rescan => {
+                debug!(dir = ?self.dir, "upload directory events lost, rescanning");
+                // Names are taken before the listing so an upload finishing
+                // in between is never mistaken for a deletion.
+                let hosted: Vec<String> = state.owners.iter().map(|e| e.key().clone()).collect();
+                match list_dir(&self.dir).await {
+                    Ok(present) => hosted
+                        .into_iter()
+                        .filter(|name| !present.contains(name))
+                        .collect(),
+                    Err(err) => {
+                        warn!(%err, dir = ?self.dir, "failed to list upload directory");
+                        Vec::new()
+                    }
+                }
+            }
+        };
+        Some(forget_missing(state, names).await)
+    }
+}
+
+fn collect(event: notify::Result<Event>, pending: &mut Pending) {
+    let event = match event {
+        Ok(event) => event,
+        Err(err) => {
+            warn!(%err, "upload directory watch error");
+            *pending = Pending::Rescan;
+            return;
+        }
+    };
+    if event.need_rescan() {
+        *pending = Pending::Rescan;
+        return;
+    }
+    if !matches!(
+        event.kind,
+        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_))
+    ) {
+        return;
+    }
+    trace!(kind = ?event.kind, paths = ?event.paths, "upload directory event");
+    if let Pending::Names(names) = pending {
+        names.extend(
+            event
+                .paths
+                .iter()
+                .filter_map(|path| path.file_name()?.to_str().map(str::to_string)),
+        );
+    }
+}
+
+/// Drop the metadata of each of `names` that is still hosted but has no
+/// body in the store.
+async fn forget_missing(state: &AppState, names: Vec<String>) -> Vec<String> {
+    let mut removed = Vec::new();
+    for name in names {
+        // Deletions made by the server drop the metadata first, so a name
+        // still hosted with no body was removed by someone else.
+        if !state.owners.contains_key(&name) || state.file_store.exists(&name).await {
+            continue;
+        }
+        let Some((_, meta)) = state.remove_owner(&name) else {
+            continue;
+        };
+        info!(
+            target: AUDIT_LOG_TARGET,
+            action = "external_delete",
+            file = %name,
+            owner_hash = %meta.owner_hash,
+            size = meta.size,
+            "file body removed outside the server, metadata dropped"
+        );
+        state.webhooks.emit(WebhookEvent::FileDeleted {
+            file: name.clone(),
+            reason: DELETED_EXTERNALLY.to_string(),
+        });
+        removed.push(name);
+    }
+    if !removed.is_empty() {
+        state.persist_owners().await;
+    }
+    removed
+}
+
+async fn list_dir(dir: &Path) -> std::io::Result<HashSet<String>> {
+    let mut names = HashSet::new();
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if let Some(name) = entry.file_name().to_str() {
+            names.insert(name.to_string());
+        }
+    }
+    Ok(names)
+}
+
+impl AppState {
+    /// Start watching the upload directory when `JUICEBOX_UPLOAD_WATCH` is
+    /// set and bodies are stored locally.
+    pub fn spawn_upload_watcher(&self, shutdown: Arc<Notify>) -> Option<JoinHandle<()>> {
+        if !watch_enabled_from_env() {
+            return None;
+        }
+        if self.file_store.backend_name() != "local" {
+            debug!(
+                backend = self.file_store.backend_name(),
+                "upload directory watcher only applies to the local file store"
+            );
+            return None;
+        }
+        let mut watcher = match UploadWatcher::new(self.upload_dir.as_ref()) {
+            Ok(watcher) => watcher,
+            Err(err) => {
+                warn!(%err, dir = ?self.upload_dir, "failed to watch upload directory");
+                return None;
+            }
+        };
+        let state = self.clone();
+        Some(tokio::spawn(
+            async move {
+                loop {
+                    tokio::select! {
+                        _ = shutdown.notified() => break,
+                        removed = watcher.next(&state) => {
+                            if removed.is_none() {
+                                break;
+                            }
+                        }
+                    }
+                }
+                debug!("upload directory watcher stopped");
+            }
+            .instrument(tracing::info_span!("maintenance.upload_watch")),
+        ))
+    }
+}
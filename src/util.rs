@@ -8,23 +8,45 @@ use sanitize_filename::sanitize;
 use serde::{Deserialize, Serialize};
 use sha2::Sha256;
 use std::{
-    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::atomic::{AtomicU64, Ordering},
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 // removed rand; using cuid now
+use crate::reload::{self, HotSettings};
+use crate::request_id::current_request_id;
 use crate::state::AppState;
 use once_cell::sync::Lazy;
-use std::sync::RwLock;
+use unicode_normalization::UnicodeNormalization;
 
 // Public constants
 // RANDOM_NAME_LEN removed (no longer needed with CUID)
 pub const UPLOAD_CONCURRENCY: usize = 8;
-// Replace const with a static that reads from env at startup
-static MAX_FILE_BYTES: Lazy<u64> = Lazy::new(|| {
-    std::env::var("MAX_FILE_SIZE")
+/// Address the HTTP server binds to.
+pub const LISTEN_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 1200);
+static MAX_FILENAME_CHARS: Lazy<usize> = Lazy::new(|| {
+    std::env::var("MAX_FILENAME_LENGTH")
         .ok()
-        .and_then(|v| parse_size_bytes(&v))
-        .unwrap_or(500 * 1024 * 1024) // default 500MB
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .filter(|v| *v >= 8)
+        .unwrap_or(120)
+});
+/// Whether `ENABLE_STREAMING_UPLOADS` opts the frontend into streaming uploads.
+pub fn streaming_uploads_enabled() -> bool {
+    std::env::var("ENABLE_STREAMING_UPLOADS")
+        .map(|v| matches!(v.to_ascii_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+        .unwrap_or(false)
+}
+/// Path prefix for links handed out after upload: the `/d/` download page by
+/// default, or the raw `/f/` file when `JUICEBOX_SHARE_LINKS=direct`.
+pub static SHARE_LINK_PREFIX: Lazy<&'static str> = Lazy::new(|| {
+    match std::env::var("JUICEBOX_SHARE_LINKS")
+        .map(|v| v.trim().to_ascii_lowercase())
+        .as_deref()
+    {
+        Ok("direct") | Ok("raw") | Ok("f") => "f",
+        _ => "d",
+    }
 });
 pub static PROD_HOST: Lazy<String> = Lazy::new(|| {
     std::env::var("JUICEBOX_PROD_HOST")
@@ -45,42 +67,13 @@ fn trim_host(input: &str) -> String {
     let without_path = input.split(['/', '?', '#']).next().unwrap_or(input);
     without_path.trim().trim_matches('/').to_string()
 }
-// Disallowed extensions
+// Disallowed extensions unless `JUICEBOX_FORBIDDEN_EXTENSIONS` replaces them
 pub const FORBIDDEN_EXTENSIONS: &[&str] = &[
     "exe", "dll", "bat", "cmd", "com", "scr", "cpl", "msi", "msp", "jar", "ps1", "psm1", "vbs",
     "js", "jse", "wsf", "wsh", "reg", "sh", "php", "pl", "py", "rb", "gadget", "hta", "mht",
     "mhtml",
 ];
 
-#[derive(Debug)]
-struct TrustedProxyConfig {
-    allow_headers: bool,
-    trusted_proxies: Vec<String>,
-}
-
-fn parse_truthy_env(var: &str) -> bool {
-    std::env::var(var)
-        .map(|v| matches!(v.to_ascii_lowercase().as_str(), "1" | "true" | "yes" | "on"))
-        .unwrap_or(false)
-}
-
-static TRUSTED_PROXY_CONFIG: Lazy<RwLock<TrustedProxyConfig>> = Lazy::new(|| {
-    let allow_headers = parse_truthy_env("TRUST_PROXY_HEADERS");
-    let trusted_proxies = std::env::var("TRUSTED_PROXY_CIDRS")
-        .map(|raw| {
-            raw.split(',')
-                .map(|segment| segment.trim())
-                .filter(|segment| !segment.is_empty())
-                .map(|segment| segment.to_string())
-                .collect::<Vec<String>>()
-        })
-        .unwrap_or_else(|_| Vec::new());
-    RwLock::new(TrustedProxyConfig {
-        allow_headers,
-        trusted_proxies,
-    })
-});
-
 fn is_local_proxy(ip: &IpAddr) -> bool {
     match ip {
         IpAddr::V4(v4) => v4.is_loopback() || v4.is_private(),
@@ -88,7 +81,7 @@ fn is_local_proxy(ip: &IpAddr) -> bool {
     }
 }
 
-fn proxy_source_trusted(cfg: &TrustedProxyConfig, source_ip: IpAddr) -> bool {
+fn proxy_source_trusted(cfg: &HotSettings, source_ip: IpAddr) -> bool {
     if is_local_proxy(&source_ip) {
         return true;
     }
@@ -99,14 +92,27 @@ fn proxy_source_trusted(cfg: &TrustedProxyConfig, source_ip: IpAddr) -> bool {
             .any(|cidr| ip_in_cidr(source_ip, cidr))
 }
 
+/// Whether `ip` is a proxy whose forwarded client headers are honoured.
+pub fn is_trusted_proxy(ip: IpAddr) -> bool {
+    let cfg = reload::current();
+    cfg.allow_proxy_headers && proxy_source_trusted(&cfg, ip)
+}
+
 #[derive(Serialize)]
 pub struct ErrorBody {
     pub code: &'static str,
     pub message: &'static str,
+    /// ID of the failed request, to quote when reporting a problem.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
 }
 
 pub fn json_error(status: StatusCode, code: &'static str, message: &'static str) -> Response {
-    let body = Json(ErrorBody { code, message });
+    let body = Json(ErrorBody {
+        code,
+        message,
+        request_id: current_request_id(),
+    });
     let mut resp = (status, body).into_response();
     resp.headers_mut().insert(
         axum::http::header::CONTENT_TYPE,
@@ -121,11 +127,10 @@ pub fn new_id() -> String {
 }
 
 pub fn is_forbidden_extension(name: &str) -> bool {
-    if let Some(dot) = name.rfind('.') {
-        if dot > 0 {
-            let ext = &name[dot + 1..].to_ascii_lowercase();
-            return FORBIDDEN_EXTENSIONS.contains(&ext.as_str());
-        }
+    if let Some(dot) = name.rfind('.')
+        && dot > 0
+    {
+        return reload::current().is_forbidden_extension(&name[dot + 1..]);
     }
     false
 }
@@ -133,15 +138,13 @@ pub fn is_forbidden_extension(name: &str) -> bool {
 pub fn make_storage_name(original: Option<&str>) -> String {
     if let Some(orig) = original {
         let sanitized = sanitize(orig);
-        if let Some(dot) = sanitized.rfind('.') {
-            if dot > 0 {
-                let ext = &sanitized[dot + 1..];
-                if !ext.is_empty()
-                    && ext.len() <= 12
-                    && ext.chars().all(|c| c.is_ascii_alphanumeric())
-                {
-                    return format!("{}.{ext}", new_id());
-                }
+        if let Some(dot) = sanitized.rfind('.')
+            && dot > 0
+        {
+            let ext = &sanitized[dot + 1..];
+            if !ext.is_empty() && ext.len() <= 12 && ext.chars().all(|c| c.is_ascii_alphanumeric())
+            {
+                return format!("{}.{ext}", new_id());
             }
         }
     }
@@ -168,6 +171,36 @@ pub fn now_secs() -> u64 {
         .as_secs()
 }
 
+/// Where [`AppState::now_secs`] reads the time from. Tests use a manual clock
+/// and move it forward instead of sleeping through TTLs.
+#[derive(Debug, Default)]
+pub enum Clock {
+    #[default]
+    System,
+    Manual(AtomicU64),
+}
+
+impl Clock {
+    /// A manual clock starting at `secs` past the epoch.
+    pub fn manual(secs: u64) -> Self {
+        Self::Manual(AtomicU64::new(secs))
+    }
+
+    pub fn now_secs(&self) -> u64 {
+        match self {
+            Self::System => now_secs(),
+            Self::Manual(secs) => secs.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Move a manual clock forward. The system clock ignores this.
+    pub fn advance(&self, secs: u64) {
+        if let Self::Manual(now) = self {
+            now.fetch_add(secs, Ordering::Relaxed);
+        }
+    }
+}
+
 pub fn looks_like_hash(value: &str) -> bool {
     value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit())
 }
@@ -193,6 +226,136 @@ fn hash_with_secret(secret: &[u8], payload: &[u8]) -> String {
     hex
 }
 
+/// Id of the key derived from `IP_HASH_SECRET`. It signs when no key ring is
+/// configured and keeps verifying afterwards, so tokens issued before one
+/// was set up stay valid until they expire.
+pub const IP_SECRET_KEY_ID: &str = "ip";
+/// Shortest signing secret accepted, in bytes.
+pub const MIN_SIGNING_SECRET_BYTES: usize = 16;
+
+/// HMAC keys for tokens the server hands out and checks later: presigned
+/// download links and lookup challenges. One key signs; every key verifies.
+/// Signatures read `{key id}.{tag}`, so a check only tries the key named,
+/// and rotating means putting a new key first while the old ones stay until
+/// their tokens have expired. Tags without a key id, from before the ring,
+/// are tried against every key.
+#[derive(Clone)]
+pub struct SigningKeys {
+    /// The signing key comes first.
+    keys: Vec<(String, Vec<u8>)>,
+}
+
+impl std::fmt::Debug for SigningKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SigningKeys")
+            .field("ids", &self.key_ids())
+            .finish()
+    }
+}
+
+impl SigningKeys {
+    /// A ring holding only `secret`, under `id`.
+    pub fn single(id: impl Into<String>, secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            keys: vec![(id.into(), secret.into())],
+        }
+    }
+
+    /// Parse `JUICEBOX_SIGNING_KEYS`-style `id:secret,id:secret` with the
+    /// signing key first, and append `ip_secret` as the verify-only
+    /// [`IP_SECRET_KEY_ID`] key. An empty list signs with `ip_secret`.
+    pub fn parse(raw: &str, ip_secret: &[u8]) -> anyhow::Result<Self> {
+        let mut keys: Vec<(String, Vec<u8>)> = Vec::new();
+        for entry in raw.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let Some((id, secret)) = entry.split_once(':') else {
+                anyhow::bail!("JUICEBOX_SIGNING_KEYS entries must look like id:secret");
+            };
+            let (id, secret) = (id.trim(), secret.trim());
+            if id.is_empty()
+                || id.len() > 16
+                || !id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                anyhow::bail!(
+                    "JUICEBOX_SIGNING_KEYS key id {id:?} must be 1-16 letters, digits, - or _"
+                );
+            }
+            if id == IP_SECRET_KEY_ID || keys.iter().any(|(known, _)| known == id) {
+                anyhow::bail!("JUICEBOX_SIGNING_KEYS key id {id:?} is used twice or reserved");
+            }
+            if secret.len() < MIN_SIGNING_SECRET_BYTES {
+                anyhow::bail!(
+                    "JUICEBOX_SIGNING_KEYS secret for {id:?} must be at least {MIN_SIGNING_SECRET_BYTES} bytes"
+                );
+            }
+            keys.push((id.to_string(), secret.as_bytes().to_vec()));
+        }
+        keys.push((IP_SECRET_KEY_ID.to_string(), ip_secret.to_vec()));
+        Ok(Self { keys })
+    }
+
+    /// Read `JUICEBOX_SIGNING_KEYS`.
+    pub fn from_env(ip_secret: &[u8]) -> anyhow::Result<Self> {
+        Self::parse(
+            &std::env::var("JUICEBOX_SIGNING_KEYS").unwrap_or_default(),
+            ip_secret,
+        )
+    }
+
+    /// Id of the key new signatures are made with.
+    pub fn signing_key_id(&self) -> &str {
+        &self.keys[0].0
+    }
+
+    /// Ids of every key that verifies, signing key first.
+    pub fn key_ids(&self) -> Vec<&str> {
+        self.keys.iter().map(|(id, _)| id.as_str()).collect()
+    }
+
+    fn mac(secret: &[u8], purpose: &str, payload: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(secret)
+            .expect("HMAC key initialization should accept arbitrary key length");
+        mac.update(purpose.as_bytes());
+        mac.update(b"\n");
+        mac.update(payload);
+        mac
+    }
+
+    /// Sign `payload` for `purpose`, which keeps a signature for one kind of
+    /// token from being accepted as another.
+    pub fn sign(&self, purpose: &str, payload: &[u8]) -> String {
+        use base64::Engine;
+        let (id, secret) = &self.keys[0];
+        let tag = Self::mac(secret, purpose, payload).finalize().into_bytes();
+        format!(
+            "{id}.{}",
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(tag)
+        )
+    }
+
+    /// Whether `signature` is a valid [`SigningKeys::sign`] result for
+    /// `payload` and `purpose` under any key in the ring.
+    pub fn verify(&self, purpose: &str, payload: &[u8], signature: &str) -> bool {
+        use base64::Engine;
+        let (id, tag) = match signature.split_once('.') {
+            Some((id, tag)) => (Some(id), tag),
+            None => (None, signature),
+        };
+        let Ok(tag) = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(tag) else {
+            return false;
+        };
+        self.keys
+            .iter()
+            .filter(|(key_id, _)| id.is_none_or(|id| id == key_id))
+            .any(|(_, secret)| {
+                Self::mac(secret, purpose, payload)
+                    .verify_slice(&tag)
+                    .is_ok()
+            })
+    }
+}
+
 fn ip_version_tag(ip: &IpAddr) -> (&'static str, IpVersion) {
     match ip {
         IpAddr::V4(_) => ("v4", IpVersion::V4),
@@ -277,6 +440,11 @@ pub fn hash_network_from_cidr(secret: &[u8], cidr: &str) -> Option<(IpVersion, u
     hash_network_from_ip(secret, &ip, prefix)
 }
 
+/// Relative share link for a stored file, honouring `SHARE_LINK_PREFIX`.
+pub fn share_path(file: &str) -> String {
+    format!("{}/{}", *SHARE_LINK_PREFIX, file)
+}
+
 pub fn qualify_path(state: &AppState, path: &str) -> String {
     if state.production {
         let p = path.trim_start_matches('/');
@@ -357,41 +525,37 @@ fn parse_ip(value: &str) -> Option<IpAddr> {
 
 pub fn extract_client_ip(headers: &HeaderMap, fallback: Option<IpAddr>) -> String {
     {
-        let cfg = TRUSTED_PROXY_CONFIG
-            .read()
-            .expect("trusted proxy configuration poisoned");
-        if cfg.allow_headers {
-            if let Some(source_ip) = fallback {
-                if proxy_source_trusted(&cfg, source_ip) {
-                    if let Some(ip) = headers
-                        .get("CF-Connecting-IP")
-                        .and_then(|v| v.to_str().ok())
-                        .and_then(parse_ip)
-                    {
-                        return ip.to_string();
-                    }
-                    if let Some(ip) = headers
-                        .get("True-Client-IP")
-                        .and_then(|v| v.to_str().ok())
-                        .and_then(parse_ip)
-                    {
-                        return ip.to_string();
-                    }
-                    if let Some(ip) = headers
-                        .get("X-Real-IP")
-                        .and_then(|v| v.to_str().ok())
-                        .and_then(parse_ip)
-                    {
+        let cfg = reload::current();
+        if cfg.allow_proxy_headers
+            && let Some(source_ip) = fallback
+            && proxy_source_trusted(&cfg, source_ip)
+        {
+            if let Some(ip) = headers
+                .get("CF-Connecting-IP")
+                .and_then(|v| v.to_str().ok())
+                .and_then(parse_ip)
+            {
+                return ip.to_string();
+            }
+            if let Some(ip) = headers
+                .get("True-Client-IP")
+                .and_then(|v| v.to_str().ok())
+                .and_then(parse_ip)
+            {
+                return ip.to_string();
+            }
+            if let Some(ip) = headers
+                .get("X-Real-IP")
+                .and_then(|v| v.to_str().ok())
+                .and_then(parse_ip)
+            {
+                return ip.to_string();
+            }
+            if let Some(val) = headers.get("X-Forwarded-For").and_then(|v| v.to_str().ok()) {
+                for candidate in val.split(',') {
+                    if let Some(ip) = parse_ip(candidate) {
                         return ip.to_string();
                     }
-                    if let Some(val) = headers.get("X-Forwarded-For").and_then(|v| v.to_str().ok())
-                    {
-                        for candidate in val.split(',') {
-                            if let Some(ip) = parse_ip(candidate) {
-                                return ip.to_string();
-                            }
-                        }
-                    }
                 }
             }
         }
@@ -408,10 +572,8 @@ pub fn real_client_ip(headers: &HeaderMap, fallback: &std::net::SocketAddr) -> S
 /// Return whether forwarded headers from the provided `headers` should be trusted
 /// for a connection that arrived from `fallback` (the socket peer IP).
 pub fn headers_trusted(_headers: &HeaderMap, fallback: Option<IpAddr>) -> bool {
-    let cfg = TRUSTED_PROXY_CONFIG
-        .read()
-        .expect("trusted proxy configuration poisoned");
-    if !cfg.allow_headers {
+    let cfg = reload::current();
+    if !cfg.allow_proxy_headers {
         return false;
     }
     if let Some(source_ip) = fallback {
@@ -422,15 +584,22 @@ pub fn headers_trusted(_headers: &HeaderMap, fallback: Option<IpAddr>) -> bool {
 
 #[cfg_attr(not(test), allow(dead_code))]
 pub fn set_trusted_proxy_config_for_tests(allow_headers: bool, cidrs: Vec<String>) {
-    if let Ok(mut cfg) = TRUSTED_PROXY_CONFIG.write() {
-        cfg.allow_headers = allow_headers;
-        cfg.trusted_proxies = cidrs;
-    }
+    reload::update(|settings| {
+        settings.allow_proxy_headers = allow_headers;
+        settings.trusted_proxies = cidrs;
+    });
 }
 
 // new: max simultaneous active files per IP
 pub const MAX_ACTIVE_FILES_PER_IP: usize = 10;
 
+/// Guest-tier files one owner may hold on top of `MAX_ACTIVE_FILES_PER_IP`.
+pub const MAX_GUEST_FILES_PER_OWNER: usize = 3;
+/// Largest file the guest tier accepts.
+pub const GUEST_MAX_BYTES: u64 = 5 * 1024 * 1024;
+/// Longest a guest-tier file lives, whatever TTL was asked for.
+pub const GUEST_TTL_SECS: u64 = 60 * 60;
+
 // admin session ttl (seconds)
 pub const ADMIN_SESSION_TTL: u64 = 24 * 3600;
 
@@ -449,8 +618,19 @@ pub fn get_cookie(headers: &HeaderMap, name: &str) -> Option<String> {
     None
 }
 
+/// The credential from an `Authorization: Bearer …` header.
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers
+        .get(axum::http::header::AUTHORIZATION)?
+        .to_str()
+        .ok()?;
+    let (scheme, token) = value.trim().split_once(' ')?;
+    let token = token.trim();
+    (scheme.eq_ignore_ascii_case("bearer") && !token.is_empty()).then_some(token)
+}
+
 // Helper: parse human-readable size (e.g. "500MB", "1GB")
-fn parse_size_bytes(s: &str) -> Option<u64> {
+pub(crate) fn parse_size_bytes(s: &str) -> Option<u64> {
     let s = s.trim().to_ascii_lowercase();
     if let Some(num) = s.strip_suffix("gb") {
         num.trim()
@@ -482,5 +662,214 @@ pub fn format_bytes(n: u64) -> String {
 }
 
 pub fn max_file_bytes() -> u64 {
-    *MAX_FILE_BYTES
+    reload::current().max_file_bytes
+}
+
+pub fn max_filename_chars() -> usize {
+    *MAX_FILENAME_CHARS
+}
+
+/// Explicit directional marks and embedding/override/isolate controls. Left in
+/// a filename they can reorder the surrounding text, e.g. to fake an extension.
+fn is_bidi_control(c: char) -> bool {
+    matches!(
+        c,
+        '\u{061C}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}'
+    )
+}
+
+/// Zero-width and other format characters that render as nothing, so two
+/// names that look the same can differ.
+fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00AD}'
+            | '\u{034F}'
+            | '\u{180E}'
+            | '\u{200B}'..='\u{200D}'
+            | '\u{2060}'..='\u{2064}'
+            | '\u{FE00}'..='\u{FE0F}'
+            | '\u{FEFF}'
+    )
+}
+
+/// Look-alikes of `.`, `/` and `\` that can fake an extension or a path.
+fn is_confusable_separator(c: char) -> bool {
+    matches!(
+        c,
+        '\u{2024}'
+            | '\u{2027}'
+            | '\u{FE52}'
+            | '\u{FF0E}'
+            | '\u{3002}'
+            | '\u{FF61}'
+            | '\u{A4F8}'
+            | '\u{2044}'
+            | '\u{2215}'
+            | '\u{2571}'
+            | '\u{29F8}'
+            | '\u{FF0F}'
+            | '\u{29F5}'
+            | '\u{29F9}'
+            | '\u{FF3C}'
+    )
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Armenian,
+}
+
+/// The scripts whose letters are commonly swapped for Latin ones.
+fn confusable_script(c: char) -> Option<Script> {
+    match c {
+        'a'..='z' | 'A'..='Z' | '\u{00C0}'..='\u{024F}' | '\u{1E00}'..='\u{1EFF}' => {
+            Some(Script::Latin)
+        }
+        '\u{0370}'..='\u{03FF}' | '\u{1F00}'..='\u{1FFF}' => Some(Script::Greek),
+        '\u{0400}'..='\u{052F}' | '\u{2DE0}'..='\u{2DFF}' | '\u{A640}'..='\u{A69F}' => {
+            Some(Script::Cyrillic)
+        }
+        '\u{0530}'..='\u{058F}' => Some(Script::Armenian),
+        _ => None,
+    }
+}
+
+/// Why an original filename may not read as what it is. Names carrying one
+/// are flagged to moderators, who also see the [`ascii_filename`] form.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NameWarning {
+    /// Bidi overrides or isolates, e.g. `invoice\u{202E}fdp.exe`.
+    BidiControl,
+    /// Zero-width or other invisible characters.
+    Invisible,
+    /// A look-alike of `.`, `/` or `\`.
+    ConfusableSeparator,
+    /// Fullwidth Latin letters or digits.
+    Fullwidth,
+    /// One word mixing Latin with Greek, Cyrillic or Armenian letters.
+    MixedScript,
+}
+
+impl NameWarning {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NameWarning::BidiControl => "bidi_control",
+            NameWarning::Invisible => "invisible",
+            NameWarning::ConfusableSeparator => "confusable_separator",
+            NameWarning::Fullwidth => "fullwidth",
+            NameWarning::MixedScript => "mixed_script",
+        }
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            NameWarning::BidiControl => "contains text-direction controls",
+            NameWarning::Invisible => "contains invisible characters",
+            NameWarning::ConfusableSeparator => "contains a look-alike dot or slash",
+            NameWarning::Fullwidth => "contains fullwidth characters",
+            NameWarning::MixedScript => "mixes Latin with look-alike letters",
+        }
+    }
+}
+
+/// The first reason `raw` could spoof its name or extension, if any.
+pub fn filename_warning(raw: &str) -> Option<NameWarning> {
+    let raw: String = raw.nfc().collect();
+    if raw.chars().any(is_bidi_control) {
+        return Some(NameWarning::BidiControl);
+    }
+    if raw.chars().any(is_invisible) {
+        return Some(NameWarning::Invisible);
+    }
+    if raw.chars().any(is_confusable_separator) {
+        return Some(NameWarning::ConfusableSeparator);
+    }
+    if raw.chars().any(|c| matches!(c, '\u{FF10}'..='\u{FF19}' | '\u{FF21}'..='\u{FF3A}' | '\u{FF41}'..='\u{FF5A}')) {
+        return Some(NameWarning::Fullwidth);
+    }
+    let mixed = raw.split(|c: char| !c.is_alphanumeric()).any(|word| {
+        let mut scripts = word.chars().filter_map(confusable_script);
+        scripts
+            .next()
+            .is_some_and(|first| scripts.any(|other| other != first))
+    });
+    mixed.then_some(NameWarning::MixedScript)
+}
+
+/// ASCII form of a filename for moderators: each dot-separated part that is
+/// not plain ASCII is Punycode-encoded with an `xn--` prefix, as in IDNA
+/// hostnames.
+pub fn ascii_filename(name: &str) -> String {
+    name.split('.')
+        .map(|part| {
+            if part.is_ascii() {
+                part.to_string()
+            } else {
+                idna::punycode::encode_str(part)
+                    .map(|encoded| format!("xn--{encoded}"))
+                    .unwrap_or_else(|| "_".repeat(part.chars().count()))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// NFC-normalise an uploaded filename and drop bidi, invisible and other
+/// control characters. The result is still unbounded in length.
+pub fn normalize_original_name(raw: &str) -> String {
+    raw.nfc()
+        .filter(|c| !c.is_control() && !is_bidi_control(*c) && !is_invisible(*c))
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Shorten `name` to at most `max` characters by replacing the middle with an
+/// ellipsis, keeping the tail so the extension stays visible.
+pub fn ellipsize_middle(name: &str, max: usize) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    if chars.len() <= max || max < 2 {
+        return name.to_string();
+    }
+    let keep = max - 1;
+    let head = keep / 2;
+    let tail = keep - head;
+    let mut out: String = chars[..head].iter().collect();
+    out.push('…');
+    out.extend(&chars[chars.len() - tail..]);
+    out
+}
+
+/// Display-safe variant of an uploaded filename for templates, emails and
+/// `Content-Disposition`. Look-alike dots and slashes become `_` so only a
+/// real `.` can start the visible extension.
+pub fn display_original_name(raw: &str) -> String {
+    let name: String = normalize_original_name(raw)
+        .chars()
+        .map(|c| if is_confusable_separator(c) { '_' } else { c })
+        .collect();
+    ellipsize_middle(&name, max_filename_chars())
+}
+
+/// `Content-Disposition: inline` naming the file, with an ASCII fallback for
+/// clients that ignore `filename*`.
+pub fn inline_content_disposition(display_name: &str) -> String {
+    let fallback: String = display_name
+        .chars()
+        .map(|c| {
+            if (c.is_ascii_graphic() || c == ' ') && c != '"' && c != '\\' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!(
+        "inline; filename=\"{fallback}\"; filename*=UTF-8''{}",
+        urlencoding::encode(display_name)
+    )
 }
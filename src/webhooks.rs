@@ -0,0 +1,347 @@
+//! Outbound webhooks: lifecycle events POSTed as JSON to operator-configured
+//! URLs, so other systems can react to uploads, removals, reports and bans
+//! without polling. Each delivery is signed with HMAC-SHA256 when a secret is
+//! set and retried a few times before it is given up.
+
+use axum::body::Bytes;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tracing::{Instrument, debug, info_span, warn};
+
+use crate::state::{AppState, IpBan, ReportRecord};
+use crate::util::{new_id, now_secs};
+
+/// `sha256=<hex>` HMAC of `"{timestamp}.{body}"` under the webhook secret.
+pub const SIGNATURE_HEADER: &str = "x-juicebox-signature";
+/// Unix time the delivery was signed at.
+pub const TIMESTAMP_HEADER: &str = "x-juicebox-timestamp";
+/// The event name, e.g. `upload.completed`.
+pub const EVENT_HEADER: &str = "x-juicebox-event";
+/// Same for every attempt at one event, so receivers can drop repeats.
+pub const DELIVERY_HEADER: &str = "x-juicebox-delivery";
+/// How long one attempt may take unless `JUICEBOX_WEBHOOK_TIMEOUT_SECS` says
+/// otherwise.
+pub const DEFAULT_WEBHOOK_TIMEOUT_SECS: u64 = 10;
+/// Deliveries allowed in flight at once; events beyond that are dropped.
+pub const WEBHOOK_MAX_IN_FLIGHT: usize = 32;
+/// `file.deleted` reason when the uploader deleted the file.
+pub const DELETED_BY_OWNER: &str = "owner";
+/// `file.deleted` reason when the last allowed download removed the file.
+pub const DELETED_AT_DOWNLOAD_LIMIT: &str = "download_limit";
+const MAX_ATTEMPTS: u32 = 3;
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Something that happened to a file or to moderation state.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum WebhookEvent {
+    UploadCompleted {
+        file: String,
+        size: u64,
+        sha256: String,
+        expires: u64,
+    },
+    FileExpired {
+        file: String,
+        expired_at: u64,
+    },
+    FileDeleted {
+        file: String,
+        /// [`DELETED_BY_OWNER`], [`DELETED_AT_DOWNLOAD_LIMIT`], or the
+        /// reason an admin gave.
+        reason: String,
+    },
+    ReportCreated {
+        file: String,
+        reason: String,
+        details: String,
+        reporter_hash: String,
+    },
+    BanAdded {
+        ban_key: String,
+        label: Option<String>,
+        reason: String,
+    },
+}
+
+impl WebhookEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::UploadCompleted { .. } => "upload.completed",
+            WebhookEvent::FileExpired { .. } => "file.expired",
+            WebhookEvent::FileDeleted { .. } => "file.deleted",
+            WebhookEvent::ReportCreated { .. } => "report.created",
+            WebhookEvent::BanAdded { .. } => "ban.added",
+        }
+    }
+
+    pub fn file_deleted(file: &str, reason: &str) -> Self {
+        WebhookEvent::FileDeleted {
+            file: file.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn report_created(report: &ReportRecord) -> Self {
+        WebhookEvent::ReportCreated {
+            file: report.file.clone(),
+            reason: report.reason.clone(),
+            details: report.details.clone(),
+            reporter_hash: report.reporter_hash.clone(),
+        }
+    }
+
+    pub fn ban_added(ban: &IpBan) -> Self {
+        WebhookEvent::BanAdded {
+            ban_key: ban.subject.key().to_string(),
+            label: ban.label.clone(),
+            reason: ban.reason.clone(),
+        }
+    }
+}
+
+/// The body of every delivery.
+#[derive(Serialize, Debug)]
+struct Envelope<'a> {
+    id: &'a str,
+    event: &'static str,
+    time: u64,
+    data: &'a WebhookEvent,
+}
+
+#[derive(Clone, Debug)]
+pub struct WebhookConfig {
+    pub urls: Vec<String>,
+    pub secret: Option<Vec<u8>>,
+    pub timeout: Duration,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            urls: Vec::new(),
+            secret: None,
+            timeout: Duration::from_secs(DEFAULT_WEBHOOK_TIMEOUT_SECS),
+        }
+    }
+}
+
+impl WebhookConfig {
+    /// Read `JUICEBOX_WEBHOOK_URLS` (comma separated),
+    /// `JUICEBOX_WEBHOOK_SECRET` and `JUICEBOX_WEBHOOK_TIMEOUT_SECS`.
+    pub fn from_env() -> Self {
+        let urls = std::env::var("JUICEBOX_WEBHOOK_URLS")
+            .map(|v| parse_urls(&v))
+            .unwrap_or_default();
+        let secret = std::env::var("JUICEBOX_WEBHOOK_SECRET")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .map(|v| v.trim().as_bytes().to_vec());
+        let timeout = std::env::var("JUICEBOX_WEBHOOK_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .unwrap_or(DEFAULT_WEBHOOK_TIMEOUT_SECS);
+        Self {
+            urls,
+            secret,
+            timeout: Duration::from_secs(timeout),
+        }
+    }
+}
+
+/// Split a comma separated URL list, skipping anything not http(s).
+pub fn parse_urls(raw: &str) -> Vec<String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .filter(|url| {
+            let ok = url.starts_with("http://") || url.starts_with("https://");
+            if !ok {
+                warn!(url, "ignoring webhook url that is not http(s)");
+            }
+            ok
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+/// `sha256=<hex>` signature of `body` sent at `timestamp`.
+pub fn sign(secret: &[u8], timestamp: u64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC can take key of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    let hex: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    format!("sha256={hex}")
+}
+
+/// Sends events to every configured URL in the background. Emitting never
+/// waits on a receiver.
+pub struct Webhooks {
+    config: WebhookConfig,
+    client: reqwest::Client,
+    in_flight: Arc<Semaphore>,
+    delivered: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl Default for Webhooks {
+    fn default() -> Self {
+        Self::new(WebhookConfig::default())
+    }
+}
+
+impl Webhooks {
+    pub fn new(config: WebhookConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap_or_default();
+        Self {
+            config,
+            client,
+            in_flight: Arc::new(Semaphore::new(WEBHOOK_MAX_IN_FLIGHT)),
+            delivered: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.config.urls.is_empty()
+    }
+
+    pub fn urls(&self) -> &[String] {
+        &self.config.urls
+    }
+
+    pub fn is_signed(&self) -> bool {
+        self.config.secret.is_some()
+    }
+
+    pub fn delivered(&self) -> u64 {
+        self.delivered.load(Ordering::Relaxed)
+    }
+
+    pub fn failed(&self) -> u64 {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Queue `event` for every configured URL.
+    pub fn emit(self: &Arc<Self>, event: WebhookEvent) {
+        if !self.is_enabled() {
+            return;
+        }
+        let id = new_id();
+        let envelope = Envelope {
+            id: &id,
+            event: event.name(),
+            time: now_secs(),
+            data: &event,
+        };
+        let body = match serde_json::to_vec(&envelope) {
+            Ok(body) => Bytes::from(body),
+            Err(err) => {
+                warn!(
+                    ?err,
+                    event = event.name(),
+                    "failed to serialize webhook event"
+                );
+                return;
+            }
+        };
+        for url in &self.config.urls {
+            let Ok(permit) = self.in_flight.clone().try_acquire_owned() else {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    url,
+                    event = event.name(),
+                    "webhook dropped: too many deliveries in flight"
+                );
+                continue;
+            };
+            let hooks = self.clone();
+            let url = url.clone();
+            let body = body.clone();
+            let name = event.name();
+            let span = info_span!("webhook.deliver", event = name, delivery = %id);
+            let id = id.clone();
+            tokio::spawn(
+                async move {
+                    hooks.deliver(&url, name, &id, body).await;
+                    drop(permit);
+                }
+                .instrument(span),
+            );
+        }
+    }
+
+    async fn deliver(&self, url: &str, event: &'static str, id: &str, body: Bytes) {
+        for attempt in 1..=MAX_ATTEMPTS {
+            let timestamp = now_secs();
+            let mut req = self
+                .client
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(EVENT_HEADER, event)
+                .header(DELIVERY_HEADER, id)
+                .header(TIMESTAMP_HEADER, timestamp.to_string())
+                .body(body.clone());
+            if let Some(secret) = &self.config.secret {
+                req = req.header(SIGNATURE_HEADER, sign(secret, timestamp, &body));
+            }
+            match req.send().await {
+                Ok(resp) if resp.status().is_success() => {
+                    self.delivered.fetch_add(1, Ordering::Relaxed);
+                    debug!(url, attempt, "webhook delivered");
+                    return;
+                }
+                Ok(resp) => {
+                    warn!(
+                        url,
+                        attempt,
+                        status = resp.status().as_u16(),
+                        "webhook rejected"
+                    )
+                }
+                Err(err) => warn!(url, attempt, %err, "webhook delivery failed"),
+            }
+            if attempt < MAX_ATTEMPTS {
+                tokio::time::sleep(RETRY_BASE_DELAY * 2u32.pow(attempt - 1)).await;
+            }
+        }
+        self.failed.fetch_add(1, Ordering::Relaxed);
+        warn!(url, "webhook given up after {MAX_ATTEMPTS} attempts");
+    }
+}
+
+impl AppState {
+    /// Send `upload.completed` for `file`, once it has passed screening.
+    pub fn notify_upload_completed(&self, file: &str) {
+        let Some(meta) = self.owners.get(file).map(|m| m.value().clone()) else {
+            return;
+        };
+        self.webhooks.emit(WebhookEvent::UploadCompleted {
+            file: file.to_string(),
+            size: meta.size,
+            sha256: meta.hash,
+            expires: meta.expires,
+        });
+    }
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use axum::body::Body;
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode};
+use axum::middleware;
+use juicebox::access_log::{ACCESS_LOG_TARGET, JsonFormat, LogFormat, access_log};
+use juicebox::handlers::build_router;
+use juicebox::request_id::{REQUEST_ID_HEADER, assign_request_id};
+use serde_json::Value;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tower::ServiceExt;
+use tracing_subscriber::fmt::MakeWriter;
+
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for Captured {
+    type Writer = Captured;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+impl Captured {
+    fn lines(&self) -> Vec<Value> {
+        String::from_utf8(self.0.lock().unwrap().clone())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+}
+
+#[test]
+fn test_log_format_parses_env_values() {
+    assert_eq!(LogFormat::parse("json"), LogFormat::Json);
+    assert_eq!(LogFormat::parse(" JSON "), LogFormat::Json);
+    assert_eq!(LogFormat::parse("text"), LogFormat::Text);
+    assert_eq!(LogFormat::parse(""), LogFormat::Text);
+}
+
+#[tokio::test]
+async fn test_json_access_log_has_one_event_per_request() {
+    let (state, _tmp) = common::setup_test_app();
+    let captured = Captured::default();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(captured.clone())
+        .event_format(JsonFormat)
+        .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let app = build_router(state.clone())
+        .layer(middleware::from_fn_with_state(state.clone(), access_log))
+        .layer(middleware::from_fn(assign_request_id));
+    let mut req = Request::builder()
+        .method(Method::GET)
+        .uri("/healthz?token=secret")
+        .body(Body::empty())
+        .unwrap();
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([198, 51, 100, 90], 6500))));
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let request_id = resp.headers()[&REQUEST_ID_HEADER].to_str().unwrap();
+
+    let access: Vec<Value> = captured
+        .lines()
+        .into_iter()
+        .filter(|line| line["target"] == ACCESS_LOG_TARGET)
+        .collect();
+    assert_eq!(access.len(), 1);
+    let event = &access[0];
+    assert_eq!(event["level"], "INFO");
+    assert_eq!(event["method"], "GET");
+    assert_eq!(event["path"], "/healthz");
+    assert_eq!(event["status"], 200);
+    assert_eq!(event["bytes"], 2);
+    assert!(event["latency_ms"].is_u64());
+    assert_eq!(event["request_id"], request_id);
+    let expected_hash = state.hash_ip_to_string("198.51.100.90").unwrap();
+    assert_eq!(event["client_ip_hash"], expected_hash.as_str());
+    assert!(!event.to_string().contains("198.51.100.90"));
+}
@@ -0,0 +1,208 @@
+mod common;
+
+use axum::Router;
+use axum::body::{Body, Bytes, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode, header};
+use juicebox::accounts::Accounts;
+use juicebox::handlers::{AccountResponse, PasteResponse, build_router};
+use serde_json::{Value, json};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const HOME: [u8; 4] = [198, 51, 100, 10];
+const CAFE: [u8; 4] = [203, 0, 113, 10];
+
+async fn send(app: &Router, req: Request<Body>) -> (StatusCode, Option<String>, Bytes) {
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let cookie = resp
+        .headers()
+        .get(header::SET_COOKIE)
+        .map(|v| v.to_str().unwrap().to_string());
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (status, cookie, body)
+}
+
+fn request(
+    method: Method,
+    uri: &str,
+    ip: [u8; 4],
+    cookie: Option<&str>,
+    body: Body,
+) -> Request<Body> {
+    let mut builder = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/json");
+    if let Some(cookie) = cookie {
+        builder = builder.header(header::COOKIE, cookie);
+    }
+    let mut req = builder.body(body).unwrap();
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from((ip, 6100))));
+    req
+}
+
+fn credentials(username: &str, password: &str) -> Body {
+    Body::from(json!({"username": username, "password": password}).to_string())
+}
+
+/// The `acct=...` pair from a `Set-Cookie` header.
+fn session(set_cookie: Option<String>) -> String {
+    set_cookie.unwrap().split(';').next().unwrap().to_string()
+}
+
+/// Share URLs `/list` returns.
+async fn list(app: &Router, ip: [u8; 4], cookie: Option<&str>) -> Vec<String> {
+    let (status, _, body) = send(
+        app,
+        request(Method::GET, "/list", ip, cookie, Body::empty()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let listed: Value = serde_json::from_slice(&body).unwrap();
+    serde_json::from_value(listed["files"].clone()).unwrap()
+}
+
+#[tokio::test]
+async fn test_accounts_are_off_unless_enabled() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state);
+    let (status, _, body) = send(
+        &app,
+        request(
+            Method::POST,
+            "/api/v1/accounts/register",
+            HOME,
+            None,
+            credentials("alice", "correct horse"),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let err: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(err["code"], "accounts_disabled");
+}
+
+#[tokio::test]
+async fn test_account_owns_uploads_across_ips() {
+    let (mut state, _tmp) = common::setup_test_app();
+    state.accounts = Arc::new(Accounts::new(true));
+    let app = build_router(state.clone());
+    let register = |username: &str, password: &str| {
+        request(
+            Method::POST,
+            "/api/v1/accounts/register",
+            HOME,
+            None,
+            credentials(username, password),
+        )
+    };
+
+    let (status, _, _) = send(&app, register("alice", "short")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _, _) = send(&app, register("a/b", "correct horse")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, cookie, body) = send(&app, register("Alice", "correct horse")).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let me: AccountResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(me.username, "alice");
+    let cookie = session(cookie);
+    assert!(cookie.starts_with("acct="));
+    let (status, _, _) = send(&app, register("alice", "another one")).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let stored = state.kv.load_hash("accounts").await.unwrap();
+    assert_eq!(stored.len(), 1);
+    assert!(stored[0].1.contains("$argon2"));
+    assert!(!stored[0].1.contains("correct horse"));
+
+    // A paste made while logged in belongs to the account, not the IP.
+    let paste = request(
+        Method::POST,
+        "/api/paste-binary?name=notes.txt",
+        HOME,
+        Some(&cookie),
+        Body::from("account notes"),
+    );
+    let (status, _, body) = send(&app, paste).await;
+    assert_eq!(status, StatusCode::OK);
+    let pasted: PasteResponse = serde_json::from_slice(&body).unwrap();
+    let owner = state.owners.get(&pasted.file).unwrap().owner_hash.clone();
+    assert!(owner.starts_with("account:"));
+
+    assert!(list(&app, HOME, None).await.is_empty());
+    let from_cafe = list(&app, CAFE, Some(&cookie)).await;
+    assert_eq!(from_cafe.len(), 1);
+    assert!(from_cafe[0].ends_with(&pasted.file));
+
+    let delete = |ip, cookie| {
+        request(
+            Method::DELETE,
+            &format!("/f/{}", pasted.file),
+            ip,
+            cookie,
+            Body::empty(),
+        )
+    };
+    let (status, _, _) = send(&app, delete(HOME, None)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _, _) = send(&app, delete(CAFE, Some(&cookie))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(state.owners.get(&pasted.file).is_none());
+
+    let (status, _, _) = send(
+        &app,
+        request(
+            Method::POST,
+            "/api/v1/accounts/logout",
+            CAFE,
+            Some(&cookie),
+            Body::empty(),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _, _) = send(
+        &app,
+        request(
+            Method::GET,
+            "/api/v1/accounts/me",
+            CAFE,
+            Some(&cookie),
+            Body::empty(),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let login = |password: &str| {
+        request(
+            Method::POST,
+            "/api/v1/accounts/login",
+            CAFE,
+            None,
+            credentials("ALICE", password),
+        )
+    };
+    let (status, _, _) = send(&app, login("wrong horse")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, cookie, _) = send(&app, login("correct horse")).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, body) = send(
+        &app,
+        request(
+            Method::GET,
+            "/api/v1/accounts/me",
+            HOME,
+            Some(&session(cookie)),
+            Body::empty(),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let me: AccountResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!((me.username.as_str(), me.files), ("alice", 0));
+}
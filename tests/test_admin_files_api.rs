@@ -0,0 +1,134 @@
+use axum::Router;
+use axum::body::{Body, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode, header};
+use juicebox::handlers::build_router;
+use juicebox::testing::{AppStateBuilder, DEFAULT_TEST_ADMIN_KEY};
+use serde_json::{Value, json};
+use std::net::SocketAddr;
+use tower::ServiceExt;
+
+async fn send(app: &Router, mut req: Request<Body>) -> (StatusCode, Value) {
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([198, 51, 100, 92], 6502))));
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+fn bearer() -> String {
+    format!("Bearer {DEFAULT_TEST_ADMIN_KEY}")
+}
+
+#[tokio::test]
+async fn test_files_api_lists_files_for_admins_only() {
+    let app = AppStateBuilder::new()
+        .with_file("a.txt", b"alpha", "203.0.113.7", 3600)
+        .with_file("b.txt", b"bravo!", "203.0.113.8", 3600)
+        .build();
+    let state = app.state.clone();
+    let router = build_router(state.clone());
+    let list = |auth: Option<(&'static str, String)>, query: &str| {
+        let mut req = Request::builder().uri(format!("/api/admin/v1/files{query}"));
+        if let Some((name, value)) = auth {
+            req = req.header(name, value);
+        }
+        req.body(Body::empty()).unwrap()
+    };
+
+    let (status, _) = send(&router, list(None, "")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let wrong = Some((header::AUTHORIZATION.as_str(), "Bearer nope".to_string()));
+    let (status, _) = send(&router, list(wrong, "")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let auth = Some((header::AUTHORIZATION.as_str(), bearer()));
+    let (status, body) = send(&router, list(auth.clone(), "")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["count"], 2);
+    assert_eq!(body["total_bytes"], 11);
+    let a = body["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|f| f["name"] == "a.txt")
+        .unwrap();
+    let meta = state.owners.get("a.txt").unwrap().clone();
+    assert_eq!(a["size"], 5);
+    assert_eq!(a["owner_hash"], meta.owner_hash.as_str());
+    assert_eq!(a["expires"], meta.expires);
+    assert_eq!(a["hash"], meta.hash.as_str());
+
+    let query = format!("?owner={}", meta.owner_hash);
+    let (_, body) = send(&router, list(auth, &query)).await;
+    assert_eq!(body["count"], 1);
+    assert_eq!(body["files"][0]["name"], "a.txt");
+
+    // The admin session cookie works too.
+    state.create_admin_session("files-admin".to_string()).await;
+    let cookie = Some((header::COOKIE.as_str(), "adm=files-admin".to_string()));
+    let (status, body) = send(&router, list(cookie, "")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["count"], 2);
+}
+
+#[tokio::test]
+async fn test_files_api_deletes_one_or_many() {
+    let app = AppStateBuilder::new()
+        .with_file("one.txt", b"1", "203.0.113.7", 3600)
+        .with_file("two.txt", b"2", "203.0.113.7", 3600)
+        .with_file("three.txt", b"3", "203.0.113.7", 3600)
+        .build();
+    let state = app.state.clone();
+    let router = build_router(state.clone());
+
+    let delete_one = |uri: &str| {
+        Request::builder()
+            .method(Method::DELETE)
+            .uri(uri)
+            .header(header::AUTHORIZATION, bearer())
+            .body(Body::empty())
+            .unwrap()
+    };
+    let (status, body) = send(
+        &router,
+        delete_one("/api/admin/v1/files/one.txt?reason=spam"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({ "deleted": ["one.txt"], "reason": "spam" }));
+    assert!(!state.owners.contains_key("one.txt"));
+    assert!(!state.file_store.exists("one.txt").await);
+
+    let (status, _) = send(&router, delete_one("/api/admin/v1/files/one.txt")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(
+        &router,
+        delete_one("/api/admin/v1/files/two.txt?reason=bogus"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let bulk = |payload: Value| {
+        Request::builder()
+            .method(Method::DELETE)
+            .uri("/api/admin/v1/files")
+            .header(header::AUTHORIZATION, bearer())
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap()
+    };
+    let (status, _) = send(&router, bulk(json!({ "files": ["../etc"] }))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, body) = send(
+        &router,
+        bulk(json!({ "files": ["two.txt", "three.txt", "gone.txt"] })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["deleted"], json!(["two.txt", "three.txt"]));
+    assert_eq!(body["missing"], json!(["gone.txt"]));
+    assert_eq!(body["reason"], "other");
+    assert!(state.owners.is_empty());
+}
@@ -0,0 +1,117 @@
+use axum::Router;
+use axum::body::{Body, to_bytes};
+use axum::http::{Request, StatusCode, header};
+use juicebox::handlers::admin_list::{AdminListQuery, SortOrder};
+use juicebox::handlers::build_router;
+use juicebox::state::{BanSubject, IpBan};
+use juicebox::testing::AppStateBuilder;
+use tower::ServiceExt;
+
+async fn page(app: &Router, uri: &str) -> String {
+    let req = Request::builder()
+        .uri(uri)
+        .header(header::COOKIE, "adm=views-admin")
+        .body(Body::empty())
+        .unwrap();
+    let resp = app.clone().oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK, "{uri}");
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+/// File names in the order the table lists them.
+fn listed(html: &str) -> Vec<&str> {
+    html.match_indices("data-file=\"")
+        .map(|(at, marker)| {
+            let rest = &html[at + marker.len()..];
+            &rest[..rest.find('"').unwrap()]
+        })
+        .collect()
+}
+
+#[test]
+fn test_paginate_clamps_page_and_size() {
+    let query = AdminListQuery {
+        page: Some(9),
+        per_page: Some(2),
+        ..Default::default()
+    };
+    let (rows, pager) = query.paginate((1..=5).collect::<Vec<_>>());
+    assert_eq!(rows, vec![5]);
+    assert_eq!((pager.page, pager.pages, pager.total), (3, 3, 5));
+
+    let query = AdminListQuery {
+        per_page: Some(0),
+        sort: Some("SIZE".to_string()),
+        order: Some("bogus".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(query.paginate(vec![1, 2]).1.per_page, 1);
+    assert_eq!(query.sort_key(&["created", "size"]), "size");
+    assert_eq!(query.order(SortOrder::Asc), SortOrder::Asc);
+}
+
+#[tokio::test]
+async fn test_files_view_sorts_filters_and_pages() {
+    let app = AppStateBuilder::new()
+        .with_file("small.txt", b"a", "203.0.113.7", 3600)
+        .with_file("medium.txt", b"abcd", "203.0.113.7", 60)
+        .with_file("large.bin", b"abcdefghij", "198.51.100.9", 7200)
+        .build();
+    let state = app.state.clone();
+    state.create_admin_session("views-admin".to_string()).await;
+    let router = build_router(state.clone());
+
+    let html = page(&router, "/admin/files?sort=size").await;
+    assert_eq!(listed(&html), ["large.bin", "medium.txt", "small.txt"]);
+    let html = page(&router, "/admin/files?sort=expires&order=asc").await;
+    assert_eq!(listed(&html), ["medium.txt", "small.txt", "large.bin"]);
+
+    let html = page(&router, "/admin/files?q=.TXT&sort=size&order=asc").await;
+    assert_eq!(listed(&html), ["small.txt", "medium.txt"]);
+
+    let owner = state.owners.get("large.bin").unwrap().owner_hash.clone();
+    let html = page(&router, &format!("/admin/files?owner={}", &owner[..12])).await;
+    assert_eq!(listed(&html), ["large.bin"]);
+
+    let html = page(&router, "/admin/files?sort=size&per_page=2").await;
+    assert_eq!(listed(&html), ["large.bin", "medium.txt"]);
+    assert!(html.contains("Page 1 of 2 · 3 matching"), "{html}");
+    assert!(
+        html.contains("page=2&amp;per_page=2&amp;sort=size"),
+        "{html}"
+    );
+    let html = page(&router, "/admin/files?sort=size&per_page=2&page=2").await;
+    assert_eq!(listed(&html), ["small.txt"]);
+    assert!(html.contains("rel=prev"));
+}
+
+#[tokio::test]
+async fn test_bans_view_filters_by_reason_and_sorts_by_time() {
+    let app = AppStateBuilder::new().build();
+    let state = app.state.clone();
+    state.create_admin_session("views-admin".to_string()).await;
+    for (hash, reason, time) in [("aaaa", "spam wave", 10), ("bbbb", "malware", 20)] {
+        state
+            .add_ban(IpBan {
+                subject: BanSubject::Exact {
+                    hash: hash.repeat(16),
+                },
+                label: Some(format!("label-{hash}")),
+                reason: reason.to_string(),
+                time,
+                expires: None,
+            })
+            .await;
+    }
+    let router = build_router(state.clone());
+
+    let html = page(&router, "/admin/ban").await;
+    assert!(html.find("label-bbbb").unwrap() < html.find("label-aaaa").unwrap());
+    let html = page(&router, "/admin/ban?order=asc").await;
+    assert!(html.find("label-aaaa").unwrap() < html.find("label-bbbb").unwrap());
+    let html = page(&router, "/admin/ban?q=spam").await;
+    assert!(html.contains("label-aaaa") && !html.contains("label-bbbb"));
+    let html = page(&router, "/admin/ban?owner=bbbb").await;
+    assert!(html.contains("label-bbbb") && !html.contains("label-aaaa"));
+}
@@ -0,0 +1,187 @@
+mod common;
+
+use axum::Router;
+use axum::body::{Body, Bytes, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode, header};
+use juicebox::handlers::{
+    ApiTokenCreated, ChunkInitResponse, UploadResponse, build_router, s3_secret_access_key,
+};
+use juicebox::rate_limit::RateLimitLayer;
+use serde_json::{Value, json};
+use std::net::SocketAddr;
+use tower::ServiceExt;
+
+const BOUNDARY: &str = "----JuiceboxTokenBoundary";
+
+async fn send(app: &Router, req: Request<Body>) -> (StatusCode, Bytes) {
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    (
+        status,
+        to_bytes(resp.into_body(), usize::MAX).await.unwrap(),
+    )
+}
+
+fn from_ip(mut req: Request<Body>, ip: [u8; 4]) -> Request<Body> {
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from((ip, 7000))));
+    req
+}
+
+fn upload(name: &str, token: Option<&str>, ip: [u8; 4]) -> Request<Body> {
+    let body = format!(
+        "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{name}\"\r\n\
+         Content-Type: text/plain\r\n\r\nscripted {name}\r\n--{BOUNDARY}--\r\n"
+    );
+    let mut builder = Request::builder()
+        .method(Method::POST)
+        .uri("/upload")
+        .header(
+            header::CONTENT_TYPE,
+            format!("multipart/form-data; boundary={BOUNDARY}"),
+        );
+    if let Some(token) = token {
+        builder = builder.header(header::AUTHORIZATION, format!("Bearer {token}"));
+    }
+    from_ip(builder.body(Body::from(body)).unwrap(), ip)
+}
+
+fn admin(method: Method, uri: &str, body: Body) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::COOKIE, "adm=token-admin")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_api_token_uploads_are_owned_by_token_across_ips() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+
+    let anonymous = Request::builder()
+        .method(Method::POST)
+        .uri("/api/admin/v1/tokens")
+        .body(Body::empty())
+        .unwrap();
+    let (status, _) = send(&app, anonymous).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    state.create_admin_session("token-admin".to_string()).await;
+    let (status, body) = send(
+        &app,
+        admin(
+            Method::POST,
+            "/api/admin/v1/tokens",
+            Body::from(r#"{"label": "ci"}"#),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let created: ApiTokenCreated = serde_json::from_slice(&body).unwrap();
+    assert!(created.token.starts_with("jbx_"));
+    assert_eq!(created.record.label.as_deref(), Some("ci"));
+    assert_eq!(
+        created.s3_secret_access_key,
+        s3_secret_access_key(&state, &created.record.id)
+    );
+
+    let (status, body) = send(
+        &app,
+        admin(Method::GET, "/api/admin/v1/tokens", Body::empty()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let listed: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(listed["tokens"][0]["id"], created.record.id);
+    assert!(!String::from_utf8_lossy(&body).contains(&created.token));
+    let persisted = state.kv.load_hash("api_tokens").await.unwrap();
+    assert_eq!(persisted.len(), 1);
+    assert!(!persisted[0].0.contains(&created.token));
+
+    let (status, body) = send(
+        &app,
+        upload("ci.txt", Some(&created.token), [198, 51, 100, 7]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let uploaded: UploadResponse = serde_json::from_slice(&body).unwrap();
+    let owner = state
+        .owners
+        .get(&uploaded.files[0])
+        .unwrap()
+        .owner_hash
+        .clone();
+    assert_eq!(owner, created.record.owner_hash);
+    assert_ne!(owner, common::hash_fixture_ip("198.51.100.7"));
+
+    // A chunk session opened with the token can be continued from another IP.
+    let init = Request::builder()
+        .method(Method::POST)
+        .uri("/chunk/init")
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::AUTHORIZATION, format!("Bearer {}", created.token))
+        .body(Body::from(
+            json!({"filename": "ci.bin", "size": 4}).to_string(),
+        ))
+        .unwrap();
+    let (status, body) = send(&app, from_ip(init, [198, 51, 100, 7])).await;
+    assert_eq!(status, StatusCode::OK);
+    let session: ChunkInitResponse = serde_json::from_slice(&body).unwrap();
+    let part = Request::builder()
+        .method(Method::PUT)
+        .uri(format!("/chunk/{}/0", session.session_id))
+        .header(header::AUTHORIZATION, format!("Bearer {}", created.token))
+        .body(Body::from("data"))
+        .unwrap();
+    let (status, _) = send(&app, from_ip(part, [203, 0, 113, 9])).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, body) = send(&app, upload("bad.txt", Some("jbx_nope"), [198, 51, 100, 7])).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let err: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(err["code"], "invalid_token");
+
+    let (status, _) = send(
+        &app,
+        admin(
+            Method::DELETE,
+            &format!("/api/admin/v1/tokens/{}", created.record.id),
+            Body::empty(),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(state.kv.load_hash("api_tokens").await.unwrap().is_empty());
+    let (status, _) = send(
+        &app,
+        upload("late.txt", Some(&created.token), [198, 51, 100, 7]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_api_token_uploads_use_their_own_rate_bucket() {
+    let (state, _tmp) = common::setup_test_app();
+    let (secret, _) = state.api_tokens.issue(None);
+    let app = build_router(state.clone())
+        .layer(RateLimitLayer::new(1, 0).with_api_tokens(state.api_tokens.clone()));
+    let ip = [192, 0, 2, 44];
+
+    let (status, _) = send(&app, upload("one.txt", None, ip)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, upload("two.txt", None, ip)).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    let (status, _) = send(&app, upload("three.txt", Some(&secret), ip)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, upload("four.txt", Some(&secret), ip)).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    // An unknown token does not earn a fresh bucket.
+    let (status, _) = send(&app, upload("five.txt", Some("jbx_made_up"), ip)).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+}
@@ -0,0 +1,74 @@
+use axum::body::Body;
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode, header};
+use juicebox::handlers::build_router;
+use juicebox::testing::AppStateBuilder;
+use std::net::SocketAddr;
+use tower::ServiceExt;
+
+const START: u64 = 1_700_000_000;
+
+fn from(mut req: Request<Body>, ip: [u8; 4]) -> Request<Body> {
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from((ip, 6100))));
+    req
+}
+
+fn fetch(file: &str) -> Request<Body> {
+    Request::builder()
+        .uri(format!("/f/{file}"))
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_builder_fixtures_clock_and_notifier() {
+    let mut app = AppStateBuilder::new()
+        .manual_clock(START)
+        .with_file("hello.txt", b"hello", "203.0.113.7", 3600)
+        .with_ban("198.51.100.0/24", "fixture")
+        .build();
+    let state = app.state.clone();
+    let router = build_router(state.clone());
+
+    let meta = state.owners.get("hello.txt").unwrap().clone();
+    assert_eq!(
+        (meta.created, meta.expires, meta.size),
+        (START, START + 3600, 5)
+    );
+    assert_eq!(
+        meta.owner_hash,
+        state.hash_ip_to_string("203.0.113.7").unwrap()
+    );
+    assert!(state.is_banned("198.51.100.20").await);
+    assert!(!state.is_banned("203.0.113.7").await);
+
+    let resp = router
+        .clone()
+        .oneshot(from(fetch("hello.txt"), [192, 0, 2, 1]))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let report = Request::builder()
+        .method(Method::POST)
+        .uri("/report")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from("file=hello.txt&reason=spam&details=fixture"))
+        .unwrap();
+    let resp = router
+        .clone()
+        .oneshot(from(report, [192, 0, 2, 1]))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let email = app.notifications.try_recv().expect("report notification");
+    assert_eq!((email.file.as_str(), email.time), ("hello.txt", START));
+
+    state.clock.advance(3601);
+    let resp = router
+        .oneshot(from(fetch("hello.txt"), [192, 0, 2, 1]))
+        .await
+        .unwrap();
+    assert!(resp.status().is_client_error(), "{}", resp.status());
+}
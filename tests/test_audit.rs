@@ -0,0 +1,169 @@
+use axum::Router;
+use axum::body::{Body, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode, header};
+use juicebox::audit::{AuditAction, session_actor};
+use juicebox::handlers::build_router;
+use juicebox::testing::{AppStateBuilder, DEFAULT_TEST_ADMIN_KEY};
+use serde_json::Value;
+use std::net::SocketAddr;
+use tower::ServiceExt;
+
+const START: u64 = 1_700_000_000;
+
+async fn send(app: &Router, mut req: Request<Body>) -> (StatusCode, String) {
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([198, 51, 100, 93], 6503))));
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8_lossy(&body).into_owned())
+}
+
+fn form(uri: &str, cookie: Option<&str>, body: &str) -> Request<Body> {
+    let mut req = Request::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded");
+    if let Some(cookie) = cookie {
+        req = req.header(header::COOKIE, cookie);
+    }
+    req.body(Body::from(body.to_string())).unwrap()
+}
+
+fn audit_page(query: &str, cookie: &str) -> Request<Body> {
+    Request::builder()
+        .uri(format!("/admin/audit{query}"))
+        .header(header::COOKIE, cookie)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_admin_actions_are_recorded_with_their_session() {
+    let app = AppStateBuilder::new()
+        .manual_clock(START)
+        .with_file("doomed.txt", b"bye", "203.0.113.9", 3600)
+        .build();
+    let state = app.state.clone();
+    let router = build_router(state.clone());
+
+    let (status, _) = send(&router, form("/auth/json", None, "key=wrong")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, body) = send(
+        &router,
+        form("/auth/json", None, &format!("key={DEFAULT_TEST_ADMIN_KEY}")),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let login: Value = serde_json::from_str(&body).unwrap();
+    let token = login["token"].as_str().unwrap().to_string();
+    let cookie = format!("adm={token}");
+    let actor = session_actor(&token);
+    assert!(!actor.contains(&token));
+
+    state.clock.advance(10);
+    let (status, _) = send(
+        &router,
+        form(
+            "/admin/ban",
+            Some(&cookie),
+            "ip=192.0.2.44&reason=spam+wave",
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    let ban_key = state.bans.read().await[0].subject.key().to_string();
+    let (status, _) = send(
+        &router,
+        form("/unban", Some(&cookie), &format!("key={ban_key}")),
+    )
+    .await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    let (status, _) = send(
+        &router,
+        form(
+            "/admin/files",
+            Some(&cookie),
+            "file=doomed.txt&reason=abuse",
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+
+    let entries = state.audit_log.entries().await;
+    let actions: Vec<AuditAction> = entries.iter().map(|e| e.action).collect();
+    assert_eq!(
+        actions,
+        [
+            AuditAction::AuthFailure,
+            AuditAction::AuthSuccess,
+            AuditAction::Ban,
+            AuditAction::Unban,
+            AuditAction::FileDelete,
+        ]
+    );
+    assert_eq!(entries[0].actor, "anonymous");
+    assert!(entries[1..].iter().all(|e| e.actor == actor));
+    assert_eq!(entries[2].target.as_deref(), Some("192.0.2.44"));
+    assert_eq!(entries[2].detail.as_deref(), Some("spam wave"));
+    assert_eq!(entries[2].time, START + 10);
+    assert_eq!(entries[3].target.as_deref(), Some(ban_key.as_str()));
+    assert_eq!(entries[4].target.as_deref(), Some("doomed.txt"));
+    assert_eq!(entries[4].detail.as_deref(), Some("abuse"));
+
+    // The log was appended to the store entry by entry and survives a reload.
+    state.load_audit_log().await.unwrap();
+    assert_eq!(state.audit_log.entries().await, entries);
+
+    let (status, _) = send(
+        &router,
+        Request::builder()
+            .uri("/admin/audit")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, page) = send(&router, audit_page("", &cookie)).await;
+    assert_eq!(status, StatusCode::OK);
+    // Newest first.
+    let file_delete = page.find("data-action=file_delete").unwrap();
+    let failure = page.find("data-action=auth_failure").unwrap();
+    assert!(file_delete < failure);
+    assert!(page.contains(&actor));
+
+    let (_, page) = send(&router, audit_page("?q=unban", &cookie)).await;
+    assert!(page.contains("data-action=unban"));
+    assert!(!page.contains("data-action=ban>"));
+    assert!(!page.contains("data-action=auth_success"));
+}
+
+#[tokio::test]
+async fn test_api_deletes_are_attributed_to_the_admin_key() {
+    let app = AppStateBuilder::new()
+        .with_file("a.txt", b"alpha", "203.0.113.7", 3600)
+        .build();
+    let state = app.state.clone();
+    let router = build_router(state.clone());
+    let (status, _) = send(
+        &router,
+        Request::builder()
+            .method(Method::DELETE)
+            .uri("/api/admin/v1/files/a.txt?reason=spam")
+            .header(
+                header::AUTHORIZATION,
+                format!("Bearer {DEFAULT_TEST_ADMIN_KEY}"),
+            )
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let entries = state.audit_log.entries().await;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].action, AuditAction::FileDelete);
+    assert_eq!(entries[0].actor, "admin_key");
+    assert_eq!(entries[0].target.as_deref(), Some("a.txt"));
+    assert_eq!(entries[0].detail.as_deref(), Some("spam"));
+}
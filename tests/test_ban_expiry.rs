@@ -0,0 +1,140 @@
+use axum::body::{Body, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode, header};
+use juicebox::handlers::{ban_gate, build_router};
+use juicebox::state::IpBan;
+use juicebox::testing::AppStateBuilder;
+use std::net::SocketAddr;
+use tower::ServiceExt;
+
+const START: u64 = 1_700_000_000;
+
+fn from(mut req: Request<Body>, ip: [u8; 4]) -> Request<Body> {
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from((ip, 6710))));
+    req
+}
+
+fn ban_form(body: &str) -> Request<Body> {
+    Request::builder()
+        .method(Method::POST)
+        .uri("/admin/ban")
+        .header(header::COOKIE, "adm=expiry-admin")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn home() -> Request<Body> {
+    Request::builder().uri("/").body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn test_temporary_ban_lapses_and_is_pruned() {
+    let app = AppStateBuilder::new().manual_clock(START).build();
+    let state = app.state.clone();
+    state.create_admin_session("expiry-admin".to_string()).await;
+    let router = build_router(state.clone()).layer(axum::middleware::from_fn_with_state(
+        state.clone(),
+        ban_gate,
+    ));
+
+    let resp = router
+        .clone()
+        .oneshot(from(
+            ban_form("ip=198.51.100.0%2F24&reason=spam&duration=2h"),
+            [192, 0, 2, 1],
+        ))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+    let ban = state.bans.read().await[0].clone();
+    assert_eq!(ban.expires, Some(START + 2 * 3600));
+
+    let resp = router
+        .clone()
+        .oneshot(from(home(), [198, 51, 100, 7]))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let html = String::from_utf8_lossy(&body);
+    assert!(html.contains(&format!("Until: {}", START + 7200)), "{html}");
+
+    // Nothing to prune while the ban runs.
+    assert_eq!(state.prune_expired_bans().await, 0);
+
+    state.clock.advance(2 * 3600);
+    assert!(!state.is_banned("198.51.100.7").await);
+    let resp = router
+        .clone()
+        .oneshot(from(home(), [198, 51, 100, 7]))
+        .await
+        .unwrap();
+    assert_ne!(resp.status(), StatusCode::FORBIDDEN);
+    // The lapsed ban stays listed until the cleanup task prunes it.
+    assert_eq!(state.bans.read().await.len(), 1);
+    assert_eq!(state.prune_expired_bans().await, 1);
+    assert!(state.bans.read().await.is_empty());
+    assert_eq!(state.ban_hits.get(&ban.subject.key()).hits, 0);
+}
+
+#[tokio::test]
+async fn test_ban_form_defaults_to_permanent_and_rejects_bad_durations() {
+    let app = AppStateBuilder::new().manual_clock(START).build();
+    let state = app.state.clone();
+    state.create_admin_session("expiry-admin".to_string()).await;
+    let router = build_router(state.clone());
+
+    let resp = router
+        .clone()
+        .oneshot(from(
+            ban_form("ip=198.51.100.9&reason=abuse&duration=forever"),
+            [192, 0, 2, 1],
+        ))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert!(state.bans.read().await.is_empty());
+
+    let resp = router
+        .clone()
+        .oneshot(from(
+            ban_form("ip=198.51.100.9&reason=abuse&duration="),
+            [192, 0, 2, 1],
+        ))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+    assert_eq!(state.bans.read().await[0].expires, None);
+
+    let page = Request::builder()
+        .uri("/admin/ban")
+        .header(header::COOKIE, "adm=expiry-admin")
+        .body(Body::empty())
+        .unwrap();
+    let resp = router
+        .clone()
+        .oneshot(from(page, [192, 0, 2, 1]))
+        .await
+        .unwrap();
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let html = String::from_utf8_lossy(&body);
+    assert!(html.contains("<td>never</td>"), "{html}");
+
+    state.clock.advance(365 * 24 * 3600);
+    assert!(state.is_banned("198.51.100.9").await);
+    assert_eq!(state.prune_expired_bans().await, 0);
+}
+
+#[test]
+fn test_bans_without_expiry_deserialize_as_permanent() {
+    let ban: IpBan = serde_json::from_str(
+        r#"{"subject":{"mode":"exact","hash":"abc"},"reason":"old","time":5}"#,
+    )
+    .unwrap();
+    assert_eq!(ban.expires, None);
+    assert!(ban.is_active(u64::MAX));
+    let json = serde_json::to_string(&ban).unwrap();
+    assert!(!json.contains("expires"), "{json}");
+}
@@ -0,0 +1,115 @@
+use axum::body::{Body, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode, header};
+use juicebox::ban_hits::BanHits;
+use juicebox::handlers::{ban_gate, build_router};
+use juicebox::testing::AppStateBuilder;
+use std::net::SocketAddr;
+use tower::ServiceExt;
+
+const START: u64 = 1_700_000_000;
+
+fn from(mut req: Request<Body>, ip: [u8; 4]) -> Request<Body> {
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from((ip, 6700))));
+    req
+}
+
+fn home() -> Request<Body> {
+    Request::builder().uri("/").body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn test_ban_gate_counts_hits_and_admin_page_shows_them() {
+    let app = AppStateBuilder::new()
+        .manual_clock(START)
+        .with_ban("198.51.100.0/24", "fixture")
+        .build();
+    let state = app.state.clone();
+    let router = build_router(state.clone()).layer(axum::middleware::from_fn_with_state(
+        state.clone(),
+        ban_gate,
+    ));
+    let key = state.bans.read().await[0].subject.key().to_string();
+    assert_eq!(state.ban_hits.get(&key), BanHits::default());
+
+    let resp = router
+        .clone()
+        .oneshot(from(home(), [198, 51, 100, 7]))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    state.clock.advance(120);
+    let resp = router
+        .clone()
+        .oneshot(from(home(), [198, 51, 100, 8]))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    // Requests the ban does not cover leave it alone.
+    let resp = router
+        .clone()
+        .oneshot(from(home(), [192, 0, 2, 1]))
+        .await
+        .unwrap();
+    assert_ne!(resp.status(), StatusCode::FORBIDDEN);
+    assert_eq!(
+        state.ban_hits.get(&key),
+        BanHits {
+            hits: 2,
+            last_hit: Some(START + 120),
+        }
+    );
+
+    state.create_admin_session("hits-admin".to_string()).await;
+    let page = Request::builder()
+        .uri("/admin/ban")
+        .header(header::COOKIE, "adm=hits-admin")
+        .body(Body::empty())
+        .unwrap();
+    let resp = router
+        .clone()
+        .oneshot(from(page, [192, 0, 2, 1]))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let html = String::from_utf8_lossy(&body);
+    assert!(html.contains("data-hits=2>"), "{html}");
+    assert!(
+        html.contains(&format!("<td>{}</td>", START + 120)),
+        "{html}"
+    );
+
+    // Persisted counters survive a reload; a lifted ban takes its counters
+    // with it.
+    state.persist_ban_hits().await;
+    state.ban_hits.remove(&key);
+    state.load_ban_hits().await.unwrap();
+    assert_eq!(state.ban_hits.get(&key).hits, 2);
+
+    let unban = Request::builder()
+        .method(Method::POST)
+        .uri("/unban")
+        .header(header::COOKIE, "adm=hits-admin")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(format!("key={key}")))
+        .unwrap();
+    let resp = router.oneshot(from(unban, [192, 0, 2, 1])).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+    assert_eq!(state.ban_hits.get(&key), BanHits::default());
+    state.persist_ban_hits().await;
+    state.load_ban_hits().await.unwrap();
+    assert!(state.ban_hits.is_empty());
+}
+
+#[test]
+fn test_ban_hits_per_day() {
+    let hits = BanHits {
+        hits: 30,
+        last_hit: Some(START),
+    };
+    // A ban younger than a day counts as one day old.
+    assert_eq!(hits.per_day(START - 60, START), 30.0);
+    assert_eq!(hits.per_day(START - 10 * 86_400, START), 3.0);
+}
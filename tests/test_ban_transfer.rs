@@ -0,0 +1,196 @@
+use axum::Router;
+use axum::body::{Body, to_bytes};
+use axum::http::{Method, Request, StatusCode, header};
+use juicebox::ban_transfer::{CSV_HEADER, parse_csv, to_csv};
+use juicebox::handlers::build_router;
+use juicebox::state::{BanSubject, IpBan};
+use juicebox::testing::{AppStateBuilder, DEFAULT_TEST_ADMIN_KEY};
+use serde_json::{Value, json};
+use tower::ServiceExt;
+
+const START: u64 = 1_700_000_000;
+
+async fn send(app: &Router, req: Request<Body>) -> (StatusCode, String) {
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+fn export(format: &str) -> Request<Body> {
+    Request::builder()
+        .uri(format!("/api/admin/v1/bans?format={format}"))
+        .header(
+            header::AUTHORIZATION,
+            format!("Bearer {DEFAULT_TEST_ADMIN_KEY}"),
+        )
+        .body(Body::empty())
+        .unwrap()
+}
+
+fn import(content_type: &str, body: String) -> Request<Body> {
+    Request::builder()
+        .method(Method::POST)
+        .uri("/api/admin/v1/bans")
+        .header(
+            header::AUTHORIZATION,
+            format!("Bearer {DEFAULT_TEST_ADMIN_KEY}"),
+        )
+        .header(header::CONTENT_TYPE, content_type)
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_export_round_trips_through_import_elsewhere() {
+    let source = AppStateBuilder::new()
+        .manual_clock(START)
+        .with_ban("198.51.100.0/24", "abuse, mostly")
+        .with_ban("203.0.113.9", "spam \"bot\"")
+        .build();
+    let source_app = build_router(source.state.clone());
+
+    let (status, csv) = send(&source_app, export("csv")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(csv.starts_with(CSV_HEADER), "{csv}");
+    assert_eq!(parse_csv(&csv).unwrap().len(), 2);
+    let (status, json_body) = send(&source_app, export("json")).await;
+    assert_eq!(status, StatusCode::OK);
+    let exported: Value = serde_json::from_str(&json_body).unwrap();
+    assert_eq!(exported["bans"].as_array().unwrap().len(), 2);
+
+    // Same hash secret, so the hashed subjects match as they are.
+    for (content_type, body) in [("text/csv", csv), ("application/json", json_body)] {
+        let target = AppStateBuilder::new().manual_clock(START).build();
+        let app = build_router(target.state.clone());
+        let (status, summary) = send(&app, import(content_type, body.clone())).await;
+        assert_eq!(status, StatusCode::OK, "{summary}");
+        let summary: Value = serde_json::from_str(&summary).unwrap();
+        assert_eq!(summary["added"], 2);
+        assert!(target.state.is_banned("198.51.100.44").await);
+        assert!(target.state.is_banned("203.0.113.9").await);
+
+        // Importing again changes nothing.
+        let (_, summary) = send(&app, import(content_type, body)).await;
+        let summary: Value = serde_json::from_str(&summary).unwrap();
+        assert_eq!(summary["added"], 0);
+        assert_eq!(summary["unchanged"], 2);
+    }
+}
+
+#[tokio::test]
+async fn test_import_merges_by_key_and_skips_lapsed_bans() {
+    let app = AppStateBuilder::new()
+        .manual_clock(START)
+        .with_ban("203.0.113.9", "old reason")
+        .with_ban("192.0.2.1", "kept")
+        .build();
+    let state = app.state.clone();
+    let router = build_router(state.clone());
+    let held = state.bans.read().await[0].clone();
+    let body = json!([
+        {"subject": held.subject, "reason": "new reason", "time": START},
+        {"subject": {"mode": "exact", "hash": "f".repeat(64)}, "reason": "gone", "time": 1, "expires": START - 1},
+    ]);
+    let (status, summary) = send(&router, import("application/json", body.to_string())).await;
+    assert_eq!(status, StatusCode::OK);
+    let summary: Value = serde_json::from_str(&summary).unwrap();
+    assert_eq!(summary["added"], 0);
+    assert_eq!(summary["updated"], 1);
+    assert_eq!(summary["expired"], 1);
+
+    let bans = state.bans.read().await.clone();
+    assert_eq!(bans.len(), 2);
+    assert_eq!(bans[0].reason, "new reason");
+    assert_eq!(bans[1].reason, "kept");
+
+    let log = state.audit_log.entries().await;
+    let last = log.last().unwrap();
+    assert_eq!(last.action.as_str(), "ban_import");
+    assert_eq!(
+        last.detail.as_deref(),
+        Some("added 0, updated 1, unchanged 0, expired 1")
+    );
+}
+
+#[tokio::test]
+async fn test_labelled_bans_are_rehashed_for_another_secret() {
+    let source = AppStateBuilder::new()
+        .hash_secret(b"source-secret")
+        .manual_clock(START)
+        .build();
+    let subject = source
+        .state
+        .ban_subject_from_input("198.51.100.0/24")
+        .unwrap();
+    let ban = IpBan {
+        subject,
+        label: Some("198.51.100.0/24".into()),
+        reason: "abuse".into(),
+        time: START,
+        expires: None,
+    };
+    let csv = to_csv(std::slice::from_ref(&ban));
+
+    let target = AppStateBuilder::new()
+        .hash_secret(b"target-secret")
+        .manual_clock(START)
+        .build();
+    let summary = target.state.import_bans(parse_csv(&csv).unwrap()).await;
+    assert_eq!(summary.added, 1);
+    assert!(target.state.is_banned("198.51.100.7").await);
+    let imported = target.state.bans.read().await[0].clone();
+    assert!(matches!(
+        imported.subject,
+        BanSubject::Network { prefix: 24, .. }
+    ));
+    assert_ne!(imported.subject.key(), ban.subject.key());
+}
+
+#[tokio::test]
+async fn test_bad_imports_and_unauthenticated_calls_are_refused() {
+    let app = AppStateBuilder::new().build();
+    let router = build_router(app.state.clone());
+    let (status, body) = send(
+        &router,
+        import("text/csv", format!("{CSV_HEADER}\nexact,,,,,x,1,\n")),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("line 2: missing hash"), "{body}");
+    let (status, _) = send(&router, import("text/csv", "hash\nabc\n".into())).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(&router, import("application/json", "{}".into())).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(&router, export("xml")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(app.state.bans.read().await.is_empty());
+
+    let (status, _) = send(
+        &router,
+        Request::builder()
+            .uri("/api/admin/v1/bans")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn test_csv_quotes_awkward_fields() {
+    let ban = IpBan {
+        subject: BanSubject::Exact { hash: "abc".into() },
+        label: Some("line one\nline two".into()),
+        reason: "a, \"b\"".into(),
+        time: 5,
+        expires: Some(10),
+    };
+    let csv = to_csv(std::slice::from_ref(&ban));
+    assert!(csv.contains("\"a, \"\"b\"\"\""), "{csv}");
+    let parsed = parse_csv(&csv).unwrap();
+    assert_eq!(parsed.len(), 1);
+    assert_eq!(parsed[0].label, ban.label);
+    assert_eq!(parsed[0].reason, ban.reason);
+    assert_eq!(parsed[0].expires, Some(10));
+}
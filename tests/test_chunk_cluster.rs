@@ -0,0 +1,238 @@
+use axum::Router;
+use axum::body::{Body, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode, header};
+use juicebox::cluster::NODE_HEADER;
+use juicebox::handlers::build_router;
+use juicebox::state::{CHUNK_SESSION_IDLE_SECS, ORPHAN_CHUNK_DIR_GRACE_SECS};
+use juicebox::testing::{AppStateBuilder, TestApp};
+use serde_json::{Value, json};
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime};
+use tower::ServiceExt;
+
+const CHUNK: usize = 64 * 1024;
+
+/// Two replicas over one storage root, as behind a round-robin balancer.
+fn replicas() -> ((TestApp, Router), (TestApp, Router)) {
+    let a = AppStateBuilder::new().node_id("node-a").build();
+    let b = AppStateBuilder::new()
+        .node_id("node-b")
+        .root(a.root.clone())
+        .build();
+    let router_a = build_router(a.state.clone());
+    let router_b = build_router(b.state.clone());
+    ((a, router_a), (b, router_b))
+}
+
+async fn send(app: &Router, mut req: Request<Body>) -> (StatusCode, String, Value) {
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([198, 51, 100, 71], 6900))));
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let node = resp
+        .headers()
+        .get(NODE_HEADER)
+        .map(|v| v.to_str().unwrap().to_string())
+        .unwrap_or_default();
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (
+        status,
+        node,
+        serde_json::from_slice(&body).unwrap_or(Value::Null),
+    )
+}
+
+fn part(id: &str, index: u32, body: Vec<u8>) -> Request<Body> {
+    Request::builder()
+        .method(Method::PUT)
+        .uri(format!("/chunk/{id}/{index}"))
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_chunks_spread_across_replicas_complete() {
+    let ((a, router_a), (b, router_b)) = replicas();
+    let size = CHUNK + 10;
+    let init = Request::builder()
+        .method(Method::POST)
+        .uri("/chunk/init")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            json!({"filename": "spread.bin", "size": size, "chunk_size": CHUNK}).to_string(),
+        ))
+        .unwrap();
+    let (status, node, session) = send(&router_a, init).await;
+    assert_eq!(status, StatusCode::OK, "{session}");
+    assert_eq!(node, "node-a");
+    assert_eq!(session["total_chunks"], 2);
+    let id = session["session_id"].as_str().unwrap();
+
+    let (status, node, _) = send(&router_a, part(id, 0, vec![b'a'; CHUNK])).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(node, "node-a");
+
+    // node-b never saw the init; it reads the session from shared storage and
+    // still points the balancer at node-a.
+    assert!(!b.state.chunk_sessions.contains_key(id));
+    let (status, node, _) = send(&router_b, part(id, 1, vec![b'b'; 10])).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(node, "node-a");
+    assert!(b.state.chunk_sessions.contains_key(id));
+
+    // node-a's own view is missing chunk 1, which is on disk all the same.
+    let complete = Request::builder()
+        .method(Method::POST)
+        .uri(format!("/chunk/{id}/complete"))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from("{}"))
+        .unwrap();
+    let (status, node, body) = send(&router_a, complete).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(node, "node-a");
+    let storage_name = session["storage_name"].as_str().unwrap();
+    let stored = a.state.file_store.open(storage_name).await.unwrap();
+    assert!(stored.is_some());
+}
+
+#[tokio::test]
+async fn test_unknown_session_names_this_replica() {
+    let (_, (_, router_b)) = replicas();
+    let status = Request::builder()
+        .uri("/chunk/nosuchsession/status")
+        .body(Body::empty())
+        .unwrap();
+    let (status, node, body) = send(&router_b, status).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["code"], "chunk_session");
+    assert_eq!(node, "node-b");
+
+    let outside = Request::builder()
+        .uri("/chunk/..%2F..%2Fdata/status")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(send(&router_b, outside).await.0, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_orphaned_chunk_dirs_are_swept_after_the_grace_window() {
+    let now = juicebox::util::now_secs();
+    let a = AppStateBuilder::new()
+        .node_id("node-a")
+        .manual_clock(now)
+        .build();
+    let b = AppStateBuilder::new()
+        .node_id("node-b")
+        .manual_clock(now)
+        .root(a.root.clone())
+        .build();
+    let router_a = build_router(a.state.clone());
+    let init = Request::builder()
+        .method(Method::POST)
+        .uri("/chunk/init")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            json!({"filename": "live.bin", "size": CHUNK}).to_string(),
+        ))
+        .unwrap();
+    let (status, _, session) = send(&router_a, init).await;
+    assert_eq!(status, StatusCode::OK, "{session}");
+    let live = a
+        .state
+        .chunk_dir
+        .join(session["session_id"].as_str().unwrap());
+    // What a crash before `session.json` was written leaves behind.
+    let crashed = a.state.chunk_dir.join("crashed");
+    std::fs::create_dir_all(&crashed).unwrap();
+    std::fs::write(crashed.join("000000.chunk"), b"partial").unwrap();
+
+    assert_eq!(a.state.sweep_orphaned_chunk_dirs().await, 0);
+    assert!(crashed.exists());
+
+    // The writes can land a second or more after the clock was pinned, so
+    // the grace window is counted from the directory's real mtime.
+    let touched = [crashed.clone(), crashed.join("000000.chunk")]
+        .iter()
+        .map(|path| {
+            std::fs::metadata(path)
+                .unwrap()
+                .modified()
+                .unwrap()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        })
+        .max()
+        .unwrap();
+    a.state
+        .clock
+        .advance(touched + ORPHAN_CHUNK_DIR_GRACE_SECS + 1 - now);
+    assert_eq!(a.state.sweep_orphaned_chunk_dirs().await, 1);
+    assert!(!crashed.exists());
+    assert!(live.exists(), "a session in memory keeps its directory");
+
+    // node-b never loaded the session, but it was written to recently.
+    assert!(
+        !b.state
+            .chunk_sessions
+            .contains_key(session["session_id"].as_str().unwrap())
+    );
+    assert_eq!(b.state.sweep_orphaned_chunk_dirs().await, 0);
+    assert!(live.exists());
+}
+
+#[tokio::test]
+async fn test_idle_sweep_leaves_sessions_another_replica_is_writing() {
+    let now = juicebox::util::now_secs();
+    let a = AppStateBuilder::new()
+        .node_id("node-a")
+        .manual_clock(now)
+        .build();
+    let b = AppStateBuilder::new()
+        .node_id("node-b")
+        .manual_clock(now)
+        .root(a.root.clone())
+        .build();
+    let router_a = build_router(a.state.clone());
+    let router_b = build_router(b.state.clone());
+    let init = Request::builder()
+        .method(Method::POST)
+        .uri("/chunk/init")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            json!({"filename": "slow.bin", "size": 3 * CHUNK, "chunk_size": CHUNK}).to_string(),
+        ))
+        .unwrap();
+    let (status, _, session) = send(&router_a, init).await;
+    assert_eq!(status, StatusCode::OK, "{session}");
+    let id = session["session_id"].as_str().unwrap();
+    let (status, _, _) = send(&router_b, part(id, 0, vec![b'a'; CHUNK])).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(b.state.chunk_sessions.contains_key(id));
+
+    // node-a keeps taking chunks long after node-b last served the session.
+    let later = now + CHUNK_SESSION_IDLE_SECS + 5;
+    a.state.clock.advance(CHUNK_SESSION_IDLE_SECS + 5);
+    b.state.clock.advance(CHUNK_SESSION_IDLE_SECS + 5);
+    let (status, _, _) = send(&router_a, part(id, 1, vec![b'b'; CHUNK])).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let dir = a.state.chunk_dir.join(id);
+    std::fs::File::options()
+        .write(true)
+        .open(dir.join("000001.chunk"))
+        .unwrap()
+        .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(later))
+        .unwrap();
+
+    b.state.cleanup_chunk_sessions().await;
+    assert!(b.state.chunk_sessions.contains_key(id));
+    assert!(dir.join("session.json").exists());
+    assert!(dir.join("000001.chunk").exists());
+
+    // Once nothing has written to it for the idle window, it goes.
+    b.state.clock.advance(CHUNK_SESSION_IDLE_SECS + 1);
+    b.state.cleanup_chunk_sessions().await;
+    assert!(!b.state.chunk_sessions.contains_key(id));
+    assert!(!dir.join("session.json").exists());
+}
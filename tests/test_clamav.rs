@@ -0,0 +1,229 @@
+use axum::Router;
+use axum::body::{Body, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode, header};
+use juicebox::clamav::{Clamd, ClamdAddress, ScanVerdict, parse_reply};
+use juicebox::handlers::build_router;
+use juicebox::quarantine::SOURCE_CLAMAV;
+use juicebox::state::AppState;
+use juicebox::testing::{AppStateBuilder, TestApp};
+use serde_json::{Value, json};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tower::ServiceExt;
+
+const EICAR: &[u8] = b"X5O!P%@AP[4\\PZX54(P^)7CC)7}$EICAR-STANDARD-ANTIVIRUS-TEST-FILE!$H+H*";
+
+/// Answer one `INSTREAM` the way clamd does, flagging anything containing
+/// "EICAR".
+async fn answer_instream(mut conn: impl AsyncRead + AsyncWrite + Unpin) {
+    let mut command = [0u8; 10];
+    conn.read_exact(&mut command).await.unwrap();
+    assert_eq!(&command, b"zINSTREAM\0");
+    let mut scanned = Vec::new();
+    loop {
+        let len = conn.read_u32().await.unwrap() as usize;
+        if len == 0 {
+            break;
+        }
+        let mut frame = vec![0u8; len];
+        conn.read_exact(&mut frame).await.unwrap();
+        scanned.extend_from_slice(&frame);
+    }
+    let infected = scanned.windows(5).any(|w| w == b"EICAR");
+    let reply: &[u8] = if infected {
+        b"stream: Eicar-Test-Signature FOUND\0"
+    } else {
+        b"stream: OK\0"
+    };
+    conn.write_all(reply).await.unwrap();
+}
+
+async fn fake_clamd() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+        loop {
+            let (conn, _) = listener.accept().await.unwrap();
+            tokio::spawn(answer_instream(conn));
+        }
+    });
+    addr
+}
+
+/// An address nothing listens on.
+async fn dead_address() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+fn app_with(clamd: Clamd) -> (TestApp, Router) {
+    let app = AppStateBuilder::new().clamd(clamd).build();
+    let router = build_router(app.state.clone());
+    (app, router)
+}
+
+async fn send(app: &Router, mut req: Request<Body>) -> (StatusCode, Value) {
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([198, 51, 100, 70], 6800))));
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+fn paste(body: &[u8]) -> Request<Body> {
+    Request::builder()
+        .method(Method::POST)
+        .uri("/api/paste-binary?name=note.txt")
+        .body(Body::from(body.to_vec()))
+        .unwrap()
+}
+
+async fn quarantined(state: &AppState) -> Vec<(String, String)> {
+    state
+        .quarantine
+        .records()
+        .await
+        .into_iter()
+        .map(|r| (r.source, r.verdict))
+        .collect()
+}
+
+#[test]
+fn test_clamd_address_and_reply_parsing() {
+    assert_eq!(
+        ClamdAddress::parse("tcp://clamav:3310"),
+        Some(ClamdAddress::Tcp("clamav:3310".into()))
+    );
+    assert_eq!(
+        ClamdAddress::parse("127.0.0.1:3310"),
+        Some(ClamdAddress::Tcp("127.0.0.1:3310".into()))
+    );
+    assert_eq!(
+        ClamdAddress::parse("unix:/run/clamav/clamd.ctl"),
+        Some(ClamdAddress::Unix(PathBuf::from("/run/clamav/clamd.ctl")))
+    );
+    assert_eq!(
+        ClamdAddress::parse("/run/clamav/clamd.ctl"),
+        Some(ClamdAddress::Unix(PathBuf::from("/run/clamav/clamd.ctl")))
+    );
+    assert_eq!(ClamdAddress::parse("clamav"), None);
+
+    assert_eq!(parse_reply("stream: OK\0").unwrap(), ScanVerdict::Clean);
+    assert_eq!(
+        parse_reply("stream: Win.Test.EICAR_HDB-1 FOUND\0").unwrap(),
+        ScanVerdict::Infected("Win.Test.EICAR_HDB-1".into())
+    );
+    let err = parse_reply("INSTREAM size limit exceeded. ERROR\0").unwrap_err();
+    assert!(err.to_string().contains("size limit"), "{err}");
+}
+
+#[tokio::test]
+async fn test_infected_paste_is_quarantined() {
+    let clamd = Clamd::new(ClamdAddress::Tcp(fake_clamd().await));
+    let (app, router) = app_with(clamd);
+
+    let (status, body) = send(&router, paste(b"just some notes")).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert!(
+        app.state
+            .owners
+            .contains_key(body["file"].as_str().unwrap())
+    );
+
+    let (status, body) = send(&router, paste(EICAR)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["code"], "virus_detected");
+    assert_eq!(app.state.owners.len(), 1);
+    assert_eq!(
+        quarantined(&app.state).await,
+        vec![(
+            SOURCE_CLAMAV.to_string(),
+            "Eicar-Test-Signature".to_string()
+        )]
+    );
+}
+
+#[tokio::test]
+async fn test_infected_chunked_upload_is_quarantined() {
+    let clamd = Clamd::new(ClamdAddress::Tcp(fake_clamd().await));
+    let (app, router) = app_with(clamd);
+
+    let init = Request::builder()
+        .method(Method::POST)
+        .uri("/chunk/init")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            json!({"filename": "eicar.txt", "size": EICAR.len()}).to_string(),
+        ))
+        .unwrap();
+    let (status, session) = send(&router, init).await;
+    assert_eq!(status, StatusCode::OK, "{session}");
+    let id = session["session_id"].as_str().unwrap();
+    let part = Request::builder()
+        .method(Method::PUT)
+        .uri(format!("/chunk/{id}/0"))
+        .body(Body::from(EICAR))
+        .unwrap();
+    assert_eq!(send(&router, part).await.0, StatusCode::NO_CONTENT);
+    let complete = Request::builder()
+        .method(Method::POST)
+        .uri(format!("/chunk/{id}/complete"))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from("{}"))
+        .unwrap();
+    let (status, body) = send(&router, complete).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["code"], "virus_detected");
+    assert!(app.state.owners.is_empty());
+    let storage_name = session["storage_name"].as_str().unwrap();
+    assert!(app.state.quarantine.get(storage_name).await.is_some());
+}
+
+#[tokio::test]
+async fn test_scan_failure_follows_fail_closed() {
+    let address = ClamdAddress::Tcp(dead_address().await);
+
+    let (app, router) = app_with(Clamd::new(address.clone()));
+    let (status, _) = send(&router, paste(b"unscanned")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(quarantined(&app.state).await.is_empty());
+
+    let (app, router) = app_with(Clamd::new(address).fail_closed(true));
+    let (status, body) = send(&router, paste(b"unscanned")).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["code"], "rejected");
+    assert_eq!(
+        quarantined(&app.state).await,
+        vec![(SOURCE_CLAMAV.to_string(), "scan failed".to_string())]
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_clamd_over_unix_socket() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("clamd.sock");
+    let listener = tokio::net::UnixListener::bind(&path).unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (conn, _) = listener.accept().await.unwrap();
+            tokio::spawn(answer_instream(conn));
+        }
+    });
+    let clamd = Clamd::new(ClamdAddress::Unix(path));
+    assert_eq!(
+        clamd.scan(&b"clean bytes"[..]).await.unwrap(),
+        ScanVerdict::Clean
+    );
+    // Bigger than one frame, with the signature at the end.
+    let mut large = vec![b'a'; 200_000];
+    large.extend_from_slice(EICAR);
+    assert_eq!(
+        clamd.scan(&large[..]).await.unwrap(),
+        ScanVerdict::Infected("Eicar-Test-Signature".into())
+    );
+}
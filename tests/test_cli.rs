@@ -0,0 +1,82 @@
+use clap::error::ErrorKind;
+use juicebox::cli::{AdminCommand, Command, parse};
+use juicebox::tombstones::RemovalReason;
+
+fn error_kind(args: &[&str]) -> Option<ErrorKind> {
+    parse(args).err().map(|err| err.kind())
+}
+
+#[test]
+fn test_cli_defaults_to_serve() {
+    assert_eq!(parse(Vec::<String>::new()).unwrap(), Command::Serve);
+    assert_eq!(parse(["serve"]).unwrap(), Command::Serve);
+}
+
+#[test]
+fn test_cli_parses_subcommands() {
+    assert_eq!(parse(["migrate"]).unwrap(), Command::Migrate);
+    assert_eq!(parse(["gc"]).unwrap(), Command::Gc);
+    assert_eq!(parse(["check-config"]).unwrap(), Command::CheckConfig);
+    assert_eq!(error_kind(&["help"]), Some(ErrorKind::DisplayHelp));
+    assert_eq!(error_kind(&["gc", "--help"]), Some(ErrorKind::DisplayHelp));
+    assert_eq!(error_kind(&["-V"]), Some(ErrorKind::DisplayVersion));
+}
+
+#[test]
+fn test_cli_rejects_unknown_arguments() {
+    assert_eq!(error_kind(&["vacuum"]), Some(ErrorKind::InvalidSubcommand));
+    assert_eq!(error_kind(&["--port"]), Some(ErrorKind::UnknownArgument));
+    assert!(parse(["migrate", "gc"]).is_err());
+}
+
+#[test]
+fn test_cli_parses_admin_actions() {
+    let admin = |args: &[&str]| match parse(args) {
+        Ok(Command::Admin(command)) => Some(command),
+        _ => None,
+    };
+    assert_eq!(admin(&["admin", "bans"]), Some(AdminCommand::ListBans));
+    assert_eq!(admin(&["admin", "owners"]), Some(AdminCommand::Owners));
+    assert_eq!(
+        admin(&["admin", "ban", "203.0.113.0/24", "--reason", "spam wave"]),
+        Some(AdminCommand::Ban {
+            target: "203.0.113.0/24".into(),
+            reason: "spam wave".into(),
+        })
+    );
+    assert_eq!(
+        admin(&["admin", "unban", "203.0.113.0/24"]),
+        Some(AdminCommand::Unban {
+            target: "203.0.113.0/24".into(),
+        })
+    );
+    assert_eq!(
+        admin(&["admin", "delete", "--reason", "malware", "abc.exe"]),
+        Some(AdminCommand::Delete {
+            file: "abc.exe".into(),
+            reason: RemovalReason::Malware,
+        })
+    );
+    assert_eq!(
+        admin(&["admin", "delete", "abc.exe"]),
+        Some(AdminCommand::Delete {
+            file: "abc.exe".into(),
+            reason: RemovalReason::Other,
+        })
+    );
+    assert_eq!(
+        error_kind(&["admin", "ban", "--help"]),
+        Some(ErrorKind::DisplayHelp)
+    );
+
+    let err = parse(["admin", "delete", "abc.exe", "--reason", "meh"]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ValueValidation);
+    assert!(err.to_string().contains("unknown removal reason 'meh'"));
+    assert_eq!(
+        error_kind(&["admin", "ban"]),
+        Some(ErrorKind::MissingRequiredArgument)
+    );
+    assert!(parse(["admin"]).is_err());
+    assert!(parse(["admin", "bans", "--reason", "x"]).is_err());
+    assert!(parse(["admin", "unban", "a", "b"]).is_err());
+}
@@ -0,0 +1,85 @@
+use juicebox::config::Config;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+const SAMPLE: &str = r#"
+[server]
+prod_host = "box.example.com"
+trust_proxy_headers = true
+trusted_proxy_cidrs = ["10.0.0.0/8", "192.168.0.0/16"]
+ip_hash_secret = "hunter2"
+
+[limits]
+max_file_size = "750MB"
+owner_quota_bytes = 1073741824
+
+[storage]
+data_dir = "/srv/juicebox/data"
+
+[sentry]
+dsn = "https://key@o1.ingest.sentry.io/1"
+traces_sample_rate = 0.5
+"#;
+
+#[test]
+fn test_config_file_maps_to_env_vars() {
+    let config = Config::parse(SAMPLE).unwrap();
+    let vars: HashMap<_, _> = config.env_vars().into_iter().collect();
+    assert_eq!(vars["JUICEBOX_PROD_HOST"], "box.example.com");
+    assert_eq!(vars["TRUST_PROXY_HEADERS"], "true");
+    assert_eq!(vars["TRUSTED_PROXY_CIDRS"], "10.0.0.0/8,192.168.0.0/16");
+    assert_eq!(vars["IP_HASH_SECRET"], "hunter2");
+    assert_eq!(vars["MAX_FILE_SIZE"], "750MB");
+    assert_eq!(vars["JUICEBOX_OWNER_QUOTA_BYTES"], "1073741824");
+    assert_eq!(vars["JUICEBOX_DATA_DIR"], "/srv/juicebox/data");
+    assert_eq!(vars["SENTRY_TRACES_SAMPLE_RATE"], "0.5");
+    // Unset values leave each subsystem's default alone.
+    assert!(!vars.contains_key("JUICEBOX_UPLOAD_DIR"));
+    assert_eq!(vars.len(), 9);
+}
+
+#[test]
+fn test_env_vars_override_the_file() {
+    let mut config = Config::parse(SAMPLE).unwrap();
+    let env: HashMap<&str, &str> = HashMap::from([
+        ("JUICEBOX_PROD_HOST", "other.example.com"),
+        ("TRUST_PROXY_HEADERS", "0"),
+        ("JUICEBOX_UPLOAD_DIR", "/mnt/files"),
+        ("SENTRY_TRACES_SAMPLE_RATE", "not a number"),
+    ]);
+    config.overlay(|name| env.get(name).map(|v| v.to_string()));
+    assert_eq!(
+        config.server.prod_host.as_deref(),
+        Some("other.example.com")
+    );
+    assert_eq!(config.server.trust_proxy_headers, Some(false));
+    assert_eq!(config.storage.upload_dir, Some(PathBuf::from("/mnt/files")));
+    // Values that do not parse keep what the file said.
+    assert_eq!(config.sentry.traces_sample_rate, Some(0.5));
+    assert_eq!(config.limits.max_file_size.as_deref(), Some("750MB"));
+}
+
+#[test]
+fn test_config_rejects_unknown_keys_and_hides_secrets() {
+    assert!(Config::parse("[server]\nprod_hots = \"typo\"\n").is_err());
+    assert!(Config::parse("[nope]\n").is_err());
+
+    let config = Config::parse(SAMPLE).unwrap();
+    let debug = format!("{config:?}");
+    assert!(!debug.contains("hunter2"));
+    assert!(!debug.contains("key@o1"));
+    assert_eq!(
+        config.server.ip_hash_secret.as_ref().map(|s| s.expose()),
+        Some("hunter2")
+    );
+}
+
+#[test]
+fn test_load_from_records_the_source_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("juicebox.toml");
+    std::fs::write(&path, SAMPLE).unwrap();
+    let config = Config::load_from(&path).unwrap();
+    assert_eq!(config.source.as_deref(), Some(path.as_path()));
+    assert!(Config::load_from(&dir.path().join("missing.toml")).is_err());
+}
@@ -0,0 +1,173 @@
+mod common;
+
+use axum::Router;
+use axum::body::Body;
+use axum::extract::ConnectInfo;
+use axum::http::{Request, StatusCode};
+use axum::routing::get;
+use juicebox::connections::{
+    ConnectionLimits, ConnectionRejected, ConnectionTracker, TrackConnections, connection_gate,
+};
+use juicebox::state::AppState;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::Notify;
+use tower::ServiceExt;
+
+fn limits(max_total: usize, max_per_ip: usize) -> ConnectionLimits {
+    ConnectionLimits {
+        max_total,
+        max_per_ip,
+    }
+}
+
+#[test]
+fn tracker_enforces_caps_and_releases_on_drop() {
+    let tracker = Arc::new(ConnectionTracker::new(limits(3, 2)));
+    let a: IpAddr = "198.51.100.1".parse().unwrap();
+    let b: IpAddr = "198.51.100.2".parse().unwrap();
+
+    let first = tracker.try_open(a, true).unwrap();
+    let _second = tracker.try_open(a, true).unwrap();
+    assert_eq!(
+        tracker.try_open(a, true).err(),
+        Some(ConnectionRejected::PerIp)
+    );
+    assert_eq!(tracker.connections_from(a), 2);
+
+    let _third = tracker.try_open(b, true).unwrap();
+    assert_eq!(
+        tracker.try_open(b, true).err(),
+        Some(ConnectionRejected::Global)
+    );
+
+    drop(first);
+    assert_eq!(tracker.connections_from(a), 1);
+    assert_eq!(tracker.open_connections(), 2);
+
+    // Trusted proxies are only held to the global cap.
+    let _proxied = tracker.try_open(a, false).unwrap();
+    assert_eq!(tracker.connections_from(a), 1);
+
+    let stats = tracker.stats();
+    assert_eq!(stats.open, 3);
+    assert_eq!(stats.peak, 3);
+    assert_eq!(stats.accepted, 4);
+    assert_eq!(stats.rejected, 2);
+}
+
+async fn send_get(stream: &mut TcpStream) -> String {
+    stream
+        .write_all(b"GET /ping HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut buf = vec![0u8; 1024];
+    let n = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    String::from_utf8_lossy(&buf[..n]).into_owned()
+}
+
+#[tokio::test]
+async fn listener_answers_connections_over_cap_with_429() {
+    let tracker = Arc::new(ConnectionTracker::new(limits(1, 0)));
+    let app = Router::new().route("/ping", get(|| async { "pong" }));
+    let handle = axum_server::Handle::new();
+    let server = axum_server::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .handle(handle.clone())
+        .serve(TrackConnections::new(
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+            tracker.clone(),
+        ));
+    tokio::spawn(server);
+    let addr = handle.listening().await.unwrap();
+
+    let mut held = TcpStream::connect(addr).await.unwrap();
+    assert!(send_get(&mut held).await.starts_with("HTTP/1.1 200"));
+    assert_eq!(tracker.open_connections(), 1);
+
+    let mut extra = TcpStream::connect(addr).await.unwrap();
+    let resp = send_get(&mut extra).await;
+    assert!(resp.starts_with("HTTP/1.1 429"), "{resp}");
+    assert!(resp.contains("server_busy"));
+
+    drop(held);
+    drop(extra);
+    for _ in 0..50 {
+        if tracker.open_connections() == 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(tracker.open_connections(), 0);
+    let mut again = TcpStream::connect(addr).await.unwrap();
+    assert!(send_get(&mut again).await.starts_with("HTTP/1.1 200"));
+    handle.shutdown();
+}
+
+#[tokio::test]
+async fn gate_caps_in_flight_requests_per_client() {
+    let (state, _tmp) = common::setup_test_app();
+    let state = AppState {
+        connections: Arc::new(ConnectionTracker::new(limits(0, 1))),
+        ..state
+    };
+    let release = Arc::new(Notify::new());
+    let wait = release.clone();
+    let app = Router::new()
+        .route(
+            "/slow",
+            get(move || {
+                let wait = wait.clone();
+                async move {
+                    wait.notified().await;
+                    "done"
+                }
+            }),
+        )
+        .route("/fast", get(|| async { "ok" }))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            connection_gate,
+        ));
+    let request = |path: &str, ip: [u8; 4]| {
+        let mut req = Request::builder().uri(path).body(Body::empty()).unwrap();
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from((ip, 4000))));
+        req
+    };
+
+    let slow = tokio::spawn(app.clone().oneshot(request("/slow", [203, 0, 113, 5])));
+    for _ in 0..50 {
+        if state.connections.stats().in_flight_requests == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let resp = app
+        .clone()
+        .oneshot(request("/fast", [203, 0, 113, 5]))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    let resp = app
+        .clone()
+        .oneshot(request("/fast", [203, 0, 113, 6]))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    release.notify_one();
+    assert_eq!(slow.await.unwrap().unwrap().status(), StatusCode::OK);
+    let resp = app
+        .oneshot(request("/fast", [203, 0, 113, 5]))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(state.connections.stats().rejected_requests, 1);
+}
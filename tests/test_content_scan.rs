@@ -0,0 +1,192 @@
+use async_trait::async_trait;
+use axum::Router;
+use axum::body::{Body, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode, header};
+use juicebox::content_scan::{
+    ContentScanner, ContentScanners, Rejection, SAMPLE_BYTES, ScanContent, ScanInput,
+};
+use juicebox::handlers::build_router;
+use juicebox::testing::AppStateBuilder;
+use serde_json::{Value, json};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::io::AsyncReadExt;
+use tower::ServiceExt;
+
+const MARKER: &[u8] = b"RULE-MATCH";
+
+/// Reads the whole upload, as a YARA or HTTP scanner would, and rejects it
+/// when the marker appears past the sample.
+#[derive(Default)]
+struct MarkerScanner {
+    scanned: AtomicUsize,
+}
+
+#[async_trait]
+impl ContentScanner for MarkerScanner {
+    fn name(&self) -> &'static str {
+        "marker"
+    }
+
+    async fn scan(&self, input: &ScanInput<'_>) -> Result<(), Rejection> {
+        self.scanned.fetch_add(1, Ordering::SeqCst);
+        let mut body = Vec::new();
+        input
+            .content
+            .open()
+            .await
+            .unwrap()
+            .read_to_end(&mut body)
+            .await
+            .unwrap();
+        assert_eq!(body.len() as u64, input.size);
+        assert_eq!(input.sample, &body[..body.len().min(SAMPLE_BYTES)]);
+        if body.windows(MARKER.len()).any(|w| w == MARKER) {
+            return Err(Rejection {
+                scanner: self.name(),
+                status: StatusCode::UNPROCESSABLE_ENTITY,
+                code: "rule_match",
+                message: "upload matched a scanning rule",
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Payload larger than the sample with the marker at the end.
+fn flagged() -> Vec<u8> {
+    let mut data = vec![b'x'; SAMPLE_BYTES + 100];
+    data.extend_from_slice(MARKER);
+    data
+}
+
+async fn send(app: &Router, mut req: Request<Body>) -> (StatusCode, Value) {
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([198, 51, 100, 60], 6900))));
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+fn multipart(name: &str, data: &[u8]) -> Request<Body> {
+    let boundary = "scanboundary";
+    let mut body = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{name}\"\r\nContent-Type: application/octet-stream\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend_from_slice(data);
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+    Request::builder()
+        .method(Method::POST)
+        .uri("/upload")
+        .header(
+            header::CONTENT_TYPE,
+            format!("multipart/form-data; boundary={boundary}"),
+        )
+        .body(Body::from(body))
+        .unwrap()
+}
+
+fn paste(data: &[u8]) -> Request<Body> {
+    Request::builder()
+        .method(Method::POST)
+        .uri("/api/paste-binary?name=notes.txt")
+        .body(Body::from(data.to_vec()))
+        .unwrap()
+}
+
+async fn chunked(app: &Router, data: &[u8]) -> (StatusCode, Value) {
+    let init = Request::builder()
+        .method(Method::POST)
+        .uri("/chunk/init")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            json!({"filename": "notes.txt", "size": data.len()}).to_string(),
+        ))
+        .unwrap();
+    let (status, session) = send(app, init).await;
+    assert_eq!(status, StatusCode::OK, "{session}");
+    let id = session["session_id"].as_str().unwrap();
+    let part = Request::builder()
+        .method(Method::PUT)
+        .uri(format!("/chunk/{id}/0"))
+        .body(Body::from(data.to_vec()))
+        .unwrap();
+    assert_eq!(send(app, part).await.0, StatusCode::NO_CONTENT);
+    let complete = Request::builder()
+        .method(Method::POST)
+        .uri(format!("/chunk/{id}/complete"))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from("{}"))
+        .unwrap();
+    send(app, complete).await
+}
+
+#[tokio::test]
+async fn test_custom_scanner_runs_on_every_upload_path() {
+    let scanner = Arc::new(MarkerScanner::default());
+    let app = AppStateBuilder::new()
+        .content_scanner(scanner.clone())
+        .build();
+    let state = app.state.clone();
+    let router = build_router(state.clone());
+    assert_eq!(
+        state.content_scanners.names(),
+        ["forbidden_extension", "forbidden_content", "marker"]
+    );
+
+    let (status, body) = send(&router, multipart("flagged.txt", &flagged())).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["code"], "rule_match");
+    let (status, body) = send(&router, paste(&flagged())).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["code"], "rule_match");
+    let (status, body) = chunked(&router, &flagged()).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["code"], "rule_match");
+    assert!(state.owners.is_empty());
+
+    let (status, _) = send(&router, multipart("clean.txt", b"fine")).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&router, paste(b"also fine")).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = chunked(&router, b"chunked and fine").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(state.owners.len(), 3);
+    assert_eq!(scanner.scanned.load(Ordering::SeqCst), 6);
+}
+
+#[tokio::test]
+async fn test_builtin_scanners_reject_before_custom_ones() {
+    let scanner = Arc::new(MarkerScanner::default());
+    let app = AppStateBuilder::new()
+        .content_scanner(scanner.clone())
+        .build();
+    let router = build_router(app.state.clone());
+
+    let (status, body) = send(&router, multipart("setup.exe", b"plain text")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["message"], "File type not allowed");
+    // A Windows executable, whatever it is called.
+    let (status, body) = send(&router, paste(b"MZ\x90\x00\x03\x00\x00\x00")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["message"], "File type not allowed (forbidden content)");
+    assert_eq!(scanner.scanned.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn test_scanner_chain_stops_at_first_rejection() {
+    let chain = ContentScanners::empty().with(Arc::new(MarkerScanner::default()));
+    let data = flagged();
+    let input = ScanInput {
+        name: None,
+        sample: &data[..SAMPLE_BYTES],
+        size: data.len() as u64,
+        content: ScanContent::Memory(&data),
+    };
+    assert_eq!(chain.scan(&input).await.unwrap_err().scanner, "marker");
+    assert!(ContentScanners::empty().scan(&input).await.is_ok());
+}
@@ -0,0 +1,196 @@
+use axum::Router;
+use axum::body::Body;
+use axum::extract::ConnectInfo;
+use axum::http::header::{
+    ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+    ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE, ALLOW,
+};
+use axum::http::{HeaderMap, Method, Request, StatusCode};
+use juicebox::config::Config;
+use juicebox::cors::{AllowedOrigins, CorsConfig};
+use juicebox::handlers::build_router;
+use juicebox::testing::{AppStateBuilder, DEFAULT_TEST_ADMIN_KEY};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tower::ServiceExt;
+
+const FRONTEND: &str = "https://frontend.example";
+
+fn router(cors: CorsConfig) -> Router {
+    let app = AppStateBuilder::new()
+        .cors(cors)
+        .with_file("a.txt", b"alpha", "203.0.113.7", 3600)
+        .build();
+    build_router(app.state.clone())
+}
+
+fn frontend_only() -> CorsConfig {
+    CorsConfig {
+        upload: AllowedOrigins::parse([FRONTEND]),
+        api: AllowedOrigins::parse([FRONTEND]),
+        max_age: Duration::from_secs(120),
+    }
+}
+
+async fn send(app: &Router, mut req: Request<Body>) -> (StatusCode, HeaderMap) {
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([198, 51, 100, 94], 6504))));
+    let resp = app.clone().oneshot(req).await.unwrap();
+    (resp.status(), resp.headers().clone())
+}
+
+fn preflight(uri: &str, origin: &str, method: &str) -> Request<Body> {
+    Request::builder()
+        .method(Method::OPTIONS)
+        .uri(uri)
+        .header("origin", origin)
+        .header("access-control-request-method", method)
+        .header(
+            "access-control-request-headers",
+            "authorization, content-type",
+        )
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_no_cors_headers_unless_configured() {
+    let app = router(CorsConfig::default());
+    let (status, headers) = send(&app, preflight("/upload", FRONTEND, "POST")).await;
+    // The OPTIONS handler still answers, just not for other origins.
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(headers[ALLOW], "POST, HEAD, OPTIONS");
+    assert!(!headers.contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+}
+
+#[tokio::test]
+async fn test_upload_preflight_reflects_the_options_handler() {
+    let app = router(frontend_only());
+    let (status, headers) = send(&app, preflight("/upload", FRONTEND, "POST")).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(headers[ACCESS_CONTROL_ALLOW_ORIGIN], FRONTEND);
+    assert_eq!(headers[ACCESS_CONTROL_ALLOW_METHODS], "POST, HEAD, OPTIONS");
+    assert_eq!(
+        headers[ACCESS_CONTROL_ALLOW_HEADERS],
+        "authorization, content-type"
+    );
+    assert_eq!(headers[ACCESS_CONTROL_MAX_AGE], "120");
+    assert!(!headers.contains_key(ACCESS_CONTROL_ALLOW_CREDENTIALS));
+
+    let (_, headers) = send(&app, preflight("/chunk/abc/0", FRONTEND, "PUT")).await;
+    assert_eq!(headers[ACCESS_CONTROL_ALLOW_METHODS], "PUT, OPTIONS");
+    let (_, headers) = send(&app, preflight("/tus/abc", FRONTEND, "PATCH")).await;
+    assert_eq!(headers[ACCESS_CONTROL_ALLOW_ORIGIN], FRONTEND);
+    assert!(headers.contains_key("tus-version"));
+
+    // Origins are compared exactly, apart from case and a trailing slash.
+    let (_, headers) = send(
+        &app,
+        preflight("/upload", "HTTPS://Frontend.Example/", "POST"),
+    )
+    .await;
+    assert_eq!(headers[ACCESS_CONTROL_ALLOW_ORIGIN], FRONTEND);
+    for origin in ["https://evil.example", "http://frontend.example"] {
+        let (_, headers) = send(&app, preflight("/upload", origin, "POST")).await;
+        assert!(
+            !headers.contains_key(ACCESS_CONTROL_ALLOW_ORIGIN),
+            "{origin}"
+        );
+    }
+    // Methods the route does not take are not offered.
+    let (_, headers) = send(&app, preflight("/upload", FRONTEND, "DELETE")).await;
+    assert!(!headers.contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+}
+
+#[tokio::test]
+async fn test_api_routes_without_options_handlers_answer_preflights() {
+    let app = router(frontend_only());
+    let (status, headers) = send(&app, preflight("/api/v1/files/lookup", FRONTEND, "POST")).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(headers[ACCESS_CONTROL_ALLOW_METHODS], "POST, OPTIONS");
+
+    let (status, headers) = send(
+        &app,
+        Request::builder()
+            .uri("/api/v1/files/a.txt/status")
+            .header("origin", FRONTEND)
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[ACCESS_CONTROL_ALLOW_ORIGIN], FRONTEND);
+    assert!(
+        headers[ACCESS_CONTROL_EXPOSE_HEADERS]
+            .to_str()
+            .unwrap()
+            .contains("x-request-id")
+    );
+    assert!(headers.get_all("vary").iter().any(|v| v == "origin"));
+}
+
+#[tokio::test]
+async fn test_admin_and_account_routes_stay_same_origin() {
+    let app = router(CorsConfig {
+        upload: AllowedOrigins::Any,
+        api: AllowedOrigins::Any,
+        ..CorsConfig::default()
+    });
+    let (_, headers) = send(&app, preflight("/upload", FRONTEND, "POST")).await;
+    assert_eq!(headers[ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+
+    for (uri, method) in [
+        ("/api/admin/v1/files/a.txt", "DELETE"),
+        ("/admin/files", "POST"),
+        ("/auth/json", "POST"),
+        ("/api/v1/accounts/login", "POST"),
+    ] {
+        let (_, headers) = send(&app, preflight(uri, FRONTEND, method)).await;
+        assert!(!headers.contains_key(ACCESS_CONTROL_ALLOW_ORIGIN), "{uri}");
+    }
+    let (status, headers) = send(
+        &app,
+        Request::builder()
+            .uri("/api/admin/v1/files")
+            .header("origin", FRONTEND)
+            .header("authorization", format!("Bearer {DEFAULT_TEST_ADMIN_KEY}"))
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!headers.contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+}
+
+#[test]
+fn test_origins_from_config_file() {
+    let mut config = Config::parse(
+        r#"
+[cors]
+upload_origins = ["https://a.example/", "ftp://nope.example"]
+api_origins = ["*"]
+max_age_secs = 60
+"#,
+    )
+    .unwrap();
+    let vars: HashMap<_, _> = config.env_vars().into_iter().collect();
+    assert_eq!(
+        vars["JUICEBOX_CORS_UPLOAD_ORIGINS"],
+        "https://a.example/,ftp://nope.example"
+    );
+    assert_eq!(vars["JUICEBOX_CORS_MAX_AGE_SECS"], "60");
+    config.overlay(|_| None);
+
+    let upload = config.cors.upload_origins.as_deref().unwrap_or_default();
+    assert_eq!(
+        AllowedOrigins::parse(upload.iter().map(String::as_str)),
+        AllowedOrigins::List(vec!["https://a.example".into()])
+    );
+    let api = config.cors.api_origins.as_deref().unwrap_or_default();
+    assert_eq!(
+        AllowedOrigins::parse(api.iter().map(String::as_str)),
+        AllowedOrigins::Any
+    );
+    assert_eq!(AllowedOrigins::parse([" "]), AllowedOrigins::None);
+}
@@ -0,0 +1,165 @@
+mod common;
+
+use axum::Router;
+use axum::body::{Body, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode, header};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use juicebox::handlers::build_router;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256, Sha512};
+use std::net::SocketAddr;
+use tower::ServiceExt;
+
+const CLIENT: [u8; 4] = [198, 51, 100, 70];
+
+async fn send(app: &Router, mut req: Request<Body>) -> (StatusCode, Value) {
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from((CLIENT, 6300))));
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+fn sha256_field(body: &[u8]) -> String {
+    format!("sha-256=:{}:", STANDARD.encode(Sha256::digest(body)))
+}
+
+fn paste(body: &'static str, header_name: &str, digest: String) -> Request<Body> {
+    Request::builder()
+        .method(Method::POST)
+        .uri("/api/paste-binary?name=digest.txt")
+        .header(header_name, digest)
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_paste_digests_are_verified_and_echoed_on_download() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+
+    let (status, body) = send(
+        &app,
+        paste("checked", "content-digest", sha256_field(b"other")),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "digest_mismatch");
+    let (status, body) = send(&app, paste("checked", "repr-digest", "sha-256=abc".into())).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "invalid_digest");
+    assert!(state.owners.is_empty());
+
+    let sha512 = format!("sha-512=:{}:", STANDARD.encode(Sha512::digest(b"checked")));
+    let (status, body) = send(
+        &app,
+        paste(
+            "checked",
+            "repr-digest",
+            format!("{sha512}, {}", sha256_field(b"checked")),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let file = body["file"].as_str().unwrap().to_string();
+
+    let mut get = Request::builder()
+        .uri(format!("/f/{file}"))
+        .body(Body::empty())
+        .unwrap();
+    get.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from((CLIENT, 6300))));
+    let resp = app.clone().oneshot(get).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let expected = sha256_field(b"checked");
+    assert_eq!(resp.headers()["repr-digest"], expected.as_str());
+    assert_eq!(resp.headers()["content-digest"], expected.as_str());
+}
+
+#[tokio::test]
+async fn test_chunk_part_digest_is_checked_before_storing() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+    let init = Request::builder()
+        .method(Method::POST)
+        .uri("/chunk/init")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            json!({"filename": "part.bin", "size": 5}).to_string(),
+        ))
+        .unwrap();
+    let (status, body) = send(&app, init).await;
+    assert_eq!(status, StatusCode::OK);
+    let session = body["session_id"].as_str().unwrap().to_string();
+
+    let part = |digest: String| {
+        Request::builder()
+            .method(Method::PUT)
+            .uri(format!("/chunk/{session}/0"))
+            .header("content-digest", digest)
+            .body(Body::from("12345"))
+            .unwrap()
+    };
+    let (status, body) = send(&app, part(sha256_field(b"54321"))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "digest_mismatch");
+    let session_state = state.chunk_sessions.get(&session).unwrap().clone();
+    assert!(!session_state.received.read().await[0]);
+
+    let (status, _) = send(&app, part(sha256_field(b"12345"))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(session_state.received.read().await[0]);
+}
+
+#[tokio::test]
+async fn test_chunk_sha256_is_checked_and_kept_with_the_session() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+    let init = Request::builder()
+        .method(Method::POST)
+        .uri("/chunk/init")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            json!({"filename": "part.bin", "size": 5}).to_string(),
+        ))
+        .unwrap();
+    let (status, body) = send(&app, init).await;
+    assert_eq!(status, StatusCode::OK);
+    let session = body["session_id"].as_str().unwrap().to_string();
+    let session_state = state.chunk_sessions.get(&session).unwrap().clone();
+    let good = format!("{:x}", Sha256::digest(b"12345"));
+
+    let part = |hash: &str, range: Option<&str>, body: &'static str| {
+        let mut req = Request::builder()
+            .method(Method::PUT)
+            .uri(format!("/chunk/{session}/0"))
+            .header("x-chunk-sha256", hash);
+        if let Some(range) = range {
+            req = req.header(header::CONTENT_RANGE, range);
+        }
+        req.body(Body::from(body)).unwrap()
+    };
+    let (status, body) = send(&app, part("not-hex", None, "12345")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "invalid_chunk_hash");
+
+    let wrong = format!("{:x}", Sha256::digest(b"54321"));
+    let (status, body) = send(&app, part(&wrong, None, "12345")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "chunk_hash_mismatch");
+    assert!(!session_state.received.read().await[0]);
+
+    // Sent in two ranges, the hash is checked once the whole chunk is in.
+    let (status, _) = send(&app, part(&good, Some("bytes 0-1/5"), "12")).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let (status, _) = send(&app, part(&good, Some("bytes 2-4/5"), "345")).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(session_state.received.read().await[0]);
+    assert_eq!(
+        session_state.chunk_hashes.read().await[0].as_deref(),
+        Some(good.as_str())
+    );
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use axum::Router;
+use axum::body::{Body, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode, header};
+use juicebox::handlers::build_router;
+use serde_json::{Value, json};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tower::ServiceExt;
+
+async fn send(app: &Router, mut req: Request<Body>) -> (StatusCode, Value) {
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([198, 51, 100, 90], 6500))));
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+fn drain(cookie: Option<&str>) -> Request<Body> {
+    let mut req = Request::builder().method(Method::POST).uri("/admin/drain");
+    if let Some(cookie) = cookie {
+        req = req.header(header::COOKIE, cookie);
+    }
+    req.body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn test_drain_refuses_new_uploads_and_waits_for_running_ones() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+
+    let (status, _) = send(&app, drain(None)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert!(!state.drain.is_draining());
+
+    // An upload holding a slot keeps the drain open.
+    let running = state.upload_sem.clone().try_acquire_owned().unwrap();
+    state.create_admin_session("drain-admin".to_string()).await;
+    let (status, body) = send(&app, drain(Some("adm=drain-admin"))).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(body["draining"], true);
+    assert_eq!(body["already_draining"], false);
+    assert_eq!(body["in_flight"], 1);
+
+    let paste = Request::builder()
+        .method(Method::POST)
+        .uri("/api/paste-binary?name=late.txt")
+        .body(Body::from("too late"))
+        .unwrap();
+    let (status, body) = send(&app, paste).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["code"], "draining");
+    let init = Request::builder()
+        .method(Method::POST)
+        .uri("/chunk/init")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            json!({"filename": "late.bin", "size": 5}).to_string(),
+        ))
+        .unwrap();
+    let (status, body) = send(&app, init).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["code"], "draining");
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(!state.drain.is_finished());
+    let (status, body) = send(&app, drain(Some("adm=drain-admin"))).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(body["already_draining"], true);
+
+    drop(running);
+    tokio::time::timeout(Duration::from_secs(5), state.drain.finished())
+        .await
+        .expect("drain finishes once the upload is done");
+}
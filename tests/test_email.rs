@@ -0,0 +1,217 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use juicebox::assets::{TEMPLATE_GLOB, load_templates};
+use juicebox::email::{EmailMessage, EmailSender, SmtpConfig, SmtpSecurity, SmtpSender};
+use juicebox::handlers::ReportRecordEmail;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+fn report(redacted: bool) -> ReportRecordEmail {
+    ReportRecordEmail {
+        file: "abc123".to_string(),
+        reason: "malware".to_string(),
+        details: "looks like <script> & friends".to_string(),
+        reporter_hash: "rep-hash".to_string(),
+        time: 1_700_000_000,
+        iso_time: "2023-11-14T22:13:20Z".to_string(),
+        owner_hash: "owner-hash".to_string(),
+        original_name: "invoice.pdf.exe".to_string(),
+        name_warning: None,
+        expires: 1_700_003_600,
+        size: 2048,
+        report_index: 0,
+        total_reports_for_file: 1,
+        total_reports: 4,
+        identifiers_redacted: redacted,
+    }
+}
+
+/// A minimal SMTP server that accepts one message and returns every line
+/// the client sent.
+async fn fake_smtp(auth: &'static str) -> (u16, tokio::task::JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let handle = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        let mut transcript = Vec::new();
+        write.write_all(b"220 fake ESMTP\r\n").await.unwrap();
+        let mut in_data = false;
+        while let Some(line) = lines.next_line().await.unwrap() {
+            transcript.push(line.clone());
+            if in_data {
+                if line == "." {
+                    in_data = false;
+                    write.write_all(b"250 queued\r\n").await.unwrap();
+                }
+                continue;
+            }
+            let reply = match line.split(' ').next().unwrap() {
+                "EHLO" => format!("250-fake\r\n250-SIZE 1000000\r\n250 {auth}\r\n"),
+                "AUTH" => "235 ok\r\n".to_string(),
+                "DATA" => {
+                    in_data = true;
+                    "354 go ahead\r\n".to_string()
+                }
+                "QUIT" => {
+                    write.write_all(b"221 bye\r\n").await.unwrap();
+                    break;
+                }
+                _ => "250 ok\r\n".to_string(),
+            };
+            write.write_all(reply.as_bytes()).await.unwrap();
+        }
+        transcript
+    });
+    (port, handle)
+}
+
+fn templates() -> tera::Tera {
+    load_templates(TEMPLATE_GLOB).unwrap()
+}
+
+fn config(port: u16, username: Option<&str>) -> SmtpConfig {
+    SmtpConfig {
+        host: "127.0.0.1".to_string(),
+        port,
+        security: SmtpSecurity::None,
+        username: username.map(str::to_string),
+        password: username.map(|_| "hunter2".to_string()),
+        timeout: Duration::from_secs(5),
+    }
+}
+
+#[test]
+fn test_report_email_escapes_details_and_hides_ban_link_when_redacted() {
+    let message = EmailMessage::report(
+        &templates(),
+        &report(false),
+        "report@example.com",
+        "admin@example.com",
+    )
+    .unwrap();
+    assert_eq!(message.subject, "[JuiceBox] Report: abc123 (malware)");
+    assert!(
+        message
+            .html
+            .contains("looks like &lt;script&gt; &amp; friends")
+    );
+    assert!(message.html.contains("/admin/ban?ip=owner-hash"));
+    assert!(message.text.contains("file=abc123"));
+
+    let redacted = EmailMessage::report(
+        &templates(),
+        &report(true),
+        "report@example.com",
+        "admin@example.com",
+    )
+    .unwrap();
+    assert!(!redacted.html.contains("/admin/ban"));
+}
+
+#[test]
+fn test_email_message_is_multipart_and_encodes_subject() {
+    let mut message = EmailMessage::report(
+        &templates(),
+        &report(false),
+        "Reports <r@example.com>",
+        "a@example.com",
+    )
+    .unwrap();
+    message.subject = "Звіт".to_string();
+    let raw = String::from_utf8(message.to_message().unwrap().formatted()).unwrap();
+    assert!(raw.contains("Subject: =?utf-8?b?"));
+    assert!(raw.contains("Content-Type: multipart/alternative;"));
+    assert!(raw.contains("Content-Type: text/html; charset=utf-8"));
+    assert!(raw.contains("@example.com>\r\n"));
+    assert!(raw.lines().all(|line| line.len() <= 998));
+}
+
+#[tokio::test]
+async fn test_smtp_sender_authenticates_and_delivers() {
+    let (port, server) = fake_smtp("AUTH LOGIN PLAIN").await;
+    let sender = SmtpSender::new(config(port, Some("mailer"))).unwrap();
+    assert_eq!(sender.backend_name(), "smtp");
+    let message = EmailMessage::report(
+        &templates(),
+        &report(false),
+        "JuiceBox <report@example.com>",
+        "admin@example.com, ops@example.com",
+    )
+    .unwrap();
+    sender.send(&message).await.unwrap();
+
+    let transcript = server.await.unwrap();
+    assert!(transcript[0].starts_with("EHLO "));
+    let token = BASE64.encode("\0mailer\0hunter2");
+    assert_eq!(transcript[1], format!("AUTH PLAIN {token}"));
+    assert_eq!(transcript[2], "MAIL FROM:<report@example.com>");
+    assert_eq!(transcript[3], "RCPT TO:<admin@example.com>");
+    assert_eq!(transcript[4], "RCPT TO:<ops@example.com>");
+    assert_eq!(transcript[5], "DATA");
+    assert!(
+        transcript
+            .iter()
+            .any(|l| l == "To: admin@example.com, ops@example.com")
+    );
+    assert_eq!(transcript.last().unwrap(), "QUIT");
+}
+
+#[tokio::test]
+async fn test_smtp_sender_without_credentials_skips_auth() {
+    let (port, server) = fake_smtp("8BITMIME").await;
+    let sender = SmtpSender::new(config(port, None)).unwrap();
+    let message = EmailMessage::report(
+        &templates(),
+        &report(false),
+        "report@example.com",
+        "admin@example.com",
+    )
+    .unwrap();
+    sender.send(&message).await.unwrap();
+    let transcript = server.await.unwrap();
+    assert!(!transcript.iter().any(|l| l.starts_with("AUTH")));
+    assert_eq!(transcript[1], "MAIL FROM:<report@example.com>");
+}
+
+#[tokio::test]
+async fn test_smtp_sender_fails_when_auth_is_not_offered() {
+    let (port, _server) = fake_smtp("8BITMIME").await;
+    let sender = SmtpSender::new(config(port, Some("mailer"))).unwrap();
+    let message = EmailMessage::report(
+        &templates(),
+        &report(false),
+        "report@example.com",
+        "admin@example.com",
+    )
+    .unwrap();
+    let err = sender.send(&message).await.unwrap_err();
+    assert!(
+        err.to_string().contains("authentication mechanism"),
+        "{err}"
+    );
+}
+
+#[test]
+fn test_report_email_text_and_custom_template() {
+    let mut tera = templates();
+    let mut ev = report(false);
+    ev.details.clear();
+    let message = EmailMessage::report(&tera, &ev, "r@example.com", "a@example.com").unwrap();
+    assert!(message.text.ends_with("details=(none)"), "{}", message.text);
+    assert!(!message.html.contains("Details"));
+
+    tera.add_raw_template(
+        "report_email.html.tera",
+        "<p>Acme moderation: {{ report.file }} {{ links.file }}</p>",
+    )
+    .unwrap();
+    let branded = EmailMessage::report(&tera, &ev, "r@example.com", "a@example.com").unwrap();
+    assert!(
+        branded
+            .html
+            .starts_with("<p>Acme moderation: abc123 https://")
+    );
+}
@@ -0,0 +1,69 @@
+use axum::body::Body;
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode, header};
+use juicebox::handlers::{EmailPrivacy, ReportRecordEmail, build_router};
+use juicebox::testing::AppStateBuilder;
+use std::net::SocketAddr;
+use tower::ServiceExt;
+
+const OWNER_IP: &str = "203.0.113.7";
+const REPORTER_IP: [u8; 4] = [192, 0, 2, 44];
+
+/// Report `hello.txt` under `privacy` and return the notification along with
+/// the full owner and reporter hashes.
+async fn report_with(privacy: EmailPrivacy) -> (ReportRecordEmail, String, String) {
+    let mut app = AppStateBuilder::new()
+        .email_privacy(privacy)
+        .with_file("hello.txt", b"hello", OWNER_IP, 3600)
+        .build();
+    let state = app.state.clone();
+    let mut req = Request::builder()
+        .method(Method::POST)
+        .uri("/report")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from("file=hello.txt&reason=spam&details=privacy"))
+        .unwrap();
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from((REPORTER_IP, 6600))));
+    let resp = build_router(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let email = app.notifications.try_recv().expect("report notification");
+    let owner = state.hash_ip_to_string(OWNER_IP).unwrap();
+    let reporter = state.hash_ip_to_string("192.0.2.44").unwrap();
+    (email, owner, reporter)
+}
+
+#[test]
+fn test_email_privacy_parse() {
+    assert_eq!(EmailPrivacy::parse(""), Some(EmailPrivacy::Full));
+    assert_eq!(
+        EmailPrivacy::parse(" Truncate "),
+        Some(EmailPrivacy::Truncate)
+    );
+    assert_eq!(EmailPrivacy::parse("HMAC"), Some(EmailPrivacy::Hmac));
+    assert_eq!(EmailPrivacy::parse("hashed"), None);
+}
+
+#[tokio::test]
+async fn test_report_email_hashes_follow_privacy_mode() {
+    let (email, owner, reporter) = report_with(EmailPrivacy::Full).await;
+    assert_eq!(email.owner_hash, owner);
+    assert_eq!(email.reporter_hash, reporter);
+    assert!(!email.identifiers_redacted);
+
+    let (email, owner, reporter) = report_with(EmailPrivacy::Truncate).await;
+    assert_eq!(email.owner_hash, owner[..8]);
+    assert_eq!(email.reporter_hash, reporter[..8]);
+    assert!(email.identifiers_redacted);
+
+    let (first, owner, reporter) = report_with(EmailPrivacy::Hmac).await;
+    assert!(first.identifiers_redacted);
+    assert_eq!(first.owner_hash.len(), 16);
+    assert!(!owner.contains(&first.owner_hash));
+    assert!(!reporter.contains(&first.reporter_hash));
+    assert_ne!(first.owner_hash, first.reporter_hash);
+    // Stable per person, so reports can still be correlated.
+    let (second, _, _) = report_with(EmailPrivacy::Hmac).await;
+    assert_eq!(first.owner_hash, second.owner_hash);
+    assert_eq!(first.reporter_hash, second.reporter_hash);
+}
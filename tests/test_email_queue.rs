@@ -0,0 +1,112 @@
+use async_trait::async_trait;
+use juicebox::email::{EmailMessage, EmailSender};
+use juicebox::email_queue::{
+    DeliveryOutcome, EMAIL_MAX_ATTEMPTS, EMAIL_RETRY_MAX_DELAY_SECS, EmailQueue, retry_delay,
+};
+use juicebox::state::{KvStore, MemoryStore};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Fails while `down` is set and counts every attempt.
+#[derive(Default)]
+struct FlakySender {
+    down: AtomicBool,
+    attempts: AtomicUsize,
+}
+
+#[async_trait]
+impl EmailSender for FlakySender {
+    fn backend_name(&self) -> &'static str {
+        "flaky"
+    }
+
+    async fn send(&self, _message: &EmailMessage) -> anyhow::Result<()> {
+        self.attempts.fetch_add(1, Ordering::SeqCst);
+        if self.down.load(Ordering::SeqCst) {
+            anyhow::bail!("mail server unavailable");
+        }
+        Ok(())
+    }
+}
+
+fn message() -> EmailMessage {
+    EmailMessage {
+        from: "report@example.com".to_string(),
+        to: "admin@example.com".to_string(),
+        subject: "[JuiceBox] Report: abc123 (malware)".to_string(),
+        text: "Report: file=abc123".to_string(),
+        html: "<p>abc123</p>".to_string(),
+    }
+}
+
+#[test]
+fn test_retry_delay_doubles_up_to_the_cap() {
+    assert_eq!(retry_delay(1), 30);
+    assert_eq!(retry_delay(2), 60);
+    assert_eq!(retry_delay(3), 120);
+    assert_eq!(retry_delay(20), EMAIL_RETRY_MAX_DELAY_SECS);
+    assert_eq!(retry_delay(u32::MAX), EMAIL_RETRY_MAX_DELAY_SECS);
+}
+
+#[tokio::test]
+async fn test_failed_email_survives_restart_and_is_retried_after_backoff() {
+    let kv: Arc<dyn KvStore> = Arc::new(MemoryStore::new("test".to_string()));
+    let sender = FlakySender::default();
+    sender.down.store(true, Ordering::SeqCst);
+
+    let mut queue = EmailQueue::load(kv.clone()).await.unwrap();
+    let id = queue.enqueue(message(), 1_000).await;
+    let outcome = queue.deliver_due(&sender, 1_000).await;
+    assert_eq!(
+        outcome,
+        DeliveryOutcome {
+            retrying: 1,
+            ..Default::default()
+        }
+    );
+    let entry = queue.get(&id).unwrap();
+    assert_eq!(entry.attempts, 1);
+    assert_eq!(entry.next_attempt, 1_030);
+    assert_eq!(entry.last_error.as_deref(), Some("mail server unavailable"));
+
+    // A restart picks the message back up from the store.
+    let mut queue = EmailQueue::load(kv.clone()).await.unwrap();
+    assert_eq!(queue.len(), 1);
+    assert_eq!(queue.next_due(), Some(1_030));
+
+    // Not due yet: no attempt is made.
+    sender.down.store(false, Ordering::SeqCst);
+    assert_eq!(
+        queue.deliver_due(&sender, 1_029).await,
+        DeliveryOutcome::default()
+    );
+    assert_eq!(sender.attempts.load(Ordering::SeqCst), 1);
+
+    let outcome = queue.deliver_due(&sender, 1_030).await;
+    assert_eq!(outcome.sent, 1);
+    assert!(queue.is_empty());
+    assert!(EmailQueue::load(kv).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_email_is_dropped_after_max_attempts() {
+    let kv: Arc<dyn KvStore> = Arc::new(MemoryStore::new("test".to_string()));
+    let sender = FlakySender::default();
+    sender.down.store(true, Ordering::SeqCst);
+    let mut queue = EmailQueue::load(kv.clone()).await.unwrap();
+    queue.enqueue(message(), 0).await;
+
+    let mut now = 0;
+    let mut dropped = 0;
+    for _ in 0..EMAIL_MAX_ATTEMPTS {
+        dropped += queue.deliver_due(&sender, now).await.dropped;
+        now = queue.next_due().unwrap_or(now);
+    }
+    assert_eq!(dropped, 1);
+    assert_eq!(
+        sender.attempts.load(Ordering::SeqCst),
+        EMAIL_MAX_ATTEMPTS as usize
+    );
+    assert!(queue.is_empty());
+    assert!(EmailQueue::load(kv).await.unwrap().is_empty());
+}
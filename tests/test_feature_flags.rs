@@ -0,0 +1,115 @@
+mod common;
+
+use axum::Router;
+use axum::body::{Body, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode, header};
+use juicebox::feature_flags::{FeatureFlags, FlagRule, FlagSource, STREAMING_UPLOADS};
+use juicebox::handlers::build_router;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+async fn send(app: &Router, mut req: Request<Body>, ip: [u8; 4]) -> (StatusCode, Value) {
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from((ip, 6200))));
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+fn admin(method: Method, uri: &str, body: Value) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::COOKIE, "adm=flags-admin")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+async fn streaming_for(app: &Router, ip: [u8; 4]) -> bool {
+    let req = Request::builder()
+        .uri("/api/config")
+        .body(Body::empty())
+        .unwrap();
+    let (status, body) = send(app, req, ip).await;
+    assert_eq!(status, StatusCode::OK);
+    body["enable_streaming_uploads"].as_bool().unwrap()
+}
+
+#[tokio::test]
+async fn test_flag_rollout_follows_owner_and_admin_overrides() {
+    let (mut state, _tmp) = common::setup_test_app();
+    let base = HashMap::from([(
+        STREAMING_UPLOADS.to_string(),
+        (
+            FlagRule {
+                enabled: true,
+                rollout: Some(50.0),
+            },
+            FlagSource::Env,
+        ),
+    )]);
+    state.flags = Arc::new(FeatureFlags::new(base));
+    state.create_admin_session("flags-admin".to_string()).await;
+    let app = build_router(state.clone());
+
+    // Half of the owners see the flag, and each owner always gets the same answer.
+    let mut enabled = 0;
+    for host in 1..=40u8 {
+        let first = streaming_for(&app, [203, 0, 113, host]).await;
+        assert_eq!(first, streaming_for(&app, [203, 0, 113, host]).await);
+        enabled += usize::from(first);
+    }
+    assert!((8..=32).contains(&enabled), "{enabled} of 40");
+
+    let (status, _) = send(
+        &app,
+        admin(
+            Method::PUT,
+            "/api/admin/v1/flags/streaming_uploads",
+            json!({"enabled": false}),
+        ),
+        [127, 0, 0, 1],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    for host in 1..=10u8 {
+        assert!(!streaming_for(&app, [203, 0, 113, host]).await);
+    }
+    let (_, listed) = send(
+        &app,
+        admin(Method::GET, "/api/admin/v1/flags", Value::Null),
+        [127, 0, 0, 1],
+    )
+    .await;
+    assert_eq!(listed[0]["name"], STREAMING_UPLOADS);
+    assert_eq!(listed[0]["source"], "admin");
+
+    // Overrides survive a restart through the key-value store.
+    let saved = state.kv.load_hash("feature_flags").await.unwrap();
+    assert_eq!(saved.len(), 1);
+    state.flags.clear_override(STREAMING_UPLOADS);
+    state.load_feature_flags().await.unwrap();
+    assert!(!state.flags.is_enabled(STREAMING_UPLOADS, Some("anyone")));
+
+    let (status, _) = send(
+        &app,
+        admin(
+            Method::DELETE,
+            "/api/admin/v1/flags/streaming_uploads",
+            Value::Null,
+        ),
+        [127, 0, 0, 1],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(
+        state.flags.rule(STREAMING_UPLOADS).unwrap().rollout,
+        Some(50.0)
+    );
+}
@@ -30,9 +30,7 @@ fn create_multipart_body(
     body.extend_from_slice(file_content.as_bytes());
     body.extend_from_slice(b"\r\n");
     body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
-    body.extend_from_slice(
-        format!("Content-Disposition: form-data; name=\"ttl\"\r\n\r\n").as_bytes(),
-    );
+    body.extend_from_slice(b"Content-Disposition: form-data; name=\"ttl\"\r\n\r\n");
     body.extend_from_slice(ttl.as_bytes());
     body.extend_from_slice(b"\r\n");
     body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
@@ -197,3 +195,47 @@ async fn test_upload_and_fetch_binary_file() {
     let response = app.clone().oneshot(fetch_req).await.unwrap();
     assert_eq!(response.status(), StatusCode::OK);
 }
+
+#[tokio::test]
+async fn test_upload_appends_transparency_log_entry() {
+    let (state, _temp_dir) = common::setup_test_app();
+    let app = build_router(state.clone());
+
+    let (content_type, body) = create_multipart_body("logged content", "logged.txt", "1h");
+    let upload_req = with_conn(
+        Request::builder()
+            .method(Method::POST)
+            .uri("/upload")
+            .header(header::CONTENT_TYPE, content_type)
+            .body(body)
+            .unwrap(),
+    );
+    let response = app.clone().oneshot(upload_req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let upload_resp: UploadResponse = serde_json::from_slice(&body).unwrap();
+    let file_name = &upload_resp.files[0];
+    let expected_hash = state.owners.get(file_name).unwrap().hash.clone();
+
+    let log_req = with_conn(
+        Request::builder()
+            .uri("/transparency.log")
+            .body(Body::empty())
+            .unwrap(),
+    );
+    let response = app.clone().oneshot(log_req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get(header::CONTENT_TYPE).unwrap(),
+        "text/plain; charset=utf-8"
+    );
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let log = String::from_utf8(body.to_vec()).unwrap();
+    assert!(!log.contains("logged.txt"));
+    let entry = juicebox::transparency::verify_chain(&log)
+        .expect("chain verifies")
+        .expect("one entry");
+    assert_eq!(entry.seq, 1);
+    assert_eq!(entry.sha256, expected_hash);
+    assert_eq!(entry.size, "logged content".len() as u64);
+}
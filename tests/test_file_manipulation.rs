@@ -7,6 +7,9 @@ use axum::{
 };
 use hyper::body::Bytes;
 use juicebox::handlers::{UploadResponse, build_router};
+use juicebox::state::FileMeta;
+use juicebox::util::now_secs;
+use serde_json::Value;
 use std::net::SocketAddr;
 use tower::ServiceExt;
 
@@ -117,7 +120,7 @@ async fn test_list_after_delete() {
     let del_req = with_conn_ip(
         Request::builder()
             .method(Method::DELETE)
-            .uri(&format!("/f/{}", fname))
+            .uri(format!("/f/{}", fname))
             .body(Body::empty())
             .unwrap(),
         [127, 0, 0, 2],
@@ -194,7 +197,7 @@ async fn test_delete_already_deleted_file() {
     let del_req = with_conn_ip(
         Request::builder()
             .method(Method::DELETE)
-            .uri(&format!("/f/{}", fname))
+            .uri(format!("/f/{}", fname))
             .body(Body::empty())
             .unwrap(),
         [127, 0, 0, 4],
@@ -205,7 +208,7 @@ async fn test_delete_already_deleted_file() {
     let del_req2 = with_conn_ip(
         Request::builder()
             .method(Method::DELETE)
-            .uri(&format!("/f/{}", fname))
+            .uri(format!("/f/{}", fname))
             .body(Body::empty())
             .unwrap(),
         [127, 0, 0, 4],
@@ -352,3 +355,287 @@ async fn test_simple_list_and_delete_flow() {
     let fetch_resp = app.clone().oneshot(fetch_req).await.unwrap();
     assert_eq!(fetch_resp.status(), StatusCode::NOT_FOUND);
 }
+
+#[tokio::test]
+async fn test_list_paginates_with_sort_and_cursor() {
+    let (state, _tmp) = common::setup_test_app();
+    let owner_hash = common::hash_fixture_ip("127.0.0.1");
+    let now = now_secs();
+    for i in 0..5u64 {
+        let name = format!("file{i}.txt");
+        state
+            .file_store
+            .write(&name, Bytes::from(format!("body {i}")))
+            .await
+            .unwrap();
+        state.insert_owner(
+            name.clone(),
+            FileMeta {
+                owner_hash: owner_hash.clone(),
+                // Expiry runs opposite to the storage name order.
+                expires: now + 3600 * (10 - i),
+                original: name,
+                original_display: String::new(),
+                created: now - i,
+                hash: format!("hash{i}"),
+                max_downloads: None,
+                downloads: 0,
+                private: false,
+                ttl_shortened_from: None,
+                guest: false,
+                network_class: None,
+                size: 0,
+            },
+        );
+    }
+    let app = build_router(state.clone());
+    let list = |uri: String| {
+        let app = app.clone();
+        async move {
+            let req = with_conn_ip(
+                Request::builder().uri(uri).body(Body::empty()).unwrap(),
+                [127, 0, 0, 1],
+                1111,
+            );
+            let resp = app.oneshot(req).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+            let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+            serde_json::from_slice::<Value>(&body).unwrap()
+        }
+    };
+    let names = |page: &Value| -> Vec<String> {
+        page["metas"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["original"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    let all = list("/list".into()).await;
+    assert_eq!(all["total"], 5);
+    assert!(all.get("next_cursor").is_none());
+    assert_eq!(names(&all)[0], "file0.txt");
+
+    let first = list("/list?limit=2&sort=expires".into()).await;
+    assert_eq!(names(&first), ["file4.txt", "file3.txt"]);
+    assert_eq!(first["next_offset"], 2);
+    let cursor = first["next_cursor"].as_str().unwrap().to_string();
+
+    // A file expiring before the cursor appears between pages and is skipped.
+    state
+        .file_store
+        .write("early.txt", Bytes::from("x"))
+        .await
+        .unwrap();
+    state.insert_owner(
+        "early.txt".into(),
+        FileMeta {
+            owner_hash: owner_hash.clone(),
+            expires: now + 60,
+            original: "early.txt".into(),
+            original_display: String::new(),
+            created: now,
+            hash: "hash-early".into(),
+            max_downloads: None,
+            downloads: 0,
+            private: false,
+            ttl_shortened_from: None,
+            guest: false,
+            network_class: None,
+            size: 0,
+        },
+    );
+    let second = list(format!("/list?limit=2&sort=expires&cursor={cursor}")).await;
+    assert_eq!(names(&second), ["file2.txt", "file1.txt"]);
+    assert_eq!(second["total"], 6);
+
+    let by_offset = list("/list?limit=2&offset=4&sort=created&order=desc".into()).await;
+    assert_eq!(names(&by_offset), ["file3.txt", "file4.txt"]);
+    assert!(by_offset.get("next_offset").is_none());
+}
+
+fn seed_owned_file(state: &juicebox::state::AppState, name: &str, owner_hash: &str) {
+    let now = now_secs();
+    state.insert_owner(
+        name.to_string(),
+        FileMeta {
+            owner_hash: owner_hash.to_string(),
+            expires: now + 3600,
+            original: name.to_string(),
+            original_display: String::new(),
+            created: now,
+            hash: format!("hash-{name}"),
+            max_downloads: None,
+            downloads: 0,
+            private: false,
+            ttl_shortened_from: None,
+            guest: false,
+            network_class: None,
+            size: 0,
+        },
+    );
+}
+
+async fn bulk_delete(app: &axum::Router, body: &str) -> (StatusCode, Value) {
+    let req = with_conn_ip(
+        Request::builder()
+            .method(Method::POST)
+            .uri("/api/v1/files/delete")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap(),
+        [127, 0, 0, 1],
+        1111,
+    );
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn test_bulk_delete_reports_per_file_results() {
+    let (state, _tmp) = common::setup_test_app();
+    let mine = common::hash_fixture_ip("127.0.0.1");
+    let theirs = common::hash_fixture_ip("10.0.0.9");
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        state
+            .file_store
+            .write(name, Bytes::from("x"))
+            .await
+            .unwrap();
+        seed_owned_file(&state, name, &mine);
+    }
+    state
+        .file_store
+        .write("other.txt", Bytes::from("x"))
+        .await
+        .unwrap();
+    seed_owned_file(&state, "other.txt", &theirs);
+    let app = build_router(state.clone());
+
+    let (status, _) = bulk_delete(&app, "{}").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, body) = bulk_delete(
+        &app,
+        r#"{"files":["a.txt","https://example.com/f/b.txt","other.txt","missing.txt","../x","a.txt"]}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["deleted"], 2);
+    let statuses: Vec<(&str, &str)> = body["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| (r["file"].as_str().unwrap(), r["status"].as_str().unwrap()))
+        .collect();
+    assert_eq!(
+        statuses,
+        [
+            ("a.txt", "deleted"),
+            ("b.txt", "deleted"),
+            ("other.txt", "not_found"),
+            ("missing.txt", "not_found"),
+            ("../x", "invalid"),
+        ]
+    );
+    assert!(!state.file_store.exists("a.txt").await);
+    assert!(state.file_store.exists("other.txt").await);
+    assert!(state.owners.contains_key("other.txt"));
+
+    let (status, body) = bulk_delete(&app, r#"{"all":true}"#).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["deleted"], 1);
+    assert!(!state.owners.contains_key("c.txt"));
+    assert!(state.owners.contains_key("other.txt"));
+}
+
+#[tokio::test]
+async fn test_simple_delete_all_removes_only_callers_files() {
+    let (state, _tmp) = common::setup_test_app();
+    let mine = common::hash_fixture_ip("127.0.0.1");
+    let theirs = common::hash_fixture_ip("10.0.0.9");
+    seed_owned_file(&state, "one.txt", &mine);
+    seed_owned_file(&state, "two.txt", &mine);
+    seed_owned_file(&state, "keep.txt", &theirs);
+    let app = build_router(state.clone());
+    let req = with_conn_ip(
+        Request::builder()
+            .method(Method::POST)
+            .uri("/simple/delete")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from("all=1"))
+            .unwrap(),
+        [127, 0, 0, 1],
+        1111,
+    );
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+    let location = resp.headers()[header::LOCATION].to_str().unwrap();
+    assert!(location.contains("Deleted%202%20file"), "{location}");
+    assert_eq!(state.owners.len(), 1);
+    assert!(state.owners.contains_key("keep.txt"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_simple_events_stream_expiry_and_deletion() {
+    use http_body_util::BodyExt;
+
+    let (state, _tmp) = common::setup_test_app();
+    let owner_hash = common::hash_fixture_ip("127.0.0.1");
+    let now = now_secs();
+    for name in ["keep.txt", "gone.txt"] {
+        state.insert_owner(
+            name.to_string(),
+            FileMeta {
+                owner_hash: owner_hash.clone(),
+                expires: now + 7200,
+                original: name.to_string(),
+                original_display: String::new(),
+                created: now,
+                hash: format!("hash-{name}"),
+                max_downloads: None,
+                downloads: 0,
+                private: false,
+                ttl_shortened_from: None,
+                guest: false,
+                network_class: None,
+                size: 0,
+            },
+        );
+    }
+    let app = build_router(state.clone());
+    let req = with_conn_ip(
+        Request::builder()
+            .uri("/simple/events")
+            .body(Body::empty())
+            .unwrap(),
+        [127, 0, 0, 1],
+        2222,
+    );
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get(header::CONTENT_TYPE).unwrap(),
+        "text/event-stream"
+    );
+    let mut body = resp.into_body();
+    let mut next_frame = async || {
+        let frame = body.frame().await.unwrap().unwrap();
+        String::from_utf8(frame.into_data().unwrap().to_vec()).unwrap()
+    };
+
+    let first = next_frame().await;
+    assert!(first.starts_with("event: expiry\n"), "{first}");
+    assert!(first.contains("\"file\":\"keep.txt\""));
+    assert!(first.contains("\"remaining\":\"2h\""));
+
+    state.remove_owner("gone.txt");
+    let deleted = next_frame().await;
+    assert!(deleted.starts_with("event: deleted\n"), "{deleted}");
+    assert!(deleted.contains("gone.txt"));
+    let tick = next_frame().await;
+    assert!(tick.contains("keep.txt") && !tick.contains("gone.txt"));
+}
@@ -0,0 +1,261 @@
+use axum::Router;
+use axum::body::{Bytes, to_bytes};
+use axum::extract::{Request, State};
+use axum::http::{Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use juicebox::file_store::{FileStore, LocalFileStore, NameTaken, S3Config, S3FileStore};
+use juicebox::state::{FileMeta, NewFileBody};
+use juicebox::testing::AppStateBuilder;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[tokio::test]
+async fn local_store_round_trips_files() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().join("files");
+    std::fs::create_dir_all(&root).unwrap();
+    let store = LocalFileStore::new(&root);
+
+    assert_eq!(store.read("a.txt").await.unwrap(), None);
+    assert_eq!(store.size("a.txt").await.unwrap(), None);
+    store
+        .write("a.txt", Bytes::from_static(b"hello"))
+        .await
+        .unwrap();
+    assert_eq!(store.size("a.txt").await.unwrap(), Some(5));
+    assert_eq!(
+        store.read_range("a.txt", 1, 3).await.unwrap().as_deref(),
+        Some(&b"ell"[..])
+    );
+    assert_eq!(store.read_range("a.txt", 4, 2).await.unwrap(), None);
+    assert_eq!(store.read_range("missing.txt", 0, 1).await.unwrap(), None);
+    assert!(store.exists("a.txt").await);
+
+    let outside = tmp.path().join("held.txt");
+    store.export("a.txt", &outside).await.unwrap();
+    assert!(!store.exists("a.txt").await);
+    assert_eq!(std::fs::read(&outside).unwrap(), b"hello");
+
+    store.import("b.txt", &outside).await.unwrap();
+    assert!(!outside.exists());
+    assert_eq!(
+        store.read("b.txt").await.unwrap().as_deref(),
+        Some(&b"hello"[..])
+    );
+
+    store.delete("b.txt").await.unwrap();
+    store.delete("b.txt").await.unwrap();
+    assert!(!root.join("b.txt").exists());
+}
+
+#[tokio::test]
+async fn local_store_never_replaces_a_stored_file() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().join("files");
+    std::fs::create_dir_all(&root).unwrap();
+    let store = LocalFileStore::new(&root);
+    store
+        .write("taken.txt", Bytes::from_static(b"first"))
+        .await
+        .unwrap();
+
+    let err = store
+        .write("taken.txt", Bytes::from_static(b"second"))
+        .await
+        .unwrap_err();
+    assert!(err.is::<NameTaken>());
+
+    let incoming = tmp.path().join("incoming.txt");
+    std::fs::write(&incoming, b"third").unwrap();
+    let err = store.import("taken.txt", &incoming).await.unwrap_err();
+    assert!(err.is::<NameTaken>());
+    assert!(incoming.exists(), "source is kept when the name is taken");
+    assert_eq!(std::fs::read(root.join("taken.txt")).unwrap(), b"first");
+}
+
+#[tokio::test]
+async fn store_new_file_picks_another_name_on_collision() {
+    let app = AppStateBuilder::new().build();
+    let state = &app.state;
+    let upload_dir = state.upload_dir.as_ref().clone();
+
+    // A body on disk with no metadata, e.g. left by an interrupted import.
+    std::fs::write(upload_dir.join("stray.txt"), b"someone else").unwrap();
+    let name = state
+        .store_new_file(
+            "stray.txt".into(),
+            NewFileBody::Bytes(Bytes::from_static(b"mine")),
+        )
+        .await
+        .unwrap();
+    assert_ne!(name, "stray.txt");
+    assert!(name.ends_with(".txt"));
+    assert_eq!(
+        std::fs::read(upload_dir.join("stray.txt")).unwrap(),
+        b"someone else"
+    );
+    assert_eq!(std::fs::read(upload_dir.join(&name)).unwrap(), b"mine");
+
+    // Metadata without a body, e.g. a crafted name from a legacy import.
+    let legacy: FileMeta = serde_json::from_value(serde_json::json!({
+        "owner_hash": "legacy-owner",
+        "expires": 4_000_000_000u64,
+        "hash": "ab",
+    }))
+    .unwrap();
+    state.insert_owner("legacy.bin".into(), legacy);
+    let spooled = app.root.join("spooled.bin");
+    std::fs::write(&spooled, b"payload").unwrap();
+    let name = state
+        .store_new_file("legacy.bin".into(), NewFileBody::Spooled(&spooled))
+        .await
+        .unwrap();
+    assert_ne!(name, "legacy.bin");
+    assert!(!upload_dir.join("legacy.bin").exists());
+    assert!(!state.storage_name_reserved("unused.bin"));
+    assert!(state.storage_name_reserved("legacy.bin"));
+    assert_ne!(
+        state.unreserved_storage_name("legacy.bin".into()).unwrap(),
+        "legacy.bin"
+    );
+}
+
+#[derive(Clone, Default)]
+struct MockS3 {
+    objects: Arc<Mutex<HashMap<String, Bytes>>>,
+    authorizations: Arc<Mutex<Vec<String>>>,
+}
+
+async fn mock_s3(State(mock): State<MockS3>, req: Request) -> Response {
+    let auth = req
+        .headers()
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    if req.headers().get("x-amz-date").is_none()
+        || req.headers().get("x-amz-content-sha256").is_none()
+    {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    mock.authorizations.lock().unwrap().push(auth);
+    let key = req.uri().path().to_string();
+    let method = req.method().clone();
+    let conditional = req.headers().get("if-none-match").is_some_and(|v| v == "*");
+    let range = req
+        .headers()
+        .get("range")
+        .and_then(|v| v.to_str().ok()?.strip_prefix("bytes=")?.split_once('-'))
+        .and_then(|(first, last)| {
+            Some((first.parse::<usize>().ok()?, last.parse::<usize>().ok()?))
+        });
+    let body = to_bytes(req.into_body(), usize::MAX).await.unwrap();
+    let mut objects = mock.objects.lock().unwrap();
+    match method {
+        Method::PUT => {
+            if conditional && objects.contains_key(&key) {
+                return StatusCode::PRECONDITION_FAILED.into_response();
+            }
+            objects.insert(key, body);
+            StatusCode::OK.into_response()
+        }
+        Method::GET | Method::HEAD => match objects.get(&key) {
+            Some(data) if method == Method::GET => match range {
+                Some((first, _)) if first >= data.len() => {
+                    StatusCode::RANGE_NOT_SATISFIABLE.into_response()
+                }
+                Some((first, last)) => (
+                    StatusCode::PARTIAL_CONTENT,
+                    data.slice(first..(last + 1).min(data.len())),
+                )
+                    .into_response(),
+                None => data.clone().into_response(),
+            },
+            Some(data) => ([("content-length", data.len().to_string())]).into_response(),
+            None => StatusCode::NOT_FOUND.into_response(),
+        },
+        Method::DELETE => {
+            objects.remove(&key);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        _ => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+    }
+}
+
+#[tokio::test]
+async fn s3_store_signs_requests_and_round_trips_files() {
+    let mock = MockS3::default();
+    let app = Router::new().fallback(mock_s3).with_state(mock.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let store = S3FileStore::new(S3Config {
+        bucket: "juicebox".into(),
+        region: "us-east-1".into(),
+        endpoint: Some(format!("http://{addr}")),
+        access_key_id: "AKIDEXAMPLE".into(),
+        secret_access_key: "secret".into(),
+        prefix: "uploads/".into(),
+        path_style: true,
+    })
+    .unwrap();
+
+    store
+        .write("my file.txt", Bytes::from_static(b"body"))
+        .await
+        .unwrap();
+    assert!(
+        mock.objects
+            .lock()
+            .unwrap()
+            .contains_key("/juicebox/uploads/my%20file.txt")
+    );
+    assert_eq!(store.size("my file.txt").await.unwrap(), Some(4));
+    assert_eq!(
+        store
+            .read_range("my file.txt", 1, 2)
+            .await
+            .unwrap()
+            .as_deref(),
+        Some(&b"od"[..])
+    );
+    assert_eq!(store.read_range("my file.txt", 3, 2).await.unwrap(), None);
+    assert_eq!(store.read_range("my file.txt", 9, 1).await.unwrap(), None);
+    assert_eq!(store.read_range("missing.txt", 0, 1).await.unwrap(), None);
+    assert_eq!(
+        store.read("my file.txt").await.unwrap().as_deref(),
+        Some(&b"body"[..])
+    );
+
+    let tmp = tempfile::tempdir().unwrap();
+    let local = tmp.path().join("big.bin");
+    std::fs::write(&local, vec![7u8; 4096]).unwrap();
+    store.import("big.bin", &local).await.unwrap();
+    assert!(!local.exists());
+    assert_eq!(store.size("big.bin").await.unwrap(), Some(4096));
+
+    store.export("big.bin", &local).await.unwrap();
+    assert_eq!(std::fs::read(&local).unwrap().len(), 4096);
+    assert_eq!(store.read("big.bin").await.unwrap(), None);
+
+    let err = store
+        .write("my file.txt", Bytes::from_static(b"again"))
+        .await
+        .unwrap_err();
+    assert!(err.is::<NameTaken>());
+    assert_eq!(
+        store.read("my file.txt").await.unwrap().as_deref(),
+        Some(&b"body"[..])
+    );
+
+    store.delete("my file.txt").await.unwrap();
+    assert!(!store.exists("my file.txt").await);
+
+    let auths = mock.authorizations.lock().unwrap();
+    assert!(auths.iter().all(|a| {
+        a.starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/")
+            && a.contains("/us-east-1/s3/aws4_request")
+            && a.contains("SignedHeaders=host;x-amz-content-sha256;x-amz-date")
+    }));
+}
@@ -5,15 +5,21 @@ use axum::{
     body::{Body, to_bytes},
     http::{Method, Request, StatusCode, header},
 };
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
 use hyper::body::Bytes;
 use juicebox::handlers::{
-    ChunkCompleteRequest, ChunkInitRequest, ChunkInitResponse, UploadResponse, build_router,
+    ChunkCompleteRequest, ChunkInitRequest, ChunkInitResponse, ChunkSessionsResponse,
+    ChunkStatusResponse, PASTE_MAX_BYTES, PasteResponse, UploadResponse, build_router,
+};
+use juicebox::state::{
+    BanSubject, ChunkLifecycle, ChunkPhase, ChunkSession, IpBan, assembly_temp_path,
 };
-use juicebox::state::{BanSubject, IpBan};
 use serde_json::Value;
 use sha2::{Digest, Sha256};
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicU64};
 use tokio::sync::Semaphore;
 use tower::ServiceExt;
 
@@ -46,6 +52,14 @@ fn with_conn_ip(mut req: Request<Body>, ip: [u8; 4], port: u16) -> Request<Body>
     req
 }
 
+async fn error_code(resp: axum::response::Response) -> String {
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice::<Value>(&body).unwrap()["code"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string()
+}
+
 #[tokio::test]
 async fn test_upload_file() {
     let (state, _tmp) = common::setup_test_app();
@@ -138,6 +152,7 @@ async fn test_upload_rejects_banned_ip() {
             label: None,
             reason: "policy".to_string(),
             time: 0,
+            expires: None,
         })
         .await;
     let app = build_router(state.clone());
@@ -233,6 +248,199 @@ async fn test_upload_large_file() {
     assert!(resp.status() == StatusCode::OK || resp.status() == StatusCode::BAD_REQUEST);
 }
 
+#[tokio::test]
+async fn test_upload_streams_field_to_store() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+    // Spans many multipart chunks; the router's default body limit is 2 MiB.
+    let content: String = (0..1_500_000u32)
+        .map(|i| char::from(b'a' + (i % 26) as u8))
+        .collect();
+    let (ct, body) = create_multipart_body(&content, "streamed.txt", "1h");
+    let upload = with_conn_ip(
+        Request::builder()
+            .method(Method::POST)
+            .uri("/upload")
+            .header(header::CONTENT_TYPE, ct)
+            .body(body)
+            .unwrap(),
+        [127, 0, 0, 1],
+        4545,
+    );
+    let resp = app.clone().oneshot(upload).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body_bytes = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let up: UploadResponse = serde_json::from_slice(&body_bytes).unwrap();
+    let stored_name = up.files[0].trim_start_matches("f/");
+
+    let stored = state.file_store.read(stored_name).await.unwrap().unwrap();
+    assert_eq!(stored.as_ref(), content.as_bytes());
+    let meta = state.owners.get(stored_name).unwrap().clone();
+    assert_eq!(
+        meta.hash,
+        format!("{:x}", Sha256::digest(content.as_bytes()))
+    );
+    let leftovers: Vec<_> = std::fs::read_dir(&*state.upload_dir)
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "part"))
+        .collect();
+    assert!(
+        leftovers.is_empty(),
+        "temp files left behind: {leftovers:?}"
+    );
+}
+
+#[tokio::test]
+async fn test_paste_binary_fast_path() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+    let paste = |uri: &str, content_type: &str, body: Vec<u8>| {
+        with_conn_ip(
+            Request::builder()
+                .method(Method::POST)
+                .uri(uri)
+                .header(header::CONTENT_TYPE, content_type)
+                .body(Body::from(body))
+                .unwrap(),
+            [127, 0, 0, 1],
+            4646,
+        )
+    };
+    // The upload semaphore being exhausted must not block small pastes.
+    let _permits = state
+        .upload_sem
+        .clone()
+        .try_acquire_many_owned(state.upload_sem.available_permits() as u32)
+        .unwrap();
+
+    let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+    png.extend_from_slice(&[0u8; 64]);
+    let resp = app
+        .clone()
+        .oneshot(paste("/api/paste-binary?ttl=1h", "image/png", png.clone()))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let pasted: PasteResponse = serde_json::from_slice(&body).unwrap();
+    assert!(pasted.file.ends_with(".png"), "{}", pasted.file);
+    assert!(pasted.url.ends_with(&pasted.file));
+    let stored = state.file_store.read(&pasted.file).await.unwrap().unwrap();
+    assert_eq!(stored.as_ref(), png.as_slice());
+    let meta = state.owners.get(&pasted.file).unwrap().clone();
+    assert_eq!(meta.original, "paste.png");
+    assert!(meta.expires <= juicebox::util::now_secs() + 3600);
+
+    let named = app
+        .clone()
+        .oneshot(paste(
+            "/api/paste-binary?name=notes.txt",
+            "text/plain",
+            b"clipboard text".to_vec(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(named.status(), StatusCode::OK);
+
+    let oversized = app
+        .clone()
+        .oneshot(paste(
+            "/api/paste-binary",
+            "application/octet-stream",
+            vec![7u8; PASTE_MAX_BYTES + 1],
+        ))
+        .await
+        .unwrap();
+    assert_eq!(oversized.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let forbidden = app
+        .clone()
+        .oneshot(paste(
+            "/api/paste-binary?name=run.exe",
+            "application/octet-stream",
+            b"MZ".to_vec(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(forbidden.status(), StatusCode::BAD_REQUEST);
+
+    let empty = app
+        .oneshot(paste("/api/paste-binary", "image/png", Vec::new()))
+        .await
+        .unwrap();
+    assert_eq!(empty.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_max_downloads_deletes_file_after_last_download() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+    let paste = |uri: &str| {
+        with_conn_ip(
+            Request::builder()
+                .method(Method::POST)
+                .uri(uri)
+                .header(header::CONTENT_TYPE, "text/plain")
+                .body(Body::from("correct horse battery staple"))
+                .unwrap(),
+            [127, 0, 0, 1],
+            4747,
+        )
+    };
+    let rejected = app
+        .clone()
+        .oneshot(paste("/api/paste-binary?name=secret.txt&max_downloads=0"))
+        .await
+        .unwrap();
+    assert_eq!(rejected.status(), StatusCode::BAD_REQUEST);
+
+    let resp = app
+        .clone()
+        .oneshot(paste("/api/paste-binary?name=secret.txt&max_downloads=2"))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let pasted: PasteResponse = serde_json::from_slice(&body).unwrap();
+    let uri = format!("/f/{}", pasted.file);
+    let fetch = |method: Method| {
+        app.clone().oneshot(
+            Request::builder()
+                .method(method)
+                .uri(&uri)
+                .body(Body::empty())
+                .unwrap(),
+        )
+    };
+
+    // HEAD requests (link previews, curl -I) do not use up a download.
+    let head = fetch(Method::HEAD).await.unwrap();
+    assert_eq!(head.status(), StatusCode::OK);
+    assert_eq!(head.headers()[header::CACHE_CONTROL], "no-store");
+    assert_eq!(
+        state.owners.get(&pasted.file).unwrap().max_downloads,
+        Some(2)
+    );
+
+    let first = fetch(Method::GET).await.unwrap();
+    assert_eq!(first.status(), StatusCode::OK);
+    assert_eq!(
+        state.owners.get(&pasted.file).unwrap().max_downloads,
+        Some(1)
+    );
+
+    let last = fetch(Method::GET).await.unwrap();
+    assert_eq!(last.status(), StatusCode::OK);
+    let body = to_bytes(last.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(body.as_ref(), b"correct horse battery staple");
+    assert!(state.owners.get(&pasted.file).is_none());
+    assert!(state.file_store.read(&pasted.file).await.unwrap().is_none());
+
+    let gone = fetch(Method::GET).await.unwrap();
+    assert_eq!(gone.status(), StatusCode::NOT_FOUND);
+}
+
 #[tokio::test]
 async fn test_chunk_upload_flow() {
     let (state, _tmp) = common::setup_test_app();
@@ -249,6 +457,8 @@ async fn test_chunk_upload_flow() {
         ttl: Some("1h".to_string()),
         chunk_size: Some(70_000),
         hash: Some(hash.clone()),
+        max_downloads: None,
+        private: false,
     };
     let init = with_conn_ip(
         Request::builder()
@@ -309,6 +519,352 @@ async fn test_chunk_upload_flow() {
     assert_eq!(meta.hash, hash);
 }
 
+#[tokio::test]
+async fn test_chunks_sent_out_of_order_assemble_in_place() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+
+    // More chunks than are copied at once, each with its own bytes, and a
+    // short last chunk.
+    let chunk_size = 64 * 1024;
+    let data: Vec<u8> = (0..chunk_size * 9 + 1234)
+        .map(|i| (i / chunk_size * 31 + i % 251) as u8)
+        .collect();
+    let hash = format!("{:x}", Sha256::digest(&data));
+    let init_req = ChunkInitRequest {
+        filename: "shuffled.bin".to_string(),
+        size: data.len() as u64,
+        ttl: Some("1h".to_string()),
+        chunk_size: Some(chunk_size as u64),
+        hash: Some(hash.clone()),
+        max_downloads: None,
+        private: false,
+    };
+    let init = with_conn_ip(
+        Request::builder()
+            .method(Method::POST)
+            .uri("/chunk/init")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_vec(&init_req).unwrap()))
+            .unwrap(),
+        [127, 0, 0, 1],
+        5000,
+    );
+    let init_resp = app.clone().oneshot(init).await.unwrap();
+    assert_eq!(init_resp.status(), StatusCode::OK);
+    let init_bytes = to_bytes(init_resp.into_body(), usize::MAX).await.unwrap();
+    let session: ChunkInitResponse = serde_json::from_slice(&init_bytes).unwrap();
+    assert_eq!(session.total_chunks, 10);
+
+    for idx in (0..session.total_chunks).rev() {
+        let start = idx as usize * chunk_size;
+        let end = std::cmp::min(start + chunk_size, data.len());
+        let part = with_conn_ip(
+            Request::builder()
+                .method(Method::PUT)
+                .uri(format!("/chunk/{}/{idx}", session.session_id))
+                .body(Body::from(Bytes::copy_from_slice(&data[start..end])))
+                .unwrap(),
+            [127, 0, 0, 1],
+            5000,
+        );
+        let resp = app.clone().oneshot(part).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    }
+
+    let complete = with_conn_ip(
+        Request::builder()
+            .method(Method::POST)
+            .uri(format!("/chunk/{}/complete", session.session_id))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from("{}"))
+            .unwrap(),
+        [127, 0, 0, 1],
+        5000,
+    );
+    let complete_resp = app.clone().oneshot(complete).await.unwrap();
+    assert_eq!(complete_resp.status(), StatusCode::OK);
+    let body = to_bytes(complete_resp.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let uploaded: UploadResponse = serde_json::from_slice(&body).unwrap();
+    let stored = state
+        .file_store
+        .read(&uploaded.files[0])
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.as_ref(), data.as_slice());
+    assert_eq!(state.owners.get(&uploaded.files[0]).unwrap().hash, hash);
+}
+
+#[tokio::test]
+async fn test_chunk_part_resumes_with_content_range() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+
+    let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+    let hash = format!("{:x}", Sha256::digest(&data));
+    let init_req = ChunkInitRequest {
+        filename: "resumed.bin".to_string(),
+        size: data.len() as u64,
+        ttl: Some("1h".to_string()),
+        chunk_size: Some(70_000),
+        hash: Some(hash.clone()),
+        max_downloads: None,
+        private: false,
+    };
+    let init = with_conn_ip(
+        Request::builder()
+            .method(Method::POST)
+            .uri("/chunk/init")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_vec(&init_req).unwrap()))
+            .unwrap(),
+        [127, 0, 0, 1],
+        5000,
+    );
+    let init_resp = app.clone().oneshot(init).await.unwrap();
+    let init_bytes = to_bytes(init_resp.into_body(), usize::MAX).await.unwrap();
+    let session: ChunkInitResponse = serde_json::from_slice(&init_bytes).unwrap();
+    assert_eq!(session.total_chunks, 2);
+    let chunk = &data[..70_000];
+    let sha256 = |bytes: &[u8]| format!("sha-256=:{}:", STANDARD.encode(Sha256::digest(bytes)));
+    let put = |range: Option<String>, repr: Option<String>, body: Body| {
+        let mut req = Request::builder()
+            .method(Method::PUT)
+            .uri(format!("/chunk/{}/0", session.session_id));
+        if let Some(range) = range {
+            req = req.header(header::CONTENT_RANGE, range);
+        }
+        if let Some(repr) = repr {
+            req = req.header("repr-digest", repr);
+        }
+        with_conn_ip(req.body(body).unwrap(), [127, 0, 0, 1], 5000)
+    };
+    // The connection drops after 30000 bytes.
+    let interrupted = || {
+        Body::from_stream(futures_util::stream::iter(vec![
+            Ok(Bytes::copy_from_slice(&chunk[..30_000])),
+            Err(std::io::Error::new(
+                std::io::ErrorKind::ConnectionReset,
+                "reset",
+            )),
+        ]))
+    };
+    let received = |state: &juicebox::state::AppState| {
+        let session = state
+            .chunk_sessions
+            .get(&session.session_id)
+            .unwrap()
+            .clone();
+        session.received.try_read().unwrap()[0]
+    };
+
+    let resp = app
+        .clone()
+        .oneshot(put(None, None, interrupted()))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(resp.headers()[header::RANGE], "bytes=0-29999");
+    assert_eq!(error_code(resp).await, "chunk_incomplete");
+    assert!(!received(&state));
+
+    // A range that leaves a gap is refused with what is stored so far.
+    let resp = app
+        .clone()
+        .oneshot(put(
+            Some("bytes 40000-69999/70000".into()),
+            None,
+            Body::from(chunk[40_000..].to_vec()),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(resp.headers()[header::RANGE], "bytes=0-29999");
+
+    // The final digest covers the whole chunk; a mismatch discards it.
+    let resp = app
+        .clone()
+        .oneshot(put(
+            Some("bytes 30000-69999/70000".into()),
+            Some(sha256(b"something else")),
+            Body::from(chunk[30_000..].to_vec()),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(error_code(resp).await, "digest_mismatch");
+    assert!(!received(&state));
+
+    let resp = app
+        .clone()
+        .oneshot(put(None, None, interrupted()))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let resp = app
+        .clone()
+        .oneshot(put(
+            Some("bytes 30000-69999/70000".into()),
+            Some(sha256(chunk)),
+            Body::from(chunk[30_000..].to_vec()),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    assert!(received(&state));
+
+    let part = with_conn_ip(
+        Request::builder()
+            .method(Method::PUT)
+            .uri(format!("/chunk/{}/1", session.session_id))
+            .body(Body::from(data[70_000..].to_vec()))
+            .unwrap(),
+        [127, 0, 0, 1],
+        5000,
+    );
+    let resp = app.clone().oneshot(part).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let complete = with_conn_ip(
+        Request::builder()
+            .method(Method::POST)
+            .uri(format!("/chunk/{}/complete", session.session_id))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                serde_json::to_vec(&ChunkCompleteRequest {
+                    hash: Some(hash.clone()),
+                })
+                .unwrap(),
+            ))
+            .unwrap(),
+        [127, 0, 0, 1],
+        5000,
+    );
+    let resp = app.clone().oneshot(complete).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_chunk_completion_enforces_declared_size() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+    let complete = |id: &str| {
+        with_conn_ip(
+            Request::builder()
+                .method(Method::POST)
+                .uri(format!("/chunk/{id}/complete"))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from("{}"))
+                .unwrap(),
+            [127, 0, 0, 1],
+            5000,
+        )
+    };
+    let error_code = |resp: axum::response::Response| async move {
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice::<Value>(&body).unwrap()["code"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    };
+
+    // A chunk file that grew past its expected length is not truncated.
+    let data = vec![b'z'; 150_000];
+    let init_req = ChunkInitRequest {
+        filename: "padded.bin".to_string(),
+        size: data.len() as u64,
+        ttl: None,
+        chunk_size: Some(70_000),
+        hash: None,
+        max_downloads: None,
+        private: false,
+    };
+    let init = with_conn_ip(
+        Request::builder()
+            .method(Method::POST)
+            .uri("/chunk/init")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_vec(&init_req).unwrap()))
+            .unwrap(),
+        [127, 0, 0, 1],
+        5000,
+    );
+    let init_resp = app.clone().oneshot(init).await.unwrap();
+    let init_bytes = to_bytes(init_resp.into_body(), usize::MAX).await.unwrap();
+    let session: ChunkInitResponse = serde_json::from_slice(&init_bytes).unwrap();
+    for idx in 0..session.total_chunks {
+        let start = idx as usize * session.chunk_size as usize;
+        let end = std::cmp::min(start + session.chunk_size as usize, data.len());
+        let part = with_conn_ip(
+            Request::builder()
+                .method(Method::PUT)
+                .uri(format!("/chunk/{}/{idx}", session.session_id))
+                .body(Body::from(data[start..end].to_vec()))
+                .unwrap(),
+            [127, 0, 0, 1],
+            5000,
+        );
+        let resp = app.clone().oneshot(part).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    }
+    let chunk_path = state
+        .chunk_sessions
+        .get(&session.session_id)
+        .unwrap()
+        .chunk_path(0);
+    let mut padded = std::fs::read(&chunk_path).unwrap();
+    padded.extend_from_slice(b"extra");
+    std::fs::write(&chunk_path, padded).unwrap();
+    let resp = app
+        .clone()
+        .oneshot(complete(&session.session_id))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(error_code(resp).await, "chunk_size");
+
+    // A session whose layout cannot produce the declared size never assembles.
+    let storage_dir = state.chunk_dir.join("bad-layout");
+    std::fs::create_dir_all(&storage_dir).unwrap();
+    for idx in 0..3 {
+        std::fs::write(storage_dir.join(format!("{idx:06}.chunk")), vec![1u8; 100]).unwrap();
+    }
+    state.chunk_sessions.insert(
+        "bad-layout".to_string(),
+        Arc::new(ChunkSession {
+            owner_hash: common::hash_fixture_ip("127.0.0.1"),
+            original_name: "short.bin".into(),
+            storage_name: "short.bin".into(),
+            ttl_code: "1h".into(),
+            expires: juicebox::util::now_secs() + 3600,
+            total_bytes: 150,
+            chunk_size: 100,
+            total_chunks: 3,
+            hash: None,
+            max_downloads: None,
+            private: false,
+            storage_dir: Arc::new(storage_dir),
+            created: juicebox::util::now_secs(),
+            received: tokio::sync::RwLock::new(vec![true; 3]),
+            chunk_hashes: tokio::sync::RwLock::new(vec![None; 3]),
+            lifecycle: ChunkLifecycle::new(ChunkPhase::Receiving),
+            last_update: AtomicU64::new(0),
+            persist_lock: tokio::sync::Mutex::new(()),
+            append_lock: tokio::sync::Mutex::new(()),
+            assembled_chunks: AtomicU32::new(0),
+            trace_parent: None,
+            node: None,
+        }),
+    );
+    let resp = app.oneshot(complete("bad-layout")).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(error_code(resp).await, "size_mismatch");
+    assert!(!state.chunk_sessions.contains_key("bad-layout"));
+    assert!(state.owners.get("short.bin").is_none());
+}
+
 #[tokio::test]
 async fn test_chunk_init_rejects_zero_length() {
     let (state, _tmp) = common::setup_test_app();
@@ -320,6 +876,8 @@ async fn test_chunk_init_rejects_zero_length() {
         ttl: None,
         chunk_size: None,
         hash: None,
+        max_downloads: None,
+        private: false,
     };
     let resp = app
         .clone()
@@ -354,6 +912,8 @@ async fn test_chunk_upload_rejects_forbidden_extension() {
         ttl: Some("1h".to_string()),
         chunk_size: None,
         hash: None,
+        max_downloads: None,
+        private: false,
     };
     let init = with_conn_ip(
         Request::builder()
@@ -384,6 +944,8 @@ async fn test_chunk_upload_rejects_forbidden_content() {
         ttl: Some("1h".to_string()),
         chunk_size: Some(2048),
         hash: None,
+        max_downloads: None,
+        private: false,
     };
     let init = with_conn_ip(
         Request::builder()
@@ -451,6 +1013,8 @@ async fn test_chunk_complete_rejects_missing_chunks() {
         ttl: Some("1h".to_string()),
         chunk_size: Some(60_000),
         hash: None,
+        max_downloads: None,
+        private: false,
     };
     let init_resp = app
         .clone()
@@ -514,6 +1078,154 @@ async fn test_chunk_complete_rejects_missing_chunks() {
     assert_eq!(json["code"], "incomplete");
 }
 
+#[tokio::test]
+async fn test_chunk_sessions_lists_the_callers_unfinished_uploads() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+
+    let init = |filename: &str, ip: [u8; 4]| {
+        let init_req = ChunkInitRequest {
+            filename: filename.to_string(),
+            size: 90_000,
+            ttl: Some("1h".to_string()),
+            chunk_size: Some(60_000),
+            hash: None,
+            max_downloads: None,
+            private: false,
+        };
+        with_conn_ip(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/chunk/init")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::to_vec(&init_req).unwrap()))
+                .unwrap(),
+            ip,
+            8900,
+        )
+    };
+    let mut ours = Vec::new();
+    for filename in ["first.bin", "second.bin"] {
+        let resp = app
+            .clone()
+            .oneshot(init(filename, [127, 0, 0, 1]))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        ours.push(serde_json::from_slice::<ChunkInitResponse>(&body).unwrap());
+    }
+    let resp = app
+        .clone()
+        .oneshot(init("theirs.bin", [10, 0, 0, 9]))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let part = with_conn_ip(
+        Request::builder()
+            .method(Method::PUT)
+            .uri(format!("/chunk/{}/0", ours[0].session_id))
+            .body(Body::from(vec![b'a'; ours[0].chunk_size as usize]))
+            .unwrap(),
+        [127, 0, 0, 1],
+        8901,
+    );
+    let resp = app.clone().oneshot(part).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+    let list = with_conn_ip(
+        Request::builder()
+            .uri("/chunk/sessions")
+            .body(Body::empty())
+            .unwrap(),
+        [127, 0, 0, 1],
+        8902,
+    );
+    let resp = app.clone().oneshot(list).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let listed: ChunkSessionsResponse = serde_json::from_slice(&body).unwrap();
+    let mut ids: Vec<&str> = listed
+        .sessions
+        .iter()
+        .map(|s| s.session_id.as_str())
+        .collect();
+    ids.sort_unstable();
+    let mut expected = vec![ours[0].session_id.as_str(), ours[1].session_id.as_str()];
+    expected.sort_unstable();
+    assert_eq!(ids, expected);
+    let first = listed
+        .sessions
+        .iter()
+        .find(|s| s.session_id == ours[0].session_id)
+        .unwrap();
+    assert_eq!(first.filename, "first.bin");
+    assert_eq!(first.received.len(), ours[0].total_chunks as usize);
+    assert!(first.received[0] && !first.received[1..].iter().any(|r| *r));
+    assert_eq!(first.state, ChunkPhase::Receiving);
+    assert!(first.expires > juicebox::util::now_secs());
+}
+
+#[tokio::test]
+async fn test_chunk_status_reports_which_chunks_are_missing() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+
+    let init_req = ChunkInitRequest {
+        filename: "gaps.bin".to_string(),
+        size: 4 * 65_536,
+        ttl: Some("1h".to_string()),
+        chunk_size: Some(65_536),
+        hash: None,
+        max_downloads: None,
+        private: false,
+    };
+    let init = with_conn_ip(
+        Request::builder()
+            .method(Method::POST)
+            .uri("/chunk/init")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_vec(&init_req).unwrap()))
+            .unwrap(),
+        [127, 0, 0, 1],
+        8950,
+    );
+    let resp = app.clone().oneshot(init).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let session: ChunkInitResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(session.total_chunks, 4);
+
+    let part = with_conn_ip(
+        Request::builder()
+            .method(Method::PUT)
+            .uri(format!("/chunk/{}/1", session.session_id))
+            .body(Body::from(vec![b'g'; 65_536]))
+            .unwrap(),
+        [127, 0, 0, 1],
+        8951,
+    );
+    let resp = app.clone().oneshot(part).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+    let status = with_conn_ip(
+        Request::builder()
+            .uri(format!("/chunk/{}/status", session.session_id))
+            .body(Body::empty())
+            .unwrap(),
+        [127, 0, 0, 1],
+        8952,
+    );
+    let resp = app.clone().oneshot(status).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let status: ChunkStatusResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(status.received, vec![false, true, false, false]);
+    assert_eq!(status.missing, vec![[0, 0], [2, 3]]);
+    assert!(!status.completed);
+}
+
 #[tokio::test]
 async fn test_chunk_session_persistence_across_restart() {
     let (state, tmp) = common::setup_test_app();
@@ -526,6 +1238,8 @@ async fn test_chunk_session_persistence_across_restart() {
         ttl: Some("1h".to_string()),
         chunk_size: Some(64_000),
         hash: None,
+        max_downloads: None,
+        private: false,
     };
     let init = with_conn_ip(
         Request::builder()
@@ -585,3 +1299,177 @@ async fn test_chunk_session_persistence_across_restart() {
     let chunk_path = restored_session.storage_dir.join("000000.chunk");
     assert!(chunk_path.exists());
 }
+
+#[tokio::test]
+async fn test_chunk_session_rejects_requests_while_assembling() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+
+    let data = vec![b'z'; 4096];
+    let init_req = ChunkInitRequest {
+        filename: "busy.bin".to_string(),
+        size: data.len() as u64,
+        ttl: Some("1h".to_string()),
+        chunk_size: Some(4096),
+        hash: None,
+        max_downloads: None,
+        private: false,
+    };
+    let init = with_conn_ip(
+        Request::builder()
+            .method(Method::POST)
+            .uri("/chunk/init")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_vec(&init_req).unwrap()))
+            .unwrap(),
+        [127, 0, 0, 1],
+        5000,
+    );
+    let init_resp = app.clone().oneshot(init).await.unwrap();
+    assert_eq!(init_resp.status(), StatusCode::OK);
+    let init_bytes = to_bytes(init_resp.into_body(), usize::MAX).await.unwrap();
+    let session: ChunkInitResponse = serde_json::from_slice(&init_bytes).unwrap();
+    let part = with_conn_ip(
+        Request::builder()
+            .method(Method::PUT)
+            .uri(format!("/chunk/{}/0", session.session_id))
+            .body(Body::from(data.clone()))
+            .unwrap(),
+        [127, 0, 0, 1],
+        5000,
+    );
+    assert_eq!(
+        app.clone().oneshot(part).await.unwrap().status(),
+        StatusCode::NO_CONTENT
+    );
+
+    // Simulate a completion already in flight.
+    let live = state
+        .chunk_sessions
+        .get(&session.session_id)
+        .unwrap()
+        .clone();
+    live.transition(ChunkPhase::Assembling).unwrap();
+
+    let complete = with_conn_ip(
+        Request::builder()
+            .method(Method::POST)
+            .uri(format!("/chunk/{}/complete", session.session_id))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from("{}"))
+            .unwrap(),
+        [127, 0, 0, 1],
+        5000,
+    );
+    let resp = app.clone().oneshot(complete).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let body: Value =
+        serde_json::from_slice(&to_bytes(resp.into_body(), usize::MAX).await.unwrap()).unwrap();
+    assert_eq!(body["code"], "assembling");
+
+    let part = with_conn_ip(
+        Request::builder()
+            .method(Method::PUT)
+            .uri(format!("/chunk/{}/0", session.session_id))
+            .body(Body::from(data))
+            .unwrap(),
+        [127, 0, 0, 1],
+        5000,
+    );
+    assert_eq!(
+        app.clone().oneshot(part).await.unwrap().status(),
+        StatusCode::CONFLICT
+    );
+    assert_eq!(live.phase(), ChunkPhase::Assembling);
+}
+
+#[tokio::test]
+async fn test_interrupted_assembly_cleaned_on_restart() {
+    let (state, tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+
+    let data = vec![b'q'; 4096];
+    let init_req = ChunkInitRequest {
+        filename: "crashed.bin".to_string(),
+        size: data.len() as u64,
+        ttl: Some("1h".to_string()),
+        chunk_size: Some(4096),
+        hash: None,
+        max_downloads: None,
+        private: false,
+    };
+    let init = with_conn_ip(
+        Request::builder()
+            .method(Method::POST)
+            .uri("/chunk/init")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_vec(&init_req).unwrap()))
+            .unwrap(),
+        [127, 0, 0, 1],
+        5000,
+    );
+    let init_resp = app.clone().oneshot(init).await.unwrap();
+    let init_bytes = to_bytes(init_resp.into_body(), usize::MAX).await.unwrap();
+    let session: ChunkInitResponse = serde_json::from_slice(&init_bytes).unwrap();
+    let part = with_conn_ip(
+        Request::builder()
+            .method(Method::PUT)
+            .uri(format!("/chunk/{}/0", session.session_id))
+            .body(Body::from(data))
+            .unwrap(),
+        [127, 0, 0, 1],
+        5000,
+    );
+    assert_eq!(
+        app.clone().oneshot(part).await.unwrap().status(),
+        StatusCode::NO_CONTENT
+    );
+
+    // Leave the session as a crash mid-assembly would.
+    let live = state
+        .chunk_sessions
+        .get(&session.session_id)
+        .unwrap()
+        .clone();
+    live.transition(ChunkPhase::Assembling).unwrap();
+    state
+        .persist_chunk_session(&session.session_id, live.as_ref())
+        .await
+        .unwrap();
+    let part_file = assembly_temp_path(&state.upload_dir, &session.storage_name);
+    tokio::fs::write(&part_file, b"half").await.unwrap();
+
+    let restored_state = common::recreate_state(tmp.path());
+    restored_state
+        .load_chunk_sessions_from_disk()
+        .await
+        .expect("load chunk sessions");
+    assert!(!part_file.exists());
+    let restored = restored_state
+        .chunk_sessions
+        .get(&session.session_id)
+        .expect("session restored")
+        .clone();
+    assert_eq!(restored.phase(), ChunkPhase::Receiving);
+}
+
+#[tokio::test]
+async fn test_finished_chunk_sessions_dropped_on_restart() {
+    let (state, tmp) = common::setup_test_app();
+    let dir = state.chunk_dir.join("done");
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    tokio::fs::write(
+        dir.join("session.json"),
+        r#"{"owner_hash":"o","original_name":"a","storage_name":"a","ttl_code":"1h","expires":0,"total_bytes":1,"chunk_size":1,"total_chunks":1,"hash":null,"created":0,"received":[true],"completed":true,"last_update":0}"#,
+    )
+    .await
+    .unwrap();
+
+    let restored_state = common::recreate_state(tmp.path());
+    restored_state
+        .load_chunk_sessions_from_disk()
+        .await
+        .expect("load chunk sessions");
+    assert!(restored_state.chunk_sessions.is_empty());
+    assert!(!dir.exists());
+}
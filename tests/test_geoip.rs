@@ -0,0 +1,226 @@
+use axum::body::Body;
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode, header};
+use juicebox::ban_transfer::{parse_csv, to_csv};
+use juicebox::geoip::{GeoInfo, GeoIp, MaxMindDb};
+use juicebox::handlers::{ban_gate, build_router};
+use juicebox::state::{BanSubject, IpBan};
+use juicebox::testing::AppStateBuilder;
+use serde_json::Value;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use tower::ServiceExt;
+
+fn ip(raw: &str) -> IpAddr {
+    raw.parse().unwrap()
+}
+
+/// Databases under `tests/fixtures/geoip`, in the MaxMind DB format:
+/// `country.mmdb` maps 198.51.100.0/24 to DE (`country`) and 2001:db8::/32 to
+/// NL (`registered_country` only); `asn.mmdb` maps 203.0.113.0/25 to AS64500.
+fn fixture(name: &str) -> MaxMindDb {
+    MaxMindDb::open(
+        &Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/geoip")
+            .join(name),
+    )
+    .unwrap()
+}
+
+fn country_db() -> MaxMindDb {
+    fixture("country.mmdb")
+}
+
+fn asn_db() -> MaxMindDb {
+    fixture("asn.mmdb")
+}
+
+#[test]
+fn test_reads_records_from_a_maxmind_db() {
+    let db = country_db();
+    assert_eq!(db.database_type(), "GeoLite2-Country");
+    let record: Value = db.lookup(ip("198.51.100.77")).unwrap().unwrap();
+    assert_eq!(record["country"]["iso_code"], "DE");
+    // IPv4-mapped IPv6 addresses are looked up as IPv4.
+    assert!(
+        db.lookup::<Value>(ip("::ffff:198.51.100.1"))
+            .unwrap()
+            .is_some()
+    );
+    let record: Value = db.lookup(ip("2001:db8::1")).unwrap().unwrap();
+    assert_eq!(record["registered_country"]["geoname_id"], 2750405);
+    assert_eq!(db.lookup::<Value>(ip("198.51.101.1")).unwrap(), None);
+    assert_eq!(db.lookup::<Value>(ip("2001:db9::1")).unwrap(), None);
+
+    assert!(MaxMindDb::from_bytes(b"not a database".to_vec()).is_err());
+    let mut truncated =
+        std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/geoip/asn.mmdb"))
+            .unwrap();
+    truncated.truncate(20);
+    assert!(MaxMindDb::from_bytes(truncated).is_err());
+}
+
+#[test]
+fn test_geoip_combines_country_and_asn() {
+    let geoip = GeoIp::new(Some(country_db()), Some(asn_db()));
+    assert_eq!(
+        geoip.lookup(ip("198.51.100.5")),
+        GeoInfo {
+            country: Some("DE".into()),
+            asn: None,
+        }
+    );
+    assert_eq!(
+        geoip.lookup(ip("2001:db8::5")).country.as_deref(),
+        Some("NL")
+    );
+    assert_eq!(geoip.lookup(ip("203.0.113.5")).asn, Some(64500));
+    assert_eq!(geoip.lookup(ip("203.0.113.200")), GeoInfo::default());
+    assert_eq!(
+        GeoIp::default().lookup(ip("198.51.100.5")),
+        GeoInfo::default()
+    );
+}
+
+#[test]
+fn test_parses_geo_ban_targets() {
+    assert_eq!(
+        BanSubject::parse_geo("country:de"),
+        Some(BanSubject::Country { code: "DE".into() })
+    );
+    assert_eq!(
+        BanSubject::parse_geo("AS64500"),
+        Some(BanSubject::Asn { number: 64500 })
+    );
+    assert_eq!(
+        BanSubject::parse_geo("asn: 13335"),
+        Some(BanSubject::Asn { number: 13335 })
+    );
+    for raw in ["country:deu", "country:", "asdf", "AS-1", "198.51.100.1"] {
+        assert_eq!(BanSubject::parse_geo(raw), None, "{raw}");
+    }
+    assert_eq!(
+        BanSubject::Country { code: "DE".into() }.key(),
+        "country:DE"
+    );
+    assert_eq!(BanSubject::Asn { number: 64500 }.key(), "asn:64500");
+}
+
+fn from(mut req: Request<Body>, addr: &str) -> Request<Body> {
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::new(ip(addr), 6720)));
+    req
+}
+
+fn ban_form(target: &str) -> Request<Body> {
+    Request::builder()
+        .method(Method::POST)
+        .uri("/admin/ban")
+        .header(header::COOKIE, "adm=geo-admin")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(format!(
+            "ip={}&reason=hosting",
+            urlencoding::encode(target)
+        )))
+        .unwrap()
+}
+
+fn home() -> Request<Body> {
+    Request::builder().uri("/").body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn test_country_and_asn_bans_block_whole_networks() {
+    let app = AppStateBuilder::new()
+        .geoip(GeoIp::new(Some(country_db()), Some(asn_db())))
+        .build();
+    let state = app.state.clone();
+    state.create_admin_session("geo-admin".to_string()).await;
+    let router = build_router(state.clone()).layer(axum::middleware::from_fn_with_state(
+        state.clone(),
+        ban_gate,
+    ));
+    for target in ["country:de", "AS64500"] {
+        let resp = router
+            .clone()
+            .oneshot(from(ban_form(target), "192.0.2.1"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER, "{target}");
+    }
+    let keys: Vec<String> = state
+        .bans
+        .read()
+        .await
+        .iter()
+        .map(|b| b.subject.key().into_owned())
+        .collect();
+    assert_eq!(keys, ["country:DE", "asn:64500"]);
+
+    assert!(state.is_banned("198.51.100.200").await);
+    assert!(state.is_banned("203.0.113.9").await);
+    assert!(!state.is_banned("203.0.113.129").await);
+    assert!(!state.is_banned("2001:db8::1").await);
+    let ban = state.find_ban_for_input("203.0.113.9").await.unwrap();
+    assert_eq!(ban.subject, BanSubject::Asn { number: 64500 });
+
+    let resp = router
+        .clone()
+        .oneshot(from(home(), "198.51.100.3"))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    assert_eq!(state.ban_hits.get("country:DE").hits, 1);
+    let resp = router
+        .clone()
+        .oneshot(from(home(), "192.0.2.1"))
+        .await
+        .unwrap();
+    assert_ne!(resp.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_geo_bans_need_their_database() {
+    let app = AppStateBuilder::new()
+        .geoip(GeoIp::new(None, Some(asn_db())))
+        .build();
+    let state = app.state.clone();
+    state.create_admin_session("geo-admin".to_string()).await;
+    let router = build_router(state.clone());
+    let resp = router
+        .clone()
+        .oneshot(from(ban_form("country:DE"), "192.0.2.1"))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let resp = router
+        .clone()
+        .oneshot(from(ban_form("AS64500"), "192.0.2.1"))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+    assert_eq!(state.bans.read().await.len(), 1);
+}
+
+#[test]
+fn test_geo_bans_survive_csv_export() {
+    let bans = [
+        IpBan {
+            subject: BanSubject::Country { code: "DE".into() },
+            label: Some("country:de".into()),
+            reason: "abuse".into(),
+            time: 1,
+            expires: None,
+        },
+        IpBan {
+            subject: BanSubject::Asn { number: 64500 },
+            label: None,
+            reason: "hosting".into(),
+            time: 2,
+            expires: Some(3),
+        },
+    ];
+    let parsed = parse_csv(&to_csv(&bans)).unwrap();
+    assert_eq!(parsed[0].subject, bans[0].subject);
+    assert_eq!(parsed[1].subject, bans[1].subject);
+}
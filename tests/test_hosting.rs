@@ -1,12 +1,18 @@
+#![allow(clippy::await_holding_lock)]
+
 mod common;
 
 use axum::body::{Body, to_bytes};
-use axum::http::{HeaderValue, Request, StatusCode, header};
+use axum::extract::ConnectInfo;
+use axum::http::{HeaderValue, Method, Request, StatusCode, header};
 use juicebox::handlers::build_router;
 use juicebox::state::FileMeta;
+use juicebox::testing::AppStateBuilder;
+use juicebox::tombstones::RemovalReason;
 use juicebox::util::now_secs;
 use once_cell::sync::Lazy;
 use serde_json::Value;
+use std::net::SocketAddr;
 use std::sync::Mutex;
 use tower::ServiceExt;
 
@@ -74,6 +80,48 @@ async fn test_config_handler_includes_telemetry_and_streaming_flag() {
     // Skipping env-toggling assertions; ensure the field exists and is boolean (checked above).
 }
 
+#[tokio::test]
+async fn test_version_endpoint_reports_build_info() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state);
+
+    let resp = app
+        .oneshot(
+            Request::builder()
+                .uri("/api/version")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get(header::CACHE_CONTROL),
+        Some(&HeaderValue::from_static("no-store"))
+    );
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let json: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(
+        json["commit"].as_str(),
+        juicebox::build_info::git_commit_short()
+    );
+    assert_eq!(json["built_at"], juicebox::build_info::build_timestamp());
+    assert_eq!(json["storage_backend"], "memory");
+    assert_eq!(json["file_store"], "local");
+    let features: Vec<&str> = json["features"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|v| v.as_str())
+        .collect();
+    assert_eq!(
+        features.contains(&"embedded-assets"),
+        cfg!(feature = "embedded-assets")
+    );
+}
+
 #[tokio::test]
 async fn test_fetch_file_serves_and_sets_cache_headers() {
     let (state, _tmp) = common::setup_test_app();
@@ -91,9 +139,17 @@ async fn test_fetch_file_serves_and_sets_cache_headers() {
         FileMeta {
             owner_hash: owner,
             expires: exp,
-            original: "hello.txt".to_string(),
+            original: "hello\u{202E}txt.exe".to_string(),
+            original_display: String::new(),
             created: now_secs(),
             hash: String::new(),
+            max_downloads: None,
+            downloads: 0,
+            private: false,
+            ttl_shortened_from: None,
+            guest: false,
+            network_class: None,
+            size: 0,
         },
     );
 
@@ -116,6 +172,13 @@ async fn test_fetch_file_serves_and_sets_cache_headers() {
         .get(header::CONTENT_TYPE)
         .and_then(|v| v.to_str().ok());
     assert_eq!(ct, Some("text/plain"));
+    // Original name is served display-safe, without the bidi override.
+    assert_eq!(
+        headers
+            .get(header::CONTENT_DISPOSITION)
+            .and_then(|v| v.to_str().ok()),
+        Some("inline; filename=\"hellotxt.exe\"; filename*=UTF-8''hellotxt.exe")
+    );
 
     // Cache headers present with max-age policy
     let cc = headers
@@ -133,6 +196,84 @@ async fn test_fetch_file_serves_and_sets_cache_headers() {
     assert_eq!(&body[..], b"hi there");
 }
 
+#[tokio::test]
+async fn test_fetch_file_honors_conditional_requests() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+    let file_name = "cached.txt".to_string();
+    std::fs::write(state.upload_dir.join(&file_name), b"same bytes").unwrap();
+    let created = now_secs() - 600;
+    state.owners.insert(
+        file_name.clone(),
+        FileMeta {
+            owner_hash: common::hash_fixture_ip("127.0.0.1"),
+            expires: now_secs() + 3600,
+            original: file_name.clone(),
+            original_display: String::new(),
+            created,
+            hash: "abc123".to_string(),
+            max_downloads: None,
+            downloads: 0,
+            private: false,
+            ttl_shortened_from: None,
+            guest: false,
+            network_class: None,
+            size: 0,
+        },
+    );
+    let get = |conditions: &[(header::HeaderName, String)]| {
+        let mut req = Request::builder().uri("/f/cached.txt");
+        for (name, value) in conditions {
+            req = req.header(name, value);
+        }
+        app.clone().oneshot(req.body(Body::empty()).unwrap())
+    };
+
+    let resp = get(&[]).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get(header::ETAG).unwrap(), "\"abc123\"");
+    let last_modified = resp
+        .headers()
+        .get(header::LAST_MODIFIED)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    for etag in ["\"abc123\"", "W/\"abc123\"", "\"other\", \"abc123\"", "*"] {
+        let resp = get(&[(header::IF_NONE_MATCH, etag.to_string())])
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED, "{etag}");
+        assert_eq!(resp.headers().get(header::ETAG).unwrap(), "\"abc123\"");
+        assert!(resp.headers().get(header::CACHE_CONTROL).is_some());
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        assert!(body.is_empty());
+    }
+    let resp = get(&[(header::IF_MODIFIED_SINCE, last_modified)])
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+
+    // A stale If-None-Match wins over a matching If-Modified-Since.
+    let future = httpdate::fmt_http_date(std::time::SystemTime::now());
+    let resp = get(&[
+        (header::IF_NONE_MATCH, "\"stale\"".to_string()),
+        (header::IF_MODIFIED_SINCE, future),
+    ])
+    .await
+    .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"same bytes");
+
+    let past = httpdate::fmt_http_date(
+        std::time::UNIX_EPOCH + std::time::Duration::from_secs(created - 1),
+    );
+    let resp = get(&[(header::IF_MODIFIED_SINCE, past)]).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
 #[tokio::test]
 async fn test_fetch_file_404_missing_or_orphan() {
     let (state, _tmp) = common::setup_test_app();
@@ -159,8 +300,16 @@ async fn test_fetch_file_404_missing_or_orphan() {
             owner_hash: common::hash_fixture_ip("10.0.0.1"),
             expires: now_secs() + 3600,
             original: orphan.clone(),
+            original_display: String::new(),
             created: now_secs(),
             hash: String::new(),
+            max_downloads: None,
+            downloads: 0,
+            private: false,
+            ttl_shortened_from: None,
+            guest: false,
+            network_class: None,
+            size: 0,
         },
     );
     let resp2 = app
@@ -176,6 +325,376 @@ async fn test_fetch_file_404_missing_or_orphan() {
     assert_eq!(resp2.status(), StatusCode::NOT_FOUND);
 }
 
+#[tokio::test]
+async fn test_downloads_are_counted_for_owner_and_admin() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+    std::fs::write(state.upload_dir.join("counted.txt"), b"hi there").unwrap();
+    state.owners.insert(
+        "counted.txt".to_string(),
+        FileMeta {
+            owner_hash: common::hash_fixture_ip("127.0.0.1"),
+            expires: now_secs() + 600,
+            original: "counted.txt".to_string(),
+            original_display: String::new(),
+            created: now_secs(),
+            hash: String::new(),
+            max_downloads: None,
+            downloads: 0,
+            private: false,
+            ttl_shortened_from: None,
+            guest: false,
+            network_class: None,
+            size: 0,
+        },
+    );
+    for method in [Method::GET, Method::HEAD, Method::GET] {
+        let resp = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri("/f/counted.txt")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+    assert_eq!(state.owners.get("counted.txt").unwrap().downloads, 2);
+
+    let mut list = Request::builder().uri("/list").body(Body::empty()).unwrap();
+    list.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 4000))));
+    let resp = app.clone().oneshot(list).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let listed: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(listed["metas"][0]["downloads"], 2);
+
+    state.create_admin_session("count-admin".to_string()).await;
+    let resp = app
+        .oneshot(
+            Request::builder()
+                .uri("/admin/files")
+                .header(header::COOKIE, "adm=count-admin")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let html = String::from_utf8_lossy(&body);
+    assert!(html.contains("<td>8</td><td>2</td>"), "{html}");
+}
+
+#[tokio::test]
+async fn test_download_page_shows_metadata_and_report_link() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+
+    let file_name = "shared.pdf".to_string();
+    std::fs::write(state.upload_dir.join(&file_name), vec![0u8; 2048]).unwrap();
+    state.owners.insert(
+        file_name.clone(),
+        FileMeta {
+            owner_hash: common::hash_fixture_ip("127.0.0.1"),
+            expires: now_secs() + 7200,
+            original: "<b>quarterly</b>.pdf".to_string(),
+            original_display: String::new(),
+            created: now_secs(),
+            hash: String::new(),
+            max_downloads: None,
+            downloads: 0,
+            private: false,
+            ttl_shortened_from: None,
+            guest: false,
+            network_class: None,
+            size: 0,
+        },
+    );
+
+    let resp = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(format!("/d/{}", file_name))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get(header::CACHE_CONTROL).unwrap(),
+        "no-store"
+    );
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let html = String::from_utf8(body.to_vec()).unwrap();
+    assert!(html.contains("&lt;b&gt;quarterly"));
+    assert!(!html.contains("<b>quarterly"));
+    assert!(html.contains("2KB"));
+    assert!(html.contains("application/pdf"));
+    assert!(html.contains("href=\"/f/shared.pdf\""));
+    assert!(html.contains("href=\"/report?file=shared.pdf\""));
+
+    // Expired files say so instead of offering a download; unknown ones 404.
+    state.owners.get_mut(&file_name).unwrap().expires = now_secs() - 1;
+    let resp = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/d/shared.pdf")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::GONE);
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let html = String::from_utf8(body.to_vec()).unwrap();
+    assert!(html.contains("This file expired on"));
+    assert!(!html.contains("href=\"/f/shared.pdf\""));
+    let resp = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/d/missing.bin")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_expired_file_leaves_tombstone_for_grace_period() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+    let file_name = "faded.txt".to_string();
+    let expired_at = now_secs() - 60;
+    std::fs::write(state.upload_dir.join(&file_name), b"gone soon").unwrap();
+    state.insert_owner(
+        file_name.clone(),
+        FileMeta {
+            owner_hash: common::hash_fixture_ip("127.0.0.1"),
+            expires: expired_at,
+            original: file_name.clone(),
+            original_display: String::new(),
+            created: expired_at - 3600,
+            hash: String::new(),
+            max_downloads: None,
+            downloads: 0,
+            private: false,
+            ttl_shortened_from: None,
+            guest: false,
+            network_class: None,
+            size: 0,
+        },
+    );
+
+    let fetch = |uri: &'static str| {
+        let app = app.clone();
+        async move {
+            app.oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap()
+        }
+    };
+    // The fetch sweeps the expired entry and answers from its tombstone.
+    let resp = fetch("/f/faded.txt").await;
+    assert_eq!(resp.status(), StatusCode::GONE);
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let text = String::from_utf8(body.to_vec()).unwrap();
+    let expected_date =
+        httpdate::fmt_http_date(std::time::UNIX_EPOCH + std::time::Duration::from_secs(expired_at));
+    assert_eq!(text, format!("this file expired on {expected_date}"));
+    assert!(state.owners.get(&file_name).is_none());
+    assert!(!state.upload_dir.join(&file_name).exists());
+    assert_eq!(fetch("/d/faded.txt").await.status(), StatusCode::GONE);
+    assert_eq!(
+        fetch("/f/never-existed.txt").await.status(),
+        StatusCode::NOT_FOUND
+    );
+
+    // Tombstones are persisted and forgotten once the grace period passes.
+    let stored = state.kv.load_hash("tombstones").await.unwrap();
+    assert_eq!(stored, vec![(file_name.clone(), expired_at.to_string())]);
+    let grace = state.tombstones.grace_secs();
+    assert_eq!(state.tombstones.prune(expired_at + grace), 1);
+    assert_eq!(fetch("/f/faded.txt").await.status(), StatusCode::NOT_FOUND);
+}
+
+fn hosted_by(ip: &str) -> FileMeta {
+    FileMeta {
+        owner_hash: common::hash_fixture_ip(ip),
+        expires: now_secs() + 3600,
+        original: String::new(),
+        original_display: String::new(),
+        created: now_secs(),
+        hash: String::new(),
+        max_downloads: None,
+        downloads: 0,
+        private: false,
+        ttl_shortened_from: None,
+        guest: false,
+        network_class: None,
+        size: 0,
+    }
+}
+
+#[tokio::test]
+async fn test_admin_removal_reason_is_shown_to_owner_only() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+    std::fs::write(state.upload_dir.join("flagged.txt"), b"payload").unwrap();
+    state.insert_owner("flagged.txt".to_string(), hosted_by("127.0.0.1"));
+    state
+        .create_admin_session("removal-admin".to_string())
+        .await;
+
+    let admin_post = |uri: &'static str, form: &'static str| {
+        Request::builder()
+            .method(Method::POST)
+            .uri(uri)
+            .header(header::COOKIE, "adm=removal-admin")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(form))
+            .unwrap()
+    };
+    let resp = app
+        .clone()
+        .oneshot(admin_post("/admin/files", "file=flagged.txt&reason=bogus"))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let resp = app
+        .clone()
+        .oneshot(admin_post(
+            "/admin/files",
+            "file=flagged.txt&reason=malware",
+        ))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+    assert!(state.owners.get("flagged.txt").is_none());
+    assert!(!state.upload_dir.join("flagged.txt").exists());
+
+    let get = |uri: &'static str, peer: Option<[u8; 4]>| {
+        let app = app.clone();
+        async move {
+            let mut req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            if let Some(ip) = peer {
+                req.extensions_mut()
+                    .insert(ConnectInfo(SocketAddr::from((ip, 4100))));
+            }
+            app.oneshot(req).await.unwrap()
+        }
+    };
+    let owner = Some([127, 0, 0, 1]);
+    let resp = get("/f/flagged.txt", owner).await;
+    assert_eq!(resp.status(), StatusCode::GONE);
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let text = String::from_utf8(body.to_vec()).unwrap();
+    assert!(
+        text.starts_with("this file was removed for ToS violation: malware on "),
+        "{text}"
+    );
+    let resp = get("/d/flagged.txt", owner).await;
+    assert_eq!(resp.status(), StatusCode::GONE);
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let html = String::from_utf8(body.to_vec()).unwrap();
+    assert!(html.contains("removed for ToS violation: malware"));
+    assert!(!html.contains("href=\"/f/flagged.txt\""));
+    let resp = get("/list", owner).await;
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let listed: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(listed["removed"][0]["reason"], "malware");
+    assert_eq!(
+        listed["removed"][0]["message"],
+        "removed for ToS violation: malware"
+    );
+    assert!(
+        listed["removed"][0]["file"]
+            .as_str()
+            .unwrap()
+            .ends_with("f/flagged.txt")
+    );
+
+    // Anyone else sees a file that never existed.
+    for peer in [None, Some([198, 51, 100, 7])] {
+        assert_eq!(
+            get("/f/flagged.txt", peer).await.status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            get("/d/flagged.txt", peer).await.status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+    let resp = get("/list", Some([198, 51, 100, 7])).await;
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let listed: Value = serde_json::from_slice(&body).unwrap();
+    assert!(listed.get("removed").is_none());
+
+    // A ban can take the banned address's files down with it.
+    std::fs::write(state.upload_dir.join("theirs.txt"), b"payload").unwrap();
+    state.insert_owner("theirs.txt".to_string(), hosted_by("203.0.113.9"));
+    let resp = app
+        .clone()
+        .oneshot(admin_post(
+            "/admin/ban",
+            "ip=203.0.113.9&reason=spam&remove_files=on",
+        ))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+    assert!(state.owners.get("theirs.txt").is_none());
+    let removal = state.tombstones.removal("theirs.txt", now_secs()).unwrap();
+    assert_eq!(removal.reason, RemovalReason::Banned);
+    assert_eq!(removal.owner_hash, common::hash_fixture_ip("203.0.113.9"));
+
+    // Removals survive a restart.
+    let restarted = AppStateBuilder::new().kv(state.kv.clone()).build().state;
+    restarted.load_tombstones().await.unwrap();
+    assert_eq!(
+        restarted
+            .tombstones
+            .removal("flagged.txt", now_secs())
+            .unwrap()
+            .reason,
+        RemovalReason::Malware
+    );
+    assert!(
+        restarted
+            .tombstones
+            .expired_at("flagged.txt", now_secs())
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn test_report_page_prefills_file() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state);
+    let resp = app
+        .oneshot(
+            Request::builder()
+                .uri("/report?file=%22%3E%3Cscript%3E")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let html = String::from_utf8(body.to_vec()).unwrap();
+    assert!(html.contains("value=\"&quot;&gt;&lt;script&gt;\""));
+}
+
 #[tokio::test]
 async fn test_static_file_served_with_cache_and_precompressed_when_requested() {
     let (state, _tmp) = common::setup_test_app();
@@ -231,8 +750,28 @@ async fn test_static_file_served_with_cache_and_precompressed_when_requested() {
     );
     // Vary header should be set when content encoding is used
     assert!(h2.get(header::VARY).is_some());
+    let br_etag = h2.get(header::ETAG).unwrap().clone();
     let body2 = to_bytes(resp2.into_body(), usize::MAX).await.unwrap();
     assert_eq!(&body2[..], b"NOTREALBR");
+
+    // 3) Revalidation is per representation.
+    let revalidate = |accept: &'static str| {
+        app.clone().oneshot(
+            Request::builder()
+                .uri(format!("/{}", css_name))
+                .header(header::ACCEPT_ENCODING, accept)
+                .header(header::IF_NONE_MATCH, br_etag.clone())
+                .body(Body::empty())
+                .unwrap(),
+        )
+    };
+    let resp3 = revalidate("br").await.unwrap();
+    assert_eq!(resp3.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(resp3.headers().get(header::CONTENT_ENCODING).unwrap(), "br");
+    assert_eq!(
+        revalidate("identity").await.unwrap().status(),
+        StatusCode::OK
+    );
 }
 
 #[tokio::test]
@@ -256,3 +795,124 @@ async fn test_file_handler_rejects_traversal_attempt() {
     let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
     assert_eq!(&body[..], b"bad path");
 }
+
+#[cfg(feature = "embedded-assets")]
+#[tokio::test]
+async fn test_embedded_assets_fill_in_for_missing_static_files() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+    assert!(!state.static_dir.join("robots.txt").exists());
+
+    let fetch = |app: axum::Router| async move {
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .uri("/robots.txt")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        to_bytes(resp.into_body(), usize::MAX).await.unwrap()
+    };
+    assert_eq!(
+        &fetch(app.clone()).await[..],
+        include_bytes!("../public/robots.txt")
+    );
+
+    // A copy on disk overrides the embedded one.
+    std::fs::write(state.static_dir.join("robots.txt"), b"User-agent: *").unwrap();
+    assert_eq!(&fetch(app).await[..], b"User-agent: *");
+
+    let tera = juicebox::assets::load_templates("/nonexistent/**/*.tera").unwrap();
+    assert!(
+        tera.get_template_names()
+            .any(|name| name == "stats.html.tera")
+    );
+}
+
+#[tokio::test]
+async fn test_link_status_reports_state_without_serving_content() {
+    use axum::extract::ConnectInfo;
+    use juicebox::handlers::LinkStatusResponse;
+    use juicebox::rate_limit::LINK_STATUS_RATE_LIMIT_BURST;
+    use std::net::SocketAddr;
+
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+    let now = now_secs();
+    let meta = |expires: u64| FileMeta {
+        owner_hash: common::hash_fixture_ip("127.0.0.1"),
+        expires,
+        original: String::new(),
+        original_display: String::new(),
+        created: now - 10,
+        hash: String::new(),
+        max_downloads: None,
+        downloads: 0,
+        private: false,
+        ttl_shortened_from: None,
+        guest: false,
+        network_class: None,
+        size: 0,
+    };
+    std::fs::write(state.upload_dir.join("live.txt"), b"12345").unwrap();
+    state.insert_owner("live.txt".into(), meta(now + 600));
+    state.insert_owner("stale.txt".into(), meta(now - 5));
+    state.tombstones.record("buried.txt", now - 100);
+    std::fs::write(state.upload_dir.join("flagged.txt"), b"bad").unwrap();
+    state.insert_owner("flagged.txt".into(), meta(now + 600));
+    state
+        .quarantine_file("flagged.txt", "hash_list", "blocklisted hash", "")
+        .await
+        .unwrap();
+
+    let status = |name: &str, ip: [u8; 4]| {
+        let mut req = Request::builder()
+            .uri(format!("/api/v1/files/{name}/status"))
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from((ip, 5000))));
+        app.clone().oneshot(req)
+    };
+    let check = |name: &'static str| async move {
+        let resp = status(name, [10, 1, 1, 1]).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK, "{name}");
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice::<LinkStatusResponse>(&body).unwrap()
+    };
+
+    let live = check("live.txt").await;
+    assert!(live.exists && !live.expired && !live.quarantined);
+    assert_eq!(live.size, Some(5));
+    assert_eq!(live.expires, Some(now + 600));
+    let stale = check("stale.txt").await;
+    assert!(!stale.exists && stale.expired);
+    assert_eq!(stale.expires, Some(now - 5));
+    let buried = check("buried.txt").await;
+    assert!(buried.expired);
+    assert_eq!(buried.expires, Some(now - 100));
+    let flagged = check("flagged.txt").await;
+    assert!(!flagged.exists && flagged.quarantined && flagged.size.is_none());
+    let unknown = check("nothing.txt").await;
+    assert!(!unknown.exists && !unknown.expired && !unknown.quarantined);
+
+    // The status check has its own budget per client.
+    let ip = [10, 2, 2, 2];
+    for _ in 0..LINK_STATUS_RATE_LIMIT_BURST {
+        assert_eq!(
+            status("live.txt", ip).await.unwrap().status(),
+            StatusCode::OK
+        );
+    }
+    assert_eq!(
+        status("live.txt", ip).await.unwrap().status(),
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    assert_eq!(
+        status("live.txt", [10, 3, 3, 3]).await.unwrap().status(),
+        StatusCode::OK
+    );
+}
@@ -0,0 +1,97 @@
+use axum::body::{Body, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{HeaderMap, HeaderValue, Method, Request, StatusCode, header};
+use juicebox::handlers::build_router;
+use juicebox::i18n::{Locale, Plural, request_language};
+use juicebox::testing::AppStateBuilder;
+use serde_json::Value;
+use std::net::SocketAddr;
+use tower::ServiceExt;
+
+async fn call(
+    app: &axum::Router,
+    method: Method,
+    uri: &str,
+    language: &str,
+    body: &'static str,
+) -> Value {
+    let mut req = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::ACCEPT_LANGUAGE, language)
+        .body(Body::from(body))
+        .unwrap();
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([198, 51, 100, 31], 9100))));
+    let resp = app.clone().oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    serde_json::from_slice(&to_bytes(resp.into_body(), usize::MAX).await.unwrap()).unwrap()
+}
+
+#[test]
+fn test_request_language_prefers_query_then_header() {
+    let mut headers = HeaderMap::new();
+    assert_eq!(request_language(&headers, None), "en");
+    headers.insert(
+        header::ACCEPT_LANGUAGE,
+        HeaderValue::from_static("fr-CA,fr;q=0.9,en;q=0.5"),
+    );
+    assert_eq!(request_language(&headers, None), "fr");
+    assert_eq!(request_language(&headers, Some("UK")), "uk");
+    assert_eq!(request_language(&headers, Some("../etc")), "en");
+}
+
+#[test]
+fn test_plural_categories() {
+    assert_eq!(Plural::for_count("en", 1), Plural::One);
+    assert_eq!(Plural::for_count("en", 0), Plural::Other);
+    assert_eq!(Plural::for_count("fr", 0), Plural::One);
+    assert_eq!(Plural::for_count("uk", 21), Plural::One);
+    assert_eq!(Plural::for_count("uk", 3), Plural::Few);
+    assert_eq!(Plural::for_count("uk", 12), Plural::Many);
+}
+
+#[tokio::test]
+async fn test_locale_formats_expiry_and_size() {
+    let en = Locale::load("en").await;
+    assert_eq!(en.expires_in(3 * 86400 + 5, 0), "in 3 days");
+    assert_eq!(en.expires_in(3600, 0), "in 1 hour");
+    assert_eq!(en.expires_in(10, 20), "expired");
+    assert_eq!(en.size(512), "512 B");
+    assert_eq!(en.size(1536), "1.5 KB");
+    assert_eq!(en.size(50 * 1024 * 1024), "50 MB");
+
+    let fr = Locale::load("fr").await;
+    assert_eq!(fr.size(1536), "1,5 Ko");
+    assert_eq!(fr.expires_in(2 * 86400, 0), "dans 2 jours");
+
+    let uk = Locale::load("uk").await;
+    assert_eq!(uk.expires_in(2 * 3600, 0), "через 2 години");
+    assert_eq!(uk.expires_in(5 * 3600, 0), "через 5 годин");
+
+    let unknown = Locale::load("zz").await;
+    assert_eq!(unknown.lang(), "en");
+}
+
+#[tokio::test]
+async fn test_paste_and_list_return_localized_strings() {
+    let app = AppStateBuilder::new().manual_clock(1_700_000_000).build();
+    let router = build_router(app.state.clone());
+
+    let paste = call(
+        &router,
+        Method::POST,
+        "/api/paste-binary?name=note.txt&ttl=3d",
+        "es-ES,es;q=0.9",
+        "hola",
+    )
+    .await;
+    assert_eq!(paste["size_human"], "4 B");
+    assert_eq!(paste["expires_human"], "en 3 días");
+
+    let list = call(&router, Method::GET, "/list?lang=fr", "es", "").await;
+    let meta = &list["metas"][0];
+    assert_eq!(meta["size"], 4);
+    assert_eq!(meta["size_human"], "4 o");
+    assert_eq!(meta["expires_human"], "dans 3 jours");
+}
@@ -0,0 +1,240 @@
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use axum::body::{Body, to_bytes};
+use axum::http::{Request, StatusCode};
+use juicebox::handlers::build_router;
+use juicebox::kv_failover::{BreakerState, CircuitBreaker, FailoverStore};
+use juicebox::state::{KvStore, MemoryStore};
+use juicebox::testing::AppStateBuilder;
+use serde_json::Value;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tower::ServiceExt;
+
+/// A memory store that fails every call while switched off.
+struct Flaky {
+    inner: MemoryStore,
+    down: AtomicBool,
+}
+
+impl Flaky {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            inner: MemoryStore::new("primary".into()),
+            down: AtomicBool::new(false),
+        })
+    }
+
+    fn set_down(&self, down: bool) {
+        self.down.store(down, Ordering::SeqCst);
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.down.load(Ordering::SeqCst) {
+            Err(anyhow!("connection refused"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[async_trait]
+impl KvStore for Flaky {
+    fn backend_name(&self) -> &'static str {
+        "flaky"
+    }
+
+    async fn replace_hash(&self, key: &str, entries: &[(String, String)]) -> Result<()> {
+        self.check()?;
+        self.inner.replace_hash(key, entries).await
+    }
+
+    async fn load_hash(&self, key: &str) -> Result<Vec<(String, String)>> {
+        self.check()?;
+        self.inner.load_hash(key).await
+    }
+
+    async fn replace_list(&self, key: &str, values: &[String]) -> Result<()> {
+        self.check()?;
+        self.inner.replace_list(key, values).await
+    }
+
+    async fn load_list(&self, key: &str) -> Result<Vec<String>> {
+        self.check()?;
+        self.inner.load_list(key).await
+    }
+
+    async fn append_list(&self, key: &str, value: &str) -> Result<()> {
+        self.check()?;
+        self.inner.append_list(key, value).await
+    }
+}
+
+fn failover(cooldown: Duration) -> (Arc<Flaky>, Arc<MemoryStore>, FailoverStore) {
+    let primary = Flaky::new();
+    let fallback = Arc::new(MemoryStore::new("fallback".into()));
+    let store = FailoverStore::new(
+        primary.clone(),
+        fallback.clone(),
+        CircuitBreaker::new(2, cooldown),
+    );
+    (primary, fallback, store)
+}
+
+fn pair(field: &str, value: &str) -> (String, String) {
+    (field.to_string(), value.to_string())
+}
+
+#[tokio::test]
+async fn test_healthy_primary_takes_writes_and_mirrors_them() {
+    let (primary, fallback, store) = failover(Duration::from_secs(60));
+    store
+        .replace_hash("owners", &[pair("a", "1")])
+        .await
+        .unwrap();
+    store.append_list("audit_log", "first").await.unwrap();
+    assert_eq!(
+        primary.inner.load_hash("owners").await.unwrap(),
+        [pair("a", "1")]
+    );
+    assert_eq!(
+        fallback.load_hash("owners").await.unwrap(),
+        [pair("a", "1")]
+    );
+    assert_eq!(fallback.load_list("audit_log").await.unwrap(), ["first"]);
+    assert_eq!(store.backend_name(), "flaky");
+
+    let status = store.failover_status().unwrap();
+    assert_eq!(status.breaker, BreakerState::Closed);
+    assert_eq!(status.pending_writes, 0);
+    assert!(!status.degraded());
+}
+
+#[tokio::test]
+async fn test_writes_fail_over_and_replay_in_order_once_the_primary_is_back() {
+    let (primary, fallback, store) = failover(Duration::ZERO);
+    store.append_list("audit_log", "before").await.unwrap();
+    primary.set_down(true);
+
+    store
+        .replace_hash("owners", &[pair("a", "1")])
+        .await
+        .unwrap();
+    store.append_list("audit_log", "during-1").await.unwrap();
+    // A later replace of the same key supersedes the queued one.
+    store
+        .replace_hash("owners", &[pair("a", "1"), pair("b", "2")])
+        .await
+        .unwrap();
+    store.append_list("audit_log", "during-2").await.unwrap();
+
+    let status = store.failover_status().unwrap();
+    assert_eq!(status.pending_writes, 3);
+    assert_eq!(status.failovers, 1);
+    assert!(status.degraded());
+    // Reads come from the fallback, which has every write.
+    assert_eq!(store.load_hash("owners").await.unwrap().len(), 2);
+    assert_eq!(
+        store.load_list("audit_log").await.unwrap(),
+        ["before", "during-1", "during-2"]
+    );
+    assert_eq!(fallback.load_hash("owners").await.unwrap().len(), 2);
+
+    // Still down: the replay attempt leaves the queue alone.
+    store.replay_pending().await;
+    assert_eq!(store.failover_status().unwrap().pending_writes, 3);
+
+    primary.set_down(false);
+    store.replay_pending().await;
+    let status = store.failover_status().unwrap();
+    assert_eq!(status.pending_writes, 0);
+    assert_eq!(status.replayed, 3);
+    assert_eq!(status.breaker, BreakerState::Closed);
+    assert_eq!(primary.inner.load_hash("owners").await.unwrap().len(), 2);
+    assert_eq!(
+        primary.inner.load_list("audit_log").await.unwrap(),
+        ["before", "during-1", "during-2"]
+    );
+}
+
+#[tokio::test]
+async fn test_open_breaker_leaves_the_primary_alone_until_the_cooldown_ends() {
+    let (primary, _fallback, store) = failover(Duration::from_secs(60));
+    primary.set_down(true);
+    store.replace_list("reports", &["r1".into()]).await.unwrap();
+    assert_eq!(
+        store.failover_status().unwrap().breaker,
+        BreakerState::Closed,
+        "one failure is under the threshold"
+    );
+    store.replace_list("reports", &["r2".into()]).await.unwrap();
+    assert_eq!(store.failover_status().unwrap().breaker, BreakerState::Open);
+
+    primary.set_down(false);
+    store.replay_pending().await;
+    let status = store.failover_status().unwrap();
+    assert_eq!(status.breaker, BreakerState::Open);
+    assert_eq!(status.pending_writes, 1);
+    assert!(primary.inner.load_list("reports").await.unwrap().is_empty());
+    assert_eq!(store.load_list("reports").await.unwrap(), ["r2"]);
+}
+
+#[tokio::test]
+async fn test_failed_reads_fall_back_without_losing_writes() {
+    let (primary, fallback, store) = failover(Duration::from_secs(60));
+    store.replace_hash("bans", &[pair("k", "v")]).await.unwrap();
+    primary.set_down(true);
+    assert_eq!(store.load_hash("bans").await.unwrap(), [pair("k", "v")]);
+    assert_eq!(store.failover_status().unwrap().pending_writes, 0);
+    fallback.replace_hash("bans", &[]).await.unwrap();
+    assert!(store.load_hash("bans").await.unwrap().is_empty());
+}
+
+async fn readyz(state: juicebox::state::AppState) -> (StatusCode, Value) {
+    let resp = build_router(state)
+        .oneshot(
+            Request::builder()
+                .uri("/readyz")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = resp.status();
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn test_readyz_reports_failover_state() {
+    let app = AppStateBuilder::new().build();
+    let (status, body) = readyz(app.state.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "ready");
+    assert_eq!(body["metadata_store"]["backend"], "memory");
+    assert_eq!(body["metadata_store"]["failover"], Value::Null);
+
+    let (primary, _fallback, store) = failover(Duration::from_secs(60));
+    let app = AppStateBuilder::new().kv(Arc::new(store)).build();
+    let (status, body) = readyz(app.state.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "ready");
+    assert_eq!(body["metadata_store"]["failover"]["fallback"], "memory");
+
+    primary.set_down(true);
+    for _ in 0..2 {
+        app.state.kv.append_list("audit_log", "x").await.unwrap();
+    }
+    let (status, body) = readyz(app.state.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "degraded");
+    let failover = &body["metadata_store"]["failover"];
+    assert_eq!(failover["breaker"], "open");
+    assert_eq!(failover["pending_writes"], 2);
+
+    app.state.drain.begin();
+    let (status, body) = readyz(app.state.clone()).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["status"], "draining");
+}
@@ -6,8 +6,11 @@ use axum::{
     http::{Method, Request, StatusCode, header},
 };
 use hyper::body::Bytes;
-use juicebox::handlers::{UploadResponse, build_router};
-use juicebox::util::{MAX_ACTIVE_FILES_PER_IP, now_secs};
+use juicebox::handlers::{PasteResponse, UploadResponse, build_router};
+use juicebox::state::SlotTier;
+use juicebox::util::{
+    GUEST_MAX_BYTES, GUEST_TTL_SECS, MAX_ACTIVE_FILES_PER_IP, MAX_GUEST_FILES_PER_OWNER, now_secs,
+};
 use serde_json::Value;
 use std::net::SocketAddr;
 use tower::ServiceExt;
@@ -223,3 +226,102 @@ async fn test_forbidden_extension_uppercase() {
     let resp = app.clone().oneshot(upload).await.unwrap();
     assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
 }
+
+fn paste(body: &str, query: &str, ip: [u8; 4]) -> Request<Body> {
+    with_conn_ip(
+        Request::builder()
+            .method(Method::POST)
+            .uri(format!("/api/paste-binary?{query}"))
+            .header(header::CONTENT_TYPE, "text/plain")
+            .body(Body::from(body.to_string()))
+            .unwrap(),
+        ip,
+        1313,
+    )
+}
+
+#[tokio::test]
+async fn test_guest_tier_bypasses_full_slots_with_its_own_cap() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+    let ip = [13, 13, 13, 13];
+    for i in 0..MAX_ACTIVE_FILES_PER_IP {
+        let resp = app
+            .clone()
+            .oneshot(paste(&format!("seed {i}"), "ttl=7d", ip))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+    let resp = app
+        .clone()
+        .oneshot(paste("one more", "", ip))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    let owner_hash = state.hash_ip_to_string("13.13.13.13").unwrap();
+    for i in 0..MAX_GUEST_FILES_PER_OWNER {
+        let resp = app
+            .clone()
+            .oneshot(paste(&format!("screenshot {i}"), "guest=1&ttl=7d", ip))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let pasted: PasteResponse = serde_json::from_slice(&body).unwrap();
+        assert!(pasted.expires <= now_secs() + GUEST_TTL_SECS);
+        assert!(state.owners.get(&pasted.file).unwrap().guest);
+    }
+    assert_eq!(
+        state.active_file_count(&owner_hash, now_secs()),
+        MAX_ACTIVE_FILES_PER_IP
+    );
+    assert_eq!(
+        state.remaining_slots_in(&owner_hash, SlotTier::Guest, now_secs()),
+        0
+    );
+
+    let resp = app
+        .clone()
+        .oneshot(paste("too many", "guest=1", ip))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let err: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(err["code"], "guest_limit");
+}
+
+#[tokio::test]
+async fn test_guest_upload_rejects_large_files() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone()).layer(axum::extract::DefaultBodyLimit::max(
+        2 * GUEST_MAX_BYTES as usize,
+    ));
+    let boundary = "----GuestBoundary";
+    let content = "G".repeat(GUEST_MAX_BYTES as usize + 1);
+    let body = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"guest\"\r\n\r\n1\r\n\
+         --{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"big.txt\"\r\n\
+         Content-Type: text/plain\r\n\r\n{content}\r\n--{boundary}--\r\n"
+    );
+    let upload = with_conn_ip(
+        Request::builder()
+            .method(Method::POST)
+            .uri("/upload")
+            .header(
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(Body::from(body))
+            .unwrap(),
+        [15, 15, 15, 15],
+        1515,
+    );
+    let resp = app.clone().oneshot(upload).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let err: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(err["code"], "guest_too_large");
+}
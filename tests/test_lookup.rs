@@ -0,0 +1,141 @@
+mod common;
+
+use axum::Router;
+use axum::body::{Body, Bytes, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode, header};
+use juicebox::handlers::{LookupChallengeResponse, LookupVerifyResponse, build_router};
+use juicebox::state::FileMeta;
+use juicebox::util::now_secs;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use tower::ServiceExt;
+
+async fn post(app: &Router, uri: &str, body: Value, ip: [u8; 4]) -> (StatusCode, Bytes) {
+    let mut req = Request::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from((ip, 5000))));
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    (
+        status,
+        to_bytes(resp.into_body(), usize::MAX).await.unwrap(),
+    )
+}
+
+async fn challenge(app: &Router, hash: &str, size: usize, ip: [u8; 4]) -> LookupChallengeResponse {
+    let (status, body) = post(
+        app,
+        "/api/v1/files/lookup",
+        json!({ "hash": hash, "size": size }),
+        ip,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    serde_json::from_slice(&body).unwrap()
+}
+
+fn prove(content: &[u8], c: &LookupChallengeResponse) -> String {
+    let nonce: Vec<u8> = (0..c.nonce.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&c.nonce[i..i + 2], 16).unwrap())
+        .collect();
+    let range = c.offset as usize..(c.offset + c.length) as usize;
+    let mut hasher = Sha256::new();
+    hasher.update(&nonce);
+    hasher.update(&content[range]);
+    format!("{:x}", hasher.finalize())
+}
+
+async fn verify(app: &Router, challenge: &str, proof: &str, ip: [u8; 4]) -> (StatusCode, Bytes) {
+    post(
+        app,
+        "/api/v1/files/lookup/verify",
+        json!({ "challenge": challenge, "proof": proof }),
+        ip,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_lookup_returns_link_only_with_possession_proof() {
+    let (state, _tmp) = common::setup_test_app();
+    let content: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+    let hash = format!("{:x}", Sha256::digest(&content));
+    std::fs::write(state.upload_dir.join("known.bin"), &content).unwrap();
+    state.insert_owner(
+        "known.bin".into(),
+        FileMeta {
+            owner_hash: common::hash_fixture_ip("10.9.9.9"),
+            expires: now_secs() + 600,
+            original: "known.bin".into(),
+            original_display: String::new(),
+            created: now_secs(),
+            hash: hash.clone(),
+            max_downloads: None,
+            downloads: 0,
+            private: false,
+            ttl_shortened_from: None,
+            guest: false,
+            network_class: None,
+            size: 0,
+        },
+    );
+    let app = build_router(state);
+    let me = [127, 0, 0, 1];
+
+    let c = challenge(&app, &hash, content.len(), me).await;
+    assert_eq!(c.length, 64 * 1024);
+    assert!(c.offset + c.length <= content.len() as u64);
+    let (status, body) = verify(&app, &c.challenge, &prove(&content, &c), me).await;
+    assert_eq!(status, StatusCode::OK);
+    let found: LookupVerifyResponse = serde_json::from_slice(&body).unwrap();
+    assert!(found.found);
+    assert_eq!(found.file.as_deref(), Some("known.bin"));
+    assert!(found.url.unwrap().ends_with("known.bin"));
+
+    // Knowing only the hash is not enough.
+    let c = challenge(&app, &hash, content.len(), me).await;
+    let (_, body) = verify(&app, &c.challenge, &"0".repeat(64), me).await;
+    let denied: LookupVerifyResponse = serde_json::from_slice(&body).unwrap();
+    assert!(!denied.found && denied.file.is_none());
+
+    // Unknown content gets the same kind of challenge and the same answer.
+    let other = vec![7u8; 1000];
+    let other_hash = format!("{:x}", Sha256::digest(&other));
+    let c = challenge(&app, &other_hash, other.len(), me).await;
+    assert_eq!((c.offset, c.length), (0, 1000));
+    let (status, unknown) = verify(&app, &c.challenge, &prove(&other, &c), me).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(unknown, body);
+
+    // Challenges are bound to the caller and cannot be edited.
+    let c = challenge(&app, &hash, content.len(), me).await;
+    let proof = prove(&content, &c);
+    let (status, _) = verify(&app, &c.challenge, &proof, [10, 0, 0, 2]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let mut tampered = c.challenge.clone();
+    tampered.replace_range(0..1, if tampered.starts_with('A') { "B" } else { "A" });
+    let (status, _) = verify(&app, &tampered, &proof, me).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_lookup_challenge_validates_input() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state);
+    let me = [127, 0, 0, 1];
+    for body in [
+        json!({ "hash": "not-a-hash", "size": 10 }),
+        json!({ "hash": "a".repeat(64), "size": 0 }),
+    ] {
+        let (status, _) = post(&app, "/api/v1/files/lookup", body, me).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
@@ -3,11 +3,14 @@ mod common;
 use axum::Router;
 use axum::body::{Body, to_bytes};
 use axum::extract::ConnectInfo;
-use axum::http::header::{CACHE_CONTROL, CONTENT_TYPE, EXPIRES};
+use axum::http::header::{CACHE_CONTROL, CONTENT_TYPE, EXPIRES, RETRY_AFTER};
 use axum::http::{HeaderValue, Request, StatusCode};
 use axum::response::Response;
 use axum::routing::get;
 use juicebox::handlers::{add_cache_headers, add_security_headers, ban_gate};
+use juicebox::rate_limit::{
+    RATE_LIMIT_LIMIT, RATE_LIMIT_REMAINING, RATE_LIMIT_RESET, RateLimitLayer,
+};
 use juicebox::state::{BanSubject, IpBan, TelemetryState};
 use std::net::SocketAddr;
 use std::sync::Arc;
@@ -89,6 +92,8 @@ async fn test_security_headers_injected_and_csp_includes_connect_origin() {
         profiles_sample_rate: 0.0,
         error_sample_rate: 0.0,
         trace_propagation_targets: vec!["^/".to_string()],
+        respect_privacy_signals: true,
+        ignored_routes: vec!["/healthz".to_string(), "/css/*".to_string()],
     });
 
     let app = Router::new()
@@ -204,6 +209,7 @@ async fn test_ban_gate_blocks_banned_ip_and_bypasses_static_assets() {
             label: Some("test-ban".to_string()),
             reason: "testing".to_string(),
             time: 0,
+            expires: None,
         })
         .await;
 
@@ -278,6 +284,7 @@ async fn test_ban_gate_falls_back_when_template_missing() {
             label: None,
             reason: "<b>bad".to_string(),
             time: 0,
+            expires: None,
         })
         .await;
 
@@ -326,3 +333,42 @@ async fn test_ban_gate_falls_back_when_template_missing() {
         "fallback body should include short hash label"
     );
 }
+
+#[tokio::test]
+async fn test_rate_limit_headers_appear_near_and_at_the_limit() {
+    let app = Router::new()
+        .route("/hello", get(|| async { "hi" }))
+        .layer(RateLimitLayer::new(20, 1));
+    let get_hello = || {
+        with_conn_ip(
+            Request::builder()
+                .uri("/hello")
+                .body(Body::empty())
+                .unwrap(),
+            [198, 51, 100, 40],
+            5000,
+        )
+    };
+
+    // Plenty left: no headers.
+    let resp = app.clone().oneshot(get_hello()).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.headers().get(RATE_LIMIT_REMAINING).is_none());
+
+    for _ in 0..17 {
+        app.clone().oneshot(get_hello()).await.unwrap();
+    }
+    // A tenth of the bucket or less left counts as nearly limited.
+    let resp = app.clone().oneshot(get_hello()).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers()[RATE_LIMIT_LIMIT], "20");
+    assert_eq!(resp.headers()[RATE_LIMIT_REMAINING], "1");
+    assert!(resp.headers().get(RETRY_AFTER).is_none());
+
+    app.clone().oneshot(get_hello()).await.unwrap();
+    let resp = app.clone().oneshot(get_hello()).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(resp.headers()[RATE_LIMIT_REMAINING], "0");
+    assert_eq!(resp.headers()[RETRY_AFTER], "1");
+    assert_eq!(resp.headers()[RATE_LIMIT_RESET], "20");
+}
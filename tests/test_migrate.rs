@@ -0,0 +1,354 @@
+use juicebox::migrate::{
+    assign_report_ids, load_admin_sessions_with_migration, load_bans_with_migration,
+    load_owners_with_migration, load_reports_with_migration,
+};
+use juicebox::state::{
+    BanSubject, FileMeta, IpBan, KvStore, MemoryStore, ReportRecord, ReportStatus,
+};
+use juicebox::util::{IpVersion, hash_ip_string, hash_network_from_cidr, ttl_to_duration};
+use std::path::{Path, PathBuf};
+
+const SECRET: &[u8] = b"legacy-migration-secret";
+const NOW: u64 = 1_700_050_000;
+const HASH_A: &str = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+const HASH_B: &str = "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/legacy")
+        .join(name)
+}
+
+fn store() -> MemoryStore {
+    MemoryStore::new("migrate".into())
+}
+
+fn ip_hash(ip: &str) -> String {
+    hash_ip_string(SECRET, ip).unwrap().1
+}
+
+fn net_hash(cidr: &str) -> (IpVersion, u8, String) {
+    hash_network_from_cidr(SECRET, cidr).unwrap()
+}
+
+#[tokio::test]
+async fn test_owners_from_plain_address_map() {
+    let (owners, migrated) =
+        load_owners_with_migration(&fixture("owners_v0.json"), SECRET, &store(), NOW)
+            .await
+            .unwrap();
+    assert!(migrated);
+    assert_eq!(owners.len(), 3);
+    let cat = &owners["cat.png"];
+    assert_eq!(cat.owner_hash, ip_hash("203.0.113.5"));
+    assert_eq!(cat.expires, NOW + ttl_to_duration("3d").as_secs());
+    assert_eq!(cat.created, NOW);
+    assert!(cat.original.is_empty() && cat.hash.is_empty());
+    assert_eq!(cat.size, 0);
+    // Values that are already hashes, or not addresses at all, are kept.
+    assert_eq!(owners["notes.txt"].owner_hash, HASH_A);
+    assert_eq!(owners["odd.bin"].owner_hash, "not-an-ip");
+}
+
+#[tokio::test]
+async fn test_owners_from_metadata_map_hash_raw_addresses() {
+    let (owners, migrated) =
+        load_owners_with_migration(&fixture("owners_v1.json"), SECRET, &store(), NOW)
+            .await
+            .unwrap();
+    assert!(migrated);
+    let cat = &owners["cat.png"];
+    assert_eq!(cat.owner_hash, ip_hash("203.0.113.5"));
+    assert_eq!(cat.expires, 1_700_086_400);
+    assert_eq!(cat.created, 1_700_000_000);
+    assert_eq!(cat.original, "cat.png");
+    assert_eq!(cat.downloads, 3);
+    let notes = &owners["notes.txt"];
+    assert_eq!(notes.owner_hash, HASH_A);
+    assert_eq!(notes.hash, "deadbeef");
+    assert_eq!(notes.max_downloads, Some(5));
+    assert!(!notes.private && !notes.guest);
+}
+
+#[tokio::test]
+async fn test_owners_prefer_the_store_over_the_file() {
+    let kv = store();
+    let meta = FileMeta {
+        owner_hash: HASH_B.into(),
+        expires: NOW + 60,
+        original: "stored.txt".into(),
+        original_display: String::new(),
+        created: NOW,
+        hash: String::new(),
+        max_downloads: None,
+        downloads: 0,
+        private: false,
+        ttl_shortened_from: None,
+        guest: false,
+        network_class: None,
+        size: 9,
+    };
+    kv.replace_hash(
+        "owners",
+        &[
+            ("stored.txt".into(), serde_json::to_string(&meta).unwrap()),
+            ("broken.txt".into(), "{".into()),
+        ],
+    )
+    .await
+    .unwrap();
+    let (owners, migrated) =
+        load_owners_with_migration(&fixture("owners_v1.json"), SECRET, &kv, NOW)
+            .await
+            .unwrap();
+    assert!(!migrated);
+    assert_eq!(owners.len(), 1);
+    assert_eq!(owners["stored.txt"].size, 9);
+}
+
+#[tokio::test]
+async fn test_missing_empty_and_unreadable_files_load_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let empty = dir.path().join("empty.json");
+    std::fs::write(&empty, b"").unwrap();
+    for path in [
+        dir.path().join("missing.json"),
+        empty,
+        fixture("garbage.json"),
+    ] {
+        let (owners, migrated) = load_owners_with_migration(&path, SECRET, &store(), NOW)
+            .await
+            .unwrap();
+        assert!(owners.is_empty() && !migrated, "{path:?}");
+        let (reports, migrated) = load_reports_with_migration(&path, SECRET, &store())
+            .await
+            .unwrap();
+        assert!(reports.is_empty() && !migrated, "{path:?}");
+        let (bans, migrated) = load_bans_with_migration(&path, SECRET, &store())
+            .await
+            .unwrap();
+        assert!(bans.is_empty() && !migrated, "{path:?}");
+    }
+    let (sessions, migrated) =
+        load_admin_sessions_with_migration(&fixture("garbage.json"), &store())
+            .await
+            .unwrap();
+    assert!(sessions.is_empty() && !migrated);
+}
+
+#[tokio::test]
+async fn test_reports_without_details_use_the_legacy_shape() {
+    let (reports, migrated) =
+        load_reports_with_migration(&fixture("reports_v0.json"), SECRET, &store())
+            .await
+            .unwrap();
+    assert!(migrated);
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[0].reporter_hash, ip_hash("198.51.100.20"));
+    assert_eq!(reports[0].details, "");
+    assert_eq!(reports[0].time, 1_700_000_100);
+    assert_eq!(reports[1].reporter_hash, HASH_B);
+    assert_eq!(reports[1].reason, "malware");
+    for report in &reports {
+        assert!(!report.id.is_empty());
+        assert_eq!(report.status, ReportStatus::Open);
+        assert!(report.resolution.is_none());
+    }
+    assert_ne!(reports[0].id, reports[1].id);
+}
+
+#[tokio::test]
+async fn test_reports_keep_ids_and_statuses_they_already_have() {
+    let (reports, migrated) =
+        load_reports_with_migration(&fixture("reports_v1.json"), SECRET, &store())
+            .await
+            .unwrap();
+    assert!(migrated);
+    assert_eq!(reports[0].reporter_hash, ip_hash("198.51.100.20"));
+    assert_eq!(reports[0].details, "ads everywhere");
+    assert!(!reports[0].id.is_empty());
+    assert_eq!(reports[1].id, "r1");
+    assert_eq!(reports[1].status, ReportStatus::Resolved);
+    assert_eq!(reports[1].resolution.as_deref(), Some("removed"));
+}
+
+#[tokio::test]
+async fn test_stored_reports_only_gain_missing_ids() {
+    let kv = store();
+    let report = |id: &str| ReportRecord {
+        id: id.into(),
+        file: "a.txt".into(),
+        reason: "spam".into(),
+        details: String::new(),
+        reporter_hash: HASH_A.into(),
+        time: NOW,
+        status: ReportStatus::Open,
+        resolution: None,
+        status_changed_at: None,
+    };
+    let encode = |r: &ReportRecord| serde_json::to_string(r).unwrap();
+    kv.replace_list("reports", &[encode(&report("kept")), "[".into()])
+        .await
+        .unwrap();
+    let (reports, migrated) = load_reports_with_migration(&fixture("reports_v0.json"), SECRET, &kv)
+        .await
+        .unwrap();
+    assert!(!migrated);
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].id, "kept");
+
+    kv.replace_list("reports", &[encode(&report(""))])
+        .await
+        .unwrap();
+    let (reports, migrated) = load_reports_with_migration(&fixture("reports_v0.json"), SECRET, &kv)
+        .await
+        .unwrap();
+    assert!(migrated);
+    assert!(!reports[0].id.is_empty());
+
+    let mut reports = vec![report("x"), report("")];
+    assert!(assign_report_ids(&mut reports));
+    assert_eq!(reports[0].id, "x");
+    assert!(!reports[1].id.is_empty());
+    assert!(!assign_report_ids(&mut reports));
+}
+
+fn subjects(bans: &[IpBan]) -> Vec<BanSubject> {
+    bans.iter().map(|b| b.subject.clone()).collect()
+}
+
+#[tokio::test]
+async fn test_bans_from_legacy_subjects() {
+    let (bans, migrated) = load_bans_with_migration(&fixture("bans_v0.json"), SECRET, &store())
+        .await
+        .unwrap();
+    assert!(migrated);
+    let (v4, p24, net24) = net_hash("192.0.2.0/24");
+    let (v6, p48, net48) = net_hash("2001:db8::1/48");
+    let (_, p16, net16) = net_hash("198.51.100.0/16");
+    assert_eq!(
+        subjects(&bans),
+        [
+            BanSubject::Exact {
+                hash: ip_hash("192.0.2.10")
+            },
+            BanSubject::Exact {
+                hash: HASH_A.into()
+            },
+            BanSubject::Network {
+                hash: net24,
+                prefix: p24,
+                version: v4,
+            },
+            BanSubject::Network {
+                hash: net48,
+                prefix: p48,
+                version: v6,
+            },
+            BanSubject::Network {
+                hash: net16,
+                prefix: p16,
+                version: IpVersion::V4,
+            },
+            BanSubject::Network {
+                hash: HASH_B.into(),
+                prefix: 64,
+                version: IpVersion::V6,
+            },
+            BanSubject::Network {
+                hash: String::new(),
+                prefix: 32,
+                version: IpVersion::V4,
+            },
+        ]
+    );
+    assert_eq!(bans[1].label.as_deref(), Some("known"));
+    assert_eq!(bans[2].reason, "botnet");
+    assert_eq!(bans[6].time, 1_700_000_306);
+}
+
+#[tokio::test]
+async fn test_bans_in_current_shape_hash_raw_addresses() {
+    let (bans, migrated) = load_bans_with_migration(&fixture("bans_v1.json"), SECRET, &store())
+        .await
+        .unwrap();
+    assert!(migrated);
+    let (v4, p24, net24) = net_hash("192.0.2.0/24");
+    assert_eq!(
+        subjects(&bans),
+        [
+            BanSubject::Exact {
+                hash: ip_hash("192.0.2.10")
+            },
+            BanSubject::Exact {
+                hash: HASH_A.into()
+            },
+            BanSubject::Network {
+                hash: net24,
+                prefix: p24,
+                version: v4,
+            },
+            BanSubject::Network {
+                hash: HASH_B.into(),
+                prefix: 64,
+                version: IpVersion::V6,
+            },
+        ]
+    );
+    assert_eq!(bans[0].label.as_deref(), Some("192.0.2.10"));
+}
+
+#[tokio::test]
+async fn test_stored_bans_win_over_the_file() {
+    let kv = store();
+    let ban = IpBan {
+        subject: BanSubject::Exact {
+            hash: HASH_B.into(),
+        },
+        label: None,
+        reason: "stored".into(),
+        time: NOW,
+        expires: None,
+    };
+    kv.replace_hash(
+        "bans",
+        &[(HASH_B.into(), serde_json::to_string(&ban).unwrap())],
+    )
+    .await
+    .unwrap();
+    let (bans, migrated) = load_bans_with_migration(&fixture("bans_v0.json"), SECRET, &kv)
+        .await
+        .unwrap();
+    assert!(!migrated);
+    assert_eq!(bans.len(), 1);
+    assert_eq!(bans[0].reason, "stored");
+}
+
+#[tokio::test]
+async fn test_admin_sessions_from_file_and_store() {
+    let (sessions, migrated) =
+        load_admin_sessions_with_migration(&fixture("admin_sessions.json"), &store())
+            .await
+            .unwrap();
+    assert!(migrated);
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(sessions["token-two"], 1_700_007_200);
+
+    let kv = store();
+    kv.replace_hash(
+        "admin_sessions",
+        &[
+            ("stored".into(), "1700009000".into()),
+            ("bad".into(), "soon".into()),
+        ],
+    )
+    .await
+    .unwrap();
+    let (sessions, migrated) =
+        load_admin_sessions_with_migration(&fixture("admin_sessions.json"), &kv)
+            .await
+            .unwrap();
+    assert!(!migrated);
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions["stored"], 1_700_009_000);
+}
@@ -61,6 +61,8 @@ async fn chunk_init_rejects_empty_and_too_large() {
         ttl: None,
         chunk_size: Some(64_000),
         hash: None,
+        max_downloads: None,
+        private: false,
     };
     let req = with_conn_ip(
         Request::builder()
@@ -85,6 +87,8 @@ async fn chunk_init_rejects_empty_and_too_large() {
         ttl: None,
         chunk_size: Some(64_000),
         hash: None,
+        max_downloads: None,
+        private: false,
     };
     let req2 = with_conn_ip(
         Request::builder()
@@ -128,6 +132,8 @@ async fn chunk_part_and_status_errors_for_missing_session_and_wrong_owner() {
         ttl: None,
         chunk_size: Some(64_000),
         hash: None,
+        max_downloads: None,
+        private: false,
     };
     let init = with_conn_ip(
         Request::builder()
@@ -197,6 +203,8 @@ async fn chunk_part_index_and_length_validation_and_incomplete_completion() {
         ttl: None,
         chunk_size: Some(64_000),
         hash: None,
+        max_downloads: None,
+        private: false,
     };
     let init = with_conn_ip(
         Request::builder()
@@ -290,6 +298,8 @@ async fn chunk_complete_hash_mismatch_and_cancel_flow() {
         ttl: None,
         chunk_size: Some(70_000),
         hash: None,
+        max_downloads: None,
+        private: false,
     };
     let init = with_conn_ip(
         Request::builder()
@@ -369,6 +379,8 @@ async fn chunk_complete_hash_mismatch_and_cancel_flow() {
         ttl: None,
         chunk_size: Some(64_000),
         hash: None,
+        max_downloads: None,
+        private: false,
     };
     let init2 = with_conn_ip(
         Request::builder()
@@ -0,0 +1,135 @@
+mod common;
+
+use axum::Router;
+use axum::body::{Body, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode, header};
+use juicebox::handlers::{PasteResponse, build_router};
+use juicebox::network_class::{NetworkClass, NetworkListConfig, NetworkLists};
+use juicebox::quarantine::SOURCE_NETWORK;
+use juicebox::rate_limit::RateLimitLayer;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const TOR_LIST: &str = "# exits\n203.0.113.5\n";
+const HOSTING_LIST: &str = "198.51.100.0/24 # datacenter\n2001:db8::/32\n";
+
+fn paste(name: &str, ip: [u8; 4]) -> Request<Body> {
+    let mut req = Request::builder()
+        .method(Method::POST)
+        .uri(format!("/api/paste-binary?name={name}"))
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(Body::from(format!("pasted {name}")))
+        .unwrap();
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from((ip, 9100))));
+    req
+}
+
+async fn send(app: &Router, req: Request<Body>) -> (StatusCode, Vec<u8>) {
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (status, body.to_vec())
+}
+
+#[test]
+fn test_network_lists_classify_addresses() {
+    let lists = NetworkLists::default();
+    let ip = |raw: &str| raw.parse().unwrap();
+    assert_eq!(lists.classify(ip("203.0.113.5")), NetworkClass::Residential);
+
+    lists.replace(Some(TOR_LIST), Some(HOSTING_LIST));
+    assert_eq!(lists.sizes(), (1, 2));
+    assert_eq!(lists.classify(ip("203.0.113.5")), NetworkClass::Tor);
+    assert_eq!(lists.classify(ip("198.51.100.200")), NetworkClass::Hosting);
+    assert_eq!(lists.classify(ip("2001:db8::7")), NetworkClass::Hosting);
+    assert_eq!(lists.classify(ip("192.0.2.1")), NetworkClass::Residential);
+
+    // A list that failed to load keeps its previous contents.
+    lists.replace(None, Some(""));
+    assert_eq!(lists.classify(ip("203.0.113.5")), NetworkClass::Tor);
+    assert_eq!(
+        lists.classify(ip("198.51.100.200")),
+        NetworkClass::Residential
+    );
+    assert_eq!(NetworkClass::parse("VPN"), Some(NetworkClass::Hosting));
+}
+
+#[tokio::test]
+async fn test_refresh_reads_local_lists() {
+    let (mut state, tmp) = common::setup_test_app();
+    let tor = tmp.path().join("tor.txt");
+    let hosting = tmp.path().join("hosting.txt");
+    std::fs::write(&tor, TOR_LIST).unwrap();
+    std::fs::write(&hosting, HOSTING_LIST).unwrap();
+    state.networks = Arc::new(NetworkLists::new(NetworkListConfig {
+        tor_source: Some(tor.display().to_string()),
+        hosting_source: Some(hosting.display().to_string()),
+        nonresidential_rate_cost: 1,
+        ..Default::default()
+    }));
+
+    state.refresh_network_lists(&reqwest::Client::new()).await;
+    assert_eq!(state.network_class("203.0.113.5"), NetworkClass::Tor);
+    assert_eq!(state.network_class("198.51.100.9"), NetworkClass::Hosting);
+    assert_eq!(state.network_class("not-an-ip"), NetworkClass::Residential);
+
+    std::fs::remove_file(&hosting).unwrap();
+    state.refresh_network_lists(&reqwest::Client::new()).await;
+    assert_eq!(state.networks.sizes(), (1, 2));
+}
+
+#[tokio::test]
+async fn test_uploads_record_network_class_and_premoderate() {
+    let (mut state, _tmp) = common::setup_test_app();
+    let networks = NetworkLists::new(NetworkListConfig {
+        nonresidential_rate_cost: 1,
+        premoderate: HashSet::from([NetworkClass::Tor]),
+        ..Default::default()
+    });
+    networks.replace(Some(TOR_LIST), Some(HOSTING_LIST));
+    state.networks = Arc::new(networks);
+    let app = build_router(state.clone());
+
+    let (status, body) = send(&app, paste("dc.txt", [198, 51, 100, 20])).await;
+    assert_eq!(status, StatusCode::OK);
+    let pasted: PasteResponse = serde_json::from_slice(&body).unwrap();
+    let meta = state.owners.get(&pasted.file).unwrap().clone();
+    assert_eq!(meta.network_class, Some(NetworkClass::Hosting));
+
+    let (status, _) = send(&app, paste("home.txt", [192, 0, 2, 20])).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(&app, paste("onion.txt", [203, 0, 113, 5])).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let held = state.quarantine.records().await;
+    assert_eq!(held.len(), 1);
+    assert_eq!(held[0].source, SOURCE_NETWORK);
+    assert_eq!(held[0].verdict, "tor");
+}
+
+#[tokio::test]
+async fn test_nonresidential_requests_cost_more_rate_tokens() {
+    let (mut state, _tmp) = common::setup_test_app();
+    let networks = NetworkLists::new(NetworkListConfig {
+        nonresidential_rate_cost: 2,
+        ..Default::default()
+    });
+    networks.replace(None, Some(HOSTING_LIST));
+    state.networks = Arc::new(networks);
+    let app = build_router(state.clone())
+        .layer(RateLimitLayer::new(2, 0).with_network_lists(state.networks.clone()));
+
+    let (status, _) = send(&app, paste("a.txt", [198, 51, 100, 30])).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, paste("b.txt", [198, 51, 100, 30])).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    for name in ["c.txt", "d.txt"] {
+        let (status, _) = send(&app, paste(name, [192, 0, 2, 30])).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
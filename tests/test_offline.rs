@@ -0,0 +1,205 @@
+mod common;
+
+use axum::extract::ConnectInfo;
+use axum::{
+    body::{Body, to_bytes},
+    http::{Method, Request, StatusCode, header},
+};
+use juicebox::handlers::build_router;
+use juicebox::testing::AppStateBuilder;
+use serde_json::{Value, json};
+use std::net::SocketAddr;
+use tower::ServiceExt;
+
+fn with_conn(mut req: Request<Body>) -> Request<Body> {
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+    req
+}
+
+async fn json_body(response: axum::response::Response) -> Value {
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn test_service_worker_uses_fingerprinted_assets() {
+    let (state, _temp_dir) = common::setup_test_app();
+    let dist = state.static_dir.join("dist");
+    std::fs::create_dir_all(&dist).unwrap();
+    std::fs::write(
+        dist.join("manifest.json"),
+        r#"{"css":"/dist/app.3f2a9c.css","app":"/dist/app.3f2a9c.js"}"#,
+    )
+    .unwrap();
+    let app = build_router(state);
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/offline-manifest.json")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let manifest = json_body(response).await;
+    let version = manifest["version"].as_str().unwrap().to_string();
+    assert!(
+        manifest["assets"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("/dist/app.3f2a9c.css"))
+    );
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/sw.js")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get(header::CONTENT_TYPE).unwrap(),
+        "application/javascript; charset=utf-8"
+    );
+    assert_eq!(
+        response.headers().get("service-worker-allowed").unwrap(),
+        "/"
+    );
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let script = String::from_utf8(body.to_vec()).unwrap();
+    assert!(script.contains(&format!("const VERSION = \"{version}\";")));
+    assert!(script.contains("/dist/app.3f2a9c.css"));
+}
+
+#[tokio::test]
+async fn test_queued_upload_registration_and_release() {
+    let (state, _temp_dir) = common::setup_test_app();
+    let app = build_router(state.clone());
+
+    let payload = json!({
+        "items": [
+            {"name": "notes.txt", "size": 12},
+            {"name": "setup.exe", "size": 12},
+            {"name": "empty.txt", "size": 0},
+        ]
+    });
+    let response = app
+        .clone()
+        .oneshot(with_conn(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/simple/queue")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(payload.to_string()))
+                .unwrap(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let ticket = json_body(response).await;
+    assert_eq!(ticket["accepted"], json!([0]));
+    let reasons: Vec<&str> = ticket["rejected"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["reason"].as_str().unwrap())
+        .collect();
+    assert_eq!(reasons, vec!["bad_filetype", "empty"]);
+    let id = ticket["id"].as_str().unwrap().to_string();
+    assert_eq!(state.queued_uploads.get(&id).unwrap().items.len(), 1);
+
+    let response = app
+        .clone()
+        .oneshot(with_conn(
+            Request::builder()
+                .method(Method::DELETE)
+                .uri(format!("/simple/queue/{id}"))
+                .body(Body::empty())
+                .unwrap(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert!(state.queued_uploads.is_empty());
+
+    let response = app
+        .oneshot(with_conn(
+            Request::builder()
+                .method(Method::DELETE)
+                .uri(format!("/simple/queue/{id}"))
+                .body(Body::empty())
+                .unwrap(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_queued_uploads_are_bounded_per_client() {
+    let test_app = AppStateBuilder::new()
+        .manual_clock(juicebox::util::now_secs())
+        .build();
+    let state = test_app.state.clone();
+    let app = build_router(state.clone());
+    let register = |count: usize| {
+        let items: Vec<Value> = (0..count)
+            .map(|i| json!({"name": format!("photo{i}.jpg"), "size": 12}))
+            .collect();
+        with_conn(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/simple/queue")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json!({ "items": items }).to_string()))
+                .unwrap(),
+        )
+    };
+
+    let first = json_body(app.clone().oneshot(register(6)).await.unwrap()).await;
+    assert_eq!(first["accepted"].as_array().unwrap().len(), 6);
+    state.clock.advance(1);
+    // The first registration still holds six of the ten slots.
+    let second = json_body(app.clone().oneshot(register(6)).await.unwrap()).await;
+    assert_eq!(second["accepted"].as_array().unwrap().len(), 4);
+    assert_eq!(second["rejected"][0]["reason"], "file_limit");
+
+    for _ in 0..2 {
+        state.clock.advance(1);
+        let ticket = json_body(app.clone().oneshot(register(1)).await.unwrap()).await;
+        assert!(ticket["accepted"].as_array().unwrap().is_empty());
+    }
+    assert_eq!(state.queued_uploads.len(), 4);
+    state.clock.advance(1);
+    // A fifth registration replaces the oldest one and gets its slots.
+    let fifth = json_body(app.clone().oneshot(register(6)).await.unwrap()).await;
+    assert_eq!(fifth["accepted"].as_array().unwrap().len(), 6);
+    assert_eq!(state.queued_uploads.len(), 4);
+    assert!(
+        !state
+            .queued_uploads
+            .contains_key(first["id"].as_str().unwrap())
+    );
+
+    let long_name = json!({"items": [{"name": "a".repeat(2000), "size": 12}]});
+    let response = app
+        .oneshot(with_conn(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/simple/queue")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(long_name.to_string()))
+                .unwrap(),
+        ))
+        .await
+        .unwrap();
+    let ticket = json_body(response).await;
+    assert_eq!(ticket["rejected"][0]["reason"], "name_too_long");
+}
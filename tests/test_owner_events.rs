@@ -0,0 +1,150 @@
+use futures_util::StreamExt;
+use juicebox::cors::{AllowedOrigins, CorsConfig};
+use juicebox::owner_events::OwnerEvent;
+use juicebox::server::Server;
+use juicebox::testing::AppStateBuilder;
+use serde_json::Value;
+use std::time::Duration;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+
+async fn next_event<S>(socket: &mut S) -> Value
+where
+    S: StreamExt<Item = Result<Message, WsError>> + Unpin,
+{
+    loop {
+        let msg = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .expect("event within five seconds")
+            .expect("socket open")
+            .expect("valid frame");
+        if let Message::Text(text) = msg {
+            return serde_json::from_str(&text).unwrap();
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_owner_socket_pushes_events_for_the_callers_files() {
+    let app = AppStateBuilder::new()
+        .with_file("soon.txt", b"soon", "127.0.0.1", 60)
+        .with_file("later.txt", b"later", "127.0.0.1", 86_400)
+        .build();
+    let state = app.state.clone();
+    let (secret, token) = state.api_tokens.issue(None);
+    for file in ["soon.txt", "later.txt"] {
+        let mut meta = state.owners.get(file).unwrap().clone();
+        meta.owner_hash = token.owner_hash.clone();
+        state.insert_owner(file.to_string(), meta);
+    }
+    let running = Server::new(state.clone())
+        .listen("127.0.0.1:0".parse().unwrap())
+        .handle_signals(false)
+        .start()
+        .await
+        .unwrap();
+    let base = running.local_addr();
+
+    let (mut socket, _) = connect_async(format!("ws://{base}/ws?token={secret}"))
+        .await
+        .unwrap();
+    let expiring = next_event(&mut socket).await;
+    assert_eq!(expiring["event"], "file.expiring");
+    assert_eq!(expiring["file"], "soon.txt");
+
+    // Someone else's notice is not delivered; ours is.
+    state.owner_events.publish(
+        "someone-else",
+        OwnerEvent::AssemblyCompleted {
+            session_id: "s1".into(),
+            file: "theirs.bin".into(),
+        },
+    );
+    let resp = reqwest::Client::new()
+        .post(format!("http://{base}/report"))
+        .form(&[("file", "later.txt"), ("reason", "spam")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 204);
+    let reported = next_event(&mut socket).await;
+    assert_eq!(reported["event"], "file.reported");
+    assert_eq!(reported["file"], "later.txt");
+    assert_eq!(reported["reason"], "spam");
+
+    drop(socket);
+    running.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_owner_socket_refuses_unknown_tokens() {
+    let app = AppStateBuilder::new().build();
+    let running = Server::new(app.state.clone())
+        .listen("127.0.0.1:0".parse().unwrap())
+        .handle_signals(false)
+        .start()
+        .await
+        .unwrap();
+    let base = running.local_addr();
+
+    match connect_async(format!("ws://{base}/ws?token=jbx_made_up")).await {
+        Err(WsError::Http(resp)) => assert_eq!(resp.status(), 401),
+        other => panic!("expected a 401 handshake, got {other:?}"),
+    }
+    // Being on the same IP as the files' owner is not enough.
+    match connect_async(format!("ws://{base}/ws")).await {
+        Err(WsError::Http(resp)) => assert_eq!(resp.status(), 401),
+        other => panic!("expected a 401 handshake, got {other:?}"),
+    }
+    let (secret, _) = app.state.api_tokens.issue(None);
+    assert!(
+        connect_async(format!("ws://{base}/ws?token={secret}"))
+            .await
+            .is_ok()
+    );
+
+    running.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_owner_socket_refuses_other_origins() {
+    let app = AppStateBuilder::new()
+        .cors(CorsConfig {
+            upload: AllowedOrigins::parse(["https://app.example"]),
+            ..CorsConfig::default()
+        })
+        .build();
+    let running = Server::new(app.state.clone())
+        .listen("127.0.0.1:0".parse().unwrap())
+        .handle_signals(false)
+        .start()
+        .await
+        .unwrap();
+    let base = running.local_addr();
+    let (secret, _) = app.state.api_tokens.issue(None);
+    let handshake = |origin: &str| {
+        let mut req = format!("ws://{base}/ws?token={secret}")
+            .into_client_request()
+            .unwrap();
+        req.headers_mut().insert("origin", origin.parse().unwrap());
+        req
+    };
+
+    match connect_async(handshake("https://evil.example")).await {
+        Err(WsError::Http(resp)) => assert_eq!(resp.status(), 403),
+        other => panic!("expected a 403 handshake, got {other:?}"),
+    }
+    assert!(
+        connect_async(handshake("https://app.example"))
+            .await
+            .is_ok()
+    );
+    assert!(
+        connect_async(handshake(&format!("http://{base}")))
+            .await
+            .is_ok()
+    );
+
+    running.shutdown().await.unwrap();
+}
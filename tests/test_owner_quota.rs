@@ -0,0 +1,129 @@
+mod common;
+
+use axum::Router;
+use axum::body::{Body, Bytes, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode, header};
+use juicebox::handlers::build_router;
+use juicebox::state::FileMeta;
+use juicebox::storage_pressure::{StorageLimits, StorageWatchdog};
+use juicebox::util::now_secs;
+use serde_json::{Value, json};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const BOUNDARY: &str = "----JuiceboxQuotaBoundary";
+const ALICE: [u8; 4] = [198, 51, 100, 50];
+const BOB: [u8; 4] = [198, 51, 100, 51];
+
+async fn send(app: &Router, mut req: Request<Body>, ip: [u8; 4]) -> (StatusCode, Value) {
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from((ip, 5100))));
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+fn paste(name: &str, body: &str) -> Request<Body> {
+    Request::builder()
+        .method(Method::POST)
+        .uri(format!("/api/paste-binary?name={name}"))
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn upload(name: &str, contents: &str) -> Request<Body> {
+    let body = format!(
+        "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{name}\"\r\n\
+         Content-Type: text/plain\r\n\r\n{contents}\r\n--{BOUNDARY}--\r\n"
+    );
+    Request::builder()
+        .method(Method::POST)
+        .uri("/upload")
+        .header(
+            header::CONTENT_TYPE,
+            format!("multipart/form-data; boundary={BOUNDARY}"),
+        )
+        .body(Body::from(body))
+        .unwrap()
+}
+
+fn chunk_init(size: u64) -> Request<Body> {
+    Request::builder()
+        .method(Method::POST)
+        .uri("/chunk/init")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            json!({"filename": "big.bin", "size": size}).to_string(),
+        ))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_owner_quota_counts_files_and_open_sessions() {
+    let (mut state, _tmp) = common::setup_test_app();
+    state.storage = Arc::new(StorageWatchdog::new(StorageLimits {
+        owner_bytes: 20,
+        ..StorageLimits::default()
+    }));
+    let app = build_router(state.clone());
+
+    let (status, body) = send(&app, paste("a.txt", "twelve bytes"), ALICE).await;
+    assert_eq!(status, StatusCode::OK);
+    let file = body["file"].as_str().unwrap().to_string();
+    assert_eq!(state.owners.get(&file).unwrap().size, 12);
+
+    let (status, body) = send(&app, paste("b.txt", "twelve more!"), ALICE).await;
+    assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE);
+    assert_eq!(body["code"], "owner_quota");
+    let (status, body) = send(&app, upload("c.txt", "still too much"), ALICE).await;
+    assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE);
+    assert_eq!(body["code"], "owner_quota");
+    let (status, body) = send(&app, chunk_init(9), ALICE).await;
+    assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE);
+    assert_eq!(body["code"], "owner_quota");
+
+    // Another owner has their own allowance, and an open session reserves it.
+    let (status, _) = send(&app, chunk_init(15), BOB).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = send(&app, upload("d.txt", "six..."), BOB).await;
+    assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE);
+    assert_eq!(body["code"], "owner_quota");
+    let (status, _) = send(&app, upload("e.txt", "five!"), BOB).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_storage_sweep_records_missing_sizes() {
+    let (state, _tmp) = common::setup_test_app();
+    let now = now_secs();
+    state
+        .file_store
+        .write("legacy.txt", Bytes::from_static(b"0123456789"))
+        .await
+        .unwrap();
+    state.owners.insert(
+        "legacy.txt".into(),
+        FileMeta {
+            owner_hash: common::hash_fixture_ip("198.51.100.50"),
+            expires: now + 3600,
+            original: "legacy.txt".into(),
+            original_display: String::new(),
+            created: now,
+            hash: "legacy".into(),
+            max_downloads: None,
+            downloads: 0,
+            private: false,
+            ttl_shortened_from: None,
+            guest: false,
+            network_class: None,
+            size: 0,
+        },
+    );
+    state.enforce_storage_limits().await;
+    assert_eq!(state.owners.get("legacy.txt").unwrap().size, 10);
+    let owner = common::hash_fixture_ip("198.51.100.50");
+    assert_eq!(state.owner_stored_bytes(&owner, now), 10);
+}
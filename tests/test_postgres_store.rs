@@ -0,0 +1,88 @@
+use juicebox::state::{KvStore, PostgresStore};
+
+/// Runs only when `JUICEBOX_TEST_POSTGRES_URL` points at a scratch database.
+async fn store(prefix: &str) -> Option<PostgresStore> {
+    let url = std::env::var("JUICEBOX_TEST_POSTGRES_URL").ok()?;
+    Some(
+        PostgresStore::connect(&url, format!("test-{prefix}-{}", std::process::id()), 4)
+            .await
+            .unwrap(),
+    )
+}
+
+fn pairs(items: &[(&str, &str)]) -> Vec<(String, String)> {
+    items
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+fn sorted(mut entries: Vec<(String, String)>) -> Vec<(String, String)> {
+    entries.sort();
+    entries
+}
+
+#[tokio::test]
+async fn postgres_store_applies_hash_and_list_replacements() {
+    let Some(store) = store("replace").await else {
+        return;
+    };
+    assert_eq!(store.backend_name(), "postgres");
+    store
+        .replace_hash("owners", &pairs(&[("a", "1"), ("b", "2")]))
+        .await
+        .unwrap();
+    store
+        .replace_hash("owners", &pairs(&[("b", "20"), ("c", "3")]))
+        .await
+        .unwrap();
+    assert_eq!(
+        sorted(store.load_hash("owners").await.unwrap()),
+        pairs(&[("b", "20"), ("c", "3")])
+    );
+    store.replace_hash("owners", &[]).await.unwrap();
+    assert!(store.load_hash("owners").await.unwrap().is_empty());
+
+    let reports: Vec<String> = ["z", "a", "m"].map(String::from).to_vec();
+    store.replace_list("reports", &reports).await.unwrap();
+    assert_eq!(store.load_list("reports").await.unwrap(), reports);
+    store.replace_list("reports", &[]).await.unwrap();
+    assert!(store.load_list("reports").await.unwrap().is_empty());
+
+    store.append_list("reports", "one").await.unwrap();
+    store.append_list("reports", "two").await.unwrap();
+    assert_eq!(store.load_list("reports").await.unwrap(), ["one", "two"]);
+    store
+        .replace_list("reports", &["kept".to_string()])
+        .await
+        .unwrap();
+    store.append_list("reports", "three").await.unwrap();
+    assert_eq!(store.load_list("reports").await.unwrap(), ["kept", "three"]);
+}
+
+#[tokio::test]
+async fn postgres_store_is_shared_between_replicas() {
+    let Some(first) = store("shared").await else {
+        return;
+    };
+    let url = std::env::var("JUICEBOX_TEST_POSTGRES_URL").unwrap();
+    let second = PostgresStore::connect(&url, format!("test-shared-{}", std::process::id()), 2)
+        .await
+        .unwrap();
+
+    let one = pairs(&[("k", "1")]);
+    let two = pairs(&[("k", "2")]);
+    let three = pairs(&[("k", "3"), ("j", "4")]);
+    let (a, b, c) = tokio::join!(
+        first.replace_hash("bans", &one),
+        second.replace_hash("bans", &two),
+        first.replace_hash("bans", &three),
+    );
+    a.unwrap();
+    b.unwrap();
+    c.unwrap();
+    let bans = second.load_hash("bans").await.unwrap();
+    assert!(!bans.is_empty());
+    assert_eq!(sorted(first.load_hash("bans").await.unwrap()), sorted(bans));
+    first.replace_hash("bans", &[]).await.unwrap();
+}
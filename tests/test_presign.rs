@@ -0,0 +1,145 @@
+mod common;
+
+use axum::Router;
+use axum::body::{Body, Bytes, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{HeaderMap, Method, Request, StatusCode, header};
+use juicebox::handlers::{PresignResponse, build_router, sign_download};
+use juicebox::state::FileMeta;
+use juicebox::util::now_secs;
+use std::net::SocketAddr;
+use tower::ServiceExt;
+
+async fn send(app: &Router, req: Request<Body>) -> (StatusCode, HeaderMap, Bytes) {
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let headers = resp.headers().clone();
+    (
+        status,
+        headers,
+        to_bytes(resp.into_body(), usize::MAX).await.unwrap(),
+    )
+}
+
+fn get(uri: &str) -> Request<Body> {
+    Request::builder().uri(uri).body(Body::empty()).unwrap()
+}
+
+fn presign(name: &str, body: &str, ip: [u8; 4]) -> Request<Body> {
+    let mut req = Request::builder()
+        .method(Method::POST)
+        .uri(format!("/api/v1/files/{name}/presign"))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from((ip, 6000))));
+    req
+}
+
+#[tokio::test]
+async fn test_private_file_only_served_through_presigned_link() {
+    let (state, _tmp) = common::setup_test_app();
+    std::fs::write(state.upload_dir.join("secret.txt"), b"top secret").unwrap();
+    let file_expires = now_secs() + 600;
+    state.insert_owner(
+        "secret.txt".into(),
+        FileMeta {
+            owner_hash: common::hash_fixture_ip("127.0.0.1"),
+            expires: file_expires,
+            original: "secret.txt".into(),
+            original_display: String::new(),
+            created: now_secs(),
+            hash: String::new(),
+            max_downloads: None,
+            downloads: 0,
+            private: true,
+            ttl_shortened_from: None,
+            guest: false,
+            network_class: None,
+            size: 0,
+        },
+    );
+    let app = build_router(state.clone());
+
+    let (status, _, _) = send(&app, get("/f/secret.txt")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _, _) = send(&app, get("/d/secret.txt")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _, _) = send(&app, presign("secret.txt", "{}", [10, 0, 0, 9])).await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "only the owner can presign");
+    let (status, _, _) = send(
+        &app,
+        presign("secret.txt", r#"{"expires_in": 0}"#, [127, 0, 0, 1]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Links never outlive the file.
+    let (status, _, body) = send(
+        &app,
+        presign("secret.txt", r#"{"expires_in": 86400}"#, [127, 0, 0, 1]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let minted: PresignResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(minted.expires, file_expires);
+    assert!(
+        minted.url.starts_with("/f/secret.txt?exp="),
+        "{}",
+        minted.url
+    );
+
+    let (status, headers, body) = send(&app, get(&minted.url)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.as_ref(), b"top secret");
+    let cache = headers[header::CACHE_CONTROL].to_str().unwrap();
+    assert!(cache.starts_with("private, max-age="), "{cache}");
+
+    let tampered = minted.url.replace(
+        &format!("exp={}", minted.expires),
+        &format!("exp={}", minted.expires + 1),
+    );
+    let (status, _, _) = send(&app, get(&tampered)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let lapsed = now_secs() - 1;
+    let sig = sign_download(&state, "secret.txt", lapsed);
+    let (status, _, _) = send(&app, get(&format!("/f/secret.txt?exp={lapsed}&sig={sig}"))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_presigned_link_survives_key_rotation() {
+    use juicebox::handlers::{PresignQuery, Presigned};
+    use juicebox::testing::AppStateBuilder;
+
+    let before = AppStateBuilder::new()
+        .signing_keys("k1:first-signing-secret")
+        .build();
+    let after = AppStateBuilder::new()
+        .signing_keys("k2:second-signing-secret,k1:first-signing-secret")
+        .build();
+    let retired = AppStateBuilder::new()
+        .signing_keys("k2:second-signing-secret")
+        .build();
+    let now = now_secs();
+    let exp = now + 60;
+    let query = PresignQuery {
+        exp: Some(exp),
+        sig: Some(sign_download(&before.state, "a.txt", exp)),
+    };
+    assert_eq!(
+        query.check(&after.state, "a.txt", now),
+        Presigned::Valid(exp)
+    );
+    assert_eq!(
+        query.check(&retired.state, "a.txt", now),
+        Presigned::Invalid
+    );
+    assert!(
+        sign_download(&after.state, "a.txt", exp).starts_with("k2."),
+        "new links are signed with the first key"
+    );
+}
@@ -0,0 +1,275 @@
+mod common;
+
+use axum::extract::ConnectInfo;
+use axum::{
+    body::{Body, to_bytes},
+    http::{Method, Request, StatusCode, header},
+};
+use hyper::body::Bytes;
+use juicebox::handlers::{UploadResponse, build_router, sign_download};
+use juicebox::quarantine::SOURCE_HASH_LIST;
+use juicebox::state::FileMeta;
+use juicebox::util::now_secs;
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use tower::ServiceExt;
+
+const BAD_CONTENT: &str = "definitely malware";
+
+fn create_multipart_body(file_content: &str, file_name: &str) -> (String, Body) {
+    let boundary = "----QuarantineBoundary";
+    let mut body = Vec::new();
+    body.extend_from_slice(format!("--{boundary}\r\n").as_bytes());
+    body.extend_from_slice(
+        format!("Content-Disposition: form-data; name=\"file\"; filename=\"{file_name}\"\r\n")
+            .as_bytes(),
+    );
+    body.extend_from_slice(b"Content-Type: text/plain\r\n\r\n");
+    body.extend_from_slice(file_content.as_bytes());
+    body.extend_from_slice(b"\r\n");
+    body.extend_from_slice(format!("--{boundary}--\r\n").as_bytes());
+    (
+        format!("multipart/form-data; boundary={boundary}"),
+        Body::from(Bytes::from(body)),
+    )
+}
+
+fn with_conn(mut req: Request<Body>) -> Request<Body> {
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+    req
+}
+
+fn admin_post(token: &str, form: &str) -> Request<Body> {
+    Request::builder()
+        .method(Method::POST)
+        .uri("/admin/quarantine")
+        .header(header::COOKIE, format!("adm={token}"))
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(form.to_string()))
+        .unwrap()
+}
+
+async fn upload_flagged(app: &axum::Router, state: &juicebox::state::AppState) -> String {
+    let hash = format!("{:x}", Sha256::digest(BAD_CONTENT.as_bytes()));
+    state.quarantine.add_blocklisted_hashes([hash]).await;
+    let (content_type, body) = create_multipart_body(BAD_CONTENT, "invoice.txt");
+    let response = app
+        .clone()
+        .oneshot(with_conn(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/upload")
+                .header(header::CONTENT_TYPE, content_type)
+                .body(body)
+                .unwrap(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let upload: UploadResponse = serde_json::from_slice(&body).unwrap();
+    assert!(upload.files.is_empty());
+    let records = state.quarantine.records().await;
+    assert_eq!(records.len(), 1);
+    records[0].file.clone()
+}
+
+#[tokio::test]
+async fn test_blocklisted_upload_is_quarantined_and_released() {
+    let (state, _temp_dir) = common::setup_test_app();
+    let app = build_router(state.clone());
+    let token = "quarantine-admin".to_string();
+    state.create_admin_session(token.clone()).await;
+
+    let file = upload_flagged(&app, &state).await;
+    assert!(!state.owners.contains_key(&file));
+    assert!(!state.upload_dir.join(&file).exists());
+    assert!(state.quarantine.dir().join(&file).exists());
+    let response = app
+        .clone()
+        .oneshot(with_conn(
+            Request::builder()
+                .uri(format!("/f/{file}"))
+                .body(Body::empty())
+                .unwrap(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    tokio::fs::write(
+        state.static_dir.join("admin_quarantine.html"),
+        "<table>{{QUARANTINE_ROWS}}</table>",
+    )
+    .await
+    .unwrap();
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/admin/quarantine")
+                .header(header::COOKIE, format!("adm={token}"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let page = String::from_utf8(body.to_vec()).unwrap();
+    assert!(page.contains(&file));
+    assert!(page.contains("blocklisted hash"));
+
+    let response = app
+        .clone()
+        .oneshot(admin_post(&token, &format!("file={file}&action=release")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(state.quarantine.get(&file).await.is_some());
+
+    let response = app
+        .clone()
+        .oneshot(admin_post(
+            &token,
+            &format!("file={file}&action=release&confirm=1"),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert!(state.quarantine.get(&file).await.is_none());
+    let response = app
+        .oneshot(with_conn(
+            Request::builder()
+                .uri(format!("/f/{file}"))
+                .body(Body::empty())
+                .unwrap(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_quarantine_ban_owner_removes_file() {
+    let (state, _temp_dir) = common::setup_test_app();
+    let app = build_router(state.clone());
+    let token = "quarantine-admin".to_string();
+    state.create_admin_session(token.clone()).await;
+
+    let file = upload_flagged(&app, &state).await;
+    let response = app
+        .clone()
+        .oneshot(admin_post(&token, "file=unknown.txt&action=delete"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = app
+        .oneshot(admin_post(&token, &format!("file={file}&action=ban")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert!(state.quarantine.records().await.is_empty());
+    assert!(!state.quarantine.dir().join(&file).exists());
+    assert!(state.is_banned("127.0.0.1").await);
+}
+
+#[tokio::test]
+async fn test_released_private_file_still_needs_a_signature() {
+    let (state, _temp_dir) = common::setup_test_app();
+    std::fs::write(state.upload_dir.join("secret.txt"), b"top secret").unwrap();
+    state.insert_owner(
+        "secret.txt".into(),
+        FileMeta {
+            owner_hash: common::hash_fixture_ip("127.0.0.1"),
+            expires: now_secs() + 600,
+            original: "secret.txt".into(),
+            original_display: String::new(),
+            created: now_secs(),
+            hash: String::new(),
+            max_downloads: Some(3),
+            downloads: 1,
+            private: true,
+            ttl_shortened_from: None,
+            guest: true,
+            network_class: None,
+            size: 10,
+        },
+    );
+    state
+        .quarantine_file("secret.txt", SOURCE_HASH_LIST, "blocklisted hash", "")
+        .await
+        .unwrap();
+    state.release_quarantined("secret.txt").await.unwrap();
+
+    let meta = state.owners.get("secret.txt").unwrap().clone();
+    assert!(meta.private);
+    assert!(meta.guest);
+    assert_eq!(meta.max_downloads, Some(3));
+    assert_eq!(meta.downloads, 1);
+    let app = build_router(state.clone());
+    let response = app
+        .clone()
+        .oneshot(with_conn(
+            Request::builder()
+                .uri("/f/secret.txt")
+                .body(Body::empty())
+                .unwrap(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let exp = now_secs() + 60;
+    let sig = sign_download(&state, "secret.txt", exp);
+    let response = app
+        .oneshot(with_conn(
+            Request::builder()
+                .uri(format!("/f/secret.txt?exp={exp}&sig={sig}"))
+                .body(Body::empty())
+                .unwrap(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_flagged_upload_is_discarded_when_quarantine_fails() {
+    let (state, _temp_dir) = common::setup_test_app();
+    let app = build_router(state.clone());
+    // A plain file where the quarantine directory should be makes every
+    // move into quarantine fail.
+    let dir = state.quarantine.dir().to_path_buf();
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::write(&dir, b"").unwrap();
+    let hash = format!("{:x}", Sha256::digest(BAD_CONTENT.as_bytes()));
+    state.quarantine.add_blocklisted_hashes([hash]).await;
+
+    let (content_type, body) = create_multipart_body(BAD_CONTENT, "invoice.txt");
+    let response = app
+        .oneshot(with_conn(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/upload")
+                .header(header::CONTENT_TYPE, content_type)
+                .body(body)
+                .unwrap(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let upload: UploadResponse = serde_json::from_slice(&body).unwrap();
+    assert!(upload.files.is_empty());
+    assert!(state.quarantine.records().await.is_empty());
+    assert!(state.owners.is_empty());
+    assert_eq!(
+        std::fs::read_dir(state.upload_dir.as_ref())
+            .unwrap()
+            .count(),
+        0
+    );
+}
@@ -0,0 +1,124 @@
+use axum::Router;
+use axum::body::{Body, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode, header};
+use juicebox::handlers::build_router;
+use juicebox::reindex::{self, ReindexState};
+use juicebox::testing::AppStateBuilder;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tower::ServiceExt;
+
+async fn send(app: &Router, method: Method, cookie: Option<&str>) -> (StatusCode, Value) {
+    let mut req = Request::builder()
+        .method(method)
+        .uri("/api/admin/v1/reindex");
+    if let Some(cookie) = cookie {
+        req = req.header(header::COOKIE, cookie);
+    }
+    let mut req = req.body(Body::empty()).unwrap();
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([198, 51, 100, 91], 6501))));
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+/// Strip what the legacy owner map never recorded.
+fn make_legacy(state: &juicebox::state::AppState, name: &str, migrated_at: u64) {
+    let mut meta = state.owners.get_mut(name).unwrap();
+    meta.original.clear();
+    meta.hash.clear();
+    meta.size = 0;
+    meta.created = migrated_at;
+}
+
+#[tokio::test]
+async fn test_reindex_backfills_legacy_entries() {
+    let app = AppStateBuilder::new()
+        .with_file("legacy.txt", b"legacy body", "203.0.113.7", 3600)
+        .with_file("current.txt", b"current", "203.0.113.7", 3600)
+        .build();
+    let state = &app.state;
+    // Stamped with a migration time far in the future of the blob's mtime.
+    make_legacy(state, "legacy.txt", u64::MAX / 2);
+    let current = state.owners.get("current.txt").unwrap().created;
+
+    assert!(state.reindex.begin());
+    reindex::run(state.clone()).await;
+
+    let meta = state.owners.get("legacy.txt").unwrap().clone();
+    assert_eq!(meta.hash, format!("{:x}", Sha256::digest(b"legacy body")));
+    assert_eq!(meta.size, 11);
+    assert!(meta.created < u64::MAX / 2);
+    assert_eq!(state.owners.get("current.txt").unwrap().created, current);
+
+    let progress = state.reindex.progress();
+    assert_eq!(progress.state, ReindexState::Finished);
+    assert_eq!(progress.total, 1);
+    assert_eq!((progress.hashed, progress.sized, progress.dated), (1, 1, 1));
+    assert_eq!(progress.bytes_hashed, 11);
+    assert!(!state.reindex.is_running());
+}
+
+#[tokio::test]
+async fn test_reindex_counts_missing_bodies() {
+    let app = AppStateBuilder::new()
+        .with_file("gone.txt", b"gone", "203.0.113.7", 3600)
+        .build();
+    let state = &app.state;
+    make_legacy(state, "gone.txt", 1);
+    state.file_store.delete("gone.txt").await.unwrap();
+
+    assert!(state.reindex.begin());
+    reindex::run(state.clone()).await;
+    let progress = state.reindex.progress();
+    assert_eq!((progress.scanned, progress.missing), (1, 1));
+    assert!(state.owners.get("gone.txt").unwrap().hash.is_empty());
+}
+
+#[tokio::test]
+async fn test_reindex_endpoint_requires_admin_and_reports_progress() {
+    let app = AppStateBuilder::new()
+        .with_file("legacy.txt", b"legacy body", "203.0.113.7", 3600)
+        .build();
+    let state = app.state.clone();
+    make_legacy(&state, "legacy.txt", 1);
+    let router = build_router(state.clone());
+
+    let (status, _) = send(&router, Method::POST, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert!(!state.reindex.is_running());
+
+    state
+        .create_admin_session("reindex-admin".to_string())
+        .await;
+    let cookie = Some("adm=reindex-admin");
+    let (status, body) = send(&router, Method::GET, cookie).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["state"], "idle");
+
+    // A job that is already running is not started twice.
+    assert!(state.reindex.begin());
+    let (status, body) = send(&router, Method::POST, cookie).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["code"], "reindex_running");
+    reindex::run(state.clone()).await;
+
+    let (status, body) = send(&router, Method::POST, cookie).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(body["started"], true);
+    let mut body = Value::Null;
+    for _ in 0..100 {
+        body = send(&router, Method::GET, cookie).await.1;
+        if body["state"] == "finished" && !state.reindex.is_running() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(body["state"], "finished");
+    assert_eq!(body["scanned"], 0);
+}
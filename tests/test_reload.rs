@@ -0,0 +1,90 @@
+use juicebox::config::Config;
+use juicebox::rate_limit::RouteLimit;
+use juicebox::reload::{self, HotSettings, Reloader};
+use juicebox::util::{is_forbidden_extension, max_file_bytes};
+use std::collections::HashMap;
+
+#[test]
+fn test_hot_settings_resolve() {
+    let vars: HashMap<&str, &str> = [
+        ("JUICEBOX_FORBIDDEN_EXTENSIONS", " .EXE, iso ,,"),
+        ("TRUST_PROXY_HEADERS", "yes"),
+        ("TRUSTED_PROXY_CIDRS", "10.0.0.0/8, 192.168.0.0/16"),
+        ("MAX_FILE_SIZE", "1GB"),
+        ("JUICEBOX_RATE_LIMIT_BURST", "0"),
+        ("JUICEBOX_RATE_LIMIT_REFILL_PER_SEC", "7"),
+        ("JUICEBOX_RATE_LIMIT_IDLE_SECS", "600"),
+        (
+            "JUICEBOX_RATE_LIMIT_ROUTES",
+            "report=2/1, files=general, nope=1/1, auth=fast",
+        ),
+    ]
+    .into_iter()
+    .collect();
+    let settings = HotSettings::resolve(|name| vars.get(name).map(|v| v.to_string()));
+    assert_eq!(settings.forbidden_extensions, vec!["exe", "iso"]);
+    assert!(settings.is_forbidden_extension("ISO"));
+    assert!(!settings.is_forbidden_extension("bat"));
+    assert!(settings.allow_proxy_headers);
+    assert_eq!(
+        settings.trusted_proxies,
+        vec!["10.0.0.0/8", "192.168.0.0/16"]
+    );
+    assert_eq!(settings.max_file_bytes, 1024 * 1024 * 1024);
+    // Zero would block every client; keep the default instead.
+    assert_eq!(
+        settings.rate_limit_burst,
+        HotSettings::default().rate_limit_burst
+    );
+    assert_eq!(settings.rate_limit_refill_per_sec, 7);
+    assert_eq!(settings.rate_limit_idle_secs, 600);
+    // Unknown policies and unparseable limits are dropped.
+    assert_eq!(
+        settings.rate_limit_routes.into_iter().collect::<Vec<_>>(),
+        vec![
+            ("files".to_string(), RouteLimit::General),
+            (
+                "report".to_string(),
+                RouteLimit::Own {
+                    capacity: 2,
+                    refill_per_minute: 1
+                }
+            ),
+        ]
+    );
+
+    assert_eq!(HotSettings::resolve(|_| None), HotSettings::default());
+}
+
+// The settings channel is process-wide, so everything that publishes lives in
+// this one test.
+#[tokio::test]
+async fn test_published_settings_reach_consumers() {
+    let mut rx = reload::subscribe();
+    let config = Config::parse(
+        r#"
+[limits]
+max_file_size = "2MB"
+forbidden_extensions = ["iso"]
+rate_limit_burst = 5
+"#,
+    )
+    .unwrap();
+    let settings = Reloader::from_env().settings_for(&config);
+    assert_eq!(settings.max_file_bytes, 2 * 1024 * 1024);
+    assert_eq!(settings.rate_limit_burst, 5);
+
+    assert!(reload::publish(settings.clone()));
+    assert!(rx.has_changed().unwrap());
+    assert_eq!(**rx.borrow_and_update(), settings);
+    assert_eq!(max_file_bytes(), 2 * 1024 * 1024);
+    assert!(is_forbidden_extension("disk.iso"));
+    assert!(!is_forbidden_extension("setup.exe"));
+
+    // Publishing the same settings again is not a change.
+    assert!(!reload::publish(settings));
+    assert!(!rx.has_changed().unwrap());
+
+    reload::publish(HotSettings::default());
+    assert!(is_forbidden_extension("setup.exe"));
+}
@@ -180,3 +180,230 @@ async fn test_report_empty_reason() {
             || report_resp.status() == StatusCode::BAD_REQUEST
     );
 }
+
+#[tokio::test]
+async fn test_bulk_ban_from_reports_previews_then_bans() {
+    use juicebox::state::{BanSubject, FileMeta, IpBan, ReportRecord, ReportStatus};
+    use juicebox::util::now_secs;
+
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+    let now = now_secs();
+    let owner = |ip: &str| common::hash_fixture_ip(ip);
+    let hosted = |file: &str, ip: &str| {
+        state.insert_owner(
+            file.to_string(),
+            FileMeta {
+                owner_hash: owner(ip),
+                expires: now + 3600,
+                original: String::new(),
+                original_display: String::new(),
+                created: now,
+                hash: String::new(),
+                max_downloads: None,
+                downloads: 0,
+                private: false,
+                ttl_shortened_from: None,
+                guest: false,
+                network_class: None,
+                size: 0,
+            },
+        );
+    };
+    hosted("a1.bin", "198.51.100.1");
+    hosted("a2.bin", "198.51.100.1");
+    hosted("old.bin", "198.51.100.2");
+    hosted("spam.bin", "198.51.100.3");
+    hosted("known.bin", "198.51.100.4");
+    let report = |file: &str, reason: &str, age: u64| ReportRecord {
+        id: format!("{file}-{age}"),
+        file: file.to_string(),
+        reason: reason.to_string(),
+        details: String::new(),
+        reporter_hash: "reporter".to_string(),
+        time: now - age,
+        status: ReportStatus::Open,
+        resolution: None,
+        status_changed_at: None,
+    };
+    state.reports.write().await.extend([
+        report("a1.bin", "malware", 60),
+        report("a1.bin", "malware", 120),
+        report("a2.bin", "Malware", 600),
+        report("old.bin", "malware", 30 * 3600),
+        report("spam.bin", "spam", 60),
+        report("known.bin", "malware", 60),
+        report("unknown.bin", "malware", 60),
+    ]);
+    state
+        .add_ban(IpBan {
+            subject: BanSubject::Exact {
+                hash: owner("198.51.100.4"),
+            },
+            label: None,
+            reason: String::new(),
+            time: 0,
+            expires: None,
+        })
+        .await;
+    state.create_admin_session("bulk-admin".to_string()).await;
+    let post = |form: String| {
+        Request::builder()
+            .method(Method::POST)
+            .uri("/admin/reports/ban")
+            .header(header::COOKIE, "adm=bulk-admin")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(form))
+            .unwrap()
+    };
+
+    let resp = app
+        .clone()
+        .oneshot(post("reason=malware&hours=0".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    // The preview lists who would be hit and bans nobody.
+    let resp = app
+        .clone()
+        .oneshot(post("reason=malware&hours=24".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let html = String::from_utf8(body.to_vec()).unwrap();
+    let a = owner("198.51.100.1");
+    assert!(
+        html.contains(&format!("title=\"{a}\""))
+            && html.contains("<td>2</td><td>3</td><td>will be banned</td>"),
+        "{html}"
+    );
+    assert!(html.contains("already banned"));
+    assert!(!html.contains(&owner("198.51.100.2")));
+    assert!(!html.contains(&owner("198.51.100.3")));
+    assert!(html.contains(&format!("name=owners value=\"{a}\"")));
+    assert!(html.contains("Ban 1 owner</button>"));
+    assert_eq!(state.bans.read().await.len(), 1);
+
+    // Confirming bans only accepted owners that still match.
+    let form = format!(
+        "reason=malware&hours=24&owners={a},{}",
+        owner("198.51.100.2")
+    );
+    let resp = app.clone().oneshot(post(form)).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+    let bans = state.bans.read().await;
+    assert_eq!(bans.len(), 2);
+    assert!(bans.iter().any(|b| b.subject.key() == a));
+    assert!(
+        !bans
+            .iter()
+            .any(|b| b.subject.key() == owner("198.51.100.2"))
+    );
+}
+
+#[tokio::test]
+async fn test_report_status_workflow() {
+    use juicebox::state::{ReportRecord, ReportStatus};
+
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+    let report = |id: &str, file: &str| ReportRecord {
+        id: id.to_string(),
+        file: file.to_string(),
+        reason: "spam".to_string(),
+        details: String::new(),
+        reporter_hash: "reporter".to_string(),
+        time: 1,
+        status: ReportStatus::Open,
+        resolution: None,
+        status_changed_at: None,
+    };
+    state
+        .reports
+        .write()
+        .await
+        .extend([report("r1", "first.bin"), report("r2", "second.bin")]);
+    state.create_admin_session("status-admin".to_string()).await;
+    let set_status = |form: &str| {
+        Request::builder()
+            .method(Method::POST)
+            .uri("/admin/reports/status")
+            .header(header::COOKIE, "adm=status-admin")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(form.to_string()))
+            .unwrap()
+    };
+    let view = |query: &str| {
+        Request::builder()
+            .uri(format!("/admin/reports{query}"))
+            .header(header::COOKIE, "adm=status-admin")
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let unauthenticated = Request::builder()
+        .method(Method::POST)
+        .uri("/admin/reports/status")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from("id=r1&status=reviewing"))
+        .unwrap();
+    let resp = app.clone().oneshot(unauthenticated).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let resp = app
+        .clone()
+        .oneshot(set_status("id=r1&status=reviewing"))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+    let resp = app
+        .clone()
+        .oneshot(set_status("id=r1&status=resolved&note=removed+the+file"))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+    {
+        let reports = state.reports.read().await;
+        assert_eq!(reports[0].status, ReportStatus::Resolved);
+        assert_eq!(reports[0].resolution.as_deref(), Some("removed the file"));
+        assert!(reports[0].status_changed_at.is_some());
+        assert_eq!(reports[1].status, ReportStatus::Open);
+    }
+
+    // Closed reports can only be reopened.
+    let resp = app
+        .clone()
+        .oneshot(set_status("id=r1&status=reviewing"))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let resp = app
+        .clone()
+        .oneshot(set_status("id=r1&status=closed"))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let resp = app
+        .clone()
+        .oneshot(set_status("id=missing&status=open"))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let resp = app.clone().oneshot(view("?status=open")).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let html = String::from_utf8(body.to_vec()).unwrap();
+    assert!(html.contains("second.bin") && !html.contains("first.bin"));
+
+    let resp = app.clone().oneshot(view("?status=resolved")).await.unwrap();
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let html = String::from_utf8(body.to_vec()).unwrap();
+    assert!(html.contains("first.bin") && html.contains("removed the file"));
+    assert!(!html.contains("second.bin"));
+
+    let resp = app.clone().oneshot(view("?status=bogus")).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
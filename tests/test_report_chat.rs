@@ -0,0 +1,117 @@
+use axum::Router;
+use axum::routing::post;
+use juicebox::handlers::ReportRecordEmail;
+use juicebox::report_chat::{ChatKind, ReportChat};
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+fn report(redacted: bool) -> ReportRecordEmail {
+    ReportRecordEmail {
+        file: "abc123".to_string(),
+        reason: "malware".to_string(),
+        details: "looks like <script> & friends".to_string(),
+        reporter_hash: "rep-hash".to_string(),
+        time: 1_700_000_000,
+        iso_time: "2023-11-14T22:13:20Z".to_string(),
+        owner_hash: "owner-hash".to_string(),
+        original_name: "invoice.pdf.exe".to_string(),
+        name_warning: Some("double extension".to_string()),
+        expires: 1_700_003_600,
+        size: 2048,
+        report_index: 0,
+        total_reports_for_file: 1,
+        total_reports: 4,
+        identifiers_redacted: redacted,
+    }
+}
+
+#[test]
+fn test_kind_is_guessed_from_the_url() {
+    let discord = ReportChat::new("https://discord.com/api/webhooks/1/token", None).unwrap();
+    assert_eq!(discord.kind, ChatKind::Discord);
+    let slack = ReportChat::new("https://hooks.slack.com/services/T/B/X", None).unwrap();
+    assert_eq!(slack.kind, ChatKind::Slack);
+    let forced = ReportChat::new("https://chat.example/hook", Some("Discord")).unwrap();
+    assert_eq!(forced.kind, ChatKind::Discord);
+    assert!(ReportChat::new("ftp://discord.com/x", None).is_none());
+    assert!(ReportChat::new("", None).is_none());
+}
+
+#[test]
+fn test_discord_payload_links_to_admin_pages() {
+    let chat = ReportChat::new("https://discord.com/api/webhooks/1/token", None).unwrap();
+    let payload = chat.payload(&report(false));
+    assert_eq!(payload["allowed_mentions"]["parse"], serde_json::json!([]));
+    let embed = &payload["embeds"][0];
+    assert!(embed["url"].as_str().unwrap().ends_with("/f/abc123"));
+    let admin = embed["fields"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|f| f["name"] == "Admin")
+        .unwrap()["value"]
+        .as_str()
+        .unwrap();
+    assert!(admin.contains("/admin/reports"));
+    assert!(admin.contains("/admin/ban?ip=owner-hash"));
+
+    let redacted = chat.payload(&report(true));
+    assert!(!redacted.to_string().contains("/admin/ban"));
+}
+
+#[test]
+fn test_slack_payload_escapes_markup() {
+    let chat = ReportChat::new("https://hooks.slack.com/services/T/B/X", None).unwrap();
+    let payload = chat.payload(&report(false));
+    assert_eq!(payload["text"], "New report: abc123 (malware)");
+    let blocks = payload["blocks"].as_array().unwrap();
+    assert!(
+        blocks[2]["text"]["text"]
+            .as_str()
+            .unwrap()
+            .contains("&lt;script&gt; &amp; friends")
+    );
+    let buttons: Vec<&str> = blocks[3]["elements"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|b| b["text"]["text"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        buttons,
+        ["Open File", "Manage Files", "View Reports", "Ban Owner IP"]
+    );
+}
+
+#[tokio::test]
+async fn test_send_posts_the_payload() {
+    let (tx, mut rx) = mpsc::unbounded_channel::<Value>();
+    let app = Router::new().route(
+        "/hook",
+        post(move |axum::Json(body): axum::Json<Value>| {
+            let tx = tx.clone();
+            async move {
+                tx.send(body).unwrap();
+                "ok"
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let chat = ReportChat::new(&format!("http://{addr}/hook"), Some("slack")).unwrap();
+    chat.send(&reqwest::Client::new(), &report(false))
+        .await
+        .unwrap();
+    let body = rx.recv().await.unwrap();
+    assert_eq!(body["text"], "New report: abc123 (malware)");
+
+    let missing = ReportChat::new(&format!("http://{addr}/nope"), Some("slack")).unwrap();
+    assert!(
+        missing
+            .send(&reqwest::Client::new(), &report(false))
+            .await
+            .is_err()
+    );
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use axum::body::{Body, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode};
+use axum::middleware;
+use juicebox::handlers::build_router;
+use juicebox::request_id::{REQUEST_ID_HEADER, assign_request_id};
+use serde_json::Value;
+use std::net::SocketAddr;
+use tower::ServiceExt;
+
+fn empty_paste() -> Request<Body> {
+    let mut req = Request::builder()
+        .method(Method::POST)
+        .uri("/api/paste-binary")
+        .header(&REQUEST_ID_HEADER, "chosen-by-client")
+        .body(Body::empty())
+        .unwrap();
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([198, 51, 100, 80], 6400))));
+    req
+}
+
+#[tokio::test]
+async fn test_errors_carry_the_request_id_header_and_body_field() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state).layer(middleware::from_fn(assign_request_id));
+
+    let mut seen = Vec::new();
+    for _ in 0..2 {
+        let resp = app.clone().oneshot(empty_paste()).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let id = resp.headers()[&REQUEST_ID_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+        // Untrusted clients cannot choose their own ID.
+        assert_ne!(id, "chosen-by-client");
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "no_files");
+        assert_eq!(body["request_id"], id.as_str());
+        seen.push(id);
+    }
+    assert_ne!(seen[0], seen[1]);
+
+    let resp = app
+        .oneshot(
+            Request::builder()
+                .uri("/healthz")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.headers().contains_key(&REQUEST_ID_HEADER));
+}
@@ -0,0 +1,363 @@
+use async_trait::async_trait;
+use axum::Router;
+use axum::body::{Body, Bytes, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode, header};
+use hmac::{Hmac, Mac};
+use juicebox::content_scan::{ContentScanner, Rejection, ScanInput};
+use juicebox::handlers::{build_router, s3_secret_access_key};
+use juicebox::state::ApiToken;
+use juicebox::testing::AppStateBuilder;
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
+use tower::ServiceExt;
+
+/// 2023-11-14T22:13:20Z, the manual clock's start.
+const NOW: u64 = 1_700_000_000;
+const AMZ_DATE: &str = "20231114T221320Z";
+const HOST: &str = "juicebox.test";
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// A request signed the way AWS SDKs sign them, with `uri` already encoded
+/// and its query already in canonical order.
+fn signed(method: Method, uri: &str, body: &[u8], access_key: &str, secret: &str) -> Request<Body> {
+    let (path, query) = uri.split_once('?').unwrap_or((uri, ""));
+    let payload = hex(&Sha256::digest(body));
+    let canonical = format!(
+        "{method}\n{path}\n{query}\nhost:{HOST}\nx-amz-content-sha256:{payload}\nx-amz-date:{AMZ_DATE}\n\nhost;x-amz-content-sha256;x-amz-date\n{payload}"
+    );
+    let scope = format!("{}/us-east-1/s3/aws4_request", &AMZ_DATE[..8]);
+    let to_sign = format!(
+        "AWS4-HMAC-SHA256\n{AMZ_DATE}\n{scope}\n{}",
+        hex(&Sha256::digest(canonical.as_bytes()))
+    );
+    let mut key = hmac(format!("AWS4{secret}").as_bytes(), &AMZ_DATE[..8]);
+    for part in ["us-east-1", "s3", "aws4_request"] {
+        key = hmac(&key, part);
+    }
+    let signature = hex(&hmac(&key, &to_sign));
+    let mut req = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::HOST, HOST)
+        .header("x-amz-date", AMZ_DATE)
+        .header("x-amz-content-sha256", payload)
+        .header(
+            header::AUTHORIZATION,
+            format!(
+                "AWS4-HMAC-SHA256 Credential={access_key}/{scope}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={signature}"
+            ),
+        )
+        .body(Body::from(body.to_vec()))
+        .unwrap();
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([198, 51, 100, 20], 7000))));
+    req
+}
+
+async fn send(app: &Router, req: Request<Body>) -> (StatusCode, header::HeaderMap, Bytes) {
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let headers = resp.headers().clone();
+    (
+        status,
+        headers,
+        to_bytes(resp.into_body(), usize::MAX).await.unwrap(),
+    )
+}
+
+struct Client {
+    app: Router,
+    token: ApiToken,
+    secret: String,
+}
+
+impl Client {
+    fn request(&self, method: Method, uri: &str, body: &[u8]) -> Request<Body> {
+        signed(method, uri, body, &self.token.id, &self.secret)
+    }
+
+    fn object(&self, key: &str) -> String {
+        format!("/s3/{}/{key}", self.token.id)
+    }
+}
+
+fn client() -> (Client, juicebox::testing::TestApp) {
+    client_with(AppStateBuilder::new())
+}
+
+fn client_with(builder: AppStateBuilder) -> (Client, juicebox::testing::TestApp) {
+    let app = builder.manual_clock(NOW).build();
+    let (_, token) = app.state.api_tokens.issue(Some("backup".to_string()));
+    let secret = s3_secret_access_key(&app.state, &token.id);
+    let client = Client {
+        app: build_router(app.state.clone()),
+        token,
+        secret,
+    };
+    (client, app)
+}
+
+#[tokio::test]
+async fn test_s3_put_get_list_delete_round_trip() {
+    let (s3, app) = client();
+
+    let (status, headers, _) = send(
+        &s3.app,
+        s3.request(Method::PUT, &s3.object("notes/today.txt"), b"hello s3"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let etag = format!("\"{}\"", hex(&Sha256::digest(b"hello s3")));
+    assert_eq!(headers[header::ETAG], etag.as_str());
+    let stored = app.state.owners_snapshot();
+    let (_, meta) = &stored.files_for(&s3.token.owner_hash)[0];
+    assert_eq!(meta.original, "notes/today.txt");
+    assert!(meta.private);
+
+    let (status, _, _) = send(
+        &s3.app,
+        s3.request(Method::PUT, &s3.object("notes/today.txt"), b"hello again"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        app.state
+            .owners_snapshot()
+            .files_for(&s3.token.owner_hash)
+            .len(),
+        1
+    );
+    let (status, _, body) = send(
+        &s3.app,
+        s3.request(Method::GET, &s3.object("notes/today.txt"), b""),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(&body[..], b"hello again");
+
+    let mut ranged = s3.request(Method::GET, &s3.object("notes/today.txt"), b"");
+    ranged
+        .headers_mut()
+        .insert(header::RANGE, "bytes=6-".parse().unwrap());
+    let (status, headers, body) = send(&s3.app, ranged).await;
+    assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+    assert_eq!(headers[header::CONTENT_RANGE], "bytes 6-10/11");
+    assert_eq!(&body[..], b"again");
+
+    let (status, _, _) = send(
+        &s3.app,
+        s3.request(Method::PUT, &s3.object("top.txt"), b"x"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let list = format!("/s3/{}?delimiter=%2F&list-type=2", s3.token.id);
+    let (status, _, body) = send(&s3.app, s3.request(Method::GET, &list, b"")).await;
+    assert_eq!(status, StatusCode::OK);
+    let xml = String::from_utf8(body.to_vec()).unwrap();
+    assert!(xml.contains("<KeyCount>2</KeyCount>"));
+    assert!(xml.contains("<Contents><Key>top.txt</Key>"));
+    assert!(xml.contains("<CommonPrefixes><Prefix>notes/</Prefix></CommonPrefixes>"));
+
+    let page = format!("/s3/{}?list-type=2&max-keys=1", s3.token.id);
+    let (_, _, body) = send(&s3.app, s3.request(Method::GET, &page, b"")).await;
+    let xml = String::from_utf8(body.to_vec()).unwrap();
+    assert!(xml.contains("<IsTruncated>true</IsTruncated>"));
+    assert!(xml.contains("<Key>notes/today.txt</Key>"));
+    let next = xml
+        .split("<NextContinuationToken>")
+        .nth(1)
+        .and_then(|rest| rest.split('<').next())
+        .unwrap();
+    let page = format!(
+        "/s3/{}?continuation-token={next}&list-type=2&max-keys=1",
+        s3.token.id
+    );
+    let (_, _, body) = send(&s3.app, s3.request(Method::GET, &page, b"")).await;
+    let xml = String::from_utf8(body.to_vec()).unwrap();
+    assert!(xml.contains("<IsTruncated>false</IsTruncated>"));
+    assert!(xml.contains("<Key>top.txt</Key>"));
+
+    let (status, _, _) = send(
+        &s3.app,
+        s3.request(Method::DELETE, &s3.object("notes/today.txt"), b""),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _, body) = send(
+        &s3.app,
+        s3.request(Method::GET, &s3.object("notes/today.txt"), b""),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(String::from_utf8_lossy(&body).contains("<Code>NoSuchKey</Code>"));
+}
+
+#[tokio::test]
+async fn test_s3_rejects_bad_signatures_and_foreign_buckets() {
+    let (s3, app) = client();
+
+    let (status, _, body) = send(
+        &s3.app,
+        signed(Method::GET, &s3.object("a.txt"), b"", &s3.token.id, "wrong"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(String::from_utf8_lossy(&body).contains("SignatureDoesNotMatch"));
+
+    let (status, _, body) = send(
+        &s3.app,
+        signed(Method::GET, "/s3/nope/a.txt", b"", "nope", &s3.secret),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(String::from_utf8_lossy(&body).contains("InvalidAccessKeyId"));
+
+    let (_, other) = app.state.api_tokens.issue(None);
+    let (status, _, body) = send(
+        &s3.app,
+        s3.request(Method::GET, &format!("/s3/{}/a.txt", other.id), b""),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(String::from_utf8_lossy(&body).contains("NoSuchBucket"));
+
+    // The signature covers the declared payload hash, which must match the body.
+    let mut tampered = s3.request(Method::PUT, &s3.object("a.txt"), b"signed");
+    *tampered.body_mut() = Body::from("swapped");
+    let (status, _, body) = send(&s3.app, tampered).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(String::from_utf8_lossy(&body).contains("XAmzContentSHA256Mismatch"));
+
+    app.state.clock.advance(16 * 60);
+    let (status, _, body) = send(&s3.app, s3.request(Method::GET, &s3.object("a.txt"), b"")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(String::from_utf8_lossy(&body).contains("RequestTimeTooSkewed"));
+
+    app.state.api_tokens.revoke(&s3.token.id);
+    let (status, _, body) = send(&s3.app, s3.request(Method::GET, &s3.object("a.txt"), b"")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(String::from_utf8_lossy(&body).contains("InvalidAccessKeyId"));
+}
+
+/// Turns away anything containing `RULE-MATCH`, as a YARA rule might.
+struct RuleScanner;
+
+#[async_trait]
+impl ContentScanner for RuleScanner {
+    fn name(&self) -> &'static str {
+        "rule"
+    }
+
+    async fn scan(&self, input: &ScanInput<'_>) -> Result<(), Rejection> {
+        let mut body = Vec::new();
+        input
+            .content
+            .open()
+            .await
+            .unwrap()
+            .read_to_end(&mut body)
+            .await
+            .unwrap();
+        if body.windows(10).any(|w| w == b"RULE-MATCH") {
+            return Err(Rejection {
+                scanner: self.name(),
+                status: StatusCode::UNPROCESSABLE_ENTITY,
+                code: "rule_match",
+                message: "upload matched a scanning rule",
+            });
+        }
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_s3_puts_go_through_the_content_scanners() {
+    let (s3, app) = client_with(AppStateBuilder::new().content_scanner(Arc::new(RuleScanner)));
+
+    let (status, _, body) = send(
+        &s3.app,
+        s3.request(Method::PUT, &s3.object("flagged.txt"), b"xx RULE-MATCH xx"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(String::from_utf8_lossy(&body).contains("InvalidArgument"));
+    // A Windows executable, whatever it is called.
+    let (status, _, _) = send(
+        &s3.app,
+        s3.request(
+            Method::PUT,
+            &s3.object("tool.txt"),
+            b"MZ\x90\x00\x03\x00\x00\x00",
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(app.state.owners.is_empty());
+
+    let (status, _, _) = send(
+        &s3.app,
+        s3.request(Method::PUT, &s3.object("clean.txt"), b"fine"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(app.state.owners.len(), 1);
+}
+
+fn spooled_files(app: &juicebox::testing::TestApp) -> usize {
+    std::fs::read_dir(app.state.upload_dir.as_path())
+        .unwrap()
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .unwrap()
+                .path()
+                .extension()
+                .is_some_and(|ext| ext == "part")
+        })
+        .count()
+}
+
+#[tokio::test]
+async fn test_s3_put_streams_the_body_to_disk() {
+    let (s3, app) = client();
+    let frames: [&'static [u8]; 3] = [b"first ", b"second ", b"third"];
+
+    let mut req = s3.request(Method::PUT, &s3.object("streamed.txt"), &frames.concat());
+    *req.body_mut() = Body::from_stream(futures_util::stream::iter(
+        frames.map(|frame| Ok::<_, std::io::Error>(Bytes::from_static(frame))),
+    ));
+    let (status, headers, _) = send(&s3.app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        headers[header::ETAG],
+        format!("\"{}\"", hex(&Sha256::digest(frames.concat())))
+    );
+    let (status, _, body) = send(
+        &s3.app,
+        s3.request(Method::GET, &s3.object("streamed.txt"), b""),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(&body[..], b"first second third");
+
+    // Signed for one payload, sent with another.
+    let mut req = s3.request(Method::PUT, &s3.object("swapped.txt"), b"promised");
+    *req.body_mut() = Body::from("delivered");
+    let (status, _, body) = send(&s3.app, req).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(String::from_utf8_lossy(&body).contains("XAmzContentSHA256Mismatch"));
+    assert_eq!(app.state.owners.len(), 1);
+    assert_eq!(spooled_files(&app), 0);
+}
@@ -1,9 +1,14 @@
+#![allow(clippy::await_holding_lock)]
+
 mod common;
 
-use axum::extract::{ConnectInfo, State};
+use axum::extract::{ConnectInfo, Query, State};
 use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
 use http_body_util::BodyExt;
+use juicebox::audit::AuditAction;
+use juicebox::handlers::admin_list::AdminListQuery;
 use juicebox::handlers::{admin_files_handler, visitor_debug_handler};
+use juicebox::rate_limit::VISITOR_DEBUG_RATE_LIMIT_BURST;
 use juicebox::state::FileMeta;
 use juicebox::util::{
     extract_client_ip, headers_trusted, now_secs, set_trusted_proxy_config_for_tests,
@@ -88,6 +93,7 @@ async fn visitor_debug_reports_hash_and_owner_snapshot() {
     set_trusted_proxy_config_for_tests(false, Vec::new());
 
     let (state, _tmp) = common::setup_test_app();
+    state.create_admin_session("debugtoken".into()).await;
     let owner_hash = common::hash_fixture_ip("127.0.0.1");
     let now = now_secs();
     state.owners.insert(
@@ -96,8 +102,16 @@ async fn visitor_debug_reports_hash_and_owner_snapshot() {
             owner_hash: owner_hash.clone(),
             expires: now + 3600,
             original: "demo.bin".to_string(),
+            original_display: String::new(),
             created: now,
             hash: "deadbeef".into(),
+            max_downloads: None,
+            downloads: 0,
+            private: false,
+            ttl_shortened_from: None,
+            guest: false,
+            network_class: None,
+            size: 0,
         },
     );
 
@@ -108,10 +122,12 @@ async fn visitor_debug_reports_hash_and_owner_snapshot() {
         "X-Forwarded-For",
         HeaderValue::from_static("198.51.100.9, 203.0.113.5"),
     );
+    headers.insert(header::COOKIE, HeaderValue::from_static("adm=debugtoken"));
     let addr: SocketAddr = SocketAddr::from(([127, 0, 0, 1], 4444));
 
     let resp = visitor_debug_handler(State(state.clone()), ConnectInfo(addr), headers).await;
     assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers()[header::CACHE_CONTROL], "no-store");
     let body_bytes = resp.into_body().collect().await.unwrap().to_bytes();
     let data: Value = serde_json::from_slice(&body_bytes).unwrap();
 
@@ -129,6 +145,93 @@ async fn visitor_debug_reports_hash_and_owner_snapshot() {
     set_trusted_proxy_config_for_tests(false, Vec::new());
 }
 
+#[tokio::test]
+async fn visitor_debug_shows_only_trust_status_without_admin_auth() {
+    let _lock = PROXY_GUARD.lock().unwrap();
+    set_trusted_proxy_config_for_tests(false, Vec::new());
+
+    let (state, _tmp) = common::setup_test_app();
+    let addr: SocketAddr = SocketAddr::from(([127, 0, 0, 1], 4445));
+    for cookie in [None, Some("adm=forged")] {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Forwarded-For", HeaderValue::from_static("198.51.100.9"));
+        if let Some(cookie) = cookie {
+            headers.insert(header::COOKIE, HeaderValue::from_static(cookie));
+        }
+        let resp = visitor_debug_handler(State(state.clone()), ConnectInfo(addr), headers).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CACHE_CONTROL], "no-store");
+        let body_bytes = resp.into_body().collect().await.unwrap().to_bytes();
+        let data: Value = serde_json::from_slice(&body_bytes).unwrap();
+        assert_eq!(data, serde_json::json!({ "headers_trusted": false }));
+    }
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::AUTHORIZATION,
+        HeaderValue::from_static("Bearer not-the-admin-key"),
+    );
+    let resp = visitor_debug_handler(State(state.clone()), ConnectInfo(addr), headers).await;
+    let body_bytes = resp.into_body().collect().await.unwrap().to_bytes();
+    let data: Value = serde_json::from_slice(&body_bytes).unwrap();
+    assert!(data.get("headers").is_none());
+
+    // Rejected credentials are audited like on any admin endpoint; sending
+    // none is not a failed attempt.
+    let failures: Vec<_> = state
+        .audit_log
+        .entries()
+        .await
+        .into_iter()
+        .map(|entry| (entry.action, entry.actor, entry.detail))
+        .collect();
+    assert_eq!(
+        failures,
+        [
+            (
+                AuditAction::AuthFailure,
+                "anonymous".to_string(),
+                Some("invalid session for visitor debug".to_string())
+            ),
+            (
+                AuditAction::AuthFailure,
+                "anonymous".to_string(),
+                Some("invalid bearer token for visitor debug".to_string())
+            ),
+        ]
+    );
+
+    set_trusted_proxy_config_for_tests(false, Vec::new());
+}
+
+#[tokio::test]
+async fn visitor_debug_is_rate_limited_per_client() {
+    let _lock = PROXY_GUARD.lock().unwrap();
+    set_trusted_proxy_config_for_tests(false, Vec::new());
+
+    let (state, _tmp) = common::setup_test_app();
+    state.create_admin_session("debugtoken".into()).await;
+    let mut headers = HeaderMap::new();
+    headers.insert(header::COOKIE, HeaderValue::from_static("adm=debugtoken"));
+    let addr: SocketAddr = SocketAddr::from(([127, 0, 0, 2], 4446));
+    for _ in 0..VISITOR_DEBUG_RATE_LIMIT_BURST {
+        let resp =
+            visitor_debug_handler(State(state.clone()), ConnectInfo(addr), headers.clone()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+    let resp =
+        visitor_debug_handler(State(state.clone()), ConnectInfo(addr), headers.clone()).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(resp.headers()[header::CACHE_CONTROL], "no-store");
+
+    // Other clients keep their own budget.
+    let other: SocketAddr = SocketAddr::from(([127, 0, 0, 3], 4446));
+    let resp = visitor_debug_handler(State(state.clone()), ConnectInfo(other), headers).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    set_trusted_proxy_config_for_tests(false, Vec::new());
+}
+
 #[tokio::test]
 async fn admin_files_handler_escapes_html_entities() {
     let (state, _tmp) = common::setup_test_app();
@@ -144,8 +247,16 @@ async fn admin_files_handler_escapes_html_entities() {
             owner_hash: owner.to_string(),
             expires: now + 3600,
             original: file_name.to_string(),
+            original_display: String::new(),
             created: now,
             hash: "deadbeef".into(),
+            max_downloads: None,
+            downloads: 0,
+            private: false,
+            ttl_shortened_from: None,
+            guest: false,
+            network_class: None,
+            size: 0,
         },
     );
 
@@ -162,7 +273,12 @@ async fn admin_files_handler_escapes_html_entities() {
         HeaderValue::from_str(&format!("adm={token}")).unwrap(),
     );
 
-    let resp = admin_files_handler(State(state.clone()), headers).await;
+    let resp = admin_files_handler(
+        State(state.clone()),
+        headers,
+        Query(AdminListQuery::default()),
+    )
+    .await;
     assert_eq!(resp.status(), StatusCode::OK);
     let body_bytes = resp.into_body().collect().await.unwrap().to_bytes();
     let body = String::from_utf8(body_bytes.to_vec()).unwrap();
@@ -181,3 +297,125 @@ async fn admin_files_handler_escapes_html_entities() {
     let escaped_file = htmlescape::encode_minimal(file_name);
     assert!(body.contains(&format!("value=\"{}\"", escaped_file)));
 }
+
+#[tokio::test]
+async fn admin_files_handler_flags_spoofed_original_names() {
+    let (state, _tmp) = common::setup_test_app();
+    let token = "flagtoken".to_string();
+    state.create_admin_session(token.clone()).await;
+
+    let now = now_secs();
+    for (file, original) in [
+        ("plain.bin", "holiday.jpg"),
+        // A one-dot leader makes "invoice.pdf" look like the extension.
+        ("spoof.bin", "invoice\u{2024}pdf.exe"),
+    ] {
+        state.owners.insert(
+            file.to_string(),
+            FileMeta {
+                owner_hash: "owner".into(),
+                expires: now + 3600,
+                original: original.to_string(),
+                original_display: String::new(),
+                created: now,
+                hash: "deadbeef".into(),
+                max_downloads: None,
+                downloads: 0,
+                private: false,
+                ttl_shortened_from: None,
+                guest: false,
+                network_class: None,
+                size: 0,
+            },
+        );
+    }
+    tokio::fs::write(
+        state.static_dir.join("admin_files.html"),
+        "<html><body><table>{{FILE_ROWS}}</table></body></html>",
+    )
+    .await
+    .unwrap();
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::COOKIE,
+        HeaderValue::from_str(&format!("adm={token}")).unwrap(),
+    );
+    let resp = admin_files_handler(State(state), headers, Query(AdminListQuery::default())).await;
+    let body = resp.into_body().collect().await.unwrap().to_bytes();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert!(body.contains("invoice_pdf.exe"));
+    assert!(body.contains("data-warning=confusable_separator"));
+    assert!(body.contains("holiday.jpg"));
+    assert_eq!(body.matches("name-warning").count(), 1);
+}
+
+// With embedded assets the compiled-in public pages are served instead.
+#[cfg(not(feature = "embedded-assets"))]
+#[tokio::test]
+async fn admin_pages_fall_back_to_builtin_html_when_static_files_missing() {
+    use juicebox::handlers::{auth_get_handler, ban_page_handler};
+
+    let (state, _tmp) = common::setup_test_app();
+    let token = "fallback-token".to_string();
+    state.create_admin_session(token.clone()).await;
+    let file_name = "<script>x</script>.bin";
+    state.owners.insert(
+        file_name.to_string(),
+        FileMeta {
+            owner_hash: common::hash_fixture_ip("203.0.113.7"),
+            expires: now_secs() + 3600,
+            original: "orig.bin".into(),
+            original_display: String::new(),
+            created: now_secs(),
+            hash: "abc".into(),
+            max_downloads: None,
+            downloads: 0,
+            private: false,
+            ttl_shortened_from: None,
+            guest: false,
+            network_class: None,
+            size: 0,
+        },
+    );
+    assert!(!state.static_dir.join("admin_files.html").exists());
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::COOKIE,
+        HeaderValue::from_str(&format!("adm={token}")).unwrap(),
+    );
+    let resp = admin_files_handler(
+        State(state.clone()),
+        headers.clone(),
+        Query(AdminListQuery::default()),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = resp.into_body().collect().await.unwrap().to_bytes();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert!(body.contains("<th scope=\"col\">Owner ID</th>"));
+    assert!(body.contains("aria-current=\"page\""));
+    assert!(body.contains("action=/admin/files"));
+    assert!(!body.contains("<script>x</script>"));
+    assert!(body.contains("storage: memory"));
+
+    let resp = ban_page_handler(
+        State(state.clone()),
+        headers,
+        Query(AdminListQuery::default()),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = resp.into_body().collect().await.unwrap().to_bytes();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert!(body.contains("action=\"/admin/ban\""));
+    assert!(body.contains("<label for=\"ban-target\">"));
+    assert!(body.contains("No entries."));
+
+    let resp = auth_get_handler(State(state), HeaderMap::new()).await;
+    let body = resp.into_body().collect().await.unwrap().to_bytes();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert!(body.contains("<label for=\"admin-key\">Admin key</label>"));
+    assert!(!body.contains("href=\"/admin/files\""));
+}
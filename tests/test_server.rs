@@ -0,0 +1,42 @@
+use axum::routing::get;
+use juicebox::server::Server;
+use juicebox::testing::AppStateBuilder;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[tokio::test]
+async fn test_embedded_server_serves_and_shuts_down() {
+    let app = AppStateBuilder::new().build();
+    let stopped = Arc::new(AtomicBool::new(false));
+    let seen = stopped.clone();
+
+    let running = Server::new(app.state.clone())
+        .listen("127.0.0.1:0".parse().unwrap())
+        .handle_signals(false)
+        .map_router(|router| router.route("/embedded", get(|| async { "hello from the host" })))
+        .background("test task", move |shutdown| async move {
+            shutdown.notified().await;
+            seen.store(true, Ordering::SeqCst);
+        })
+        .start()
+        .await
+        .unwrap();
+    let base = format!("http://{}", running.local_addr());
+    let client = reqwest::Client::new();
+
+    let resp = client.get(format!("{base}/embedded")).send().await.unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.text().await.unwrap(), "hello from the host");
+
+    let resp = client
+        .get(format!("{base}/api/version"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert!(resp.headers().contains_key("x-request-id"));
+
+    running.shutdown().await.unwrap();
+    assert!(stopped.load(Ordering::SeqCst));
+    assert!(client.get(format!("{base}/embedded")).send().await.is_err());
+}
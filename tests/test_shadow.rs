@@ -0,0 +1,128 @@
+mod common;
+
+use axum::Router;
+use axum::body::Body;
+use axum::extract::ConnectInfo;
+use axum::http::{HeaderMap, Method, Request, StatusCode, header};
+use axum::middleware;
+use axum::routing::any;
+use juicebox::handlers::build_router;
+use juicebox::shadow::{SHADOW_HEADER, Shadow, ShadowConfig, shadow_gate};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tower::ServiceExt;
+
+type Seen = Arc<Mutex<Vec<(String, HeaderMap)>>>;
+
+/// A staging stand-in that records what reaches it and answers 418.
+async fn spawn_staging() -> (String, Seen) {
+    let seen: Seen = Arc::default();
+    let record = seen.clone();
+    let app = Router::new().fallback(any(move |req: Request<Body>| {
+        let record = record.clone();
+        async move {
+            record
+                .lock()
+                .unwrap()
+                .push((req.uri().to_string(), req.headers().clone()));
+            StatusCode::IM_A_TEAPOT
+        }
+    }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (format!("http://{addr}"), seen)
+}
+
+fn request(method: Method, uri: &str) -> Request<Body> {
+    let mut req = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::COOKIE, "adm=shadow-admin; acct=secret")
+        .header(header::USER_AGENT, "curl/8")
+        .header("x-forwarded-for", "192.0.2.9")
+        .header(header::ACCEPT, "application/json")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::empty())
+        .unwrap();
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([192, 0, 2, 9], 4100))));
+    req
+}
+
+async fn settle(state: &juicebox::state::AppState, mirrored: u64) {
+    for _ in 0..200 {
+        let status = state.shadow.status();
+        if status.mirrored >= mirrored
+            && status.matched + status.mismatched + status.failed >= mirrored
+        {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("shadow requests did not finish");
+}
+
+#[tokio::test]
+async fn test_shadow_mirrors_scrubbed_read_requests_until_switched_off() {
+    let (mut state, _tmp) = common::setup_test_app();
+    let (staging, seen) = spawn_staging().await;
+    state.shadow = Arc::new(Shadow::new(ShadowConfig {
+        base_url: Some(staging),
+        sample: 1.0,
+        ..ShadowConfig::default()
+    }));
+    state.create_admin_session("shadow-admin".to_string()).await;
+    let app = build_router(state.clone())
+        .layer(middleware::from_fn_with_state(state.clone(), shadow_gate));
+
+    let resp = app
+        .clone()
+        .oneshot(request(Method::GET, "/api/config"))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    for uri in ["/f/missing.txt?exp=1&sig=abc", "/api/admin/v1/runtime"] {
+        app.clone()
+            .oneshot(request(Method::GET, uri))
+            .await
+            .unwrap();
+    }
+    app.clone()
+        .oneshot(request(Method::GET, "/list?offset=0"))
+        .await
+        .unwrap();
+    settle(&state, 2).await;
+
+    let status = state.shadow.status();
+    assert_eq!((status.mirrored, status.mismatched), (2, 2));
+    {
+        let seen = seen.lock().unwrap();
+        let uris: Vec<&str> = seen.iter().map(|(uri, _)| uri.as_str()).collect();
+        assert_eq!(uris, ["/api/config", "/list?offset=0"]);
+        let (_, headers) = &seen[0];
+        assert_eq!(headers.get(SHADOW_HEADER).unwrap(), "1");
+        assert_eq!(headers.get(header::ACCEPT).unwrap(), "application/json");
+        for leaked in [
+            header::COOKIE.as_str(),
+            "x-forwarded-for",
+            header::USER_AGENT.as_str(),
+        ] {
+            assert!(headers.get(leaked).is_none(), "{leaked} was forwarded");
+        }
+    }
+
+    let mut off = request(Method::POST, "/api/admin/v1/shadow");
+    *off.body_mut() = Body::from(r#"{"enabled": false}"#);
+    let resp = app.clone().oneshot(off).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(!state.shadow.is_enabled());
+    app.clone()
+        .oneshot(request(Method::GET, "/api/config"))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(seen.lock().unwrap().len(), 2);
+    assert_eq!(state.shadow.status().mirrored, 2);
+}
@@ -0,0 +1,72 @@
+use juicebox::state::{KvStore, SqliteStore};
+
+fn pairs(items: &[(&str, &str)]) -> Vec<(String, String)> {
+    items
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[tokio::test]
+async fn sqlite_store_replaces_hashes_and_lists() {
+    let store = SqliteStore::in_memory().unwrap();
+    assert_eq!(store.backend_name(), "sqlite");
+    assert!(store.load_hash("owners").await.unwrap().is_empty());
+
+    store
+        .replace_hash("owners", &pairs(&[("a", "1"), ("b", "2")]))
+        .await
+        .unwrap();
+    store
+        .replace_hash("owners", &pairs(&[("c", "3")]))
+        .await
+        .unwrap();
+    store
+        .replace_hash("bans", &pairs(&[("x", "y")]))
+        .await
+        .unwrap();
+    assert_eq!(
+        store.load_hash("owners").await.unwrap(),
+        pairs(&[("c", "3")])
+    );
+    assert_eq!(store.load_hash("bans").await.unwrap(), pairs(&[("x", "y")]));
+
+    let reports: Vec<String> = ["third", "first", "second"].map(String::from).to_vec();
+    store.replace_list("reports", &reports).await.unwrap();
+    assert_eq!(store.load_list("reports").await.unwrap(), reports);
+    store.replace_list("reports", &[]).await.unwrap();
+    assert!(store.load_list("reports").await.unwrap().is_empty());
+
+    store.append_list("reports", "one").await.unwrap();
+    store.append_list("reports", "two").await.unwrap();
+    assert_eq!(store.load_list("reports").await.unwrap(), ["one", "two"]);
+    store
+        .replace_list("reports", &["kept".to_string()])
+        .await
+        .unwrap();
+    store.append_list("reports", "three").await.unwrap();
+    assert_eq!(store.load_list("reports").await.unwrap(), ["kept", "three"]);
+}
+
+#[tokio::test]
+async fn sqlite_store_persists_across_reopen() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("juicebox.db");
+    {
+        let store = SqliteStore::open(&path).unwrap();
+        store
+            .replace_hash("admin_sessions", &pairs(&[("token", "1700000000")]))
+            .await
+            .unwrap();
+        store
+            .replace_list("reports", &["r1".to_string()])
+            .await
+            .unwrap();
+    }
+    let store = SqliteStore::open(&path).unwrap();
+    assert_eq!(
+        store.load_hash("admin_sessions").await.unwrap(),
+        pairs(&[("token", "1700000000")])
+    );
+    assert_eq!(store.load_list("reports").await.unwrap(), vec!["r1"]);
+}
@@ -1,20 +1,31 @@
 mod common;
 
 use juicebox::state::{
-    BanSubject, FileMeta, IpBan, check_storage_integrity, cleanup_expired,
+    BanSubject, FileMeta, IpBan, OwnersPersister, check_storage_integrity, cleanup_expired,
     verify_user_entries_with_report,
 };
 use juicebox::util::now_secs;
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::fs;
+use tokio::sync::Notify;
 
 fn meta(owner_hash: String, expires: u64, original: &str) -> FileMeta {
     FileMeta {
         owner_hash,
         expires,
         original: original.to_string(),
+        original_display: String::new(),
         created: now_secs(),
         hash: "deadbeef".into(),
+        max_downloads: None,
+        downloads: 0,
+        private: false,
+        ttl_shortened_from: None,
+        guest: false,
+        network_class: None,
+        size: 0,
     }
 }
 
@@ -54,6 +65,7 @@ async fn bans_exact_hash_add_find_remove() {
             label: Some("unit-test".into()),
             reason: "testing".into(),
             time: 0,
+            expires: None,
         })
         .await;
 
@@ -70,7 +82,7 @@ async fn bans_exact_hash_add_find_remove() {
     assert!(found_hash.is_some());
 
     // Remove ban
-    state.remove_ban(found.unwrap().subject.key()).await;
+    state.remove_ban(&found.unwrap().subject.key()).await;
     assert!(!state.is_banned(ip).await);
 }
 
@@ -94,6 +106,7 @@ async fn bans_network_scope_and_nonmember() {
             label: None,
             reason: "test-net".into(),
             time: 0,
+            expires: None,
         })
         .await;
 
@@ -188,19 +201,20 @@ async fn verify_user_entries_reconciles_with_disk() {
     let file_c = "c.txt".to_string();
     let other_owner = common::hash_fixture_ip("192.0.2.1");
 
-    let mut serialized = Vec::new();
-    serialized.push((
-        file_a.clone(),
-        serde_json::to_string(&meta(owner_hash.clone(), now + 9999, "new.txt")).unwrap(),
-    ));
-    serialized.push((
-        file_c.clone(),
-        serde_json::to_string(&meta(owner_hash.clone(), now + 3333, "c.txt")).unwrap(),
-    ));
-    serialized.push((
-        "d.txt".to_string(),
-        serde_json::to_string(&meta(other_owner, now + 1234, "d.txt")).unwrap(),
-    ));
+    let serialized = vec![
+        (
+            file_a.clone(),
+            serde_json::to_string(&meta(owner_hash.clone(), now + 9999, "new.txt")).unwrap(),
+        ),
+        (
+            file_c.clone(),
+            serde_json::to_string(&meta(owner_hash.clone(), now + 3333, "c.txt")).unwrap(),
+        ),
+        (
+            "d.txt".to_string(),
+            serde_json::to_string(&meta(other_owner, now + 1234, "d.txt")).unwrap(),
+        ),
+    ];
 
     fs::write(state.upload_dir.join(&file_c), b"existing-c")
         .await
@@ -332,3 +346,62 @@ async fn reconcile_cleans_stale_store_entries() {
         "stale kv entry should have been removed"
     );
 }
+
+#[tokio::test]
+async fn owners_persist_is_debounced_and_flushed_on_shutdown() {
+    let (mut state, _tmp) = common::setup_test_app();
+    state.owners_persister = Arc::new(OwnersPersister::new(Duration::from_secs(3600)));
+    let shutdown = Arc::new(Notify::new());
+    let handle = state.spawn_owners_persister(shutdown.clone());
+
+    for i in 0..5 {
+        let name = format!("burst{i}.bin");
+        state
+            .owners
+            .insert(name.clone(), meta("owner".into(), now_secs() + 60, &name));
+        state.persist_owners().await;
+    }
+    tokio::task::yield_now().await;
+    assert!(state.owners_persister.is_dirty());
+    assert!(state.kv.load_hash("owners").await.unwrap().is_empty());
+
+    shutdown.notify_waiters();
+    handle.await.unwrap();
+    assert!(!state.owners_persister.is_dirty());
+    assert_eq!(state.kv.load_hash("owners").await.unwrap().len(), 5);
+
+    // Once the persister has stopped, writes go straight to the store.
+    state.owners.clear();
+    state.persist_owners().await;
+    assert!(state.kv.load_hash("owners").await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn owners_snapshot_tracks_mutations() {
+    let (state, _tmp) = common::setup_test_app();
+    let owner = common::hash_fixture_ip("198.51.100.20");
+    let mut first = meta(owner.clone(), now_secs() + 60, "b.txt");
+    first.hash = "hash-b".into();
+    state.insert_owner("b.bin".into(), first);
+
+    let before = state.owners_snapshot();
+    assert_eq!(before.files_for(&owner).len(), 1);
+    assert_eq!(before.file_with_hash("hash-b"), Some("b.bin"));
+
+    let mut second = meta(owner.clone(), now_secs() + 60, "a.txt");
+    second.hash = "hash-a".into();
+    state.insert_owner("a.bin".into(), second);
+    state.remove_owner("b.bin");
+
+    // Earlier snapshots stay intact for readers still holding them.
+    assert_eq!(before.files_for(&owner).len(), 1);
+    let after = state.owners_snapshot();
+    let names: Vec<&str> = after
+        .files_for(&owner)
+        .iter()
+        .map(|(file, _)| file.as_str())
+        .collect();
+    assert_eq!(names, ["a.bin"]);
+    assert!(after.file_with_hash("hash-b").is_none());
+    assert!(Arc::ptr_eq(&after, &state.owners_snapshot()));
+}
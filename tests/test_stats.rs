@@ -0,0 +1,176 @@
+mod common;
+
+use axum::extract::ConnectInfo;
+use axum::{
+    body::{Body, to_bytes},
+    http::{Request, StatusCode, header},
+};
+use juicebox::handlers::build_router;
+use juicebox::state::FileMeta;
+use juicebox::util::now_secs;
+use serde_json::Value;
+use std::net::SocketAddr;
+use tower::ServiceExt;
+
+async fn get(app: &axum::Router, uri: &str, cookie: Option<&str>) -> (StatusCode, Vec<u8>) {
+    let mut builder = Request::builder().uri(uri);
+    if let Some(cookie) = cookie {
+        builder = builder.header(header::COOKIE, cookie);
+    }
+    let response = app
+        .clone()
+        .oneshot(builder.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, body.to_vec())
+}
+
+#[tokio::test]
+async fn test_public_stats_are_coarse_and_stable() {
+    let (state, _temp_dir) = common::setup_test_app();
+    let now = now_secs();
+    for i in 0..23 {
+        let file = format!("file{i}.txt");
+        std::fs::write(state.upload_dir.join(&file), b"0123456789").unwrap();
+        state.owners.insert(
+            file.clone(),
+            FileMeta {
+                owner_hash: format!("owner{}", i % 3),
+                expires: now + 3600,
+                original: file,
+                original_display: String::new(),
+                created: now,
+                hash: String::new(),
+                max_downloads: None,
+                downloads: 0,
+                private: false,
+                ttl_shortened_from: None,
+                guest: false,
+                network_class: None,
+                size: 0,
+            },
+        );
+    }
+    let app = build_router(state.clone());
+
+    let (status, body) = get(&app, "/api/stats", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let stats: Value = serde_json::from_slice(&body).unwrap();
+    let files = stats["files_hosted"].as_u64().unwrap();
+    assert_eq!(files % 10, 0);
+    assert!((10..=30).contains(&files));
+    assert_eq!(stats["storage_used"], "under 100 MB");
+    assert!(stats.get("owners").is_none());
+
+    // Cached for the window, so repeated polling cannot average out the noise.
+    let (_, again) = get(&app, "/api/stats", None).await;
+    assert_eq!(body, again);
+
+    let (status, page) = get(&app, "/stats", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(
+        String::from_utf8(page)
+            .unwrap()
+            .contains(&format!("~{files}"))
+    );
+}
+
+#[tokio::test]
+async fn test_admin_stats_require_session_and_are_exact() {
+    let (state, _temp_dir) = common::setup_test_app();
+    let app = build_router(state.clone());
+    let (status, _) = get(&app, "/admin/stats", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    state.create_admin_session("stats-admin".to_string()).await;
+    let (status, body) = get(&app, "/admin/stats", Some("adm=stats-admin")).await;
+    assert_eq!(status, StatusCode::OK);
+    let stats: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(stats["files"], 0);
+    assert_eq!(stats["storage_bytes"], 0);
+}
+
+#[tokio::test]
+async fn test_admin_runtime_reports_effective_configuration() {
+    let (state, _temp_dir) = common::setup_test_app();
+    let app = build_router(state.clone());
+    let (status, _) = get(&app, "/api/admin/v1/runtime", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    state
+        .create_admin_session("runtime-admin".to_string())
+        .await;
+    let (status, body) = get(&app, "/api/admin/v1/runtime", Some("adm=runtime-admin")).await;
+    assert_eq!(status, StatusCode::OK);
+    let runtime: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(runtime["build"]["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(runtime["listen_addr"], "0.0.0.0:1200");
+    assert_eq!(runtime["backends"]["kv"], "memory");
+    assert_eq!(runtime["backends"]["file_store"], "local");
+    assert_eq!(
+        runtime["dirs"]["upload_dir"],
+        state.upload_dir.display().to_string()
+    );
+    assert_eq!(
+        runtime["limits"]["max_file_bytes"],
+        juicebox::util::max_file_bytes()
+    );
+    assert_eq!(runtime["features"]["report_email"], true);
+    assert_eq!(
+        runtime["trace_sampling"]["effective_rate"],
+        state.trace_sampler.rate()
+    );
+    assert_eq!(runtime["trace_sampling"]["ceiling"], 1.0);
+}
+
+fn paste_from(user_agent: &str, privacy: bool) -> Request<Body> {
+    let mut builder = Request::builder()
+        .method("POST")
+        .uri("/api/paste-binary?name=note.txt")
+        .header(header::USER_AGENT, user_agent);
+    if privacy {
+        builder = builder.header("sec-gpc", "1");
+    }
+    let mut req = builder
+        .body(Body::from(format!("hello from {user_agent} {privacy}")))
+        .unwrap();
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([198, 51, 100, 71], 6300))));
+    req
+}
+
+#[tokio::test]
+async fn test_admin_stats_count_uploads_by_method_size_and_client() {
+    let (state, _temp_dir) = common::setup_test_app();
+    let app = build_router(state.clone());
+    for (agent, privacy) in [
+        ("curl/8.5.0", false),
+        (
+            "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0",
+            false,
+        ),
+        ("curl/8.5.0", true),
+    ] {
+        let resp = app
+            .clone()
+            .oneshot(paste_from(agent, privacy))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    state.create_admin_session("stats-admin".to_string()).await;
+    let (status, body) = get(&app, "/admin/stats", Some("adm=stats-admin")).await;
+    assert_eq!(status, StatusCode::OK);
+    let stats: Value = serde_json::from_slice(&body).unwrap();
+    let uploads = &stats["uploads"];
+    // The upload sent with a privacy signal is not counted.
+    assert_eq!(uploads["total"], 2);
+    assert_eq!(uploads["by_method"]["paste"], 2);
+    assert_eq!(uploads["by_file_size"]["<100KiB"], 2);
+    assert_eq!(uploads["by_client"]["curl"], 1);
+    assert_eq!(uploads["by_client"]["firefox"], 1);
+    assert!(uploads["by_chunk_size"].as_object().unwrap().is_empty());
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use axum::body::{Body, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode, header};
+use juicebox::handlers::build_router;
+use juicebox::state::FileMeta;
+use juicebox::storage_pressure::{StorageLimits, StorageWatchdog, StoredFile, plan_shortening};
+use juicebox::util::now_secs;
+use serde_json::Value;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const DAY: u64 = 24 * 60 * 60;
+
+fn stored(name: &str, size: u64, age: u64, left: u64, now: u64) -> StoredFile {
+    StoredFile {
+        name: name.to_string(),
+        size,
+        created: now - age,
+        expires: now + left,
+    }
+}
+
+#[test]
+fn test_plan_shortens_largest_oldest_until_overage_is_covered() {
+    let now = 1_000_000_000;
+    let limits = StorageLimits {
+        soft_bytes: 1000,
+        hard_bytes: 0,
+        min_ttl_secs: 3600,
+        owner_bytes: 0,
+    };
+    let files = [
+        stored("big-new.bin", 800, 60, 10 * DAY, now),
+        stored("big-old.bin", 700, 2 * DAY, 10 * DAY, now),
+        stored("small.bin", 500, DAY, 10 * DAY, now),
+        stored("soon.bin", 1, 5 * DAY, 60, now),
+    ];
+    // 2001 bytes against a 1000 byte budget: the old 700 byte file ranks
+    // first, and one more file is needed to cover the 1001 byte overage.
+    let plan = plan_shortening(&files, 2001, &limits, now);
+    let names: Vec<&str> = plan.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["big-old.bin", "small.bin"]);
+    let keep = (10 * DAY) as f64 * 1000.0 / 2001.0;
+    assert_eq!(plan[0].1, now + keep as u64);
+
+    // Nothing to do within budget, and never below the minimum TTL.
+    assert!(plan_shortening(&files, 1000, &limits, now).is_empty());
+    let plan = plan_shortening(&files[3..], 2001, &limits, now);
+    assert!(
+        plan.is_empty(),
+        "a file expiring within the floor is left alone"
+    );
+}
+
+#[tokio::test]
+async fn test_storage_pressure_shortens_ttls_and_blocks_uploads_when_full() {
+    let (mut state, _tmp) = common::setup_test_app();
+    state.storage = Arc::new(StorageWatchdog::new(StorageLimits {
+        soft_bytes: 600,
+        hard_bytes: 1100,
+        min_ttl_secs: 3600,
+        owner_bytes: 0,
+    }));
+    let now = now_secs();
+    let owner = common::hash_fixture_ip("127.0.0.1");
+    for (name, size) in [("large.bin", 1000usize), ("tiny.bin", 50)] {
+        std::fs::write(state.upload_dir.join(name), vec![0u8; size]).unwrap();
+        state.insert_owner(
+            name.to_string(),
+            FileMeta {
+                owner_hash: owner.clone(),
+                expires: now + 10 * DAY,
+                original: name.to_string(),
+                original_display: String::new(),
+                created: now - 60,
+                hash: String::new(),
+                max_downloads: None,
+                downloads: 0,
+                private: false,
+                ttl_shortened_from: None,
+                guest: false,
+                network_class: None,
+                size: 0,
+            },
+        );
+    }
+
+    assert_eq!(state.enforce_storage_limits().await, 1);
+    assert_eq!(state.storage.used_bytes(), 1050);
+    let large = state.owners.get("large.bin").unwrap().clone();
+    assert_eq!(large.ttl_shortened_from, Some(now + 10 * DAY));
+    assert!(large.expires < now + 6 * DAY, "{}", large.expires - now);
+    assert_eq!(
+        state.owners.get("tiny.bin").unwrap().ttl_shortened_from,
+        None
+    );
+
+    let app = build_router(state.clone());
+    let mut list = Request::builder().uri("/list").body(Body::empty()).unwrap();
+    list.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 4000))));
+    let resp = app.clone().oneshot(list).await.unwrap();
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let listed: Value = serde_json::from_slice(&body).unwrap();
+    let entry = listed["metas"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|m| m["file"].as_str().unwrap().ends_with("large.bin"))
+        .unwrap();
+    assert_eq!(entry["shortened_from"], now + 10 * DAY);
+
+    // Bytes stored between sweeps count against the hard limit.
+    state.storage.record_stored(100);
+    let mut paste = Request::builder()
+        .method(Method::POST)
+        .uri("/api/paste-binary?name=more.txt")
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(Body::from("more"))
+        .unwrap();
+    paste
+        .extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 4000))));
+    let resp = app.oneshot(paste).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::INSUFFICIENT_STORAGE);
+}
@@ -0,0 +1,228 @@
+mod common;
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{HeaderMap, Method, Request, StatusCode},
+    middleware,
+    routing::{get, post},
+};
+use juicebox::handlers::telemetry::{
+    chunk_session_id, has_privacy_signal, route_is_ignored, sentry_trace_to_traceparent,
+    telemetry_gate, upload_method,
+};
+use juicebox::state::{AppState, ChunkLifecycle, ChunkPhase, ChunkSession};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use tokio::sync::{Mutex, RwLock};
+use tower::ServiceExt;
+
+fn echo_router(state: AppState) -> Router {
+    async fn echo(headers: HeaderMap) -> String {
+        headers
+            .get("sentry-trace")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string()
+    }
+    Router::new()
+        .route("/echo", get(echo))
+        .route("/upload", post(echo))
+        .layer(middleware::from_fn_with_state(state, telemetry_gate))
+}
+
+async fn call(app: &Router, method: Method, uri: &str, header: Option<(&str, &str)>) -> String {
+    let mut builder = Request::builder().method(method).uri(uri);
+    if let Some((name, value)) = header {
+        builder = builder.header(name, value);
+    }
+    let response = app
+        .clone()
+        .oneshot(builder.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[test]
+fn test_privacy_signal_detection() {
+    let mut headers = HeaderMap::new();
+    assert!(!has_privacy_signal(&headers));
+    headers.insert("dnt", "0".parse().unwrap());
+    assert!(!has_privacy_signal(&headers));
+    headers.insert("sec-gpc", "1".parse().unwrap());
+    assert!(has_privacy_signal(&headers));
+}
+
+#[tokio::test]
+async fn test_privacy_signals_skip_counters_and_unsample_trace() {
+    let (state, _temp_dir) = common::setup_test_app();
+    let app = echo_router(state.clone());
+
+    let trace = call(&app, Method::GET, "/echo", None).await;
+    assert!(trace.is_empty());
+    call(&app, Method::POST, "/upload", None).await;
+    assert_eq!(state.analytics.requests.load(Ordering::Relaxed), 2);
+    assert_eq!(state.analytics.uploads.load(Ordering::Relaxed), 1);
+
+    let trace = call(&app, Method::GET, "/echo", Some(("Sec-GPC", "1"))).await;
+    assert!(trace.ends_with("-0"), "unexpected sentry-trace {trace}");
+    call(&app, Method::POST, "/upload", Some(("DNT", "1"))).await;
+    assert_eq!(state.analytics.requests.load(Ordering::Relaxed), 2);
+    assert_eq!(state.analytics.uploads.load(Ordering::Relaxed), 1);
+}
+
+#[tokio::test]
+async fn test_privacy_signals_ignored_when_policy_disabled() {
+    let (mut state, _temp_dir) = common::setup_test_app();
+    let mut telemetry = (*state.telemetry).clone();
+    telemetry.respect_privacy_signals = false;
+    state.telemetry = std::sync::Arc::new(telemetry);
+    let app = echo_router(state.clone());
+
+    let trace = call(&app, Method::GET, "/echo", Some(("DNT", "1"))).await;
+    assert!(trace.is_empty());
+    assert_eq!(state.analytics.requests.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn test_ignored_route_patterns() {
+    let patterns = vec!["/healthz".to_string(), "/css/*".to_string()];
+    assert!(route_is_ignored("/healthz", &patterns));
+    assert!(route_is_ignored("/css/app.css", &patterns));
+    assert!(!route_is_ignored("/healthz/extra", &patterns));
+    assert!(!route_is_ignored("/upload", &patterns));
+}
+
+#[test]
+fn test_upload_method_tags() {
+    assert_eq!(upload_method(&Method::POST, "/upload"), Some("multipart"));
+    assert_eq!(
+        upload_method(&Method::PUT, "/chunk/{id}/{index}"),
+        Some("chunked")
+    );
+    assert_eq!(
+        upload_method(&Method::POST, "/api/paste-binary"),
+        Some("paste")
+    );
+    assert_eq!(upload_method(&Method::GET, "/upload"), None);
+}
+
+#[tokio::test]
+async fn test_ignored_routes_unsampled_but_counted() {
+    let (state, _temp_dir) = common::setup_test_app();
+    let app = Router::new()
+        .route(
+            "/healthz",
+            get(|headers: HeaderMap| async move {
+                headers
+                    .get("sentry-trace")
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or_default()
+                    .to_string()
+            }),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            telemetry_gate,
+        ));
+
+    let trace = call(&app, Method::GET, "/healthz", None).await;
+    assert!(trace.ends_with("-0"), "unexpected sentry-trace {trace}");
+    assert_eq!(state.analytics.requests.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn test_chunk_session_id_from_path() {
+    assert_eq!(chunk_session_id("/chunk/abc/3"), Some("abc"));
+    assert_eq!(chunk_session_id("/chunk/abc/complete"), Some("abc"));
+    assert_eq!(chunk_session_id("/chunk/init"), None);
+    assert_eq!(chunk_session_id("/upload"), None);
+}
+
+#[test]
+fn test_sentry_trace_converts_to_traceparent() {
+    let trace = "0af7651916cd43dd8448eb211c80319c";
+    let span = "b7ad6b7169203331";
+    assert_eq!(
+        sentry_trace_to_traceparent(&format!("{trace}-{span}-1")).unwrap(),
+        format!("00-{trace}-{span}-01")
+    );
+    assert_eq!(
+        sentry_trace_to_traceparent(&format!("{trace}-{span}")).unwrap(),
+        format!("00-{trace}-{span}-00")
+    );
+    assert!(sentry_trace_to_traceparent("garbage").is_none());
+}
+
+#[tokio::test]
+async fn test_chunk_requests_continue_init_trace() {
+    let (state, _temp_dir) = common::setup_test_app();
+    let parent = "0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-1";
+    state.chunk_sessions.insert(
+        "sess1".to_string(),
+        Arc::new(ChunkSession {
+            owner_hash: "owner".into(),
+            original_name: "a.txt".into(),
+            storage_name: "a.txt".into(),
+            ttl_code: "1h".into(),
+            expires: 0,
+            total_bytes: 1,
+            chunk_size: 1,
+            total_chunks: 1,
+            hash: None,
+            max_downloads: None,
+            private: false,
+            storage_dir: Arc::new(PathBuf::new()),
+            created: 0,
+            received: RwLock::new(vec![false]),
+            chunk_hashes: RwLock::new(vec![None]),
+            lifecycle: ChunkLifecycle::new(ChunkPhase::Init),
+            last_update: AtomicU64::new(0),
+            persist_lock: Mutex::new(()),
+            append_lock: Mutex::new(()),
+            assembled_chunks: AtomicU32::new(0),
+            trace_parent: Some(parent.to_string()),
+            node: None,
+        }),
+    );
+    let app = Router::new()
+        .route(
+            "/chunk/{id}/{index}",
+            axum::routing::put(|headers: HeaderMap| async move {
+                headers
+                    .get("sentry-trace")
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or_default()
+                    .to_string()
+            }),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            telemetry_gate,
+        ));
+
+    assert_eq!(
+        call(&app, Method::PUT, "/chunk/sess1/0", None).await,
+        parent
+    );
+    assert!(
+        call(&app, Method::PUT, "/chunk/other/0", None)
+            .await
+            .is_empty()
+    );
+    let own = "11111111111111111111111111111111-2222222222222222-1";
+    assert_eq!(
+        call(
+            &app,
+            Method::PUT,
+            "/chunk/sess1/0",
+            Some(("sentry-trace", own))
+        )
+        .await,
+        own
+    );
+}
@@ -0,0 +1,116 @@
+use juicebox::telemetry_config::TelemetryConfig;
+use juicebox::trace_sampling::{DEFAULT_ERROR_BUDGET, DEFAULT_TRACES_FLOOR};
+use std::collections::HashMap;
+
+fn config(production: bool, vars: &[(&str, &str)]) -> anyhow::Result<TelemetryConfig> {
+    let vars: HashMap<String, String> = vars
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    TelemetryConfig::from_vars(production, |name| vars.get(name).cloned())
+}
+
+#[test]
+fn test_defaults_without_any_variables() {
+    let config = config(true, &[]).unwrap();
+    assert_eq!(config.dsn, None);
+    assert_eq!(config.environment, "production");
+    assert_eq!(config.traces_sample_rate(), 1.0);
+    assert_eq!(config.sampling.floor, DEFAULT_TRACES_FLOOR);
+    assert_eq!(config.sampling.error_budget, DEFAULT_ERROR_BUDGET);
+    assert_eq!(config.error_sample_rate, 1.0);
+    assert_eq!(config.profiles_sample_rate, 1.0);
+    assert_eq!(config.trace_propagation_targets, vec!["^/".to_string()]);
+    assert!(!config.ignored_routes.is_empty());
+    assert!(config.respect_privacy_signals);
+    assert!(!config.release.is_empty());
+    assert_eq!(environment_for(false), "development");
+}
+
+fn environment_for(production: bool) -> String {
+    config(production, &[]).unwrap().environment
+}
+
+#[test]
+fn test_values_are_read_and_trimmed() {
+    let config = config(
+        false,
+        &[
+            ("SENTRY_DSN", " https://key@o1.ingest.sentry.io/2 "),
+            ("SENTRY_ENV", "staging"),
+            ("SENTRY_RELEASE", "v9"),
+            ("SENTRY_TRACES_SAMPLE_RATE", "0.5"),
+            ("SENTRY_TRACES_SAMPLE_RATE_MIN", "0.1"),
+            ("SENTRY_TRACES_ERROR_BUDGET", "0.2"),
+            ("SENTRY_SAMPLE_RATE", "0.75"),
+            ("SENTRY_TRACE_PROPAGATION_TARGETS", "^/api, ,^/f"),
+            ("SENTRY_IGNORED_ROUTES", ""),
+            ("TELEMETRY_RESPECT_PRIVACY_SIGNALS", "off"),
+        ],
+    )
+    .unwrap();
+    assert_eq!(
+        config.dsn.as_deref(),
+        Some("https://key@o1.ingest.sentry.io/2")
+    );
+    assert_eq!(config.environment, "staging");
+    assert_eq!(config.release, "v9");
+    assert_eq!(config.sampling.floor, 0.1);
+    assert_eq!(config.sampling.ceiling, 0.5);
+    assert_eq!(config.sampling.error_budget, 0.2);
+    assert_eq!(config.error_sample_rate, 0.75);
+    // Profiles follow the traces rate unless set.
+    assert_eq!(config.profiles_sample_rate, 0.5);
+    assert_eq!(config.trace_propagation_targets, vec!["^/api", "^/f"]);
+    assert!(config.ignored_routes.is_empty());
+    assert!(!config.respect_privacy_signals);
+
+    let state = config.state();
+    assert!(state.sentry_enabled());
+    assert_eq!(state.traces_sample_rate, 0.5);
+}
+
+#[test]
+fn test_disabled_dsn_and_lowered_ceiling() {
+    let config = config(
+        false,
+        &[
+            ("SENTRY_DSN", "off"),
+            ("SENTRY_TRACES_SAMPLE_RATE", "0.01"),
+            ("GIT_SHA", "0123456789abcdef"),
+        ],
+    )
+    .unwrap();
+    assert_eq!(config.dsn, None);
+    // The default floor never exceeds a lowered ceiling.
+    assert_eq!(config.sampling.floor, 0.01);
+    assert!(
+        config.release.ends_with("+0123456789ab"),
+        "{}",
+        config.release
+    );
+}
+
+#[test]
+fn test_invalid_values_are_rejected() {
+    for (name, value) in [
+        ("SENTRY_TRACES_SAMPLE_RATE", "1.5"),
+        ("SENTRY_TRACES_SAMPLE_RATE", "all"),
+        ("SENTRY_SAMPLE_RATE", "-0.1"),
+        ("SENTRY_PROFILES_SAMPLE_RATE", "NaN"),
+        ("SENTRY_TRACES_ERROR_BUDGET", "0"),
+        ("TELEMETRY_RESPECT_PRIVACY_SIGNALS", "maybe"),
+    ] {
+        let err = config(false, &[(name, value)]).unwrap_err();
+        assert!(err.to_string().contains(name), "{name}: {err}");
+    }
+    let err = config(
+        false,
+        &[
+            ("SENTRY_TRACES_SAMPLE_RATE", "0.2"),
+            ("SENTRY_TRACES_SAMPLE_RATE_MIN", "0.5"),
+        ],
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("floor"), "{err}");
+}
@@ -0,0 +1,142 @@
+use axum::Router;
+use axum::routing::get;
+use juicebox::tls::{TlsAcceptor, TlsSettings};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/tls")
+        .join(name)
+}
+
+/// Copies of the `a` pair in a temp dir, so tests can overwrite them.
+fn installed_pair(dir: &Path) -> TlsSettings {
+    let settings = TlsSettings {
+        cert_path: dir.join("cert.pem"),
+        key_path: dir.join("key.pem"),
+    };
+    std::fs::copy(fixture("cert_a.pem"), &settings.cert_path).unwrap();
+    std::fs::copy(fixture("key_a.pem"), &settings.key_path).unwrap();
+    settings
+}
+
+/// Handshake with `addr`, trusting the fixture CA, and return the leaf
+/// certificate the server presented along with the HTTP response.
+async fn fetch(addr: SocketAddr) -> (CertificateDer<'static>, String) {
+    let mut roots = RootCertStore::empty();
+    roots
+        .add(CertificateDer::from_pem_file(fixture("ca.pem")).unwrap())
+        .unwrap();
+    let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let mut tls = TlsConnector::from(Arc::new(config))
+        .connect(ServerName::try_from("localhost").unwrap(), tcp)
+        .await
+        .unwrap();
+    let leaf = tls.get_ref().1.peer_certificates().unwrap()[0].clone();
+    tls.write_all(b"GET /ping HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    tls.read_to_string(&mut response).await.unwrap();
+    (leaf, response)
+}
+
+fn leaf_of(name: &str) -> CertificateDer<'static> {
+    CertificateDer::from_pem_file(fixture(name)).unwrap()
+}
+
+#[test]
+fn test_tls_settings_resolve() {
+    assert_eq!(TlsSettings::resolve(|_| None).unwrap(), None);
+
+    let vars: HashMap<&str, &str> = [
+        ("TLS_CERT_PATH", " /etc/juicebox/cert.pem "),
+        ("TLS_KEY_PATH", "/etc/juicebox/key.pem"),
+    ]
+    .into_iter()
+    .collect();
+    let settings = TlsSettings::resolve(|name| vars.get(name).map(|v| v.to_string()))
+        .unwrap()
+        .unwrap();
+    assert_eq!(settings.cert_path, PathBuf::from("/etc/juicebox/cert.pem"));
+    assert_eq!(settings.key_path, PathBuf::from("/etc/juicebox/key.pem"));
+
+    // Half a pair is a mistake, not a request for plain HTTP.
+    let err = TlsSettings::resolve(|name| (name == "TLS_CERT_PATH").then(|| "cert.pem".into()))
+        .unwrap_err();
+    assert!(err.to_string().contains("TLS_KEY_PATH"));
+}
+
+#[test]
+fn test_tls_load_rejects_bad_pairs() {
+    let good = TlsSettings {
+        cert_path: fixture("cert_a.pem"),
+        key_path: fixture("key_a.pem"),
+    };
+    assert!(good.load().is_ok());
+
+    let mismatched = TlsSettings {
+        cert_path: fixture("cert_a.pem"),
+        key_path: fixture("key_b.pem"),
+    };
+    assert!(mismatched.load().is_err());
+
+    let missing = TlsSettings {
+        cert_path: fixture("missing.pem"),
+        key_path: fixture("key_a.pem"),
+    };
+    let err = format!("{:#}", missing.load().unwrap_err());
+    assert!(err.contains("missing.pem"), "{err}");
+}
+
+#[tokio::test]
+async fn test_tls_serves_and_reloads_certificate() {
+    let dir = tempfile::tempdir().unwrap();
+    let settings = installed_pair(dir.path());
+    let acceptor = TlsAcceptor::new(settings.clone()).unwrap();
+
+    let handle = axum_server::Handle::new();
+    let app = Router::new().route("/ping", get(|| async { "pong" }));
+    let server = axum_server::bind("127.0.0.1:0".parse().unwrap())
+        .acceptor(acceptor.clone())
+        .handle(handle.clone())
+        .serve(app.into_make_service());
+    let server = tokio::spawn(server);
+    let addr = handle.listening().await.unwrap();
+
+    let (leaf, response) = fetch(addr).await;
+    assert_eq!(leaf, leaf_of("cert_a.pem"));
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    assert!(response.ends_with("pong"), "{response}");
+
+    // Unchanged files are not reloaded.
+    assert!(!acceptor.reload_if_changed());
+
+    // A half-written renewal keeps the old certificate in service.
+    std::fs::write(&settings.cert_path, "not a certificate").unwrap();
+    assert!(!acceptor.reload_if_changed());
+    assert_eq!(fetch(addr).await.0, leaf_of("cert_a.pem"));
+
+    std::fs::copy(fixture("cert_b.pem"), &settings.cert_path).unwrap();
+    std::fs::copy(fixture("key_b.pem"), &settings.key_path).unwrap();
+    assert!(acceptor.reload_if_changed());
+    assert_eq!(fetch(addr).await.0, leaf_of("cert_b.pem"));
+
+    handle.shutdown();
+    server.await.unwrap().unwrap();
+}
@@ -0,0 +1,148 @@
+use axum::Router;
+use axum::body::{Body, to_bytes};
+use axum::http::{Method, Request, StatusCode, header};
+use axum::middleware;
+use axum::routing::get;
+use juicebox::handlers::build_router;
+use juicebox::handlers::telemetry::telemetry_gate;
+use juicebox::testing::AppStateBuilder;
+use juicebox::trace_sampling::{SamplingUpdate, TraceSampler, TraceSamplingConfig};
+use serde_json::Value;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+fn sampler() -> TraceSampler {
+    TraceSampler::new(TraceSamplingConfig {
+        floor: 0.1,
+        ceiling: 0.9,
+        error_budget: 0.1,
+    })
+}
+
+#[test]
+fn test_sampler_rises_with_errors_and_settles_when_healthy() {
+    let sampler = sampler();
+    assert_eq!(sampler.rate(), 0.1);
+
+    let now = 1_000_000;
+    for _ in 0..95 {
+        sampler.record(now, false);
+    }
+    assert_eq!(sampler.rate(), 0.1);
+
+    // 5% errors is half the budget: halfway between floor and ceiling.
+    for _ in 0..5 {
+        sampler.record(now, true);
+    }
+    assert!((sampler.rate() - 0.5).abs() < 1e-6);
+    assert!((sampler.status().error_rate - 0.05).abs() < 1e-9);
+
+    // Past the budget the rate stays at the ceiling.
+    for _ in 0..50 {
+        sampler.record(now + 5, true);
+    }
+    assert_eq!(sampler.rate(), 0.9);
+
+    // Once the errors age out of the one-minute window the floor returns.
+    sampler.record(now + 70, false);
+    assert_eq!(sampler.rate(), 0.1);
+    assert_eq!(sampler.status().error_rate, 0.0);
+}
+
+#[tokio::test]
+async fn test_telemetry_gate_feeds_server_errors_to_the_sampler() {
+    let app = AppStateBuilder::new().manual_clock(1_700_000_000).build();
+    let mut state = app.state.clone();
+    state.trace_sampler = Arc::new(sampler());
+    let router = Router::new()
+        .route("/ok", get(|| async { StatusCode::OK }))
+        .route("/boom", get(|| async { StatusCode::INTERNAL_SERVER_ERROR }))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            telemetry_gate,
+        ));
+
+    for uri in ["/ok", "/boom"] {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        router.clone().oneshot(req).await.unwrap();
+    }
+    let status = state.trace_sampler.status();
+    assert_eq!(status.error_rate, 0.5);
+    assert_eq!(status.effective_rate, 0.9);
+}
+
+#[test]
+fn test_sampling_update_applies_all_or_nothing() {
+    let sampler = sampler();
+    let update = SamplingUpdate {
+        traces_sample_rate_min: Some(1.0),
+        traces_sample_rate: Some(1.0),
+        error_sample_rate: Some(0.25),
+        ..Default::default()
+    };
+    update.apply(&sampler).unwrap();
+    assert_eq!(sampler.rate(), 1.0);
+    assert_eq!(sampler.error_sample_rate(), 0.25);
+
+    // A floor above the ceiling is refused and nothing changes.
+    let bad = SamplingUpdate {
+        traces_sample_rate: Some(0.5),
+        error_sample_rate: Some(0.0),
+        ..Default::default()
+    };
+    assert!(bad.apply(&sampler).is_err());
+    assert_eq!(sampler.config().ceiling, 1.0);
+    assert_eq!(sampler.error_sample_rate(), 0.25);
+
+    sampler.set_error_sample_rate(0.0);
+    assert!(!sampler.keep_error_event());
+    sampler.set_error_sample_rate(1.0);
+    assert!(sampler.keep_error_event());
+}
+
+#[tokio::test]
+async fn test_admin_can_raise_sampling_at_runtime() {
+    let app = AppStateBuilder::new().admin_key("k").build();
+    let router = build_router(app.state.clone());
+    let patch = |cookie: Option<&'static str>, body: &'static str| {
+        let mut req = Request::builder()
+            .method(Method::PATCH)
+            .uri("/api/admin/v1/runtime")
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(cookie) = cookie {
+            req = req.header(header::COOKIE, cookie);
+        }
+        req.body(Body::from(body)).unwrap()
+    };
+
+    let body = r#"{"traces_sample_rate_min": 1.0, "error_sample_rate": 0.5}"#;
+    let resp = router.clone().oneshot(patch(None, body)).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    app.state
+        .create_admin_session("sampling-admin".to_string())
+        .await;
+    let resp = router
+        .clone()
+        .oneshot(patch(Some("adm=sampling-admin"), body))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let runtime: Value =
+        serde_json::from_slice(&to_bytes(resp.into_body(), usize::MAX).await.unwrap()).unwrap();
+    assert_eq!(runtime["trace_sampling"]["floor"], 1.0);
+    assert_eq!(runtime["trace_sampling"]["effective_rate"], 1.0);
+    assert_eq!(runtime["trace_sampling"]["error_sample_rate"], 0.5);
+    assert_eq!(app.state.trace_sampler.rate(), 1.0);
+
+    let resp = router
+        .clone()
+        .oneshot(patch(
+            Some("adm=sampling-admin"),
+            r#"{"traces_sample_rate": 2}"#,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(app.state.trace_sampler.config().ceiling, 1.0);
+}
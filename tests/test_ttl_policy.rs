@@ -0,0 +1,145 @@
+mod common;
+
+use axum::body::{Body, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode, header};
+use juicebox::handlers::{PasteResponse, build_router};
+use juicebox::ttl_policy::{ClientAttributes, TtlPolicy, parse_ttl_secs};
+use juicebox::util::now_secs;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+
+fn write_policy(dir: &Path) -> std::path::PathBuf {
+    std::fs::write(
+        dir.join("tor_exits.txt"),
+        "# exits\n203.0.113.66\n203.0.113.67 # relay\n",
+    )
+    .unwrap();
+    let path = dir.join("ttl_policy.json");
+    std::fs::write(
+        &path,
+        r#"{
+            "max_ttl": "14d",
+            "tor_exit_list": "tor_exits.txt",
+            "rules": [
+                {"name": "tor", "tor_exit": true, "default_ttl": "1h", "max_ttl": "3h"},
+                {"name": "de", "countries": ["DE"], "max_ttl": "1d"},
+                {"name": "french", "languages": ["fr"], "default_ttl": "12h"}
+            ]
+        }"#,
+    )
+    .unwrap();
+    path
+}
+
+#[test]
+fn test_ttl_policy_first_matching_rule_sets_limits() {
+    let dir = tempfile::tempdir().unwrap();
+    let policy = TtlPolicy::load(&write_policy(dir.path())).unwrap();
+    assert_eq!(policy.rule_count(), 3);
+    assert_eq!(policy.tor_exit_count(), 2);
+
+    let tor = ClientAttributes {
+        country: Some("DE".into()),
+        tor_exit: true,
+        ..Default::default()
+    };
+    let effective = policy.resolve(&tor, Some("7d"), "24h");
+    assert_eq!(effective.rule.as_deref(), Some("tor"));
+    assert_eq!(effective.ttl, 3 * HOUR);
+    assert_eq!(policy.resolve(&tor, None, "24h").ttl, HOUR);
+
+    let german = ClientAttributes {
+        country: Some("DE".into()),
+        ..Default::default()
+    };
+    let effective = policy.resolve(&german, Some("7d"), "24h");
+    assert_eq!(effective.rule.as_deref(), Some("de"));
+    assert_eq!((effective.ttl, effective.max_ttl), (DAY, Some(DAY)));
+    assert_eq!(effective.default_ttl, DAY, "defaults are capped too");
+
+    let anyone = policy.resolve(&ClientAttributes::default(), Some("14d"), "24h");
+    assert_eq!(anyone.rule, None);
+    assert_eq!(anyone.ttl, 14 * DAY);
+    assert_eq!(
+        TtlPolicy::default()
+            .resolve(&ClientAttributes::default(), None, "1h")
+            .ttl,
+        HOUR
+    );
+
+    assert_eq!(parse_ttl_secs("90"), Some(90));
+    assert_eq!(parse_ttl_secs("30m"), Some(30 * 60));
+    assert_eq!(parse_ttl_secs("0h"), None);
+    assert_eq!(parse_ttl_secs("3w"), None);
+    std::fs::write(dir.path().join("bad.json"), r#"{"max_ttl": "soon"}"#).unwrap();
+    assert!(TtlPolicy::load(&dir.path().join("bad.json")).is_err());
+}
+
+async fn paste(
+    app: &axum::Router,
+    ip: [u8; 4],
+    query: &str,
+    language: Option<&str>,
+) -> PasteResponse {
+    let mut builder = Request::builder()
+        .method(Method::POST)
+        .uri(format!("/api/paste-binary?{query}"))
+        .header(header::CONTENT_TYPE, "text/plain");
+    if let Some(language) = language {
+        builder = builder.header(header::ACCEPT_LANGUAGE, language);
+    }
+    let mut req = builder.body(Body::from(query.to_string())).unwrap();
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from((ip, 8100))));
+    let resp = app.clone().oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    serde_json::from_slice(&to_bytes(resp.into_body(), usize::MAX).await.unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn test_upload_echoes_effective_ttl_policy() {
+    let (mut state, tmp) = common::setup_test_app();
+    state.ttl_policy = Arc::new(TtlPolicy::load(&write_policy(tmp.path())).unwrap());
+    let app = build_router(state.clone());
+
+    let before = now_secs();
+    let tor = paste(&app, [203, 0, 113, 66], "name=a.txt&ttl=7d", None).await;
+    let policy = tor.ttl_policy.unwrap();
+    assert_eq!(policy.rule.as_deref(), Some("tor"));
+    assert_eq!(policy.ttl, 3 * HOUR);
+    assert!(tor.expires >= before + 3 * HOUR && tor.expires <= now_secs() + 3 * HOUR);
+    assert_eq!(state.owners.get(&tor.file).unwrap().expires, tor.expires);
+
+    let french = paste(
+        &app,
+        [198, 51, 100, 3],
+        "name=b.txt",
+        Some("fr-CA,fr;q=0.9"),
+    )
+    .await;
+    let policy = french.ttl_policy.unwrap();
+    assert_eq!(policy.rule.as_deref(), Some("french"));
+    assert_eq!((policy.default_ttl, policy.ttl), (12 * HOUR, 12 * HOUR));
+
+    // Country headers are ignored unless the request came through a trusted proxy.
+    let mut req = Request::builder()
+        .method(Method::POST)
+        .uri("/api/paste-binary?name=c.txt&ttl=7d")
+        .header("CF-IPCountry", "DE")
+        .body(Body::from("c"))
+        .unwrap();
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([198, 51, 100, 4], 8100))));
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let body: PasteResponse =
+        serde_json::from_slice(&to_bytes(resp.into_body(), usize::MAX).await.unwrap()).unwrap();
+    let policy = body.ttl_policy.unwrap();
+    assert_eq!(policy.rule, None);
+    assert_eq!(policy.ttl, 7 * DAY);
+}
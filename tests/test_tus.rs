@@ -0,0 +1,177 @@
+mod common;
+
+use axum::Router;
+use axum::body::Body;
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, Response, StatusCode};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use juicebox::handlers::build_router;
+use std::net::SocketAddr;
+use tower::ServiceExt;
+
+const MIB: usize = 1024 * 1024;
+
+fn tus_request(
+    method: Method,
+    uri: &str,
+    headers: &[(&str, String)],
+    body: Vec<u8>,
+) -> Request<Body> {
+    let mut builder = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("Tus-Resumable", "1.0.0");
+    for (name, value) in headers {
+        builder = builder.header(*name, value);
+    }
+    let mut req = builder.body(Body::from(body)).unwrap();
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 5000))));
+    req
+}
+
+async fn send(app: &Router, req: Request<Body>) -> Response<Body> {
+    app.clone().oneshot(req).await.unwrap()
+}
+
+fn header<'a>(resp: &'a Response<Body>, name: &str) -> &'a str {
+    resp.headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+}
+
+async fn create(app: &Router, length: usize, filename: &str) -> String {
+    // "filename <base64>", as sent by tus-js-client.
+    let encoded = BASE64.encode(filename);
+    let resp = send(
+        app,
+        tus_request(
+            Method::POST,
+            "/tus/",
+            &[
+                ("Upload-Length", length.to_string()),
+                ("Upload-Metadata", format!("filename {encoded},ttl MWg=")),
+            ],
+            Vec::new(),
+        ),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    assert_eq!(header(&resp, "tus-resumable"), "1.0.0");
+    header(&resp, "location").to_string()
+}
+
+async fn patch(app: &Router, location: &str, offset: usize, body: &[u8]) -> Response<Body> {
+    send(
+        app,
+        tus_request(
+            Method::PATCH,
+            location,
+            &[
+                ("Upload-Offset", offset.to_string()),
+                (
+                    "Content-Type",
+                    "application/offset+octet-stream".to_string(),
+                ),
+            ],
+            body.to_vec(),
+        ),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn tus_upload_resumes_across_chunk_boundaries() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+
+    let resp = send(&app, tus_request(Method::OPTIONS, "/tus/", &[], Vec::new())).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    assert_eq!(header(&resp, "tus-version"), "1.0.0");
+    assert!(header(&resp, "tus-extension").contains("creation"));
+
+    let data: Vec<u8> = (0..8 * MIB + 4096).map(|i| (i % 251) as u8).collect();
+    let location = create(&app, data.len(), "resumable.bin").await;
+    assert!(location.starts_with("/tus/"));
+
+    let head = tus_request(Method::HEAD, &location, &[], Vec::new());
+    let resp = send(&app, head).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(header(&resp, "upload-offset"), "0");
+    assert_eq!(header(&resp, "upload-length"), data.len().to_string());
+
+    // First PATCH stops partway through the first 8 MiB chunk.
+    let split = 3 * MIB + 17;
+    let resp = patch(&app, &location, 0, &data[..split]).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    assert_eq!(header(&resp, "upload-offset"), split.to_string());
+
+    let resp = patch(&app, &location, 0, &data[..10]).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    assert_eq!(header(&resp, "upload-offset"), split.to_string());
+
+    let resp = send(&app, tus_request(Method::HEAD, &location, &[], Vec::new())).await;
+    assert_eq!(header(&resp, "upload-offset"), split.to_string());
+
+    let resp = patch(&app, &location, split, &data[split..]).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    assert_eq!(header(&resp, "upload-offset"), data.len().to_string());
+    let file_url = header(&resp, "x-juicebox-file").to_string();
+    let storage_name = file_url.rsplit('/').next().unwrap().to_string();
+    assert!(storage_name.ends_with(".bin"), "{file_url}");
+
+    let stored = state.file_store.read(&storage_name).await.unwrap().unwrap();
+    assert_eq!(stored.len(), data.len());
+    assert!(stored.as_ref() == data.as_slice());
+    let meta = state.owners.get(&storage_name).unwrap();
+    assert_eq!(meta.original, "resumable.bin");
+    assert!(state.chunk_sessions.is_empty());
+}
+
+#[tokio::test]
+async fn tus_rejects_bad_requests_and_terminates() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+
+    let mut req = tus_request(
+        Method::POST,
+        "/tus/",
+        &[("Upload-Length", "10".to_string())],
+        Vec::new(),
+    );
+    req.headers_mut().remove("Tus-Resumable");
+    let resp = send(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::PRECONDITION_FAILED);
+    assert_eq!(header(&resp, "tus-version"), "1.0.0");
+
+    let resp = send(&app, tus_request(Method::POST, "/tus/", &[], Vec::new())).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let location = create(&app, 100, "small.txt").await;
+    let resp = send(
+        &app,
+        tus_request(
+            Method::PATCH,
+            &location,
+            &[("Upload-Offset", "0".to_string())],
+            b"hello".to_vec(),
+        ),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    let resp = patch(&app, &location, 0, &[1u8; 101]).await;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let resp = send(
+        &app,
+        tus_request(Method::DELETE, &location, &[], Vec::new()),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    assert!(state.chunk_sessions.is_empty());
+    let resp = send(&app, tus_request(Method::HEAD, &location, &[], Vec::new())).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,52 @@
+use juicebox::upload_stats::{
+    UploadMethod, UploadStats, agent_family, chunk_size_bucket, size_bucket,
+};
+
+#[test]
+fn test_agent_families() {
+    let chrome = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0 Safari/537.36";
+    let edge = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0 Safari/537.36 Edg/126.0";
+    let safari = "Mozilla/5.0 (Macintosh; Intel Mac OS X 14_5) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.5 Safari/605.1.15";
+    assert_eq!(agent_family(Some(chrome)), "chrome");
+    assert_eq!(agent_family(Some(edge)), "edge");
+    assert_eq!(agent_family(Some(safari)), "safari");
+    assert_eq!(agent_family(Some("python-requests/2.32")), "python");
+    assert_eq!(agent_family(Some("Go-http-client/2.0")), "go");
+    assert_eq!(agent_family(Some("ShareX/16.1")), "sharex");
+    assert_eq!(agent_family(Some("custom-uploader")), "other");
+    assert_eq!(agent_family(Some("  ")), "unknown");
+    assert_eq!(agent_family(None), "unknown");
+}
+
+#[test]
+fn test_size_and_chunk_buckets() {
+    assert_eq!(size_bucket(0), "<100KiB");
+    assert_eq!(size_bucket(1024 * 1024), "<10MiB");
+    assert_eq!(size_bucket(5 * 1024 * 1024 * 1024), ">=1GiB");
+    assert_eq!(chunk_size_bucket(1024 * 1024), "<=1MiB");
+    assert_eq!(chunk_size_bucket(1024 * 1024 + 1), "<=2MiB");
+    assert_eq!(chunk_size_bucket(100 * 1024), "<=256KiB");
+    assert_eq!(chunk_size_bucket(65 * 1024 * 1024), ">64MiB");
+}
+
+#[test]
+fn test_snapshot_groups_counters_by_dimension() {
+    let stats = UploadStats::new();
+    stats.record(
+        UploadMethod::Chunked,
+        50 * 1024 * 1024,
+        Some(8 << 20),
+        Some("curl/8.0"),
+    );
+    stats.record(UploadMethod::Chunked, 20 * 1024 * 1024, Some(8 << 20), None);
+    stats.record(UploadMethod::Form, 10, None, Some("Firefox/128.0"));
+    let snapshot = stats.snapshot();
+    assert_eq!(snapshot.total, 3);
+    assert_eq!(snapshot.by_method["chunked"], 2);
+    assert_eq!(snapshot.by_method["form"], 1);
+    assert_eq!(snapshot.by_chunk_size["<=8MiB"], 2);
+    assert_eq!(snapshot.by_chunk_size.len(), 1);
+    assert_eq!(snapshot.by_file_size["<100MiB"], 2);
+    assert_eq!(snapshot.by_client["unknown"], 1);
+    assert_eq!(snapshot.by_client["firefox"], 1);
+}
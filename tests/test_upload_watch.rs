@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+use juicebox::testing::AppStateBuilder;
+use juicebox::upload_watch::UploadWatcher;
+
+const EVENT_WAIT: Duration = Duration::from_secs(5);
+
+#[tokio::test]
+async fn test_externally_deleted_body_drops_metadata() {
+    let app = AppStateBuilder::new()
+        .with_file("kept.txt", b"kept", "203.0.113.7", 3600)
+        .with_file("gone.txt", b"gone", "203.0.113.7", 3600)
+        .build();
+    let state = &app.state;
+
+    let mut watcher = UploadWatcher::new(state.upload_dir.as_ref()).unwrap();
+    std::fs::remove_file(state.upload_dir.join("gone.txt")).unwrap();
+    let removed = tokio::time::timeout(EVENT_WAIT, watcher.next(state))
+        .await
+        .expect("no event for the removed body")
+        .unwrap();
+    assert_eq!(removed, vec!["gone.txt".to_string()]);
+    assert!(!state.owners.contains_key("gone.txt"));
+    assert!(state.owners.contains_key("kept.txt"));
+}
+
+#[tokio::test]
+async fn test_files_deleted_through_the_server_are_not_reported() {
+    let app = AppStateBuilder::new()
+        .with_file("mine.txt", b"mine", "203.0.113.7", 3600)
+        .build();
+    let state = &app.state;
+
+    let mut watcher = UploadWatcher::new(state.upload_dir.as_ref()).unwrap();
+    state.remove_owner("mine.txt");
+    state.file_store.delete("mine.txt").await.unwrap();
+    let removed = tokio::time::timeout(EVENT_WAIT, watcher.next(state))
+        .await
+        .expect("no event for the removed body")
+        .unwrap();
+    assert!(removed.is_empty());
+}
@@ -3,9 +3,11 @@ mod common;
 use axum::http::{HeaderMap, HeaderValue, header};
 
 use juicebox::util::{
-    IpVersion, format_bytes, get_cookie, hash_ip_addr, hash_ip_string, hash_network_from_cidr,
-    hash_network_from_ip, is_forbidden_extension, looks_like_hash, make_storage_name, qualify_path,
-    ttl_to_duration,
+    IP_SECRET_KEY_ID, IpVersion, NameWarning, SigningKeys, ascii_filename, display_original_name,
+    ellipsize_middle, filename_warning, format_bytes, get_cookie, hash_ip_addr, hash_ip_string,
+    hash_network_from_cidr, hash_network_from_ip, inline_content_disposition,
+    is_forbidden_extension, looks_like_hash, make_storage_name, normalize_original_name,
+    qualify_path, ttl_to_duration,
 };
 
 #[test]
@@ -161,3 +163,134 @@ fn test_qualify_path_production_prefix() {
     assert!(q2.ends_with("/a/b"), "unexpected: {q2}");
     assert_eq!(q1, q2);
 }
+
+#[test]
+fn test_original_name_normalization() {
+    // Decomposed "e" + combining acute becomes a single precomposed char.
+    assert_eq!(normalize_original_name("cafe\u{301}.txt"), "caf\u{e9}.txt");
+    // RLO would render "invoice_gpj.exe" as "invoice_exe.jpg".
+    assert_eq!(
+        normalize_original_name("invoice_\u{202E}gpj.exe"),
+        "invoice_gpj.exe"
+    );
+    assert_eq!(normalize_original_name(" a\u{0}\nb\u{2066}.png "), "ab.png");
+}
+
+#[test]
+fn test_filename_warnings_catch_spoofed_names() {
+    assert_eq!(filename_warning("report-2024.pdf"), None);
+    assert_eq!(filename_warning("r\u{e9}sum\u{e9}.pdf"), None);
+    assert_eq!(
+        filename_warning("\u{43e}\u{442}\u{447}\u{435}\u{442}.pdf"),
+        None
+    );
+    assert_eq!(
+        filename_warning("invoice.pdf\u{202E}exe."),
+        Some(NameWarning::BidiControl)
+    );
+    assert_eq!(
+        filename_warning("photo\u{200B}.jpg"),
+        Some(NameWarning::Invisible)
+    );
+    assert_eq!(
+        filename_warning("invoice\u{2024}pdf.exe"),
+        Some(NameWarning::ConfusableSeparator)
+    );
+    assert_eq!(
+        filename_warning("\u{ff50}\u{ff44}\u{ff46}.exe"),
+        Some(NameWarning::Fullwidth)
+    );
+    // Cyrillic "а" in an otherwise Latin word.
+    assert_eq!(
+        filename_warning("p\u{430}ypal.exe"),
+        Some(NameWarning::MixedScript)
+    );
+
+    // Look-alike dots never reach the display name, invisible marks are gone.
+    assert_eq!(
+        display_original_name("invoice\u{2024}pdf.exe"),
+        "invoice_pdf.exe"
+    );
+    assert_eq!(display_original_name("photo\u{200B}.jpg"), "photo.jpg");
+    assert_eq!(ascii_filename("p\u{430}ypal.exe"), "xn--pypal-4ve.exe");
+    assert_eq!(ascii_filename("plain.txt"), "plain.txt");
+}
+
+#[test]
+fn test_original_name_ellipsized_in_middle() {
+    assert_eq!(ellipsize_middle("short.txt", 20), "short.txt");
+    let long = format!("{}.tar.gz", "x".repeat(300));
+    let shown = ellipsize_middle(&long, 20);
+    assert_eq!(shown.chars().count(), 20);
+    assert!(shown.contains('…'));
+    assert!(shown.ends_with(".tar.gz"));
+    assert!(display_original_name(&long).chars().count() <= 120);
+}
+
+#[test]
+fn test_inline_content_disposition_escapes_name() {
+    assert_eq!(
+        inline_content_disposition("r\u{e9}sum\u{e9} \"v2\".pdf"),
+        "inline; filename=\"r_sum_ _v2_.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9%20%22v2%22.pdf"
+    );
+}
+
+#[test]
+fn test_signing_keys_verify_across_rotation() {
+    let ip_secret = [0x22; 32];
+    let old = SigningKeys::parse("k1:first-signing-secret", &ip_secret).unwrap();
+    let rotated = SigningKeys::parse(
+        "k2:second-signing-secret, k1:first-signing-secret",
+        &ip_secret,
+    )
+    .unwrap();
+    assert_eq!(old.signing_key_id(), "k1");
+    assert_eq!(rotated.key_ids(), vec!["k2", "k1", IP_SECRET_KEY_ID]);
+
+    let sig = old.sign("presign", b"file\n10");
+    assert!(sig.starts_with("k1."));
+    assert!(rotated.verify("presign", b"file\n10", &sig));
+    assert!(!rotated.verify("presign", b"file\n11", &sig));
+    // A signature for one purpose is not accepted for another.
+    assert!(!rotated.verify("lookup-challenge", b"file\n10", &sig));
+
+    let new_sig = rotated.sign("presign", b"file\n10");
+    assert!(new_sig.starts_with("k2."));
+    assert!(!old.verify("presign", b"file\n10", &new_sig));
+
+    // Once k1 is dropped, its signatures stop verifying.
+    let retired = SigningKeys::parse("k2:second-signing-secret", &ip_secret).unwrap();
+    assert!(!retired.verify("presign", b"file\n10", &sig));
+    // Naming one key while carrying another's tag is rejected.
+    let (_, tag) = sig.split_once('.').unwrap();
+    assert!(!rotated.verify("presign", b"file\n10", &format!("k2.{tag}")));
+}
+
+#[test]
+fn test_signing_keys_fall_back_to_ip_secret() {
+    let ip_secret = [0x22; 32];
+    let keys = SigningKeys::parse("", &ip_secret).unwrap();
+    assert_eq!(keys.signing_key_id(), IP_SECRET_KEY_ID);
+    let sig = keys.sign("presign", b"payload");
+
+    // Tags from before signatures carried a key id are tried on every key.
+    let (_, bare) = sig.split_once('.').unwrap();
+    let configured = SigningKeys::parse("k1:first-signing-secret", &ip_secret).unwrap();
+    assert!(configured.verify("presign", b"payload", bare));
+    assert!(configured.verify("presign", b"payload", &sig));
+}
+
+#[test]
+fn test_signing_keys_reject_bad_entries() {
+    let ip_secret = [0x22; 32];
+    for raw in [
+        "nocolon",
+        "k1:short",
+        ":first-signing-secret",
+        "bad id:first-signing-secret",
+        "ip:first-signing-secret",
+        "k1:first-signing-secret,k1:second-signing-secret",
+    ] {
+        assert!(SigningKeys::parse(raw, &ip_secret).is_err(), "{raw}");
+    }
+}
@@ -0,0 +1,150 @@
+use axum::Router;
+use axum::body::{Body, Bytes, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{HeaderMap, Method, Request, StatusCode};
+use axum::routing::post;
+use juicebox::handlers::build_router;
+use juicebox::state::{BanSubject, IpBan};
+use juicebox::testing::AppStateBuilder;
+use juicebox::webhooks::{
+    EVENT_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER, WebhookConfig, parse_urls, sign,
+};
+use serde_json::Value;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tower::ServiceExt;
+
+const SECRET: &[u8] = b"webhook-test-secret";
+
+/// A receiver that records each delivery, failing the first `failures`.
+async fn spawn_receiver(failures: usize) -> (String, mpsc::UnboundedReceiver<(HeaderMap, Bytes)>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = Router::new().route(
+        "/hook",
+        post(move |headers: HeaderMap, body: Bytes| {
+            let tx = tx.clone();
+            let calls = calls.clone();
+            async move {
+                if calls.fetch_add(1, Ordering::SeqCst) < failures {
+                    return StatusCode::INTERNAL_SERVER_ERROR;
+                }
+                tx.send((headers, body)).unwrap();
+                StatusCode::NO_CONTENT
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (format!("http://{addr}/hook"), rx)
+}
+
+fn config(url: String) -> WebhookConfig {
+    WebhookConfig {
+        urls: vec![url],
+        secret: Some(SECRET.to_vec()),
+        ..WebhookConfig::default()
+    }
+}
+
+async fn next_event(rx: &mut mpsc::UnboundedReceiver<(HeaderMap, Bytes)>) -> (HeaderMap, Value) {
+    let (headers, body) = tokio::time::timeout(Duration::from_secs(10), rx.recv())
+        .await
+        .expect("webhook delivered")
+        .unwrap();
+    let timestamp: u64 = headers[TIMESTAMP_HEADER].to_str().unwrap().parse().unwrap();
+    assert_eq!(
+        headers[SIGNATURE_HEADER].to_str().unwrap(),
+        sign(SECRET, timestamp, &body)
+    );
+    (headers, serde_json::from_slice(&body).unwrap())
+}
+
+async fn send(app: &Router, method: Method, uri: &str, body: &'static str) -> (StatusCode, Value) {
+    let mut req = Request::builder()
+        .method(method)
+        .uri(uri)
+        .body(Body::from(body))
+        .unwrap();
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([198, 51, 100, 72], 7000))));
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn test_upload_delete_and_ban_are_delivered_signed() {
+    let (url, mut rx) = spawn_receiver(0).await;
+    let app = AppStateBuilder::new().webhooks(config(url)).build();
+    let router = build_router(app.state.clone());
+
+    let (status, paste) = send(
+        &router,
+        Method::POST,
+        "/api/paste-binary?name=note.txt",
+        "hello hooks",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let file = paste["file"].as_str().unwrap();
+    let (headers, event) = next_event(&mut rx).await;
+    assert_eq!(headers[EVENT_HEADER], "upload.completed");
+    assert_eq!(event["event"], "upload.completed");
+    assert_eq!(event["data"]["file"], file);
+    assert_eq!(event["data"]["size"], 11);
+
+    let (status, _) = send(&router, Method::DELETE, &format!("/f/{file}"), "").await;
+    assert!(status.is_success(), "{status}");
+    let (_, event) = next_event(&mut rx).await;
+    assert_eq!(event["event"], "file.deleted");
+    assert_eq!(event["data"]["file"], file);
+    assert_eq!(event["data"]["reason"], "owner");
+
+    app.state
+        .add_ban(IpBan {
+            subject: BanSubject::Exact {
+                hash: "abc123".to_string(),
+            },
+            label: Some("spam".to_string()),
+            reason: "abuse".to_string(),
+            time: 0,
+            expires: None,
+        })
+        .await;
+    let (_, event) = next_event(&mut rx).await;
+    assert_eq!(event["event"], "ban.added");
+    assert_eq!(event["data"]["ban_key"], "abc123");
+    assert_eq!(event["data"]["reason"], "abuse");
+}
+
+#[tokio::test]
+async fn test_failed_delivery_is_retried() {
+    let (url, mut rx) = spawn_receiver(1).await;
+    let app = AppStateBuilder::new().webhooks(config(url)).build();
+    let router = build_router(app.state.clone());
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/api/paste-binary?name=retry.txt",
+        "again",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, event) = next_event(&mut rx).await;
+    assert_eq!(event["event"], "upload.completed");
+}
+
+#[test]
+fn test_parse_urls_keeps_http_only() {
+    assert_eq!(
+        parse_urls(" https://a.example/hook, ftp://b.example ,,http://c.example/x"),
+        vec!["https://a.example/hook", "http://c.example/x"]
+    );
+}
@@ -0,0 +1,208 @@
+mod common;
+
+use axum::body::{Body, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode};
+use juicebox::handlers::{
+    AccountResponse, ChunkInitResponse, DuplicateMeta, DuplicateResponse, LinkStatusResponse,
+    LookupVerifyResponse, PresignResponse, UploadResponse, UploadedFileEntry, build_router,
+};
+use juicebox::state::FileMeta;
+use juicebox::ttl_policy::EffectiveTtl;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+use std::net::SocketAddr;
+use tower::ServiceExt;
+
+/// Serialise `value`, compare with the expected wire JSON, and check it
+/// deserialises back to the same JSON.
+fn assert_wire<T: Serialize + DeserializeOwned>(value: &T, expected: Value) {
+    let wire = serde_json::to_value(value).unwrap();
+    assert_eq!(wire, expected);
+    let back: T = serde_json::from_value(wire).unwrap();
+    assert_eq!(serde_json::to_value(&back).unwrap(), expected);
+}
+
+#[test]
+fn test_upload_dtos_wire_format() {
+    assert_wire(
+        &UploadResponse {
+            files: vec!["f/abc.txt".into()],
+            truncated: false,
+            remaining: 0,
+            limit_reached: true,
+            ttl_policy: Some(EffectiveTtl {
+                rule: None,
+                default_ttl: 3600,
+                max_ttl: Some(86_400),
+                ttl: 3600,
+            }),
+            uploaded: vec![UploadedFileEntry {
+                file: "abc.txt".into(),
+                size: 1536,
+                expires: 7200,
+                size_human: "1.5 KB".into(),
+                expires_human: "in 1 hour".into(),
+            }],
+        },
+        json!({
+            "files": ["f/abc.txt"],
+            "truncated": false,
+            "remaining": 0,
+            "limit_reached": true,
+            "ttl_policy": {"rule": null, "default_ttl": 3600, "max_ttl": 86400, "ttl": 3600},
+            "uploaded": [{
+                "file": "abc.txt",
+                "size": 1536,
+                "expires": 7200,
+                "size_human": "1.5 KB",
+                "expires_human": "in 1 hour",
+            }],
+        }),
+    );
+    assert_wire(
+        &ChunkInitResponse {
+            session_id: "s1".into(),
+            chunk_size: 65_536,
+            total_chunks: 2,
+            expires: 100,
+            storage_name: "abc.bin".into(),
+            ttl_policy: None,
+            traceparent: None,
+        },
+        json!({
+            "session_id": "s1",
+            "chunk_size": 65536,
+            "total_chunks": 2,
+            "expires": 100,
+            "storage_name": "abc.bin",
+        }),
+    );
+}
+
+#[test]
+fn test_duplicate_response_omits_private_fields() {
+    let meta = FileMeta {
+        owner_hash: "someone-else".into(),
+        expires: 200,
+        original: "secret-name.pdf".into(),
+        original_display: "secret-name.pdf".into(),
+        created: 100,
+        hash: "ab".repeat(32),
+        max_downloads: Some(3),
+        downloads: 1,
+        private: false,
+        ttl_shortened_from: None,
+        network_class: None,
+        size: 42,
+        guest: false,
+    };
+    let duplicate = DuplicateResponse::new("abc.pdf", &meta);
+    assert_eq!(
+        duplicate.meta,
+        DuplicateMeta {
+            hash: "ab".repeat(32),
+            size: 42,
+            created: 100,
+            expires: 200,
+        }
+    );
+    assert_wire(
+        &duplicate,
+        json!({
+            "duplicate": true,
+            "file": "abc.pdf",
+            "meta": {"hash": "ab".repeat(32), "size": 42, "created": 100, "expires": 200},
+        }),
+    );
+}
+
+#[test]
+fn test_public_lookup_and_link_dtos_wire_format() {
+    assert_wire(
+        &LinkStatusResponse {
+            name: "abc.txt".into(),
+            exists: true,
+            expired: false,
+            quarantined: false,
+            size: Some(5),
+            expires: None,
+            downloads_left: Some(2),
+        },
+        json!({
+            "name": "abc.txt",
+            "exists": true,
+            "expired": false,
+            "quarantined": false,
+            "size": 5,
+            "downloads_left": 2,
+        }),
+    );
+    assert_wire(
+        &LookupVerifyResponse {
+            found: false,
+            file: None,
+            url: None,
+            expires: None,
+        },
+        json!({"found": false}),
+    );
+    assert_wire(
+        &PresignResponse {
+            url: "https://box.example/f/abc.txt?exp=1&sig=x".into(),
+            expires: 1,
+        },
+        json!({"url": "https://box.example/f/abc.txt?exp=1&sig=x", "expires": 1}),
+    );
+    assert_wire(
+        &AccountResponse {
+            username: "juice".into(),
+            created: 7,
+            files: 3,
+        },
+        json!({"username": "juice", "created": 7, "files": 3}),
+    );
+}
+
+#[tokio::test]
+async fn test_duplicate_paste_does_not_leak_owner() {
+    let (state, _tmp) = common::setup_test_app();
+    let app = build_router(state.clone());
+    let paste = |ip: [u8; 4]| {
+        let mut req = Request::builder()
+            .method(Method::POST)
+            .uri("/api/paste-binary?name=first-owner-name.txt")
+            .body(Body::from("same bytes"))
+            .unwrap();
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from((ip, 6400))));
+        req
+    };
+    let resp = app
+        .clone()
+        .oneshot(paste([198, 51, 100, 80]))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = app
+        .clone()
+        .oneshot(paste([198, 51, 100, 81]))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let raw = String::from_utf8(body.to_vec()).unwrap();
+    let duplicate: DuplicateResponse = serde_json::from_str(&raw).unwrap();
+    assert!(duplicate.duplicate);
+    assert_eq!(duplicate.meta.size, "same bytes".len() as u64);
+    let owner = state
+        .owners
+        .get(&duplicate.file)
+        .unwrap()
+        .owner_hash
+        .clone();
+    assert!(!raw.contains(&owner), "{raw}");
+    assert!(!raw.contains("first-owner-name"), "{raw}");
+}